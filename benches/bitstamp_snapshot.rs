@@ -0,0 +1,132 @@
+//! Peak memory and time of parsing a deep Bitstamp order_book response, buffered into a
+//! `Value` versus streamed into levels kept to the diff channel's depth.
+//!
+//! `cargo bench --bench bitstamp_snapshot`
+
+use keyrock_mm_rust_task::modules::bitstamp::{
+    BITSTAMP_DIFF_DEPTH, parse_bitstamp_snapshot, read_bitstamp_snapshot,
+};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::Read;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Levels per side of the response, about what Bitstamp serves for its deepest pairs
+const LEVELS: usize = 50_000;
+const RUNS: u32 = 20;
+/// What a REST body arrives in
+const CHUNK: usize = 16 << 10;
+
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let now = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        PEAK.fetch_max(now, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Hands out the response a chunk at a time, as the network would
+struct Chunked<'a> {
+    body: &'a [u8],
+}
+
+impl Read for Chunked<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = buf.len().min(CHUNK).min(self.body.len());
+        buf[..n].copy_from_slice(&self.body[..n]);
+        self.body = &self.body[n..];
+        Ok(n)
+    }
+}
+
+fn response() -> String {
+    let side = |best: f64, step: f64| -> String {
+        (0..LEVELS)
+            .map(|i| {
+                format!(
+                    r#"["{:.8}","{:.8}"]"#,
+                    best + step * i as f64,
+                    0.5 + i as f64
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    format!(
+        r#"{{"timestamp":"1700000000","microtimestamp":"1700000000123456","bids":[{}],"asks":[{}]}}"#,
+        side(0.05123, -0.000001),
+        side(0.05125, 0.000001)
+    )
+}
+
+/// Mean time and peak bytes allocated above the baseline over the runs of `parse`
+fn measure(parse: impl Fn() -> usize) -> (Duration, usize) {
+    let mut peak = 0;
+    let started = Instant::now();
+    for _ in 0..RUNS {
+        let baseline = ALLOCATED.load(Ordering::Relaxed);
+        PEAK.store(baseline, Ordering::Relaxed);
+        assert!(parse() > 0);
+        peak = peak.max(PEAK.load(Ordering::Relaxed) - baseline);
+    }
+    (started.elapsed() / RUNS, peak)
+}
+
+fn main() {
+    let body = response();
+    println!(
+        "Bitstamp order_book response: {} levels per side, {} KiB",
+        LEVELS,
+        body.len() >> 10
+    );
+    let buffered = measure(|| {
+        // What `read_body` accumulates before the parse
+        let mut copy = String::new();
+        Chunked {
+            body: body.as_bytes(),
+        }
+        .read_to_string(&mut copy)
+        .unwrap();
+        parse_bitstamp_snapshot(&copy).unwrap().bids.len()
+    });
+    let streamed_top = measure(|| {
+        let body = Chunked {
+            body: body.as_bytes(),
+        };
+        read_bitstamp_snapshot(body, Some(BITSTAMP_DIFF_DEPTH))
+            .unwrap()
+            .bids
+            .len()
+    });
+    let streamed_whole = measure(|| {
+        let body = Chunked {
+            body: body.as_bytes(),
+        };
+        read_bitstamp_snapshot(body, None).unwrap().bids.len()
+    });
+    for (name, (time, peak)) in [
+        ("buffered, whole book", buffered),
+        ("streamed, whole book", streamed_whole),
+        ("streamed, top 100 levels", streamed_top),
+    ] {
+        println!(
+            "{:<26} {:>8.2} ms {:>10} KiB peak",
+            name,
+            time.as_secs_f64() * 1e3,
+            peak >> 10
+        );
+    }
+}
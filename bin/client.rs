@@ -1,4 +1,11 @@
+use clap::{Parser, Subcommand, ValueEnum};
 use futures_util::StreamExt;
+use keyrock_mm_rust_task::client::format::{
+    Decimals, MISSING, Row, consolidate, format_number, format_optional,
+};
+use keyrock_mm_rust_task::client::smoke;
+use keyrock_mm_rust_task::grpc_error;
+use keyrock_mm_rust_task::modules::checksum::checksum;
 use tonic::Request;
 use tonic::transport::Channel;
 
@@ -7,34 +14,217 @@ pub mod orderbook {
     tonic::include_proto!("orderbook");
 }
 
-use orderbook::Empty;
-use orderbook::orderbook_aggregator_client::OrderbookAggregatorClient;
+use orderbook::market_data_client::MarketDataClient;
+use orderbook::{DataQuality, DepthUnit, EmissionKind, EmissionReason, SummaryRequest};
+
+#[derive(Parser)]
+struct Args {
+    /// Decimals for prices, or 'auto' to infer from the first received prices
+    #[arg(long, default_value = "auto")]
+    price_decimals: Decimals,
+
+    /// Decimals for amounts, or 'auto' to infer from the first received amounts
+    #[arg(long, default_value = "auto")]
+    amount_decimals: Decimals,
+
+    /// Whether the 10-deep ladder counts price levels or individual exchange levels
+    #[arg(long, value_enum, default_value_t = Unit::PriceLevels)]
+    depth_unit: Unit,
+
+    /// Merge levels quoting the same price on several exchanges into one row; without it
+    /// each exchange's level gets its own row
+    #[arg(long)]
+    consolidate: bool,
+
+    /// Recompute each Summary's checksum from the received ladder and report mismatches
+    #[arg(long)]
+    verify_checksum: bool,
+
+    /// Show the book quote/base flipped, e.g. ETH/BTC as BTC/ETH
+    #[arg(long)]
+    invert: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Call every RPC once, check the responses and exit non-zero on any failure
+    Smoke {
+        #[arg(long, default_value = "http://127.0.0.1:5002")]
+        server: String,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Unit {
+    PriceLevels,
+    Entries,
+}
+
+/// Rows to display for one side, consolidated or one per exchange level
+fn rows(levels: &[orderbook::Level], consolidated: bool) -> Vec<Row> {
+    let levels = levels
+        .iter()
+        .map(|l| (l.exchange.as_str(), l.price, l.amount));
+    if consolidated {
+        consolidate(levels)
+    } else {
+        levels
+            .map(|(exchange, price, amount)| Row {
+                exchange: exchange.to_string(),
+                price,
+                amount,
+            })
+            .collect()
+    }
+}
+
+/// Why the server sent a Summary, e.g. "book change (binance #42)"
+fn describe_reason(reason: Option<&EmissionReason>) -> String {
+    let Some(reason) = reason else {
+        return MISSING.to_string();
+    };
+    match reason.kind() {
+        EmissionKind::BookChange => {
+            let exchange = reason.exchange.as_deref().unwrap_or(MISSING);
+            match reason.update_id {
+                Some(id) => format!("book change ({} #{})", exchange, id),
+                None => format!("book change ({} dropped)", exchange),
+            }
+        }
+        EmissionKind::Heartbeat => "heartbeat".to_string(),
+        EmissionKind::InitialSnapshot => "initial snapshot".to_string(),
+        EmissionKind::Resync => "resync".to_string(),
+        EmissionKind::ConfigChange => "config change".to_string(),
+        EmissionKind::Reset => "reset".to_string(),
+    }
+}
+
+/// Banner for a book missing configured exchanges; empty while every one contributes
+fn describe_quality(summary: &orderbook::Summary) -> String {
+    let lagging = summary.lagging_exchanges.join(", ");
+    match summary.data_quality() {
+        DataQuality::Full => String::new(),
+        DataQuality::Partial => format!("⚠️  PARTIAL DATA: no fresh data from {}", lagging),
+        DataQuality::Stale => format!(
+            "🛑 STALE DATA: no fresh data from any exchange ({})",
+            lagging
+        ),
+    }
+}
+
+fn checksum_entry(level: &orderbook::Level) -> (&str, f64, f64) {
+    (level.exchange.as_str(), level.price, level.amount)
+}
+
+/// Print one side's table, widening the exchange column for combined labels
+/// A side the server flags as not present is shown as one row of dashes
+fn print_side(
+    title: &str,
+    present: bool,
+    rows: &[Row],
+    price_decimals: usize,
+    amount_decimals: usize,
+) {
+    let width = rows
+        .iter()
+        .map(|r| r.exchange.len())
+        .max()
+        .unwrap_or(0)
+        .max(11);
+    let rule = "─".repeat(width + 2);
+    println!("{}", title);
+    println!("┌{}┬──────────────────┬──────────────────┐", rule);
+    println!(
+        "│ {:<width$} │ Price            │ Quantity         │",
+        "Exchange"
+    );
+    println!("├{}┼──────────────────┼──────────────────┤", rule);
+    if !present {
+        println!(
+            "│ {:<width$} │ {:>16} │ {:>16} │",
+            MISSING, MISSING, MISSING
+        );
+    }
+    for row in rows {
+        println!(
+            "│ {:<width$} │ {:>16} │ {:>16} │",
+            row.exchange,
+            format_number(row.price, price_decimals),
+            format_number(row.amount, amount_decimals)
+        );
+    }
+    println!("└{}┴──────────────────┴──────────────────┘", rule);
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Initialize tracing
     tracing_subscriber::fmt::init();
+    let args = Args::parse();
+
+    if let Some(Command::Smoke { server }) = args.command {
+        let channel = Channel::from_shared(server)?.connect().await?;
+        let results = smoke::run(channel).await;
+        print!("{}", smoke::render(&results));
+        if !results.iter().all(|r| r.passed()) {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
 
     // Connect to the gRPC server
     let channel = Channel::from_static("http://127.0.0.1:5002")
         .connect()
         .await?;
-    let mut client = OrderbookAggregatorClient::new(channel);
+    let mut client = MarketDataClient::new(channel);
 
     println!("Connected to gRPC server. Starting to receive orderbook updates...");
 
     // Hide cursor for cleaner display
     print!("\x1B[?25l");
 
-    // Create an empty request
-    let request = Request::new(Empty {});
+    let depth_unit = match args.depth_unit {
+        Unit::PriceLevels => DepthUnit::PriceLevels,
+        Unit::Entries => DepthUnit::Entries,
+    };
+    let request = Request::new(SummaryRequest {
+        depth_unit: depth_unit as i32,
+        invert: args.invert,
+        ..Default::default()
+    });
 
     // Call the streaming RPC
-    let mut stream = client.book_summary(request).await?.into_inner();
+    let mut stream = client
+        .book_summary(request)
+        .await
+        .map_err(|status| grpc_error::describe(&status))?
+        .into_inner();
+
+    // Resolved once from the first non-empty summary so columns don't jump around
+    let mut precision: Option<(usize, usize)> = None;
+    let mut checksum_mismatches = 0u64;
 
     while let Some(result) = stream.next().await {
         match result {
             Ok(summary) => {
+                let levels = || summary.bids.iter().chain(summary.asks.iter());
+                let (price_decimals, amount_decimals) = match precision {
+                    Some(p) => p,
+                    None => {
+                        let p = (
+                            args.price_decimals.resolve(levels().map(|l| l.price)),
+                            args.amount_decimals.resolve(levels().map(|l| l.amount)),
+                        );
+                        if levels().next().is_some() {
+                            precision = Some(p);
+                        }
+                        p
+                    }
+                };
+
                 // Move cursor to top without clearing screen
                 print!("\x1B[1;1H");
 
@@ -42,44 +232,90 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 println!("╔══════════════════════════════════════════════════════════════╗");
                 println!("║                    ORDERBOOK AGGREGATOR                     ║");
                 println!("╚══════════════════════════════════════════════════════════════╝");
+                if summary.inverted {
+                    println!("🔁 Inverted: prices in base per quote, quantities in quote");
+                }
+                // Padded so the banner going away blanks the line
+                println!("{:<64}", describe_quality(&summary));
                 println!();
 
-                // Spread
-                println!("📊 Spread: {:.8}", summary.spread);
-                println!();
-
-                // Asks (Sell orders)
-                println!("🔴 ASKS (Sell Orders)");
-                println!("┌─────────────┬──────────────┬──────────────┐");
-                println!("│ Exchange    │ Price        │ Quantity     │");
-                println!("├─────────────┼──────────────┼──────────────┤");
-                for ask in &summary.asks {
+                // Spread, with the signed value when the server reports crossings otherwise;
+                // padded so the flag going away blanks it
+                let crossed = match summary.signed_spread {
+                    Some(signed) if summary.crossed && summary.spread != Some(signed) => {
+                        format!(" (crossed: {})", format_number(signed, price_decimals))
+                    }
+                    _ if summary.crossed => " (crossed)".to_string(),
+                    _ => String::new(),
+                };
+                println!(
+                    "📊 Spread: {:<40}",
+                    format!(
+                        "{}{}",
+                        format_optional(summary.spread, price_decimals),
+                        crossed
+                    )
+                );
+                if let Some(index_price) = summary.index_price {
+                    println!("📈 Index: {}", format_number(index_price, price_decimals));
+                }
+                // Padded so a shorter reason overwrites the previous one
+                println!(
+                    "📨 Reason: {:<40}",
+                    describe_reason(summary.reason.as_ref())
+                );
+                if summary.smart_best_bid.is_some() || summary.smart_best_ask.is_some() {
                     println!(
-                        "│ {:<11} │ {:<12.8} │ {:<12.8} │",
-                        ask.exchange, ask.price, ask.amount
+                        "🎯 Smart best: {} / {}",
+                        format_optional(summary.smart_best_bid, price_decimals),
+                        format_optional(summary.smart_best_ask, price_decimals)
                     );
                 }
-                println!("└─────────────┴──────────────┴──────────────┘");
-                println!();
-
-                // Bids (Buy orders)
-                println!("🟢 BIDS (Buy Orders)");
-                println!("┌─────────────┬──────────────┬──────────────┐");
-                println!("│ Exchange    │ Price        │ Quantity     │");
-                println!("├─────────────┼──────────────┼──────────────┤");
-                for bid in &summary.bids {
+                if args.verify_checksum {
+                    let computed = checksum(
+                        summary.bids.iter().map(checksum_entry),
+                        summary.asks.iter().map(checksum_entry),
+                    );
+                    let verdict = if computed == summary.checksum {
+                        "verified"
+                    } else {
+                        checksum_mismatches += 1;
+                        tracing::warn!(
+                            "Summary version {}: checksum {:08x} but the ladder received checksums to {:08x}",
+                            summary.version,
+                            summary.checksum,
+                            computed
+                        );
+                        "MISMATCH"
+                    };
                     println!(
-                        "│ {:<11} │ {:<12.8} │ {:<12.8} │",
-                        bid.exchange, bid.price, bid.amount
+                        "🔒 Checksum: {:08x} {} ({} mismatches so far)",
+                        summary.checksum, verdict, checksum_mismatches
                     );
                 }
-                println!("└─────────────┴──────────────┴──────────────┘");
+                println!();
+
+                print_side(
+                    "🔴 ASKS (Sell Orders)",
+                    summary.asks_present,
+                    &rows(&summary.asks, args.consolidate),
+                    price_decimals,
+                    amount_decimals,
+                );
+                println!();
+                print_side(
+                    "🟢 BIDS (Buy Orders)",
+                    summary.bids_present,
+                    &rows(&summary.bids, args.consolidate),
+                    price_decimals,
+                    amount_decimals,
+                );
 
                 // Move cursor to bottom and flush output
                 println!("\n");
             }
             Err(e) => {
-                eprintln!("Error receiving update: {}", e);
+                eprintln!("Error receiving update: {}", grpc_error::describe(&e));
                 break;
             }
         }
@@ -1,4 +1,34 @@
+#[path = "src/modules/build_env.rs"]
+mod build_env;
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "grpc")]
     tonic_build::compile_protos("protos/orderbook.proto")?;
+
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let built_at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let features: Vec<String> = std::env::vars()
+        .filter_map(|(key, _)| Some(key.strip_prefix("CARGO_FEATURE_")?.to_lowercase()))
+        .filter(|feature| feature != "default")
+        .collect();
+    println!(
+        "cargo:rustc-env=BUILD_GIT_HASH={}",
+        build_env::git_hash("git")
+    );
+    println!("cargo:rustc-env=BUILD_TIMESTAMP={}", built_at);
+    println!(
+        "cargo:rustc-env=BUILD_RUSTC_VERSION={}",
+        build_env::rustc_version(&rustc)
+    );
+    println!(
+        "cargo:rustc-env=BUILD_FEATURES={}",
+        build_env::feature_list(features)
+    );
+    println!("cargo:rerun-if-changed=protos/orderbook.proto");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
     Ok(())
 }
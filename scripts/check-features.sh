@@ -0,0 +1,13 @@
+#!/usr/bin/env bash
+# Build, lint and test every supported feature combination
+set -euo pipefail
+
+for features in "core" "core,connectors" "core,grpc"; do
+    echo "== --no-default-features --features $features"
+    cargo clippy --no-default-features --features "$features" --all-targets -- -D warnings
+    cargo test --no-default-features --features "$features"
+done
+
+echo "== default features"
+cargo clippy --all-targets -- -D warnings
+cargo test
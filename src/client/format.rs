@@ -0,0 +1,211 @@
+use std::str::FromStr;
+
+/// Significant digits shown when the precision is inferred
+const AUTO_SIGNIFICANT_DIGITS: i32 = 6;
+const MAX_DECIMALS: usize = 12;
+
+/// Number of decimals to print, either fixed or inferred from the first received values
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decimals {
+    Auto,
+    Fixed(usize),
+}
+
+impl FromStr for Decimals {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(Decimals::Auto);
+        }
+        s.parse::<usize>()
+            .map(|d| Decimals::Fixed(d.min(MAX_DECIMALS)))
+            .map_err(|_| format!("invalid decimals '{}' (expected a number or 'auto')", s))
+    }
+}
+
+impl Decimals {
+    /// Resolve `Auto` against a sample of values, keeping `Fixed` as is
+    pub fn resolve(self, sample: impl IntoIterator<Item = f64>) -> usize {
+        match self {
+            Decimals::Fixed(d) => d,
+            Decimals::Auto => {
+                let largest = sample
+                    .into_iter()
+                    .filter(|v| v.is_finite())
+                    .map(f64::abs)
+                    .fold(0.0, f64::max);
+                auto_decimals(largest)
+            }
+        }
+    }
+}
+
+/// Decimals needed to show `AUTO_SIGNIFICANT_DIGITS` significant digits of `magnitude`
+pub fn auto_decimals(magnitude: f64) -> usize {
+    if magnitude <= 0.0 || !magnitude.is_finite() {
+        return 8;
+    }
+    let exponent = magnitude.log10().floor() as i32;
+    (AUTO_SIGNIFICANT_DIGITS - 1 - exponent).clamp(0, MAX_DECIMALS as i32) as usize
+}
+
+/// Format with a fixed number of decimals and thousands separators in the integer part
+pub fn format_number(value: f64, decimals: usize) -> String {
+    let formatted = format!("{:.*}", decimals, value.abs());
+    let (int_part, frac_part) = match formatted.split_once('.') {
+        Some((int_part, frac_part)) => (int_part, Some(frac_part)),
+        None => (formatted.as_str(), None),
+    };
+
+    let mut grouped = String::with_capacity(formatted.len() + int_part.len() / 3 + 1);
+    if value.is_sign_negative() && formatted.chars().any(|c| c != '0' && c != '.') {
+        grouped.push('-');
+    }
+    for (i, digit) in int_part.chars().enumerate() {
+        if i > 0 && (int_part.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    if let Some(frac_part) = frac_part {
+        grouped.push('.');
+        grouped.push_str(frac_part);
+    }
+    grouped
+}
+
+/// Shown for a value the server left unset, e.g. the spread of a one-sided book
+pub const MISSING: &str = "—";
+
+/// [`format_number`], or [`MISSING`] for `None`
+pub fn format_optional(value: Option<f64>, decimals: usize) -> String {
+    value.map_or_else(
+        || MISSING.to_string(),
+        |value| format_number(value, decimals),
+    )
+}
+
+/// One displayed ladder row
+#[derive(Clone, Debug, PartialEq)]
+pub struct Row {
+    pub exchange: String,
+    pub price: f64,
+    pub amount: f64,
+}
+
+/// Merge `(exchange, price, amount)` levels quoting exactly the same price into one row with
+/// the summed amount, labelled with the sorted exchanges joined by '+'. Rows keep the order
+/// of the first level at each price, so a ladder sorted best-first stays sorted.
+pub fn consolidate<'a>(levels: impl IntoIterator<Item = (&'a str, f64, f64)>) -> Vec<Row> {
+    let mut merged: Vec<(f64, f64, Vec<&'a str>)> = vec![];
+    for (exchange, price, amount) in levels {
+        match merged.iter_mut().find(|(p, _, _)| *p == price) {
+            Some((_, total, exchanges)) => {
+                *total += amount;
+                if !exchanges.contains(&exchange) {
+                    exchanges.push(exchange);
+                }
+            }
+            None => merged.push((price, amount, vec![exchange])),
+        }
+    }
+    merged
+        .into_iter()
+        .map(|(price, amount, mut exchanges)| {
+            exchanges.sort_unstable();
+            Row {
+                exchange: exchanges.join("+"),
+                price,
+                amount,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auto_decimals_follow_magnitude() {
+        assert_eq!(auto_decimals(0.0000001), 12);
+        assert_eq!(auto_decimals(0.067), 7);
+        assert_eq!(auto_decimals(61234.5), 1);
+        assert_eq!(auto_decimals(2_500_000.0), 0);
+    }
+
+    #[test]
+    fn formats_representative_magnitudes() {
+        assert_eq!(
+            format_number(0.0000001, auto_decimals(0.0000001)),
+            "0.000000100000"
+        );
+        assert_eq!(format_number(0.067, auto_decimals(0.067)), "0.0670000");
+        assert_eq!(format_number(61234.5, auto_decimals(61234.5)), "61,234.5");
+        assert_eq!(format_number(1234567.891, 2), "1,234,567.89");
+        assert_eq!(format_number(-1234.5, 1), "-1,234.5");
+        assert_eq!(format_number(-0.0000001, 2), "0.00");
+        assert_eq!(format_number(999.0, 0), "999");
+        assert_eq!(format_optional(Some(1234.5), 1), "1,234.5");
+        assert_eq!(format_optional(None, 1), "—");
+    }
+
+    #[test]
+    fn resolve_uses_largest_sample_for_auto() {
+        assert_eq!(Decimals::Auto.resolve([61234.5, 61230.0]), 1);
+        assert_eq!(Decimals::Fixed(3).resolve([61234.5]), 3);
+        assert_eq!("auto".parse(), Ok(Decimals::Auto));
+        assert_eq!("4".parse(), Ok(Decimals::Fixed(4)));
+        assert!("four".parse::<Decimals>().is_err());
+    }
+
+    fn row(exchange: &str, price: f64, amount: f64) -> Row {
+        Row {
+            exchange: exchange.to_string(),
+            price,
+            amount,
+        }
+    }
+
+    #[test]
+    fn consolidate_sums_equal_prices_under_a_combined_label() {
+        let asks = [
+            ("bitstamp", 0.05125, 0.75),
+            ("binance", 0.05125, 2.0),
+            ("binance", 0.05126, 1.0),
+        ];
+        assert_eq!(
+            consolidate(asks),
+            vec![
+                row("binance+bitstamp", 0.05125, 2.75),
+                row("binance", 0.05126, 1.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn consolidate_keeps_ladder_order_when_only_some_prices_overlap() {
+        let bids = [
+            ("binance", 0.0513, 1.0),
+            ("binance", 0.0512, 2.0),
+            ("bitstamp", 0.0512, 3.0),
+            ("bitstamp", 0.0511, 4.0),
+            ("binance", 0.0510, 5.0),
+            ("bitstamp", 0.0510, 6.0),
+        ];
+        assert_eq!(
+            consolidate(bids),
+            vec![
+                row("binance", 0.0513, 1.0),
+                row("binance+bitstamp", 0.0512, 5.0),
+                row("bitstamp", 0.0511, 4.0),
+                row("binance+bitstamp", 0.0510, 11.0),
+            ]
+        );
+        // Prices are compared exactly as received, so a near miss stays a separate row
+        let near = [("binance", 0.0512, 1.0), ("bitstamp", 0.05120000001, 1.0)];
+        assert_eq!(consolidate(near).len(), 2);
+        assert!(consolidate([]).is_empty());
+    }
+}
@@ -0,0 +1,436 @@
+use crate::grpc_error;
+use crate::grpc_service::orderbook::admin_client::AdminClient;
+use crate::grpc_service::orderbook::discovery_client::DiscoveryClient;
+use crate::grpc_service::orderbook::market_data_client::MarketDataClient;
+use crate::grpc_service::orderbook::{
+    BookStats, CircuitState, ConnectionState, DepthCurve, DepthCurveRequest, DepthPoint, Empty,
+    Level, ParseFailureList, ParseFailuresRequest, ServerInfo, StatusReport, Summary,
+    SummaryRequest, SymbolList, TimeRange, UptimeReport,
+};
+use crate::modules::checksum;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use tonic::transport::Channel;
+use tonic::{Code, Response, Status};
+
+/// Absolute slack on top of the price tick when comparing the spread to the ladder
+const FLOAT_TOLERANCE: f64 = 1e-9;
+const DEPTH_CURVE_POINTS: u32 = 10;
+const DEPTH_CURVE_RANGE_BPS: f64 = 100.0;
+
+/// Outcome of one RPC call and its response checks
+#[derive(Clone, Debug, PartialEq)]
+pub struct CheckResult {
+    pub rpc: &'static str,
+    /// `Ok(detail)` on pass, `Err(reason)` on failure
+    pub outcome: Result<String, String>,
+}
+
+impl CheckResult {
+    pub fn passed(&self) -> bool {
+        self.outcome.is_ok()
+    }
+}
+
+/// Call every RPC once and check each response. A served book is expected: the server only
+/// listens once its first snapshot is merged.
+pub async fn run(channel: Channel) -> Vec<CheckResult> {
+    let mut market_data = MarketDataClient::new(channel.clone());
+    let mut discovery = DiscoveryClient::new(channel.clone());
+    let mut admin = AdminClient::new(channel);
+    let mut results = vec![];
+
+    results.push(check(
+        "Discovery.GetServerInfo",
+        discovery
+            .get_server_info(Empty {})
+            .await
+            .map(Response::into_inner),
+        check_server_info,
+    ));
+
+    let symbols = discovery
+        .list_symbols(Empty {})
+        .await
+        .map(Response::into_inner);
+    let tick = symbols
+        .as_ref()
+        .ok()
+        .and_then(|s| s.symbols.first())
+        .map_or(0.0, |s| 1.0 / s.price_scale);
+    results.push(check("Discovery.ListSymbols", symbols, check_symbols));
+
+    let summary = match market_data.book_summary(SummaryRequest::default()).await {
+        Ok(stream) => match stream.into_inner().message().await {
+            Ok(Some(summary)) => Ok(summary),
+            Ok(None) => Err(Status::unavailable("stream ended before a Summary")),
+            Err(status) => Err(status),
+        },
+        Err(status) => Err(status),
+    };
+    results.push(check("MarketData.BookSummary", summary, |s| {
+        check_summary(s, tick)
+    }));
+
+    let request = DepthCurveRequest {
+        symbol: String::new(),
+        points: DEPTH_CURVE_POINTS,
+        range_bps: DEPTH_CURVE_RANGE_BPS,
+        ..Default::default()
+    };
+    results.push(check(
+        "MarketData.GetDepthCurve",
+        market_data
+            .get_depth_curve(request)
+            .await
+            .map(Response::into_inner),
+        check_depth_curve,
+    ));
+    results.push(check(
+        "MarketData.GetBookStats",
+        market_data
+            .get_book_stats(Empty {})
+            .await
+            .map(Response::into_inner),
+        check_book_stats,
+    ));
+    results.push(check(
+        "Discovery.GetStatus",
+        discovery
+            .get_status(Empty {})
+            .await
+            .map(Response::into_inner),
+        check_status,
+    ));
+    results.push(check(
+        "Discovery.GetUptimeReport",
+        discovery
+            .get_uptime_report(TimeRange::default())
+            .await
+            .map(Response::into_inner),
+        check_uptime,
+    ));
+
+    // Admin may be disabled or moved to its own listener; that is not a failure
+    let failures = admin
+        .get_parse_failures(ParseFailuresRequest::default())
+        .await
+        .map(Response::into_inner);
+    results.push(match failures {
+        Err(status) if status.code() == Code::Unimplemented => CheckResult {
+            rpc: "Admin.GetParseFailures",
+            outcome: Ok("not served on this listener".to_string()),
+        },
+        failures => check("Admin.GetParseFailures", failures, check_parse_failures),
+    });
+    results
+}
+
+fn check<T>(
+    rpc: &'static str,
+    response: Result<T, Status>,
+    validate: impl FnOnce(&T) -> Result<String, String>,
+) -> CheckResult {
+    let outcome = match response {
+        Ok(response) => validate(&response),
+        Err(status) => Err(grpc_error::describe(&status)),
+    };
+    CheckResult { rpc, outcome }
+}
+
+/// Pass/fail table, one row per RPC
+pub fn render(results: &[CheckResult]) -> String {
+    let width = results.iter().map(|r| r.rpc.len()).max().unwrap_or(0);
+    let mut table = String::new();
+    for result in results {
+        let (verdict, detail) = match &result.outcome {
+            Ok(detail) => ("PASS", detail),
+            Err(reason) => ("FAIL", reason),
+        };
+        let _ = writeln!(table, "{:<width$}  {}  {}", result.rpc, verdict, detail);
+    }
+    let failed = results.iter().filter(|r| !r.passed()).count();
+    let _ = writeln!(table, "{} checks, {} failed", results.len(), failed);
+    table
+}
+
+/// Identifies the build under test: version, hash, compiler and uptime
+pub fn check_server_info(info: &ServerInfo) -> Result<String, String> {
+    if info.version.is_empty() || info.git_hash.is_empty() || info.rustc_version.is_empty() {
+        return Err("missing version, git hash or rustc version".to_string());
+    }
+    if info.symbols.is_empty() {
+        return Err("no symbols configured".to_string());
+    }
+    Ok(format!(
+        "{} ({}), up {}s",
+        info.build_id,
+        info.rustc_version,
+        info.uptime_ms / 1_000
+    ))
+}
+
+pub fn check_symbols(list: &SymbolList) -> Result<String, String> {
+    let symbol = list.symbols.first().ok_or("no symbols served")?;
+    if symbol.symbol.is_empty() {
+        return Err("empty symbol name".to_string());
+    }
+    if !(symbol.price_scale.is_finite() && symbol.price_scale > 0.0) {
+        return Err(format!(
+            "price_scale {} is not positive",
+            symbol.price_scale
+        ));
+    }
+    Ok(format!(
+        "{} symbol(s), serving {}",
+        list.symbols.len(),
+        symbol.symbol
+    ))
+}
+
+/// Non-empty ordered ladders with positive amounts, both sides flagged present, and a
+/// spread matching best ask minus best bid to within `tick` (prices are bucketed to the
+/// tick before the spread is taken)
+pub fn check_summary(summary: &Summary, tick: f64) -> Result<String, String> {
+    let (best_bid, best_ask) = match (summary.bids.first(), summary.asks.first()) {
+        (Some(bid), Some(ask)) => (bid.price, ask.price),
+        _ => return Err("empty ladder on a served book".to_string()),
+    };
+    if !(summary.bids_present && summary.asks_present) {
+        return Err(format!(
+            "levels on both sides but bids_present={} asks_present={}",
+            summary.bids_present, summary.asks_present
+        ));
+    }
+    // `spread` may be clamped or made absolute on a crossed book; servers that predate
+    // `signed_spread` only send the signed value
+    let Some(spread) = summary.signed_spread.or(summary.spread) else {
+        return Err("no spread with levels on both sides".to_string());
+    };
+    if !summary.bids.windows(2).all(|w| w[0].price >= w[1].price) {
+        return Err("bids are not sorted best first".to_string());
+    }
+    if !summary.asks.windows(2).all(|w| w[0].price <= w[1].price) {
+        return Err("asks are not sorted best first".to_string());
+    }
+    let levels = || summary.bids.iter().chain(&summary.asks);
+    if let Some(level) = levels().find(|l| !(l.amount > 0.0 && l.price > 0.0)) {
+        return Err(format!(
+            "{} level at {} has amount {}",
+            level.exchange, level.price, level.amount
+        ));
+    }
+    if let Some(level) = levels().find(|l| l.exchange.is_empty()) {
+        return Err(format!("level at {} has no exchange", level.price));
+    }
+    let expected = best_ask - best_bid;
+    if (spread - expected).abs() > tick + FLOAT_TOLERANCE {
+        return Err(format!(
+            "spread {} but best ask - best bid is {}",
+            spread, expected
+        ));
+    }
+    check_checksum(summary)?;
+    Ok(format!(
+        "{} bids, {} asks, spread {}",
+        summary.bids.len(),
+        summary.asks.len(),
+        spread
+    ))
+}
+
+/// Checksum of the ladder as received
+pub fn summary_checksum(summary: &Summary) -> u32 {
+    fn entry(level: &Level) -> (&str, f64, f64) {
+        (level.exchange.as_str(), level.price, level.amount)
+    }
+    checksum::checksum(
+        summary.bids.iter().map(entry),
+        summary.asks.iter().map(entry),
+    )
+}
+
+/// Recompute the checksum of the ladder as received and compare it with the one sent
+pub fn check_checksum(summary: &Summary) -> Result<u32, String> {
+    let computed = summary_checksum(summary);
+    if computed != summary.checksum {
+        return Err(format!(
+            "checksum {:08x} but the ladder received checksums to {:08x}",
+            summary.checksum, computed
+        ));
+    }
+    Ok(computed)
+}
+
+pub fn check_depth_curve(curve: &DepthCurve) -> Result<String, String> {
+    let non_decreasing = |points: &[DepthPoint]| {
+        points
+            .windows(2)
+            .all(|w| w[0].cumulative_amount <= w[1].cumulative_amount)
+    };
+    if !non_decreasing(&curve.bids) || !non_decreasing(&curve.asks) {
+        return Err("cumulative depth decreases away from the mid".to_string());
+    }
+    Ok(format!(
+        "{} bid / {} ask points",
+        curve.bids.len(),
+        curve.asks.len()
+    ))
+}
+
+pub fn check_book_stats(stats: &BookStats) -> Result<String, String> {
+    if !stats.exchange_share.is_empty() {
+        let total: f64 = stats.exchange_share.values().sum();
+        if (total - 1.0).abs() > 1e-6 {
+            return Err(format!("exchange shares sum to {}", total));
+        }
+    }
+    Ok(format!("version {}", stats.version))
+}
+
+pub fn check_status(report: &StatusReport) -> Result<String, String> {
+    let mut seen = BTreeSet::new();
+    for status in &report.exchanges {
+        if status.exchange.is_empty() || !seen.insert(status.exchange.as_str()) {
+            return Err(format!(
+                "missing or duplicate exchange '{}'",
+                status.exchange
+            ));
+        }
+        if ConnectionState::try_from(status.connection).is_err() {
+            return Err(format!(
+                "{} has unknown connection state {}",
+                status.exchange, status.connection
+            ));
+        }
+        if CircuitState::try_from(status.circuit).is_err() {
+            return Err(format!(
+                "{} has unknown circuit state {}",
+                status.exchange, status.circuit
+            ));
+        }
+        if status.consecutive_failures > status.total_failures {
+            return Err(format!(
+                "{} has more consecutive than total failures",
+                status.exchange
+            ));
+        }
+    }
+    Ok(format!("{} exchange(s)", report.exchanges.len()))
+}
+
+pub fn check_uptime(report: &UptimeReport) -> Result<String, String> {
+    if report.from > report.to {
+        return Err(format!("range {}..{} is reversed", report.from, report.to));
+    }
+    for (exchange, uptime) in &report.exchanges {
+        let in_range = |f: f64| (0.0..=1.0).contains(&f);
+        if !in_range(uptime.connected)
+            || !in_range(uptime.contributing)
+            || uptime.contributing > uptime.connected
+        {
+            return Err(format!(
+                "{} uptime connected {} / contributing {} is inconsistent",
+                exchange, uptime.connected, uptime.contributing
+            ));
+        }
+    }
+    Ok(format!("{} exchange(s)", report.exchanges.len()))
+}
+
+pub fn check_parse_failures(list: &ParseFailureList) -> Result<String, String> {
+    match list.failures.iter().find(|f| f.exchange.is_empty()) {
+        Some(failure) => Err(format!("failure at {} has no exchange", failure.at)),
+        None => Ok(format!("{} sample(s)", list.failures.len())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grpc_service::orderbook::{ExchangeStatus, ExchangeUptime};
+
+    fn level(price: f64, amount: f64) -> Level {
+        Level {
+            exchange: "binance".to_string(),
+            price,
+            amount,
+            detail: None,
+            raw_price: None,
+        }
+    }
+
+    fn summary(spread: f64) -> Summary {
+        let mut summary = Summary {
+            spread: Some(spread),
+            bids_present: true,
+            asks_present: true,
+            bids: vec![level(100.0, 1.0), level(99.0, 2.0)],
+            asks: vec![level(101.0, 1.0), level(102.0, 3.0)],
+            ..Default::default()
+        };
+        summary.checksum = summary_checksum(&summary);
+        summary
+    }
+
+    #[test]
+    fn summary_checks_catch_spread_ordering_and_empty_ladders() {
+        assert!(check_summary(&summary(1.0), 0.01).is_ok());
+        assert!(
+            check_summary(&summary(1.005), 0.01).is_ok(),
+            "within a tick"
+        );
+        let err = check_summary(&summary(2.0), 0.01).unwrap_err();
+        assert!(err.contains("best ask - best bid"), "{}", err);
+
+        let mut unsorted = summary(1.0);
+        unsorted.bids.reverse();
+        assert!(check_summary(&unsorted, 0.01).is_err());
+
+        let mut empty = summary(1.0);
+        empty.asks.clear();
+        assert!(check_summary(&empty, 0.01).is_err());
+        let mut unflagged = summary(1.0);
+        unflagged.asks_present = false;
+        assert!(check_summary(&unflagged, 0.01).is_err());
+        let mut no_spread = summary(1.0);
+        no_spread.spread = None;
+        assert!(check_summary(&no_spread, 0.01).is_err());
+
+        let mut corrupted = summary(1.0);
+        corrupted.bids[1].amount = 2.5;
+        let err = check_summary(&corrupted, 0.01).unwrap_err();
+        assert!(err.contains("checksum"), "{}", err);
+    }
+
+    #[test]
+    fn status_and_uptime_checks_reject_malformed_entries() {
+        let status = |exchange: &str, connection: i32| ExchangeStatus {
+            exchange: exchange.to_string(),
+            connection,
+            ..Default::default()
+        };
+        let report = |exchanges| StatusReport {
+            exchanges,
+            ..Default::default()
+        };
+        assert!(check_status(&report(vec![status("binance", 1), status("bitstamp", 0)])).is_ok());
+        assert!(check_status(&report(vec![status("binance", 1), status("binance", 1)])).is_err());
+        assert!(check_status(&report(vec![status("binance", 42)])).is_err());
+
+        let uptime = |connected, contributing| UptimeReport {
+            exchanges: [(
+                "binance".to_string(),
+                ExchangeUptime {
+                    connected,
+                    contributing,
+                },
+            )]
+            .into(),
+            ..Default::default()
+        };
+        assert!(check_uptime(&uptime(0.9, 0.8)).is_ok());
+        assert!(check_uptime(&uptime(0.5, 0.8)).is_err());
+        assert!(check_uptime(&uptime(1.5, 0.8)).is_err());
+    }
+}
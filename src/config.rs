@@ -0,0 +1,1880 @@
+use crate::modules::alarms::AlarmConfig;
+use crate::modules::binance::{BinanceEndpoint, BinanceVariant};
+use crate::modules::bitfinex::BitfinexEndpoint;
+use crate::modules::bitstamp::BitstampEndpoint;
+use crate::modules::bybit::BybitEndpoint;
+use crate::modules::coinbase::CoinbaseEndpoint;
+use crate::modules::consistency::SummaryConsistency;
+use crate::modules::gateio::GateIoEndpoint;
+use crate::modules::kraken::KrakenEndpoint;
+use crate::modules::kucoin::KucoinEndpoint;
+use crate::modules::okx::OkxEndpoint;
+use crate::modules::one_sided::OneSidedSummaries;
+use crate::modules::sequence_reset::SequenceResetConfig;
+use crate::modules::spread_convention::SpreadConvention;
+use crate::modules::tie_break::{TieBreak, TieBreaker};
+use crate::modules::types::{AggregatedOrderBook, Exchange};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::{Mutex, RwLock};
+use tracing_subscriber::filter::LevelFilter;
+
+mod env;
+pub use env::{ENV_PREFIX, EnvLayer};
+
+/// Public gRPC listener when `grpc_listen` is unset
+pub const DEFAULT_GRPC_LISTEN: &str = "127.0.0.1:5002";
+
+pub const DEFAULT_PRICE_SCALE: f64 = 1_000_000_000.0;
+
+/// Quote currencies recognized at the end of an instrument code, longest first
+const QUOTE_CURRENCIES: [&str; 12] = [
+    "fdusd", "usdt", "usdc", "busd", "tusd", "usd", "eur", "gbp", "try", "btc", "eth", "bnb",
+];
+
+/// Quote currency of an instrument code like "BTCUSDT", if it ends in a known one
+pub fn quote_currency(instrument: &str) -> Option<&'static str> {
+    let instrument = instrument.to_lowercase();
+    QUOTE_CURRENCIES
+        .into_iter()
+        .find(|quote| instrument.len() > quote.len() && instrument.ends_with(quote))
+}
+
+/// `instrument` upper-cased with base and quote split by `-`, e.g. `ETH-BTC` for ethbtc, as
+/// Coinbase and OKX name them. A code already split, or with no known quote, is only
+/// upper-cased.
+pub fn dashed_instrument(instrument: &str) -> String {
+    let instrument = instrument.to_uppercase();
+    if instrument.contains('-') {
+        return instrument;
+    }
+    match quote_currency(&instrument) {
+        Some(quote) => {
+            let (base, quote) = instrument.split_at(instrument.len() - quote.len());
+            format!("{}-{}", base, quote)
+        }
+        None => instrument,
+    }
+}
+
+fn is_toml(path: &str) -> bool {
+    path.ends_with(".toml")
+}
+
+fn read_file(path: &str) -> Result<String, String> {
+    std::fs::read_to_string(path).map_err(|e| format!("failed to read config {}: {}", path, e))
+}
+
+/// Config key of an exchange in per-symbol `exchanges` overrides
+fn exchange_key(exchange: Exchange) -> &'static str {
+    match exchange {
+        Exchange::Binance | Exchange::BinanceUs => "binance",
+        Exchange::Bitstamp => "bitstamp",
+        Exchange::Kraken => "kraken",
+        Exchange::Coinbase => "coinbase",
+        Exchange::Okx => "okx",
+        Exchange::Bybit => "bybit",
+        Exchange::Kucoin => "kucoin",
+        Exchange::GateIo => "gateio",
+        Exchange::Bitfinex => "bitfinex",
+    }
+}
+
+/// Book tuning knobs. `defaults` in the config file sets them globally and
+/// `symbols.<symbol>` sections override individual values per pair.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct BookSettings {
+    /// Prices are bucketed by `round(price * price_scale)`, i.e. the tick is `1 / price_scale`
+    pub price_scale: f64,
+    /// Keep at most this many price levels per side
+    pub max_depth: Option<usize>,
+    /// Levels with an amount below this are treated as removals
+    pub dust_threshold: f64,
+    /// Ignore incoming levels further than this from the current mid price (in basis points)
+    pub outlier_tolerance_bps: Option<f64>,
+    /// Drop an exchange's levels and resync if it hasn't sent data for this many milliseconds
+    pub stale_after_ms: Option<u64>,
+    /// Weight of each exchange's mid in the index price; unlisted exchanges weigh 1.0
+    pub index_weights: BTreeMap<String, f64>,
+    /// How much of each exchange's displayed size (0 to 1) counts in consolidated figures:
+    /// cumulative Summaries, the depth curve, imbalance, book shape and the aggregated
+    /// VWAP. Unlisted exchanges weigh 1.0; per-exchange figures stay raw.
+    pub depth_weights: BTreeMap<String, f64>,
+    /// Which exchange comes first at a price several quote: "alphabetical", "larger_size"
+    /// or "venue_priority"
+    pub tie_break: TieBreak,
+    /// Exchanges in order of preference for the "venue_priority" tie-break
+    pub venue_priority: Vec<String>,
+    /// Report per side the best price whose cumulative size from the top reaches this
+    pub smart_best_min_qty: Option<f64>,
+    /// Leave levels further than this from the mid (in basis points) out of derived metrics:
+    /// imbalance, book shape and the depth curve, which also stops sampling here. The ladder
+    /// keeps them.
+    pub max_level_distance_bps: Option<f64>,
+    /// After each (re)subscription, reconnect if the Bitstamp diff channel sends no data
+    /// within this many milliseconds. Quiet pairs need a longer window.
+    pub first_data_timeout_ms: Option<u64>,
+    /// The instrument's real tick; fetched from Binance exchangeInfo when `round_to_tick` is
+    /// set without one
+    pub tick_size: Option<f64>,
+    /// Snap incoming prices to `tick_size` (bids down, asks up) so venues quoting more
+    /// decimals consolidate into the same levels; each exchange's own price is kept
+    pub round_to_tick: bool,
+}
+
+impl BookSettings {
+    /// Share of `exchange`'s amounts counted in consolidated figures
+    pub fn depth_weight(&self, exchange: &str) -> f64 {
+        self.depth_weights.get(exchange).copied().unwrap_or(1.0)
+    }
+
+    /// The depth weights that change anything, to label weighted outputs with
+    pub fn applied_depth_weights(&self) -> BTreeMap<String, f64> {
+        self.depth_weights
+            .iter()
+            .filter(|(_, weight)| **weight != 1.0)
+            .map(|(exchange, weight)| (exchange.clone(), *weight))
+            .collect()
+    }
+
+    pub fn tie_breaker(&self) -> TieBreaker<'_> {
+        TieBreaker {
+            policy: self.tie_break,
+            priority: &self.venue_priority,
+        }
+    }
+}
+
+impl Default for BookSettings {
+    fn default() -> Self {
+        Self {
+            price_scale: DEFAULT_PRICE_SCALE,
+            max_depth: None,
+            dust_threshold: 0.0,
+            outlier_tolerance_bps: None,
+            stale_after_ms: None,
+            index_weights: BTreeMap::new(),
+            depth_weights: BTreeMap::new(),
+            tie_break: TieBreak::default(),
+            venue_priority: vec![],
+            smart_best_min_qty: None,
+            max_level_distance_bps: None,
+            first_data_timeout_ms: None,
+            tick_size: None,
+            round_to_tick: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SymbolOverrides {
+    pub price_scale: Option<f64>,
+    pub max_depth: Option<usize>,
+    pub dust_threshold: Option<f64>,
+    pub outlier_tolerance_bps: Option<f64>,
+    pub stale_after_ms: Option<u64>,
+    pub index_weights: Option<BTreeMap<String, f64>>,
+    pub depth_weights: Option<BTreeMap<String, f64>>,
+    pub tie_break: Option<TieBreak>,
+    pub venue_priority: Option<Vec<String>>,
+    pub smart_best_min_qty: Option<f64>,
+    pub max_level_distance_bps: Option<f64>,
+    pub first_data_timeout_ms: Option<u64>,
+    pub tick_size: Option<f64>,
+    pub round_to_tick: Option<bool>,
+    /// Instrument code per exchange ("binance", "bitstamp") where it isn't the symbol itself,
+    /// e.g. BTCUSDT on Binance for a btcusd book. The book and the API keep the symbol.
+    pub exchanges: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct AppConfig {
+    /// Binance deployment to connect to: "global" (default) or "us"
+    pub binance_variant: BinanceVariant,
+    /// Connectors to run: "binance" (the configured variant) and/or "bitstamp"; unset runs both
+    pub exchanges: Option<Vec<String>>,
+    /// Maximum log level (error, warn, info, debug, trace); unset keeps the startup level
+    pub log_level: Option<String>,
+    /// Public gRPC listener as "host:port"; unset listens on 127.0.0.1:5002
+    pub grpc_listen: Option<String>,
+    /// Several public gRPC listeners, each with its own TLS and auth, instead of
+    /// `grpc_listen`
+    pub grpc_listeners: Vec<GrpcListenerConfig>,
+    /// Base URL overrides for the exchange APIs (e.g. a proxy or a local mock)
+    pub endpoints: EndpointOverrides,
+    pub defaults: BookSettings,
+    pub symbols: BTreeMap<String, SymbolOverrides>,
+    pub admin: AdminConfig,
+    /// When a backwards update id counts as the exchange restarting its sequence, per
+    /// exchange ("binance", "bitstamp"); unlisted exchanges use the defaults
+    pub sequence_reset: BTreeMap<String, SequenceResetConfig>,
+    /// Whether Summary streams send one-sided books: "emit" (default) or "hold" until both
+    /// sides have levels again
+    pub one_sided_summaries: OneSidedSummaries,
+    /// What Summary streams do with a Summary whose spread, ordering or amounts don't add
+    /// up: "fix_spread" (default) or "skip"
+    pub summary_consistency: SummaryConsistency,
+    /// How Summaries and the spread history report a crossed book's spread: "signed"
+    /// (default), "clamp_zero" or "absolute"
+    pub spread_convention: SpreadConvention,
+    /// Notifiers, and which alarm kinds go to which of them
+    pub alarms: AlarmConfig,
+}
+
+/// Where the gRPC Admin service is reachable. By default it shares the public listener.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct AdminConfig {
+    pub enabled: bool,
+    /// Serve Admin only here instead: "host:port", or "unix:/path/to/socket"
+    pub listen: Option<String>,
+}
+
+impl Default for AdminConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            listen: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl ListenAddr {
+    /// Parse `listen`, the value of config key `field`: "host:port" or "unix:/path"
+    pub fn parse(field: &str, listen: &str) -> Result<Self, String> {
+        if let Some(path) = listen.strip_prefix("unix:") {
+            if path.is_empty() {
+                return Err(format!(
+                    "invalid config: {} has an empty socket path",
+                    field
+                ));
+            }
+            return Ok(ListenAddr::Unix(PathBuf::from(path)));
+        }
+        listen.parse().map(ListenAddr::Tcp).map_err(|_| {
+            format!(
+                "invalid config: {} '{}' is neither host:port nor unix:<path>",
+                field, listen
+            )
+        })
+    }
+}
+
+impl std::fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ListenAddr::Tcp(addr) => write!(f, "{}", addr),
+            ListenAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+impl AdminConfig {
+    /// The separate Admin listener, if one is configured and Admin is enabled
+    pub fn listener(&self) -> Result<Option<ListenAddr>, String> {
+        let Some(listen) = self.listen.as_deref().filter(|_| self.enabled) else {
+            return Ok(None);
+        };
+        ListenAddr::parse("admin.listen", listen).map(Some)
+    }
+}
+
+/// One entry of `grpc_listeners`
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GrpcListenerConfig {
+    /// "host:port" (IPv6 as "[::1]:5002") or "unix:/path/to/socket"
+    pub listen: String,
+    /// Serve TLS with this PEM certificate chain and key; TCP listeners only
+    pub tls: Option<TlsConfig>,
+    /// Require `authorization: Bearer <token>` on every call made through this listener
+    pub auth_token: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+/// A validated public gRPC listener
+#[derive(Clone, Debug, PartialEq)]
+pub struct GrpcListener {
+    pub addr: ListenAddr,
+    pub tls: Option<TlsConfig>,
+    pub auth_token: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EndpointOverrides {
+    pub binance_rest: Option<String>,
+    pub binance_ws: Option<String>,
+    pub bitstamp_rest: Option<String>,
+    pub bitstamp_ws: Option<String>,
+    pub kraken_rest: Option<String>,
+    pub kraken_ws: Option<String>,
+    pub coinbase_rest: Option<String>,
+    pub coinbase_ws: Option<String>,
+    pub okx_rest: Option<String>,
+    pub okx_ws: Option<String>,
+    pub bybit_rest: Option<String>,
+    pub bybit_ws: Option<String>,
+    pub kucoin_rest: Option<String>,
+    /// Connected to directly instead of asking `bullet-public` for a token and server
+    pub kucoin_ws: Option<String>,
+    pub gateio_rest: Option<String>,
+    pub gateio_ws: Option<String>,
+    pub bitfinex_rest: Option<String>,
+    pub bitfinex_ws: Option<String>,
+}
+
+/// Effective settings for one symbol after applying its overrides to the defaults
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SymbolConfig {
+    pub symbol: String,
+    pub settings: BookSettings,
+}
+
+impl AppConfig {
+    pub fn from_json_str(text: &str) -> Result<Self, String> {
+        let config: AppConfig =
+            serde_json::from_str(text).map_err(|e| format!("invalid config: {}", e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Parse a TOML config, e.g. with `[defaults]` and `[symbols.ethbtc]` sections
+    pub fn from_toml_str(text: &str) -> Result<Self, String> {
+        let value: Value = toml::from_str(text).map_err(|e| format!("invalid config: {}", e))?;
+        let config: AppConfig =
+            serde_json::from_value(value).map_err(|e| format!("invalid config: {}", e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// The config file at `path`: TOML if it ends in `.toml`, JSON otherwise
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = read_file(path)?;
+        if is_toml(path) {
+            Self::from_toml_str(&text)
+        } else {
+            Self::from_json_str(&text)
+        }
+    }
+
+    /// The config file at `path` (if any) with the `AGG_*` variables of `env` layered over it
+    pub fn load_layered(path: Option<&str>, env: &EnvLayer) -> Result<Self, String> {
+        if env.is_empty() {
+            return path.map_or_else(|| Ok(Self::default()), Self::load);
+        }
+        let mut value = match path {
+            Some(path) => {
+                let text = read_file(path)?;
+                if is_toml(path) {
+                    toml::from_str(&text).map_err(|e| format!("invalid config: {}", e))?
+                } else {
+                    serde_json::from_str(&text).map_err(|e| format!("invalid config: {}", e))?
+                }
+            }
+            None => Value::Object(Default::default()),
+        };
+        env::merge(&mut value, env.to_json()?);
+        let config: AppConfig = serde_json::from_value(value)
+            .map_err(|e| format!("invalid config (with {}* variables): {}", ENV_PREFIX, e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for symbol in self.symbols.keys() {
+            let resolved = self.resolve(symbol);
+            if !(resolved.settings.price_scale.is_finite() && resolved.settings.price_scale > 0.0) {
+                return Err(format!(
+                    "invalid config: symbols.{}.price_scale must be positive",
+                    symbol
+                ));
+            }
+        }
+        let weights = self
+            .symbols
+            .iter()
+            .filter_map(|(symbol, o)| o.index_weights.as_ref().map(|w| (symbol.as_str(), w)))
+            .chain(std::iter::once(("defaults", &self.defaults.index_weights)));
+        for (section, weights) in weights {
+            if let Some((exchange, _)) =
+                weights.iter().find(|(_, w)| !(w.is_finite() && **w >= 0.0))
+            {
+                return Err(format!(
+                    "invalid config: {}.index_weights.{} must be a non-negative number",
+                    section, exchange
+                ));
+            }
+        }
+        let depth_weights = self
+            .symbols
+            .iter()
+            .filter_map(|(symbol, o)| o.depth_weights.as_ref().map(|w| (symbol.as_str(), w)))
+            .chain(std::iter::once(("defaults", &self.defaults.depth_weights)));
+        for (section, weights) in depth_weights {
+            if let Some((exchange, _)) = weights.iter().find(|(_, w)| !(0.0..=1.0).contains(*w)) {
+                return Err(format!(
+                    "invalid config: {}.depth_weights.{} must be between 0 and 1",
+                    section, exchange
+                ));
+            }
+        }
+        let exchange_symbols = self
+            .symbols
+            .iter()
+            .filter_map(|(symbol, o)| o.exchanges.as_ref().map(|e| (symbol, e)));
+        for (symbol, exchanges) in exchange_symbols {
+            for (exchange, instrument) in exchanges {
+                let known = [
+                    "binance", "bitstamp", "kraken", "coinbase", "okx", "bybit", "kucoin",
+                    "gateio", "bitfinex",
+                ];
+                if !known.contains(&exchange.as_str()) {
+                    return Err(format!(
+                        "invalid config: symbols.{}.exchanges.{} is not an exchange (expected binance, bitstamp, kraken, coinbase, okx, bybit, kucoin, gateio or bitfinex)",
+                        symbol, exchange
+                    ));
+                }
+                // Coinbase, OKX and KuCoin split base and quote with a dash, e.g. BTC-USD,
+                // Gate.io with an underscore, e.g. BTC_USDT, and Bitfinex long names with a
+                // colon, e.g. tTESTBTC:TESTUSD
+                let dashed = ["coinbase", "okx", "kucoin"].contains(&exchange.as_str());
+                let underscored = exchange == "gateio";
+                let coloned = exchange == "bitfinex";
+                let code = |c: char| {
+                    c.is_ascii_alphanumeric()
+                        || (dashed && c == '-')
+                        || (underscored && c == '_')
+                        || (coloned && c == ':')
+                };
+                if instrument.is_empty() || !instrument.chars().all(code) {
+                    return Err(format!(
+                        "invalid config: symbols.{}.exchanges.{} '{}' is not an instrument code",
+                        symbol, exchange, instrument
+                    ));
+                }
+            }
+        }
+        let sections = self
+            .symbols
+            .keys()
+            .map(|symbol| (format!("symbols.{}", symbol), self.resolve(symbol).settings))
+            .chain(std::iter::once((
+                "defaults".to_string(),
+                self.defaults.clone(),
+            )));
+        for (section, settings) in sections {
+            if let Some(min_qty) = settings.smart_best_min_qty
+                && !(min_qty.is_finite() && min_qty > 0.0)
+            {
+                return Err(format!(
+                    "invalid config: {}.smart_best_min_qty must be positive",
+                    section
+                ));
+            }
+            if let Some(distance) = settings.max_level_distance_bps
+                && !(distance.is_finite() && distance > 0.0)
+            {
+                return Err(format!(
+                    "invalid config: {}.max_level_distance_bps must be positive",
+                    section
+                ));
+            }
+            if let Some(tick) = settings.tick_size
+                && !(tick.is_finite() && tick > 0.0)
+            {
+                return Err(format!(
+                    "invalid config: {}.tick_size must be positive",
+                    section
+                ));
+            }
+            if settings.first_data_timeout_ms == Some(0) {
+                return Err(format!(
+                    "invalid config: {}.first_data_timeout_ms must be positive",
+                    section
+                ));
+            }
+            if settings.tie_break == TieBreak::VenuePriority && settings.venue_priority.is_empty() {
+                return Err(format!(
+                    "invalid config: {}.venue_priority must list exchanges for the venue_priority tie-break",
+                    section
+                ));
+            }
+            let known = [
+                Exchange::Binance,
+                Exchange::BinanceUs,
+                Exchange::Bitstamp,
+                Exchange::Kraken,
+                Exchange::Coinbase,
+                Exchange::Okx,
+                Exchange::Bybit,
+                Exchange::Kucoin,
+                Exchange::GateIo,
+                Exchange::Bitfinex,
+            ];
+            if let Some(venue) = settings
+                .venue_priority
+                .iter()
+                .find(|v| !known.iter().any(|e| e.as_str() == v.as_str()))
+            {
+                return Err(format!(
+                    "invalid config: {}.venue_priority lists unknown exchange '{}'",
+                    section, venue
+                ));
+            }
+        }
+        for (exchange, reset) in &self.sequence_reset {
+            if exchange != "binance" && exchange != "bitstamp" {
+                return Err(format!(
+                    "invalid config: sequence_reset.{} is not an exchange (expected binance or bitstamp)",
+                    exchange
+                ));
+            }
+            if !(reset.min_factor.is_finite() && reset.min_factor >= 1.0) {
+                return Err(format!(
+                    "invalid config: sequence_reset.{}.min_factor must be at least 1",
+                    exchange
+                ));
+            }
+        }
+        if !(self.defaults.price_scale.is_finite() && self.defaults.price_scale > 0.0) {
+            return Err("invalid config: defaults.price_scale must be positive".to_string());
+        }
+        if let Some(level) = &self.log_level {
+            level
+                .parse::<LevelFilter>()
+                .map_err(|_| format!("invalid config: unknown log_level '{}'", level))?;
+        }
+        self.admin.listener()?;
+        self.alarms.validate()?;
+        self.grpc_listeners()?;
+        self.enabled_exchanges()?;
+        Ok(())
+    }
+
+    pub fn grpc_addr(&self) -> Result<SocketAddr, String> {
+        let listen = self.grpc_listen.as_deref().unwrap_or(DEFAULT_GRPC_LISTEN);
+        listen
+            .parse()
+            .map_err(|_| format!("invalid config: grpc_listen '{}' is not host:port", listen))
+    }
+
+    /// Public gRPC listeners: `grpc_listeners` if set, otherwise the one `grpc_listen`
+    pub fn grpc_listeners(&self) -> Result<Vec<GrpcListener>, String> {
+        if self.grpc_listeners.is_empty() {
+            return Ok(vec![GrpcListener {
+                addr: ListenAddr::Tcp(self.grpc_addr()?),
+                tls: None,
+                auth_token: None,
+            }]);
+        }
+        if self.grpc_listen.is_some() {
+            return Err(
+                "invalid config: set either grpc_listen or grpc_listeners, not both".to_string(),
+            );
+        }
+        let mut listeners: Vec<GrpcListener> = vec![];
+        for (i, listener) in self.grpc_listeners.iter().enumerate() {
+            let field = format!("grpc_listeners[{}].listen", i);
+            let addr = ListenAddr::parse(&field, &listener.listen)?;
+            if listener.tls.is_some() && matches!(addr, ListenAddr::Unix(_)) {
+                return Err(format!(
+                    "invalid config: grpc_listeners[{}].tls is only supported on host:port listeners",
+                    i
+                ));
+            }
+            if listener.auth_token.as_deref() == Some("") {
+                return Err(format!(
+                    "invalid config: grpc_listeners[{}].auth_token is empty",
+                    i
+                ));
+            }
+            if listeners.iter().any(|l| l.addr == addr) {
+                return Err(format!(
+                    "invalid config: {} '{}' is listed twice",
+                    field, addr
+                ));
+            }
+            listeners.push(GrpcListener {
+                addr,
+                tls: listener.tls.clone(),
+                auth_token: listener.auth_token.clone(),
+            });
+        }
+        Ok(listeners)
+    }
+
+    /// Exchanges to connect to, in connector order
+    pub fn enabled_exchanges(&self) -> Result<Vec<Exchange>, String> {
+        let Some(names) = &self.exchanges else {
+            return Ok(vec![Exchange::Bitstamp, self.binance_variant.exchange()]);
+        };
+        if names.is_empty() {
+            return Err("invalid config: exchanges must list at least one exchange".to_string());
+        }
+        let mut enabled = vec![];
+        for name in names {
+            let exchange = match name.to_lowercase().as_str() {
+                "binance" => self.binance_variant.exchange(),
+                "bitstamp" => Exchange::Bitstamp,
+                "kraken" => Exchange::Kraken,
+                "coinbase" => Exchange::Coinbase,
+                "okx" => Exchange::Okx,
+                "bybit" => Exchange::Bybit,
+                "kucoin" => Exchange::Kucoin,
+                "gateio" => Exchange::GateIo,
+                "bitfinex" => Exchange::Bitfinex,
+                _ => {
+                    return Err(format!(
+                        "invalid config: unknown exchange '{}' (expected binance, bitstamp, kraken, coinbase, okx, bybit, kucoin, gateio or bitfinex)",
+                        name
+                    ));
+                }
+            };
+            if enabled.contains(&exchange) {
+                return Err(format!("invalid config: exchange '{}' listed twice", name));
+            }
+            enabled.push(exchange);
+        }
+        enabled.sort_by_key(|e| *e != Exchange::Bitstamp);
+        Ok(enabled)
+    }
+
+    pub fn binance_endpoint(&self) -> BinanceEndpoint {
+        let mut endpoint = self.binance_variant.endpoint();
+        if let Some(rest) = &self.endpoints.binance_rest {
+            endpoint.rest = rest.trim_end_matches('/').to_string();
+        }
+        if let Some(ws) = &self.endpoints.binance_ws {
+            endpoint.ws = ws.trim_end_matches('/').to_string();
+        }
+        endpoint
+    }
+
+    pub fn bitstamp_endpoint(&self) -> BitstampEndpoint {
+        let mut endpoint = BitstampEndpoint::default();
+        if let Some(rest) = &self.endpoints.bitstamp_rest {
+            endpoint.rest = rest.trim_end_matches('/').to_string();
+        }
+        if let Some(ws) = &self.endpoints.bitstamp_ws {
+            endpoint.ws = ws.to_string();
+        }
+        endpoint
+    }
+
+    pub fn kraken_endpoint(&self) -> KrakenEndpoint {
+        let mut endpoint = KrakenEndpoint::default();
+        if let Some(rest) = &self.endpoints.kraken_rest {
+            endpoint.rest = rest.trim_end_matches('/').to_string();
+        }
+        if let Some(ws) = &self.endpoints.kraken_ws {
+            endpoint.ws = ws.to_string();
+        }
+        endpoint
+    }
+
+    pub fn coinbase_endpoint(&self) -> CoinbaseEndpoint {
+        let mut endpoint = CoinbaseEndpoint::default();
+        if let Some(rest) = &self.endpoints.coinbase_rest {
+            endpoint.rest = rest.trim_end_matches('/').to_string();
+        }
+        if let Some(ws) = &self.endpoints.coinbase_ws {
+            endpoint.ws = ws.to_string();
+        }
+        endpoint
+    }
+
+    pub fn okx_endpoint(&self) -> OkxEndpoint {
+        let mut endpoint = OkxEndpoint::default();
+        if let Some(rest) = &self.endpoints.okx_rest {
+            endpoint.rest = rest.trim_end_matches('/').to_string();
+        }
+        if let Some(ws) = &self.endpoints.okx_ws {
+            endpoint.ws = ws.to_string();
+        }
+        endpoint
+    }
+
+    pub fn bybit_endpoint(&self) -> BybitEndpoint {
+        let mut endpoint = BybitEndpoint::default();
+        if let Some(rest) = &self.endpoints.bybit_rest {
+            endpoint.rest = rest.trim_end_matches('/').to_string();
+        }
+        if let Some(ws) = &self.endpoints.bybit_ws {
+            endpoint.ws = ws.to_string();
+        }
+        endpoint
+    }
+
+    pub fn kucoin_endpoint(&self) -> KucoinEndpoint {
+        let mut endpoint = KucoinEndpoint::default();
+        if let Some(rest) = &self.endpoints.kucoin_rest {
+            endpoint.rest = rest.trim_end_matches('/').to_string();
+        }
+        endpoint.ws = self.endpoints.kucoin_ws.clone();
+        endpoint
+    }
+
+    pub fn gateio_endpoint(&self) -> GateIoEndpoint {
+        let mut endpoint = GateIoEndpoint::default();
+        if let Some(rest) = &self.endpoints.gateio_rest {
+            endpoint.rest = rest.trim_end_matches('/').to_string();
+        }
+        if let Some(ws) = &self.endpoints.gateio_ws {
+            endpoint.ws = ws.to_string();
+        }
+        endpoint
+    }
+
+    pub fn bitfinex_endpoint(&self) -> BitfinexEndpoint {
+        let mut endpoint = BitfinexEndpoint::default();
+        if let Some(rest) = &self.endpoints.bitfinex_rest {
+            endpoint.rest = rest.trim_end_matches('/').to_string();
+        }
+        if let Some(ws) = &self.endpoints.bitfinex_ws {
+            endpoint.ws = ws.to_string();
+        }
+        endpoint
+    }
+
+    /// Instrument code connectors use for `symbol` on `exchange`: its `exchanges` override,
+    /// or else the symbol itself. Connectors apply the exchange's casing.
+    pub fn exchange_symbol(&self, symbol: &str, exchange: Exchange) -> String {
+        let symbol = symbol.to_lowercase();
+        self.symbols
+            .get(&symbol)
+            .and_then(|o| o.exchanges.as_ref())
+            .and_then(|exchanges| exchanges.get(exchange_key(exchange)))
+            .cloned()
+            .unwrap_or(symbol)
+    }
+
+    /// Symbols whose exchanges trade instruments in different quote currencies, which are
+    /// still aggregated into one book. Not an error: USDT against USD may be intended.
+    pub fn symbol_warnings(&self) -> Vec<String> {
+        let mut warnings = vec![];
+        for (symbol, overrides) in &self.symbols {
+            if overrides.exchanges.is_none() {
+                continue;
+            }
+            let quotes: Vec<(&str, String, &str)> = [Exchange::Binance, Exchange::Bitstamp]
+                .into_iter()
+                .filter_map(|exchange| {
+                    let instrument = self.exchange_symbol(symbol, exchange);
+                    let quote = quote_currency(&instrument)?;
+                    Some((exchange_key(exchange), instrument, quote))
+                })
+                .collect();
+            if let [(a, a_instrument, a_quote), (b, b_instrument, b_quote)] = quotes.as_slice()
+                && a_quote != b_quote
+            {
+                warnings.push(format!(
+                    "symbols.{}: {} trades {} quoted in {} but {} trades {} quoted in {}; both are aggregated into one book",
+                    symbol, a, a_instrument, a_quote, b, b_instrument, b_quote
+                ));
+            }
+        }
+        warnings
+    }
+
+    /// Resolve the effective settings for a symbol, falling back to the defaults
+    pub fn resolve(&self, symbol: &str) -> SymbolConfig {
+        let symbol = symbol.to_lowercase();
+        let mut settings = self.defaults.clone();
+        if let Some(o) = self.symbols.get(&symbol) {
+            if let Some(v) = o.price_scale {
+                settings.price_scale = v;
+            }
+            if let Some(v) = o.max_depth {
+                settings.max_depth = Some(v);
+            }
+            if let Some(v) = o.dust_threshold {
+                settings.dust_threshold = v;
+            }
+            if let Some(v) = o.outlier_tolerance_bps {
+                settings.outlier_tolerance_bps = Some(v);
+            }
+            if let Some(v) = o.stale_after_ms {
+                settings.stale_after_ms = Some(v);
+            }
+            if let Some(v) = &o.index_weights {
+                settings.index_weights = v.clone();
+            }
+            if let Some(v) = &o.depth_weights {
+                settings.depth_weights = v.clone();
+            }
+            if let Some(v) = o.tie_break {
+                settings.tie_break = v;
+            }
+            if let Some(v) = &o.venue_priority {
+                settings.venue_priority = v.clone();
+            }
+            if let Some(v) = o.smart_best_min_qty {
+                settings.smart_best_min_qty = Some(v);
+            }
+            if let Some(v) = o.max_level_distance_bps {
+                settings.max_level_distance_bps = Some(v);
+            }
+            if let Some(v) = o.first_data_timeout_ms {
+                settings.first_data_timeout_ms = Some(v);
+            }
+            if let Some(v) = o.tick_size {
+                settings.tick_size = Some(v);
+            }
+            if let Some(v) = o.round_to_tick {
+                settings.round_to_tick = v;
+            }
+        }
+        SymbolConfig { symbol, settings }
+    }
+
+    /// Compare a freshly loaded config against this (running) one for `symbol`
+    pub fn diff(&self, new: &AppConfig, symbol: &str) -> ConfigDiff {
+        let mut diff = ConfigDiff::default();
+        let old_settings = self.resolve(symbol).settings;
+        let new_settings = new.resolve(symbol).settings;
+        let mut check = |name: &str, old: String, new: String, reloadable: bool| {
+            if old != new {
+                let change = format!("{}: {} -> {}", name, old, new);
+                if reloadable {
+                    diff.reloadable.push(change);
+                } else {
+                    diff.requires_restart.push(change);
+                }
+            }
+        };
+        // Existing levels are bucketed with the old scale, so changing it needs a fresh book
+        check(
+            "price_scale",
+            format!("{}", old_settings.price_scale),
+            format!("{}", new_settings.price_scale),
+            false,
+        );
+        // Existing levels were snapped to the old tick, if any
+        check(
+            "tick_size",
+            format!("{:?}", old_settings.tick_size),
+            format!("{:?}", new_settings.tick_size),
+            false,
+        );
+        check(
+            "round_to_tick",
+            format!("{}", old_settings.round_to_tick),
+            format!("{}", new_settings.round_to_tick),
+            false,
+        );
+        check(
+            "max_depth",
+            format!("{:?}", old_settings.max_depth),
+            format!("{:?}", new_settings.max_depth),
+            true,
+        );
+        check(
+            "dust_threshold",
+            format!("{}", old_settings.dust_threshold),
+            format!("{}", new_settings.dust_threshold),
+            true,
+        );
+        check(
+            "outlier_tolerance_bps",
+            format!("{:?}", old_settings.outlier_tolerance_bps),
+            format!("{:?}", new_settings.outlier_tolerance_bps),
+            true,
+        );
+        check(
+            "stale_after_ms",
+            format!("{:?}", old_settings.stale_after_ms),
+            format!("{:?}", new_settings.stale_after_ms),
+            true,
+        );
+        check(
+            "index_weights",
+            format!("{:?}", old_settings.index_weights),
+            format!("{:?}", new_settings.index_weights),
+            true,
+        );
+        check(
+            "depth_weights",
+            format!("{:?}", old_settings.depth_weights),
+            format!("{:?}", new_settings.depth_weights),
+            true,
+        );
+        check(
+            "tie_break",
+            format!("{:?}", old_settings.tie_break),
+            format!("{:?}", new_settings.tie_break),
+            true,
+        );
+        check(
+            "venue_priority",
+            format!("{:?}", old_settings.venue_priority),
+            format!("{:?}", new_settings.venue_priority),
+            true,
+        );
+        check(
+            "smart_best_min_qty",
+            format!("{:?}", old_settings.smart_best_min_qty),
+            format!("{:?}", new_settings.smart_best_min_qty),
+            true,
+        );
+        check(
+            "max_level_distance_bps",
+            format!("{:?}", old_settings.max_level_distance_bps),
+            format!("{:?}", new_settings.max_level_distance_bps),
+            true,
+        );
+        // Read at each connect attempt
+        check(
+            "first_data_timeout_ms",
+            format!("{:?}", old_settings.first_data_timeout_ms),
+            format!("{:?}", new_settings.first_data_timeout_ms),
+            true,
+        );
+        check(
+            "log_level",
+            format!("{:?}", self.log_level),
+            format!("{:?}", new.log_level),
+            true,
+        );
+        check(
+            "binance_variant",
+            format!("{:?}", self.binance_variant),
+            format!("{:?}", new.binance_variant),
+            false,
+        );
+        check(
+            "exchanges",
+            format!("{:?}", self.exchanges),
+            format!("{:?}", new.exchanges),
+            false,
+        );
+        check(
+            "grpc_listen",
+            format!("{:?}", self.grpc_listen),
+            format!("{:?}", new.grpc_listen),
+            false,
+        );
+        check(
+            "grpc_listeners",
+            format!("{:?}", self.grpc_listeners),
+            format!("{:?}", new.grpc_listeners),
+            false,
+        );
+        check(
+            "endpoints",
+            format!("{:?}", self.endpoints),
+            format!("{:?}", new.endpoints),
+            false,
+        );
+        check(
+            "exchange_symbols",
+            format!("{:?}", self.exchange_symbols_of(symbol)),
+            format!("{:?}", new.exchange_symbols_of(symbol)),
+            false,
+        );
+        check(
+            "admin",
+            format!("{:?}", self.admin),
+            format!("{:?}", new.admin),
+            false,
+        );
+        check(
+            "sequence_reset",
+            format!("{:?}", self.sequence_reset),
+            format!("{:?}", new.sequence_reset),
+            false,
+        );
+        check(
+            "one_sided_summaries",
+            format!("{:?}", self.one_sided_summaries),
+            format!("{:?}", new.one_sided_summaries),
+            false,
+        );
+        check(
+            "summary_consistency",
+            format!("{:?}", self.summary_consistency),
+            format!("{:?}", new.summary_consistency),
+            false,
+        );
+        check(
+            "spread_convention",
+            format!("{:?}", self.spread_convention),
+            format!("{:?}", new.spread_convention),
+            false,
+        );
+        check(
+            "alarms",
+            format!("{:?}", self.alarms),
+            format!("{:?}", new.alarms),
+            false,
+        );
+        diff
+    }
+
+    /// Sequence reset thresholds of `exchange`
+    pub fn sequence_reset(&self, exchange: Exchange) -> SequenceResetConfig {
+        self.sequence_reset
+            .get(exchange_key(exchange))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn exchange_symbols_of(&self, symbol: &str) -> Option<&BTreeMap<String, String>> {
+        self.symbols.get(&symbol.to_lowercase())?.exchanges.as_ref()
+    }
+
+    /// `new` with every restart-only setting for `symbol` kept at its value in `self`
+    fn with_restart_settings_of(&self, mut new: AppConfig, symbol: &str) -> AppConfig {
+        new.binance_variant = self.binance_variant;
+        new.exchanges = self.exchanges.clone();
+        new.grpc_listen = self.grpc_listen.clone();
+        new.grpc_listeners = self.grpc_listeners.clone();
+        new.endpoints = self.endpoints.clone();
+        new.admin = self.admin.clone();
+        new.sequence_reset = self.sequence_reset.clone();
+        new.one_sided_summaries = self.one_sided_summaries;
+        new.summary_consistency = self.summary_consistency;
+        new.spread_convention = self.spread_convention;
+        new.alarms = self.alarms.clone();
+        let running_scale = self.resolve(symbol).settings.price_scale;
+        if new.resolve(symbol).settings.price_scale != running_scale {
+            new.symbols
+                .entry(symbol.to_lowercase())
+                .or_default()
+                .price_scale = Some(running_scale);
+        }
+        let running = self.resolve(symbol).settings;
+        let resolved = new.resolve(symbol).settings;
+        if (resolved.tick_size, resolved.round_to_tick)
+            != (running.tick_size, running.round_to_tick)
+        {
+            let overrides = new.symbols.entry(symbol.to_lowercase()).or_default();
+            overrides.tick_size = running.tick_size;
+            overrides.round_to_tick = Some(running.round_to_tick);
+        }
+        if new.exchange_symbols_of(symbol) != self.exchange_symbols_of(symbol) {
+            new.symbols
+                .entry(symbol.to_lowercase())
+                .or_default()
+                .exchanges = self.exchange_symbols_of(symbol).cloned();
+        }
+        new
+    }
+}
+
+/// Changed settings found by a config reload, as human-readable `name: old -> new` entries
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConfigDiff {
+    /// Applied to the running aggregator
+    pub reloadable: Vec<String>,
+    /// Not applied: these only take effect after a restart
+    pub requires_restart: Vec<String>,
+}
+
+type LogLevelHook = Box<dyn Fn(LevelFilter) -> Result<(), String> + Send + Sync>;
+
+/// Re-reads the config file and hot-applies what can change on a running aggregator
+pub struct ConfigReloader {
+    path: Option<String>,
+    env: EnvLayer,
+    symbol: String,
+    current: Mutex<AppConfig>,
+    book: Arc<RwLock<AggregatedOrderBook>>,
+    log_level_hook: Option<LogLevelHook>,
+}
+
+impl Debug for ConfigReloader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConfigReloader")
+            .field("path", &self.path)
+            .field("symbol", &self.symbol)
+            .finish()
+    }
+}
+
+impl ConfigReloader {
+    pub fn new(
+        path: Option<String>,
+        symbol: &str,
+        current: AppConfig,
+        book: Arc<RwLock<AggregatedOrderBook>>,
+    ) -> Self {
+        Self {
+            path,
+            env: EnvLayer::default(),
+            symbol: symbol.to_lowercase(),
+            current: Mutex::new(current),
+            book,
+            log_level_hook: None,
+        }
+    }
+
+    /// `AGG_*` variables layered over the file on every reload, as at startup
+    pub fn with_env(mut self, env: EnvLayer) -> Self {
+        self.env = env;
+        self
+    }
+
+    /// Called with the new level when `log_level` changes
+    pub fn with_log_level_hook(
+        mut self,
+        hook: impl Fn(LevelFilter) -> Result<(), String> + Send + Sync + 'static,
+    ) -> Self {
+        self.log_level_hook = Some(Box::new(hook));
+        self
+    }
+
+    /// Re-read the config file and apply it
+    pub async fn reload(&self) -> Result<ConfigDiff, String> {
+        let path = self
+            .path
+            .as_deref()
+            .ok_or("no config file was given at startup (--config)")?;
+        let new = AppConfig::load_layered(Some(path), &self.env)?;
+        self.apply(new).await
+    }
+
+    /// Apply the reloadable part of `new`. The symbol's settings are swapped in under a
+    /// single write lock so readers never see a partially applied profile.
+    pub async fn apply(&self, new: AppConfig) -> Result<ConfigDiff, String> {
+        new.validate()?;
+        let mut current = self.current.lock().await;
+        let diff = current.diff(&new, &self.symbol);
+        if diff.reloadable.is_empty() {
+            return Ok(diff);
+        }
+
+        let effective = current.with_restart_settings_of(new, &self.symbol);
+        if effective.log_level != current.log_level
+            && let (Some(hook), Some(level)) = (&self.log_level_hook, &effective.log_level)
+        {
+            let level = level
+                .parse::<LevelFilter>()
+                .map_err(|_| format!("unknown log_level '{}'", level))?;
+            hook(level)?;
+        }
+        self.book
+            .write()
+            .await
+            .apply_settings(effective.resolve(&self.symbol).settings)?;
+        *current = effective;
+        Ok(diff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"{
+        "defaults": { "max_depth": 500, "dust_threshold": 0.0001 },
+        "symbols": {
+            "btcusdt": { "price_scale": 100.0, "max_depth": 50, "outlier_tolerance_bps": 300.0 }
+        }
+    }"#;
+
+    #[test]
+    fn override_and_default_symbols_resolve_differently() {
+        let config = AppConfig::from_json_str(CONFIG).unwrap();
+
+        let btc = config.resolve("BTCUSDT");
+        assert_eq!(btc.symbol, "btcusdt");
+        assert_eq!(btc.settings.price_scale, 100.0);
+        assert_eq!(btc.settings.max_depth, Some(50));
+        assert_eq!(btc.settings.dust_threshold, 0.0001);
+        assert_eq!(btc.settings.outlier_tolerance_bps, Some(300.0));
+
+        let eth = config.resolve("ethbtc");
+        assert_eq!(eth.settings.price_scale, DEFAULT_PRICE_SCALE);
+        assert_eq!(eth.settings.max_depth, Some(500));
+        assert_eq!(eth.settings.dust_threshold, 0.0001);
+        assert_eq!(eth.settings.outlier_tolerance_bps, None);
+        assert_eq!(config.binance_variant, BinanceVariant::Global);
+    }
+
+    #[test]
+    fn exchange_symbols_prefer_overrides_over_the_symbol() {
+        let config = AppConfig::from_json_str(
+            r#"{ "symbols": { "btcusd": { "exchanges": { "binance": "BTCUSDT" } } } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.exchange_symbol("BTCUSD", Exchange::Binance),
+            "BTCUSDT"
+        );
+        assert_eq!(
+            config.exchange_symbol("btcusd", Exchange::BinanceUs),
+            "BTCUSDT",
+            "binance covers both deployments"
+        );
+        assert_eq!(
+            config.exchange_symbol("BTCUSD", Exchange::Bitstamp),
+            "btcusd"
+        );
+        assert_eq!(
+            config.exchange_symbol("ETHBTC", Exchange::Binance),
+            "ethbtc"
+        );
+        assert_eq!(config.resolve("btcusd").symbol, "btcusd");
+        let config = AppConfig::from_json_str(
+            r#"{ "symbols": { "btcusd": { "exchanges": { "kraken": "XBTUSD" } } } }"#,
+        )
+        .unwrap();
+        assert_eq!(config.exchange_symbol("btcusd", Exchange::Kraken), "XBTUSD");
+        let config = AppConfig::from_json_str(
+            r#"{ "symbols": { "btcusd": { "exchanges": { "coinbase": "BTC-USD" } } } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.exchange_symbol("btcusd", Exchange::Coinbase),
+            "BTC-USD"
+        );
+        let config = AppConfig::from_json_str(
+            r#"{ "symbols": { "btcusd": { "exchanges": { "okx": "BTC-USDT" } } } }"#,
+        )
+        .unwrap();
+        assert_eq!(config.exchange_symbol("btcusd", Exchange::Okx), "BTC-USDT");
+        let config = AppConfig::from_json_str(
+            r#"{ "symbols": { "btcusd": { "exchanges": { "bybit": "BTCUSDT" } } } }"#,
+        )
+        .unwrap();
+        assert_eq!(config.exchange_symbol("btcusd", Exchange::Bybit), "BTCUSDT");
+        let config = AppConfig::from_json_str(
+            r#"{ "symbols": { "btcusd": { "exchanges": { "kucoin": "BTC-USDT" } } } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.exchange_symbol("btcusd", Exchange::Kucoin),
+            "BTC-USDT"
+        );
+        let config = AppConfig::from_json_str(
+            r#"{ "symbols": { "btcusd": { "exchanges": { "gateio": "BTC_USDT" } } } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.exchange_symbol("btcusd", Exchange::GateIo),
+            "BTC_USDT"
+        );
+        let config = AppConfig::from_json_str(
+            r#"{ "symbols": { "testusd": { "exchanges": { "bitfinex": "tTESTBTC:TESTUSD" } } } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.exchange_symbol("testusd", Exchange::Bitfinex),
+            "tTESTBTC:TESTUSD"
+        );
+
+        for invalid in [
+            r#"{ "symbols": { "btcusd": { "exchanges": { "huobi": "btcusdt" } } } }"#,
+            r#"{ "symbols": { "btcusd": { "exchanges": { "bybit": "BTC-USDT" } } } }"#,
+            r#"{ "symbols": { "btcusd": { "exchanges": { "kucoin": "BTC_USDT" } } } }"#,
+            r#"{ "symbols": { "btcusd": { "exchanges": { "gateio": "BTC:USDT" } } } }"#,
+            r#"{ "symbols": { "btcusd": { "exchanges": { "binance": "BTC/USDT" } } } }"#,
+            r#"{ "symbols": { "btcusd": { "exchanges": { "binance": "BTC-USDT" } } } }"#,
+            r#"{ "symbols": { "btcusd": { "exchanges": { "bitstamp": "" } } } }"#,
+        ] {
+            assert!(AppConfig::from_json_str(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn overrides_quoted_in_different_currencies_warn() {
+        let config = AppConfig::from_json_str(
+            r#"{ "symbols": {
+                "btcusd": { "exchanges": { "binance": "BTCUSDT", "bitstamp": "btcusd" } },
+                "ethbtc": { "exchanges": { "binance": "ETHBTC" } },
+                "ethusd": { "exchanges": { "binance": "ETHUSD" } }
+            } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.symbol_warnings(),
+            vec![
+                "symbols.btcusd: binance trades BTCUSDT quoted in usdt but bitstamp trades btcusd quoted in usd; both are aggregated into one book"
+            ]
+        );
+        assert_eq!(quote_currency("btcfdusd"), Some("fdusd"));
+        assert_eq!(quote_currency("usdt"), None);
+    }
+
+    #[test]
+    fn binance_variant_is_configurable() {
+        let config = AppConfig::from_json_str(r#"{ "binance_variant": "us" }"#).unwrap();
+        assert_eq!(config.binance_variant, BinanceVariant::Us);
+        assert_eq!(config.binance_endpoint(), BinanceVariant::Us.endpoint());
+
+        let config = AppConfig::from_json_str(
+            r#"{ "endpoints": { "binance_rest": "http://127.0.0.1:8080/", "bitstamp_ws": "ws://127.0.0.1:8081" } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.binance_endpoint().depth_url("ethbtc"),
+            "http://127.0.0.1:8080/api/v3/depth?symbol=ETHBTC&limit=1000"
+        );
+        assert_eq!(config.bitstamp_endpoint().ws, "ws://127.0.0.1:8081");
+        // KuCoin asks its REST API for a websocket unless one is given
+        assert_eq!(config.kucoin_endpoint().ws, None);
+        let config =
+            AppConfig::from_json_str(r#"{ "endpoints": { "kucoin_ws": "ws://127.0.0.1:8082" } }"#)
+                .unwrap();
+        assert_eq!(
+            config.kucoin_endpoint().ws.as_deref(),
+            Some("ws://127.0.0.1:8082")
+        );
+        let config = AppConfig::from_json_str(
+            r#"{ "endpoints": { "gateio_rest": "http://127.0.0.1:8083/", "gateio_ws": "ws://127.0.0.1:8084" } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.gateio_endpoint().order_book_url("ethbtc"),
+            "http://127.0.0.1:8083/api/v4/spot/order_book?currency_pair=ETH_BTC&limit=100&with_id=true"
+        );
+        assert_eq!(config.gateio_endpoint().ws, "ws://127.0.0.1:8084");
+        let config = AppConfig::from_json_str(
+            r#"{ "endpoints": { "bitfinex_rest": "http://127.0.0.1:8085/", "bitfinex_ws": "ws://127.0.0.1:8086" } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.bitfinex_endpoint().book_url("ethbtc"),
+            "http://127.0.0.1:8085/v2/book/tETHBTC/P0?len=100"
+        );
+        assert_eq!(config.bitfinex_endpoint().ws, "ws://127.0.0.1:8086");
+        assert!(AppConfig::from_json_str(r#"{ "binance_variant": "eu" }"#).is_err());
+    }
+
+    #[test]
+    fn exchanges_default_to_both_and_can_be_narrowed() {
+        let both = AppConfig::default().enabled_exchanges().unwrap();
+        assert_eq!(both, vec![Exchange::Bitstamp, Exchange::Binance]);
+
+        let config =
+            AppConfig::from_json_str(r#"{ "binance_variant": "us", "exchanges": ["binance"] }"#)
+                .unwrap();
+        assert_eq!(
+            config.enabled_exchanges().unwrap(),
+            vec![Exchange::BinanceUs]
+        );
+        // Kraken, Coinbase, OKX, Bybit, KuCoin, Gate.io and Bitfinex are opt-in
+        let config = AppConfig::from_json_str(
+            r#"{ "exchanges": ["kraken", "coinbase", "okx", "bybit", "kucoin", "gateio", "bitfinex", "binance", "bitstamp"] }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.enabled_exchanges().unwrap(),
+            vec![
+                Exchange::Bitstamp,
+                Exchange::Kraken,
+                Exchange::Coinbase,
+                Exchange::Okx,
+                Exchange::Bybit,
+                Exchange::Kucoin,
+                Exchange::GateIo,
+                Exchange::Bitfinex,
+                Exchange::Binance
+            ]
+        );
+
+        for invalid in [
+            r#"{ "exchanges": [] }"#,
+            r#"{ "exchanges": ["huobi"] }"#,
+            r#"{ "exchanges": ["bitstamp", "Bitstamp"] }"#,
+        ] {
+            assert!(AppConfig::from_json_str(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn toml_sections_resolve_like_json() {
+        let config = AppConfig::from_toml_str(
+            r#"
+            [defaults]
+            max_depth = 500
+            dust_threshold = 0.0001
+
+            [symbols.btcusdt]
+            price_scale = 100.0
+            max_depth = 50
+            outlier_tolerance_bps = 300.0
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config, AppConfig::from_json_str(CONFIG).unwrap());
+        assert_eq!(config.resolve("btcusdt").settings.max_depth, Some(50));
+        assert_eq!(config.resolve("ethbtc").settings.max_depth, Some(500));
+
+        let err = AppConfig::from_toml_str("[symbols.ethbtc]\nmax_dept = 5").unwrap_err();
+        assert!(err.contains("max_dept"), "{}", err);
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let err = AppConfig::from_json_str(r#"{ "symbols": { "ethbtc": { "max_dept": 5 } } }"#)
+            .unwrap_err();
+        assert!(err.contains("max_dept"), "{}", err);
+
+        let err = AppConfig::from_json_str(r#"{ "default": {} }"#).unwrap_err();
+        assert!(err.contains("default"), "{}", err);
+    }
+
+    fn reloader(config: &AppConfig) -> (ConfigReloader, Arc<RwLock<AggregatedOrderBook>>) {
+        let book = Arc::new(RwLock::new(
+            AggregatedOrderBook::new().with_config(config.resolve("btcusdt")),
+        ));
+        let reloader = ConfigReloader::new(None, "btcusdt", config.clone(), book.clone());
+        (reloader, book)
+    }
+
+    #[test]
+    fn diff_separates_reloadable_and_restart_only_changes() {
+        let old = AppConfig::from_json_str(CONFIG).unwrap();
+        let new = AppConfig::from_json_str(
+            r#"{
+            "binance_variant": "us",
+            "log_level": "debug",
+            "defaults": { "max_depth": 500, "dust_threshold": 0.001, "stale_after_ms": 5000 },
+            "symbols": { "btcusdt": { "price_scale": 10.0, "max_depth": 50, "outlier_tolerance_bps": 300.0 } }
+        }"#,
+        )
+        .unwrap();
+
+        let diff = old.diff(&new, "btcusdt");
+        assert_eq!(
+            diff.reloadable,
+            vec![
+                "dust_threshold: 0.0001 -> 0.001",
+                "stale_after_ms: None -> Some(5000)",
+                "log_level: None -> Some(\"debug\")",
+            ]
+        );
+        assert_eq!(
+            diff.requires_restart,
+            vec!["price_scale: 100 -> 10", "binance_variant: Global -> Us"]
+        );
+        assert_eq!(old.diff(&old, "btcusdt"), ConfigDiff::default());
+    }
+
+    #[tokio::test]
+    async fn reload_applies_hot_settings_and_keeps_restart_only_ones() {
+        let old = AppConfig::from_json_str(CONFIG).unwrap();
+        let (reloader, book) = reloader(&old);
+        let new = AppConfig::from_json_str(
+            r#"{
+            "binance_variant": "us",
+            "defaults": { "max_depth": 500, "dust_threshold": 0.5 },
+            "symbols": { "btcusdt": { "price_scale": 10.0, "max_depth": 2 } }
+        }"#,
+        )
+        .unwrap();
+
+        let diff = reloader.apply(new.clone()).await.unwrap();
+        assert_eq!(diff.requires_restart.len(), 2);
+        let settings = book.read().await.config.settings.clone();
+        assert_eq!(settings.max_depth, Some(2));
+        assert_eq!(settings.dust_threshold, 0.5);
+        assert_eq!(settings.outlier_tolerance_bps, None);
+        assert_eq!(settings.price_scale, 100.0);
+
+        // Restart-only changes keep being reported until the process restarts
+        let again = reloader.apply(new).await.unwrap();
+        assert!(again.reloadable.is_empty());
+        assert_eq!(again.requires_restart, diff.requires_restart);
+    }
+
+    #[tokio::test]
+    async fn invalid_reload_leaves_the_running_config_untouched() {
+        let old = AppConfig::from_json_str(CONFIG).unwrap();
+        let (reloader, book) = reloader(&old);
+        let mut bad = old.clone();
+        bad.defaults.dust_threshold = 1.0;
+        bad.log_level = Some("loud".to_string());
+        assert!(reloader.apply(bad).await.is_err());
+        assert_eq!(book.read().await.config.settings.dust_threshold, 0.0001);
+        assert!(reloader.reload().await.is_err());
+    }
+
+    #[test]
+    fn index_weights_override_and_validate() {
+        let config = AppConfig::from_json_str(
+            r#"{ "defaults": { "index_weights": { "binance": 2.0 } },
+                 "symbols": { "btcusdt": { "index_weights": { "bitstamp": 3.0 } } } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.resolve("ethbtc").settings.index_weights,
+            BTreeMap::from([("binance".to_string(), 2.0)])
+        );
+        assert_eq!(
+            config.resolve("btcusdt").settings.index_weights,
+            BTreeMap::from([("bitstamp".to_string(), 3.0)])
+        );
+
+        let err = AppConfig::from_json_str(
+            r#"{ "symbols": { "btcusdt": { "index_weights": { "binance": -1 } } } }"#,
+        )
+        .unwrap_err();
+        assert!(err.contains("btcusdt.index_weights.binance"), "{}", err);
+    }
+
+    #[test]
+    fn depth_weights_override_and_validate() {
+        let config = AppConfig::from_json_str(
+            r#"{ "defaults": { "depth_weights": { "bitstamp": 0.5 } },
+                 "symbols": { "btcusdt": { "depth_weights": { "binance": 0.8, "bitstamp": 1.0 } } } }"#,
+        )
+        .unwrap();
+        let ethbtc = config.resolve("ethbtc").settings;
+        assert_eq!(
+            (
+                ethbtc.depth_weight("bitstamp"),
+                ethbtc.depth_weight("binance")
+            ),
+            (0.5, 1.0)
+        );
+        let btcusdt = config.resolve("btcusdt").settings;
+        assert_eq!(
+            btcusdt.applied_depth_weights(),
+            BTreeMap::from([("binance".to_string(), 0.8)])
+        );
+
+        let err =
+            AppConfig::from_json_str(r#"{ "defaults": { "depth_weights": { "binance": 1.5 } } }"#)
+                .unwrap_err();
+        assert!(err.contains("defaults.depth_weights.binance"), "{}", err);
+        assert!(
+            AppConfig::from_json_str(
+                r#"{ "symbols": { "btcusdt": { "depth_weights": { "binance": -0.1 } } } }"#
+            )
+            .is_err()
+        );
+        let diff = AppConfig::default().diff(&config, "ethbtc");
+        assert_eq!(
+            diff.reloadable,
+            ["depth_weights: {} -> {\"bitstamp\": 0.5}"]
+        );
+        assert!(diff.requires_restart.is_empty());
+    }
+
+    #[test]
+    fn tie_break_overrides_and_validates() {
+        let config = AppConfig::from_json_str(
+            r#"{ "defaults": { "tie_break": "larger_size" },
+                 "symbols": { "btcusdt": { "tie_break": "venue_priority",
+                                           "venue_priority": ["bitstamp", "binance"] } } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.resolve("ethbtc").settings.tie_break,
+            TieBreak::LargerSize
+        );
+        let btcusdt = config.resolve("btcusdt").settings;
+        assert_eq!(btcusdt.tie_break, TieBreak::VenuePriority);
+        assert_eq!(btcusdt.venue_priority, ["bitstamp", "binance"]);
+
+        let err = AppConfig::from_json_str(r#"{ "defaults": { "tie_break": "venue_priority" } }"#)
+            .unwrap_err();
+        assert!(err.contains("defaults.venue_priority must list"), "{}", err);
+        let err = AppConfig::from_json_str(
+            r#"{ "symbols": { "btcusdt": { "venue_priority": ["huobi"] } } }"#,
+        )
+        .unwrap_err();
+        assert!(err.contains("unknown exchange 'huobi'"), "{}", err);
+        assert!(AppConfig::from_json_str(r#"{ "defaults": { "tie_break": "random" } }"#).is_err());
+    }
+
+    #[test]
+    fn smart_best_min_qty_overrides_validates_and_reloads() {
+        let config = AppConfig::from_json_str(
+            r#"{ "defaults": { "smart_best_min_qty": 1.5 },
+                 "symbols": { "btcusdt": { "smart_best_min_qty": 0.1 } } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.resolve("ethbtc").settings.smart_best_min_qty,
+            Some(1.5)
+        );
+        assert_eq!(
+            config.resolve("btcusdt").settings.smart_best_min_qty,
+            Some(0.1)
+        );
+        assert_eq!(
+            AppConfig::default().diff(&config, "ethbtc").reloadable,
+            ["smart_best_min_qty: None -> Some(1.5)"]
+        );
+        let err = AppConfig::from_json_str(
+            r#"{ "symbols": { "btcusdt": { "smart_best_min_qty": 0.0 } } }"#,
+        )
+        .unwrap_err();
+        assert!(
+            err.contains("symbols.btcusdt.smart_best_min_qty"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn max_level_distance_bps_overrides_validates_and_reloads() {
+        let config = AppConfig::from_json_str(
+            r#"{ "symbols": { "btcusdt": { "max_level_distance_bps": 500.0 } } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.resolve("ethbtc").settings.max_level_distance_bps,
+            None
+        );
+        assert_eq!(
+            config.resolve("btcusdt").settings.max_level_distance_bps,
+            Some(500.0)
+        );
+        assert_eq!(
+            AppConfig::default().diff(&config, "btcusdt").reloadable,
+            ["max_level_distance_bps: None -> Some(500.0)"]
+        );
+        let err = AppConfig::from_json_str(r#"{ "defaults": { "max_level_distance_bps": -1.0 } }"#)
+            .unwrap_err();
+        assert!(err.contains("defaults.max_level_distance_bps"), "{}", err);
+    }
+
+    #[test]
+    fn tick_size_overrides_validates_and_needs_a_restart() {
+        let config = AppConfig::from_json_str(
+            r#"{ "symbols": { "ethbtc": { "tick_size": 0.000001, "round_to_tick": true } } }"#,
+        )
+        .unwrap();
+        let settings = config.resolve("ethbtc").settings;
+        assert_eq!(
+            (settings.tick_size, settings.round_to_tick),
+            (Some(0.000001), true)
+        );
+        assert!(!config.resolve("btcusdt").settings.round_to_tick);
+        assert_eq!(
+            AppConfig::default()
+                .diff(&config, "ethbtc")
+                .requires_restart,
+            [
+                "tick_size: None -> Some(1e-6)",
+                "round_to_tick: false -> true"
+            ]
+        );
+        let err =
+            AppConfig::from_json_str(r#"{ "defaults": { "tick_size": -0.01 } }"#).unwrap_err();
+        assert!(err.contains("defaults.tick_size"), "{}", err);
+    }
+
+    #[test]
+    fn first_data_timeout_overrides_per_symbol_and_reloads() {
+        let config = AppConfig::from_json_str(
+            r#"{ "defaults": { "first_data_timeout_ms": 5000 },
+                 "symbols": { "ethbtc": { "first_data_timeout_ms": 15000 } } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.resolve("btcusdt").settings.first_data_timeout_ms,
+            Some(5_000)
+        );
+        assert_eq!(
+            config.resolve("ethbtc").settings.first_data_timeout_ms,
+            Some(15_000)
+        );
+        assert_eq!(
+            AppConfig::default().diff(&config, "ethbtc").reloadable,
+            ["first_data_timeout_ms: None -> Some(15000)"]
+        );
+        let err = AppConfig::from_json_str(r#"{ "defaults": { "first_data_timeout_ms": 0 } }"#)
+            .unwrap_err();
+        assert!(err.contains("defaults.first_data_timeout_ms"), "{}", err);
+    }
+
+    #[test]
+    fn one_sided_summaries_default_to_emit_and_need_a_restart() {
+        assert_eq!(
+            AppConfig::default().one_sided_summaries,
+            OneSidedSummaries::Emit
+        );
+        let hold = AppConfig::from_json_str(r#"{ "one_sided_summaries": "hold" }"#).unwrap();
+        assert_eq!(hold.one_sided_summaries, OneSidedSummaries::Hold);
+        assert!(AppConfig::from_json_str(r#"{ "one_sided_summaries": "never" }"#).is_err());
+        let diff = AppConfig::default().diff(&hold, "ethbtc");
+        assert_eq!(diff.requires_restart, ["one_sided_summaries: Emit -> Hold"]);
+    }
+
+    #[test]
+    fn summary_consistency_defaults_to_fix_spread_and_needs_a_restart() {
+        assert_eq!(
+            AppConfig::default().summary_consistency,
+            SummaryConsistency::FixSpread
+        );
+        let skip = AppConfig::from_json_str(r#"{ "summary_consistency": "skip" }"#).unwrap();
+        assert_eq!(skip.summary_consistency, SummaryConsistency::Skip);
+        let diff = AppConfig::default().diff(&skip, "ethbtc");
+        assert_eq!(
+            diff.requires_restart,
+            ["summary_consistency: FixSpread -> Skip"]
+        );
+    }
+
+    #[test]
+    fn spread_convention_defaults_to_signed_and_needs_a_restart() {
+        assert_eq!(
+            AppConfig::default().spread_convention,
+            SpreadConvention::Signed
+        );
+        let clamp = AppConfig::from_json_str(r#"{ "spread_convention": "clamp_zero" }"#).unwrap();
+        assert_eq!(clamp.spread_convention, SpreadConvention::ClampZero);
+        assert!(AppConfig::from_json_str(r#"{ "spread_convention": "positive" }"#).is_err());
+        let diff = AppConfig::default().diff(&clamp, "ethbtc");
+        assert_eq!(
+            diff.requires_restart,
+            ["spread_convention: Signed -> ClampZero"]
+        );
+    }
+
+    #[test]
+    fn alarms_are_validated_and_need_a_restart() {
+        let config = AppConfig::from_json_str(
+            r#"{ "alarms": {
+                "notifiers": { "ops": { "type": "webhook", "url": "https://hooks.example/a" } },
+                "events": { "all_exchanges_stale": { "notify": ["ops"] } }
+            } }"#,
+        )
+        .unwrap();
+        assert_eq!(config.alarms.crossed_after_secs, 5);
+        let diff = AppConfig::default().diff(&config, "ethbtc");
+        assert!(
+            diff.requires_restart[0].starts_with("alarms: "),
+            "{:?}",
+            diff
+        );
+
+        let err = AppConfig::from_json_str(
+            r#"{ "alarms": { "notifiers": { "ops": { "type": "webhook", "url": "hooks" } } } }"#,
+        )
+        .unwrap_err();
+        assert!(err.contains("alarms.notifiers.ops.url"), "{}", err);
+        let err = AppConfig::from_json_str(
+            r#"{ "alarms": { "events": { "book_crossed": { "notify": ["ops"] } } } }"#,
+        )
+        .unwrap_err();
+        assert!(err.contains("unknown notifier 'ops'"), "{}", err);
+    }
+
+    #[test]
+    fn sequence_reset_thresholds_are_per_exchange() {
+        let config = AppConfig::from_json_str(
+            r#"{ "binance_variant": "us",
+                 "sequence_reset": { "binance": { "min_drop": 50, "min_factor": 10.0 } } }"#,
+        )
+        .unwrap();
+        let binance = config.sequence_reset(Exchange::BinanceUs);
+        assert_eq!((binance.min_drop, binance.min_factor), (50, 10.0));
+        assert!(binance.enabled);
+        assert_eq!(
+            config.sequence_reset(Exchange::Bitstamp),
+            SequenceResetConfig::default()
+        );
+
+        let err =
+            AppConfig::from_json_str(r#"{ "sequence_reset": { "kraken": {} } }"#).unwrap_err();
+        assert!(err.contains("sequence_reset.kraken"), "{}", err);
+        let err = AppConfig::from_json_str(
+            r#"{ "sequence_reset": { "bitstamp": { "min_factor": 0.5 } } }"#,
+        )
+        .unwrap_err();
+        assert!(err.contains("min_factor must be at least 1"), "{}", err);
+    }
+
+    #[test]
+    fn non_positive_price_scale_is_rejected() {
+        let err = AppConfig::from_json_str(r#"{ "symbols": { "ethbtc": { "price_scale": 0 } } }"#)
+            .unwrap_err();
+        assert!(err.contains("symbols.ethbtc.price_scale"), "{}", err);
+    }
+
+    #[test]
+    fn admin_listener_is_tcp_or_unix() {
+        let listener = |json: &str| AppConfig::from_json_str(json).map(|c| c.admin.listener());
+        assert_eq!(listener("{}"), Ok(Ok(None)));
+        assert_eq!(
+            listener(r#"{ "admin": { "listen": "127.0.0.1:5003" } }"#),
+            Ok(Ok(Some(ListenAddr::Tcp("127.0.0.1:5003".parse().unwrap()))))
+        );
+        assert_eq!(
+            listener(r#"{ "admin": { "listen": "unix:/tmp/admin.sock" } }"#),
+            Ok(Ok(Some(ListenAddr::Unix(PathBuf::from("/tmp/admin.sock")))))
+        );
+        assert_eq!(
+            listener(r#"{ "admin": { "enabled": false, "listen": "unix:/tmp/admin.sock" } }"#),
+            Ok(Ok(None))
+        );
+        let err =
+            AppConfig::from_json_str(r#"{ "admin": { "listen": "localhost" } }"#).unwrap_err();
+        assert!(err.contains("admin.listen"), "{}", err);
+    }
+
+    #[test]
+    fn grpc_listeners_replace_grpc_listen() {
+        let listeners =
+            |json: &str| AppConfig::from_json_str(json).and_then(|c| c.grpc_listeners());
+        let default = listeners("{}").unwrap();
+        assert_eq!(default.len(), 1);
+        assert_eq!(default[0].addr.to_string(), DEFAULT_GRPC_LISTEN);
+
+        let several = listeners(
+            r#"{ "grpc_listeners": [
+                { "listen": "10.0.0.5:5002" },
+                { "listen": "[::1]:5002", "tls": { "cert": "/etc/agg/cert.pem", "key": "/etc/agg/key.pem" }, "auth_token": "s3cret" },
+                { "listen": "unix:/run/agg.sock" }
+            ] }"#,
+        )
+        .unwrap();
+        let addrs: Vec<String> = several.iter().map(|l| l.addr.to_string()).collect();
+        assert_eq!(addrs, ["10.0.0.5:5002", "[::1]:5002", "unix:/run/agg.sock"]);
+        assert!(several[0].tls.is_none() && several[0].auth_token.is_none());
+        assert_eq!(
+            several[1].tls.as_ref().map(|tls| tls.cert.clone()),
+            Some(PathBuf::from("/etc/agg/cert.pem"))
+        );
+        assert_eq!(several[1].auth_token.as_deref(), Some("s3cret"));
+
+        for (json, expected) in [
+            (
+                r#"{ "grpc_listen": "127.0.0.1:1", "grpc_listeners": [{ "listen": "127.0.0.1:2" }] }"#,
+                "not both",
+            ),
+            (
+                r#"{ "grpc_listeners": [{ "listen": "127.0.0.1:2" }, { "listen": "nowhere" }] }"#,
+                "grpc_listeners[1].listen 'nowhere'",
+            ),
+            (
+                r#"{ "grpc_listeners": [{ "listen": "unix:/run/agg.sock", "tls": { "cert": "c", "key": "k" } }] }"#,
+                "grpc_listeners[0].tls",
+            ),
+            (
+                r#"{ "grpc_listeners": [{ "listen": "127.0.0.1:2" }, { "listen": "127.0.0.1:2" }] }"#,
+                "listed twice",
+            ),
+        ] {
+            let err = AppConfig::from_json_str(json).unwrap_err();
+            assert!(err.contains(expected), "{}", err);
+        }
+    }
+}
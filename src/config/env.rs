@@ -0,0 +1,296 @@
+use serde_json::{Map, Number, Value};
+use std::collections::BTreeMap;
+
+pub const ENV_PREFIX: &str = "AGG_";
+
+/// Separates nesting levels in a variable name, e.g. `AGG_SYMBOLS__ETHBTC__MAX_DEPTH`
+const NESTING: &str = "__";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Str,
+    Int,
+    Float,
+    Bool,
+    /// Comma-separated strings
+    List,
+}
+
+/// Every config file key, as lowercase path segments; `*` matches a symbol or exchange name
+const SCHEMA: &[(&str, Kind)] = &[
+    ("binance_variant", Kind::Str),
+    ("log_level", Kind::Str),
+    ("exchanges", Kind::List),
+    ("grpc_listen", Kind::Str),
+    ("endpoints.binance_rest", Kind::Str),
+    ("endpoints.binance_ws", Kind::Str),
+    ("endpoints.bitstamp_rest", Kind::Str),
+    ("endpoints.bitstamp_ws", Kind::Str),
+    ("endpoints.kraken_rest", Kind::Str),
+    ("endpoints.kraken_ws", Kind::Str),
+    ("endpoints.coinbase_rest", Kind::Str),
+    ("endpoints.coinbase_ws", Kind::Str),
+    ("endpoints.okx_rest", Kind::Str),
+    ("endpoints.okx_ws", Kind::Str),
+    ("endpoints.bybit_rest", Kind::Str),
+    ("endpoints.bybit_ws", Kind::Str),
+    ("endpoints.kucoin_rest", Kind::Str),
+    ("endpoints.kucoin_ws", Kind::Str),
+    ("endpoints.gateio_rest", Kind::Str),
+    ("endpoints.gateio_ws", Kind::Str),
+    ("endpoints.bitfinex_rest", Kind::Str),
+    ("endpoints.bitfinex_ws", Kind::Str),
+    ("admin.enabled", Kind::Bool),
+    ("admin.listen", Kind::Str),
+    ("sequence_reset.*.enabled", Kind::Bool),
+    ("sequence_reset.*.min_drop", Kind::Int),
+    ("sequence_reset.*.min_factor", Kind::Float),
+    ("one_sided_summaries", Kind::Str),
+    ("spread_convention", Kind::Str),
+    ("defaults.price_scale", Kind::Float),
+    ("defaults.max_depth", Kind::Int),
+    ("defaults.dust_threshold", Kind::Float),
+    ("defaults.outlier_tolerance_bps", Kind::Float),
+    ("defaults.stale_after_ms", Kind::Int),
+    ("defaults.index_weights.*", Kind::Float),
+    ("defaults.depth_weights.*", Kind::Float),
+    ("defaults.tie_break", Kind::Str),
+    ("defaults.venue_priority", Kind::List),
+    ("defaults.smart_best_min_qty", Kind::Float),
+    ("defaults.max_level_distance_bps", Kind::Float),
+    ("defaults.first_data_timeout_ms", Kind::Int),
+    ("symbols.*.price_scale", Kind::Float),
+    ("symbols.*.max_depth", Kind::Int),
+    ("symbols.*.dust_threshold", Kind::Float),
+    ("symbols.*.outlier_tolerance_bps", Kind::Float),
+    ("symbols.*.stale_after_ms", Kind::Int),
+    ("symbols.*.index_weights.*", Kind::Float),
+    ("symbols.*.depth_weights.*", Kind::Float),
+    ("symbols.*.tie_break", Kind::Str),
+    ("symbols.*.venue_priority", Kind::List),
+    ("symbols.*.smart_best_min_qty", Kind::Float),
+    ("symbols.*.max_level_distance_bps", Kind::Float),
+    ("symbols.*.first_data_timeout_ms", Kind::Int),
+    ("symbols.*.exchanges.*", Kind::Str),
+];
+
+/// `AGG_*` environment variables layered over the config file. Variables read by
+/// command-line flags (e.g. `AGG_QUEUE_CAPACITY`) are left to the flags.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EnvLayer {
+    vars: BTreeMap<String, String>,
+}
+
+impl EnvLayer {
+    /// The `AGG_*` entries of `vars`, except the names in `cli`
+    pub fn new(vars: impl IntoIterator<Item = (String, String)>, cli: &[&str]) -> Self {
+        Self {
+            vars: vars
+                .into_iter()
+                .filter(|(name, _)| name.starts_with(ENV_PREFIX) && !cli.contains(&name.as_str()))
+                .collect(),
+        }
+    }
+
+    pub fn from_process(cli: &[&str]) -> Self {
+        Self::new(std::env::vars(), cli)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vars.is_empty()
+    }
+
+    /// The variables as a config file object. Errors name the offending variable.
+    pub fn to_json(&self) -> Result<Value, String> {
+        let mut root = Value::Object(Map::new());
+        for (name, raw) in &self.vars {
+            let invalid = |reason: String| format!("invalid config: {}: {}", name, reason);
+            let path: Vec<String> = name[ENV_PREFIX.len()..]
+                .split(NESTING)
+                .map(str::to_lowercase)
+                .collect();
+            if path.iter().any(String::is_empty) {
+                return Err(invalid("empty path segment".to_string()));
+            }
+            let kind = kind_of(&path).ok_or_else(|| invalid("not a config key".to_string()))?;
+            let value = parse(raw, kind).map_err(invalid)?;
+            insert(&mut root, &path, value).map_err(invalid)?;
+        }
+        Ok(root)
+    }
+}
+
+fn kind_of(path: &[String]) -> Option<Kind> {
+    SCHEMA.iter().find_map(|(pattern, kind)| {
+        let segments: Vec<&str> = pattern.split('.').collect();
+        let matches = segments.len() == path.len()
+            && segments
+                .iter()
+                .zip(path)
+                .all(|(pattern, segment)| *pattern == "*" || pattern == segment);
+        matches.then_some(*kind)
+    })
+}
+
+fn parse(raw: &str, kind: Kind) -> Result<Value, String> {
+    let raw = raw.trim();
+    match kind {
+        Kind::Str => Ok(Value::String(raw.to_string())),
+        Kind::Int => raw
+            .parse::<u64>()
+            .map(Value::from)
+            .map_err(|_| format!("'{}' is not a non-negative integer", raw)),
+        Kind::Float => raw
+            .parse::<f64>()
+            .ok()
+            .and_then(Number::from_f64)
+            .map(Value::Number)
+            .ok_or_else(|| format!("'{}' is not a number", raw)),
+        Kind::Bool => match raw.to_lowercase().as_str() {
+            "true" | "1" => Ok(Value::Bool(true)),
+            "false" | "0" => Ok(Value::Bool(false)),
+            _ => Err(format!("'{}' is not true or false", raw)),
+        },
+        Kind::List => {
+            let items: Vec<&str> = raw.split(',').map(str::trim).collect();
+            if items.iter().any(|item| item.is_empty()) {
+                return Err(format!(
+                    "'{}' has an empty item; expected a comma-separated list like a,b",
+                    raw
+                ));
+            }
+            Ok(Value::from(items))
+        }
+    }
+}
+
+fn insert(root: &mut Value, path: &[String], value: Value) -> Result<(), String> {
+    let (last, parents) = path.split_last().expect("paths are never empty");
+    let mut node = root;
+    for segment in parents {
+        node = node
+            .as_object_mut()
+            .ok_or("conflicts with another variable")?
+            .entry(segment.clone())
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+    node.as_object_mut()
+        .ok_or("conflicts with another variable")?
+        .insert(last.clone(), value);
+    Ok(())
+}
+
+/// Overwrite `base` with every key set in `layer`, merging nested objects
+pub fn merge(base: &mut Value, layer: Value) {
+    match (base, layer) {
+        (Value::Object(base), Value::Object(layer)) => {
+            for (key, value) in layer {
+                merge(base.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (base, layer) => *base = layer,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AppConfig;
+    use crate::modules::binance::BinanceVariant;
+    use crate::modules::types::Exchange;
+    use std::collections::HashMap;
+
+    fn env(vars: &[(&str, &str)]) -> EnvLayer {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        EnvLayer::new(vars, &["AGG_QUEUE_CAPACITY"])
+    }
+
+    #[test]
+    fn a_complete_config_builds_from_env_vars_alone() {
+        let layer = env(&[
+            ("AGG_BINANCE_VARIANT", "us"),
+            ("AGG_LOG_LEVEL", "debug"),
+            ("AGG_EXCHANGES", "binance, bitstamp"),
+            ("AGG_GRPC_LISTEN", "0.0.0.0:6000"),
+            ("AGG_ENDPOINTS__BINANCE_REST", "http://127.0.0.1:8080"),
+            ("AGG_ENDPOINTS__BINANCE_WS", "ws://127.0.0.1:8081"),
+            ("AGG_ENDPOINTS__BITSTAMP_REST", "http://127.0.0.1:8082"),
+            ("AGG_ENDPOINTS__BITSTAMP_WS", "ws://127.0.0.1:8083"),
+            ("AGG_ADMIN__ENABLED", "true"),
+            ("AGG_ADMIN__LISTEN", "unix:/tmp/admin.sock"),
+            ("AGG_DEFAULTS__PRICE_SCALE", "1e8"),
+            ("AGG_DEFAULTS__MAX_DEPTH", "500"),
+            ("AGG_DEFAULTS__DUST_THRESHOLD", "0.0001"),
+            ("AGG_DEFAULTS__OUTLIER_TOLERANCE_BPS", "300"),
+            ("AGG_DEFAULTS__STALE_AFTER_MS", "5000"),
+            ("AGG_DEFAULTS__INDEX_WEIGHTS__BINANCE_US", "2"),
+            ("AGG_SYMBOLS__BTCUSDT__PRICE_SCALE", "100"),
+            ("AGG_SYMBOLS__BTCUSDT__MAX_DEPTH", "50"),
+            ("AGG_SYMBOLS__BTCUSDT__INDEX_WEIGHTS__BITSTAMP", "0.5"),
+            // Read by its command-line flag, not the config
+            ("AGG_QUEUE_CAPACITY", "64"),
+            ("PATH", "/usr/bin"),
+        ]);
+        let config = AppConfig::load_layered(None, &layer).unwrap();
+
+        assert_eq!(config.binance_variant, BinanceVariant::Us);
+        assert_eq!(config.log_level.as_deref(), Some("debug"));
+        assert_eq!(
+            config.enabled_exchanges().unwrap(),
+            vec![Exchange::Bitstamp, Exchange::BinanceUs]
+        );
+        assert_eq!(config.grpc_addr().unwrap().port(), 6000);
+        assert_eq!(config.bitstamp_endpoint().ws, "ws://127.0.0.1:8083");
+        assert_eq!(config.binance_endpoint().rest, "http://127.0.0.1:8080");
+        assert_eq!(config.admin.listen.as_deref(), Some("unix:/tmp/admin.sock"));
+        let eth = config.resolve("ethbtc").settings;
+        assert_eq!(eth.price_scale, 1e8);
+        assert_eq!(eth.max_depth, Some(500));
+        assert_eq!(eth.stale_after_ms, Some(5000));
+        assert_eq!(eth.index_weights["binance_us"], 2.0);
+        let btc = config.resolve("btcusdt").settings;
+        assert_eq!(btc.price_scale, 100.0);
+        assert_eq!(btc.max_depth, Some(50));
+        assert_eq!(btc.dust_threshold, 0.0001);
+        assert_eq!(btc.index_weights["bitstamp"], 0.5);
+    }
+
+    #[test]
+    fn malformed_values_name_the_variable() {
+        let err = AppConfig::load_layered(None, &env(&[("AGG_EXCHANGES", "binance,,bitstamp")]))
+            .unwrap_err();
+        assert!(err.contains("AGG_EXCHANGES"), "{}", err);
+        assert!(err.contains("comma-separated list"), "{}", err);
+
+        for (name, value) in [
+            ("AGG_DEFAULTS__MAX_DEPTH", "-3"),
+            ("AGG_ADMIN__ENABLED", "yes please"),
+            ("AGG_SYMBOLS__ETHBTC__PRICE_SCALE", "wide"),
+            ("AGG_DEFAULTS__MAX_DEPT", "5"),
+            ("AGG_SYMBOLS____MAX_DEPTH", "5"),
+        ] {
+            let err = AppConfig::load_layered(None, &env(&[(name, value)])).unwrap_err();
+            assert!(err.contains(name), "{}: {}", name, err);
+        }
+    }
+
+    #[test]
+    fn env_vars_override_the_file() {
+        let path = std::env::temp_dir().join(format!("env-layer-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"{"defaults":{"max_depth":10,"dust_threshold":0.5},"log_level":"info"}"#,
+        )
+        .unwrap();
+        let layer = env(&[("AGG_DEFAULTS__MAX_DEPTH", "20")]);
+        let config = AppConfig::load_layered(path.to_str(), &layer).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let settings = config.resolve("ethbtc").settings;
+        assert_eq!(settings.max_depth, Some(20));
+        assert_eq!(settings.dust_threshold, 0.5, "kept from the file");
+        assert_eq!(config.log_level.as_deref(), Some("info"));
+    }
+}
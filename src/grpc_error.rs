@@ -0,0 +1,139 @@
+use crate::grpc_service::orderbook;
+use crate::handlers::HandlerError;
+use std::fmt::Write;
+use tonic::metadata::{MetadataMap, MetadataValue};
+use tonic::{Code, Status};
+
+/// Reason code of a refused request, e.g. `UNKNOWN_SYMBOL`
+pub const REASON_KEY: &str = "x-error-reason";
+/// Request field at fault, e.g. `points`
+pub const FIELD_KEY: &str = "x-error-field";
+/// With `NOT_SYNCED`, one `exchange=STATE` value per known exchange
+pub const SYNC_STATE_KEY: &str = "x-sync-state";
+
+/// The one place a handler error becomes a gRPC status: a code, the message, and the
+/// reason, field and sync states as metadata
+impl From<HandlerError> for Status {
+    fn from(error: HandlerError) -> Self {
+        let code = match &error {
+            HandlerError::InvalidArgument { .. } | HandlerError::DepthOutOfRange { .. } => {
+                Code::InvalidArgument
+            }
+            HandlerError::UnknownSymbol(_) => Code::NotFound,
+            HandlerError::ExchangeDisabled { .. } | HandlerError::FailedPrecondition(_) => {
+                Code::FailedPrecondition
+            }
+            HandlerError::NotSynced(_) => Code::Unavailable,
+            HandlerError::NotEnabled(_) => Code::Unimplemented,
+        };
+        let mut metadata = MetadataMap::new();
+        metadata.insert(REASON_KEY, MetadataValue::from_static(error.reason()));
+        if let Some(field) = error.field() {
+            metadata.insert(FIELD_KEY, MetadataValue::from_static(field));
+        }
+        if let HandlerError::NotSynced(states) = &error {
+            for (exchange, state) in states {
+                let state = orderbook::ConnectionState::from(*state).as_str_name();
+                // Exchange names are ASCII identifiers
+                if let Ok(value) = format!("{}={}", exchange, state).parse() {
+                    metadata.append(SYNC_STATE_KEY, value);
+                }
+            }
+        }
+        Status::with_metadata(code, error.to_string(), metadata)
+    }
+}
+
+/// Structured detail of a refused request, read back from a status's metadata
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ErrorDetails {
+    pub reason: Option<String>,
+    pub field: Option<String>,
+    /// (exchange, connection state) for `NOT_SYNCED`
+    pub sync_states: Vec<(String, String)>,
+}
+
+impl ErrorDetails {
+    pub fn from_status(status: &Status) -> Self {
+        let metadata = status.metadata();
+        let text = |key| {
+            metadata
+                .get(key)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+        let sync_states = metadata
+            .get_all(SYNC_STATE_KEY)
+            .iter()
+            .filter_map(|v| v.to_str().ok()?.split_once('='))
+            .map(|(exchange, state)| (exchange.to_string(), state.to_string()))
+            .collect();
+        Self {
+            reason: text(REASON_KEY),
+            field: text(FIELD_KEY),
+            sync_states,
+        }
+    }
+}
+
+/// `Code: message`, followed by the reason, field and sync states when the server sent them
+pub fn describe(status: &Status) -> String {
+    let mut text = format!("{:?}: {}", status.code(), status.message());
+    let details = ErrorDetails::from_status(status);
+    if let Some(reason) = &details.reason {
+        let _ = write!(text, " [{}", reason);
+        if let Some(field) = &details.field {
+            let _ = write!(text, " field={}", field);
+        }
+        for (exchange, state) in &details.sync_states {
+            let _ = write!(text, " {}={}", exchange, state);
+        }
+        text.push(']');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::status::ConnectionState;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn each_error_carries_its_code_reason_and_field() {
+        let status = Status::from(HandlerError::DepthOutOfRange {
+            field: "points",
+            message: "points must be between 1 and 10000".to_string(),
+        });
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(
+            describe(&status),
+            "InvalidArgument: points must be between 1 and 10000 [DEPTH_OUT_OF_RANGE field=points]"
+        );
+
+        let status = Status::from(HandlerError::NotEnabled("config reload"));
+        assert_eq!(status.code(), Code::Unimplemented);
+        assert_eq!(status.message(), "config reload is not enabled");
+        let details = ErrorDetails::from_status(&status);
+        assert_eq!(details.reason.as_deref(), Some("NOT_ENABLED"));
+        assert_eq!(details.field, None);
+
+        let states = BTreeMap::from([
+            ("binance".to_string(), ConnectionState::Subscribing),
+            ("bitstamp".to_string(), ConnectionState::NotConfigured),
+        ]);
+        let status = Status::from(HandlerError::NotSynced(states));
+        assert_eq!(status.code(), Code::Unavailable);
+        assert_eq!(
+            ErrorDetails::from_status(&status).sync_states,
+            vec![
+                ("binance".to_string(), "SUBSCRIBING".to_string()),
+                ("bitstamp".to_string(), "NOT_CONFIGURED".to_string()),
+            ]
+        );
+
+        let plain = Status::not_found("gone");
+        assert_eq!(ErrorDetails::from_status(&plain), ErrorDetails::default());
+        assert_eq!(describe(&plain), "NotFound: gone");
+    }
+}
@@ -0,0 +1,317 @@
+use crate::config::{GrpcListener, ListenAddr, TlsConfig};
+use std::io;
+use std::net::SocketAddr;
+use std::os::unix::fs::FileTypeExt;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream, UnixListener};
+use tokio::sync::mpsc;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tonic::service::Interceptor;
+use tonic::transport::server::{Connected, Router, TcpConnectInfo};
+use tonic::{Request, Status};
+
+/// A client that opened a connection has this long to finish the TLS handshake
+pub const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Connections accepted but still in their TLS handshake, per listener
+const PENDING_HANDSHAKES: usize = 64;
+
+/// A configured listener with its socket bound, ready to serve
+#[derive(Debug)]
+pub struct BoundListener {
+    pub config: GrpcListener,
+    socket: Socket,
+}
+
+enum Socket {
+    Tcp(TcpListener),
+    Tls(TcpListener, TlsAcceptor),
+    Unix(UnixListener),
+}
+
+impl std::fmt::Debug for Socket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Socket::Tcp(listener) => write!(f, "Tcp({:?})", listener.local_addr()),
+            Socket::Tls(listener, _) => write!(f, "Tls({:?})", listener.local_addr()),
+            Socket::Unix(listener) => write!(f, "Unix({:?})", listener.local_addr()),
+        }
+    }
+}
+
+/// Bind every listener, failing on the first one that can't be bound and naming it.
+/// Sockets already bound are closed again when that happens.
+pub async fn bind_all(listeners: &[GrpcListener]) -> Result<Vec<BoundListener>, String> {
+    let mut bound = vec![];
+    for listener in listeners {
+        bound.push(bind(listener).await?);
+    }
+    Ok(bound)
+}
+
+pub async fn bind(listener: &GrpcListener) -> Result<BoundListener, String> {
+    let addr = &listener.addr;
+    let socket = match addr {
+        ListenAddr::Tcp(socket_addr) => {
+            let tcp = TcpListener::bind(socket_addr)
+                .await
+                .map_err(|e| format!("failed to bind gRPC listener {}: {}", addr, e))?;
+            match &listener.tls {
+                Some(tls) => Socket::Tls(
+                    tcp,
+                    tls_acceptor(tls)
+                        .map_err(|e| format!("gRPC listener {}: invalid TLS setup: {}", addr, e))?,
+                ),
+                None => Socket::Tcp(tcp),
+            }
+        }
+        ListenAddr::Unix(path) => {
+            // A socket left behind by a previous run would make bind fail; never touch
+            // anything else at that path
+            if std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+                let _ = std::fs::remove_file(path);
+            }
+            Socket::Unix(
+                UnixListener::bind(path)
+                    .map_err(|e| format!("failed to bind gRPC listener {}: {}", addr, e))?,
+            )
+        }
+    };
+    Ok(BoundListener {
+        config: listener.clone(),
+        socket,
+    })
+}
+
+fn tls_acceptor(tls: &TlsConfig) -> Result<TlsAcceptor, String> {
+    let read = |path: &std::path::Path| {
+        std::fs::read(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))
+    };
+    let certs = CertificateDer::pem_slice_iter(&read(&tls.cert)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("bad certificate in {}: {:?}", tls.cert.display(), e))?;
+    if certs.is_empty() {
+        return Err(format!("no certificate in {}", tls.cert.display()));
+    }
+    let key = PrivateKeyDer::from_pem_slice(&read(&tls.key)?)
+        .map_err(|e| format!("bad private key in {}: {:?}", tls.key.display(), e))?;
+    let mut config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| e.to_string())?;
+    config.alpn_protocols = vec![b"h2".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+impl BoundListener {
+    /// The bound TCP address, e.g. to find the port picked for port 0
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match &self.socket {
+            Socket::Tcp(listener) | Socket::Tls(listener, _) => listener.local_addr().ok(),
+            Socket::Unix(_) => None,
+        }
+    }
+
+    /// Serve `router` on this listener until it fails
+    pub async fn serve(self, router: Router) -> Result<(), String> {
+        let addr = self.config.addr;
+        let tls = if self.config.tls.is_some() {
+            " (TLS)"
+        } else {
+            ""
+        };
+        tracing::info!("gRPC server starting on {}{}", addr, tls);
+        let served = match self.socket {
+            Socket::Tcp(listener) => {
+                let incoming = async_stream::stream! {
+                    loop {
+                        yield listener.accept().await.map(|(stream, _)| stream);
+                    }
+                };
+                router.serve_with_incoming(incoming).await
+            }
+            Socket::Unix(listener) => {
+                let incoming = async_stream::stream! {
+                    loop {
+                        yield listener.accept().await.map(|(stream, _)| stream);
+                    }
+                };
+                router.serve_with_incoming(incoming).await
+            }
+            Socket::Tls(listener, acceptor) => {
+                let mut handshaken = tls_handshakes(listener, acceptor);
+                let incoming = async_stream::stream! {
+                    while let Some(connection) = handshaken.recv().await {
+                        yield Ok::<_, io::Error>(connection);
+                    }
+                };
+                router.serve_with_incoming(incoming).await
+            }
+        };
+        served.map_err(|e| format!("gRPC server on {} failed: {}", addr, e))
+    }
+}
+
+/// Accept connections and run their TLS handshakes concurrently, so a slow client doesn't
+/// hold up the others. Failed handshakes are logged and dropped.
+fn tls_handshakes(listener: TcpListener, acceptor: TlsAcceptor) -> mpsc::Receiver<TlsConnection> {
+    let (tx, rx) = mpsc::channel(PENDING_HANDSHAKES);
+    tokio::spawn(async move {
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    tracing::debug!("TLS listener accept failed: {}", e);
+                    continue;
+                }
+            };
+            if tx.is_closed() {
+                break;
+            }
+            let acceptor = acceptor.clone();
+            let handshaken = tx.clone();
+            tokio::spawn(async move {
+                match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => {
+                        let _ = handshaken.send(TlsConnection(stream)).await;
+                    }
+                    Ok(Err(e)) => tracing::debug!("TLS handshake with {} failed: {}", peer, e),
+                    Err(_) => tracing::debug!("TLS handshake with {} timed out", peer),
+                }
+            });
+        }
+    });
+    rx
+}
+
+/// A TLS connection that tonic can serve, reporting the peer like a plain TCP one
+struct TlsConnection(tokio_rustls::server::TlsStream<TcpStream>);
+
+impl Connected for TlsConnection {
+    type ConnectInfo = TcpConnectInfo;
+
+    fn connect_info(&self) -> Self::ConnectInfo {
+        self.0.get_ref().0.connect_info()
+    }
+}
+
+impl AsyncRead for TlsConnection {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for TlsConnection {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().0).poll_shutdown(cx)
+    }
+}
+
+/// Rejects calls without `authorization: Bearer <token>` when a token is configured
+#[derive(Clone, Debug, Default)]
+pub struct BearerAuth {
+    token: Option<Arc<str>>,
+}
+
+impl BearerAuth {
+    pub fn new(token: Option<String>) -> Self {
+        Self {
+            token: token.map(Arc::from),
+        }
+    }
+}
+
+impl Interceptor for BearerAuth {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let Some(expected) = &self.token else {
+            return Ok(request);
+        };
+        let presented = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match presented {
+            Some(token) if constant_time_eq(token.as_bytes(), expected.as_bytes()) => Ok(request),
+            _ => Err(Status::unauthenticated("missing or invalid bearer token")),
+        }
+    }
+}
+
+/// Compare without returning early, so timing doesn't reveal how much of a token matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bearer_auth_only_checks_when_a_token_is_set() {
+        let request = |auth: Option<&str>| {
+            let mut request = Request::new(());
+            if let Some(auth) = auth {
+                request
+                    .metadata_mut()
+                    .insert("authorization", auth.parse().unwrap());
+            }
+            request
+        };
+        let mut open = BearerAuth::new(None);
+        assert!(open.call(request(None)).is_ok());
+
+        let mut guarded = BearerAuth::new(Some("s3cret".to_string()));
+        assert!(guarded.call(request(Some("Bearer s3cret"))).is_ok());
+        for auth in [
+            None,
+            Some("Bearer s3cre"),
+            Some("s3cret"),
+            Some("Bearer s3cret2"),
+        ] {
+            let status = guarded.call(request(auth)).unwrap_err();
+            assert_eq!(status.code(), tonic::Code::Unauthenticated, "{:?}", auth);
+        }
+    }
+
+    #[tokio::test]
+    async fn bad_tls_files_fail_the_bind_naming_the_listener() {
+        let listener = GrpcListener {
+            addr: ListenAddr::Tcp("127.0.0.1:0".parse().unwrap()),
+            tls: Some(TlsConfig {
+                cert: "/nonexistent/cert.pem".into(),
+                key: "/nonexistent/key.pem".into(),
+            }),
+            auth_token: None,
+        };
+        let err = bind(&listener).await.unwrap_err();
+        assert!(err.contains("gRPC listener 127.0.0.1:0"), "{}", err);
+        assert!(err.contains("/nonexistent/cert.pem"), "{}", err);
+    }
+}
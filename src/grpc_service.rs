@@ -1,7 +1,41 @@
+use crate::config::ConfigReloader;
+use crate::grpc_listeners::BearerAuth;
+use crate::handlers::{HandlerError, Handlers};
+use crate::modules::aggregated_orderbook::{
+    BookState, CumulativeScope, DepthCurve, DepthUnit, EmissionReason, LevelDetail, Top10Snapshot,
+};
+use crate::modules::build_info::{BuildInfo, ServerInfo};
+use crate::modules::circuit_breaker::CircuitState;
+use crate::modules::commands::ConnectorCommand;
+use crate::modules::consistency::SummaryConsistency;
+use crate::modules::cross_check::CrossCheckCounts;
+use crate::modules::data_quality::DataQuality;
+use crate::modules::dedup::{DedupConfig, SummaryDedup};
+use crate::modules::limits::PayloadViolations;
+use crate::modules::multi_summary::MultiSummary;
+use crate::modules::one_sided::OneSidedSummaries;
+use crate::modules::parse_failures::ParseFailure;
+use crate::modules::quote::{NotionalUnit, QuoteConverter};
+use crate::modules::rate::RateStats;
+use crate::modules::snapshot_fetch::SyncProgress;
+use crate::modules::startup::Readiness;
+use crate::modules::stats::{
+    BookStats, ExcludedLevels, ImprovementSample, SideImprovement, SideShape, VenueImprovement,
+};
+use crate::modules::status::{ConnectionState, ExchangeStatus, SharedStatus};
+use crate::modules::stream_metrics::{StreamEnd, StreamGuard};
+use crate::modules::throttle::GaugeSample;
+use crate::modules::timeseries::{Bucket, SeriesInfo};
+use crate::modules::traded_estimate::{TradedEstimate, TradedVolumeEstimator};
 use crate::modules::types::AggregatedOrderBook;
-use async_stream::try_stream;
+use crate::modules::uptime::UptimeReport;
+use futures::StreamExt;
+use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::sync::atomic::Ordering;
 use tokio::sync::RwLock;
+use tonic::transport::Server;
+use tonic::transport::server::Router;
 use tonic::{Request, Response, Status};
 
 // Include the generated gRPC code
@@ -9,80 +43,1067 @@ pub mod orderbook {
     tonic::include_proto!("orderbook");
 }
 
-use orderbook::orderbook_aggregator_server::{OrderbookAggregator, OrderbookAggregatorServer};
-use orderbook::{Empty, Level, Summary};
+use orderbook::admin_server::{Admin, AdminServer};
+use orderbook::discovery_server::{Discovery, DiscoveryServer};
+use orderbook::market_data_server::{MarketData, MarketDataServer};
+use orderbook::{
+    ActiveConnection, ConnectorCommandRequest, DepthCurveRequest, DepthPoint, Empty, Level,
+    MultiSummaryRequest, ParseFailureList, ParseFailuresRequest, PriceImprovementRequest,
+    PublisherStats, ReloadReport, ResetSymbolReport, ResetSymbolRequest, SeriesBucket,
+    SnapshotSync, StatefulStream, StatusReport, StreamStats, Summary, SummaryRequest, SymbolInfo,
+    SymbolList, TimeRange, TimeSeriesRequest,
+};
 
+#[derive(Clone)]
 pub struct OrderbookAggregatorService {
     pub aggregated_orderbook: Arc<RwLock<AggregatedOrderBook>>,
+    pub status: SharedStatus,
+    pub reloader: Option<Arc<ConfigReloader>>,
+    pub dedup: Option<DedupConfig>,
+    pub converter: Option<Arc<dyn QuoteConverter>>,
+    /// Set on every Summary when given
+    pub build_id: Option<String>,
+    pub one_sided: OneSidedSummaries,
+    pub consistency: SummaryConsistency,
 }
 
 impl OrderbookAggregatorService {
     pub fn new(aggregated_orderbook: Arc<RwLock<AggregatedOrderBook>>) -> Self {
         Self {
             aggregated_orderbook,
+            status: SharedStatus::default(),
+            reloader: None,
+            dedup: None,
+            converter: None,
+            build_id: None,
+            one_sided: OneSidedSummaries::default(),
+            consistency: SummaryConsistency::default(),
         }
     }
+
+    /// Stamp this build's `version+hash` on every Summary
+    pub fn with_build_id_stamp(mut self) -> Self {
+        self.build_id = Some(BuildInfo::current().build_id());
+        self
+    }
+
+    /// Skip Summaries identical to the previous one on each stream (off by default)
+    pub fn with_dedup(mut self, dedup: DedupConfig) -> Self {
+        self.dedup = Some(dedup);
+        self
+    }
+
+    /// Whether BookSummary streams send Summaries while a side of the book is empty
+    pub fn with_one_sided(mut self, one_sided: OneSidedSummaries) -> Self {
+        self.one_sided = one_sided;
+        self
+    }
+
+    /// What BookSummary streams do with a Summary that fails the consistency check
+    pub fn with_consistency(mut self, consistency: SummaryConsistency) -> Self {
+        self.consistency = consistency;
+        self
+    }
+
+    /// Lets GetDepthCurve report notionals in the converter's currency on request
+    pub fn with_converter(mut self, converter: Arc<dyn QuoteConverter>) -> Self {
+        self.converter = Some(converter);
+        self
+    }
+
+    pub fn with_reloader(mut self, reloader: Arc<ConfigReloader>) -> Self {
+        self.reloader = Some(reloader);
+        self
+    }
+
+    pub fn with_status(mut self, status: SharedStatus) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Count and log a server stream from open to close
+    fn open_stream<T: Send + 'static>(
+        &self,
+        method: &'static str,
+        peer: Option<std::net::SocketAddr>,
+        request: String,
+        stream: impl futures::Stream<Item = Result<T, Status>> + Send + 'static,
+    ) -> std::pin::Pin<Box<dyn futures::Stream<Item = Result<T, Status>> + Send + 'static>> {
+        let guard = self
+            .status
+            .streams
+            .open(method, peer.map(|peer| peer.to_string()), request);
+        Box::pin(logged(stream, guard))
+    }
+
+    fn handlers(&self) -> Handlers {
+        Handlers::new(
+            Arc::clone(&self.aggregated_orderbook),
+            Arc::clone(&self.status),
+        )
+        .with_dedup(self.dedup)
+        .with_converter(self.converter.clone())
+        .with_one_sided(self.one_sided)
+        .with_consistency(self.consistency)
+    }
 }
 
 #[tonic::async_trait]
-impl OrderbookAggregator for OrderbookAggregatorService {
+impl MarketData for OrderbookAggregatorService {
     // Not exactly sure what this is for or what it does, but it's required by the tonic library
     type BookSummaryStream =
         std::pin::Pin<Box<dyn futures::Stream<Item = Result<Summary, Status>> + Send + 'static>>;
 
     async fn book_summary(
         &self,
-        _request: Request<Empty>,
+        request: Request<SummaryRequest>,
     ) -> Result<Response<Self::BookSummaryStream>, Status> {
-        let agg_shared = Arc::clone(&self.aggregated_orderbook);
-
-        let stream = try_stream! {
-            loop {
-                let agg = agg_shared.read().await;
-
-                // Get top 10 levels from the aggregated orderbook
-                // Take an atomic snapshot (bids, asks, spread from same moment)
-                let snap = agg.get_top10_snapshot();
-
-                // Convert to gRPC format
-                let bids: Vec<Level> = snap.bids.into_iter().map(|level| Level {
-                    exchange: level.exchange.to_string(),
-                    price: level.price,
-                    amount: level.amount,
-                }).collect();
-
-                let asks: Vec<Level> = snap.asks.into_iter().map(|level| Level {
-                    exchange: level.exchange.to_string(),
-                    price: level.price,
-                    amount: level.amount,
-                }).collect();
-
-                let summary = Summary {
-                    spread: snap.spread,
-                    asks,
-                    bids,
+        let peer = request.remote_addr();
+        let request = request.into_inner();
+        let symbol = self.aggregated_orderbook.read().await.config.symbol.clone();
+        let described = format!(
+            "symbol={} depth={} depth_unit={:?} details={} cumulative={} cumulative_per_exchange={} display_decimals={:?} invert={}",
+            symbol,
+            SUMMARY_DEPTH,
+            request.depth_unit(),
+            request.include_level_details,
+            request.cumulative,
+            request.cumulative_per_exchange,
+            request.display_decimals,
+            request.invert
+        );
+        // Deduplicating streams keep state per client, so they count towards the limit
+        let peer = peer.map(|peer| peer.to_string());
+        let guard = match self.dedup {
+            Some(_) => self
+                .status
+                .streams
+                .open_stateful(
+                    "BookSummary",
+                    peer,
+                    described,
+                    SummaryDedup::retained_state(),
+                )
+                .map_err(Status::resource_exhausted)?,
+            None => self.status.streams.open("BookSummary", peer, described),
+        };
+        let unit = match request.depth_unit() {
+            orderbook::DepthUnit::PriceLevels => DepthUnit::PriceLevels,
+            orderbook::DepthUnit::Entries => DepthUnit::Entries,
+        };
+        let details = request.include_level_details;
+        let build_id = self.build_id.clone();
+        let cumulative = request
+            .cumulative
+            .then_some(if request.cumulative_per_exchange {
+                CumulativeScope::PerExchange
+            } else {
+                CumulativeScope::Consolidated
+            });
+        let summaries = self
+            .handlers()
+            .subscribe(
+                Some(SUMMARY_DEPTH),
+                unit,
+                request.display_decimals,
+                request.invert,
+            )
+            .await
+            .map(move |snap| {
+                let snap = match cumulative {
+                    Some(scope) => snap.into_cumulative(scope),
+                    None => snap,
+                };
+                let mut summary = if details {
+                    summary_with_details(snap)
+                } else {
+                    Summary::from(snap)
                 };
+                summary.build_id = build_id.clone();
+                tracing::debug!(
+                    "Sending snapshot: {} bids, {} asks, spread: {:?}",
+                    summary.bids.len(),
+                    summary.asks.len(),
+                    summary.spread
+                );
+                summary
+            });
+
+        Ok(Response::new(Box::pin(logged(summaries.map(Ok), guard))))
+    }
+
+    async fn get_depth_curve(
+        &self,
+        request: Request<DepthCurveRequest>,
+    ) -> Result<Response<orderbook::DepthCurve>, Status> {
+        let request = request.into_inner();
+        let curve = self
+            .handlers()
+            .depth_curve(
+                &request.symbol,
+                request.points,
+                request.range_bps,
+                request.convert_notional,
+                request.invert,
+            )
+            .await?;
+        Ok(Response::new(orderbook::DepthCurve::from(curve)))
+    }
+
+    async fn get_book_stats(
+        &self,
+        _request: Request<Empty>,
+    ) -> Result<Response<orderbook::BookStats>, Status> {
+        let stats = self.handlers().book_stats().await;
+        Ok(Response::new(orderbook::BookStats::from(stats)))
+    }
+
+    async fn get_price_improvement(
+        &self,
+        request: Request<PriceImprovementRequest>,
+    ) -> Result<Response<orderbook::PriceImprovement>, Status> {
+        let request = request.into_inner();
+        let sample = self
+            .handlers()
+            .price_improvement(&request.symbol, request.clip_size)
+            .await?;
+        Ok(Response::new(orderbook::PriceImprovement::from(sample)))
+    }
+
+    async fn get_traded_volume_estimate(
+        &self,
+        _request: Request<Empty>,
+    ) -> Result<Response<orderbook::TradedVolumeEstimate>, Status> {
+        let estimate = self
+            .handlers()
+            .traded_volume_estimate()
+            .await
+            .ok_or(HandlerError::NotEnabled("the traded volume estimate"))?;
+        Ok(Response::new(orderbook::TradedVolumeEstimate::from(
+            estimate,
+        )))
+    }
+
+    type StreamImbalanceStream = std::pin::Pin<
+        Box<dyn futures::Stream<Item = Result<orderbook::GaugeSample, Status>> + Send + 'static>,
+    >;
+
+    async fn stream_imbalance(
+        &self,
+        request: Request<Empty>,
+    ) -> Result<Response<Self::StreamImbalanceStream>, Status> {
+        let peer = request.remote_addr();
+        let samples = self
+            .handlers()
+            .subscribe_imbalance()
+            .await
+            .ok_or(HandlerError::NotEnabled("the imbalance gauge"))?;
+        let samples = samples.map(orderbook::GaugeSample::from);
+        Ok(Response::new(self.open_stream(
+            "StreamImbalance",
+            peer,
+            String::new(),
+            samples.map(Ok),
+        )))
+    }
+
+    async fn get_multi_summary(
+        &self,
+        request: Request<MultiSummaryRequest>,
+    ) -> Result<Response<orderbook::MultiSummary>, Status> {
+        let request = request.into_inner();
+        let depth = multi_summary_depth(request.depth)?;
+        let summary = self
+            .handlers()
+            .multi_summary(&request.symbols, Some(depth), DepthUnit::PriceLevels)
+            .await?;
+        Ok(Response::new(orderbook::MultiSummary::from(summary)))
+    }
+
+    type StreamMultiSummaryStream = std::pin::Pin<
+        Box<dyn futures::Stream<Item = Result<orderbook::MultiSummary, Status>> + Send + 'static>,
+    >;
+
+    async fn stream_multi_summary(
+        &self,
+        request: Request<MultiSummaryRequest>,
+    ) -> Result<Response<Self::StreamMultiSummaryStream>, Status> {
+        let peer = request.remote_addr();
+        let request = request.into_inner();
+        let depth = multi_summary_depth(request.depth)?;
+        let summaries = self
+            .handlers()
+            .subscribe_multi(&request.symbols, Some(depth), DepthUnit::PriceLevels)
+            .await?
+            .map(orderbook::MultiSummary::from);
+        Ok(Response::new(self.open_stream(
+            "StreamMultiSummary",
+            peer,
+            format!("symbols={:?} depth={}", request.symbols, depth),
+            summaries.map(Ok),
+        )))
+    }
+}
+
+/// `depth` of a `MultiSummaryRequest`, by default the depth of a Summary
+fn multi_summary_depth(depth: Option<u32>) -> Result<usize, HandlerError> {
+    match depth {
+        None => Ok(SUMMARY_DEPTH),
+        Some(depth) if (1..=SUMMARY_DEPTH as u32).contains(&depth) => Ok(depth as usize),
+        Some(_) => Err(HandlerError::DepthOutOfRange {
+            field: "depth",
+            message: format!("depth must be between 1 and {}", SUMMARY_DEPTH),
+        }),
+    }
+}
 
-                tracing::debug!("Sending snapshot: {} bids, {} asks, spread: {:.4}",
-                    summary.bids.len(), summary.asks.len(), summary.spread);
+#[tonic::async_trait]
+impl Admin for OrderbookAggregatorService {
+    async fn reload_config(
+        &self,
+        _request: Request<Empty>,
+    ) -> Result<Response<ReloadReport>, Status> {
+        let reloader = self
+            .reloader
+            .as_ref()
+            .ok_or(HandlerError::NotEnabled("config reload"))?;
+        let diff = reloader
+            .reload()
+            .await
+            .map_err(HandlerError::FailedPrecondition)?;
+        tracing::info!(
+            "Config reloaded via RPC: applied {:?}, requires restart {:?}",
+            diff.reloadable,
+            diff.requires_restart
+        );
+        Ok(Response::new(ReloadReport {
+            applied: diff.reloadable,
+            requires_restart: diff.requires_restart,
+        }))
+    }
 
-                yield summary;
+    async fn get_parse_failures(
+        &self,
+        request: Request<ParseFailuresRequest>,
+    ) -> Result<Response<ParseFailureList>, Status> {
+        let failures = self
+            .handlers()
+            .parse_failures(&request.into_inner().exchange)
+            .into_iter()
+            .map(orderbook::ParseFailure::from)
+            .collect();
+        Ok(Response::new(ParseFailureList { failures }))
+    }
 
-                // Release the lock and wait
-                // drop(agg);
+    async fn send_connector_command(
+        &self,
+        request: Request<ConnectorCommandRequest>,
+    ) -> Result<Response<Empty>, Status> {
+        let request = request.into_inner();
+        let command = match request.command() {
+            orderbook::ConnectorCommand::Resync => ConnectorCommand::Resync,
+            orderbook::ConnectorCommand::Pause => ConnectorCommand::Pause,
+            orderbook::ConnectorCommand::Resume => ConnectorCommand::Resume,
+            orderbook::ConnectorCommand::ReconnectNow => ConnectorCommand::ReconnectNow,
+            orderbook::ConnectorCommand::Shutdown => ConnectorCommand::Shutdown,
+            orderbook::ConnectorCommand::SetStreamSpeed if request.speed > 0.0 => {
+                ConnectorCommand::SetStreamSpeed(request.speed)
+            }
+            orderbook::ConnectorCommand::SetStreamSpeed => {
+                return Err(HandlerError::InvalidArgument {
+                    field: "speed",
+                    message: "speed must be positive".to_string(),
+                }
+                .into());
+            }
+        };
+        self.handlers()
+            .send_command(&request.exchange, &request.symbol, command)
+            .await?;
+        Ok(Response::new(Empty {}))
+    }
 
-                // Sleep for 1 second
-                // tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+    async fn reset_symbol(
+        &self,
+        request: Request<ResetSymbolRequest>,
+    ) -> Result<Response<ResetSymbolReport>, Status> {
+        let reset = self
+            .handlers()
+            .reset_symbol(&request.into_inner().symbol)
+            .await?;
+        Ok(Response::new(ResetSymbolReport {
+            symbol: reset.symbol,
+            generation: reset.generation,
+            epoch: reset.epoch,
+            resynced: reset.resynced,
+        }))
+    }
+}
+
+#[tonic::async_trait]
+impl Discovery for OrderbookAggregatorService {
+    async fn list_symbols(&self, _request: Request<Empty>) -> Result<Response<SymbolList>, Status> {
+        let config = self.aggregated_orderbook.read().await.config.clone();
+        let symbols = vec![SymbolInfo {
+            symbol: config.symbol,
+            price_scale: config.settings.price_scale,
+            max_depth: config.settings.max_depth.map(|d| d as u64),
+            dust_threshold: config.settings.dust_threshold,
+            outlier_tolerance_bps: config.settings.outlier_tolerance_bps,
+            stale_after_ms: config.settings.stale_after_ms,
+            index_weights: config.settings.index_weights.into_iter().collect(),
+            depth_weights: config.settings.depth_weights.into_iter().collect(),
+            smart_best_min_qty: config.settings.smart_best_min_qty,
+            max_level_distance_bps: config.settings.max_level_distance_bps,
+        }];
+        Ok(Response::new(SymbolList { symbols }))
+    }
+
+    async fn get_status(&self, _request: Request<Empty>) -> Result<Response<StatusReport>, Status> {
+        let exchanges = self
+            .status
+            .exchanges()
+            .into_iter()
+            .map(orderbook::ExchangeStatus::from)
+            .collect();
+        let publisher = &self.status.publisher;
+        let consistency = &self.status.consistency;
+        let retained = self.status.streams.retained_state();
+        let (readiness, stragglers) = match self.status.startup.readiness() {
+            Readiness::Starting => (orderbook::Readiness::Starting, BTreeMap::new()),
+            Readiness::Ready => (orderbook::Readiness::Ready, BTreeMap::new()),
+            // A failed start never serves
+            Readiness::Degraded { failed } | Readiness::Failed { failed } => {
+                (orderbook::Readiness::Degraded, failed)
             }
         };
+        Ok(Response::new(StatusReport {
+            exchanges,
+            readiness: readiness as i32,
+            stragglers: stragglers.into_iter().collect(),
+            publisher: Some(PublisherStats {
+                emitted: publisher.emitted.load(Ordering::Relaxed),
+                skipped: publisher.skipped.load(Ordering::Relaxed),
+                heartbeats: publisher.heartbeats.load(Ordering::Relaxed),
+                inconsistent: consistency.violations.load(Ordering::Relaxed),
+                spread_fixed: consistency.fixed.load(Ordering::Relaxed),
+                inconsistent_skipped: consistency.skipped.load(Ordering::Relaxed),
+            }),
+            connections: self
+                .status
+                .connections
+                .active()
+                .into_iter()
+                .map(|(exchange, symbol)| ActiveConnection { exchange, symbol })
+                .collect(),
+            duplicate_connection_claims: self.status.connections.duplicate_claims(),
+            suppressed_warnings: self
+                .status
+                .log_limiter
+                .suppressed_totals()
+                .into_iter()
+                .collect(),
+            streams: Some(StreamStats {
+                active_streams: self.status.streams.active_streams(),
+                streams_total: self.status.streams.streams_total(),
+                messages_sent_total: self
+                    .status
+                    .streams
+                    .methods()
+                    .into_iter()
+                    .map(|(method, counters)| (method.to_string(), counters.messages_sent))
+                    .collect(),
+                retained_state_bytes: retained.bytes,
+                retained_state_levels: retained.levels,
+                stateful_streams: self
+                    .status
+                    .streams
+                    .stateful_streams()
+                    .into_iter()
+                    .map(|stream| StatefulStream {
+                        method: stream.method.to_string(),
+                        peer: stream.peer,
+                        retained_levels: stream.state.levels,
+                        retained_bytes: stream.state.bytes,
+                    })
+                    .collect(),
+            }),
+            snapshot_syncs: self
+                .status
+                .snapshots
+                .progress()
+                .into_iter()
+                .map(|((exchange, symbol), progress)| SnapshotSync {
+                    exchange,
+                    symbol,
+                    progress: orderbook::SnapshotProgress::from(progress) as i32,
+                })
+                .collect(),
+        }))
+    }
+
+    async fn get_uptime_report(
+        &self,
+        request: Request<TimeRange>,
+    ) -> Result<Response<orderbook::UptimeReport>, Status> {
+        let range = request.into_inner();
+        let report = self.handlers().uptime_report(range.from, range.to).await?;
+        Ok(Response::new(orderbook::UptimeReport::from(report)))
+    }
+
+    async fn get_server_info(
+        &self,
+        _request: Request<Empty>,
+    ) -> Result<Response<orderbook::ServerInfo>, Status> {
+        let info = self.handlers().server_info().await;
+        Ok(Response::new(orderbook::ServerInfo::from(info)))
+    }
+
+    async fn get_time_series(
+        &self,
+        request: Request<TimeSeriesRequest>,
+    ) -> Result<Response<orderbook::TimeSeries>, Status> {
+        let request = request.into_inner();
+        let handlers = self.handlers();
+        let points = if request.name.is_empty() {
+            vec![]
+        } else {
+            handlers.time_series(
+                &request.name,
+                &request.label,
+                request.from,
+                request.to,
+                request.max_points,
+            )?
+        };
+        Ok(Response::new(orderbook::TimeSeries {
+            series: handlers
+                .series_list()
+                .into_iter()
+                .map(orderbook::SeriesInfo::from)
+                .collect(),
+            points: points.into_iter().map(SeriesBucket::from).collect(),
+        }))
+    }
+}
+
+const SUMMARY_DEPTH: usize = 10;
+
+impl From<SeriesInfo> for orderbook::SeriesInfo {
+    fn from(info: SeriesInfo) -> Self {
+        orderbook::SeriesInfo {
+            name: info.key.name,
+            label: info.key.label,
+            points: info.points as u64,
+            first_at: info.first_at,
+            last_at: info.last_at,
+        }
+    }
+}
+
+impl From<Bucket> for SeriesBucket {
+    fn from(bucket: Bucket) -> Self {
+        SeriesBucket {
+            start: bucket.start,
+            end: bucket.end,
+            min: bucket.min,
+            max: bucket.max,
+            avg: bucket.avg,
+            count: bucket.count as u64,
+        }
+    }
+}
+
+impl From<SideShape> for orderbook::SideShape {
+    fn from(shape: SideShape) -> Self {
+        orderbook::SideShape {
+            levels: shape.levels as u64,
+            mean_size: shape.mean_size,
+            median_size: shape.median_size,
+            top10_distance: shape.top10_distance,
+        }
+    }
+}
+
+impl From<ExcludedLevels> for orderbook::ExcludedLevels {
+    fn from(excluded: ExcludedLevels) -> Self {
+        orderbook::ExcludedLevels {
+            bids: excluded.bids as u64,
+            asks: excluded.asks as u64,
+        }
+    }
+}
+
+impl From<BookStats> for orderbook::BookStats {
+    fn from(stats: BookStats) -> Self {
+        let latest = stats.latest;
+        let shape = stats.shape;
+        orderbook::BookStats {
+            version: latest.map_or(0, |s| s.version),
+            at: latest.map_or(0, |s| s.at),
+            spread: latest.and_then(|s| s.spread),
+            index_price: latest.and_then(|s| s.index_price),
+            shape_at: shape.as_ref().map(|s| s.at),
+            bids: shape.as_ref().map(|s| s.shape.bids.clone().into()),
+            asks: shape.as_ref().map(|s| s.shape.asks.clone().into()),
+            shape_excluded_levels: shape.as_ref().map(|s| s.shape.excluded_levels.into()),
+            exchange_share: shape
+                .map(|s| s.shape.exchange_share.into_iter().collect())
+                .unwrap_or_default(),
+            imbalance: stats.imbalance.map(orderbook::GaugeSample::from),
+            imbalance_excluded_levels: stats.imbalance_excluded_levels.map(Into::into),
+            update_rates: stats
+                .update_rates
+                .into_iter()
+                .map(|(exchange, rate)| (exchange, rate.into()))
+                .collect(),
+            slow_apply_total: stats.slow_apply_total.into_iter().collect(),
+            price_improvement: stats
+                .price_improvement
+                .map(orderbook::PriceImprovement::from),
+            traded_estimate: stats
+                .traded_estimate
+                .map(orderbook::TradedVolumeEstimate::from),
+            depth_weights: stats.depth_weights.into_iter().collect(),
+        }
+    }
+}
+
+impl From<TradedVolumeEstimator> for orderbook::TradedVolumeEstimate {
+    fn from(estimator: TradedVolumeEstimator) -> Self {
+        orderbook::TradedVolumeEstimate {
+            since: estimator.since,
+            exchanges: estimator
+                .exchanges
+                .into_iter()
+                .map(|(exchange, estimate)| (exchange, estimate.into()))
+                .collect(),
+        }
+    }
+}
+
+impl From<TradedEstimate> for orderbook::TradedEstimate {
+    fn from(estimate: TradedEstimate) -> Self {
+        orderbook::TradedEstimate {
+            bid_volume: estimate.bid_volume,
+            ask_volume: estimate.ask_volume,
+            events: estimate.events,
+            last_at: estimate.last_at,
+        }
+    }
+}
+
+impl From<ImprovementSample> for orderbook::PriceImprovement {
+    fn from(sample: ImprovementSample) -> Self {
+        let improvement = sample.improvement;
+        orderbook::PriceImprovement {
+            at: sample.at,
+            version: sample.version,
+            clip_size: improvement.clip_size,
+            bids: Some(improvement.bids.into()),
+            asks: Some(improvement.asks.into()),
+            depth_weights: improvement.depth_weights.into_iter().collect(),
+        }
+    }
+}
+
+impl From<SideImprovement> for orderbook::SideImprovement {
+    fn from(side: SideImprovement) -> Self {
+        orderbook::SideImprovement {
+            best_price: side.best_price,
+            best_exchange: side.best_exchange,
+            vwap: side.vwap,
+            venues: side
+                .venues
+                .into_iter()
+                .map(|(exchange, venue)| (exchange, venue.into()))
+                .collect(),
+        }
+    }
+}
+
+impl From<VenueImprovement> for orderbook::VenueImprovement {
+    fn from(venue: VenueImprovement) -> Self {
+        orderbook::VenueImprovement {
+            best_price: venue.best_price,
+            best_delta: venue.best_delta,
+            best_delta_bps: venue.best_delta_bps,
+            vwap: venue.vwap,
+            vwap_delta: venue.vwap_delta,
+            vwap_delta_bps: venue.vwap_delta_bps,
+        }
+    }
+}
+
+impl From<GaugeSample> for orderbook::GaugeSample {
+    fn from(sample: GaugeSample) -> Self {
+        orderbook::GaugeSample {
+            at: sample.at,
+            value: sample.value,
+        }
+    }
+}
+
+impl From<ParseFailure> for orderbook::ParseFailure {
+    fn from(failure: ParseFailure) -> Self {
+        orderbook::ParseFailure {
+            exchange: failure.exchange,
+            at: failure.at,
+            reason: failure.reason,
+            payload: failure.payload,
+            payload_len: failure.payload_len as u64,
+        }
+    }
+}
+
+impl From<DepthCurve> for orderbook::DepthCurve {
+    fn from(curve: DepthCurve) -> Self {
+        let to_points = |points: Vec<(f64, f64, f64)>| {
+            points
+                .into_iter()
+                .map(
+                    |(price, cumulative_amount, cumulative_notional)| DepthPoint {
+                        price,
+                        cumulative_amount,
+                        cumulative_notional,
+                    },
+                )
+                .collect()
+        };
+        let (notional_currency, conversion_rate, conversion_unavailable) = match curve.notional_unit
+        {
+            NotionalUnit::Quote => (None, None, false),
+            NotionalUnit::Converted { currency, rate } => (Some(currency), Some(rate), false),
+            NotionalUnit::Unavailable { .. } => (None, None, true),
+        };
+        orderbook::DepthCurve {
+            bids: to_points(curve.bids),
+            asks: to_points(curve.asks),
+            notional_currency,
+            conversion_rate,
+            conversion_unavailable,
+            depth_weights: curve.depth_weights.into_iter().collect(),
+            excluded_levels: Some(curve.excluded_levels.into()),
+        }
+    }
+}
+
+impl From<ConnectionState> for orderbook::ConnectionState {
+    fn from(state: ConnectionState) -> Self {
+        match state {
+            ConnectionState::Connecting => orderbook::ConnectionState::Connecting,
+            ConnectionState::Subscribing => orderbook::ConnectionState::Subscribing,
+            ConnectionState::Connected => orderbook::ConnectionState::Connected,
+            ConnectionState::Disconnected => orderbook::ConnectionState::Disconnected,
+            ConnectionState::Quarantined => orderbook::ConnectionState::Quarantined,
+            ConnectionState::NotConfigured => orderbook::ConnectionState::NotConfigured,
+            ConnectionState::Degraded => orderbook::ConnectionState::Degraded,
+        }
+    }
+}
+
+impl From<UptimeReport> for orderbook::UptimeReport {
+    fn from(report: UptimeReport) -> Self {
+        Self {
+            from: report.from,
+            to: report.to,
+            exchanges: report
+                .exchanges
+                .into_iter()
+                .map(|(exchange, uptime)| {
+                    let uptime = orderbook::ExchangeUptime {
+                        connected: uptime.connected,
+                        contributing: uptime.contributing,
+                    };
+                    (exchange, uptime)
+                })
+                .collect(),
+            average_spread: report.average_spread,
+        }
+    }
+}
+
+impl From<ServerInfo> for orderbook::ServerInfo {
+    fn from(info: ServerInfo) -> Self {
+        Self {
+            build_id: info.build.build_id(),
+            version: info.build.version.to_string(),
+            git_hash: info.build.git_hash.to_string(),
+            built_at: info.build.built_at,
+            features: info.build.features.iter().map(|f| f.to_string()).collect(),
+            rustc_version: info.build.rustc_version.to_string(),
+            started_at: info.started_at,
+            uptime_ms: info.uptime_ms,
+            exchanges: info.exchanges,
+            symbols: info.symbols,
+        }
+    }
+}
+
+impl From<SyncProgress> for orderbook::SnapshotProgress {
+    fn from(progress: SyncProgress) -> Self {
+        match progress {
+            SyncProgress::Queued => orderbook::SnapshotProgress::Queued,
+            SyncProgress::Fetching => orderbook::SnapshotProgress::Fetching,
+            SyncProgress::Synced => orderbook::SnapshotProgress::Synced,
+            SyncProgress::Failed => orderbook::SnapshotProgress::Failed,
+        }
+    }
+}
+
+impl From<CircuitState> for orderbook::CircuitState {
+    fn from(state: CircuitState) -> Self {
+        match state {
+            CircuitState::Closed => orderbook::CircuitState::Closed,
+            CircuitState::Open => orderbook::CircuitState::Open,
+            CircuitState::HalfOpen => orderbook::CircuitState::HalfOpen,
+        }
+    }
+}
+
+impl From<ExchangeStatus> for orderbook::ExchangeStatus {
+    fn from(status: ExchangeStatus) -> Self {
+        orderbook::ExchangeStatus {
+            exchange: status.exchange,
+            connection: orderbook::ConnectionState::from(status.connection) as i32,
+            circuit: orderbook::CircuitState::from(status.breaker.state) as i32,
+            consecutive_failures: status.breaker.consecutive_failures,
+            total_failures: status.breaker.total_failures,
+            times_opened: status.breaker.times_opened,
+            open_until: status.breaker.open_until,
+            quarantined_until: status.quarantined_until,
+            times_quarantined: status.times_quarantined,
+            update_rate: Some(status.rate.into()),
+            payload_violations: Some(status.payload.into()),
+            slow_apply_total: status.slow_applies,
+            cross_checks: Some(status.cross_checks.into()),
+            dropped_as_old: status.dropped_as_old,
+            queue_overflows: status.queue_overflows,
+            authoritative_depth: status.authoritative_depth.map(|depth| depth as u64),
+        }
+    }
+}
+
+impl From<CrossCheckCounts> for orderbook::CrossChecks {
+    fn from(counts: CrossCheckCounts) -> Self {
+        orderbook::CrossChecks {
+            checks: counts.checks,
+            divergences: counts.divergences,
+            resyncs: counts.resyncs,
+        }
+    }
+}
+
+impl From<PayloadViolations> for orderbook::PayloadViolations {
+    fn from(violations: PayloadViolations) -> Self {
+        orderbook::PayloadViolations {
+            levels_dropped: violations.levels_dropped,
+            oversized_frames: violations.oversized_frames,
+            oversized_snapshots: violations.oversized_snapshots,
+        }
+    }
+}
+
+impl From<RateStats> for orderbook::UpdateRate {
+    fn from(rate: RateStats) -> Self {
+        orderbook::UpdateRate {
+            last_second: rate.last_second,
+            smoothed: rate.smoothed,
+            peak_last_minute: rate.peak_last_minute,
+            bursting: rate.bursting,
+        }
+    }
+}
+
+impl From<DataQuality> for orderbook::DataQuality {
+    fn from(quality: DataQuality) -> Self {
+        match quality {
+            DataQuality::Full => orderbook::DataQuality::Full,
+            DataQuality::Partial => orderbook::DataQuality::Partial,
+            DataQuality::Stale => orderbook::DataQuality::Stale,
+        }
+    }
+}
+
+impl From<BookState> for orderbook::BookState {
+    fn from(state: BookState) -> Self {
+        match state {
+            BookState::Normal => orderbook::BookState::Normal,
+            BookState::Crossed => orderbook::BookState::Crossed,
+            BookState::Degraded => orderbook::BookState::Degraded,
+        }
+    }
+}
+
+impl From<EmissionReason> for orderbook::EmissionReason {
+    fn from(reason: EmissionReason) -> Self {
+        use orderbook::EmissionKind;
+        let kind = match &reason {
+            EmissionReason::BookChange { .. } => EmissionKind::BookChange,
+            EmissionReason::Heartbeat => EmissionKind::Heartbeat,
+            EmissionReason::InitialSnapshot => EmissionKind::InitialSnapshot,
+            EmissionReason::Resync => EmissionKind::Resync,
+            EmissionReason::ConfigChange => EmissionKind::ConfigChange,
+            EmissionReason::Reset => EmissionKind::Reset,
+        };
+        let (exchange, update_id) = match reason {
+            EmissionReason::BookChange {
+                exchange,
+                update_id,
+            } => (Some(exchange), update_id),
+            _ => (None, None),
+        };
+        orderbook::EmissionReason {
+            kind: kind as i32,
+            exchange,
+            update_id,
+        }
+    }
+}
+
+impl From<Top10Snapshot> for Summary {
+    fn from(snap: Top10Snapshot) -> Self {
+        let to_level =
+            |(level, raw_price): (crate::modules::types::OrderLevel, Option<f64>)| Level {
+                exchange: level.exchange.to_string(),
+                price: level.price,
+                amount: level.amount,
+                detail: None,
+                raw_price,
+            };
+        // Raw prices are only there once the ladder was snapped to the tick or rounded for display
+        let with_raw = |levels: Vec<crate::modules::types::OrderLevel>, raw: Vec<f64>| {
+            let raw = raw.into_iter().map(Some).chain(std::iter::repeat(None));
+            levels.into_iter().zip(raw).map(to_level).collect()
+        };
+        let counts = |counts: BTreeMap<String, usize>| {
+            counts
+                .into_iter()
+                .map(|(exchange, count)| (exchange, count as u64))
+                .collect()
+        };
+        Summary {
+            spread: snap.spread,
+            signed_spread: snap.signed_spread,
+            crossed: snap.crossed,
+            bids: with_raw(snap.bids, snap.raw_bid_prices),
+            asks: with_raw(snap.asks, snap.raw_ask_prices),
+            generated_at: snap.generated_at,
+            symbol: snap.symbol,
+            version: snap.version,
+            generation: snap.generation,
+            epoch: snap.epoch,
+            last_update_ids: snap.last_update_ids.into_iter().collect(),
+            state: orderbook::BookState::from(snap.state) as i32,
+            data_quality: orderbook::DataQuality::from(snap.data_quality) as i32,
+            lagging_exchanges: snap.lagging_exchanges,
+            exchanges: snap.exchanges,
+            index_price: snap.index_price,
+            is_initial_snapshot: snap.is_initial_snapshot,
+            total_bid_levels: snap.total_bid_levels as u64,
+            total_ask_levels: snap.total_ask_levels as u64,
+            bid_levels_by_exchange: counts(snap.bid_levels_by_exchange),
+            ask_levels_by_exchange: counts(snap.ask_levels_by_exchange),
+            bids_present: snap.total_bid_levels > 0,
+            asks_present: snap.total_ask_levels > 0,
+            smart_best_bid: snap.smart_best_bid,
+            smart_best_ask: snap.smart_best_ask,
+            inverted: snap.inverted,
+            reason: Some(snap.reason.into()),
+            build_id: None,
+            checksum: snap.checksum,
+            depth_weights: match snap.cumulative {
+                Some(CumulativeScope::Consolidated) => snap.depth_weights.into_iter().collect(),
+                _ => Default::default(),
+            },
+            amount_kind: match snap.cumulative {
+                None => orderbook::AmountKind::PerLevel,
+                Some(CumulativeScope::Consolidated) => orderbook::AmountKind::Cumulative,
+                Some(CumulativeScope::PerExchange) => orderbook::AmountKind::CumulativePerExchange,
+            } as i32,
+        }
+    }
+}
 
-        Ok(Response::new(Box::pin(stream)))
+impl From<LevelDetail> for orderbook::LevelDetail {
+    fn from(detail: LevelDetail) -> Self {
+        orderbook::LevelDetail {
+            contributor_count: detail.contributor_count,
+            newest_contribution_age_ms: detail.newest_age_ms,
+            origin_update_id: detail.origin.map(|origin| origin.update_id),
+            origin_event_time: detail.origin.and_then(|origin| origin.event_time),
+        }
+    }
+}
+
+impl From<MultiSummary> for orderbook::MultiSummary {
+    fn from(multi: MultiSummary) -> Self {
+        orderbook::MultiSummary {
+            generated_at: multi.generated_at,
+            summaries: multi.summaries.into_iter().map(Summary::from).collect(),
+            changed_symbol: multi.changed,
+        }
     }
 }
 
-pub fn create_grpc_server(
-    aggregated_orderbook: Arc<RwLock<AggregatedOrderBook>>,
-) -> OrderbookAggregatorServer<OrderbookAggregatorService> {
-    let service = OrderbookAggregatorService::new(aggregated_orderbook);
-    OrderbookAggregatorServer::new(service)
+/// Summary with every level's `detail` filled in
+pub fn summary_with_details(mut snap: Top10Snapshot) -> Summary {
+    let bid_details = std::mem::take(&mut snap.bid_details);
+    let ask_details = std::mem::take(&mut snap.ask_details);
+    let mut summary = Summary::from(snap);
+    for (levels, details) in [
+        (&mut summary.bids, bid_details),
+        (&mut summary.asks, ask_details),
+    ] {
+        for (level, detail) in levels.iter_mut().zip(details) {
+            level.detail = Some(detail.into());
+        }
+    }
+    summary
+}
+
+/// MarketData and Discovery, plus Admin when `with_admin` is set. Admin can instead be
+/// served on its own listener with [`create_admin_server`].
+/// Pass `stream` through, counting what is sent and how it ends on `guard`
+fn logged<T: Send + 'static>(
+    stream: impl futures::Stream<Item = Result<T, Status>> + Send + 'static,
+    mut guard: StreamGuard,
+) -> impl futures::Stream<Item = Result<T, Status>> + Send + 'static {
+    async_stream::stream! {
+        futures::pin_mut!(stream);
+        while let Some(item) = stream.next().await {
+            match &item {
+                Ok(_) => guard.record_sent(),
+                Err(status) => guard.end(StreamEnd::Error(status.message().to_string())),
+            }
+            yield item;
+        }
+        guard.end(StreamEnd::ServerShutdown);
+    }
+}
+
+pub fn create_grpc_server(service: OrderbookAggregatorService, with_admin: bool) -> Router {
+    create_grpc_server_with_auth(service, with_admin, None)
+}
+
+/// Like [`create_grpc_server`], with every call needing `authorization: Bearer <token>`
+/// when `auth_token` is set
+pub fn create_grpc_server_with_auth(
+    service: OrderbookAggregatorService,
+    with_admin: bool,
+    auth_token: Option<String>,
+) -> Router {
+    let auth = BearerAuth::new(auth_token);
+    let admin = with_admin.then(|| AdminServer::with_interceptor(service.clone(), auth.clone()));
+    Server::builder()
+        .add_service(MarketDataServer::with_interceptor(
+            service.clone(),
+            auth.clone(),
+        ))
+        .add_service(DiscoveryServer::with_interceptor(service, auth))
+        .add_optional_service(admin)
+}
+
+pub fn create_admin_server(service: OrderbookAggregatorService) -> Router {
+    Server::builder().add_service(AdminServer::new(service))
 }
@@ -0,0 +1,572 @@
+use crate::modules::aggregated_orderbook::{DepthCurve, DepthUnit, EmissionReason, Top10Snapshot};
+use crate::modules::build_info::{BuildInfo, ServerInfo};
+use crate::modules::clock::SharedClock;
+use crate::modules::commands::ConnectorCommand;
+use crate::modules::consistency::SummaryConsistency;
+use crate::modules::dedup::{DedupConfig, Emission, SummaryDedup};
+use crate::modules::multi_summary::{self, MultiSummary};
+use crate::modules::one_sided::OneSidedSummaries;
+use crate::modules::parse_failures::ParseFailure;
+use crate::modules::quote::{NotionalUnit, QuoteConverter};
+use crate::modules::stats::{BookStats, ImprovementSample};
+use crate::modules::status::{ConnectionState, SharedStatus};
+use crate::modules::throttle::GaugeSample;
+use crate::modules::timeseries::{Bucket, SeriesInfo, SeriesKey};
+use crate::modules::traded_estimate::TradedVolumeEstimator;
+use crate::modules::types::AggregatedOrderBook;
+use crate::modules::uptime::{DEFAULT_UPTIME_WINDOW, UptimeReport};
+use async_stream::stream;
+use futures::Stream;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use tokio::sync::{RwLock, watch};
+
+pub const MAX_DEPTH_CURVE_POINTS: u32 = 10_000;
+pub const MAX_TIME_SERIES_POINTS: u32 = 10_000;
+
+/// Why a request was refused, so each transport can map it to its own error codes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HandlerError {
+    InvalidArgument {
+        field: &'static str,
+        message: String,
+    },
+    /// A depth or point count outside its allowed range
+    DepthOutOfRange {
+        field: &'static str,
+        message: String,
+    },
+    UnknownSymbol(String),
+    /// No connector is running for the exchange
+    ExchangeDisabled {
+        exchange: String,
+        message: String,
+    },
+    /// No exchange contributes to the book yet; the connection state of each one
+    NotSynced(BTreeMap<String, ConnectionState>),
+    /// The feature behind the request is turned off
+    NotEnabled(&'static str),
+    FailedPrecondition(String),
+}
+
+impl HandlerError {
+    /// Machine-readable reason code
+    pub fn reason(&self) -> &'static str {
+        match self {
+            HandlerError::InvalidArgument { .. } => "INVALID_ARGUMENT",
+            HandlerError::DepthOutOfRange { .. } => "DEPTH_OUT_OF_RANGE",
+            HandlerError::UnknownSymbol(_) => "UNKNOWN_SYMBOL",
+            HandlerError::ExchangeDisabled { .. } => "EXCHANGE_DISABLED",
+            HandlerError::NotSynced(_) => "NOT_SYNCED",
+            HandlerError::NotEnabled(_) => "NOT_ENABLED",
+            HandlerError::FailedPrecondition(_) => "FAILED_PRECONDITION",
+        }
+    }
+
+    /// The request field at fault, if any
+    pub fn field(&self) -> Option<&'static str> {
+        match self {
+            HandlerError::InvalidArgument { field, .. }
+            | HandlerError::DepthOutOfRange { field, .. } => Some(field),
+            HandlerError::UnknownSymbol(_) => Some("symbol"),
+            HandlerError::ExchangeDisabled { .. } => Some("exchange"),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for HandlerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HandlerError::InvalidArgument { message, .. }
+            | HandlerError::DepthOutOfRange { message, .. }
+            | HandlerError::ExchangeDisabled { message, .. }
+            | HandlerError::FailedPrecondition(message) => f.write_str(message),
+            HandlerError::UnknownSymbol(symbol) => write!(f, "symbol {} is not served", symbol),
+            HandlerError::NotSynced(_) => f.write_str("no exchange is synced yet"),
+            HandlerError::NotEnabled(feature) => write!(f, "{} is not enabled", feature),
+        }
+    }
+}
+
+/// Outcome of `Handlers::reset_symbol`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SymbolReset {
+    pub symbol: String,
+    pub generation: u64,
+    pub epoch: u64,
+    /// Exchanges whose connector was told to resync
+    pub resynced: Vec<String>,
+}
+
+/// Request handling shared by the gRPC service and the stdio mode
+#[derive(Clone)]
+pub struct Handlers {
+    pub book: Arc<RwLock<AggregatedOrderBook>>,
+    pub status: SharedStatus,
+    pub dedup: Option<DedupConfig>,
+    pub converter: Option<Arc<dyn QuoteConverter>>,
+    pub one_sided: OneSidedSummaries,
+    pub consistency: SummaryConsistency,
+}
+
+impl Handlers {
+    pub fn new(book: Arc<RwLock<AggregatedOrderBook>>, status: SharedStatus) -> Self {
+        Self {
+            book,
+            status,
+            dedup: None,
+            converter: None,
+            one_sided: OneSidedSummaries::default(),
+            consistency: SummaryConsistency::default(),
+        }
+    }
+
+    pub fn with_dedup(mut self, dedup: Option<DedupConfig>) -> Self {
+        self.dedup = dedup;
+        self
+    }
+
+    pub fn with_converter(mut self, converter: Option<Arc<dyn QuoteConverter>>) -> Self {
+        self.converter = converter;
+        self
+    }
+
+    /// Whether subscriptions get Summaries while a side of the book is empty
+    pub fn with_one_sided(mut self, one_sided: OneSidedSummaries) -> Self {
+        self.one_sided = one_sided;
+        self
+    }
+
+    /// What subscriptions do with a snapshot that fails the consistency check
+    pub fn with_consistency(mut self, consistency: SummaryConsistency) -> Self {
+        self.consistency = consistency;
+        self
+    }
+
+    /// Latest published snapshot, cut to `depth` price levels or entries per side if given
+    pub async fn summary(&self, depth: Option<usize>, unit: DepthUnit) -> Top10Snapshot {
+        let snap = self.book.read().await.published_snapshot();
+        truncate(Top10Snapshot::clone(&snap), depth, unit)
+    }
+
+    /// Spread of the published book; `None` while either side is empty
+    pub async fn spread(&self) -> Option<f64> {
+        self.book.read().await.published_snapshot().spread
+    }
+
+    /// Every published snapshot from now on, deduplicated if configured. Readers only ever
+    /// see snapshots published between whole updates. The first one, and the first one of
+    /// each new generation after a reset, is always sent, at the full requested depth, and
+    /// marked `is_initial_snapshot`. Each one's `reason` is the book's, except for those
+    /// and dedup heartbeats, which say so. With `display_decimals` prices are rounded before
+    /// dedup, so moves below the precision are skipped. With `invert` the book is shown
+    /// quote/base flipped, before any rounding.
+    pub async fn subscribe(
+        &self,
+        depth: Option<usize>,
+        unit: DepthUnit,
+        display_decimals: Option<u32>,
+        invert: bool,
+    ) -> impl Stream<Item = Top10Snapshot> + Send + 'static {
+        let status = Arc::clone(&self.status);
+        let (mut published, clock) = {
+            let book = self.book.read().await;
+            (book.subscribe(), book.clock.clone())
+        };
+        let mut dedup = self.dedup.map(|config| SummaryDedup::new(clock, config));
+        let one_sided = self.one_sided;
+        let consistency = self.consistency;
+        status
+            .counters
+            .streams_served
+            .fetch_add(1, Ordering::Relaxed);
+
+        stream! {
+            let mut generation = None;
+            loop {
+                let mut snap = published.borrow_and_update().clone();
+                if invert {
+                    snap = Arc::new(Top10Snapshot::clone(&snap).into_inverted());
+                }
+                if let Some(decimals) = display_decimals {
+                    snap = Arc::new(Top10Snapshot::clone(&snap).into_display_rounded(decimals));
+                }
+                if one_sided.holds(&snap) {
+                    match dedup.as_ref() {
+                        Some(dedup) => tokio::time::sleep(dedup.poll_interval()).await,
+                        None if published.changed().await.is_err() => break,
+                        None => {}
+                    }
+                    continue;
+                }
+                let initial = generation != Some(snap.generation);
+
+                let emission = dedup
+                    .as_mut()
+                    .map(|dedup| dedup.check(&snap, &status.publisher));
+                if let Some(dedup) = dedup.as_ref()
+                    && emission == Some(Emission::Skip)
+                    && !initial
+                {
+                    tokio::time::sleep(dedup.poll_interval()).await;
+                    continue;
+                }
+
+                let mut snap = truncate(Top10Snapshot::clone(&snap), depth, unit);
+                snap.is_initial_snapshot = initial;
+                if initial {
+                    snap.reason = EmissionReason::InitialSnapshot;
+                } else if emission == Some(Emission::Heartbeat) {
+                    snap.reason = EmissionReason::Heartbeat;
+                }
+                let vetted = consistency.vet(snap, &status.consistency, || {
+                    status.log_limiter.allow("inconsistent summary")
+                });
+                if let Some(snap) = vetted {
+                    generation = Some(snap.generation);
+                    yield snap;
+                }
+
+                if dedup.is_none() && published.changed().await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// The latest snapshot of each of `symbols` (empty for every served one), read together
+    /// and stamped with one time
+    pub async fn multi_summary(
+        &self,
+        symbols: &[String],
+        depth: Option<usize>,
+        unit: DepthUnit,
+    ) -> Result<MultiSummary, HandlerError> {
+        let (mut books, clock) = self.symbol_feeds(symbols).await?;
+        Ok(multi_summary::capture(
+            &mut books, &clock, depth, unit, None,
+        ))
+    }
+
+    /// Like `multi_summary`, then again whenever any of the symbols changes, naming it
+    pub async fn subscribe_multi(
+        &self,
+        symbols: &[String],
+        depth: Option<usize>,
+        unit: DepthUnit,
+    ) -> Result<impl Stream<Item = MultiSummary> + Send + 'static, HandlerError> {
+        let (books, clock) = self.symbol_feeds(symbols).await?;
+        self.status
+            .counters
+            .streams_served
+            .fetch_add(1, Ordering::Relaxed);
+        Ok(multi_summary::subscribe(books, clock, depth, unit))
+    }
+
+    /// Published snapshots of the books behind `symbols`, each served symbol once
+    async fn symbol_feeds(
+        &self,
+        symbols: &[String],
+    ) -> Result<(Vec<watch::Receiver<Arc<Top10Snapshot>>>, SharedClock), HandlerError> {
+        let book = self.book.read().await;
+        for symbol in symbols {
+            check_symbol(&book, symbol)?;
+        }
+        // Only one book is served, so every symbol asked for is that one
+        Ok((vec![book.subscribe()], book.clock.clone()))
+    }
+
+    /// Every published notional imbalance from now on, starting with the latest one, or
+    /// `None` if the gauge is disabled
+    pub async fn subscribe_imbalance(
+        &self,
+    ) -> Option<impl Stream<Item = GaugeSample> + Send + 'static> {
+        let mut published = self.book.read().await.imbalance.as_ref()?.gauge.subscribe();
+        Some(stream! {
+            loop {
+                let sample = *published.borrow_and_update();
+                if let Some(sample) = sample {
+                    yield sample;
+                }
+                if published.changed().await.is_err() {
+                    break;
+                }
+            }
+        })
+    }
+
+    pub async fn book_stats(&self) -> BookStats {
+        let mut stats = self.book.read().await.stats();
+        stats.update_rates = self.status.rates.all();
+        stats.slow_apply_total = self.status.latency.all_slow_totals();
+        stats
+    }
+
+    /// Cumulative traded-through volume estimates by exchange, or `None` if the estimator
+    /// is disabled
+    pub async fn traded_volume_estimate(&self) -> Option<TradedVolumeEstimator> {
+        self.book.read().await.traded_estimate.clone()
+    }
+
+    /// Uptime and time-weighted spread over `[from, to]` in unix millis, by default the
+    /// last 24h
+    /// Build and runtime details of this server
+    pub async fn server_info(&self) -> ServerInfo {
+        let book = self.book.read().await;
+        let started_at = self.status.uptime.started_at();
+        let exchanges = self
+            .status
+            .exchanges()
+            .into_iter()
+            .filter(|status| status.connection != ConnectionState::NotConfigured)
+            .map(|status| status.exchange)
+            .collect();
+        ServerInfo {
+            build: BuildInfo::current(),
+            started_at,
+            uptime_ms: book.clock.now_millis().saturating_sub(started_at),
+            exchanges,
+            symbols: vec![book.config.symbol.clone()],
+        }
+    }
+
+    pub async fn uptime_report(
+        &self,
+        from: Option<u64>,
+        to: Option<u64>,
+    ) -> Result<UptimeReport, HandlerError> {
+        let book = self.book.read().await;
+        let to = to.unwrap_or_else(|| book.clock.now_millis());
+        let from =
+            from.unwrap_or_else(|| to.saturating_sub(DEFAULT_UPTIME_WINDOW.as_millis() as u64));
+        if from >= to {
+            return Err(HandlerError::InvalidArgument {
+                field: "from",
+                message: "from must be before to".to_string(),
+            });
+        }
+        let spreads: Vec<(u64, f64)> = book
+            .history
+            .samples()
+            .filter_map(|s| Some((s.at, book.spread_convention.report(s.spread?))))
+            .collect();
+        drop(book);
+        Ok(self.status.uptime.report(from, to, &spreads))
+    }
+
+    /// `symbol` may be empty for the served symbol. With `convert_notional`, notionals are
+    /// converted by the configured converter, or flagged as unconverted if there is none or
+    /// it has no fresh rate.
+    pub async fn depth_curve(
+        &self,
+        symbol: &str,
+        points: u32,
+        range_bps: f64,
+        convert_notional: bool,
+        invert: bool,
+    ) -> Result<DepthCurve, HandlerError> {
+        if points == 0 || points > MAX_DEPTH_CURVE_POINTS {
+            return Err(HandlerError::DepthOutOfRange {
+                field: "points",
+                message: format!("points must be between 1 and {}", MAX_DEPTH_CURVE_POINTS),
+            });
+        }
+        if !(range_bps.is_finite() && range_bps > 0.0) {
+            return Err(HandlerError::InvalidArgument {
+                field: "range_bps",
+                message: "range_bps must be positive".to_string(),
+            });
+        }
+        if invert && convert_notional {
+            return Err(HandlerError::InvalidArgument {
+                field: "invert",
+                message:
+                    "an inverted curve's notionals are in the base currency and can't be converted"
+                        .to_string(),
+            });
+        }
+        let book = self.book.read().await;
+        check_symbol(&book, symbol)?;
+        self.check_synced(&book)?;
+        let mut curve = book.depth_curve(points as usize, range_bps);
+        drop(book);
+        if convert_notional {
+            match &self.converter {
+                Some(converter) => curve.convert_notional(converter.as_ref()),
+                None => {
+                    curve.notional_unit = NotionalUnit::Unavailable {
+                        currency: String::new(),
+                    }
+                }
+            }
+        }
+        Ok(if invert { curve.into_inverted() } else { curve })
+    }
+
+    /// Price improvement of the aggregated book over each venue for `clip_size` of `symbol`
+    /// (empty for the served one), as of now
+    pub async fn price_improvement(
+        &self,
+        symbol: &str,
+        clip_size: f64,
+    ) -> Result<ImprovementSample, HandlerError> {
+        if !(clip_size.is_finite() && clip_size > 0.0) {
+            return Err(HandlerError::InvalidArgument {
+                field: "clip_size",
+                message: "clip_size must be positive".to_string(),
+            });
+        }
+        let book = self.book.read().await;
+        check_symbol(&book, symbol)?;
+        self.check_synced(&book)?;
+        Ok(ImprovementSample {
+            at: book.clock.now_millis(),
+            version: book.version,
+            improvement: book.price_improvement(clip_size),
+        })
+    }
+
+    /// Clear the book of `symbol` (empty for the served one) and resync every connector
+    /// feeding it. Streams start the new generation with an `is_initial_snapshot` message.
+    pub async fn reset_symbol(&self, symbol: &str) -> Result<SymbolReset, HandlerError> {
+        let mut book = self.book.write().await;
+        check_symbol(&book, symbol)?;
+        let contributing: Vec<String> = book.last_update_id.keys().cloned().collect();
+        book.clear();
+        let mut reset = SymbolReset {
+            symbol: book.config.symbol.clone(),
+            generation: book.generation,
+            epoch: book.epoch,
+            resynced: vec![],
+        };
+        drop(book);
+        for exchange in contributing {
+            self.status.set_contributing(&exchange, false);
+        }
+
+        for (exchange, feed_symbol) in self.status.commands.feeds() {
+            if feed_symbol != reset.symbol {
+                continue;
+            }
+            match self
+                .status
+                .commands
+                .send(&exchange, &feed_symbol, ConnectorCommand::Resync)
+            {
+                Ok(()) => reset.resynced.push(exchange),
+                Err(e) => tracing::warn!(exchange, "Reset could not resync: {}", e),
+            }
+        }
+        tracing::warn!(
+            symbol = reset.symbol,
+            generation = reset.generation,
+            epoch = reset.epoch,
+            resynced = ?reset.resynced,
+            "Book reset"
+        );
+        Ok(reset)
+    }
+
+    /// Queue `command` for the connector of `exchange` and `symbol` (empty for the served one)
+    pub async fn send_command(
+        &self,
+        exchange: &str,
+        symbol: &str,
+        command: ConnectorCommand,
+    ) -> Result<(), HandlerError> {
+        let exchange = exchange.to_lowercase();
+        let symbol = if symbol.is_empty() {
+            self.book.read().await.config.symbol.clone()
+        } else {
+            symbol.to_lowercase()
+        };
+        let commands = &self.status.commands;
+        commands
+            .send(&exchange, &symbol, command)
+            .map_err(|message| {
+                let running = commands.feeds().contains(&(exchange.clone(), symbol));
+                if running {
+                    HandlerError::FailedPrecondition(message)
+                } else {
+                    HandlerError::ExchangeDisabled { exchange, message }
+                }
+            })
+    }
+
+    /// Refuse with each exchange's connection state while none contributes to the book
+    fn check_synced(&self, book: &AggregatedOrderBook) -> Result<(), HandlerError> {
+        if !book.last_update_id.is_empty() {
+            return Ok(());
+        }
+        let states = self.status.exchanges().into_iter();
+        Err(HandlerError::NotSynced(
+            states.map(|s| (s.exchange, s.connection)).collect(),
+        ))
+    }
+
+    /// Every recorded time series
+    pub fn series_list(&self) -> Vec<SeriesInfo> {
+        self.status.timeseries.list()
+    }
+
+    /// Points of the series `name` for `label` within `[from, to]` in unix millis,
+    /// downsampled to at most `max_points` buckets (0 for every point)
+    pub fn time_series(
+        &self,
+        name: &str,
+        label: &str,
+        from: Option<u64>,
+        to: Option<u64>,
+        max_points: u32,
+    ) -> Result<Vec<Bucket>, HandlerError> {
+        if max_points > MAX_TIME_SERIES_POINTS {
+            return Err(HandlerError::DepthOutOfRange {
+                field: "max_points",
+                message: format!("max_points must be at most {}", MAX_TIME_SERIES_POINTS),
+            });
+        }
+        if let (Some(from), Some(to)) = (from, to)
+            && from > to
+        {
+            return Err(HandlerError::InvalidArgument {
+                field: "from",
+                message: "from must not be after to".to_string(),
+            });
+        }
+        let key = SeriesKey {
+            name: name.to_string(),
+            label: label.to_lowercase(),
+        };
+        self.status
+            .timeseries
+            .query(&key, from, to, max_points as usize)
+            .ok_or_else(|| HandlerError::InvalidArgument {
+                field: "name",
+                message: format!("no series {} labelled '{}'", key.name, key.label),
+            })
+    }
+
+    /// Samples for one exchange, or all of them when `exchange` is empty
+    pub fn parse_failures(&self, exchange: &str) -> Vec<ParseFailure> {
+        let exchange = exchange.to_lowercase();
+        let exchange = (!exchange.is_empty()).then_some(exchange.as_str());
+        self.status.parse_failures.samples(exchange)
+    }
+}
+
+/// `symbol` may be empty for the served symbol
+fn check_symbol(book: &AggregatedOrderBook, symbol: &str) -> Result<(), HandlerError> {
+    if !symbol.is_empty() && symbol.to_lowercase() != book.config.symbol {
+        return Err(HandlerError::UnknownSymbol(symbol.to_string()));
+    }
+    Ok(())
+}
+
+fn truncate(mut snap: Top10Snapshot, depth: Option<usize>, unit: DepthUnit) -> Top10Snapshot {
+    if let Some(depth) = depth {
+        snap.truncate(depth, unit);
+    }
+    snap
+}
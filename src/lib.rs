@@ -1 +1,12 @@
+#[cfg(feature = "grpc")]
+pub mod client;
+pub mod config;
+#[cfg(feature = "grpc")]
+pub mod grpc_error;
+#[cfg(feature = "grpc")]
+pub mod grpc_listeners;
+#[cfg(feature = "grpc")]
+pub mod grpc_service;
+pub mod handlers;
 pub mod modules;
+pub mod stdio_service;
@@ -1,204 +1,1810 @@
+use std::collections::BTreeMap;
+use std::os::unix::fs::FileTypeExt;
+use std::process::ExitCode;
 use std::sync::Arc;
-use std::time::Instant;
+use std::task::Poll;
+use std::time::{Duration, Instant};
 
-use clap::Parser;
-use futures_util::StreamExt;
-use futures_util::stream::select;
-use tokio::sync::RwLock;
-use tokio_tungstenite::tungstenite::Message;
-use tonic::transport::Server;
+use clap::{CommandFactory, Parser};
+use futures_util::future::join_all;
+use futures_util::{StreamExt, stream};
+use tokio::io::BufReader;
+use tokio::net::UnixListener;
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::sync::{RwLock, mpsc};
+use tokio::task::JoinSet;
+use tokio::time::MissedTickBehavior;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use tonic::transport::server::TcpIncoming;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::reload;
 
-use crate::grpc_service::create_grpc_server;
-use crate::modules::types::{AggregatedOrderBook, Exchange, OrderBookUpdate};
+use keyrock_mm_rust_task::config::{AppConfig, ConfigReloader, EnvLayer, ListenAddr};
+use keyrock_mm_rust_task::grpc_listeners;
+use keyrock_mm_rust_task::grpc_service::{
+    OrderbookAggregatorService, create_admin_server, create_grpc_server_with_auth,
+};
+use keyrock_mm_rust_task::handlers::Handlers;
+use keyrock_mm_rust_task::modules::aggregated_orderbook::{
+    BoundaryPolicy, SkipReason, TombstoneConfig,
+};
+use keyrock_mm_rust_task::modules::alarms::{
+    ALARM_FLUSH_TIMEOUT, AlarmDispatcher, AlarmEvent, Alarms, BookAlarmWatch,
+};
+use keyrock_mm_rust_task::modules::bitstamp::parse_bitstamp_full_book;
+use keyrock_mm_rust_task::modules::build_info::BuildInfo;
+use keyrock_mm_rust_task::modules::circuit_breaker::{
+    BreakerStats, CircuitBreaker, CircuitBreakerConfig, CircuitState,
+};
+use keyrock_mm_rust_task::modules::clock::system_clock;
+use keyrock_mm_rust_task::modules::commands::{CommandAction, ConnectorCommand, FeedControl};
+use keyrock_mm_rust_task::modules::connectors::{self, WsSink, WsStream};
+use keyrock_mm_rust_task::modules::cross_check::{CrossCheck, CrossCheckConfig, CrossChecker};
+use keyrock_mm_rust_task::modules::dedup::DedupConfig;
+use keyrock_mm_rust_task::modules::exchange_connector::{
+    BitfinexConnector, BitstampConnector, BybitConnector, ExchangeConnector, KucoinConnector,
+    OkxConnector, Screen, Verdict,
+};
+use keyrock_mm_rust_task::modules::handshake::{
+    self, Confirmation, Confirmed, DEFAULT_HANDSHAKE_TIMEOUT,
+};
+use keyrock_mm_rust_task::modules::latency::{
+    self, ApplyTiming, BudgetEvent, LatencyBudget, LatencyMonitor,
+};
+use keyrock_mm_rust_task::modules::limits::PayloadLimits;
+use keyrock_mm_rust_task::modules::log_limiter::LogLimiter;
+use keyrock_mm_rust_task::modules::quarantine::{Admission, Quarantine, QuarantineConfig};
+use keyrock_mm_rust_task::modules::quote::ReferenceMid;
+use keyrock_mm_rust_task::modules::rate::{self, RateConfig, RateTracker};
+use keyrock_mm_rust_task::modules::router::RoutedMessage;
+use keyrock_mm_rust_task::modules::shutdown::{ExitReason, ShutdownReport};
+use keyrock_mm_rust_task::modules::snapshot_fetch::FetchPriority;
+use keyrock_mm_rust_task::modules::startup::{Readiness, StragglerRetry};
+use keyrock_mm_rust_task::modules::status::{ConnectionState, SharedStatus, StatusRegistry};
+use keyrock_mm_rust_task::modules::stuck_snapshot::StuckSnapshotConfig;
+use keyrock_mm_rust_task::modules::sync_state::SyncTracker;
+use keyrock_mm_rust_task::modules::throttle::{GAUGE_PUBLISH_INTERVAL, ThrottleConfig};
+use keyrock_mm_rust_task::modules::timeseries::{
+    DEFAULT_SERIES_CAPACITY, Retention, TimeSeriesStore,
+};
+use keyrock_mm_rust_task::modules::types::{
+    AggregatedOrderBook, Exchange, OrderBook, OrderBookUpdate,
+};
+use keyrock_mm_rust_task::modules::update_age::{DEFAULT_MAX_UPDATE_AGE, UpdateAgeGuard};
+use keyrock_mm_rust_task::modules::update_queue::{self, OverflowPolicy};
+use keyrock_mm_rust_task::stdio_service;
 
-mod grpc_service;
-mod modules;
+/// How often the connector loop looks for exchanges that have gone quiet
+const STALENESS_CHECK_INTERVAL: Duration = Duration::from_millis(250);
 
 #[derive(Parser)]
 struct Args {
-    #[arg(default_value = "ethbtc")]
+    #[arg(env = "AGG_SYMBOL", default_value = "ethbtc")]
     symbol: String,
+
+    /// Path to a JSON config file with `defaults` and per-symbol `symbols.<symbol>` overrides
+    #[arg(long, env = "AGG_CONFIG")]
+    config: Option<String>,
+
+    /// Maximum number of buffered messages per exchange between the socket and the book
+    #[arg(long, env = "AGG_QUEUE_CAPACITY", default_value_t = 1024)]
+    queue_capacity: usize,
+
+    /// What to do when an exchange queue is full: block, drop-oldest or drop-newest
+    #[arg(long, env = "AGG_OVERFLOW_POLICY", default_value = "block")]
+    overflow_policy: OverflowPolicy,
+
+    /// Drop updates read off the socket more than this many milliseconds before they're
+    /// applied, and resync their exchange
+    #[arg(long, env = "AGG_MAX_UPDATE_AGE_MS", default_value_t = DEFAULT_MAX_UPDATE_AGE.as_millis() as u64)]
+    max_update_age_ms: u64,
+
+    /// Fail a connection whose subscription isn't confirmed within this many milliseconds
+    /// (the Bitstamp ack, or Binance's first data frame)
+    #[arg(long, env = "AGG_HANDSHAKE_TIMEOUT_MS", default_value_t = DEFAULT_HANDSHAKE_TIMEOUT.as_millis() as u64)]
+    handshake_timeout_ms: u64,
+
+    /// After each Bitstamp (re)subscription, reconnect if the diff channel sends no data within
+    /// this many milliseconds (off by default; the config's first_data_timeout_ms takes precedence)
+    #[arg(long, env = "AGG_FIRST_DATA_TIMEOUT_MS")]
+    first_data_timeout_ms: Option<u64>,
+
+    /// Drop an exchange's levels and resync if it hasn't sent data for this many milliseconds
+    #[arg(long, env = "AGG_STALE_AFTER_MS")]
+    stale_after_ms: Option<u64>,
+
+    /// Tombstone levels removed near the top of the book for this many milliseconds (off by default)
+    #[arg(long, env = "AGG_TOMBSTONE_WINDOW_MS")]
+    tombstone_window_ms: Option<u64>,
+
+    /// Number of top price levels eligible for tombstone smoothing
+    #[arg(long, env = "AGG_TOMBSTONE_TOP_N", default_value_t = 10)]
+    tombstone_top_n: usize,
+
+    /// Consecutive failed connect/sync attempts within the breaker window that open an exchange's circuit
+    #[arg(long, env = "AGG_BREAKER_FAILURES", default_value_t = 5)]
+    breaker_failures: usize,
+
+    /// Window in seconds over which consecutive failures are counted
+    #[arg(long, env = "AGG_BREAKER_WINDOW_SECS", default_value_t = 60)]
+    breaker_window_secs: u64,
+
+    /// How long an open circuit blocks attempts before a probe, in seconds
+    #[arg(long, env = "AGG_BREAKER_COOL_DOWN_SECS", default_value_t = 300)]
+    breaker_cool_down_secs: u64,
+
+    /// Quarantine an exchange after more than this many consecutive stale updates
+    #[arg(long, env = "AGG_QUARANTINE_STALE_UPDATES", default_value_t = 50)]
+    quarantine_stale_updates: usize,
+
+    /// Window in seconds over which consecutive stale updates are counted
+    #[arg(long, env = "AGG_QUARANTINE_WINDOW_SECS", default_value_t = 10)]
+    quarantine_window_secs: u64,
+
+    /// How long a quarantined exchange is ignored before it is resynced, in seconds
+    #[arg(long, env = "AGG_QUARANTINE_COOL_DOWN_SECS", default_value_t = 30)]
+    quarantine_cool_down_secs: u64,
+
+    /// Cache-busting retries of a Bitstamp REST snapshot that repeats the last one while the
+    /// stream has moved on, before Bitstamp is quarantined
+    #[arg(long, env = "AGG_STUCK_SNAPSHOT_RETRIES", default_value_t = 3)]
+    stuck_snapshot_retries: usize,
+
+    /// How far past a repeated Bitstamp snapshot the stream must be for the REST cache to
+    /// count as stuck, in seconds
+    #[arg(long, env = "AGG_STUCK_SNAPSHOT_LEAD_SECS", default_value_t = 10)]
+    stuck_snapshot_lead_secs: u64,
+
+    /// Stop reading Bitstamp's REST book after this many levels per side (off by default:
+    /// the whole book is read)
+    #[arg(long, env = "AGG_BITSTAMP_SNAPSHOT_DEPTH")]
+    bitstamp_snapshot_depth: Option<usize>,
+
+    /// How the first diff after a snapshot is treated when it ends exactly at the snapshot's
+    /// id: strict or apply-if-overlapping (default: each exchange's documented behavior)
+    #[arg(long, env = "AGG_BOUNDARY_POLICY")]
+    boundary_policy: Option<BoundaryPolicy>,
+
+    /// Sample book-shape statistics at most this often, in milliseconds (0 disables)
+    #[arg(long, env = "AGG_SHAPE_SAMPLE_MS", default_value_t = 1000)]
+    shape_sample_ms: u64,
+
+    /// Clip size, in base currency, of the sampled price improvement over single venues
+    #[arg(long, env = "AGG_IMPROVEMENT_CLIP_SIZE", default_value_t = 1.0)]
+    improvement_clip_size: f64,
+
+    /// Sample the price improvement at most this often, in milliseconds (0 disables)
+    #[arg(long, env = "AGG_IMPROVEMENT_SAMPLE_MS", default_value_t = 1000)]
+    improvement_sample_ms: u64,
+
+    /// Estimate traded volume from levels deleted at the top of each exchange's book
+    #[arg(long, env = "AGG_ESTIMATE_TRADED_VOLUME")]
+    estimate_traded_volume: bool,
+
+    /// Set `build_id` (version+git hash) on every Summary
+    #[arg(long, env = "AGG_STAMP_BUILD_ID")]
+    stamp_build_id: bool,
+
+    /// Price levels per side in the notional imbalance gauge (0 disables)
+    #[arg(long, env = "AGG_IMBALANCE_DEPTH", default_value_t = 10)]
+    imbalance_depth: usize,
+
+    /// Republish the imbalance only once it moves by more than this (at most once a second)
+    #[arg(long, env = "AGG_IMBALANCE_MIN_DELTA", default_value_t = 0.05)]
+    imbalance_min_delta: f64,
+
+    /// Time constant of the smoothed per-exchange message rate, in seconds
+    #[arg(long, env = "AGG_RATE_HORIZON_SECS", default_value_t = 30)]
+    rate_horizon_secs: u64,
+
+    /// Flag a burst while an exchange's last-second rate exceeds this multiple of its smoothed rate
+    #[arg(long, env = "AGG_BURST_MULTIPLE", default_value_t = 3.0)]
+    burst_multiple: f64,
+
+    /// Concurrent REST snapshot fetches allowed per exchange
+    #[arg(long, env = "AGG_SNAPSHOT_FETCH_CONCURRENCY", default_value_t = 4)]
+    snapshot_fetch_concurrency: usize,
+
+    /// Snapshot fetches for a feed requested within this many milliseconds share one REST call
+    #[arg(long, env = "AGG_RESYNC_COALESCE_MS", default_value_t = 100)]
+    resync_coalesce_ms: u64,
+
+    /// When exchanges resync together, publish the book once all have merged their snapshot,
+    /// or after this many milliseconds at the latest
+    #[arg(long, env = "AGG_RESYNC_PUBLISH_TIMEOUT_MS", default_value_t = 5000)]
+    resync_publish_timeout_ms: u64,
+
+    /// Binance symbol whose mid converts notionals out of the quote currency, e.g. btcusdt
+    #[arg(long, env = "AGG_NOTIONAL_REFERENCE")]
+    notional_reference: Option<String>,
+
+    /// Label for the currency the reference converts into
+    #[arg(long, env = "AGG_NOTIONAL_CURRENCY", default_value = "usd")]
+    notional_currency: String,
+
+    /// Report notionals unconverted once the reference price is older than this, in milliseconds
+    #[arg(long, env = "AGG_NOTIONAL_MAX_AGE_MS", default_value_t = 5000)]
+    notional_max_age_ms: u64,
+
+    /// Skip Summaries identical to the previous one, forcing a heartbeat after this many milliseconds (off by default)
+    #[arg(long, env = "AGG_DEDUP_HEARTBEAT_MS")]
+    dedup_heartbeat_ms: Option<u64>,
+
+    /// Wait this many seconds of startup for every exchange to sync; serve degraded past it
+    /// if some did, exit if none did
+    #[arg(long, env = "AGG_STARTUP_TIMEOUT_SECS", default_value_t = 30)]
+    startup_timeout_secs: u64,
+
+    /// Serve line-delimited JSON requests on stdin/stdout instead of gRPC; logs go to stderr
+    #[arg(long, env = "AGG_STDIO")]
+    stdio: bool,
+
+    /// Refuse new deduplicating BookSummary streams with RESOURCE_EXHAUSTED once the state kept
+    /// by all open stateful streams would exceed this many bytes (unlimited by default)
+    #[arg(long, env = "AGG_MAX_STREAM_STATE_BYTES")]
+    max_stream_state_bytes: Option<u64>,
+
+    /// Log the first of a run of identical hot-path warnings (stale update ids, failed
+    /// updates, capped payloads), then only a count of the repeats once per this many
+    /// milliseconds; 0 logs every one
+    #[arg(long, env = "AGG_LOG_SUPPRESSION_WINDOW_MS", default_value_t = 60_000)]
+    log_suppression_window_ms: u64,
+
+    /// Points kept per series for GetTimeSeries (spread, apply latency, update rates, book
+    /// shape); 0 records none
+    #[arg(long, env = "AGG_TIMESERIES_MAX_SAMPLES", default_value_t = DEFAULT_SERIES_CAPACITY)]
+    timeseries_max_samples: usize,
+
+    /// Also drop series points older than this many seconds (kept until evicted by count
+    /// if unset)
+    #[arg(long, env = "AGG_TIMESERIES_MAX_AGE_SECS")]
+    timeseries_max_age_secs: Option<u64>,
+
+    /// How often a deduplicating stream re-checks an unchanged book, in milliseconds
+    #[arg(long, env = "AGG_DEDUP_POLL_MS", default_value_t = 50)]
+    dedup_poll_ms: u64,
+
+    /// Updates taking longer than this to apply (lock wait included) are logged and counted,
+    /// in microseconds
+    #[arg(long, env = "AGG_APPLY_BUDGET_US", default_value_t = 5000)]
+    apply_budget_us: u64,
+
+    /// Mark an exchange degraded after this many slow applies in a row
+    #[arg(long, env = "AGG_DEGRADED_AFTER_SLOW_APPLIES", default_value_t = 10)]
+    degraded_after_slow_applies: usize,
+
+    /// Largest websocket message accepted from an exchange, in bytes; a larger one reconnects
+    #[arg(long, env = "AGG_MAX_FRAME_BYTES", default_value_t = PayloadLimits::default().max_frame_bytes)]
+    max_frame_bytes: usize,
+
+    /// Levels kept per side of one exchange update; excess levels are dropped and counted
+    #[arg(long, env = "AGG_MAX_UPDATE_LEVELS", default_value_t = PayloadLimits::default().max_update_levels)]
+    max_update_levels: usize,
+
+    /// Largest REST snapshot body read from an exchange, in bytes
+    #[arg(long, env = "AGG_MAX_SNAPSHOT_BYTES", default_value_t = PayloadLimits::default().max_snapshot_bytes)]
+    max_snapshot_bytes: usize,
+
+    /// Also subscribe to Bitstamp's full order_book channel and compare it against the
+    /// Bitstamp levels maintained from diffs
+    #[arg(long, env = "AGG_BITSTAMP_CROSS_CHECK")]
+    bitstamp_cross_check: bool,
+
+    /// Largest relative amount difference between the full book and ours that still matches
+    #[arg(long, env = "AGG_CROSS_CHECK_TOLERANCE", default_value_t = CrossCheckConfig::default().tolerance)]
+    cross_check_tolerance: f64,
+
+    /// Resync after this many divergent cross-checks in a row
+    #[arg(long, env = "AGG_CROSS_CHECK_RESYNC_AFTER", default_value_t = CrossCheckConfig::default().resync_after)]
+    cross_check_resync_after: usize,
+}
+
+/// An enabled exchange's connector and what the connector loop keeps for it across
+/// connections
+struct Venue {
+    connector: Box<dyn ExchangeConnector>,
+    /// The exchange's name for the symbol
+    instrument: String,
+    breaker: CircuitBreaker,
+    sync: SyncTracker,
+    commands: Option<mpsc::Receiver<ConnectorCommand>>,
+    control: FeedControl,
+}
+
+impl Venue {
+    fn exchange(&self) -> Exchange {
+        self.connector.exchange()
+    }
+}
+
+/// Exchanges in the order they are connected and reported
+fn connection_order(binance: Exchange) -> [Exchange; 9] {
+    [
+        Exchange::Bitstamp,
+        binance,
+        Exchange::Kraken,
+        Exchange::Coinbase,
+        Exchange::Okx,
+        Exchange::Bybit,
+        Exchange::Kucoin,
+        Exchange::GateIo,
+        Exchange::Bitfinex,
+    ]
+}
+
+/// A connector for each enabled exchange, in connection order
+fn exchange_connectors(
+    app_config: &AppConfig,
+    enabled: &[Exchange],
+    args: &Args,
+) -> Vec<Box<dyn ExchangeConnector>> {
+    // Bitstamp's ids are microtimestamps
+    let stuck_snapshot = StuckSnapshotConfig {
+        min_lead: Duration::from_secs(args.stuck_snapshot_lead_secs).as_micros() as u64,
+        max_retries: args.stuck_snapshot_retries,
+    };
+    connection_order(app_config.binance_variant.exchange())
+        .into_iter()
+        .filter(|exchange| enabled.contains(exchange))
+        .map(|exchange| -> Box<dyn ExchangeConnector> {
+            match exchange {
+                Exchange::Bitstamp => {
+                    let mut bitstamp =
+                        BitstampConnector::new(app_config.bitstamp_endpoint(), stuck_snapshot);
+                    if args.bitstamp_cross_check {
+                        bitstamp = bitstamp.with_full_book();
+                    }
+                    if let Some(depth) = args.bitstamp_snapshot_depth {
+                        bitstamp = bitstamp.with_depth(depth);
+                    }
+                    Box::new(bitstamp)
+                }
+                Exchange::Binance | Exchange::BinanceUs => Box::new(app_config.binance_endpoint()),
+                Exchange::Kraken => Box::new(app_config.kraken_endpoint()),
+                Exchange::Coinbase => Box::new(app_config.coinbase_endpoint()),
+                Exchange::Okx => Box::new(OkxConnector::new(app_config.okx_endpoint())),
+                Exchange::Bybit => Box::new(BybitConnector::new(app_config.bybit_endpoint())),
+                Exchange::Kucoin => Box::new(KucoinConnector::new(app_config.kucoin_endpoint())),
+                Exchange::GateIo => Box::new(app_config.gateio_endpoint()),
+                Exchange::Bitfinex => {
+                    Box::new(BitfinexConnector::new(app_config.bitfinex_endpoint()))
+                }
+            }
+        })
+        .collect()
+}
+
+/// A subscribed exchange connection and the snapshot to start its book from
+struct Synced {
+    sink: WsSink,
+    stream: Confirmed<WsStream>,
+    snapshot: OrderBook,
+    ping_interval: Option<Duration>,
+}
+
+// Connect the stream first so no updates are missed and wait for the subscription to be
+// confirmed, then fetch the snapshot through the coordinator; with the stream already
+// buffering diffs it goes ahead of unconnected symbols
+
+async fn connect_and_snapshot(
+    connector: &mut dyn ExchangeConnector,
+    instrument: &str,
+    symbol: &str,
+    status: &SharedStatus,
+    handshake: (&PayloadLimits, Duration, Option<Duration>),
+) -> Result<Synced, String> {
+    let exchange = connector.exchange();
+    let (limits, timeout, first_data) = handshake;
+    let connection = connector.connect(instrument, limits).await?;
+    status.set_connection(exchange.as_str(), ConnectionState::Subscribing);
+    let confirmation = connector.confirmation(instrument);
+    // Without an ack there is nothing to await data after
+    let first_data = first_data.filter(|_| confirmation != Confirmation::FirstFrame);
+    let stream = handshake::confirm(connection.stream, &confirmation, timeout, first_data).await?;
+    status.set_connection(exchange.as_str(), ConnectionState::Connecting);
+    let snapshot = status
+        .snapshots
+        .fetch(
+            exchange.as_str(),
+            symbol,
+            FetchPriority::Connected,
+            connector.fetch_snapshot(instrument, limits),
+        )
+        .await?;
+    Ok(Synced {
+        sink: connection.sink,
+        stream,
+        snapshot,
+        ping_interval: connection.ping_interval,
+    })
+}
+
+// Feed a connect/sync outcome into the exchange's circuit breaker, quarantine and status.
+// `None` means no attempt was made because the circuit is open.
+async fn settle_attempt(
+    exchange: Exchange,
+    outcome: Option<Result<Synced, String>>,
+    breaker: &mut CircuitBreaker,
+    quarantine: &mut Quarantine,
+    status: &SharedStatus,
+    agg: &RwLock<AggregatedOrderBook>,
+) -> Option<Synced> {
+    let synced = match outcome {
+        None => None,
+        Some(Ok(synced)) => {
+            breaker.record_success();
+            status.set_connection(exchange.as_str(), ConnectionState::Connected);
+            Some(synced)
+        }
+        Some(Err(e)) => {
+            tracing::error!("{} connect/sync failed: {}", exchange.as_str(), e);
+            status.startup.failed(exchange.as_str(), &e);
+            if connectors::is_body_too_large(&e) {
+                status.record_oversized_snapshot(exchange.as_str());
+            }
+            if breaker.record_failure() == CircuitState::Open {
+                agg.write().await.remove_exchange(exchange.as_str());
+                status.set_contributing(exchange.as_str(), false);
+                tracing::warn!(
+                    "{} circuit open, levels removed from the book",
+                    exchange.as_str()
+                );
+            }
+            status.set_connection(exchange.as_str(), ConnectionState::Disconnected);
+            if quarantine.on_sync_failure(&mut *agg.write().await, exchange.as_str(), &e) {
+                report_quarantine(exchange, quarantine, status);
+            }
+            None
+        }
+    };
+    status.set_breaker(exchange.as_str(), breaker.stats());
+    synced
+}
+
+/// `exchange: last error (circuit state)` for each exchange that missed the startup deadline
+fn startup_failures(failed: &BTreeMap<String, String>, status: &SharedStatus) -> String {
+    let breakers: BTreeMap<String, BreakerStats> = status
+        .exchanges()
+        .into_iter()
+        .map(|status| (status.exchange, status.breaker))
+        .collect();
+    failed
+        .iter()
+        .map(|(exchange, error)| match breakers.get(exchange) {
+            Some(breaker) => format!(
+                "{}: {} (circuit {:?}, {} failures)",
+                exchange, error, breaker.state, breaker.total_failures
+            ),
+            None => format!("{}: {}", exchange, error),
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Next command for any exchange's connector, with the index of its venue; never resolves
+/// while none has one
+async fn next_command(venues: &mut [Venue]) -> (usize, ConnectorCommand) {
+    std::future::poll_fn(|cx| {
+        for (index, venue) in venues.iter_mut().enumerate() {
+            let Some(commands) = venue.commands.as_mut() else {
+                continue;
+            };
+            match commands.poll_recv(cx) {
+                Poll::Ready(Some(command)) => return Poll::Ready((index, command)),
+                // Nothing can be sent on a closed channel any more
+                Poll::Ready(None) => venue.commands = None,
+                Poll::Pending => {}
+            }
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+/// Apply a command to an exchange's feed; pausing or stopping takes it out of the book
+async fn on_command(
+    exchange: Exchange,
+    command: ConnectorCommand,
+    control: &mut FeedControl,
+    status: &SharedStatus,
+    agg: &RwLock<AggregatedOrderBook>,
+) -> CommandAction {
+    let name = exchange.as_str();
+    tracing::info!(
+        exchange = name,
+        command = command.as_str(),
+        "Connector command"
+    );
+    let action = control.apply(command);
+    if command == ConnectorCommand::Pause || action == CommandAction::Stop {
+        agg.write().await.remove_exchange(name);
+        status.set_contributing(name, false);
+        status.set_connection(name, ConnectionState::Disconnected);
+    }
+    action
+}
+
+/// Release a resync hold that timed out and drop the levels of exchanges that have sent no
+/// data for `stale_after_ms`. Returns whether any did, to resync them.
+async fn expire_stale_exchanges(
+    agg: &RwLock<AggregatedOrderBook>,
+    stale_after: Option<Duration>,
+    status: &SharedStatus,
+) -> bool {
+    let mut agg = agg.write().await;
+    agg.release_expired_hold();
+    agg.expire_tombstones();
+    let Some(max_age) = agg
+        .config
+        .settings
+        .stale_after_ms
+        .map(Duration::from_millis)
+        .or(stale_after)
+    else {
+        return false;
+    };
+    let expired = agg.expire_stale_exchanges(max_age);
+    drop(agg);
+    if expired.is_empty() {
+        return false;
+    }
+    for exchange in &expired {
+        status.set_contributing(exchange, false);
+    }
+    tracing::warn!(
+        "No data from {:?} for over {}ms, resyncing",
+        expired,
+        max_age.as_millis()
+    );
+    true
+}
+
+/// Count a received message and log update bursts as they start and end
+fn record_message(exchange: Exchange, status: &SharedStatus) {
+    let name = exchange.as_str();
+    status.counters.record_message(name);
+    match status.rates.record(name) {
+        Some(rate::BurstEvent::Started(rate)) => tracing::warn!(
+            exchange = name,
+            rate = rate.last_second,
+            smoothed = rate.smoothed,
+            "Update burst started"
+        ),
+        Some(rate::BurstEvent::Ended(rate)) => tracing::info!(
+            exchange = name,
+            rate = rate.last_second,
+            smoothed = rate.smoothed,
+            peak = rate.peak_last_minute,
+            "Update burst ended"
+        ),
+        None => {}
+    }
+}
+
+/// Count and log an update that waited longer than the max update age; it must not be applied
+fn drop_if_too_old(
+    exchange: Exchange,
+    update: &OrderBookUpdate,
+    update_age: &mut UpdateAgeGuard,
+    status: &SharedStatus,
+) -> bool {
+    let Some(age_ms) = update_age.too_old(update) else {
+        return false;
+    };
+    let name = exchange.as_str();
+    status.record_dropped_as_old(name);
+    tracing::warn!(
+        exchange = name,
+        update_id = update.update_id,
+        age_ms,
+        max_age_ms = update_age.max_age().as_millis() as u64,
+        "Update too old to apply, dropped"
+    );
+    true
+}
+
+/// Count and log an update that took longer than the latency budget, and mark the exchange
+/// degraded after a run of them
+fn check_apply_latency(
+    exchange: Exchange,
+    (bids, asks): (usize, usize),
+    timing: ApplyTiming,
+    status: &SharedStatus,
+) {
+    let name = exchange.as_str();
+    let check = status.latency.record(name, timing);
+    status
+        .timeseries
+        .record("apply_latency_us", name, timing.total().as_micros() as f64);
+    if check.slow {
+        tracing::warn!(
+            exchange = name,
+            bids,
+            asks,
+            lock_wait_us = timing.lock_wait.as_micros() as u64,
+            apply_us = timing.apply.as_micros() as u64,
+            budget_us = status.latency.budget().as_micros() as u64,
+            consecutive = check.consecutive_slow,
+            "Update apply over the latency budget"
+        );
+    }
+    match check.event {
+        Some(BudgetEvent::Degraded) => {
+            tracing::warn!(
+                exchange = name,
+                consecutive = check.consecutive_slow,
+                "Exchange degraded: updates keep exceeding the latency budget"
+            );
+            status.set_degraded(name, true);
+        }
+        Some(BudgetEvent::Recovered) => {
+            tracing::info!(exchange = name, "Update latency back within budget");
+            status.set_degraded(name, false);
+        }
+        None => {}
+    }
+}
+
+/// Compare a message of Bitstamp's full order_book channel against the Bitstamp levels kept
+/// from diffs. Returns true when the divergence persisted and the book should be resynced.
+async fn cross_check_bitstamp(
+    checker: &mut CrossChecker,
+    text: &str,
+    status: &SharedStatus,
+    agg: &RwLock<AggregatedOrderBook>,
+    now: u64,
+) -> bool {
+    let name = Exchange::Bitstamp.as_str();
+    let reference = match parse_bitstamp_full_book(text) {
+        Ok(Some(reference)) => reference,
+        Ok(None) => return false,
+        Err(reason) => {
+            status.parse_failures.record(name, now, &reason, text);
+            return false;
+        }
+    };
+    let ours = agg
+        .read()
+        .await
+        .exchange_book(name, checker.config().levels);
+    let outcome = checker.check(&reference, &ours);
+    status.record_cross_check(name, &outcome);
+    match &outcome {
+        CrossCheck::Match => false,
+        CrossCheck::Diverged(divergence) => {
+            tracing::warn!(
+                exchange = name,
+                side = ?divergence.side,
+                index = divergence.index,
+                reference = ?divergence.reference,
+                ours = ?divergence.ours,
+                "Full book diverges from the diff-maintained book"
+            );
+            false
+        }
+        CrossCheck::Resync(divergence) => {
+            tracing::error!(
+                exchange = name,
+                side = ?divergence.side,
+                index = divergence.index,
+                reference = ?divergence.reference,
+                ours = ?divergence.ours,
+                "Full book keeps diverging from the diff-maintained book, resyncing"
+            );
+            true
+        }
+    }
+}
+
+fn report_quarantine(exchange: Exchange, quarantine: &Quarantine, status: &SharedStatus) {
+    let name = exchange.as_str();
+    if quarantine.is_quarantined(name) {
+        status.set_connection(name, ConnectionState::Quarantined);
+        status.set_contributing(name, false);
+    }
+    status.set_quarantine(
+        name,
+        quarantine.quarantined_until(name),
+        quarantine.times_quarantined(name),
+    );
+}
+
+/// Serve the Admin service alone on its dedicated TCP port or Unix socket
+async fn serve_admin(
+    service: OrderbookAggregatorService,
+    listen: ListenAddr,
+) -> Result<(), ExitReason> {
+    let router = create_admin_server(service);
+    match listen {
+        ListenAddr::Tcp(addr) => {
+            let incoming = TcpIncoming::new(addr, false, None).map_err(|e| {
+                ExitReason::GrpcBind(format!("failed to bind Admin port {}: {}", addr, e))
+            })?;
+            tracing::info!("gRPC Admin server starting on {}", addr);
+            router.serve_with_incoming(incoming).await.map_err(|e| {
+                ExitReason::Other(format!("gRPC Admin server on {} failed: {}", addr, e))
+            })
+        }
+        ListenAddr::Unix(path) => {
+            // A socket left behind by a previous run would make bind fail; never touch
+            // anything else at that path
+            if std::fs::symlink_metadata(&path).is_ok_and(|m| m.file_type().is_socket()) {
+                let _ = std::fs::remove_file(&path);
+            }
+            let listener = UnixListener::bind(&path).map_err(|e| {
+                ExitReason::GrpcBind(format!(
+                    "failed to bind Admin socket {}: {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+            tracing::info!("gRPC Admin server starting on unix:{}", path.display());
+            let incoming = async_stream::stream! {
+                loop {
+                    yield listener.accept().await.map(|(stream, _)| stream);
+                }
+            };
+            router.serve_with_incoming(incoming).await.map_err(|e| {
+                ExitReason::Other(format!(
+                    "gRPC Admin server on {} failed: {}",
+                    path.display(),
+                    e
+                ))
+            })
+        }
+    }
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize tracing
-    tracing_subscriber::fmt::init();
+async fn main() -> ExitCode {
+    let started = Instant::now();
     let args = Args::parse();
+    let rates = RateTracker::new(
+        system_clock(),
+        RateConfig {
+            horizon: Duration::from_secs(args.rate_horizon_secs),
+            burst_multiple: args.burst_multiple,
+        },
+    );
+    let status: SharedStatus = Arc::new(
+        StatusRegistry::default()
+            .with_snapshot_fetches(
+                args.snapshot_fetch_concurrency,
+                Duration::from_millis(args.resync_coalesce_ms),
+            )
+            .with_stream_state_limit(args.max_stream_state_bytes)
+            .with_log_limiter(Arc::new(LogLimiter::new(
+                system_clock(),
+                Duration::from_millis(args.log_suppression_window_ms),
+            )))
+            .with_timeseries(Arc::new(TimeSeriesStore::new(
+                system_clock(),
+                Retention {
+                    max_samples: args.timeseries_max_samples,
+                    max_age: args.timeseries_max_age_secs.map(Duration::from_secs),
+                },
+            )))
+            .with_rates(rates)
+            .with_latency(LatencyMonitor::new(LatencyBudget {
+                budget: Duration::from_micros(args.apply_budget_us),
+                degraded_after: args.degraded_after_slow_applies,
+            })),
+    );
+    let reason = match run(args, Arc::clone(&status)).await {
+        Ok(cause) => ExitReason::Clean(cause.to_string()),
+        Err(reason) => reason,
+    };
+    let report = ShutdownReport::collect(&status, started.elapsed(), reason);
+    // A config error can end the process before logging is set up
+    let logging = tracing::dispatcher::has_been_set();
+    for line in report.lines() {
+        if logging {
+            tracing::info!("{}", line);
+        } else {
+            eprintln!("{}", line);
+        }
+    }
+    if logging && report.exit_code() != 0 {
+        eprintln!("{}", report.reason.describe());
+    }
+    ExitCode::from(report.exit_code())
+}
 
+// Startup runs in phases: config, shared state, connectors, readiness, then gRPC.
+// Any error before serving ends the process before a client can connect to an empty book.
+// Returns what caused a clean shutdown.
+async fn run(args: Args, status: SharedStatus) -> Result<&'static str, ExitReason> {
+    // Phase 1: load and validate the configuration
     let symbol = args.symbol.to_lowercase();
+    // `AGG_*` variables read by flags are left to clap; the rest set config file keys
+    let cli_env: Vec<String> = Args::command()
+        .get_arguments()
+        .filter_map(|arg| arg.get_env())
+        .map(|name| name.to_string_lossy().into_owned())
+        .collect();
+    let cli_env: Vec<&str> = cli_env.iter().map(String::as_str).collect();
+    let env = EnvLayer::from_process(&cli_env);
+    let app_config =
+        AppConfig::load_layered(args.config.as_deref(), &env).map_err(ExitReason::Config)?;
+    if !(args.improvement_clip_size.is_finite() && args.improvement_clip_size > 0.0) {
+        return Err(ExitReason::Config(
+            "--improvement-clip-size must be positive".to_string(),
+        ));
+    }
+
+    // Initialize tracing, with the level reloadable from the config file
+    let initial_level = match &app_config.log_level {
+        Some(level) => level
+            .parse::<LevelFilter>()
+            .map_err(|e| ExitReason::Config(format!("invalid log_level: {}", e)))?,
+        None => LevelFilter::INFO,
+    };
+    let (level_layer, level_handle) = reload::Layer::new(initial_level);
+    // stdout carries responses in stdio mode, so logs must stay on stderr
+    let log_writer = if args.stdio {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
+    tracing_subscriber::registry()
+        .with(level_layer)
+        .with(tracing_subscriber::fmt::layer().with_writer(log_writer))
+        .init();
+    let build = BuildInfo::current();
+    tracing::info!(
+        "Build {} ({}, features [{}])",
+        build.build_id(),
+        build.rustc_version,
+        build.features.join(",")
+    );
+    let mut symbol_config = app_config.resolve(&symbol);
+    for warning in app_config.symbol_warnings() {
+        tracing::warn!("{}", warning);
+    }
+    tracing::info!(
+        "Effective config for {}: {:?}",
+        symbol,
+        symbol_config.settings
+    );
+    let queue_capacity = args.queue_capacity;
+    let overflow_policy = args.overflow_policy;
+    // Fallback when the config doesn't set stale_after_ms
+    let stale_after = args.stale_after_ms.map(Duration::from_millis);
+    let max_update_age = Duration::from_millis(args.max_update_age_ms);
+    let handshake_timeout = Duration::from_millis(args.handshake_timeout_ms);
+    // Fallback when the config doesn't set first_data_timeout_ms
+    let first_data_timeout = args.first_data_timeout_ms.map(Duration::from_millis);
+    let resync_publish_timeout = Duration::from_millis(args.resync_publish_timeout_ms);
+    let payload_limits = PayloadLimits {
+        max_frame_bytes: args.max_frame_bytes,
+        max_update_levels: args.max_update_levels,
+        max_snapshot_bytes: args.max_snapshot_bytes,
+    };
+    let cross_check = args.bitstamp_cross_check.then_some(CrossCheckConfig {
+        tolerance: args.cross_check_tolerance,
+        resync_after: args.cross_check_resync_after,
+        ..Default::default()
+    });
+    let binance_variant = app_config.binance_variant;
+    let binance_endpoint = app_config.binance_endpoint();
+    let admin_enabled = app_config.admin.enabled;
+    let admin_listener = app_config.admin.listener().map_err(ExitReason::Config)?;
+    let grpc_listeners = app_config.grpc_listeners().map_err(ExitReason::Config)?;
+    let binance_exchange = binance_variant.exchange();
+    let enabled = app_config.enabled_exchanges().map_err(ExitReason::Config)?;
+    let binance_enabled = enabled.contains(&binance_exchange);
+    // Connectors subscribe to each exchange's instrument; the book keeps the symbol
+    let binance_symbol = app_config.exchange_symbol(&symbol, binance_exchange);
+    let venues: Vec<(Box<dyn ExchangeConnector>, String)> =
+        exchange_connectors(&app_config, &enabled, &args)
+            .into_iter()
+            .map(|connector| {
+                let instrument = app_config.exchange_symbol(&symbol, connector.exchange());
+                (connector, instrument)
+            })
+            .collect();
+    let startup_timeout = Duration::from_secs(args.startup_timeout_secs);
 
-    // Create empty aggregated orderbook initially
-    let agg = AggregatedOrderBook::new();
+    // Fail fast on pairs the selected Binance deployment doesn't list
+    if binance_enabled {
+        match connectors::is_binance_symbol_listed(&binance_symbol, &binance_endpoint).await {
+            Ok(true) => {}
+            Ok(false) => {
+                return Err(ExitReason::Config(format!(
+                    "symbol {} is not listed on {}",
+                    binance_symbol,
+                    binance_exchange.as_str()
+                )));
+            }
+            Err(e) => tracing::warn!("Could not validate symbol {}: {}", symbol, e),
+        }
+    }
+    // Snapping to the tick needs one: the configured tick, else the one Binance lists
+    let settings = &mut symbol_config.settings;
+    if settings.round_to_tick && settings.tick_size.is_none() {
+        let fetched = if binance_enabled {
+            connectors::get_binance_tick_size(&binance_symbol, &binance_endpoint).await
+        } else {
+            Err("the tick can only be fetched from Binance, which is not enabled".to_string())
+        };
+        match fetched {
+            Ok(tick) => {
+                tracing::info!("Snapping {} prices to Binance's tick of {}", symbol, tick);
+                settings.tick_size = Some(tick);
+            }
+            Err(e) => tracing::warn!(
+                "round_to_tick is set but {} has no tick size, prices are not snapped: {}",
+                symbol,
+                e
+            ),
+        }
+    }
+    for exchange in connection_order(binance_exchange) {
+        if !enabled.contains(&exchange) {
+            tracing::info!("{} is not configured, not connecting", exchange.as_str());
+            status.set_connection(exchange.as_str(), ConnectionState::NotConfigured);
+        }
+    }
+    status
+        .startup
+        .expect(enabled.iter().map(|exchange| exchange.as_str()));
+
+    // Phase 2: shared state. The book starts empty
+    let clock = system_clock();
+    let mut agg = AggregatedOrderBook::with_clock(clock.clone())
+        .with_config(symbol_config)
+        .with_configured_exchanges(enabled.iter().map(|exchange| exchange.as_str()))
+        .with_spread_convention(app_config.spread_convention)
+        .with_log_limiter(Arc::clone(&status.log_limiter))
+        .with_timeseries(Arc::clone(&status.timeseries));
+    if let Some(window_ms) = args.tombstone_window_ms {
+        agg = agg.with_smoothing(TombstoneConfig {
+            top_n: args.tombstone_top_n,
+            window: Duration::from_millis(window_ms),
+        });
+    }
+    if let Some(policy) = args.boundary_policy {
+        agg = agg.with_boundary_policy(policy);
+    }
+    if args.shape_sample_ms > 0 {
+        agg = agg.with_shape_sampling(Duration::from_millis(args.shape_sample_ms));
+    }
+    if args.improvement_sample_ms > 0 {
+        agg = agg.with_improvement_sampling(
+            args.improvement_clip_size,
+            Duration::from_millis(args.improvement_sample_ms),
+        );
+    }
+    if args.estimate_traded_volume {
+        agg = agg.with_traded_volume_estimate();
+    }
+    for exchange in &enabled {
+        agg = agg.with_sequence_reset(*exchange, app_config.sequence_reset(*exchange));
+    }
+    if args.imbalance_depth > 0 {
+        agg = agg.with_imbalance_gauge(
+            args.imbalance_depth,
+            ThrottleConfig {
+                min_interval: GAUGE_PUBLISH_INTERVAL,
+                min_delta: args.imbalance_min_delta,
+            },
+        );
+    }
+    let one_sided = app_config.one_sided_summaries;
+    let consistency = app_config.summary_consistency;
     let agg_shared = Arc::new(RwLock::new(agg));
 
-    // Start gRPC server
-    let agg_for_grpc = Arc::clone(&agg_shared);
-    let grpc_server = tokio::spawn(async move {
-        let addr = "127.0.0.1:5002".parse().unwrap();
-        let service = create_grpc_server(agg_for_grpc);
-
-        tracing::info!("gRPC server starting on {}", addr);
-        Server::builder()
-            .add_service(service)
-            .serve(addr)
-            .await
-            .unwrap();
+    // Alarms raised anywhere go to the notifiers configured for their kind
+    let (alarms, raised_alarms) = Alarms::channel();
+    let notifiers = app_config
+        .alarms
+        .notifiers
+        .iter()
+        .map(|(name, notifier)| {
+            (
+                name.clone(),
+                connectors::build_notifier(notifier, clock.clone()),
+            )
+        })
+        .collect();
+    let dispatcher =
+        AlarmDispatcher::new(clock.clone(), &symbol).with_config(&app_config.alarms, &notifiers);
+    tokio::spawn(dispatcher.run(raised_alarms));
+    let mut alarm_watch = BookAlarmWatch::new(
+        clock.clone(),
+        enabled.iter().map(|e| e.as_str().to_string()).collect(),
+        stale_after,
+        Duration::from_secs(app_config.alarms.crossed_after_secs),
+    );
+    let (watched, watch_alarms) = (Arc::clone(&agg_shared), alarms.clone());
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            ticks.tick().await;
+            let raised = alarm_watch.check(&*watched.read().await);
+            for event in raised {
+                watch_alarms.raise(event);
+            }
+        }
+    });
+    let reloader = Arc::new(
+        ConfigReloader::new(
+            args.config.clone(),
+            &symbol,
+            app_config,
+            Arc::clone(&agg_shared),
+        )
+        .with_env(env)
+        .with_log_level_hook(move |level| {
+            level_handle
+                .modify(|filter| *filter = level)
+                .map_err(|e| e.to_string())
+        }),
+    );
+    // Optional reference price for converting notionals, e.g. btcusdt for an ethbtc book
+    let converter = args.notional_reference.as_ref().map(|reference| {
+        let mid = Arc::new(ReferenceMid::new(
+            &args.notional_currency,
+            clock.clone(),
+            Duration::from_millis(args.notional_max_age_ms),
+        ));
+        let (follower, reference, endpoint) = (
+            Arc::clone(&mid),
+            reference.clone(),
+            binance_endpoint.clone(),
+        );
+        tokio::spawn(async move { follower.follow_binance(&reference, &endpoint).await });
+        mid
+    });
+    let breaker_config = CircuitBreakerConfig {
+        failure_threshold: args.breaker_failures,
+        window: Duration::from_secs(args.breaker_window_secs),
+        cool_down: Duration::from_secs(args.breaker_cool_down_secs),
+    };
+
+    let quarantine_config = QuarantineConfig {
+        max_stale: args.quarantine_stale_updates,
+        window: Duration::from_secs(args.quarantine_window_secs),
+        cool_down: Duration::from_secs(args.quarantine_cool_down_secs),
+    };
+    let dedup = args.dedup_heartbeat_ms.map(|heartbeat_ms| DedupConfig {
+        heartbeat: Duration::from_millis(heartbeat_ms),
+        poll_interval: Duration::from_millis(args.dedup_poll_ms),
+    });
+
+    // Report suppressed warnings even once their repeats stop
+    let limiter = Arc::clone(&status.log_limiter);
+    if !limiter.window().is_zero() {
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(limiter.window());
+            loop {
+                ticks.tick().await;
+                limiter.flush();
+            }
+        });
+    }
+
+    // Sample each exchange's update rate into its series once a second
+    let sampled = Arc::clone(&status);
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            ticks.tick().await;
+            for (exchange, rate) in sampled.rates.all() {
+                sampled
+                    .timeseries
+                    .record("update_rate", &exchange, rate.last_second);
+            }
+        }
+    });
+
+    // Re-read the config file on SIGHUP
+    let mut hangup = signal(SignalKind::hangup())
+        .map_err(|e| ExitReason::Other(format!("failed to install SIGHUP handler: {}", e)))?;
+    let reloader_for_signal = Arc::clone(&reloader);
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            match reloader_for_signal.reload().await {
+                Ok(diff) => {
+                    tracing::info!("Config reloaded: applied {:?}", diff.reloadable);
+                    if !diff.requires_restart.is_empty() {
+                        tracing::warn!(
+                            "Config changes not applied, restart required: {:?}",
+                            diff.requires_restart
+                        );
+                    }
+                }
+                Err(e) => tracing::error!("Config reload failed, keeping current config: {}", e),
+            }
+        }
     });
 
-    // Start WebSocket processing
+    // Phase 3: spawn the connectors. They report each exchange's first merged snapshot
     let agg_for_websocket = Arc::clone(&agg_shared);
+    let status_for_grpc = Arc::clone(&status);
 
     // Listen to the combined stream and handle the updates
+    let quarantine_alarms = alarms.clone();
     let websocket_task = tokio::spawn(async move {
+        let mut venues: Vec<Venue> = venues
+            .into_iter()
+            .map(|(connector, instrument)| {
+                let name = connector.exchange().as_str();
+                Venue {
+                    breaker: CircuitBreaker::new(name, clock.clone(), breaker_config),
+                    sync: SyncTracker::new(name),
+                    commands: Some(status.commands.register(name, &symbol)),
+                    control: FeedControl::default(),
+                    connector,
+                    instrument,
+                }
+            })
+            .collect();
+        let mut quarantine =
+            Quarantine::new(clock.clone(), quarantine_config).with_alarms(quarantine_alarms);
+        let mut update_age = UpdateAgeGuard::new(clock.clone(), max_update_age);
+        let mut bitstamp_cross_check = cross_check.map(CrossChecker::new);
+        let mut straggler_retry = StragglerRetry::new(
+            clock.clone(),
+            Duration::from_secs(2),
+            Duration::from_secs(60),
+        );
+        let mut first_attempt = true;
         loop {
-            // Connect to streams first to avoid missing updates
-            tracing::info!("Connecting to exchange streams...");
-            let (_bitstamp_sink, bitstamp_stream) =
-                modules::bitstamp::get_bitstamp_stream(&symbol).await;
-            let (_binance_sink, binance_stream) =
-                modules::binance::get_binance_stream(&symbol).await;
-
-            // Then fetch fresh snapshots concurrently and merge
+            // Exchanges with an open circuit are skipped until their cool-down has passed
+            // and a feed that still has a live connection is never connected twice.
+            // Exchanges paused or shut down are never attempted, and a quarantined one waits
+            // out its cool-down.
+            let claims: Vec<_> = venues
+                .iter_mut()
+                .map(|venue| {
+                    let name = venue.exchange().as_str();
+                    (venue.control.active()
+                        && venue.breaker.allow_attempt()
+                        && !quarantine.cooling_down(name))
+                    .then(|| status.connections.claim(name, &symbol))
+                    .flatten()
+                })
+                .collect();
+            let allowed: Vec<bool> = venues
+                .iter()
+                .zip(&claims)
+                .map(|(venue, claim)| claim.is_some() && venue.sync.begin_sync())
+                .collect();
+            for (venue, &allowed) in venues.iter().zip(&allowed) {
+                if allowed {
+                    let name = venue.exchange().as_str();
+                    status.set_connection(name, ConnectionState::Connecting);
+                    if !first_attempt {
+                        status.counters.record_reconnect(name);
+                    }
+                }
+            }
+            first_attempt = false;
+            // Exchanges resyncing together publish the book once, not once per step
+            let resyncing: Vec<&str> = venues
+                .iter()
+                .zip(&allowed)
+                .filter_map(|(venue, &allowed)| allowed.then_some(venue.exchange().as_str()))
+                .collect();
+            if resyncing.len() > 1 {
+                agg_for_websocket
+                    .write()
+                    .await
+                    .hold_for_resync(resyncing, resync_publish_timeout);
+            }
+
+            // Awaited after a subscription ack; Binance's first frame is already data
+            let first_data = agg_for_websocket
+                .read()
+                .await
+                .config
+                .settings
+                .first_data_timeout_ms
+                .map(Duration::from_millis)
+                .or(first_data_timeout);
+
+            // Connect and fetch fresh snapshots for the enabled exchanges concurrently
             let snapshot_start = Instant::now();
-            tracing::info!("Fetching fresh snapshots in parallel after connecting streams...");
-            let (binance_snapshot, bitstamp_snapshot) = tokio::join!(
-                modules::binance::get_binance_snapshot(&symbol),
-                modules::bitstamp::get_bitstamp_snapshot(&symbol)
-            );
+            tracing::info!("Connecting to exchange streams and fetching snapshots...");
+            let outcomes = {
+                let (symbol, status, limits) = (symbol.as_str(), &status, &payload_limits);
+                let attempts =
+                    venues
+                        .iter_mut()
+                        .zip(&allowed)
+                        .map(|(venue, &allowed)| async move {
+                            if !allowed {
+                                return None;
+                            }
+                            Some(
+                                connect_and_snapshot(
+                                    venue.connector.as_mut(),
+                                    &venue.instrument,
+                                    symbol,
+                                    status,
+                                    (limits, handshake_timeout, first_data),
+                                )
+                                .await,
+                            )
+                        });
+                join_all(attempts).await
+            };
             tracing::info!(
-                "Snapshots fetched in parallel in {}ms",
+                "Connect/sync attempts finished in {}ms",
                 snapshot_start.elapsed().as_millis()
             );
-            {
+            // The merge would skip a snapshot older than what the book already applied, so it
+            // fails the sync and the breaker, quarantine and status count it like any other
+            let outcomes: Vec<_> = {
+                let agg = agg_for_websocket.read().await;
+                venues
+                    .iter()
+                    .zip(outcomes)
+                    .map(|(venue, outcome)| match outcome {
+                        Some(Ok(synced)) => {
+                            match agg.stale_snapshot(venue.exchange(), &synced.snapshot) {
+                                Some(SkipReason::Stale { applied, snapshot }) => {
+                                    Some(Err(format!(
+                                        "snapshot {} is older than the applied update {}",
+                                        snapshot, applied
+                                    )))
+                                }
+                                None => Some(Ok(synced)),
+                            }
+                        }
+                        outcome => outcome,
+                    })
+                    .collect()
+            };
+            let mut synced = Vec::with_capacity(venues.len());
+            for (venue, outcome) in venues.iter_mut().zip(outcomes) {
+                synced.push(
+                    settle_attempt(
+                        venue.exchange(),
+                        outcome,
+                        &mut venue.breaker,
+                        &mut quarantine,
+                        &status,
+                        &agg_for_websocket,
+                    )
+                    .await,
+                );
+            }
+            // A resync requested while a snapshot was in flight supersedes it: sync again
+            // straight away rather than merging a snapshot that is already out of date
+            let mut resync_pending = false;
+            for ((venue, &allowed), synced) in venues.iter().zip(&allowed).zip(&synced) {
+                if allowed {
+                    resync_pending |= venue.sync.finish_sync(synced.is_some());
+                }
+                if allowed && synced.is_none() {
+                    agg_for_websocket
+                        .write()
+                        .await
+                        .end_resync(venue.exchange().as_str());
+                }
+            }
+            if resync_pending {
+                tracing::info!("Resync requested during sync, fetching fresh snapshots");
+                continue;
+            }
+            // A fresh snapshot starts a session on its connection and ends a quarantine
+            let mut snapshots = Vec::new();
+            let mut connections = Vec::with_capacity(venues.len());
+            for (venue, synced) in venues.iter_mut().zip(synced) {
+                let Some(synced) = synced else {
+                    connections.push(None);
+                    continue;
+                };
+                venue.connector.start_session(&synced.snapshot);
+                let exchange = venue.exchange();
+                if quarantine.is_quarantined(exchange.as_str()) {
+                    quarantine.release(exchange.as_str());
+                    report_quarantine(exchange, &quarantine, &status);
+                }
+                snapshots.push(synced.snapshot);
+                connections.push(Some((synced.sink, synced.stream, synced.ping_interval)));
+            }
+            let merged: Vec<&str> = venues
+                .iter()
+                .zip(&connections)
+                .filter_map(|(venue, connection)| {
+                    connection.is_some().then_some(venue.exchange().as_str())
+                })
+                .collect();
+            let any_synced = !snapshots.is_empty();
+            if any_synced {
                 let mut agg = agg_for_websocket.write().await;
-                agg.merge_snapshots(vec![bitstamp_snapshot, binance_snapshot]);
-                tracing::info!("Snapshots merged into aggregated orderbook");
-            }
-
-            // Tag streams by source and combine
-            let bitstamp_tagged = bitstamp_stream.map(|m| (Exchange::Bitstamp.as_str(), m));
-            let binance_tagged = binance_stream.map(|m| (Exchange::Binance.as_str(), m));
-            let mut combined = select(bitstamp_tagged, binance_tagged);
-
-            tracing::info!("Connected to exchanges");
-
-            while let Some((source, msg_result)) = combined.next().await {
-                match msg_result {
-                    Ok(msg) => match source {
-                        "bitstamp" => match msg {
-                            Message::Text(text) => {
-                                if let Some(update) = OrderBookUpdate::from_bitstamp_json(&text) {
-                                    tracing::info!(
-                                        "Received Bitstamp update: {:?} bids, {:?} asks (ID: {})",
-                                        update.bids.len(),
-                                        update.asks.len(),
-                                        update.update_id
-                                    );
-                                    // tracing::info!("Received Bitstamp update: {:?}", update);
-                                    let bitstamp_update_start = Instant::now();
-                                    let res = {
-                                        let mut agg = agg_for_websocket.write().await;
-                                        agg.handle_update(update)
-                                    };
-                                    match res {
-                                        Ok(_) => {
-                                            // tracing::info!(
-                                            //     "Bitstamp update took {}ms to apply successfully",
-                                            //     bitstamp_update_start.elapsed().as_millis()
-                                            // );
-                                        }
-                                        Err(e) => {
-                                            tracing::error!(
-                                                "Bitstamp update failed after {}ms: {}",
-                                                bitstamp_update_start.elapsed().as_millis(),
-                                                e
-                                            );
-                                        }
+                let report = agg.merge_snapshots(snapshots);
+                for (exchange, stats) in &report.per_exchange {
+                    status.counters.record_merge(exchange.as_str(), stats);
+                    tracing::info!(
+                        exchange = exchange.as_str(),
+                        inserted = stats.inserted,
+                        replaced = stats.replaced,
+                        removed = stats.removed,
+                        skipped = ?stats.skipped_reason,
+                        "Snapshot merged"
+                    );
+                }
+                // Also ends the resync of an exchange whose snapshot had no levels
+                for exchange in &merged {
+                    agg.end_resync(exchange);
+                }
+                for exchange in agg.last_update_id.keys() {
+                    status.set_contributing(exchange, true);
+                }
+                for exchange in &merged {
+                    status.startup.synced(exchange);
+                }
+            }
+            // Exchanges that didn't sync while others stream are retried on their own schedule
+            let stragglers: Vec<(&str, &CircuitBreaker)> = venues
+                .iter()
+                .filter(|venue| {
+                    let name = venue.exchange().as_str();
+                    venue.control.active()
+                        && !merged.contains(&name)
+                        && !quarantine.cooling_down(name)
+                })
+                .map(|venue| (venue.exchange().as_str(), &venue.breaker))
+                .collect();
+            if any_synced
+                && let Some(delay) =
+                    straggler_retry.after_pass(stragglers.iter().map(|(_, breaker)| *breaker))
+            {
+                tracing::info!(
+                    "Retrying {:?} in {} seconds",
+                    stragglers
+                        .iter()
+                        .map(|(exchange, _)| exchange)
+                        .collect::<Vec<_>>(),
+                    delay.as_secs()
+                );
+            }
+
+            // Decouple socket reads from book updates with bounded per-exchange queues.
+            // An exchange that didn't sync gets no reader, so its queue stream ends right away.
+            // A connection that must be pinged hands its sink to the pings; the others keep
+            // theirs, e.g. to resubscribe on a sequence gap.
+            let mut queues = Vec::with_capacity(venues.len());
+            let mut sinks: Vec<Option<WsSink>> = Vec::with_capacity(venues.len());
+            let mut tagged = Vec::with_capacity(venues.len());
+            let mut tasks = Vec::new();
+            for (index, (venue, connection)) in venues.iter().zip(connections).enumerate() {
+                let (tx, rx) = update_queue::bounded(queue_capacity, overflow_policy);
+                queues.push(rx.handle());
+                let mut kept = None;
+                if let Some((sink, stream, ping_interval)) = connection {
+                    let reader = tokio::spawn(update_queue::forward(stream, tx, clock.clone()));
+                    tasks.push(reader.abort_handle());
+                    match ping_interval {
+                        Some(interval) => {
+                            let pings = tokio::spawn(venue.connector.keep_alive(sink, interval));
+                            tasks.push(pings.abort_handle());
+                        }
+                        None => kept = Some(sink),
+                    }
+                }
+                sinks.push(kept);
+                // Tag streams by source and combine
+                tagged.push(
+                    rx.into_stream()
+                        .map(move |(received_at, m)| (index, received_at, m))
+                        .boxed(),
+                );
+            }
+            let mut combined = stream::select_all(tagged);
+
+            if any_synced {
+                tracing::info!("Connected to exchanges");
+            }
+            // Exchanges gone quiet are found on a timer, not per message, so that a silent
+            // feed is caught even when nothing else arrives
+            let mut staleness_check = tokio::time::interval(STALENESS_CHECK_INTERVAL);
+            staleness_check.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+            // Resyncs and reconnects asked for by command skip the reconnect delay
+            let mut immediate = false;
+            // An exchange that restarted its update ids, to rebuild from a snapshot
+            let mut sequence_reset: Option<Exchange> = None;
+            loop {
+                // Pending commands go first, so none is lost when the streams end
+                let (index, received_at, msg_result) = tokio::select! {
+                    biased;
+                    (index, command) = next_command(&mut venues) => {
+                        let venue = &mut venues[index];
+                        let action = on_command(
+                            venue.exchange(),
+                            command,
+                            &mut venue.control,
+                            &status,
+                            &agg_for_websocket,
+                        )
+                        .await;
+                        if action == CommandAction::Stop {
+                            venue.commands = None;
+                        }
+                        if action == CommandAction::Continue {
+                            continue;
+                        }
+                        immediate = true;
+                        break;
+                    }
+                    _ = staleness_check.tick() => {
+                        if expire_stale_exchanges(&agg_for_websocket, stale_after, &status).await {
+                            break;
+                        }
+                        continue;
+                    }
+                    next = combined.next() => match next {
+                        Some(next) => next,
+                        None => break,
+                    },
+                };
+                if !venues[index].control.active() {
+                    continue;
+                }
+
+                // A dropped message leaves a gap in the sequence, so rebuild from fresh snapshots
+                let overflowed = venues
+                    .iter()
+                    .zip(&queues)
+                    .find(|(_, queue)| queue.take_resync());
+                if let Some((venue, queue)) = overflowed {
+                    let stats = queue.stats();
+                    tracing::warn!(
+                        "{} update queue overflowed ({} dropped so far, capacity {}), resyncing",
+                        venue.exchange().as_str(),
+                        stats.overflow_count,
+                        stats.capacity
+                    );
+                    break;
+                }
+
+                // Same for updates dropped as too old to apply
+                let too_old = update_age.take_resync();
+                if !too_old.is_empty() {
+                    tracing::warn!(
+                        "Updates from {:?} dropped as older than {}ms, resyncing",
+                        too_old,
+                        update_age.max_age().as_millis()
+                    );
+                    break;
+                }
+
+                // Resync to let an exchange whose cool-down elapsed probe its connection
+                if venues.iter().any(|venue| venue.breaker.probe_due()) {
+                    tracing::info!("Circuit cool-down elapsed, resyncing to probe");
+                    break;
+                }
+                if quarantine.resync_due() {
+                    tracing::info!("Quarantine cool-down elapsed, resyncing");
+                    break;
+                }
+                if let Some(exchange) = sequence_reset {
+                    tracing::info!(
+                        "{} restarted its sequence, resyncing for a full book in the new one",
+                        exchange.as_str()
+                    );
+                    break;
+                }
+                if straggler_retry.due() {
+                    tracing::info!("Retrying exchanges that did not sync, resyncing");
+                    break;
+                }
+
+                let venue = &mut venues[index];
+                let source = venue.exchange();
+                let name = source.as_str();
+                let routed = match msg_result {
+                    Ok(msg) => {
+                        if let Message::Text(text) = &msg {
+                            match venue.connector.screen(text, &venue.instrument) {
+                                Screen::Pass => {}
+                                Screen::Warn(warning) => {
+                                    if status.log_limiter.allow(&format!("{} screen", name)) {
+                                        tracing::warn!(exchange = name, "{}", warning);
                                     }
                                 }
+                                Screen::Drop => {
+                                    if status.log_limiter.allow(&format!("{} screen", name)) {
+                                        tracing::debug!(
+                                            exchange = name,
+                                            "Dropping a frame not for the book followed here"
+                                        );
+                                    }
+                                    continue;
+                                }
                             }
-                            Message::Ping(_payload) => {
-                                tracing::debug!("Received ping from Bitstamp, sending pong");
-                                // Note: tungstenite handles pong automatically for ping frames
-                            }
-                            Message::Pong(_) => {
-                                tracing::debug!("Received pong from Bitstamp");
-                            }
-                            Message::Close(_) => {
-                                tracing::warn!("Bitstamp connection closed, will reconnect");
-                                break; // Exit inner loop to reconnect
-                            }
-                            _ => {}
-                        },
-                        "binance" => match msg {
-                            Message::Text(text) => {
-                                if let Some(update) = OrderBookUpdate::from_binance_json(&text) {
-                                    tracing::info!(
-                                        "Received Binance update: {:?} bids, {:?} asks (ID: {})",
-                                        update.bids.len(),
-                                        update.asks.len(),
-                                        update.update_id
-                                    );
-                                    // tracing::info!("Received Binance update: {:?}", update);
-                                    let binance_update_start = Instant::now();
-                                    let res = {
-                                        let mut agg = agg_for_websocket.write().await;
-                                        agg.handle_update(update)
-                                    };
-                                    match res {
-                                        Ok(_) => {
-                                            // tracing::info!(
-                                            //     "Binance update took {}ms to apply successfully",
-                                            //     binance_update_start.elapsed().as_millis()
-                                            // );
-                                        }
-                                        Err(e) => {
-                                            tracing::error!(
-                                                "Binance update failed after {}ms: {}",
-                                                binance_update_start.elapsed().as_millis(),
-                                                e
-                                            );
-                                        }
+                        }
+                        venue.connector.route(msg)
+                    }
+                    Err(e) => {
+                        if let WsError::Capacity(reason) = &e {
+                            status.record_oversized_frame(name);
+                            tracing::warn!(
+                                exchange = name,
+                                "Message over the size limit ({}), dropping the connection",
+                                reason
+                            );
+                        }
+                        tracing::error!("{} stream error: {}, will reconnect", name, e);
+                        break; // Exit inner loop to reconnect
+                    }
+                };
+                match routed {
+                    RoutedMessage::Update(mut update) => {
+                        record_message(source, &status);
+                        match venue.connector.check_update(&update) {
+                            Verdict::Apply => {}
+                            Verdict::Skip => continue,
+                            Verdict::Resubscribe(gap) => {
+                                tracing::warn!(exchange = name, "{}, resubscribing", gap);
+                                let resubscribed = match sinks[index].as_mut() {
+                                    Some(sink) => {
+                                        venue.connector.resubscribe(sink, &venue.instrument).await
                                     }
+                                    None => Err(format!("{} has no connection", name)),
+                                };
+                                if let Err(e) = resubscribed {
+                                    tracing::error!("{}, will reconnect", e);
+                                    break;
                                 }
+                                continue;
                             }
-                            Message::Ping(_payload) => {
-                                tracing::debug!("Received ping from Binance, sending pong");
+                            Verdict::Resync(gap) => {
+                                tracing::warn!(exchange = name, "{}, resyncing", gap);
+                                break;
                             }
-                            Message::Pong(_) => {
-                                tracing::debug!("Received pong from Binance");
+                        }
+                        payload_limits.cap_update(&mut update, &status);
+                        update.received_at = Some(received_at);
+                        tracing::info!(
+                            "Received {} update: {:?} bids, {:?} asks (ID: {})",
+                            name,
+                            update.bids.len(),
+                            update.asks.len(),
+                            update.update_id
+                        );
+                        if drop_if_too_old(source, &update, &mut update_age, &status) {
+                            continue;
+                        }
+                        let levels = (update.bids.len(), update.asks.len());
+                        let (res, timing) = latency::timed_write(&agg_for_websocket, |agg| {
+                            quarantine.apply(agg, update)
+                        })
+                        .await;
+                        check_apply_latency(source, levels, timing, &status);
+                        status.counters.record_update(
+                            name,
+                            matches!(res, Ok(Admission::Applied | Admission::SequenceReset)),
+                        );
+                        match res {
+                            Ok(Admission::Quarantined) => {
+                                report_quarantine(source, &quarantine, &status)
                             }
-                            Message::Close(_) => {
-                                tracing::warn!("Binance connection closed, will reconnect");
-                                break; // Exit inner loop to reconnect
+                            Ok(Admission::SequenceReset) => sequence_reset = Some(source),
+                            Ok(_) => {}
+                            Err(e) => {
+                                if status
+                                    .log_limiter
+                                    .allow(&format!("{} update failed after apply", name))
+                                {
+                                    tracing::error!(
+                                        "{} update failed after {}ms: {}",
+                                        name,
+                                        timing.total().as_millis(),
+                                        e
+                                    );
+                                }
                             }
-                            _ => {}
-                        },
-                        _ => {}
-                    },
-                    Err(e) => {
-                        tracing::error!("{} stream error: {}, will reconnect", source, e);
+                        }
+                    }
+                    RoutedMessage::Snapshot(mut book) => {
+                        record_message(source, &status);
+                        // Coinbase's streamed snapshot has no time: its arrival stands in
+                        if book.last_update_id == 0 {
+                            book.last_update_id = received_at * 1_000;
+                        }
+                        let mut agg = agg_for_websocket.write().await;
+                        // A snapshot that restarts the sequence is the book even when its id
+                        // went backwards, as after a restart of Bybit's service
+                        if venue.connector.on_streamed_snapshot(&book) {
+                            agg.last_update_id.remove(name);
+                        }
+                        let report = agg.merge_snapshots(vec![book]);
+                        drop(agg);
+                        for (exchange, stats) in &report.per_exchange {
+                            status.counters.record_merge(exchange.as_str(), stats);
+                            tracing::info!(
+                                exchange = exchange.as_str(),
+                                inserted = stats.inserted,
+                                replaced = stats.replaced,
+                                removed = stats.removed,
+                                "Streamed snapshot merged"
+                            );
+                        }
+                    }
+                    RoutedMessage::FullBook(text) => {
+                        if let Some(checker) = bitstamp_cross_check.as_mut()
+                            && cross_check_bitstamp(
+                                checker,
+                                &text,
+                                &status,
+                                &agg_for_websocket,
+                                clock.now_millis(),
+                            )
+                            .await
+                        {
+                            break;
+                        }
+                    }
+                    RoutedMessage::ParseFailure { reason, text } => {
+                        record_message(source, &status);
+                        status
+                            .parse_failures
+                            .record(name, received_at, &reason, &text);
+                    }
+                    RoutedMessage::Control(kind) if kind.ends_connection() => {
+                        tracing::warn!("{} connection ended ({:?}), will reconnect", name, kind);
                         break; // Exit inner loop to reconnect
                     }
+                    // Pings are answered by tungstenite
+                    RoutedMessage::Control(kind) => {
+                        tracing::debug!("Received {:?} from {}", kind, name);
+                    }
+                    RoutedMessage::Ignored => {}
+                }
+            }
+
+            for task in tasks {
+                task.abort();
+            }
+            drop(claims);
+            for (venue, queue) in venues.iter().zip(&queues) {
+                let overflows = queue.stats().overflow_count;
+                if overflows > 0 {
+                    status.record_queue_overflows(venue.exchange().as_str(), overflows);
                 }
             }
 
+            if immediate {
+                tracing::info!("Reconnecting to exchanges now");
+                continue;
+            }
             // Reconnection delay
             tracing::info!("Reconnecting to exchanges in 2 seconds...");
-            tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+            tokio::time::sleep(Duration::from_secs(2)).await;
         }
     });
 
-    // Wait for either task to complete
+    // Phase 4: wait for every exchange to sync, up to the startup deadline, or a connector
+    // failure. Past the deadline, serve what synced and keep retrying the rest.
+    let mut websocket_task = websocket_task;
     tokio::select! {
-        _ = grpc_server => {
-            tracing::info!("gRPC server stopped");
+        readiness = status_for_grpc.startup.wait(startup_timeout) => match readiness {
+            Readiness::Degraded { failed } => {
+                tracing::warn!(
+                    "Serving degraded: not synced within {}s: {}",
+                    startup_timeout.as_secs(),
+                    startup_failures(&failed, &status_for_grpc)
+                );
+            }
+            Readiness::Failed { failed } => {
+                websocket_task.abort();
+                return Err(ExitReason::StartupDeadline(format!(
+                    "no exchange data within {}s of startup, not serving: {}",
+                    startup_timeout.as_secs(),
+                    startup_failures(&failed, &status_for_grpc)
+                )));
+            }
+            Readiness::Ready | Readiness::Starting => tracing::info!("Connectors ready"),
+        },
+        result = &mut websocket_task => {
+            return Err(ExitReason::Connector(match result {
+                Err(e) if e.is_panic() => "connector task panicked during startup".to_string(),
+                _ => "connector task stopped during startup".to_string(),
+            }));
         }
-        _ = websocket_task => {
-            tracing::info!("WebSocket processing stopped");
+    }
+
+    // Phase 5: serve requests, over stdin/stdout or gRPC
+    if args.stdio {
+        let handlers = Handlers::new(Arc::clone(&agg_shared), Arc::clone(&status_for_grpc))
+            .with_dedup(dedup)
+            .with_one_sided(one_sided)
+            .with_consistency(consistency);
+        tracing::info!("Serving JSON requests on stdin/stdout");
+        // The parent closing stdin is a normal shutdown
+        return tokio::select! {
+            result = stdio_service::serve(
+                handlers,
+                BufReader::new(tokio::io::stdin()),
+                tokio::io::stdout(),
+            ) => result.map(|()| "stdin closed").map_err(ExitReason::Other),
+            result = websocket_task => Err(connector_stopped(result)),
+        };
+    }
+
+    let mut service = OrderbookAggregatorService::new(Arc::clone(&agg_shared))
+        .with_status(status_for_grpc)
+        .with_reloader(Arc::clone(&reloader))
+        .with_one_sided(one_sided)
+        .with_consistency(consistency);
+    if let Some(dedup) = dedup {
+        service = service.with_dedup(dedup);
+    }
+    if let Some(converter) = converter {
+        service = service.with_converter(converter);
+    }
+    if args.stamp_build_id {
+        service = service.with_build_id_stamp();
+    }
+    if !admin_enabled {
+        tracing::info!("gRPC Admin service disabled");
+    }
+
+    // Every public listener is bound before any serves, so one that can't bind fails startup.
+    // Admin joins them unless it has a listener of its own.
+    let bound = match grpc_listeners::bind_all(&grpc_listeners).await {
+        Ok(bound) => bound,
+        Err(e) => {
+            let unavailable = AlarmEvent::GrpcUnavailable { reason: e.clone() };
+            alarms
+                .raise_and_wait(unavailable, ALARM_FLUSH_TIMEOUT)
+                .await;
+            return Err(ExitReason::GrpcBind(e));
         }
+    };
+    let mut grpc_servers = JoinSet::new();
+    for listener in bound {
+        let router = create_grpc_server_with_auth(
+            service.clone(),
+            admin_enabled && admin_listener.is_none(),
+            listener.config.auth_token.clone(),
+        );
+        grpc_servers.spawn(listener.serve(router));
     }
+    let admin_server = tokio::spawn(async move {
+        match admin_listener {
+            Some(listen) => serve_admin(service, listen).await,
+            None => std::future::pending().await,
+        }
+    });
+
+    let mut terminate = signal(SignalKind::terminate())
+        .map_err(|e| ExitReason::Other(format!("failed to install SIGTERM handler: {}", e)))?;
+
+    // Run until a signal or until any task stops; a stopped task is fatal
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => Ok("SIGINT"),
+        _ = terminate.recv() => Ok("SIGTERM"),
+        Some(result) = grpc_servers.join_next() => {
+            let reason = match result {
+                Ok(Ok(())) => "gRPC server stopped".to_string(),
+                Ok(Err(e)) => e,
+                Err(e) => format!("gRPC server task failed: {}", e),
+            };
+            let unavailable = AlarmEvent::GrpcUnavailable { reason: reason.clone() };
+            alarms.raise_and_wait(unavailable, ALARM_FLUSH_TIMEOUT).await;
+            Err(ExitReason::Other(reason))
+        }
+        result = admin_server => match result {
+            Ok(Ok(())) => Err(ExitReason::Other("gRPC Admin server stopped".to_string())),
+            Ok(Err(reason)) => Err(reason),
+            Err(e) => Err(ExitReason::Other(format!("gRPC Admin server task failed: {}", e))),
+        },
+        result = websocket_task => Err(connector_stopped(result)),
+    }
+}
 
-    Ok(())
+fn connector_stopped(result: Result<(), tokio::task::JoinError>) -> ExitReason {
+    ExitReason::Connector(match result {
+        Err(e) if e.is_panic() => "connector task panicked".to_string(),
+        _ => "connector task stopped".to_string(),
+    })
 }
@@ -1,215 +1,1490 @@
-use crate::modules::types::{AggregatedOrderBook, OrderBook, OrderBookUpdate, OrderLevel};
-use std::collections::{BTreeMap, HashMap, HashSet};
+use crate::config::{BookSettings, SymbolConfig};
+use crate::modules::binance::BINANCE_SNAPSHOT_DEPTH;
+use crate::modules::bitfinex::BITFINEX_DEPTH;
+use crate::modules::bitstamp::BITSTAMP_DIFF_DEPTH;
+use crate::modules::bybit::BYBIT_DEPTH;
+use crate::modules::checksum;
+use crate::modules::clock::{SharedClock, system_clock};
+use crate::modules::data_quality::{DataQuality, QualityTracker, assess};
+use crate::modules::gateio::GATEIO_SNAPSHOT_DEPTH;
+use crate::modules::kraken::KRAKEN_DEPTH;
+use crate::modules::kucoin::KUCOIN_SNAPSHOT_DEPTH;
+use crate::modules::log_limiter::{DEFAULT_SUPPRESSION_WINDOW, LogLimiter};
+use crate::modules::okx::OKX_DEPTH;
+use crate::modules::quote::{NotionalUnit, QuoteConverter};
+use crate::modules::sequence_reset::SequenceResetConfig;
+use crate::modules::smart_best::smart_best;
+use crate::modules::spread_convention::SpreadConvention;
+use crate::modules::stats::{
+    BookShape, BookStats, ExcludedLevels, ImprovementSample, PriceImprovement, ShapeSample,
+    SideImprovement, SideShape, StatsHistory, StatsSample,
+};
+use crate::modules::throttle::{ThrottleConfig, ThrottledGauge};
+use crate::modules::timeseries::{Retention, TimeSeriesStore};
+use crate::modules::traded_estimate::{TradedVolumeEstimator, traded_through};
+use crate::modules::types::{
+    AggregatedOrderBook, Exchange, OrderBook, OrderBookUpdate, OrderLevel, Side,
+};
+use async_stream::stream;
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
 
-const PRICE_SCALE: f64 = 1_000_000_000.0;
+#[cfg(test)]
+const PRICE_SCALE: f64 = crate::config::DEFAULT_PRICE_SCALE;
 
-#[derive(Clone, Debug)]
+/// Bumped whenever the serialized `Top10Snapshot` shape changes incompatibly
+pub const SNAPSHOT_SCHEMA_VERSION: u32 = 1;
+
+/// Relative difference between a stored price and its bucket's index-derived price above
+/// which debug builds report drift
+pub const PRICE_DRIFT_EPSILON: f64 = 1e-12;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BookState {
+    Normal,
+    /// Best bid at or above best ask
+    Crossed,
+    /// At least one side of the book is empty
+    #[default]
+    Degraded,
+}
+
+/// Why a snapshot was published, or sent on a stream
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EmissionReason {
+    /// An applied diff, or the exchange's levels being dropped (no update id)
+    #[serde(rename_all = "camelCase")]
+    BookChange {
+        exchange: String,
+        update_id: Option<u64>,
+    },
+    /// Unchanged, sent by a deduplicating stream so the client can tell it is alive
+    Heartbeat,
+    /// The book as it was built, or the first snapshot of a stream or of a new generation
+    #[default]
+    InitialSnapshot,
+    /// Snapshots merged, or changes held during a resync published
+    Resync,
+    /// Reloaded settings applied
+    ConfigChange,
+    /// The book was cleared
+    Reset,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpdateOutcome {
+    Applied,
+    /// Update id not newer than the last applied one; the book is unchanged
+    Stale,
+    /// The id went far backwards on newer data: the exchange restarted its sequence. Its
+    /// levels were cleared and the update applied as the new baseline.
+    SequenceReset,
+}
+
+/// Why a snapshot's levels for an exchange were not merged
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The snapshot is older than data already applied for the exchange
+    Stale { applied: u64, snapshot: u64 },
+}
+
+/// What merging snapshots did to one exchange's entries
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MergeStats {
+    /// Entries the exchange didn't have at that price
+    pub inserted: u64,
+    /// Entries whose amount the snapshot overwrote
+    pub replaced: u64,
+    /// Entries removed by a zero (or dust) amount in the snapshot, or left out of it
+    pub removed: u64,
+    pub skipped_reason: Option<SkipReason>,
+}
+
+/// Returned by `merge_snapshots`, per exchange found in the snapshots
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    pub per_exchange: HashMap<Exchange, MergeStats>,
+}
+
+/// What `upsert_level` did to the exchange's entry at the level's price
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Upsert {
+    Inserted,
+    Replaced,
+    Removed,
+    /// A removal for an entry the exchange didn't have
+    Absent,
+}
+
+impl MergeStats {
+    fn record(&mut self, upsert: Upsert) {
+        match upsert {
+            Upsert::Inserted => self.inserted += 1,
+            Upsert::Replaced => self.replaced += 1,
+            Upsert::Removed => self.removed += 1,
+            Upsert::Absent => {}
+        }
+    }
+}
+
+/// How the first diff after a snapshot is treated when its final id equals the snapshot's.
+/// The REST and stream pipelines aren't perfectly consistent, so such a diff may carry
+/// changes the snapshot doesn't reflect yet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BoundaryPolicy {
+    /// Only diffs ending past the snapshot's id are applied
+    Strict,
+    /// Also apply the diff if it overlaps the snapshot (`U <= id == u`); later diffs
+    /// must still be strictly newer
+    ApplyIfOverlapping,
+}
+
+impl BoundaryPolicy {
+    /// Binance's and Gate.io's diffs carry the `U..u` range they cover, so an overlapping
+    /// diff can be recognized. Bitstamp ids are microtimestamps with no range, so only newer ones count.
+    /// Kraken, Coinbase and Bitfinex ids are timestamps of levels, messages or frames, which a
+    /// later frame can repeat. OKX's and Bybit's channel snapshots are current to their id, so only newer
+    /// updates count, as on KuCoin, whose deltas ending at the snapshot's sequence are in it.
+    pub fn documented_for(exchange: Exchange) -> Self {
+        match exchange {
+            Exchange::Binance
+            | Exchange::BinanceUs
+            | Exchange::GateIo
+            | Exchange::Kraken
+            | Exchange::Coinbase
+            | Exchange::Bitfinex => BoundaryPolicy::ApplyIfOverlapping,
+            Exchange::Bitstamp | Exchange::Okx | Exchange::Bybit | Exchange::Kucoin => {
+                BoundaryPolicy::Strict
+            }
+        }
+    }
+}
+
+impl FromStr for BoundaryPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('_', "-").as_str() {
+            "strict" => Ok(BoundaryPolicy::Strict),
+            "apply-if-overlapping" => Ok(BoundaryPolicy::ApplyIfOverlapping),
+            other => Err(format!(
+                "unknown boundary policy '{}' (expected strict or apply-if-overlapping)",
+                other
+            )),
+        }
+    }
+}
+
+/// Levels per side of an exchange's own book that its feed keeps current, by rank from its
+/// best price; `None` where the whole book is. Deeper levels are never reliably removed, so
+/// the book doesn't keep them. Binance, KuCoin and Gate.io are known as deep as their REST
+/// snapshots, Bitstamp's diff channel covers its top 100 and Kraken, OKX, Bybit and Bitfinex
+/// send their subscribed depth. Coinbase's level2 channel covers the whole book.
+pub fn authoritative_depth(exchange: Exchange) -> Option<usize> {
+    match exchange {
+        Exchange::Binance | Exchange::BinanceUs => Some(BINANCE_SNAPSHOT_DEPTH),
+        Exchange::Bitstamp => Some(BITSTAMP_DIFF_DEPTH),
+        Exchange::Kraken => Some(KRAKEN_DEPTH),
+        Exchange::Coinbase => None,
+        Exchange::Okx => Some(OKX_DEPTH),
+        Exchange::Bybit => Some(BYBIT_DEPTH),
+        Exchange::Kucoin => Some(KUCOIN_SNAPSHOT_DEPTH),
+        Exchange::GateIo => Some(GATEIO_SNAPSHOT_DEPTH),
+        Exchange::Bitfinex => Some(BITFINEX_DEPTH),
+    }
+}
+
+/// Whether consecutive updates from `exchange` can carry the same id, so only an older id is
+/// stale. Kraken's and Bitfinex's ids are frame timestamps and Coinbase's message times,
+/// which frames sent together share; their levels are absolute, so a repeat is harmless.
+/// The other exchanges number each update, and a repeat was already applied.
+pub fn repeats_update_ids(exchange: Exchange) -> bool {
+    matches!(
+        exchange,
+        Exchange::Kraken | Exchange::Coinbase | Exchange::Bitfinex
+    )
+}
+
+/// Whether `exchange`'s REST snapshot ids are on the scale of its stream's update ids, so
+/// a snapshot older than an applied update can be told. Kraken's is its newest level's
+/// timestamp, behind the stream whenever levels were removed since; OKX's and Bitfinex's
+/// REST books carry no id the stream's compare with.
+pub fn snapshot_ids_follow_stream(exchange: Exchange) -> bool {
+    !matches!(
+        exchange,
+        Exchange::Kraken | Exchange::Okx | Exchange::Bitfinex
+    )
+}
+
+/// What a snapshot depth counts. A price level can hold one entry per exchange, so `depth`
+/// price levels may carry more than `depth` entries.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DepthUnit {
+    #[default]
+    PriceLevels,
+    /// Exactly `depth` entries, best price first and by the tie-break within a price. The
+    /// last price level may be cut part-way through.
+    Entries,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Top10Snapshot {
-    pub spread: f64,
+    pub schema_version: u32,
+    pub symbol: String,
+    pub version: u64,
+    /// Bumped each time the book is cleared; a client holding an older generation must
+    /// discard its state
+    #[serde(default)]
+    pub generation: u64,
+    /// When this book was started or last reset (unix millis). Unlike `version` it changes
+    /// across server restarts, so a recorder seeing a new epoch knows to resnapshot.
+    #[serde(default)]
+    pub epoch: u64,
+    /// Best ask - best bid as reported under `spread_convention`; `None` when either side
+    /// is empty
+    pub spread: Option<f64>,
+    /// Best ask - best bid, negative while the book is crossed, whatever the convention
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signed_spread: Option<f64>,
+    /// Best bid above best ask
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub crossed: bool,
+    /// How `spread` reports a crossed book; not serialized
+    #[serde(skip)]
+    pub spread_convention: SpreadConvention,
     pub bids: Vec<OrderLevel>,
     pub asks: Vec<OrderLevel>,
+    /// Unix millis
+    pub generated_at: u64,
+    pub last_update_ids: BTreeMap<String, u64>,
+    pub state: BookState,
+    pub exchanges: Vec<String>, // exchanges with levels in this snapshot, sorted
+    pub index_price: Option<f64>,
+    /// Price levels per side in the whole book, not just this ladder
+    #[serde(default)]
+    pub total_bid_levels: usize,
+    #[serde(default)]
+    pub total_ask_levels: usize,
+    /// Levels each exchange has per side in the whole book
+    #[serde(default)]
+    pub bid_levels_by_exchange: BTreeMap<String, usize>,
+    #[serde(default)]
+    pub ask_levels_by_exchange: BTreeMap<String, usize>,
+    /// CRC-32 of the top of `bids`/`asks` as they are, see [`checksum::canonical`]. Kept
+    /// up to date by every method that changes the ladder.
+    #[serde(default)]
+    pub checksum: u32,
+    /// Best price per side whose cumulative size across the whole book reaches the symbol's
+    /// `smart_best_min_qty`; unset if that isn't configured or the side is too thin
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smart_best_bid: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smart_best_ask: Option<f64>,
+    /// Price-level detail for each entry of `bids`/`asks`, by index; not serialized
+    #[serde(skip)]
+    pub bid_details: Vec<LevelDetail>,
+    #[serde(skip)]
+    pub ask_details: Vec<LevelDetail>,
+    /// Set once amounts have been replaced by running totals; not serialized
+    #[serde(skip)]
+    pub cumulative: Option<CumulativeScope>,
+    /// The symbol's `depth_weights` other than 1.0, applied to consolidated running totals;
+    /// not serialized
+    #[serde(skip)]
+    pub depth_weights: BTreeMap<String, f64>,
+    /// Each entry's own price once `bids`/`asks` are snapped to the tick or rounded for
+    /// display; not serialized
+    #[serde(skip)]
+    pub raw_bid_prices: Vec<f64>,
+    #[serde(skip)]
+    pub raw_ask_prices: Vec<f64>,
+    /// Set on the first snapshot sent on a stream; not serialized
+    #[serde(skip)]
+    pub is_initial_snapshot: bool,
+    /// Set once the book is shown quote/base flipped, see [`Top10Snapshot::into_inverted`];
+    /// not serialized
+    #[serde(skip)]
+    pub inverted: bool,
+    /// Why it was published, or once on a stream, why it was sent
+    #[serde(default)]
+    pub reason: EmissionReason,
+    /// Whether every configured exchange is contributing fresh data
+    #[serde(default)]
+    pub data_quality: DataQuality,
+    /// Configured exchanges not contributing fresh data
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lagging_exchanges: Vec<String>,
+}
+
+/// Which levels a cumulative amount sums
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CumulativeScope {
+    /// Every level at or better than this one on its side, whatever the exchange
+    Consolidated,
+    /// Only this exchange's levels at or better than this one
+    PerExchange,
+}
+
+/// The price level an entry belongs to, as of the snapshot
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LevelDetail {
+    /// Exchanges with a live level at this price
+    pub contributor_count: u32,
+    /// Time since the most recent of their changes; `None` if none of them is timestamped
+    pub newest_age_ms: Option<u64>,
+    /// The update that last wrote this entry, for the entry's own exchange
+    pub origin: Option<LevelOrigin>,
+}
+
+/// The update that last wrote a book entry: a diff, or the snapshot it was loaded from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LevelOrigin {
+    pub update_id: u64,
+    /// Unix millis the exchange stamped the update with; `None` for snapshots
+    pub event_time: Option<u64>,
+}
+
+/// Levels each exchange has on each side of the book
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LevelCounts {
+    pub bids: BTreeMap<String, usize>,
+    pub asks: BTreeMap<String, usize>,
+}
+
+impl LevelCounts {
+    fn removed(counts: &mut BTreeMap<String, usize>, exchange: &str) {
+        if let Some(count) = counts.get_mut(exchange) {
+            *count -= 1;
+            if *count == 0 {
+                counts.remove(exchange);
+            }
+        }
+    }
+}
+
+impl Top10Snapshot {
+    /// Set the spread from best ask - best bid: `signed_spread` and `crossed` as they are,
+    /// `spread` as the convention reports it
+    pub fn set_spread(&mut self, signed: Option<f64>) {
+        self.signed_spread = signed;
+        self.crossed = signed.is_some_and(|spread| spread < 0.0);
+        self.spread = signed.map(|spread| self.spread_convention.report(spread));
+    }
+
+    /// Cut both sides to `depth` price levels or entries
+    pub fn truncate(&mut self, depth: usize, unit: DepthUnit) {
+        for (side, details, raw_prices) in [
+            (
+                &mut self.bids,
+                &mut self.bid_details,
+                &mut self.raw_bid_prices,
+            ),
+            (
+                &mut self.asks,
+                &mut self.ask_details,
+                &mut self.raw_ask_prices,
+            ),
+        ] {
+            let keep = match unit {
+                DepthUnit::Entries => depth,
+                DepthUnit::PriceLevels => entries_in_levels(side, depth),
+            };
+            side.truncate(keep);
+            details.truncate(keep);
+            raw_prices.truncate(keep);
+        }
+        self.exchanges = exchanges_in(&self.bids, &self.asks);
+        self.stamp_checksum();
+    }
+
+    /// The same ladder with each level's amount replaced by the running total of the side
+    /// down to it, so a fill size can be binary-searched. Levels are already best first.
+    /// Consolidated totals count each exchange's amounts by its depth weight.
+    pub fn into_cumulative(mut self, scope: CumulativeScope) -> Self {
+        for side in [&mut self.bids, &mut self.asks] {
+            let mut totals: HashMap<Option<Exchange>, f64> = HashMap::new();
+            for level in side.iter_mut() {
+                let (key, weight) = match scope {
+                    CumulativeScope::Consolidated => (
+                        None,
+                        self.depth_weights
+                            .get(level.exchange.as_str())
+                            .copied()
+                            .unwrap_or(1.0),
+                    ),
+                    CumulativeScope::PerExchange => (Some(level.exchange), 1.0),
+                };
+                let total = totals.entry(key).or_default();
+                *total += level.amount * weight;
+                level.amount = *total;
+            }
+        }
+        self.cumulative = Some(scope);
+        self.stamp_checksum();
+        self
+    }
+
+    /// The book seen from the other currency, e.g. ETH/BTC as BTC/ETH: every price is
+    /// inverted (1/p), amounts are converted to the other currency (amount × price), and
+    /// bids and asks swap, since a bid for the base is an ask for the quote. Inversion
+    /// reverses price order, so each swapped side is still best first, ties in tie-break
+    /// order. Amounts must still be per level: invert before [`into_cumulative`].
+    /// Inverting twice gives back the original within float rounding.
+    ///
+    /// [`into_cumulative`]: Top10Snapshot::into_cumulative
+    pub fn into_inverted(mut self) -> Self {
+        debug_assert!(
+            self.cumulative.is_none(),
+            "running totals can't be converted level by level"
+        );
+        let invert = |levels: Vec<OrderLevel>| -> Vec<OrderLevel> {
+            levels
+                .into_iter()
+                .map(|level| OrderLevel {
+                    exchange: level.exchange,
+                    price: 1.0 / level.price,
+                    amount: level.amount * level.price,
+                })
+                .collect()
+        };
+        let invert_prices = |prices: Vec<f64>| -> Vec<f64> {
+            prices.into_iter().map(|price| 1.0 / price).collect()
+        };
+        let bids = std::mem::take(&mut self.bids);
+        self.bids = invert(std::mem::replace(&mut self.asks, invert(bids)));
+        std::mem::swap(&mut self.bid_details, &mut self.ask_details);
+        let raw_bids = std::mem::take(&mut self.raw_bid_prices);
+        self.raw_bid_prices = invert_prices(std::mem::replace(
+            &mut self.raw_ask_prices,
+            invert_prices(raw_bids),
+        ));
+        std::mem::swap(&mut self.total_bid_levels, &mut self.total_ask_levels);
+        std::mem::swap(
+            &mut self.bid_levels_by_exchange,
+            &mut self.ask_levels_by_exchange,
+        );
+        (self.smart_best_bid, self.smart_best_ask) = (
+            self.smart_best_ask.map(|ask| 1.0 / ask),
+            self.smart_best_bid.map(|bid| 1.0 / bid),
+        );
+        self.set_spread(match (self.bids.first(), self.asks.first()) {
+            (Some(bid), Some(ask)) => Some(ask.price - bid.price),
+            _ => None,
+        });
+        self.index_price = self.index_price.map(|index| 1.0 / index);
+        self.inverted = !self.inverted;
+        self.stamp_checksum();
+        self
+    }
+
+    /// The same ladder with prices rounded to `decimals` for display, conservatively: bids
+    /// down and asks up. The spread is recomputed from the rounded best prices and the index
+    /// rounded to nearest, so jitter below the precision leaves the snapshot unchanged.
+    pub fn into_display_rounded(mut self, decimals: u32) -> Self {
+        let factor = 10f64.powi(decimals as i32);
+        // Snap values within float noise of a multiple first, so 0.29 stays 0.29
+        let scaled = |price: f64| {
+            let scaled = price * factor;
+            let nearest = scaled.round();
+            if (scaled - nearest).abs() <= 1e-9 * nearest.abs().max(1.0) {
+                nearest
+            } else {
+                scaled
+            }
+        };
+        for (side, raw_prices, round) in [
+            (
+                &mut self.bids,
+                &mut self.raw_bid_prices,
+                f64::floor as fn(f64) -> f64,
+            ),
+            (&mut self.asks, &mut self.raw_ask_prices, f64::ceil),
+        ] {
+            // Prices snapped to the tick already carry the exchange's own
+            if raw_prices.is_empty() {
+                *raw_prices = side.iter().map(|level| level.price).collect();
+            }
+            for level in side.iter_mut() {
+                level.price = round(scaled(level.price)) / factor;
+            }
+        }
+        if let (Some(bid), Some(ask)) = (self.bids.first(), self.asks.first()) {
+            self.set_spread(Some(((ask.price - bid.price) * factor).round() / factor));
+        }
+        self.index_price = self.index_price.map(|index| scaled(index).round() / factor);
+        self.smart_best_bid = self.smart_best_bid.map(|bid| scaled(bid).floor() / factor);
+        self.smart_best_ask = self.smart_best_ask.map(|ask| scaled(ask).ceil() / factor);
+        self.stamp_checksum();
+        self
+    }
+
+    /// Recompute `checksum` from the ladder
+    pub fn stamp_checksum(&mut self) {
+        self.checksum = checksum::book_checksum(&self.bids, &self.asks);
+    }
+}
+
+fn exchanges_in(bids: &[OrderLevel], asks: &[OrderLevel]) -> Vec<String> {
+    let mut exchanges: Vec<String> = bids
+        .iter()
+        .chain(asks.iter())
+        .map(|l| l.exchange.to_string())
+        .collect();
+    exchanges.sort();
+    exchanges.dedup();
+    exchanges
+}
+
+// Number of leading entries that fall within the first `depth` distinct prices
+fn entries_in_levels(levels: &[OrderLevel], depth: usize) -> usize {
+    let mut prices = 0;
+    for (i, level) in levels.iter().enumerate() {
+        if i == 0 || level.price != levels[i - 1].price {
+            prices += 1;
+            if prices > depth {
+                return i;
+            }
+        }
+    }
+    levels.len()
+}
+
+/// Cumulative depth sampled at evenly spaced prices around the mid, as (price, cumulative amount,
+/// cumulative notional). Bids run from the mid downward and asks upward.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DepthCurve {
+    pub bids: Vec<(f64, f64, f64)>,
+    pub asks: Vec<(f64, f64, f64)>,
+    pub notional_unit: NotionalUnit,
+    /// Depth weights other than 1.0 the amounts were counted with
+    pub depth_weights: BTreeMap<String, f64>,
+    /// Levels beyond `max_level_distance_bps`, which the curve neither counts nor reaches
+    pub excluded_levels: ExcludedLevels,
+}
+
+impl DepthCurve {
+    /// Convert the notionals out of the quote currency, or flag them as unconverted if
+    /// `converter` has no usable rate
+    pub fn convert_notional(&mut self, converter: &dyn QuoteConverter) {
+        let currency = converter.currency().to_string();
+        let Some(rate) = converter.rate() else {
+            self.notional_unit = NotionalUnit::Unavailable { currency };
+            return;
+        };
+        for point in self.bids.iter_mut().chain(self.asks.iter_mut()) {
+            point.2 *= rate;
+        }
+        self.notional_unit = NotionalUnit::Converted { currency, rate };
+    }
+
+    /// The curve seen from the other currency, like [`Top10Snapshot::into_inverted`]: each
+    /// bid point becomes an ask point at 1/price and vice versa, with the cumulative amount
+    /// and notional trading places. Exact for quote notionals only; converted ones have no
+    /// inverse.
+    pub fn into_inverted(self) -> Self {
+        let invert = |points: Vec<(f64, f64, f64)>| {
+            points
+                .into_iter()
+                .map(|(price, amount, notional)| (1.0 / price, notional, amount))
+                .collect()
+        };
+        DepthCurve {
+            bids: invert(self.asks),
+            asks: invert(self.bids),
+            notional_unit: self.notional_unit,
+            depth_weights: self.depth_weights,
+            excluded_levels: ExcludedLevels {
+                bids: self.excluded_levels.asks,
+                asks: self.excluded_levels.bids,
+            },
+        }
+    }
+}
+
+/// Flicker smoothing: a level removed within the top `top_n` is tombstoned for `window`. The
+/// published ladder keeps it at its last amount until the window expires, so a re-add at the
+/// same price within it only drops the tombstone and the ladder doesn't churn. The spread
+/// always uses the true book, so it drops the level at once.
+#[derive(Clone, Copy, Debug)]
+pub struct TombstoneConfig {
+    pub top_n: usize,
+    pub window: Duration,
+}
+
+/// (bid notional - ask notional) / (bid notional + ask notional) over the top `top_n` price
+/// levels of each side, from -1 (all asks) to 1 (all bids)
+#[derive(Debug)]
+pub struct ImbalanceGauge {
+    pub top_n: usize,
+    pub gauge: ThrottledGauge,
+}
+
+/// Publication held back while several exchanges resync at once, so the book goes from its
+/// state before the resync to the one after it in a single version
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResyncHold {
+    /// Exchanges that have neither merged a snapshot nor given up yet
+    pub pending: BTreeSet<String>,
+    pub deadline: u64, // unix millis; held changes are published then at the latest
+    pub held_changes: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Tombstone {
+    pub level: OrderLevel,
+    pub expires_at: u64, // unix millis
+}
+
+/// A book entry as it was before a diff touched it, to roll the diff back if a later level fails
+struct UndoEntry {
+    key: (Side, usize, String),
+    level: Option<OrderLevel>,
+    updated_at: Option<u64>,
+    origin: Option<LevelOrigin>,
+    raw_price: Option<f64>,
+    tombstone: Option<Tombstone>,
+}
+
+impl Default for AggregatedOrderBook {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl AggregatedOrderBook {
     pub fn new() -> Self {
+        Self::with_clock(system_clock())
+    }
+
+    pub fn with_clock(clock: SharedClock) -> Self {
         Self {
-            spread: 0.0,
+            spread: None,
             bids: BTreeMap::new(),
             asks: BTreeMap::new(),
+            level_counts: LevelCounts::default(),
             last_update_id: HashMap::new(),
+            last_update_at: HashMap::new(),
+            version: 0,
+            generation: 0,
+            epoch: clock.now_millis(),
+            log_limiter: Arc::new(LogLimiter::new(clock.clone(), DEFAULT_SUPPRESSION_WINDOW)),
+            timeseries: Arc::new(TimeSeriesStore::new(clock.clone(), Retention::default())),
+            clock,
+            smoothing: None,
+            tombstones: HashMap::new(),
+            level_updated_at: HashMap::new(),
+            level_origins: HashMap::new(),
+            raw_prices: HashMap::new(),
+            boundary_policy: None,
+            awaiting_boundary: HashSet::new(),
+            awaiting_snapshot: HashSet::new(),
+            config: SymbolConfig::default(),
+            history: StatsHistory::default(),
+            shape_interval: None,
+            improvement_sampling: None,
+            imbalance: None,
+            traded_estimate: None,
+            resync_hold: None,
+            sequence_reset: HashMap::new(),
+            last_event_at: HashMap::new(),
+            configured_exchanges: vec![],
+            quality: QualityTracker::default(),
+            spread_convention: SpreadConvention::default(),
+            last_reason: EmissionReason::InitialSnapshot,
+            published: watch::channel(Arc::new(Top10Snapshot {
+                schema_version: SNAPSHOT_SCHEMA_VERSION,
+                ..Default::default()
+            }))
+            .0,
+        }
+        .published()
+    }
+
+    /// Judge data quality against these exchanges rather than those that have synced
+    pub fn with_configured_exchanges<'a>(
+        mut self,
+        exchanges: impl IntoIterator<Item = &'a str>,
+    ) -> Self {
+        self.configured_exchanges = exchanges.into_iter().map(str::to_string).collect();
+        self.publish();
+        self
+    }
+
+    /// Report crossed spreads in snapshots and the spread series by `convention`
+    pub fn with_spread_convention(mut self, convention: SpreadConvention) -> Self {
+        self.spread_convention = convention;
+        self.publish();
+        self
+    }
+
+    /// Use the resolved per-symbol settings (scale, depth cap, dust and outlier thresholds)
+    pub fn with_config(mut self, config: SymbolConfig) -> Self {
+        self.config = config;
+        self.publish();
+        self
+    }
+
+    fn published(mut self) -> Self {
+        self.publish();
+        self
+    }
+
+    /// Receiver of the snapshot published after each whole applied change. This is what
+    /// readers should use: it never reflects a partially applied update.
+    pub fn subscribe(&self) -> watch::Receiver<Arc<Top10Snapshot>> {
+        self.published.subscribe()
+    }
+
+    /// The most recently published snapshot
+    pub fn published_snapshot(&self) -> Arc<Top10Snapshot> {
+        self.published.borrow().clone()
+    }
+
+    /// The published snapshots as a stream: the latest one, then one per applied change.
+    /// A consumer that falls behind skips to the newest snapshot instead of queueing the
+    /// ones in between. The stream ends once the book is dropped. It holds its own
+    /// receiver, so dropping it leaves other subscribers and streams untouched.
+    ///
+    /// ```
+    /// use futures::StreamExt;
+    /// use keyrock_mm_rust_task::modules::types::AggregatedOrderBook;
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let book = AggregatedOrderBook::new();
+    /// let mut snapshots = Box::pin(book.snapshot_stream());
+    /// let first = snapshots.next().await.unwrap();
+    /// assert_eq!(first.version, book.published_snapshot().version);
+    ///
+    /// drop(book);
+    /// assert!(snapshots.next().await.is_none());
+    /// # }
+    /// ```
+    pub fn snapshot_stream(&self) -> impl Stream<Item = Arc<Top10Snapshot>> + Send + 'static {
+        self.snapshots_every(None)
+    }
+
+    /// Like [`snapshot_stream`](Self::snapshot_stream), but at most one snapshot per
+    /// `interval`: the newest one once it has passed, if the book changed meanwhile
+    ///
+    /// ```
+    /// use futures::StreamExt;
+    /// use keyrock_mm_rust_task::modules::types::AggregatedOrderBook;
+    /// use std::time::Duration;
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let book = AggregatedOrderBook::new();
+    /// let snapshots = book.snapshot_stream_throttled(Duration::from_millis(100));
+    /// let versions: Vec<u64> = snapshots.take(1).map(|snap| snap.version).collect().await;
+    /// assert_eq!(versions.len(), 1);
+    /// # }
+    /// ```
+    pub fn snapshot_stream_throttled(
+        &self,
+        interval: Duration,
+    ) -> impl Stream<Item = Arc<Top10Snapshot>> + Send + 'static {
+        self.snapshots_every(Some(interval))
+    }
+
+    fn snapshots_every(
+        &self,
+        interval: Option<Duration>,
+    ) -> impl Stream<Item = Arc<Top10Snapshot>> + Send + 'static {
+        let mut published = self.subscribe();
+        stream! {
+            loop {
+                let snap = published.borrow_and_update().clone();
+                yield snap;
+                if let Some(interval) = interval {
+                    tokio::time::sleep(interval).await;
+                }
+                if published.changed().await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    fn publish(&mut self) {
+        let snapshot = Arc::new(self.get_top10_snapshot());
+        let lagging = &snapshot.lagging_exchanges;
+        match self.quality.observe(snapshot.data_quality, lagging) {
+            Some(change) if snapshot.data_quality == DataQuality::Full => {
+                tracing::info!(symbol = self.config.symbol, "{}", change)
+            }
+            Some(change) => tracing::warn!(symbol = self.config.symbol, "{}", change),
+            None => {}
+        }
+        self.published.send_replace(snapshot);
+    }
+
+    /// End of an applied change: bump the version, sample stats and publish. This is the
+    /// only place the version changes, so readers only see it move at update boundaries.
+    /// Changes held for a resync are published as one, with `Resync` as the reason.
+    fn commit(&mut self, mut reason: EmissionReason) {
+        debug_assert_eq!(
+            self.published.borrow().version,
+            self.version,
+            "version changed outside of commit()"
+        );
+        if let Some(hold) = &mut self.resync_hold {
+            let expired = self.clock.now_millis() >= hold.deadline;
+            if !hold.pending.is_empty() && !expired {
+                hold.held_changes += 1;
+                return;
+            }
+            if self.take_hold().is_some_and(|hold| hold.held_changes > 0) {
+                reason = EmissionReason::Resync;
+            }
+        }
+        self.last_reason = reason;
+        self.version += 1;
+        self.check_price_drift();
+        self.record_stats();
+        if let Some(imbalance) = &self.imbalance
+            && let Some(value) = self.notional_imbalance(imbalance.top_n)
+        {
+            imbalance.gauge.offer(value);
+        }
+        self.publish();
+    }
+
+    /// Hold publication until each of `exchanges` has merged a snapshot or ended its resync,
+    /// or until `timeout` passes. Changes meanwhile are applied, then published as one
+    /// version. Joining a hold in progress keeps its deadline.
+    pub fn hold_for_resync<'a>(
+        &mut self,
+        exchanges: impl IntoIterator<Item = &'a str>,
+        timeout: Duration,
+    ) {
+        let deadline = self.clock.now_millis() + timeout.as_millis() as u64;
+        let hold = self.resync_hold.get_or_insert_with(|| ResyncHold {
+            pending: BTreeSet::new(),
+            deadline,
+            held_changes: 0,
+        });
+        hold.pending
+            .extend(exchanges.into_iter().map(str::to_string));
+    }
+
+    /// `exchange` gave up resyncing; publishes the held changes if it was the last one
+    pub fn end_resync(&mut self, exchange: &str) {
+        let Some(hold) = &mut self.resync_hold else {
+            return;
+        };
+        hold.pending.remove(exchange);
+        if hold.pending.is_empty() {
+            self.release_hold();
+        }
+    }
+
+    /// Publish the held changes if the hold's deadline has passed, so a venue that is slow
+    /// to resync can't hold the others back
+    pub fn release_expired_hold(&mut self) {
+        if let Some(hold) = &self.resync_hold
+            && self.clock.now_millis() >= hold.deadline
+        {
+            self.release_hold();
+        }
+    }
+
+    fn release_hold(&mut self) {
+        if let Some(hold) = self.take_hold()
+            && hold.held_changes > 0
+        {
+            self.commit(EmissionReason::Resync);
+        }
+    }
+
+    fn take_hold(&mut self) -> Option<ResyncHold> {
+        let hold = self.resync_hold.take()?;
+        if hold.pending.is_empty() {
+            tracing::info!(
+                held_changes = hold.held_changes,
+                "Resync complete, publishing"
+            );
+        } else {
+            tracing::warn!(
+                pending = ?hold.pending,
+                held_changes = hold.held_changes,
+                "Resync hold timed out, publishing without them"
+            );
         }
+        Some(hold)
+    }
+
+    /// Enable tombstone smoothing of removals at the top of the book (off by default)
+    pub fn with_smoothing(mut self, smoothing: TombstoneConfig) -> Self {
+        self.smoothing = Some(smoothing);
+        self
+    }
+
+    /// Share `limiter` for the warnings repeated on every bad update, e.g. with the connectors
+    pub fn with_log_limiter(mut self, limiter: Arc<LogLimiter>) -> Self {
+        self.log_limiter = limiter;
+        self
+    }
+
+    /// Record the book's sampled metrics into `store`, e.g. the one `GetTimeSeries` reads
+    pub fn with_timeseries(mut self, store: Arc<TimeSeriesStore>) -> Self {
+        self.timeseries = store;
+        self
+    }
+
+    /// Use `policy` for every exchange instead of each one's documented behavior
+    pub fn with_boundary_policy(mut self, policy: BoundaryPolicy) -> Self {
+        self.boundary_policy = Some(policy);
+        self
+    }
+
+    /// Compute the top-`top_n` notional imbalance on every change, publishing it as throttled
+    /// by `throttle` (off by default)
+    pub fn with_imbalance_gauge(mut self, top_n: usize, throttle: ThrottleConfig) -> Self {
+        self.imbalance = Some(ImbalanceGauge {
+            top_n,
+            gauge: ThrottledGauge::new(self.clock.clone(), throttle),
+        });
+        self
+    }
+
+    /// Sample `book_shape_stats()` into the history at most once per `interval` (off by default)
+    pub fn with_shape_sampling(mut self, interval: Duration) -> Self {
+        self.shape_interval = Some(interval);
+        self
+    }
+
+    /// Estimate traded volume from top-of-book levels removed by diffs (off by default)
+    pub fn with_traded_volume_estimate(mut self) -> Self {
+        self.traded_estimate = Some(TradedVolumeEstimator::new(self.clock.now_millis()));
+        self
+    }
+
+    /// Sequence reset thresholds for `exchange` in place of the defaults
+    pub fn with_sequence_reset(mut self, exchange: Exchange, config: SequenceResetConfig) -> Self {
+        self.sequence_reset.insert(exchange.to_string(), config);
+        self
+    }
+
+    /// Sample `price_improvement(clip_size)` into the history at most once per `interval`
+    /// (off by default)
+    pub fn with_improvement_sampling(mut self, clip_size: f64, interval: Duration) -> Self {
+        self.improvement_sampling = Some((clip_size, interval));
+        self
     }
 
     /// Prune the orderbook to keep only top 20 bids and asks to avoid excessive memory usage
     /// we can enable this if we face memory issues
     pub fn prune(&mut self) {
-        // Keep only top 20 bids (highest prices)
-        if self.bids.len() > 20 {
-            let keys_to_remove: Vec<usize> = self.bids.keys().rev().skip(20).cloned().collect();
+        self.prune_to(20);
+    }
+
+    /// Prune the orderbook to keep only the top `depth` bids and asks
+    pub fn prune_to(&mut self, depth: usize) {
+        // Keep only top bids (highest prices)
+        if self.bids.len() > depth {
+            let keys_to_remove: Vec<usize> = self.bids.keys().rev().skip(depth).cloned().collect();
             for key in keys_to_remove {
-                self.bids.remove(&key);
+                if let Some(bucket) = self.bids.remove(&key) {
+                    for exchange in bucket.into_keys() {
+                        LevelCounts::removed(&mut self.level_counts.bids, &exchange);
+                        self.level_updated_at
+                            .remove(&(Side::Bid, key, exchange.clone()));
+                        self.level_origins
+                            .remove(&(Side::Bid, key, exchange.clone()));
+                        self.raw_prices.remove(&(Side::Bid, key, exchange));
+                    }
+                }
             }
         }
 
-        // Keep only top 20 asks (lowest prices)
-        if self.asks.len() > 20 {
-            let keys_to_remove: Vec<usize> = self.asks.keys().skip(20).cloned().collect();
+        // Keep only top asks (lowest prices)
+        if self.asks.len() > depth {
+            let keys_to_remove: Vec<usize> = self.asks.keys().skip(depth).cloned().collect();
             for key in keys_to_remove {
-                self.asks.remove(&key);
+                if let Some(bucket) = self.asks.remove(&key) {
+                    for exchange in bucket.into_keys() {
+                        LevelCounts::removed(&mut self.level_counts.asks, &exchange);
+                        self.level_updated_at
+                            .remove(&(Side::Ask, key, exchange.clone()));
+                        self.level_origins
+                            .remove(&(Side::Ask, key, exchange.clone()));
+                        self.raw_prices.remove(&(Side::Ask, key, exchange));
+                    }
+                }
             }
         }
     }
 
-    /// Merge snapshots from both exchanges into the aggregated orderbook
-    pub fn merge_snapshots(&mut self, snapshots: Vec<OrderBook>) {
-        for snapshot in snapshots {
-            for level in snapshot.bids.iter() {
-                Self::upsert_level(&mut self.bids, level);
+    /// Drop `exchange`'s levels ranked past its authoritative depth on either side, worst
+    /// first. Only the excess over the exchange's level count is looked for, so a book within
+    /// its depth costs nothing.
+    fn trim_to_authoritative_depth(&mut self, exchange: Exchange) {
+        let Some(depth) = authoritative_depth(exchange) else {
+            return;
+        };
+        let key = exchange.to_string();
+        for side in [Side::Bid, Side::Ask] {
+            let (map, counts) = match side {
+                Side::Bid => (&mut self.bids, &mut self.level_counts.bids),
+                Side::Ask => (&mut self.asks, &mut self.level_counts.asks),
+            };
+            let excess = counts
+                .get(&key)
+                .map_or(0, |&count| count.saturating_sub(depth));
+            if excess == 0 {
+                continue;
             }
-            for level in snapshot.asks.iter() {
-                Self::upsert_level(&mut self.asks, level);
+            let holds = |(idx, bucket): (&usize, &HashMap<String, OrderLevel>)| {
+                bucket.contains_key(&key).then_some(*idx)
+            };
+            // Worst first: the lowest bids, the highest asks
+            let worst: Vec<usize> = match side {
+                Side::Bid => map.iter().filter_map(holds).take(excess).collect(),
+                Side::Ask => map.iter().rev().filter_map(holds).take(excess).collect(),
+            };
+            for idx in worst {
+                if let Some(bucket) = map.get_mut(&idx) {
+                    bucket.remove(&key);
+                    if bucket.is_empty() {
+                        map.remove(&idx);
+                    }
+                }
+                LevelCounts::removed(counts, &key);
+                let entry = (side, idx, key.clone());
+                self.level_updated_at.remove(&entry);
+                self.level_origins.remove(&entry);
+                self.raw_prices.remove(&entry);
             }
+        }
+    }
 
-            let mut seen: HashSet<&'static str> = HashSet::new();
+    /// Why `merge_snapshots` would skip `snapshot`'s levels for `exchange`, if it would
+    pub fn stale_snapshot(&self, exchange: Exchange, snapshot: &OrderBook) -> Option<SkipReason> {
+        if !snapshot_ids_follow_stream(exchange) {
+            return None;
+        }
+        let applied = *self.last_update_id.get(exchange.as_str())?;
+        (snapshot.last_update_id < applied).then_some(SkipReason::Stale {
+            applied,
+            snapshot: snapshot.last_update_id,
+        })
+    }
+
+    /// Merge snapshots from both exchanges into the aggregated orderbook. An exchange's
+    /// levels are skipped if the snapshot is older than data already applied for it.
+    pub fn merge_snapshots(&mut self, snapshots: Vec<OrderBook>) -> MergeReport {
+        let mut report = MergeReport::default();
+        for snapshot in snapshots {
+            let mut seen: Vec<Exchange> = vec![];
             for ex in snapshot
                 .bids
                 .iter()
                 .map(|l| l.exchange)
                 .chain(snapshot.asks.iter().map(|l| l.exchange))
             {
-                if seen.insert(ex) {
-                    self.last_update_id
-                        .insert(ex.to_lowercase(), snapshot.last_update_id);
+                if seen.contains(&ex) {
+                    continue;
+                }
+                seen.push(ex);
+                let stale = self.stale_snapshot(ex, &snapshot);
+                if let Some(SkipReason::Stale { applied, snapshot }) = stale {
+                    tracing::warn!(
+                        "{} snapshot {} is older than the applied update {}, skipping it",
+                        ex.as_str(),
+                        snapshot,
+                        applied
+                    );
+                }
+                report.per_exchange.entry(ex).or_default().skipped_reason = stale;
+            }
+            let merged: Vec<Exchange> = seen
+                .into_iter()
+                .filter(|ex| report.per_exchange[ex].skipped_reason.is_none())
+                .collect();
+            // A snapshot is the exchange's whole book: levels it no longer lists must go. What
+            // it had before still decides whether an entry counts as replaced or removed.
+            let mut prior: HashSet<(Side, usize, Exchange)> = HashSet::new();
+            for ex in &merged {
+                for (side, map) in [(Side::Bid, &self.bids), (Side::Ask, &self.asks)] {
+                    for (idx, bucket) in map.iter() {
+                        if bucket.contains_key(ex.as_str()) {
+                            prior.insert((side, *idx, *ex));
+                        }
+                    }
                 }
+                // Event times still order later diffs for sequence reset detection
+                let freshest = self.last_event_at.get(ex.as_str()).copied();
+                self.clear_exchange(ex.as_str());
+                if let Some(at) = freshest {
+                    self.last_event_at.insert(ex.to_string(), at);
+                }
+            }
+            let origin = LevelOrigin {
+                update_id: snapshot.last_update_id,
+                event_time: None,
+            };
+            for raw in snapshot.bids.iter() {
+                if !merged.contains(&raw.exchange) {
+                    continue;
+                }
+                let level = &self.on_tick(Side::Bid, raw);
+                let idx = Self::price_index(level.price, self.config.settings.price_scale);
+                let upsert = Self::upsert_level(
+                    &mut self.bids,
+                    &mut self.level_counts.bids,
+                    level,
+                    &self.config.settings,
+                );
+                let upsert = match (upsert, prior.remove(&(Side::Bid, idx, raw.exchange))) {
+                    (Upsert::Inserted, true) => Upsert::Replaced,
+                    (Upsert::Absent, true) => Upsert::Removed,
+                    (upsert, _) => upsert,
+                };
+                report
+                    .per_exchange
+                    .entry(raw.exchange)
+                    .or_default()
+                    .record(upsert);
+                self.touch_level(Side::Bid, level, raw.price, origin);
+            }
+            for raw in snapshot.asks.iter() {
+                if !merged.contains(&raw.exchange) {
+                    continue;
+                }
+                let level = &self.on_tick(Side::Ask, raw);
+                let idx = Self::price_index(level.price, self.config.settings.price_scale);
+                let upsert = Self::upsert_level(
+                    &mut self.asks,
+                    &mut self.level_counts.asks,
+                    level,
+                    &self.config.settings,
+                );
+                let upsert = match (upsert, prior.remove(&(Side::Ask, idx, raw.exchange))) {
+                    (Upsert::Inserted, true) => Upsert::Replaced,
+                    (Upsert::Absent, true) => Upsert::Removed,
+                    (upsert, _) => upsert,
+                };
+                report
+                    .per_exchange
+                    .entry(raw.exchange)
+                    .or_default()
+                    .record(upsert);
+                self.touch_level(Side::Ask, level, raw.price, origin);
+            }
+
+            for (_, _, ex) in prior {
+                report.per_exchange.entry(ex).or_default().removed += 1;
+            }
+            for ex in merged {
+                if let Some(hold) = &mut self.resync_hold {
+                    hold.pending.remove(ex.as_str());
+                }
+                self.last_update_id
+                    .insert(ex.to_string(), snapshot.last_update_id);
+                self.awaiting_boundary.insert(ex.to_string());
+                self.awaiting_snapshot.remove(ex.as_str());
+                self.last_update_at
+                    .insert(ex.to_string(), self.clock.now_millis());
+                self.trim_to_authoritative_depth(ex);
             }
         }
+        let all_skipped = !report.per_exchange.is_empty()
+            && report
+                .per_exchange
+                .values()
+                .all(|stats| stats.skipped_reason.is_some());
+        if all_skipped {
+            return report;
+        }
+
+        // Prune to the configured depth cap
+        if let Some(depth) = self.config.settings.max_depth {
+            self.prune_to(depth);
+        }
 
         if let Err(e) = self.try_recompute_spread() {
             tracing::error!("Failed to recompute spread: {}", e);
         }
-
-        // Prune to keep only top 10 levels
-        // self.prune();
+        self.commit(EmissionReason::Resync);
+        report
     }
 
     /// Handle update from one of the exchanges
     pub fn handle_update(&mut self, update: OrderBookUpdate) -> Result<(), String> {
+        self.apply_update(update).map(|_| ())
+    }
+
+    /// Like `handle_update`, but reports whether the update was applied or ignored as stale
+    pub fn apply_update(&mut self, update: OrderBookUpdate) -> Result<UpdateOutcome, String> {
         match self.try_apply_update(&update) {
-            Ok(_) => {
+            Ok(outcome) => {
                 tracing::debug!(
-                    "Successfully applied update for {} (ID: {})",
+                    "{:?} update for {} (ID: {})",
+                    outcome,
                     update.exchange,
                     update.update_id
                 );
-                // Prune to keep only top 10 levels
-                // self.prune();
-                Ok(())
+                Ok(outcome)
             }
             Err(e) => {
-                tracing::warn!(
-                    "Failed to apply update for {} (ID: {}): {}",
-                    update.exchange,
-                    update.update_id,
-                    e
-                );
+                let key = format!("{} failed update", update.exchange);
+                if self.log_limiter.allow(&key) {
+                    tracing::warn!(
+                        "Failed to apply update for {} (ID: {}): {}",
+                        update.exchange,
+                        update.update_id,
+                        e
+                    );
+                }
                 Err(e)
             }
         }
     }
 
     /// Try to apply update from one of the exchanges
-    fn try_apply_update(&mut self, update: &OrderBookUpdate) -> Result<(), String> {
-        // Only apply update if the update id is greater than the last update id; otherwise ignore
-        if self.validate_update(update).is_err() {
-            return Ok(());
+    fn try_apply_update(&mut self, update: &OrderBookUpdate) -> Result<UpdateOutcome, String> {
+        if self.awaiting_snapshot.contains(update.exchange.as_str()) {
+            return Ok(UpdateOutcome::Stale);
+        }
+        // Only apply update if the update id is greater than the last update id; otherwise
+        // ignore it, unless the exchange restarted its sequence
+        let reset = match self.validate_update(update) {
+            Ok(()) => false,
+            Err(_) if self.is_sequence_reset(update) => true,
+            Err(_) => return Ok(UpdateOutcome::Stale),
+        };
+        if reset {
+            let exchange = update.exchange.as_str();
+            tracing::warn!(
+                exchange,
+                update_id = update.update_id,
+                last_update_id = self.last_update_id.get(exchange).copied().unwrap_or(0),
+                event_time = update.event_time.unwrap_or(0),
+                freshest_event_time = self.last_event_at.get(exchange).copied().unwrap_or(0),
+                "Sequence reset detected: clearing {}'s levels and taking the update as its new baseline",
+                exchange
+            );
+            self.clear_exchange(exchange);
         }
 
-        // Update last update ID
-        self.last_update_id
-            .insert(update.exchange.to_lowercase(), update.update_id);
+        let after_snapshot = self.awaiting_boundary.contains(update.exchange.as_str()) || reset;
+        // The first diff after a snapshot reconciles against it; its removals aren't trades
+        let traded = (self.traded_estimate.is_some() && !after_snapshot).then(|| {
+            (
+                self.traded_through(Side::Bid, update),
+                self.traded_through(Side::Ask, update),
+            )
+        });
+
+        let mid = self.mid_price();
 
-        // Apply bids with error handling and detailed logging
-        for level in update.bids.iter() {
-            if let Err(e) = Self::try_upsert_level(&mut self.bids, level) {
+        // Apply every level or none: a level that fails rolls back the ones before it, and
+        // the update id isn't recorded, so the book stays consistent with the last id
+        let mut undo = Vec::new();
+        let origin = LevelOrigin {
+            update_id: update.update_id,
+            event_time: update.event_time,
+        };
+        let levels = update
+            .bids
+            .iter()
+            .map(|level| (Side::Bid, level))
+            .chain(update.asks.iter().map(|level| (Side::Ask, level)));
+        for (side, raw) in levels {
+            let level = &self.on_tick(side, raw);
+            if self.is_outlier(level, mid) {
+                continue;
+            }
+            if let Err(e) = self.try_apply_level(side, level, raw.price, origin, &mut undo) {
+                let applied = undo.len();
+                self.roll_back(undo);
                 tracing::error!(
-                    "Failed to upsert bid level: {} (price: {}, amount: {})",
+                    "Failed to upsert {:?} level: {} (price: {}, amount: {}); rolled back {} applied levels",
+                    side,
                     e,
                     level.price,
-                    level.amount
+                    level.amount,
+                    applied
                 );
-                return Err(format!("Failed to upsert bid level: {}", e));
+                return Err(format!(
+                    "Rolled back update: {:?} level at {} failed after {} of {} levels applied: {}",
+                    side,
+                    level.price,
+                    applied,
+                    update.bids.len() + update.asks.len(),
+                    e
+                ));
             }
         }
 
-        // Apply asks with error handling and detailed logging
-        for level in update.asks.iter() {
-            if let Err(e) = Self::try_upsert_level(&mut self.asks, level) {
-                tracing::error!(
-                    "Failed to upsert ask level: {} (price: {}, amount: {})",
-                    e,
-                    level.price,
-                    level.amount
-                );
-                return Err(format!("Failed to upsert ask level: {}", e));
-            }
+        self.trim_to_authoritative_depth(update.exchange);
+
+        // Update last update ID
+        self.last_update_id
+            .insert(update.exchange.to_string(), update.update_id);
+        self.last_update_at
+            .insert(update.exchange.to_string(), self.clock.now_millis());
+        if let Some(at) = update.event_time {
+            let freshest = self
+                .last_event_at
+                .entry(update.exchange.to_string())
+                .or_default();
+            *freshest = (*freshest).max(at);
+        }
+        self.awaiting_boundary.remove(update.exchange.as_str());
+
+        if !self.tombstones.is_empty() {
+            let now = self.clock.now_millis();
+            self.tombstones.retain(|_, t| t.expires_at > now);
+        }
+
+        // Prune to the configured depth cap
+        if let Some(depth) = self.config.settings.max_depth {
+            self.prune_to(depth);
         }
 
         // Recompute spread with error handling
         if let Err(e) = self.try_recompute_spread() {
             return Err(format!("Failed to recompute spread: {}", e));
         }
+        if let (Some(estimator), Some((bid, ask))) = (self.traded_estimate.as_mut(), traded) {
+            estimator.record(update.exchange.as_str(), bid, ask, self.clock.now_millis());
+        }
+        self.commit(EmissionReason::BookChange {
+            exchange: update.exchange.to_string(),
+            update_id: Some(update.update_id),
+        });
 
         // Debug: Log final state
         tracing::debug!(
-            "Update complete: {} total bids, {} total asks, spread: {}",
+            "Update complete: {} total bids, {} total asks, spread: {:?}",
             self.bids.len(),
             self.asks.len(),
             self.spread
         );
 
-        Ok(())
+        Ok(if reset {
+            UpdateOutcome::SequenceReset
+        } else {
+            UpdateOutcome::Applied
+        })
     }
 
-    /// ignore out of order updates
+    /// Whether `update`, rejected as not newer than the last applied id, restarts its
+    /// exchange's sequence
+    fn is_sequence_reset(&self, update: &OrderBookUpdate) -> bool {
+        let exchange = update.exchange.as_str();
+        let Some(&last_id) = self.last_update_id.get(exchange) else {
+            return false;
+        };
+        self.sequence_reset
+            .get(exchange)
+            .copied()
+            .unwrap_or_default()
+            .is_reset(
+                last_id,
+                update.update_id,
+                update.event_time,
+                self.last_event_at.get(exchange).copied(),
+            )
+    }
+
+    /// Amount `update` removes from the top of its exchange's side, before it is applied
+    fn traded_through(&self, side: Side, update: &OrderBookUpdate) -> f64 {
+        let (map, levels) = match side {
+            Side::Bid => (&self.bids, &update.bids),
+            Side::Ask => (&self.asks, &update.asks),
+        };
+        let settings = &self.config.settings;
+        let removed: HashSet<usize> = levels
+            .iter()
+            .filter(|level| level.amount == 0.0 || level.amount < settings.dust_threshold)
+            .map(|level| Self::price_index(level.price, settings.price_scale))
+            .collect();
+        if removed.is_empty() {
+            return 0.0;
+        }
+        let exchange = update.exchange.as_str();
+        let own = |(idx, bucket): (&usize, &HashMap<String, OrderLevel>)| {
+            bucket.get(exchange).map(|level| (*idx, level.amount))
+        };
+        match side {
+            Side::Bid => traded_through(map.iter().rev().filter_map(own), &removed),
+            Side::Ask => traded_through(map.iter().filter_map(own), &removed),
+        }
+    }
+
+    /// ignore out of order updates
     fn validate_update(&self, update: &OrderBookUpdate) -> Result<(), String> {
         // Validate update ID sequencing
-        let exchange_key = update.exchange.to_lowercase();
+        let exchange_key = update.exchange.to_string();
         if let Some(&last_id) = self.last_update_id.get(&exchange_key) {
-            match update.exchange {
-                "binance" => {
-                    if update.update_id <= last_id {
-                        tracing::warn!(
-                            "Binance update ID {} is not greater than last ID {}",
-                            update.update_id,
-                            last_id
-                        );
-                        return Err(format!(
-                            "Binance update ID {} is not greater than last ID {}",
-                            update.update_id, last_id
-                        ));
-                    }
-                }
-                "bitstamp" => {
-                    // For Bitstamp, the update ID should be greater than our last update ID
-                    if update.update_id <= last_id {
-                        tracing::warn!(
-                            "Bitstamp update ID {} is not greater than last ID {}",
-                            update.update_id,
-                            last_id
-                        );
-                        return Err(format!(
-                            "Bitstamp update ID {} is not greater than last ID {}",
-                            update.update_id, last_id
-                        ));
-                    }
-                }
-                _ => {
-                    // For other exchanges, just ensure it's greater
-                    if update.update_id <= last_id {
-                        return Err(format!(
-                            "Update ID {} is not greater than last ID {} for exchange {}",
-                            update.update_id, last_id, update.exchange
-                        ));
-                    }
+            if self.applies_at_boundary(update, last_id) {
+                return Ok(());
+            }
+            let (stale, relation) = if repeats_update_ids(update.exchange) {
+                (update.update_id < last_id, "older than")
+            } else {
+                (update.update_id <= last_id, "not greater than")
+            };
+            if stale {
+                let error = format!(
+                    "{} update ID {} is {} last ID {}",
+                    update.exchange, update.update_id, relation, last_id
+                );
+                // Replays after a reconnect repeat this for every buffered update
+                if self
+                    .log_limiter
+                    .allow(&format!("{} stale update id", update.exchange))
+                {
+                    tracing::warn!("{}", error);
                 }
+                return Err(error);
             }
         }
 
         Ok(())
     }
 
+    /// Whether `update` is the first diff after a snapshot, ends exactly at the snapshot's id
+    /// and overlaps it, and the exchange's boundary policy applies such diffs
+    fn applies_at_boundary(&self, update: &OrderBookUpdate, last_id: u64) -> bool {
+        let policy = self
+            .boundary_policy
+            .unwrap_or(BoundaryPolicy::documented_for(update.exchange));
+        policy == BoundaryPolicy::ApplyIfOverlapping
+            && self.awaiting_boundary.contains(update.exchange.as_str())
+            && update.update_id == last_id
+            && update.first_update_id.is_none_or(|first| first <= last_id)
+    }
+
     /// insert or update level in the orderbook
+    #[allow(clippy::unwrap_or_default)]
     fn try_upsert_level(
         map: &mut BTreeMap<usize, HashMap<String, OrderLevel>>,
+        counts: &mut BTreeMap<String, usize>,
         level: &OrderLevel,
+        settings: &BookSettings,
     ) -> Result<(), String> {
-        let idx = Self::price_index(level.price);
-        let exchange_key = level.exchange.to_lowercase();
+        let idx = Self::try_price_index(level.price, settings.price_scale)?;
+        let exchange_key = level.exchange.to_string();
 
-        if level.amount == 0.0 {
+        if level.amount == 0.0 || level.amount < settings.dust_threshold {
             // Remove level
             if let Some(bucket) = map.get_mut(&idx) {
-                bucket.remove(&exchange_key);
+                if bucket.remove(&exchange_key).is_some() {
+                    LevelCounts::removed(counts, &exchange_key);
+                }
                 if bucket.is_empty() {
                     map.remove(&idx);
                 }
@@ -217,105 +1492,1021 @@ impl AggregatedOrderBook {
         } else {
             // Insert or update level
             let bucket = map.entry(idx).or_insert_with(HashMap::new);
-            bucket.insert(exchange_key, level.clone());
+            if bucket.insert(exchange_key.clone(), level.clone()).is_none() {
+                *counts.entry(exchange_key).or_default() += 1;
+            }
         }
 
         Ok(())
     }
 
-    /// recompute spread from the best bid and ask prices
+    /// recompute spread from the best bid and ask prices; unset when a side is empty
     fn try_recompute_spread(&mut self) -> Result<(), String> {
-        let best_bid_idx = self.bids.keys().rev().next().copied().unwrap_or(0);
-        let best_ask_idx = self.asks.keys().next().copied().unwrap_or(0);
-
-        self.spread = (best_ask_idx as f64 - best_bid_idx as f64) / PRICE_SCALE;
+        self.spread = match (self.best_price(Side::Bid), self.best_price(Side::Ask)) {
+            (Some(bid), Some(ask)) => Some(ask - bid),
+            _ => None,
+        };
 
         Ok(())
     }
 
-    /// get top 10 bids and asks from the aggregated orderbook
-    pub fn get_top10_snapshot(&self) -> Top10Snapshot {
-        // Get top 10 price levels for bids (highest prices first)
-        let bid_levels: Vec<OrderLevel> = self
-            .bids
+    /// The stored price of a price level's first entry in tie-break order, as published.
+    /// Stored prices are the single source of truth for outputs: `idx / price_scale` can
+    /// differ from them in the last digits.
+    fn bucket_price(&self, bucket: &HashMap<String, OrderLevel>) -> f64 {
+        self.config
+            .settings
+            .tie_breaker()
+            .best(bucket.values())
+            .map_or(0.0, |level| level.price)
+    }
+
+    /// Stored price of the best level on a side
+    fn best_price(&self, side: Side) -> Option<f64> {
+        let bucket = match side {
+            Side::Bid => self.bids.values().next_back()?,
+            Side::Ask => self.asks.values().next()?,
+        };
+        Some(self.bucket_price(bucket))
+    }
+
+    /// [`smart_best`] of one side of the whole book, if `smart_best_min_qty` is configured
+    pub fn smart_best(&self, side: Side) -> Option<f64> {
+        let min_qty = self.config.settings.smart_best_min_qty?;
+        smart_best(self.bucket_totals(side), min_qty)
+    }
+
+    /// Mid price from the best bid and ask, if both sides are present
+    pub fn mid_price(&self) -> Option<f64> {
+        Some((self.best_price(Side::Bid)? + self.best_price(Side::Ask)?) / 2.0)
+    }
+
+    /// Largest difference between an entry's stored price and the price derived from its
+    /// bucket index (`idx / price_scale`), relative to the stored price. Float rounding
+    /// keeps it around 1e-16; a larger one means a level that isn't on the price grid.
+    pub fn max_price_drift(&self) -> f64 {
+        let price_scale = self.config.settings.price_scale;
+        self.bids
             .iter()
+            .chain(self.asks.iter())
+            .flat_map(|(idx, bucket)| {
+                let derived = *idx as f64 / price_scale;
+                bucket
+                    .values()
+                    .map(move |level| (level.price - derived).abs() / level.price.abs())
+            })
+            .filter(|drift| drift.is_finite())
+            .fold(0.0, f64::max)
+    }
+
+    /// In debug builds, report stored prices that drift from their bucket's index
+    fn check_price_drift(&self) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+        let drift = self.max_price_drift();
+        if drift > PRICE_DRIFT_EPSILON {
+            tracing::warn!(
+                "Stored level prices drift from their bucket index by up to {:e} (relative) at price scale {}",
+                drift,
+                self.config.settings.price_scale
+            );
+        }
+    }
+
+    /// Sample the cumulative depth at `points` prices per side, evenly spaced out to
+    /// `range_bps` from the mid. Points beyond the last level on a side are dropped
+    /// rather than repeating its total, and so are points beyond `max_level_distance_bps`.
+    pub fn depth_curve(&self, points: usize, range_bps: f64) -> DepthCurve {
+        let Some(mid) = self.mid_price() else {
+            return DepthCurve::default();
+        };
+        let step = mid * range_bps / 10_000.0 / points.max(1) as f64;
+        let max_distance = self.config.settings.max_level_distance_bps;
+        let sampled = (1..=points).take_while(|i| {
+            max_distance.is_none_or(|max| *i as f64 * range_bps / points as f64 <= max + 1e-9)
+        });
+        let (bids, excluded_bids) = self.derived_bucket_totals(Side::Bid);
+        let (asks, excluded_asks) = self.derived_bucket_totals(Side::Ask);
+        DepthCurve {
+            bids: Self::sample_cumulative(
+                bids,
+                sampled.clone().map(|i| mid - step * i as f64),
+                |p, at| p >= at,
+            ),
+            asks: Self::sample_cumulative(asks, sampled.map(|i| mid + step * i as f64), |p, at| {
+                p <= at
+            }),
+            notional_unit: NotionalUnit::Quote,
+            depth_weights: self.config.settings.applied_depth_weights(),
+            excluded_levels: ExcludedLevels {
+                bids: excluded_bids,
+                asks: excluded_asks,
+            },
+        }
+    }
+
+    /// Whether a price is further from `mid` than `max_level_distance_bps`, which keeps it
+    /// out of derived metrics. Nothing is while there's no mid.
+    fn beyond_level_distance(&self, price: f64, mid: Option<f64>) -> bool {
+        let (Some(max_bps), Some(mid)) = (self.config.settings.max_level_distance_bps, mid) else {
+            return false;
+        };
+        mid > 0.0 && (price - mid).abs() / mid * 10_000.0 > max_bps
+    }
+
+    /// `bucket_totals` without the levels beyond `max_level_distance_bps`, and how many
+    /// those were
+    fn derived_bucket_totals(&self, side: Side) -> (Vec<(f64, f64)>, usize) {
+        let mid = self.mid_price();
+        let (kept, excluded): (Vec<_>, Vec<_>) = self
+            .bucket_totals(side)
+            .into_iter()
+            .partition(|(price, _)| !self.beyond_level_distance(*price, mid));
+        (kept, excluded.len())
+    }
+
+    /// (price, total amount) per bucket, best first, amounts counted by depth weight
+    fn bucket_totals(&self, side: Side) -> Vec<(f64, f64)> {
+        let total = |bucket: &HashMap<String, OrderLevel>| {
+            (
+                self.bucket_price(bucket),
+                bucket
+                    .iter()
+                    .map(|(e, l)| l.amount * self.depth_weight(e))
+                    .sum(),
+            )
+        };
+        match side {
+            Side::Bid => self.bids.values().rev().map(total).collect(),
+            Side::Ask => self.asks.values().map(total).collect(),
+        }
+    }
+
+    fn sample_cumulative(
+        levels: Vec<(f64, f64)>,
+        prices: impl Iterator<Item = f64>,
+        reached: impl Fn(f64, f64) -> bool,
+    ) -> Vec<(f64, f64, f64)> {
+        let mut levels = levels.into_iter().peekable();
+        let (mut cumulative, mut notional) = (0.0, 0.0);
+        let mut curve = vec![];
+        for price in prices {
+            if levels.peek().is_none() {
+                break;
+            }
+            while let Some((level_price, amount)) = levels.next_if(|(p, _)| reached(*p, price)) {
+                cumulative += amount;
+                notional += amount * level_price;
+            }
+            curve.push((price, cumulative, notional));
+        }
+        curve
+    }
+
+    fn depth_weight(&self, exchange: &str) -> f64 {
+        self.config.settings.depth_weight(exchange)
+    }
+
+    /// Mid price of one exchange's own best bid and ask
+    pub fn exchange_mid_price(&self, exchange: &str) -> Option<f64> {
+        let best_bid = self
+            .bids
+            .values()
             .rev()
-            .take(10) // Take first 10 price levels
-            .flat_map(|(_, exchange_map)| exchange_map.values().cloned())
-            .collect();
+            .find_map(|bucket| bucket.get(exchange))?;
+        let best_ask = self.asks.values().find_map(|bucket| bucket.get(exchange))?;
+        Some((best_bid.price + best_ask.price) / 2.0)
+    }
 
-        // Get top 10 price levels for asks (lowest prices first)
-        let ask_levels: Vec<OrderLevel> = self
-            .asks
-            .iter()
-            .take(10) // Take first 10 price levels
-            .flat_map(|(_, exchange_map)| exchange_map.values().cloned())
-            .collect();
+    /// One exchange's own top `depth` levels per side, best first, at its last update id
+    pub fn exchange_book(&self, exchange: &str, depth: usize) -> OrderBook {
+        let levels = |buckets: &mut dyn Iterator<Item = &HashMap<String, OrderLevel>>| {
+            buckets
+                .filter_map(|bucket| bucket.get(exchange).cloned())
+                .take(depth)
+                .collect()
+        };
+        OrderBook {
+            last_update_id: self.last_update_id.get(exchange).copied().unwrap_or(0),
+            bids: levels(&mut self.bids.values().rev()),
+            asks: levels(&mut self.asks.values()),
+        }
+    }
 
-        Top10Snapshot {
+    /// Weighted average of the per-exchange mids, using `index_weights` (1.0 for unlisted
+    /// exchanges). Stale exchanges are left out and the remaining weights renormalized;
+    /// `None` when no exchange with a positive weight has a two-sided book.
+    pub fn get_index_price(&self) -> Option<f64> {
+        let settings = &self.config.settings;
+        let stale = settings
+            .stale_after_ms
+            .map(|ms| self.stale_exchanges(Duration::from_millis(ms)))
+            .unwrap_or_default();
+        let (weighted, total) = self
+            .last_update_at
+            .keys()
+            .filter(|exchange| !stale.contains(exchange))
+            .filter_map(|exchange| {
+                let weight = settings.index_weights.get(exchange).copied().unwrap_or(1.0);
+                let mid = self.exchange_mid_price(exchange)?;
+                (weight > 0.0).then_some((mid * weight, weight))
+            })
+            .fold((0.0, 0.0), |(sum, total), (value, weight)| {
+                (sum + value, total + weight)
+            });
+        (total > 0.0).then(|| weighted / total)
+    }
+
+    fn record_stats(&mut self) {
+        let sample = StatsSample {
+            at: self.clock.now_millis(),
+            version: self.version,
             spread: self.spread,
+            index_price: self.get_index_price(),
+        };
+        self.history.record(sample);
+        let symbol = self.config.symbol.as_str();
+        if let Some(spread) = sample.spread {
+            let reported = self.spread_convention.report(spread);
+            self.timeseries
+                .record_at("spread", symbol, sample.at, reported);
+        }
+        if let Some(index_price) = sample.index_price {
+            self.timeseries
+                .record_at("index_price", symbol, sample.at, index_price);
+        }
+
+        if let Some(interval) = self.shape_interval {
+            let due = self.history.latest_shape().is_none_or(|last| {
+                sample.at.saturating_sub(last.at) >= interval.as_millis() as u64
+            });
+            if due {
+                let shape = self.book_shape_stats();
+                let series = &self.timeseries;
+                series.record_at("bid_levels", symbol, sample.at, shape.bids.levels as f64);
+                series.record_at("ask_levels", symbol, sample.at, shape.asks.levels as f64);
+                for (exchange, share) in &shape.exchange_share {
+                    series.record_at("exchange_share", exchange, sample.at, *share);
+                }
+                self.history.record_shape(ShapeSample {
+                    at: sample.at,
+                    version: self.version,
+                    shape,
+                });
+            }
+        }
+
+        if let Some((clip_size, interval)) = self.improvement_sampling {
+            let due = self.history.latest_improvement().is_none_or(|last| {
+                sample.at.saturating_sub(last.at) >= interval.as_millis() as u64
+            });
+            if due {
+                self.history.record_improvement(ImprovementSample {
+                    at: sample.at,
+                    version: self.version,
+                    improvement: self.price_improvement(clip_size),
+                });
+            }
+        }
+    }
+
+    /// Latest stats and book-shape samples
+    pub fn stats(&self) -> BookStats {
+        BookStats {
+            latest: self.history.latest().copied(),
+            shape: self.history.latest_shape().cloned(),
+            imbalance: self.imbalance.as_ref().and_then(|i| i.gauge.latest()),
+            imbalance_excluded_levels: self
+                .imbalance
+                .as_ref()
+                .map(|i| self.imbalance_excluded_levels(i.top_n)),
+            update_rates: BTreeMap::new(),
+            slow_apply_total: BTreeMap::new(),
+            price_improvement: self.history.latest_improvement().cloned(),
+            traded_estimate: self.traded_estimate.clone(),
+            depth_weights: self.config.settings.applied_depth_weights(),
+        }
+    }
+
+    /// Best price and VWAP for `clip_size` on each side, aggregated and for every exchange in
+    /// the book on its own. An exchange without levels on a side has no prices there. The
+    /// best price is credited to one exchange by the tie-break. The aggregated VWAP counts
+    /// amounts by depth weight; each exchange's own is raw.
+    pub fn price_improvement(&self, clip_size: f64) -> PriceImprovement {
+        let tie_breaker = self.config.settings.tie_breaker();
+        let exchanges: BTreeSet<&String> = self
+            .last_update_at
+            .keys()
+            .chain(self.level_counts.bids.keys())
+            .chain(self.level_counts.asks.keys())
+            .collect();
+        let side = |side: Side| {
+            let map = match side {
+                Side::Bid => &self.bids,
+                Side::Ask => &self.asks,
+            };
+            let buckets = || -> Box<dyn Iterator<Item = &HashMap<String, OrderLevel>>> {
+                match side {
+                    Side::Bid => Box::new(map.values().rev()),
+                    Side::Ask => Box::new(map.values()),
+                }
+            };
+            let ordered = |bucket: &HashMap<String, OrderLevel>| {
+                let mut levels: Vec<OrderLevel> = bucket.values().cloned().collect();
+                tie_breaker.sort(&mut levels);
+                levels
+            };
+            let aggregated: Vec<(f64, f64)> = buckets()
+                .flat_map(ordered)
+                .map(|level| {
+                    let weight = self.depth_weight(level.exchange.as_str());
+                    (level.price, level.amount * weight)
+                })
+                .collect();
+            let best_exchange = buckets()
+                .next()
+                .and_then(|bucket| tie_breaker.best(bucket.values()))
+                .map(|level| level.exchange.to_string());
+            let venues = exchanges.iter().map(|exchange| {
+                let levels = buckets()
+                    .filter_map(|bucket| bucket.get(*exchange))
+                    .map(|level| (level.price, level.amount))
+                    .collect();
+                (exchange.to_string(), levels)
+            });
+            SideImprovement {
+                best_exchange,
+                ..SideImprovement::compare(side, clip_size, &aggregated, venues)
+            }
+        };
+        PriceImprovement {
+            clip_size,
+            bids: side(Side::Bid),
+            asks: side(Side::Ask),
+            depth_weights: self.config.settings.applied_depth_weights(),
+        }
+    }
+
+    /// (bid - ask) / (bid + ask) notional over the top `top_n` price levels per side, or
+    /// `None` while both are empty. Amounts count by depth weight; levels among the top
+    /// `top_n` beyond `max_level_distance_bps` don't count.
+    pub fn notional_imbalance(&self, top_n: usize) -> Option<f64> {
+        let mid = self.mid_price();
+        let notional = |levels: &mut dyn Iterator<Item = &HashMap<String, OrderLevel>>| -> f64 {
+            levels
+                .take(top_n)
+                .filter(|bucket| !self.beyond_level_distance(self.bucket_price(bucket), mid))
+                .flat_map(|bucket| bucket.iter())
+                .map(|(exchange, level)| level.price * level.amount * self.depth_weight(exchange))
+                .sum()
+        };
+        let bid = notional(&mut self.bids.values().rev());
+        let ask = notional(&mut self.asks.values());
+        let total = bid + ask;
+        (total > 0.0).then(|| (bid - ask) / total)
+    }
+
+    /// Levels among the top `top_n` per side that `notional_imbalance` leaves out
+    pub fn imbalance_excluded_levels(&self, top_n: usize) -> ExcludedLevels {
+        let mid = self.mid_price();
+        let beyond = |levels: &mut dyn Iterator<Item = &HashMap<String, OrderLevel>>| {
+            levels
+                .take(top_n)
+                .filter(|bucket| self.beyond_level_distance(self.bucket_price(bucket), mid))
+                .count()
+        };
+        ExcludedLevels {
+            bids: beyond(&mut self.bids.values().rev()),
+            asks: beyond(&mut self.asks.values()),
+        }
+    }
+
+    /// Level counts and sizes per side, and each exchange's share of the total amount, over
+    /// the levels within `max_level_distance_bps`
+    pub fn book_shape_stats(&self) -> BookShape {
+        let mid = self.mid_price();
+        let mut by_exchange: BTreeMap<String, f64> = BTreeMap::new();
+        for level in self
+            .bids
+            .values()
+            .chain(self.asks.values())
+            .filter(|b| !self.beyond_level_distance(self.bucket_price(b), mid))
+            .flat_map(|b| b.values())
+        {
+            *by_exchange.entry(level.exchange.to_string()).or_default() += level.amount;
+        }
+        let total: f64 = by_exchange.values().sum();
+        if total > 0.0 {
+            by_exchange.values_mut().for_each(|amount| *amount /= total);
+        } else {
+            by_exchange.clear();
+        }
+        let (bids, excluded_bids) = self.derived_bucket_totals(Side::Bid);
+        let (asks, excluded_asks) = self.derived_bucket_totals(Side::Ask);
+        BookShape {
+            bids: SideShape::from_levels(&bids),
+            asks: SideShape::from_levels(&asks),
+            exchange_share: by_exchange,
+            excluded_levels: ExcludedLevels {
+                bids: excluded_bids,
+                asks: excluded_asks,
+            },
+        }
+    }
+
+    /// Whether an incoming level is too far from the mid to be trusted. Removals are never outliers.
+    /// `level` with its price snapped to the instrument's tick when `round_to_tick` is on:
+    /// bids down and asks up, so snapping never makes a venue look better than it is
+    fn on_tick(&self, side: Side, level: &OrderLevel) -> OrderLevel {
+        let settings = &self.config.settings;
+        let (true, Some(tick)) = (settings.round_to_tick, settings.tick_size) else {
+            return level.clone();
+        };
+        // Dividing by ticks per unit keeps decimal ticks exact: 51234 / 1e6 is 0.051234,
+        // where 51234 * 1e-6 is not
+        let per_unit = 1.0 / tick;
+        let ticks = level.price * per_unit;
+        let nearest = ticks.round();
+        // A price already on the tick stays put despite float noise in the division
+        let snapped = if (ticks - nearest).abs() <= 1e-9 * nearest.abs().max(1.0) {
+            nearest
+        } else {
+            match side {
+                Side::Bid => ticks.floor(),
+                Side::Ask => ticks.ceil(),
+            }
+        };
+        OrderLevel {
+            price: snapped / per_unit,
+            ..level.clone()
+        }
+    }
+
+    fn is_outlier(&self, level: &OrderLevel, mid: Option<f64>) -> bool {
+        let (Some(tolerance_bps), Some(mid)) = (self.config.settings.outlier_tolerance_bps, mid)
+        else {
+            return false;
+        };
+        if level.amount == 0.0 || mid <= 0.0 {
+            return false;
+        }
+        let distance_bps = (level.price - mid).abs() / mid * 10_000.0;
+        if distance_bps > tolerance_bps {
+            tracing::debug!(
+                "Ignoring outlier {} level at {} ({:.1} bps from mid {})",
+                level.exchange,
+                level.price,
+                distance_bps,
+                mid
+            );
+            return true;
+        }
+        false
+    }
+
+    /// Drop the tombstones whose window has passed, publishing the ladder without their
+    /// levels. Returns whether any expired.
+    pub fn expire_tombstones(&mut self) -> bool {
+        let now = self.clock.now_millis();
+        let expired = self
+            .tombstones
+            .values()
+            .find(|t| t.expires_at <= now)
+            .map(|t| t.level.exchange);
+        let Some(exchange) = expired else {
+            return false;
+        };
+        self.tombstones.retain(|_, t| t.expires_at > now);
+        self.commit(EmissionReason::BookChange {
+            exchange: exchange.to_string(),
+            update_id: None,
+        });
+        true
+    }
+
+    /// Remember a top-of-book level that is about to be removed, or forget it once re-added
+    fn track_tombstone(&mut self, side: Side, level: &OrderLevel) {
+        let Some(smoothing) = self.smoothing else {
+            return;
+        };
+        let idx = Self::price_index(level.price, self.config.settings.price_scale);
+        let key = (side, idx, level.exchange.to_string());
+        if level.amount != 0.0 {
+            self.tombstones.remove(&key);
+            return;
+        }
+
+        let map = match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        };
+        let Some(existing) = map.get(&idx).and_then(|bucket| bucket.get(&key.2)) else {
+            return;
+        };
+        let better_levels = match side {
+            Side::Bid => map.range(idx + 1..).take(smoothing.top_n).count(),
+            Side::Ask => map.range(..idx).take(smoothing.top_n).count(),
+        };
+        if better_levels < smoothing.top_n {
+            let tombstone = Tombstone {
+                level: existing.clone(),
+                expires_at: self.clock.now_millis() + smoothing.window.as_millis() as u64,
+            };
+            self.tombstones.insert(key, tombstone);
+        }
+    }
+
+    /// Top `depth` price levels of one side, best first, with the levels still tombstoned
+    /// put back where they were
+    fn top_levels(&self, side: Side, depth: usize) -> Vec<OrderLevel> {
+        let map = match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        };
+        let now = self.clock.now_millis();
+        let mut tombstoned: BTreeMap<usize, Vec<OrderLevel>> = BTreeMap::new();
+        for ((tombstone_side, idx, _), tombstone) in &self.tombstones {
+            if *tombstone_side == side && tombstone.expires_at > now {
+                tombstoned
+                    .entry(*idx)
+                    .or_default()
+                    .push(tombstone.level.clone());
+            }
+        }
+        let mut prices: BTreeSet<usize> = match side {
+            Side::Bid => map.keys().rev().take(depth).copied().collect(),
+            Side::Ask => map.keys().take(depth).copied().collect(),
+        };
+        prices.extend(tombstoned.keys());
+        let prices: Vec<usize> = match side {
+            Side::Bid => prices.into_iter().rev().take(depth).collect(),
+            Side::Ask => prices.into_iter().take(depth).collect(),
+        };
+
+        prices
+            .into_iter()
+            .flat_map(|idx| {
+                // Exchanges within a price level are ordered by the tie-break so cuts are
+                // deterministic
+                let mut bucket: Vec<OrderLevel> = map
+                    .get(&idx)
+                    .into_iter()
+                    .flat_map(|bucket| bucket.values().cloned())
+                    .chain(tombstoned.remove(&idx).unwrap_or_default())
+                    .collect();
+                self.config.settings.tie_breaker().sort(&mut bucket);
+                bucket
+            })
+            .collect()
+    }
+
+    /// get top 10 bids and asks from the aggregated orderbook
+    pub fn get_top10_snapshot(&self) -> Top10Snapshot {
+        self.get_snapshot(10, DepthUnit::PriceLevels)
+    }
+
+    /// Top `depth` price levels or entries per side
+    pub fn get_snapshot(&self, depth: usize, unit: DepthUnit) -> Top10Snapshot {
+        // Every price level has at least one entry, so `depth` levels always cover `depth` entries
+        let mut bid_levels = self.top_levels(Side::Bid, depth);
+        let mut ask_levels = self.top_levels(Side::Ask, depth);
+        if unit == DepthUnit::Entries {
+            bid_levels.truncate(depth);
+            ask_levels.truncate(depth);
+        }
+        let exchanges = exchanges_in(&bid_levels, &ask_levels);
+        let now = self.clock.now_millis();
+        let (data_quality, lagging_exchanges) = self.data_quality();
+
+        let mut snapshot = Top10Snapshot {
+            schema_version: SNAPSHOT_SCHEMA_VERSION,
+            symbol: self.config.symbol.clone(),
+            version: self.version,
+            generation: self.generation,
+            epoch: self.epoch,
+            spread: None,
+            signed_spread: None,
+            crossed: false,
+            spread_convention: self.spread_convention,
+            bid_details: self.level_details(Side::Bid, &bid_levels, now),
+            ask_details: self.level_details(Side::Ask, &ask_levels, now),
+            generated_at: now,
+            last_update_ids: self.last_update_id.clone().into_iter().collect(),
+            state: self.book_state(),
+            exchanges,
+            index_price: self.get_index_price(),
+            total_bid_levels: self.bids.len(),
+            total_ask_levels: self.asks.len(),
+            bid_levels_by_exchange: self.level_counts.bids.clone(),
+            ask_levels_by_exchange: self.level_counts.asks.clone(),
+            checksum: 0,
+            smart_best_bid: self.smart_best(Side::Bid),
+            smart_best_ask: self.smart_best(Side::Ask),
+            cumulative: None,
+            depth_weights: self.config.settings.applied_depth_weights(),
+            raw_bid_prices: self.raw_prices_of(Side::Bid, &bid_levels),
+            raw_ask_prices: self.raw_prices_of(Side::Ask, &ask_levels),
             bids: bid_levels,
             asks: ask_levels,
+            is_initial_snapshot: false,
+            inverted: false,
+            reason: self.last_reason.clone(),
+            data_quality,
+            lagging_exchanges,
+        };
+        snapshot.set_spread(self.spread);
+        snapshot.stamp_checksum();
+        snapshot
+    }
+
+    /// Each entry's own price, for the entries snapped to the tick; empty if none were
+    fn raw_prices_of(&self, side: Side, levels: &[OrderLevel]) -> Vec<f64> {
+        if self.raw_prices.is_empty() {
+            return vec![];
+        }
+        levels
+            .iter()
+            .map(|level| {
+                let idx = Self::price_index(level.price, self.config.settings.price_scale);
+                let key = (side, idx, level.exchange.to_string());
+                self.raw_prices.get(&key).copied().unwrap_or(level.price)
+            })
+            .collect()
+    }
+
+    /// Contributors and newest contribution age of each entry's price level
+    fn level_details(&self, side: Side, levels: &[OrderLevel], now: u64) -> Vec<LevelDetail> {
+        let map = match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        };
+        levels
+            .iter()
+            .map(|level| {
+                let idx = Self::price_index(level.price, self.config.settings.price_scale);
+                let bucket = map.get(&idx);
+                let newest = bucket
+                    .into_iter()
+                    .flat_map(|b| b.keys())
+                    .filter_map(|ex| self.level_updated_at.get(&(side, idx, ex.clone())))
+                    .max();
+                LevelDetail {
+                    contributor_count: bucket.map_or(0, |b| b.len() as u32),
+                    newest_age_ms: newest.map(|at| now.saturating_sub(*at)),
+                    origin: self
+                        .level_origins
+                        .get(&(side, idx, level.exchange.to_string()))
+                        .copied(),
+                }
+            })
+            .collect()
+    }
+
+    /// Classify the current book from its best levels
+    pub fn book_state(&self) -> BookState {
+        match (self.bids.keys().next_back(), self.asks.keys().next()) {
+            (Some(best_bid), Some(best_ask)) if best_bid >= best_ask => BookState::Crossed,
+            (Some(_), Some(_)) => BookState::Normal,
+            _ => BookState::Degraded,
+        }
+    }
+
+    /// Data quality against the configured exchanges (those that have synced if none are
+    /// configured). An exchange is fresh once synced, until it is removed, reset or goes
+    /// `stale_after_ms` without data.
+    fn data_quality(&self) -> (DataQuality, Vec<String>) {
+        let stale = self
+            .config
+            .settings
+            .stale_after_ms
+            .map(|ms| self.stale_exchanges(Duration::from_millis(ms)))
+            .unwrap_or_default();
+        let fresh = |exchange: &str| {
+            self.last_update_at.contains_key(exchange)
+                && !self.awaiting_snapshot.contains(exchange)
+                && !stale.iter().any(|e| e == exchange)
+        };
+        if self.configured_exchanges.is_empty() {
+            let mut synced: Vec<String> = self.last_update_at.keys().cloned().collect();
+            synced.sort();
+            return assess(&synced, fresh);
+        }
+        assess(&self.configured_exchanges, fresh)
+    }
+
+    /// Exchanges whose last applied snapshot/update is older than `max_age`
+    pub fn stale_exchanges(&self, max_age: Duration) -> Vec<String> {
+        let now = self.clock.now_millis();
+        let max_age = max_age.as_millis() as u64;
+        let mut stale: Vec<String> = self
+            .last_update_at
+            .iter()
+            .filter(|(_, at)| now.saturating_sub(**at) > max_age)
+            .map(|(exchange, _)| exchange.clone())
+            .collect();
+        stale.sort();
+        stale
+    }
+
+    /// Remove the levels of every stale exchange so they don't linger in the book.
+    /// Returns the expired exchanges; they need a fresh snapshot before their diffs can be applied again.
+    pub fn expire_stale_exchanges(&mut self, max_age: Duration) -> Vec<String> {
+        let stale = self.stale_exchanges(max_age);
+        for exchange in stale.iter() {
+            self.remove_exchange(exchange);
+        }
+        stale
+    }
+
+    /// Swap in reloaded settings. The price scale can't change on a live book since
+    /// existing levels are bucketed with it.
+    pub fn apply_settings(&mut self, settings: BookSettings) -> Result<(), String> {
+        if settings.price_scale != self.config.settings.price_scale {
+            return Err(format!(
+                "price_scale can't change on a running book ({} -> {})",
+                self.config.settings.price_scale, settings.price_scale
+            ));
+        }
+        // Levels are already snapped to the running tick, which may have been fetched rather
+        // than configured; it only changes on restart
+        let running = &self.config.settings;
+        let settings = BookSettings {
+            tick_size: running.tick_size,
+            round_to_tick: running.round_to_tick,
+            ..settings
+        };
+        self.config.settings = settings;
+        if let Some(depth) = self.config.settings.max_depth {
+            self.prune_to(depth);
+        }
+        if let Err(e) = self.try_recompute_spread() {
+            tracing::error!("Failed to recompute spread: {}", e);
+        }
+        self.commit(EmissionReason::ConfigChange);
+        Ok(())
+    }
+
+    /// Start the book over: every level, update id and tombstone is dropped and the spread
+    /// reset. The version keeps counting so streams stay ordered; the generation is bumped
+    /// and a new epoch started instead. Diffs are ignored until each exchange's next
+    /// snapshot is merged.
+    pub fn clear(&mut self) {
+        self.awaiting_snapshot
+            .extend(self.last_update_id.keys().cloned());
+        self.bids.clear();
+        self.asks.clear();
+        self.level_counts = LevelCounts::default();
+        self.spread = None;
+        self.last_update_id.clear();
+        self.last_update_at.clear();
+        self.awaiting_boundary.clear();
+        self.tombstones.clear();
+        self.level_updated_at.clear();
+        self.level_origins.clear();
+        self.raw_prices.clear();
+        self.generation += 1;
+        // A new epoch even if the clock hasn't moved
+        self.epoch = self.clock.now_millis().max(self.epoch + 1);
+        // A reset supersedes any resync in progress and is published straight away
+        self.resync_hold = None;
+        self.commit(EmissionReason::Reset);
+    }
+
+    /// Drop all levels and sequencing state for one exchange
+    pub fn remove_exchange(&mut self, exchange: &str) {
+        self.clear_exchange(exchange);
+        if let Err(e) = self.try_recompute_spread() {
+            tracing::error!("Failed to recompute spread: {}", e);
+        }
+        self.commit(EmissionReason::BookChange {
+            exchange: exchange.to_string(),
+            update_id: None,
+        });
+    }
+
+    /// Drop the exchange's levels and sequence state, without publishing
+    fn clear_exchange(&mut self, exchange: &str) {
+        let exchange_key = exchange.to_string();
+        for map in [&mut self.bids, &mut self.asks] {
+            map.retain(|_, bucket| {
+                bucket.remove(&exchange_key);
+                !bucket.is_empty()
+            });
+        }
+        self.level_counts.bids.remove(&exchange_key);
+        self.level_counts.asks.remove(&exchange_key);
+        self.last_update_id.remove(&exchange_key);
+        self.last_update_at.remove(&exchange_key);
+        self.awaiting_boundary.remove(&exchange_key);
+        self.tombstones.retain(|(_, _, ex), _| *ex != exchange_key);
+        self.level_updated_at
+            .retain(|(_, _, ex), _| *ex != exchange_key);
+        self.level_origins
+            .retain(|(_, _, ex), _| *ex != exchange_key);
+        self.raw_prices.retain(|(_, _, ex), _| *ex != exchange_key);
+        self.last_event_at.remove(&exchange_key);
+    }
+
+    /// Like `price_index`, but fails on a price with no index: non-finite, negative, or
+    /// overflowing once scaled
+    fn try_price_index(price: f64, price_scale: f64) -> Result<usize, String> {
+        let scaled = (price * price_scale).round();
+        // usize::MAX rounds up to 2^64 as an f64, which no longer fits
+        if scaled.is_finite() && scaled >= 0.0 && scaled < usize::MAX as f64 {
+            Ok(scaled as usize)
+        } else {
+            Err(format!(
+                "price {} has no index at price scale {}",
+                price, price_scale
+            ))
         }
     }
 
     #[inline]
-    fn price_index(price: f64) -> usize {
-        let scaled = (price * PRICE_SCALE).round();
+    fn price_index(price: f64, price_scale: f64) -> usize {
+        let scaled = (price * price_scale).round();
         if scaled.is_finite() && scaled >= 0.0 {
             scaled as usize
         } else {
             // Fallback for edge cases
-            (price * PRICE_SCALE).round() as usize
+            (price * price_scale).round() as usize
+        }
+    }
+
+    /// Apply one level of a diff, first recording the entry's prior state in `undo`
+    fn try_apply_level(
+        &mut self,
+        side: Side,
+        level: &OrderLevel,
+        raw_price: f64,
+        origin: LevelOrigin,
+        undo: &mut Vec<UndoEntry>,
+    ) -> Result<(), String> {
+        let idx = Self::try_price_index(level.price, self.config.settings.price_scale)?;
+        let key = (side, idx, level.exchange.to_string());
+        let map = match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        };
+        undo.push(UndoEntry {
+            level: map.get(&idx).and_then(|bucket| bucket.get(&key.2)).cloned(),
+            updated_at: self.level_updated_at.get(&key).copied(),
+            origin: self.level_origins.get(&key).copied(),
+            raw_price: self.raw_prices.get(&key).copied(),
+            tombstone: self.tombstones.get(&key).cloned(),
+            key,
+        });
+
+        self.track_tombstone(side, level);
+        let (map, counts) = match side {
+            Side::Bid => (&mut self.bids, &mut self.level_counts.bids),
+            Side::Ask => (&mut self.asks, &mut self.level_counts.asks),
+        };
+        Self::try_upsert_level(map, counts, level, &self.config.settings)?;
+        self.touch_level(side, level, raw_price, origin);
+        Ok(())
+    }
+
+    /// Restore the entries a failed diff touched, newest first so an entry touched twice
+    /// ends up as it was before the diff
+    fn roll_back(&mut self, undo: Vec<UndoEntry>) {
+        for entry in undo.into_iter().rev() {
+            let (side, idx, exchange_key) = &entry.key;
+            let (map, counts) = match side {
+                Side::Bid => (&mut self.bids, &mut self.level_counts.bids),
+                Side::Ask => (&mut self.asks, &mut self.level_counts.asks),
+            };
+            match entry.level {
+                Some(level) => {
+                    let bucket = map.entry(*idx).or_default();
+                    if bucket.insert(exchange_key.clone(), level).is_none() {
+                        *counts.entry(exchange_key.clone()).or_default() += 1;
+                    }
+                }
+                None => {
+                    if let Some(bucket) = map.get_mut(idx) {
+                        if bucket.remove(exchange_key).is_some() {
+                            LevelCounts::removed(counts, exchange_key);
+                        }
+                        if bucket.is_empty() {
+                            map.remove(idx);
+                        }
+                    }
+                }
+            }
+            match entry.updated_at {
+                Some(at) => self.level_updated_at.insert(entry.key.clone(), at),
+                None => self.level_updated_at.remove(&entry.key),
+            };
+            match entry.origin {
+                Some(origin) => self.level_origins.insert(entry.key.clone(), origin),
+                None => self.level_origins.remove(&entry.key),
+            };
+            match entry.raw_price {
+                Some(price) => self.raw_prices.insert(entry.key.clone(), price),
+                None => self.raw_prices.remove(&entry.key),
+            };
+            match entry.tombstone {
+                Some(tombstone) => self.tombstones.insert(entry.key, tombstone),
+                None => self.tombstones.remove(&entry.key),
+            };
+        }
+    }
+
+    /// Record when and by which update an entry last changed, for `LevelDetail`, and the
+    /// exchange's own price if it was snapped to the tick
+    fn touch_level(&mut self, side: Side, level: &OrderLevel, raw_price: f64, origin: LevelOrigin) {
+        let idx = Self::price_index(level.price, self.config.settings.price_scale);
+        let key = (side, idx, level.exchange.to_string());
+        if level.amount == 0.0 || level.amount < self.config.settings.dust_threshold {
+            self.level_updated_at.remove(&key);
+            self.level_origins.remove(&key);
+            self.raw_prices.remove(&key);
+        } else {
+            self.level_updated_at
+                .insert(key.clone(), self.clock.now_millis());
+            self.level_origins.insert(key.clone(), origin);
+            if raw_price != level.price {
+                self.raw_prices.insert(key, raw_price);
+            } else {
+                self.raw_prices.remove(&key);
+            }
         }
     }
 
-    // Insert or update a level in the orderbook. If the level amount is 0, remove the level.
-    fn upsert_level(map: &mut BTreeMap<usize, HashMap<String, OrderLevel>>, level: &OrderLevel) {
-        let idx = Self::price_index(level.price);
-        let exchange_key = level.exchange.to_lowercase();
+    // Insert or update a level in the orderbook. If the level amount is 0 (or dust), remove the level.
+    #[allow(clippy::unwrap_or_default)]
+    fn upsert_level(
+        map: &mut BTreeMap<usize, HashMap<String, OrderLevel>>,
+        counts: &mut BTreeMap<String, usize>,
+        level: &OrderLevel,
+        settings: &BookSettings,
+    ) -> Upsert {
+        let idx = Self::price_index(level.price, settings.price_scale);
+        let exchange_key = level.exchange.to_string();
 
-        if level.amount == 0.0 {
+        if level.amount == 0.0 || level.amount < settings.dust_threshold {
+            let mut upsert = Upsert::Absent;
             if let Some(bucket) = map.get_mut(&idx) {
-                bucket.remove(&exchange_key);
+                if bucket.remove(&exchange_key).is_some() {
+                    LevelCounts::removed(counts, &exchange_key);
+                    upsert = Upsert::Removed;
+                }
                 if bucket.is_empty() {
                     map.remove(&idx);
                 }
             }
-            return;
+            return upsert;
         }
 
         let bucket = map.entry(idx).or_insert_with(HashMap::new);
-        bucket.insert(exchange_key, level.clone());
+        if bucket.insert(exchange_key.clone(), level.clone()).is_none() {
+            *counts.entry(exchange_key).or_default() += 1;
+            Upsert::Inserted
+        } else {
+            Upsert::Replaced
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::BookSettings;
+    use crate::modules::clock::{MockClock, TestTime};
+    use crate::modules::stats::VenueImprovement;
     use crate::modules::types::{Exchange, OrderBook, OrderLevel};
+    use std::sync::Arc;
 
     fn make_snapshot(exchange: Exchange) -> OrderBook {
         // Create 20 bid levels descending from 100.0, and 20 ask levels ascending from 100.5.
         // Prices are identical across exchanges so buckets should merge under the same price index.
         let bids: Vec<OrderLevel> = (0..20)
             .map(|i| OrderLevel {
-                exchange: exchange.as_str(),
+                exchange,
                 price: 100.0 - (i as f64) * 0.01,
                 amount: 1.0 + (i as f64) * 0.1,
             })
             .collect();
         let asks: Vec<OrderLevel> = (0..20)
             .map(|i| OrderLevel {
-                exchange: exchange.as_str(),
+                exchange,
                 price: 100.5 + (i as f64) * 0.01,
                 amount: 2.0 + (i as f64) * 0.05,
             })
             .collect();
         OrderBook {
             last_update_id: match exchange {
-                Exchange::Binance => 111,
+                Exchange::Binance | Exchange::BinanceUs => 111,
                 Exchange::Bitstamp => 222,
+                Exchange::Kraken => 333,
+                Exchange::Coinbase => 444,
+                Exchange::Okx => 555,
+                Exchange::Bybit => 666,
+                Exchange::Kucoin => 777,
+                Exchange::GateIo => 888,
+                Exchange::Bitfinex => 999,
             },
             bids,
             asks,
@@ -323,6 +2514,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::manual_next_back)]
     fn merge_snapshots_keeps_all_levels_and_combines_exchanges() {
         let mut agg = AggregatedOrderBook::new();
         let binance = make_snapshot(Exchange::Binance);
@@ -334,11 +2526,10 @@ mod tests {
         assert!(agg.bids.len() == 20);
         assert!(agg.asks.len() == 20);
 
-        // Spread derived from best bid/ask indices
+        // Spread derived from the best bid/ask prices
         let best_bid_idx = *agg.bids.keys().rev().next().expect("best bid idx");
         let best_ask_idx = *agg.asks.keys().next().expect("best ask idx");
-        let expected_spread = (best_ask_idx as f64 - best_bid_idx as f64) / PRICE_SCALE;
-        assert!((agg.spread - expected_spread).abs() < 1e-12);
+        assert_eq!(agg.spread, Some(100.5 - 100.0));
 
         // Buckets at best levels include both exchanges
         let bid_bucket = agg.bids.get(&best_bid_idx).expect("bid bucket");
@@ -355,26 +2546,485 @@ mod tests {
     }
 
     #[test]
-    fn get_top10_methods_return_correct_levels() {
+    fn merges_report_inserted_replaced_removed_and_stale_skips() {
         let mut agg = AggregatedOrderBook::new();
+        let report = agg.merge_snapshots(vec![make_snapshot(Exchange::Binance)]);
+        let fresh = MergeStats {
+            inserted: 40,
+            ..Default::default()
+        };
+        assert_eq!(
+            report.per_exchange,
+            HashMap::from([(Exchange::Binance, fresh)])
+        );
 
-        // Create a snapshot with 25 bid levels and 25 ask levels
-        let mut bids = Vec::new();
-        let mut asks = Vec::new();
+        // The same levels again replace the entries; a zero amount removes one
+        let mut again = make_snapshot(Exchange::Binance);
+        again.bids[0].amount = 0.0;
+        again.asks.push(OrderLevel {
+            exchange: Exchange::Binance,
+            price: 99.0,
+            amount: 0.0,
+        });
+        let report = agg.merge_snapshots(vec![again, make_snapshot(Exchange::Bitstamp)]);
+        let replaced = MergeStats {
+            replaced: 39,
+            removed: 1,
+            ..Default::default()
+        };
+        assert_eq!(report.per_exchange[&Exchange::Binance], replaced);
+        assert_eq!(report.per_exchange[&Exchange::Bitstamp].inserted, 40);
+        assert_eq!(agg.level_counts.bids["binance"], 19);
 
-        // Create 25 bid levels (prices 100.0 down to 99.76)
-        for i in 0..25 {
-            bids.push(OrderLevel {
-                exchange: Exchange::Binance.as_str(),
-                price: 100.0 - (i as f64) * 0.01,
-                amount: 1.0 + (i as f64) * 0.1,
-            });
-        }
+        // Levels a snapshot leaves out are removed with it
+        let mut fewer = make_snapshot(Exchange::Binance);
+        fewer.bids.truncate(10);
+        let report = agg.merge_snapshots(vec![fewer]);
+        let trimmed = MergeStats {
+            inserted: 1,
+            replaced: 29,
+            removed: 10,
+            ..Default::default()
+        };
+        assert_eq!(report.per_exchange[&Exchange::Binance], trimmed);
+        assert_eq!(agg.level_counts.bids["binance"], 10);
+
+        // Older than the update ids already applied: nothing of it is merged
+        let version = agg.version;
+        let mut old = make_snapshot(Exchange::Bitstamp);
+        old.last_update_id = 200;
+        old.bids[0].amount = 50.0;
+        let report = agg.merge_snapshots(vec![old]);
+        let skipped = MergeStats {
+            skipped_reason: Some(SkipReason::Stale {
+                applied: 222,
+                snapshot: 200,
+            }),
+            ..Default::default()
+        };
+        assert_eq!(report.per_exchange[&Exchange::Bitstamp], skipped);
+        assert_eq!(agg.last_update_id["bitstamp"], 222);
+        let best_bid = agg.bids.values().next_back().unwrap();
+        assert_ne!(best_bid["bitstamp"].amount, 50.0);
+        assert_eq!(agg.version, version, "nothing was published");
+    }
+
+    #[test]
+    fn snapshots_without_a_stream_comparable_id_are_never_stale() {
+        for exchange in [Exchange::Kraken, Exchange::Okx, Exchange::Bitfinex] {
+            let mut agg = AggregatedOrderBook::new();
+            let mut snapshot = make_snapshot(exchange);
+            snapshot.last_update_id = 100;
+            agg.merge_snapshots(vec![snapshot]);
+            let mut update = diff(exchange, None, 500);
+            update.bids[0].amount = 9.0;
+            agg.apply_update(update).unwrap();
+            assert_eq!(agg.last_update_id[exchange.as_str()], 500);
+
+            // A resync's REST book with no id, after the stream got to 500
+            let mut resync = make_snapshot(exchange);
+            resync.last_update_id = 0;
+            resync.bids.truncate(5);
+            assert_eq!(agg.stale_snapshot(exchange, &resync), None);
+            let report = agg.merge_snapshots(vec![resync]);
+            assert_eq!(report.per_exchange[&exchange].skipped_reason, None);
+            assert_eq!(report.per_exchange[&exchange].removed, 15, "{}", exchange);
+            assert_eq!(agg.last_update_id[exchange.as_str()], 0);
+            assert_eq!(agg.level_counts.bids[exchange.as_str()], 5);
+        }
+    }
+
+    #[test]
+    fn spread_mid_and_summary_agree_on_stored_prices() {
+        let level = |exchange, price| OrderLevel {
+            exchange,
+            price,
+            amount: 1.0,
+        };
+        // Pairs whose index-derived difference isn't the difference of the stored prices
+        for (bid, ask) in [
+            (0.1, 0.30000001),
+            (1234.5678, 1234.56780001),
+            (16.1, 16.10000001),
+        ] {
+            let mut agg = AggregatedOrderBook::new();
+            agg.merge_snapshots(vec![OrderBook {
+                last_update_id: 1,
+                bids: vec![level(Exchange::Binance, bid)],
+                asks: vec![level(Exchange::Binance, ask)],
+            }]);
+            // How the spread used to be derived
+            let by_index =
+                ((PRICE_SCALE * ask).round() - (PRICE_SCALE * bid).round()) / PRICE_SCALE;
+            let snap = agg.get_top10_snapshot();
+            assert_ne!(snap.asks[0].price - snap.bids[0].price, by_index);
+            assert_eq!(snap.spread, Some(snap.asks[0].price - snap.bids[0].price));
+            assert_eq!(
+                agg.mid_price(),
+                Some((snap.bids[0].price + snap.asks[0].price) / 2.0)
+            );
+            assert!(agg.max_price_drift() <= PRICE_DRIFT_EPSILON);
+        }
+
+        // A level off the price grid shares a bucket with one on it: the tie-break's first
+        // entry sets the price, and the drift is reported
+        let mut agg = AggregatedOrderBook::new();
+        agg.merge_snapshots(vec![OrderBook {
+            last_update_id: 1,
+            bids: vec![
+                level(Exchange::Binance, 100.0000000004),
+                level(Exchange::Bitstamp, 100.0),
+            ],
+            asks: vec![level(Exchange::Bitstamp, 100.5)],
+        }]);
+        let snap = agg.get_top10_snapshot();
+        assert_eq!(snap.bids.len(), 2, "one bucket, two entries");
+        assert_eq!(snap.spread, Some(100.5 - 100.0000000004));
+        assert_eq!(snap.spread, Some(snap.asks[0].price - snap.bids[0].price));
+        assert!(agg.max_price_drift() > PRICE_DRIFT_EPSILON);
+    }
+
+    #[test]
+    fn a_level_that_fails_rolls_back_the_whole_update() {
+        let mut agg = AggregatedOrderBook::new();
+        agg.merge_snapshots(vec![make_snapshot(Exchange::Binance)]);
+        let before = agg.get_top10_snapshot();
+        let touched_before = agg.level_updated_at.clone();
+        let origins_before = agg.level_origins.clone();
+        let level = |price, amount| OrderLevel {
+            exchange: Exchange::Binance,
+            price,
+            amount,
+        };
+        let update = |asks| OrderBookUpdate {
+            exchange: Exchange::Binance,
+            update_id: 112,
+            event_time: Some(5_000),
+            // A new best bid, a removal and a change of an existing level
+            bids: vec![level(101.0, 1.0), level(100.0, 0.0), level(99.99, 7.0)],
+            asks,
+            ..Default::default()
+        };
+        let mut asks: Vec<_> = (0..20).map(|i| level(100.5 + i as f64, 3.0)).collect();
+        // The 5th of 20 asks has no price index
+        asks[4].price = f64::INFINITY;
+        // ...after an earlier ask at the same price was changed twice
+        asks[1] = level(100.5, 4.0);
+
+        let err = agg.handle_update(update(asks.clone())).unwrap_err();
+        assert!(
+            err.contains("Ask level at inf failed after 7 of 23 levels applied"),
+            "{}",
+            err
+        );
+        let after = agg.get_top10_snapshot();
+        assert_eq!(after.bids, before.bids);
+        assert_eq!(after.asks, before.asks);
+        assert_eq!(after.spread, before.spread);
+        assert_eq!((after.total_bid_levels, after.total_ask_levels), (20, 20));
+        assert_eq!(after.bid_levels_by_exchange.get("binance"), Some(&20));
+        assert_eq!(after.version, before.version);
+        assert_eq!(agg.last_update_id.get("binance"), Some(&111));
+        assert!(!agg.last_event_at.contains_key("binance"));
+        assert_eq!(agg.level_updated_at, touched_before);
+        assert_eq!(agg.level_origins, origins_before);
+
+        // The id wasn't recorded, so the corrected update still applies
+        asks[4].price = 104.5;
+        assert_eq!(
+            agg.apply_update(update(asks)).unwrap(),
+            UpdateOutcome::Applied
+        );
+        assert_eq!(agg.last_update_id.get("binance"), Some(&112));
+        assert_eq!(agg.get_top10_snapshot().bids[0].price, 101.0);
+    }
+
+    #[test]
+    fn levels_past_a_venues_authoritative_depth_are_trimmed_by_rank() {
+        let deep = |exchange, levels: usize, last_update_id| {
+            let level = |price| OrderLevel {
+                exchange,
+                price,
+                amount: 1.0,
+            };
+            OrderBook {
+                last_update_id,
+                bids: (0..levels)
+                    .map(|i| level(100.0 - i as f64 * 0.01))
+                    .collect(),
+                asks: (0..levels)
+                    .map(|i| level(101.0 + i as f64 * 0.01))
+                    .collect(),
+            }
+        };
+        let mut agg = AggregatedOrderBook::new();
+        agg.merge_snapshots(vec![
+            deep(Exchange::Bitstamp, 150, 222),
+            deep(Exchange::Coinbase, 150, 444),
+            deep(Exchange::Bybit, 80, 666),
+        ]);
+        let counts = |agg: &AggregatedOrderBook, exchange: &str| {
+            (
+                agg.level_counts.bids[exchange],
+                agg.level_counts.asks[exchange],
+            )
+        };
+        // Bitstamp's diffs cover its top 100 and Bybit's topic 50; Coinbase keeps its book
+        assert_eq!(counts(&agg, "bitstamp"), (100, 100));
+        assert_eq!(counts(&agg, "bybit"), (50, 50));
+        assert_eq!(counts(&agg, "coinbase"), (150, 150));
+        let holds = |map: &BTreeMap<usize, HashMap<String, OrderLevel>>, price: f64| {
+            map.get(&AggregatedOrderBook::price_index(price, PRICE_SCALE))
+                .map(|bucket| {
+                    let mut exchanges: Vec<_> = bucket.keys().cloned().collect();
+                    exchanges.sort();
+                    exchanges
+                })
+                .unwrap_or_default()
+        };
+        assert_eq!(holds(&agg.bids, 99.01), ["bitstamp", "coinbase"]);
+        assert_eq!(holds(&agg.bids, 99.0), ["coinbase"]);
+        assert_eq!(holds(&agg.asks, 101.49), ["bitstamp", "bybit", "coinbase"]);
+        assert_eq!(holds(&agg.asks, 101.5), ["bitstamp", "coinbase"]);
+
+        // A better bid pushes Bitstamp's worst out; one past its depth is never stored
+        let level = |price| OrderLevel {
+            exchange: Exchange::Bitstamp,
+            price,
+            amount: 2.0,
+        };
+        agg.handle_update(OrderBookUpdate {
+            exchange: Exchange::Bitstamp,
+            update_id: 223,
+            bids: vec![level(100.5), level(98.0)],
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(counts(&agg, "bitstamp"), (100, 100));
+        assert_eq!(holds(&agg.bids, 100.5), ["bitstamp"]);
+        assert_eq!(holds(&agg.bids, 99.01), ["coinbase"]);
+        assert_eq!(holds(&agg.bids, 98.0), Vec::<String>::new());
+        assert_eq!(counts(&agg, "coinbase"), (150, 150));
+        assert_eq!(counts(&agg, "bybit"), (50, 50));
+
+        // Levels trimmed away don't come back when better ones go
+        agg.handle_update(OrderBookUpdate {
+            exchange: Exchange::Bitstamp,
+            update_id: 224,
+            bids: vec![OrderLevel {
+                amount: 0.0,
+                ..level(100.5)
+            }],
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(counts(&agg, "bitstamp"), (99, 100));
+    }
+
+    #[test]
+    fn level_counts_follow_merges_updates_removals_and_pruning() {
+        let mut agg = AggregatedOrderBook::new();
+        agg.merge_snapshots(vec![
+            make_snapshot(Exchange::Binance),
+            make_snapshot(Exchange::Bitstamp),
+        ]);
+        let counts = |agg: &AggregatedOrderBook, exchange: &str| {
+            let snap = agg.get_top10_snapshot();
+            let count = |counts: &BTreeMap<String, usize>| counts.get(exchange).copied();
+            // The incremental counts must match a full recount of the buckets
+            for (map, counts) in [
+                (&agg.bids, &agg.level_counts.bids),
+                (&agg.asks, &agg.level_counts.asks),
+            ] {
+                let recount = map.values().flat_map(|bucket| bucket.keys());
+                assert_eq!(recount.count(), counts.values().sum::<usize>());
+            }
+            (
+                (snap.total_bid_levels, snap.total_ask_levels),
+                count(&snap.bid_levels_by_exchange),
+                count(&snap.ask_levels_by_exchange),
+            )
+        };
+        assert_eq!(counts(&agg, "binance"), ((20, 20), Some(20), Some(20)));
+        assert_eq!(counts(&agg, "bitstamp"), ((20, 20), Some(20), Some(20)));
+
+        let level = |exchange, price, amount| OrderLevel {
+            exchange,
+            price,
+            amount,
+        };
+        agg.handle_update(OrderBookUpdate {
+            exchange: Exchange::Binance,
+            update_id: 112,
+            bids: vec![level(Exchange::Binance, 101.0, 1.0)],
+            asks: vec![level(Exchange::Binance, 100.5, 9.0)],
+            ..Default::default()
+        })
+        .unwrap();
+        agg.handle_update(OrderBookUpdate {
+            exchange: Exchange::Bitstamp,
+            update_id: 223,
+            bids: vec![
+                level(Exchange::Bitstamp, 100.0, 0.0),
+                level(Exchange::Bitstamp, 90.0, 0.0),
+            ],
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            counts(&agg, "binance"),
+            ((21, 20), Some(21), Some(20)),
+            "a new price adds a level, a changed amount doesn't"
+        );
+        assert_eq!(
+            counts(&agg, "bitstamp"),
+            ((21, 20), Some(19), Some(20)),
+            "removing an absent level changes nothing"
+        );
+
+        agg.prune_to(10);
+        assert_eq!(counts(&agg, "binance"), ((10, 10), Some(10), Some(10)));
+        assert_eq!(counts(&agg, "bitstamp"), ((10, 10), Some(8), Some(10)));
+
+        agg.remove_exchange("bitstamp");
+        assert_eq!(counts(&agg, "bitstamp"), ((10, 10), None, None));
+        assert_eq!(counts(&agg, "binance"), ((10, 10), Some(10), Some(10)));
+    }
+
+    #[test]
+    fn each_commit_publishes_why_it_happened() {
+        let mut agg = AggregatedOrderBook::new();
+        let reason = |agg: &AggregatedOrderBook| agg.published_snapshot().reason.clone();
+        assert_eq!(reason(&agg), EmissionReason::InitialSnapshot);
+        agg.merge_snapshots(vec![make_snapshot(Exchange::Binance)]);
+        assert_eq!(reason(&agg), EmissionReason::Resync);
+
+        let update = |update_id| OrderBookUpdate {
+            exchange: Exchange::Binance,
+            update_id,
+            bids: vec![OrderLevel {
+                exchange: Exchange::Binance,
+                price: 100.0,
+                amount: update_id as f64,
+            }],
+            ..Default::default()
+        };
+        agg.handle_update(update(112)).unwrap();
+        let change = EmissionReason::BookChange {
+            exchange: "binance".to_string(),
+            update_id: Some(112),
+        };
+        assert_eq!(reason(&agg), change);
+        assert_eq!(
+            serde_json::to_value(&change).unwrap(),
+            serde_json::json!({"kind": "book_change", "exchange": "binance", "updateId": 112})
+        );
+
+        agg.apply_settings(agg.config.settings.clone()).unwrap();
+        assert_eq!(reason(&agg), EmissionReason::ConfigChange);
+
+        // Diffs held for a resync go out with the resync that releases them
+        agg.hold_for_resync(["bitstamp"], Duration::from_secs(5));
+        agg.handle_update(update(113)).unwrap();
+        assert_eq!(reason(&agg), EmissionReason::ConfigChange, "still held");
+        agg.merge_snapshots(vec![make_snapshot(Exchange::Bitstamp)]);
+        assert_eq!(reason(&agg), EmissionReason::Resync);
+
+        agg.remove_exchange("bitstamp");
+        assert_eq!(
+            reason(&agg),
+            EmissionReason::BookChange {
+                exchange: "bitstamp".to_string(),
+                update_id: None,
+            }
+        );
+        agg.clear();
+        assert_eq!(reason(&agg), EmissionReason::Reset);
+    }
+
+    #[test]
+    fn clear_starts_a_new_generation_and_waits_for_snapshots() {
+        let mut agg = AggregatedOrderBook::new();
+        agg.merge_snapshots(vec![make_snapshot(Exchange::Binance)]);
+        let (version, epoch) = (agg.version, agg.epoch);
+        agg.clear();
+        let snap = agg.published_snapshot();
+        assert_eq!((snap.generation, snap.version), (1, version + 1));
+        assert!(
+            snap.epoch > epoch,
+            "a new epoch even within the same millisecond"
+        );
+        assert!(snap.bids.is_empty() && snap.last_update_ids.is_empty());
+        assert_eq!((snap.total_bid_levels, snap.spread), (0, None));
+
+        // Diffs are only applied on top of a fresh snapshot
+        let diff = |update_id| OrderBookUpdate {
+            exchange: Exchange::Binance,
+            update_id,
+            bids: vec![OrderLevel {
+                exchange: Exchange::Binance,
+                price: 101.0,
+                amount: 1.0,
+            }],
+            ..Default::default()
+        };
+        assert_eq!(agg.apply_update(diff(500)), Ok(UpdateOutcome::Stale));
+        assert!(agg.bids.is_empty());
+        agg.merge_snapshots(vec![make_snapshot(Exchange::Binance)]);
+        assert_eq!(agg.apply_update(diff(501)), Ok(UpdateOutcome::Applied));
+        assert_eq!(agg.published_snapshot().generation, 1);
+        assert_eq!(
+            agg.published_snapshot().epoch,
+            snap.epoch,
+            "updates keep the epoch"
+        );
+    }
+
+    #[test]
+    fn repeated_stale_updates_are_logged_once_per_window() {
+        let clock = Arc::new(MockClock::new(1_000));
+        let limiter = Arc::new(LogLimiter::new(clock.clone(), Duration::from_secs(60)));
+        let mut agg =
+            AggregatedOrderBook::with_clock(clock.clone()).with_log_limiter(Arc::clone(&limiter));
+        agg.merge_snapshots(vec![make_snapshot(Exchange::Binance)]);
+        let stale = || OrderBookUpdate {
+            exchange: Exchange::Binance,
+            update_id: 100,
+            ..Default::default()
+        };
+        for _ in 0..5 {
+            assert_eq!(agg.apply_update(stale()), Ok(UpdateOutcome::Stale));
+        }
+        assert_eq!(
+            limiter.suppressed_totals(),
+            BTreeMap::from([("binance stale update id".to_string(), 4)])
+        );
+        clock.advance(Duration::from_secs(60));
+        let rollups = limiter.take_rollups();
+        assert_eq!(rollups[0].suppressed, 4);
+    }
+
+    #[test]
+    fn get_top10_methods_return_correct_levels() {
+        let mut agg = AggregatedOrderBook::new();
+
+        // Create a snapshot with 25 bid levels and 25 ask levels
+        let mut bids = Vec::new();
+        let mut asks = Vec::new();
+
+        // Create 25 bid levels (prices 100.0 down to 99.76)
+        for i in 0..25 {
+            bids.push(OrderLevel {
+                exchange: Exchange::Binance,
+                price: 100.0 - (i as f64) * 0.01,
+                amount: 1.0 + (i as f64) * 0.1,
+            });
+        }
 
         // Create 25 ask levels (prices 100.5 up to 100.74)
         for i in 0..25 {
             asks.push(OrderLevel {
-                exchange: Exchange::Binance.as_str(),
+                exchange: Exchange::Binance,
                 price: 100.5 + (i as f64) * 0.01,
                 amount: 2.0 + (i as f64) * 0.05,
             });
@@ -422,4 +3072,1594 @@ mod tests {
             .unwrap();
         assert_eq!(lowest_ask.price, 100.5);
     }
+
+    #[tokio::test(start_paused = true)]
+    async fn snapshot_streams_follow_the_book_and_skip_to_the_latest() {
+        use futures::StreamExt;
+
+        let mut agg = AggregatedOrderBook::new();
+        let mut snapshots = Box::pin(agg.snapshot_stream());
+        let mut throttled = Box::pin(agg.snapshot_stream_throttled(Duration::from_secs(1)));
+        let dropped = agg.snapshot_stream();
+        let initial = agg.published_snapshot().version;
+        assert_eq!(snapshots.next().await.unwrap().version, initial);
+        assert_eq!(throttled.next().await.unwrap().version, initial);
+        drop(dropped);
+
+        agg.merge_snapshots(vec![make_snapshot(Exchange::Binance)]);
+        let snap = snapshots.next().await.unwrap();
+        assert_eq!((snap.version, snap.total_bid_levels), (initial + 1, 20));
+
+        // Two changes before the next poll: only the second is seen
+        for update_id in [112, 113] {
+            agg.handle_update(OrderBookUpdate {
+                exchange: Exchange::Binance,
+                update_id,
+                bids: vec![OrderLevel {
+                    exchange: Exchange::Binance,
+                    price: 101.0,
+                    amount: update_id as f64,
+                }],
+                ..Default::default()
+            })
+            .unwrap();
+        }
+        let snap = snapshots.next().await.unwrap();
+        assert_eq!(snap.version, initial + 3);
+        assert_eq!(snap.bids[0].amount, 113.0);
+
+        // The throttled stream waits out its interval, then sends the newest snapshot
+        let started = tokio::time::Instant::now();
+        assert_eq!(throttled.next().await.unwrap().version, initial + 3);
+        assert_eq!(started.elapsed(), Duration::from_secs(1));
+
+        drop(agg);
+        assert!(snapshots.next().await.is_none());
+        assert!(throttled.next().await.is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stale_exchange_expires_without_sleeping() {
+        let time = TestTime::new(1_000_000);
+        let mut agg = AggregatedOrderBook::with_clock(time.shared());
+        agg.merge_snapshots(vec![
+            make_snapshot(Exchange::Binance),
+            make_snapshot(Exchange::Bitstamp),
+        ]);
+
+        time.advance(Duration::from_millis(1_500)).await;
+        let update = OrderBookUpdate {
+            exchange: Exchange::Binance,
+            update_id: 112,
+            first_update_id: None,
+            received_at: None,
+            event_time: None,
+            bids: vec![],
+            asks: vec![],
+        };
+        agg.handle_update(update).unwrap();
+
+        time.advance(Duration::from_millis(1_000)).await;
+        assert_eq!(
+            agg.stale_exchanges(Duration::from_secs(2)),
+            vec!["bitstamp"]
+        );
+
+        let expired = agg.expire_stale_exchanges(Duration::from_secs(2));
+        assert_eq!(expired, vec!["bitstamp"]);
+        assert!(
+            agg.bids
+                .values()
+                .chain(agg.asks.values())
+                .all(|bucket| !bucket.contains_key("bitstamp"))
+        );
+        assert!(!agg.last_update_id.contains_key("bitstamp"));
+        assert_eq!(agg.bids.len(), 20);
+        assert!(agg.stale_exchanges(Duration::from_secs(2)).is_empty());
+    }
+
+    #[test]
+    fn top10_snapshot_is_stamped_with_the_clock() {
+        let clock = Arc::new(MockClock::new(42_000));
+        let agg = AggregatedOrderBook::with_clock(clock.clone());
+        assert_eq!(agg.get_top10_snapshot().generated_at, 42_000);
+        clock.advance(Duration::from_millis(7));
+        assert_eq!(agg.get_top10_snapshot().generated_at, 42_007);
+    }
+
+    fn ladder(snapshot: &Top10Snapshot) -> Vec<(&'static str, f64, f64)> {
+        let mut levels: Vec<_> = snapshot
+            .bids
+            .iter()
+            .chain(snapshot.asks.iter())
+            .map(|l| (l.exchange.as_str(), l.price, l.amount))
+            .collect();
+        levels.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        levels
+    }
+
+    /// Remove the best bid and re-add it 100ms later, counting published ladder changes
+    fn count_flicker_changes(smoothing: Option<TombstoneConfig>) -> (usize, AggregatedOrderBook) {
+        let clock = Arc::new(MockClock::new(0));
+        let mut agg = AggregatedOrderBook::with_clock(clock.clone());
+        if let Some(smoothing) = smoothing {
+            agg = agg.with_smoothing(smoothing);
+        }
+        agg.merge_snapshots(vec![make_snapshot(Exchange::Binance)]);
+
+        let best = agg.get_top10_snapshot().bids[0].clone();
+        let mut previous = ladder(&agg.get_top10_snapshot());
+        let mut changes = 0;
+        for (update_id, amount) in (112..).zip([0.0, best.amount, 0.0, best.amount]) {
+            clock.advance(Duration::from_millis(100));
+            let update = OrderBookUpdate {
+                exchange: Exchange::Binance,
+                update_id,
+                first_update_id: None,
+                received_at: None,
+                event_time: None,
+                bids: vec![OrderLevel {
+                    amount,
+                    ..best.clone()
+                }],
+                asks: vec![],
+            };
+            agg.handle_update(update).unwrap();
+            let current = ladder(&agg.get_top10_snapshot());
+            if current != previous {
+                changes += 1;
+            }
+            previous = current;
+        }
+        (changes, agg)
+    }
+
+    #[test]
+    fn tombstones_keep_flickering_levels_in_the_ladder_and_readds_drop_them() {
+        let (changes_off, _) = count_flicker_changes(None);
+        assert_eq!(changes_off, 4);
+
+        let smoothing = TombstoneConfig {
+            top_n: 10,
+            window: Duration::from_millis(200),
+        };
+        let (changes_on, agg) = count_flicker_changes(Some(smoothing));
+        assert!(changes_on < changes_off);
+        assert_eq!(changes_on, 0);
+        assert!(agg.tombstones.is_empty());
+    }
+
+    #[test]
+    fn tombstoned_levels_stay_in_the_ladder_but_not_the_spread_until_the_window_expires() {
+        let clock = Arc::new(MockClock::new(0));
+        let mut agg =
+            AggregatedOrderBook::with_clock(clock.clone()).with_smoothing(TombstoneConfig {
+                top_n: 10,
+                window: Duration::from_millis(200),
+            });
+        agg.merge_snapshots(vec![make_snapshot(Exchange::Binance)]);
+        let best = agg.get_top10_snapshot().bids[0].clone();
+        let spread_before = agg.spread.unwrap();
+
+        agg.handle_update(OrderBookUpdate {
+            exchange: Exchange::Binance,
+            update_id: 112,
+            first_update_id: None,
+            received_at: None,
+            event_time: None,
+            bids: vec![OrderLevel {
+                amount: 0.0,
+                ..best.clone()
+            }],
+            asks: vec![],
+        })
+        .unwrap();
+
+        // True book has lost the level and the spread widened by one tick
+        assert_eq!(agg.bids.len(), 19);
+        assert!((agg.spread.unwrap() - (spread_before + 0.01)).abs() < 1e-9);
+        assert_eq!(agg.tombstones.len(), 1);
+        // ...while the published ladder still shows it
+        assert_eq!(agg.published_snapshot().bids[0], best);
+        assert_eq!(agg.get_top10_snapshot().bids.len(), 10);
+        assert!(!agg.expire_tombstones());
+
+        clock.advance(Duration::from_millis(201));
+        let version = agg.version;
+        assert!(agg.expire_tombstones());
+        assert!(agg.tombstones.is_empty());
+        assert_eq!(agg.version, version + 1);
+        let snapshot = agg.published_snapshot();
+        assert!(snapshot.bids[0].price < best.price);
+        assert!(
+            (snapshot.asks[0].price - snapshot.bids[0].price - agg.spread.unwrap()).abs() < 1e-9
+        );
+    }
+
+    #[test]
+    fn removals_below_top_n_are_not_tombstoned() {
+        let clock = Arc::new(MockClock::new(0));
+        let mut agg = AggregatedOrderBook::with_clock(clock).with_smoothing(TombstoneConfig {
+            top_n: 3,
+            window: Duration::from_millis(200),
+        });
+        agg.merge_snapshots(vec![make_snapshot(Exchange::Binance)]);
+        let fifth = agg.get_top10_snapshot().bids[4].clone();
+        agg.handle_update(OrderBookUpdate {
+            exchange: Exchange::Binance,
+            update_id: 112,
+            first_update_id: None,
+            received_at: None,
+            event_time: None,
+            bids: vec![OrderLevel {
+                amount: 0.0,
+                ..fifth
+            }],
+            asks: vec![],
+        })
+        .unwrap();
+        assert!(agg.tombstones.is_empty());
+    }
+
+    #[test]
+    fn symbol_settings_apply_depth_cap_dust_and_outliers() {
+        let config = SymbolConfig {
+            symbol: "ethbtc".to_string(),
+            settings: BookSettings {
+                price_scale: 100.0,
+                max_depth: Some(5),
+                dust_threshold: 0.5,
+                outlier_tolerance_bps: Some(100.0),
+                ..BookSettings::default()
+            },
+        };
+        let mut agg = AggregatedOrderBook::new().with_config(config);
+        agg.merge_snapshots(vec![make_snapshot(Exchange::Binance)]);
+        assert_eq!(agg.bids.len(), 5);
+        assert_eq!(agg.asks.len(), 5);
+        assert!((agg.spread.unwrap() - 0.5).abs() < 1e-9);
+
+        let update = OrderBookUpdate {
+            exchange: Exchange::Binance,
+            update_id: 112,
+            first_update_id: None,
+            received_at: None,
+            event_time: None,
+            bids: vec![
+                // Dust: treated as a removal of the best bid
+                OrderLevel {
+                    exchange: Exchange::Binance,
+                    price: 100.0,
+                    amount: 0.1,
+                },
+                // 10% away from the mid: ignored
+                OrderLevel {
+                    exchange: Exchange::Binance,
+                    price: 90.0,
+                    amount: 5.0,
+                },
+            ],
+            asks: vec![],
+        };
+        agg.handle_update(update).unwrap();
+        assert_eq!(agg.bids.len(), 4);
+        assert_eq!(*agg.bids.keys().next_back().unwrap(), 9999);
+        assert!(!agg.bids.contains_key(&9000));
+    }
+
+    #[test]
+    fn top10_snapshot_describes_the_book_it_came_from() {
+        let clock = Arc::new(MockClock::new(5_000));
+        let config = SymbolConfig {
+            symbol: "ethbtc".to_string(),
+            ..SymbolConfig::default()
+        };
+        let mut agg = AggregatedOrderBook::with_clock(clock.clone()).with_config(config);
+        let empty = agg.get_top10_snapshot();
+        assert_eq!(empty.version, 0);
+        assert_eq!(empty.state, BookState::Degraded);
+        assert!(empty.exchanges.is_empty());
+
+        agg.merge_snapshots(vec![
+            make_snapshot(Exchange::Binance),
+            make_snapshot(Exchange::Bitstamp),
+        ]);
+        // A stale update is ignored and must not bump the version
+        agg.handle_update(OrderBookUpdate {
+            exchange: Exchange::Binance,
+            update_id: 100,
+            first_update_id: None,
+            received_at: None,
+            event_time: None,
+            bids: vec![],
+            asks: vec![],
+        })
+        .unwrap();
+        // Crossing bid from Binance
+        agg.handle_update(OrderBookUpdate {
+            exchange: Exchange::Binance,
+            update_id: 112,
+            first_update_id: None,
+            received_at: None,
+            event_time: None,
+            bids: vec![OrderLevel {
+                exchange: Exchange::Binance,
+                price: 100.6,
+                amount: 1.0,
+            }],
+            asks: vec![],
+        })
+        .unwrap();
+        clock.advance(Duration::from_millis(3));
+
+        let snap = agg.get_top10_snapshot();
+        assert_eq!(snap.symbol, "ethbtc");
+        assert_eq!(snap.version, 2);
+        assert_eq!(snap.generated_at, 5_003);
+        assert_eq!(snap.state, BookState::Crossed);
+        assert_eq!(snap.exchanges, vec!["binance", "bitstamp"]);
+        assert_eq!(snap.last_update_ids.get("binance"), Some(&112));
+        assert_eq!(snap.last_update_ids.get("bitstamp"), Some(&222));
+
+        agg.remove_exchange("binance");
+        let snap = agg.get_top10_snapshot();
+        assert_eq!(snap.version, 3);
+        assert_eq!(snap.state, BookState::Normal);
+        assert_eq!(snap.exchanges, vec!["bitstamp"]);
+        assert!(!snap.last_update_ids.contains_key("binance"));
+    }
+
+    fn two_venue_book(clock: &Arc<MockClock>, weights: &[(&str, f64)]) -> AggregatedOrderBook {
+        let config = SymbolConfig {
+            symbol: "ethbtc".to_string(),
+            settings: BookSettings {
+                stale_after_ms: Some(2_000),
+                index_weights: weights.iter().map(|(e, w)| (e.to_string(), *w)).collect(),
+                ..BookSettings::default()
+            },
+        };
+        let mut agg = AggregatedOrderBook::with_clock(clock.clone()).with_config(config);
+        let book = |exchange: Exchange, bid: f64, ask: f64| OrderBook {
+            last_update_id: 1,
+            bids: vec![OrderLevel {
+                exchange,
+                price: bid,
+                amount: 1.0,
+            }],
+            asks: vec![OrderLevel {
+                exchange,
+                price: ask,
+                amount: 1.0,
+            }],
+        };
+        agg.merge_snapshots(vec![
+            book(Exchange::Binance, 99.0, 101.0),
+            book(Exchange::Bitstamp, 101.0, 103.0),
+        ]);
+        agg
+    }
+
+    #[test]
+    fn index_price_weights_venue_mids() {
+        let clock = Arc::new(MockClock::new(1_000_000));
+        let equal = two_venue_book(&clock, &[]);
+        assert_eq!(equal.exchange_mid_price("binance"), Some(100.0));
+        assert_eq!(equal.exchange_mid_price("bitstamp"), Some(102.0));
+        assert_eq!(equal.get_index_price(), Some(101.0));
+
+        let weighted = two_venue_book(&clock, &[("binance", 3.0)]);
+        assert_eq!(weighted.get_index_price(), Some(100.5));
+        assert_eq!(weighted.get_top10_snapshot().index_price, Some(100.5));
+        assert_eq!(weighted.history.latest().unwrap().index_price, Some(100.5));
+
+        let excluded = two_venue_book(&clock, &[("bitstamp", 0.0)]);
+        assert_eq!(excluded.get_index_price(), Some(100.0));
+    }
+
+    #[test]
+    fn index_price_renormalizes_without_stale_venues() {
+        let clock = Arc::new(MockClock::new(1_000_000));
+        let mut agg = two_venue_book(&clock, &[("binance", 3.0), ("bitstamp", 1.0)]);
+
+        clock.advance(Duration::from_millis(1_500));
+        agg.handle_update(OrderBookUpdate {
+            exchange: Exchange::Bitstamp,
+            update_id: 2,
+            first_update_id: None,
+            received_at: None,
+            event_time: None,
+            bids: vec![],
+            asks: vec![],
+        })
+        .unwrap();
+        clock.advance(Duration::from_millis(1_000));
+        // Binance is stale: the index is Bitstamp's mid alone
+        assert_eq!(agg.get_index_price(), Some(102.0));
+
+        clock.advance(Duration::from_millis(2_000));
+        assert_eq!(agg.get_index_price(), None);
+        assert_eq!(agg.get_top10_snapshot().index_price, None);
+    }
+
+    #[test]
+    fn spread_conventions_only_change_how_a_crossed_spread_is_reported() {
+        use crate::modules::consistency;
+        use crate::modules::timeseries::SeriesKey;
+        for (convention, reported) in [
+            (SpreadConvention::Signed, -0.2),
+            (SpreadConvention::ClampZero, 0.0),
+            (SpreadConvention::Absolute, 0.2),
+        ] {
+            let mut agg = AggregatedOrderBook::new().with_spread_convention(convention);
+            agg.merge_snapshots(vec![
+                make_snapshot(Exchange::Binance),
+                make_snapshot(Exchange::Bitstamp),
+            ]);
+            // A Binance bid 0.2 through the best ask
+            agg.apply_update(OrderBookUpdate {
+                exchange: Exchange::Binance,
+                update_id: 112,
+                bids: vec![OrderLevel {
+                    exchange: Exchange::Binance,
+                    price: 100.7,
+                    amount: 1.0,
+                }],
+                ..Default::default()
+            })
+            .unwrap();
+
+            // The book and its stats history keep the signed value
+            let signed = agg.spread.unwrap();
+            assert!((signed + 0.2).abs() < 1e-9, "{:?}", convention);
+            assert_eq!(agg.history.latest().unwrap().spread, Some(signed));
+            assert_eq!(agg.book_state(), BookState::Crossed);
+
+            let snap = agg.get_top10_snapshot();
+            assert!(snap.crossed);
+            assert_eq!(snap.signed_spread, Some(signed));
+            assert!(
+                (snap.spread.unwrap() - reported).abs() < 1e-9,
+                "{:?}: {:?}",
+                convention,
+                snap.spread
+            );
+            assert!(consistency::check(&snap).is_empty(), "{:?}", convention);
+            let rounded = snap.into_display_rounded(1);
+            assert_eq!(rounded.signed_spread, Some(-0.2));
+            assert_eq!(rounded.spread, Some(reported));
+
+            let key = SeriesKey {
+                name: "spread".to_string(),
+                label: agg.config.symbol.clone(),
+            };
+            let series = agg.timeseries.query(&key, None, None, 0).unwrap();
+            assert!((series.last().unwrap().avg - reported).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn data_quality_is_judged_against_the_configured_exchanges() {
+        let clock = Arc::new(MockClock::new(1_000_000));
+        let quality = |snap: &Top10Snapshot| (snap.data_quality, snap.lagging_exchanges.clone());
+        let synced = two_venue_book(&clock, &[]);
+        assert_eq!(
+            quality(&synced.published_snapshot()),
+            (DataQuality::Full, vec![])
+        );
+
+        // Bitstamp never synced, so the book is partial even though every exchange with
+        // levels is fresh
+        let mut agg = AggregatedOrderBook::with_clock(clock.clone())
+            .with_configured_exchanges(["bitstamp", "binance"]);
+        let empty = agg.published_snapshot();
+        assert_eq!(empty.data_quality, DataQuality::Stale);
+        assert_eq!(empty.lagging_exchanges, vec!["bitstamp", "binance"]);
+        agg.merge_snapshots(vec![make_snapshot(Exchange::Binance)]);
+        assert_eq!(
+            quality(&agg.published_snapshot()),
+            (DataQuality::Partial, vec!["bitstamp".to_string()])
+        );
+        assert_eq!(agg.quality.quality, DataQuality::Partial);
+        agg.merge_snapshots(vec![make_snapshot(Exchange::Bitstamp)]);
+        assert_eq!(agg.published_snapshot().data_quality, DataQuality::Full);
+
+        // Freshness follows stale_after_ms
+        let mut agg =
+            two_venue_book(&clock, &[]).with_configured_exchanges(["bitstamp", "binance"]);
+        clock.advance(Duration::from_millis(1_500));
+        agg.handle_update(OrderBookUpdate {
+            exchange: Exchange::Bitstamp,
+            update_id: 2,
+            bids: vec![],
+            asks: vec![],
+            ..Default::default()
+        })
+        .unwrap();
+        clock.advance(Duration::from_millis(1_000));
+        assert_eq!(
+            quality(&agg.get_top10_snapshot()),
+            (DataQuality::Partial, vec!["binance".to_string()])
+        );
+        agg.expire_stale_exchanges(Duration::from_secs(2));
+        assert_eq!(agg.published_snapshot().data_quality, DataQuality::Partial);
+        assert_eq!(agg.quality.lagging, vec!["binance"]);
+        clock.advance(Duration::from_millis(2_000));
+        assert_eq!(agg.get_top10_snapshot().data_quality, DataQuality::Stale);
+        agg.remove_exchange("bitstamp");
+        assert_eq!(agg.quality.quality, DataQuality::Stale);
+    }
+
+    #[test]
+    fn depth_curve_samples_cumulative_amounts_around_the_mid() {
+        let config = SymbolConfig {
+            symbol: "ethbtc".to_string(),
+            settings: BookSettings {
+                price_scale: 100.0,
+                ..BookSettings::default()
+            },
+        };
+        let mut agg = AggregatedOrderBook::new().with_config(config);
+        let level = |exchange: Exchange, price: f64, amount: f64| OrderLevel {
+            exchange,
+            price,
+            amount,
+        };
+        agg.merge_snapshots(vec![
+            OrderBook {
+                last_update_id: 1,
+                bids: vec![
+                    level(Exchange::Binance, 99.5, 1.0),
+                    level(Exchange::Binance, 99.0, 2.0),
+                ],
+                asks: vec![
+                    level(Exchange::Binance, 100.5, 1.0),
+                    level(Exchange::Binance, 101.5, 4.0),
+                ],
+            },
+            OrderBook {
+                last_update_id: 1,
+                bids: vec![level(Exchange::Bitstamp, 99.5, 0.5)],
+                asks: vec![],
+            },
+        ]);
+        assert_eq!(agg.mid_price(), Some(100.0));
+
+        // 4 points over 200bps = $2 either side of the mid, every $0.50
+        let curve = agg.depth_curve(4, 200.0);
+        assert_eq!(
+            curve.asks,
+            vec![
+                (100.5, 1.0, 100.5),
+                (101.0, 1.0, 100.5),
+                (101.5, 5.0, 506.5)
+            ]
+        );
+        // Bids end at 99.0, so the curve stops there instead of repeating the total
+        assert_eq!(curve.bids, vec![(99.5, 1.5, 149.25), (99.0, 3.5, 347.25)]);
+
+        assert_eq!(agg.depth_curve(0, 200.0), DepthCurve::default());
+        assert_eq!(
+            AggregatedOrderBook::new().depth_curve(4, 200.0),
+            DepthCurve::default()
+        );
+    }
+
+    #[test]
+    fn derived_metrics_leave_out_levels_beyond_max_level_distance() {
+        let sparse = |max_level_distance_bps| {
+            let config = SymbolConfig {
+                symbol: "ethbtc".to_string(),
+                settings: BookSettings {
+                    price_scale: 100.0,
+                    max_level_distance_bps,
+                    ..BookSettings::default()
+                },
+            };
+            let mut agg = AggregatedOrderBook::new().with_config(config);
+            let level = |exchange: Exchange, price: f64, amount: f64| OrderLevel {
+                exchange,
+                price,
+                amount,
+            };
+            // A dead pair: Bitstamp's levels are 40% and more away from the mid
+            agg.merge_snapshots(vec![
+                OrderBook {
+                    last_update_id: 1,
+                    bids: vec![
+                        level(Exchange::Binance, 99.5, 1.0),
+                        level(Exchange::Binance, 96.0, 0.5),
+                    ],
+                    asks: vec![level(Exchange::Binance, 100.5, 1.0)],
+                },
+                OrderBook {
+                    last_update_id: 1,
+                    bids: vec![
+                        level(Exchange::Bitstamp, 60.0, 50.0),
+                        level(Exchange::Bitstamp, 40.0, 100.0),
+                    ],
+                    asks: vec![level(Exchange::Bitstamp, 150.0, 30.0)],
+                },
+            ]);
+            agg
+        };
+        let agg = sparse(Some(500.0));
+        assert_eq!(agg.mid_price(), Some(100.0));
+        let excluded = ExcludedLevels { bids: 2, asks: 1 };
+
+        let near = 99.5 + 96.0 * 0.5;
+        let expected = (near - 100.5) / (near + 100.5);
+        assert!((agg.notional_imbalance(10).unwrap() - expected).abs() < 1e-12);
+        assert_eq!(agg.imbalance_excluded_levels(10), excluded);
+        assert_eq!(agg.imbalance_excluded_levels(1), ExcludedLevels::default());
+
+        let shape = agg.book_shape_stats();
+        assert_eq!((shape.bids.levels, shape.asks.levels), (2, 1));
+        assert_eq!(shape.bids.mean_size, 0.75);
+        assert_eq!(
+            shape.exchange_share,
+            BTreeMap::from([("binance".to_string(), 1.0)])
+        );
+        assert_eq!(shape.excluded_levels, excluded);
+
+        // 4 points over 1000bps; the curve stops at 500bps instead of reaching for 60
+        let curve = agg.depth_curve(4, 1000.0);
+        assert_eq!(curve.bids, vec![(97.5, 1.0, 99.5), (95.0, 1.5, 147.5)]);
+        assert_eq!(curve.asks, vec![(102.5, 1.0, 100.5)]);
+        assert_eq!(curve.excluded_levels, excluded);
+
+        // Unset, every level counts as before and nothing is reported excluded
+        let agg = sparse(None);
+        assert_eq!(agg.imbalance_excluded_levels(10), ExcludedLevels::default());
+        assert_eq!(agg.book_shape_stats().bids.levels, 4);
+        let curve = agg.depth_curve(4, 1000.0);
+        assert_eq!(curve.bids.len(), 4);
+        assert_eq!(curve.bids[3], (90.0, 1.5, 147.5));
+        assert_eq!(curve.excluded_levels, ExcludedLevels::default());
+        assert!(agg.notional_imbalance(10).unwrap() > 0.0);
+    }
+
+    #[test]
+    fn depth_can_count_price_levels_or_entries() {
+        let mut agg = AggregatedOrderBook::new();
+        agg.merge_snapshots(vec![
+            make_snapshot(Exchange::Bitstamp),
+            make_snapshot(Exchange::Binance),
+        ]);
+        let prices = |levels: &[OrderLevel]| {
+            levels
+                .iter()
+                .map(|l| (l.exchange.as_str(), l.price))
+                .collect::<Vec<_>>()
+        };
+
+        // Both exchanges quote every price, so 10 price levels carry 20 entries
+        let by_levels = agg.get_snapshot(10, DepthUnit::PriceLevels);
+        assert_eq!(by_levels.bids.len(), 20);
+        assert_eq!(by_levels.asks.len(), 20);
+
+        let by_entries = agg.get_snapshot(10, DepthUnit::Entries);
+        assert_eq!(by_entries.bids.len(), 10);
+        assert_eq!(by_entries.asks.len(), 10);
+        assert_eq!(prices(&by_entries.bids), prices(&by_levels.bids[..10]));
+
+        // An odd count cuts the last price level part-way, keeping exchanges in name order
+        let cut = agg.get_snapshot(3, DepthUnit::Entries);
+        assert_eq!(
+            prices(&cut.bids),
+            vec![("binance", 100.0), ("bitstamp", 100.0), ("binance", 99.99)]
+        );
+        assert_eq!(
+            prices(&cut.asks),
+            vec![("binance", 100.5), ("bitstamp", 100.5), ("binance", 100.51)]
+        );
+
+        // Cutting a published snapshot gives the same result as building it cut
+        let mut published = agg.get_top10_snapshot();
+        published.truncate(3, DepthUnit::Entries);
+        assert_eq!(prices(&published.bids), prices(&cut.bids));
+        published.truncate(1, DepthUnit::PriceLevels);
+        assert_eq!(
+            prices(&published.bids),
+            vec![("binance", 100.0), ("bitstamp", 100.0)]
+        );
+        assert_eq!(published.exchanges, vec!["binance", "bitstamp"]);
+    }
+
+    fn boundary_book(policy: Option<BoundaryPolicy>) -> AggregatedOrderBook {
+        let mut agg = AggregatedOrderBook::new();
+        if let Some(policy) = policy {
+            agg = agg.with_boundary_policy(policy);
+        }
+        let level = |exchange| OrderLevel {
+            exchange,
+            price: 100.0,
+            amount: 1.0,
+        };
+        for exchange in [Exchange::Binance, Exchange::Bitstamp] {
+            agg.merge_snapshots(vec![OrderBook {
+                last_update_id: 100,
+                bids: vec![level(exchange)],
+                asks: vec![],
+            }]);
+        }
+        agg
+    }
+
+    fn diff(exchange: Exchange, first: Option<u64>, last: u64) -> OrderBookUpdate {
+        OrderBookUpdate {
+            exchange,
+            update_id: last,
+            first_update_id: first,
+            received_at: None,
+            event_time: None,
+            bids: vec![OrderLevel {
+                exchange,
+                price: 100.0,
+                amount: 2.0,
+            }],
+            asks: vec![],
+        }
+    }
+
+    #[test]
+    fn binance_applies_the_first_diff_overlapping_the_snapshot_id() {
+        let mut agg = boundary_book(None);
+        let apply = |agg: &mut AggregatedOrderBook, first, last| {
+            agg.apply_update(diff(Exchange::Binance, first, last))
+                .unwrap()
+        };
+        // Entirely before the snapshot: stale regardless of policy
+        assert_eq!(apply(&mut agg, Some(95), 99), UpdateOutcome::Stale);
+        assert_eq!(apply(&mut agg, Some(97), 100), UpdateOutcome::Applied);
+        assert_eq!(
+            crate::modules::replay::exchange_levels(&agg, Exchange::Binance, true),
+            vec![(100.0, 2.0)]
+        );
+        // Only the first diff gets the benefit; a repeat of the same id is stale
+        assert_eq!(apply(&mut agg, Some(100), 100), UpdateOutcome::Stale);
+        assert_eq!(apply(&mut agg, Some(101), 101), UpdateOutcome::Applied);
+
+        let mut strict = boundary_book(Some(BoundaryPolicy::Strict));
+        assert_eq!(apply(&mut strict, Some(97), 100), UpdateOutcome::Stale);
+        assert_eq!(apply(&mut strict, Some(101), 102), UpdateOutcome::Applied);
+
+        // A newer diff first ends the boundary window
+        let mut agg = boundary_book(None);
+        assert_eq!(apply(&mut agg, Some(101), 101), UpdateOutcome::Applied);
+        assert_eq!(apply(&mut agg, Some(101), 101), UpdateOutcome::Stale);
+    }
+
+    #[test]
+    fn gateio_is_sequenced_like_binance_but_keyed_apart_from_it() {
+        let mut agg = boundary_book(None);
+        agg.merge_snapshots(vec![OrderBook {
+            last_update_id: 5_000,
+            bids: vec![OrderLevel {
+                exchange: Exchange::GateIo,
+                price: 100.0,
+                amount: 1.0,
+            }],
+            asks: vec![],
+        }]);
+        let apply = |agg: &mut AggregatedOrderBook, exchange, first, last| {
+            agg.apply_update(diff(exchange, Some(first), last)).unwrap()
+        };
+        assert_eq!(
+            apply(&mut agg, Exchange::GateIo, 4_990, 4_999),
+            UpdateOutcome::Stale
+        );
+        assert_eq!(
+            apply(&mut agg, Exchange::GateIo, 4_998, 5_000),
+            UpdateOutcome::Applied
+        );
+        assert_eq!(
+            apply(&mut agg, Exchange::GateIo, 5_001, 5_003),
+            UpdateOutcome::Applied
+        );
+        // Binance's ids are its own: far below Gate.io's, and still current
+        assert_eq!(
+            apply(&mut agg, Exchange::Binance, 101, 102),
+            UpdateOutcome::Applied
+        );
+        assert_eq!(agg.last_update_id["gateio"], 5_003);
+        assert_eq!(agg.last_update_id["binance"], 102);
+        assert_eq!(
+            crate::modules::replay::exchange_levels(&agg, Exchange::GateIo, true),
+            vec![(100.0, 2.0)]
+        );
+    }
+
+    #[test]
+    fn bitfinex_frames_sharing_a_timestamp_all_apply() {
+        let mut agg = AggregatedOrderBook::new();
+        agg.merge_snapshots(vec![OrderBook {
+            last_update_id: 1_700_000_000_000,
+            bids: vec![OrderLevel {
+                exchange: Exchange::Bitfinex,
+                price: 100.0,
+                amount: 1.0,
+            }],
+            asks: vec![],
+        }]);
+        let apply = |agg: &mut AggregatedOrderBook, mts, price, amount| {
+            let mut update = diff(Exchange::Bitfinex, None, mts);
+            update.bids[0].price = price;
+            update.bids[0].amount = amount;
+            agg.apply_update(update).unwrap()
+        };
+        // Sent with the snapshot, then together: the timestamp repeats
+        assert_eq!(
+            apply(&mut agg, 1_700_000_000_000, 99.0, 3.0),
+            UpdateOutcome::Applied
+        );
+        assert_eq!(
+            apply(&mut agg, 1_700_000_000_100, 98.0, 1.0),
+            UpdateOutcome::Applied
+        );
+        assert_eq!(
+            apply(&mut agg, 1_700_000_000_100, 100.0, 0.0),
+            UpdateOutcome::Applied
+        );
+        assert_eq!(
+            apply(&mut agg, 1_700_000_000_099, 97.0, 1.0),
+            UpdateOutcome::Stale
+        );
+        assert_eq!(
+            crate::modules::replay::exchange_levels(&agg, Exchange::Bitfinex, true),
+            vec![(99.0, 3.0), (98.0, 1.0)]
+        );
+    }
+
+    #[test]
+    fn bitstamp_treats_an_equal_microtimestamp_as_stale_unless_configured() {
+        let apply = |agg: &mut AggregatedOrderBook, last| {
+            agg.apply_update(diff(Exchange::Bitstamp, None, last))
+                .unwrap()
+        };
+        let mut agg = boundary_book(None);
+        assert_eq!(apply(&mut agg, 100), UpdateOutcome::Stale);
+        assert_eq!(apply(&mut agg, 101), UpdateOutcome::Applied);
+
+        let mut overlapping = boundary_book(Some(BoundaryPolicy::ApplyIfOverlapping));
+        assert_eq!(apply(&mut overlapping, 100), UpdateOutcome::Applied);
+        assert_eq!(apply(&mut overlapping, 100), UpdateOutcome::Stale);
+
+        assert_eq!(
+            "apply_if_overlapping".parse::<BoundaryPolicy>(),
+            Ok(BoundaryPolicy::ApplyIfOverlapping)
+        );
+        assert!("lenient".parse::<BoundaryPolicy>().is_err());
+    }
+
+    #[test]
+    fn level_details_count_contributors_and_age_the_newest_change() {
+        let clock = Arc::new(MockClock::new(1_000));
+        let mut agg = AggregatedOrderBook::with_clock(clock.clone());
+        let level = |exchange, price, amount| OrderLevel {
+            exchange,
+            price,
+            amount,
+        };
+        agg.merge_snapshots(vec![OrderBook {
+            last_update_id: 1,
+            bids: vec![level(Exchange::Binance, 100.0, 1.0)],
+            asks: vec![level(Exchange::Binance, 101.0, 1.0)],
+        }]);
+        clock.advance(Duration::from_millis(300));
+        agg.merge_snapshots(vec![OrderBook {
+            last_update_id: 1,
+            bids: vec![level(Exchange::Bitstamp, 100.0, 2.0)],
+            asks: vec![],
+        }]);
+        clock.advance(Duration::from_millis(200));
+        agg.handle_update(OrderBookUpdate {
+            exchange: Exchange::Binance,
+            update_id: 2,
+            first_update_id: None,
+            received_at: None,
+            event_time: None,
+            bids: vec![],
+            asks: vec![level(Exchange::Binance, 101.0, 3.0)],
+        })
+        .unwrap();
+        clock.advance(Duration::from_millis(50));
+
+        // Both venues quote 100.0; Bitstamp changed last, 250ms ago
+        let snap = agg.get_top10_snapshot();
+        let detail = |contributor_count, age, update_id| LevelDetail {
+            contributor_count,
+            newest_age_ms: Some(age),
+            origin: Some(LevelOrigin {
+                update_id,
+                event_time: None,
+            }),
+        };
+        assert_eq!(snap.bids.len(), 2);
+        assert_eq!(snap.bid_details, vec![detail(2, 250, 1); 2]);
+        assert_eq!(snap.ask_details, vec![detail(1, 50, 2)]);
+
+        // Once Bitstamp leaves, Binance's 550ms-old entry is the newest
+        agg.handle_update(OrderBookUpdate {
+            exchange: Exchange::Bitstamp,
+            update_id: 2,
+            first_update_id: None,
+            received_at: None,
+            event_time: None,
+            bids: vec![level(Exchange::Bitstamp, 100.0, 0.0)],
+            asks: vec![],
+        })
+        .unwrap();
+        let mut snap = agg.get_top10_snapshot();
+        assert_eq!(snap.bid_details, vec![detail(1, 550, 1)]);
+        snap.truncate(0, DepthUnit::Entries);
+        assert!(snap.bid_details.is_empty() && snap.ask_details.is_empty());
+    }
+
+    #[test]
+    fn prices_snapped_to_the_tick_consolidate_and_keep_each_exchange_price() {
+        let level = |exchange, price| OrderLevel {
+            exchange,
+            price,
+            amount: 1.0,
+        };
+        // Binance quotes ethbtc to 6 decimals, the other venue to 8
+        let snapshot = || OrderBook {
+            last_update_id: 1,
+            bids: vec![
+                level(Exchange::Binance, 0.051234),
+                level(Exchange::Bitstamp, 0.05123471),
+            ],
+            asks: vec![
+                level(Exchange::Binance, 0.051236),
+                level(Exchange::Bitstamp, 0.05123529),
+            ],
+        };
+        let book = |round_to_tick| {
+            let mut agg = AggregatedOrderBook::new().with_config(SymbolConfig {
+                symbol: "ethbtc".to_string(),
+                settings: BookSettings {
+                    tick_size: Some(0.000001),
+                    round_to_tick,
+                    ..BookSettings::default()
+                },
+            });
+            agg.merge_snapshots(vec![snapshot()]);
+            agg
+        };
+
+        let prices = |levels: &[OrderLevel]| levels.iter().map(|l| l.price).collect::<Vec<_>>();
+        let raw = book(false).get_top10_snapshot();
+        assert_eq!((raw.bids.len(), raw.total_bid_levels), (2, 2), "two levels");
+        assert!(raw.raw_bid_prices.is_empty());
+
+        let mut agg = book(true);
+        let snap = agg.get_top10_snapshot();
+        assert_eq!((snap.total_bid_levels, snap.total_ask_levels), (1, 1));
+        // Bids snap down and asks up, so neither side looks better than quoted
+        assert_eq!(prices(&snap.bids), vec![0.051234; 2]);
+        assert_eq!(prices(&snap.asks), vec![0.051236; 2]);
+        let raw_of = |snap: &Top10Snapshot, exchange: Exchange| {
+            let i = snap
+                .bids
+                .iter()
+                .position(|l| l.exchange == exchange)
+                .unwrap();
+            snap.raw_bid_prices[i]
+        };
+        assert_eq!(raw_of(&snap, Exchange::Bitstamp), 0.05123471);
+        assert_eq!(raw_of(&snap, Exchange::Binance), 0.051234);
+
+        // A diff at the raw price updates the same entry, and removing it forgets its price
+        agg.handle_update(OrderBookUpdate {
+            exchange: Exchange::Bitstamp,
+            update_id: 2,
+            bids: vec![level(Exchange::Bitstamp, 0.05123488)],
+            ..Default::default()
+        })
+        .unwrap();
+        let snap = agg.get_top10_snapshot();
+        assert_eq!(snap.total_bid_levels, 1);
+        assert_eq!(raw_of(&snap, Exchange::Bitstamp), 0.05123488);
+        agg.handle_update(OrderBookUpdate {
+            exchange: Exchange::Bitstamp,
+            update_id: 3,
+            bids: vec![OrderLevel {
+                amount: 0.0,
+                ..level(Exchange::Bitstamp, 0.05123488)
+            }],
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(agg.get_top10_snapshot().bids.len(), 1);
+        assert_eq!(
+            agg.raw_prices.len(),
+            1,
+            "only Bitstamp's ask is off the tick"
+        );
+    }
+
+    #[test]
+    fn each_entry_records_the_update_that_last_wrote_it() {
+        let mut agg = AggregatedOrderBook::new();
+        let level = |exchange, amount| OrderLevel {
+            exchange,
+            price: 100.0,
+            amount,
+        };
+        agg.merge_snapshots(vec![OrderBook {
+            last_update_id: 10,
+            bids: vec![
+                level(Exchange::Binance, 1.0),
+                level(Exchange::Bitstamp, 1.0),
+            ],
+            asks: vec![],
+        }]);
+        let update = |update_id, amount| OrderBookUpdate {
+            exchange: Exchange::Binance,
+            update_id,
+            event_time: Some(5_000 + update_id),
+            bids: vec![level(Exchange::Binance, amount)],
+            ..Default::default()
+        };
+        for (update_id, amount) in [(11, 2.0), (12, 3.0), (13, 2.5)] {
+            agg.handle_update(update(update_id, amount)).unwrap();
+        }
+        let origin = |agg: &AggregatedOrderBook, exchange: Exchange| {
+            let idx = AggregatedOrderBook::price_index(100.0, agg.config.settings.price_scale);
+            agg.level_origins
+                .get(&(Side::Bid, idx, exchange.to_string()))
+                .copied()
+        };
+        assert_eq!(
+            origin(&agg, Exchange::Binance),
+            Some(LevelOrigin {
+                update_id: 13,
+                event_time: Some(5_013),
+            })
+        );
+        // Bitstamp's entry at the same price is still the snapshot's
+        assert_eq!(
+            origin(&agg, Exchange::Bitstamp),
+            Some(LevelOrigin {
+                update_id: 10,
+                event_time: None,
+            })
+        );
+        let snap = agg.get_top10_snapshot();
+        for (level, detail) in snap.bids.iter().zip(&snap.bid_details) {
+            assert_eq!(detail.origin, origin(&agg, level.exchange));
+        }
+
+        // Removing the entry forgets where it came from
+        agg.handle_update(update(14, 0.0)).unwrap();
+        assert_eq!(origin(&agg, Exchange::Binance), None);
+    }
+
+    #[test]
+    fn price_improvement_is_signed_against_each_venue() {
+        let clock = Arc::new(MockClock::new(1_000));
+        let mut agg = AggregatedOrderBook::with_clock(clock.clone())
+            .with_improvement_sampling(2.0, Duration::from_secs(1));
+        let level = |exchange: Exchange, price: f64, amount: f64| OrderLevel {
+            exchange,
+            price,
+            amount,
+        };
+        // Binance is strictly better on both sides; Binance.US only quotes one thin bid
+        agg.merge_snapshots(vec![
+            OrderBook {
+                last_update_id: 1,
+                bids: vec![
+                    level(Exchange::Binance, 101.0, 1.0),
+                    level(Exchange::Binance, 100.0, 1.0),
+                ],
+                asks: vec![
+                    level(Exchange::Binance, 102.0, 1.0),
+                    level(Exchange::Binance, 103.0, 1.0),
+                ],
+            },
+            OrderBook {
+                last_update_id: 1,
+                bids: vec![
+                    level(Exchange::Bitstamp, 100.0, 0.5),
+                    level(Exchange::Bitstamp, 99.0, 2.0),
+                ],
+                asks: vec![
+                    level(Exchange::Bitstamp, 103.0, 0.5),
+                    level(Exchange::Bitstamp, 104.0, 2.0),
+                ],
+            },
+            OrderBook {
+                last_update_id: 1,
+                bids: vec![level(Exchange::BinanceUs, 90.0, 1.0)],
+                asks: vec![],
+            },
+        ]);
+
+        let improvement = agg.price_improvement(2.0);
+        let (bids, asks) = (&improvement.bids, &improvement.asks);
+        assert_eq!((bids.best_price, bids.vwap), (Some(101.0), Some(100.5)));
+        assert_eq!((asks.best_price, asks.vwap), (Some(102.0), Some(102.5)));
+
+        let binance = &bids.venues["binance"];
+        assert_eq!(
+            (binance.best_delta, binance.vwap_delta),
+            (Some(0.0), Some(0.0))
+        );
+        // Bitstamp alone sells 2 at 99.25 and buys 2 at 103.75
+        let bitstamp = &bids.venues["bitstamp"];
+        assert_eq!(bitstamp.vwap, Some(99.25));
+        assert_eq!(
+            (bitstamp.best_delta, bitstamp.vwap_delta),
+            (Some(1.0), Some(1.25))
+        );
+        assert_eq!(bitstamp.best_delta_bps, Some(100.0));
+        assert!((bitstamp.vwap_delta_bps.unwrap() - 1.25 / 99.25 * 10_000.0).abs() < 1e-9);
+        let bitstamp = &asks.venues["bitstamp"];
+        assert_eq!(
+            (bitstamp.best_delta, bitstamp.vwap_delta),
+            (Some(1.0), Some(1.25)),
+            "a cheaper aggregated ask is a positive delta too"
+        );
+
+        // Too thin to fill the clip, and absent from the asks
+        let binance_us = &bids.venues["binance_us"];
+        assert_eq!(binance_us.best_delta, Some(11.0));
+        assert_eq!((binance_us.vwap, binance_us.vwap_delta), (None, None));
+        assert_eq!(asks.venues["binance_us"], VenueImprovement::default());
+
+        let sampled = agg.stats().price_improvement.unwrap();
+        assert_eq!(sampled.improvement, improvement);
+        let empty = AggregatedOrderBook::new().price_improvement(1.0);
+        assert_eq!((empty.bids.best_price, empty.bids.venues.len()), (None, 0));
+    }
+
+    #[test]
+    fn smart_best_sums_across_exchanges_over_the_whole_book() {
+        let settings = |smart_best_min_qty| SymbolConfig {
+            symbol: "ethbtc".to_string(),
+            settings: BookSettings {
+                price_scale: 100.0,
+                smart_best_min_qty,
+                ..BookSettings::default()
+            },
+        };
+        let level = |exchange: Exchange, price: f64, amount: f64| OrderLevel {
+            exchange,
+            price,
+            amount,
+        };
+        let mut agg = AggregatedOrderBook::new().with_config(settings(Some(2.0)));
+        agg.merge_snapshots(vec![
+            OrderBook {
+                last_update_id: 1,
+                bids: vec![
+                    level(Exchange::Binance, 100.0, 0.01),
+                    level(Exchange::Binance, 99.0, 1.5),
+                ],
+                asks: vec![level(Exchange::Binance, 101.0, 2.5)],
+            },
+            OrderBook {
+                last_update_id: 1,
+                // Deeper than the 10-level ladder still counts
+                bids: (0..12)
+                    .map(|i| level(Exchange::Bitstamp, 98.9 - i as f64 * 0.1, 0.008))
+                    .chain([level(Exchange::Bitstamp, 99.0, 0.4)])
+                    .collect(),
+                asks: vec![],
+            },
+        ]);
+        let snap = agg.get_top10_snapshot();
+        // 0.01 at 100, 1.5 + 0.4 from two exchanges at 99, then 0.008 a level until the
+        // 12th, the 14th price level
+        assert_eq!(snap.bids[0].price, 100.0);
+        // Reported at the level's stored price, like the ladder, not as idx / price_scale
+        assert_eq!(snap.smart_best_bid, Some(98.9 - 11.0 * 0.1));
+        assert_eq!(snap.smart_best_ask, Some(101.0), "the raw best qualifies");
+
+        agg.apply_settings(settings(Some(10.0)).settings).unwrap();
+        let snap = agg.get_top10_snapshot();
+        assert_eq!((snap.smart_best_bid, snap.smart_best_ask), (None, None));
+        agg.apply_settings(settings(None).settings).unwrap();
+        assert_eq!(agg.smart_best(Side::Bid), None, "off unless configured");
+    }
+
+    #[test]
+    fn book_shape_stats_describe_levels_and_exchange_shares() {
+        let clock = Arc::new(MockClock::new(1_000));
+        let config = SymbolConfig {
+            symbol: "ethbtc".to_string(),
+            settings: BookSettings {
+                price_scale: 100.0,
+                ..BookSettings::default()
+            },
+        };
+        let mut agg = AggregatedOrderBook::with_clock(clock.clone())
+            .with_config(config)
+            .with_shape_sampling(Duration::from_secs(1));
+        let level = |exchange: Exchange, price: f64, amount: f64| OrderLevel {
+            exchange,
+            price,
+            amount,
+        };
+        agg.merge_snapshots(vec![
+            OrderBook {
+                last_update_id: 1,
+                bids: vec![
+                    level(Exchange::Binance, 100.0, 1.0),
+                    level(Exchange::Binance, 99.0, 2.0),
+                ],
+                // 12 asks of 1.0 every $0.50 from 101.0
+                asks: (0..12)
+                    .map(|i| level(Exchange::Binance, 101.0 + i as f64 * 0.5, 1.0))
+                    .collect(),
+            },
+            OrderBook {
+                last_update_id: 1,
+                bids: vec![
+                    level(Exchange::Bitstamp, 100.0, 3.0),
+                    level(Exchange::Bitstamp, 98.0, 10.0),
+                ],
+                asks: vec![],
+            },
+        ]);
+
+        let shape = agg.book_shape_stats();
+        // Bid levels: 100 -> 4.0 (both venues), 99 -> 2.0, 98 -> 10.0
+        assert_eq!(shape.bids.levels, 3);
+        assert!((shape.bids.mean_size - 16.0 / 3.0).abs() < 1e-12);
+        assert_eq!(shape.bids.median_size, 4.0);
+        assert_eq!(shape.bids.top10_distance, None);
+        assert_eq!(shape.asks.levels, 12);
+        assert_eq!(shape.asks.mean_size, 1.0);
+        assert_eq!(shape.asks.median_size, 1.0);
+        assert_eq!(shape.asks.top10_distance, Some(4.5));
+        // binance 1 + 2 + 12 = 15, bitstamp 13, of 28
+        assert!((shape.exchange_share["binance"] - 15.0 / 28.0).abs() < 1e-12);
+        assert!((shape.exchange_share["bitstamp"] - 13.0 / 28.0).abs() < 1e-12);
+
+        // Sampled at the merge, then at most once per interval
+        assert_eq!(agg.stats().shape.unwrap().shape, shape);
+        let remove_bitstamp_98 = |update_id| OrderBookUpdate {
+            exchange: Exchange::Bitstamp,
+            update_id,
+            first_update_id: None,
+            received_at: None,
+            event_time: None,
+            bids: vec![level(Exchange::Bitstamp, 98.0, 0.0)],
+            asks: vec![],
+        };
+        agg.handle_update(remove_bitstamp_98(2)).unwrap();
+        assert_eq!(agg.history.shapes().count(), 1);
+        clock.advance(Duration::from_secs(1));
+        agg.handle_update(OrderBookUpdate {
+            bids: vec![level(Exchange::Bitstamp, 97.0, 1.0)],
+            ..remove_bitstamp_98(3)
+        })
+        .unwrap();
+        let stats = agg.stats();
+        assert_eq!(agg.history.shapes().count(), 2);
+        assert_eq!(stats.shape.unwrap().shape.bids.levels, 3);
+        assert_eq!(stats.latest.unwrap().version, agg.version);
+    }
+
+    #[test]
+    fn depth_weights_scale_consolidated_figures_and_leave_venues_raw() {
+        let config = SymbolConfig {
+            symbol: "ethbtc".to_string(),
+            settings: BookSettings {
+                depth_weights: BTreeMap::from([("bitstamp".to_string(), 0.5)]),
+                ..BookSettings::default()
+            },
+        };
+        let mut agg = AggregatedOrderBook::new().with_config(config);
+        let level = |exchange: Exchange, price: f64| OrderLevel {
+            exchange,
+            price,
+            amount: 2.0,
+        };
+        agg.merge_snapshots(vec![
+            OrderBook {
+                last_update_id: 1,
+                bids: vec![level(Exchange::Binance, 100.0)],
+                asks: vec![level(Exchange::Binance, 101.0)],
+            },
+            OrderBook {
+                last_update_id: 1,
+                bids: vec![
+                    level(Exchange::Bitstamp, 100.0),
+                    level(Exchange::Bitstamp, 99.0),
+                ],
+                asks: vec![
+                    level(Exchange::Bitstamp, 101.0),
+                    level(Exchange::Bitstamp, 102.0),
+                ],
+            },
+        ]);
+        let weights = BTreeMap::from([("bitstamp".to_string(), 0.5)]);
+
+        // The ladder itself and per-exchange totals stay raw
+        let snap = agg.get_top10_snapshot();
+        assert_eq!(amounts(&snap.bids), vec![2.0, 2.0, 2.0]);
+        assert_eq!(snap.depth_weights, weights);
+        let per_exchange = snap.clone().into_cumulative(CumulativeScope::PerExchange);
+        assert_eq!(amounts(&per_exchange.bids), vec![2.0, 2.0, 4.0]);
+        let consolidated = snap.into_cumulative(CumulativeScope::Consolidated);
+        assert_eq!(amounts(&consolidated.bids), vec![2.0, 3.0, 4.0]);
+        assert_eq!(amounts(&consolidated.asks), vec![2.0, 3.0, 4.0]);
+
+        // A clip of 4 now reaches past the top price: 3 at 100 and 1 at 99
+        let improvement = agg.price_improvement(4.0);
+        assert_eq!(improvement.bids.vwap, Some(99.75));
+        assert_eq!(improvement.asks.vwap, Some(101.25));
+        assert_eq!(improvement.bids.venues["bitstamp"].vwap, Some(99.5));
+        assert_eq!(improvement.depth_weights, weights);
+
+        // bids 100 * 3, asks 101 * 3
+        let imbalance = agg.notional_imbalance(1).unwrap();
+        assert!((imbalance - (300.0 - 303.0) / 603.0).abs() < 1e-12);
+        let curve = agg.depth_curve(2, 200.0);
+        let totals: Vec<_> = curve.bids.iter().map(|(_, amount, _)| *amount).collect();
+        assert_eq!(totals, vec![3.0, 4.0]);
+        assert_eq!(curve.depth_weights, weights);
+        let shape = agg.book_shape_stats();
+        assert_eq!(shape.bids.mean_size, 2.0);
+        assert_eq!(
+            shape.exchange_share["bitstamp"],
+            8.0 / 12.0,
+            "shares stay raw"
+        );
+        assert_eq!(agg.stats().depth_weights, weights);
+    }
+
+    #[test]
+    fn notional_imbalance_covers_the_top_levels_and_is_throttled() {
+        let clock = Arc::new(MockClock::new(1_000));
+        let config = SymbolConfig {
+            symbol: "ethbtc".to_string(),
+            settings: BookSettings {
+                price_scale: 100.0,
+                ..BookSettings::default()
+            },
+        };
+        let mut agg = AggregatedOrderBook::with_clock(clock.clone())
+            .with_config(config)
+            .with_imbalance_gauge(
+                2,
+                ThrottleConfig {
+                    min_interval: Duration::from_secs(1),
+                    min_delta: 0.05,
+                },
+            );
+        let level = |price: f64, amount: f64| OrderLevel {
+            exchange: Exchange::Binance,
+            price,
+            amount,
+        };
+        assert_eq!(agg.notional_imbalance(2), None);
+        agg.merge_snapshots(vec![OrderBook {
+            last_update_id: 1,
+            // The third bid level is outside the top 2
+            bids: vec![level(100.0, 3.0), level(99.0, 1.0), level(98.0, 50.0)],
+            asks: vec![level(101.0, 1.0), level(102.0, 1.0)],
+        }]);
+        // bids 300 + 99 = 399, asks 101 + 102 = 203
+        let expected = (399.0 - 203.0) / 602.0;
+        assert!((agg.notional_imbalance(2).unwrap() - expected).abs() < 1e-12);
+        assert_eq!(agg.stats().imbalance.unwrap().value, expected);
+
+        let ask_update = |update_id, amount| OrderBookUpdate {
+            exchange: Exchange::Binance,
+            update_id,
+            first_update_id: None,
+            received_at: None,
+            event_time: None,
+            bids: vec![],
+            asks: vec![level(101.0, amount)],
+        };
+        // A large move within the second is held back; the next change after it publishes
+        agg.handle_update(ask_update(2, 4.0)).unwrap();
+        assert_eq!(agg.stats().imbalance.unwrap().at, 1_000);
+        clock.advance(Duration::from_secs(1));
+        agg.handle_update(ask_update(3, 4.5)).unwrap();
+        let published = agg.stats().imbalance.unwrap();
+        assert_eq!(published.at, 2_000);
+        assert!(published.value < 0.0, "asks now outweigh bids");
+    }
+
+    fn mixed_ladder() -> Top10Snapshot {
+        let level = |exchange, price, amount| OrderLevel {
+            exchange,
+            price,
+            amount,
+        };
+        Top10Snapshot {
+            bids: vec![
+                level(Exchange::Binance, 100.0, 1.0),
+                level(Exchange::Bitstamp, 100.0, 2.0),
+                level(Exchange::Binance, 99.0, 3.0),
+                level(Exchange::Bitstamp, 98.0, 4.0),
+            ],
+            asks: vec![
+                level(Exchange::Bitstamp, 101.0, 0.5),
+                level(Exchange::Binance, 102.0, 1.5),
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn amounts(levels: &[OrderLevel]) -> Vec<f64> {
+        levels.iter().map(|l| l.amount).collect()
+    }
+
+    #[test]
+    fn cumulative_ladders_sum_across_or_within_exchanges() {
+        let plain = mixed_ladder();
+        assert_eq!(plain.cumulative, None);
+
+        let consolidated = mixed_ladder().into_cumulative(CumulativeScope::Consolidated);
+        assert_eq!(amounts(&consolidated.bids), vec![1.0, 3.0, 6.0, 10.0]);
+        assert_eq!(amounts(&consolidated.asks), vec![0.5, 2.0]);
+        assert_eq!(consolidated.cumulative, Some(CumulativeScope::Consolidated));
+
+        let per_exchange = mixed_ladder().into_cumulative(CumulativeScope::PerExchange);
+        // binance 1, 1+3; bitstamp 2, 2+4
+        assert_eq!(amounts(&per_exchange.bids), vec![1.0, 2.0, 4.0, 6.0]);
+        assert_eq!(amounts(&per_exchange.asks), vec![0.5, 1.5]);
+        // Prices and order are untouched
+        let prices = |levels: &[OrderLevel]| levels.iter().map(|l| l.price).collect::<Vec<_>>();
+        assert_eq!(prices(&per_exchange.bids), prices(&plain.bids));
+    }
+
+    #[test]
+    fn per_exchange_totals_match_a_ladder_filtered_to_that_exchange() {
+        let only = |mut snap: Top10Snapshot, exchange: Exchange| {
+            snap.bids.retain(|l| l.exchange == exchange);
+            snap.asks.retain(|l| l.exchange == exchange);
+            snap
+        };
+        for exchange in [Exchange::Binance, Exchange::Bitstamp] {
+            let filtered_first =
+                only(mixed_ladder(), exchange).into_cumulative(CumulativeScope::Consolidated);
+            let cumulated_first = only(
+                mixed_ladder().into_cumulative(CumulativeScope::PerExchange),
+                exchange,
+            );
+            assert_eq!(filtered_first.bids, cumulated_first.bids);
+            assert_eq!(filtered_first.asks, cumulated_first.asks);
+        }
+        // Consolidated totals of the full ladder do depend on the other exchange
+        let consolidated = only(
+            mixed_ladder().into_cumulative(CumulativeScope::Consolidated),
+            Exchange::Bitstamp,
+        );
+        assert_eq!(amounts(&consolidated.bids), vec![3.0, 10.0]);
+    }
+
+    #[test]
+    fn display_rounding_moves_bids_down_and_asks_up() {
+        let level = |price: f64| OrderLevel {
+            exchange: Exchange::Binance,
+            price,
+            amount: 1.0,
+        };
+        let snap = Top10Snapshot {
+            spread: Some(0.0337),
+            bids: vec![level(100.0049), level(0.29), level(99.0)],
+            asks: vec![level(100.0386), level(100.04)],
+            index_price: Some(100.0217),
+            smart_best_bid: Some(99.0049),
+            smart_best_ask: Some(100.0511),
+            ..Default::default()
+        };
+        let rounded = snap.clone().into_display_rounded(2);
+        let prices = |levels: &[OrderLevel]| levels.iter().map(|l| l.price).collect::<Vec<_>>();
+        assert_eq!(
+            prices(&rounded.bids),
+            vec![100.0, 0.29, 99.0],
+            "exact multiples are kept despite float noise"
+        );
+        assert_eq!(prices(&rounded.asks), vec![100.04, 100.04]);
+        assert_eq!(rounded.raw_bid_prices, prices(&snap.bids));
+        assert_eq!(rounded.raw_ask_prices, prices(&snap.asks));
+        assert_eq!(rounded.spread, Some(0.04));
+        assert_eq!(rounded.index_price, Some(100.02));
+        assert_eq!(
+            (rounded.smart_best_bid, rounded.smart_best_ask),
+            (Some(99.0), Some(100.06))
+        );
+
+        // Truncation keeps raw prices aligned with their levels
+        let mut cut = snap.into_display_rounded(0);
+        assert_eq!((cut.bids[0].price, cut.asks[0].price), (100.0, 101.0));
+        cut.truncate(1, DepthUnit::Entries);
+        assert_eq!(cut.raw_ask_prices, vec![100.0386]);
+    }
+
+    #[test]
+    fn inversion_swaps_sides_converts_amounts_and_round_trips() {
+        let mut snap = mixed_ladder();
+        snap.total_bid_levels = 3;
+        snap.total_ask_levels = 2;
+        snap.bid_levels_by_exchange = BTreeMap::from([("binance".to_string(), 2)]);
+        snap.index_price = Some(100.5);
+        snap.smart_best_bid = Some(99.0);
+        snap.spread = Some(1.0);
+
+        let inverted = snap.clone().into_inverted();
+        assert!(inverted.inverted);
+        // The best ask at 101 becomes the best bid at 1/101, for 0.5 * 101 of the quote
+        assert_eq!(
+            inverted.bids[0],
+            OrderLevel {
+                exchange: Exchange::Bitstamp,
+                price: 1.0 / 101.0,
+                amount: 50.5,
+            }
+        );
+        let prices = |levels: &[OrderLevel]| levels.iter().map(|l| l.price).collect::<Vec<_>>();
+        assert_eq!(prices(&inverted.bids), vec![1.0 / 101.0, 1.0 / 102.0]);
+        assert_eq!(
+            prices(&inverted.asks),
+            vec![1.0 / 100.0, 1.0 / 100.0, 1.0 / 99.0, 1.0 / 98.0],
+            "still best first, the tie at 100 in tie-break order"
+        );
+        assert_eq!(amounts(&inverted.asks), vec![100.0, 200.0, 297.0, 392.0]);
+        assert_eq!(
+            inverted.spread,
+            Some(inverted.asks[0].price - inverted.bids[0].price)
+        );
+        assert_eq!(
+            (inverted.total_bid_levels, inverted.total_ask_levels),
+            (2, 3)
+        );
+        assert_eq!(inverted.ask_levels_by_exchange["binance"], 2);
+        assert!(inverted.bid_levels_by_exchange.is_empty());
+        assert_eq!(inverted.index_price, Some(1.0 / 100.5));
+        assert_eq!(
+            (inverted.smart_best_bid, inverted.smart_best_ask),
+            (None, Some(1.0 / 99.0))
+        );
+        assert_eq!(
+            inverted.checksum,
+            checksum::book_checksum(&inverted.bids, &inverted.asks)
+        );
+
+        let back = inverted.into_inverted();
+        assert!(!back.inverted);
+        let close = |a: f64, b: f64| (a - b).abs() <= 1e-12 * b.abs();
+        for (side, original) in [(&back.bids, &snap.bids), (&back.asks, &snap.asks)] {
+            assert_eq!(side.len(), original.len());
+            for (level, original) in side.iter().zip(original) {
+                assert_eq!(level.exchange, original.exchange);
+                assert!(close(level.price, original.price), "{:?}", level);
+                assert!(close(level.amount, original.amount), "{:?}", level);
+            }
+        }
+        assert!(close(back.spread.unwrap(), 1.0));
+        assert!(close(back.index_price.unwrap(), 100.5));
+        assert!(close(back.smart_best_bid.unwrap(), 99.0));
+    }
+
+    #[test]
+    fn an_inverted_depth_curve_trades_amounts_and_notionals() {
+        let curve = DepthCurve {
+            bids: vec![(99.0, 2.0, 198.0)],
+            asks: vec![(101.0, 1.0, 101.0), (102.0, 3.0, 407.0)],
+            notional_unit: NotionalUnit::Quote,
+            depth_weights: BTreeMap::new(),
+            excluded_levels: ExcludedLevels { bids: 0, asks: 2 },
+        };
+        let inverted = curve.clone().into_inverted();
+        assert_eq!(
+            inverted.excluded_levels,
+            ExcludedLevels { bids: 2, asks: 0 }
+        );
+        assert_eq!(
+            inverted.bids,
+            vec![(1.0 / 101.0, 101.0, 1.0), (1.0 / 102.0, 407.0, 3.0)]
+        );
+        assert_eq!(inverted.asks, vec![(1.0 / 99.0, 198.0, 2.0)]);
+        assert_eq!(inverted.into_inverted().bids[0].1, curve.bids[0].1);
+    }
 }
@@ -0,0 +1,758 @@
+use crate::modules::clock::SharedClock;
+use crate::modules::types::{AggregatedOrderBook, OrderLevel};
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+/// Raised alarms waiting for the dispatcher; further ones are dropped rather than block the
+/// component raising them
+pub const ALARM_QUEUE_CAPACITY: usize = 64;
+
+/// How long an alarm raised right before the process exits may take to be delivered
+pub const ALARM_FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What an alarm is about; the keys of `alarms.events` in the config file
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlarmKind {
+    AllExchangesStale,
+    BookCrossed,
+    GrpcUnavailable,
+    Quarantined,
+}
+
+impl AlarmKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlarmKind::AllExchangesStale => "all_exchanges_stale",
+            AlarmKind::BookCrossed => "book_crossed",
+            AlarmKind::GrpcUnavailable => "grpc_unavailable",
+            AlarmKind::Quarantined => "quarantined",
+        }
+    }
+}
+
+/// A critical condition, raised once when it starts
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AlarmEvent {
+    /// None of the exchanges has sent data within the staleness limit
+    AllExchangesStale {
+        exchanges: Vec<String>,
+        stale_after_ms: u64,
+    },
+    /// The best bid has stayed above the best ask for longer than allowed
+    BookCrossed {
+        best_bid: f64,
+        best_ask: f64,
+        crossed_for_ms: u64,
+    },
+    /// A public gRPC listener failed, so clients can't connect
+    GrpcUnavailable { reason: String },
+    /// An exchange was quarantined after a run of stale updates
+    Quarantined {
+        exchange: String,
+        until: u64, // unix millis
+    },
+}
+
+impl AlarmEvent {
+    pub fn kind(&self) -> AlarmKind {
+        match self {
+            AlarmEvent::AllExchangesStale { .. } => AlarmKind::AllExchangesStale,
+            AlarmEvent::BookCrossed { .. } => AlarmKind::BookCrossed,
+            AlarmEvent::GrpcUnavailable { .. } => AlarmKind::GrpcUnavailable,
+            AlarmEvent::Quarantined { .. } => AlarmKind::Quarantined,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            AlarmEvent::AllExchangesStale {
+                exchanges,
+                stale_after_ms,
+            } => format!(
+                "no data from any exchange ({}) for over {}ms",
+                exchanges.join(", "),
+                stale_after_ms
+            ),
+            AlarmEvent::BookCrossed {
+                best_bid,
+                best_ask,
+                crossed_for_ms,
+            } => format!(
+                "book crossed for {}ms: best bid {} above best ask {}",
+                crossed_for_ms, best_bid, best_ask
+            ),
+            AlarmEvent::GrpcUnavailable { reason } => {
+                format!("gRPC clients can't connect: {}", reason)
+            }
+            AlarmEvent::Quarantined { exchange, until } => {
+                format!("{} quarantined until {}", exchange, until)
+            }
+        }
+    }
+}
+
+/// What notifiers receive, and the JSON body a webhook posts
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Alarm {
+    pub symbol: String,
+    pub raised_at: u64, // unix millis
+    pub message: String,
+    /// Alarms of this kind held back by the cooldown since the last one sent
+    pub suppressed: u64,
+    #[serde(flatten)]
+    pub event: AlarmEvent,
+}
+
+pub type NotifyFuture<'a> = Pin<Box<dyn Future<Output = Result<(), String>> + Send + 'a>>;
+
+/// Where alarms are pushed. Retrying is up to the notifier; an `Err` is logged and dropped.
+pub trait Notifier: Send + Sync + Debug {
+    fn notify<'a>(&'a self, alarm: &'a Alarm) -> NotifyFuture<'a>;
+}
+
+/// Writes alarms to the log only
+#[derive(Debug, Default)]
+pub struct LogNotifier;
+
+impl Notifier for LogNotifier {
+    fn notify<'a>(&'a self, alarm: &'a Alarm) -> NotifyFuture<'a> {
+        tracing::error!(
+            kind = alarm.event.kind().as_str(),
+            suppressed = alarm.suppressed,
+            "ALARM {}: {}",
+            alarm.symbol,
+            alarm.message
+        );
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct WebhookConfig {
+    /// Alarms are POSTed here as JSON
+    pub url: String,
+    /// Attempts after the first failed one, with doubling delays
+    pub max_retries: u32,
+    pub initial_backoff_ms: u64,
+    /// Per attempt
+    pub timeout_ms: u64,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            max_retries: 3,
+            initial_backoff_ms: 500,
+            timeout_ms: 5_000,
+        }
+    }
+}
+
+/// POSTs each alarm to a URL, retrying failures with backoff. Sending needs the
+/// `connectors` feature.
+#[derive(Debug)]
+pub struct WebhookNotifier {
+    pub config: WebhookConfig,
+    pub clock: SharedClock,
+}
+
+impl WebhookNotifier {
+    pub fn new(clock: SharedClock, config: WebhookConfig) -> Self {
+        Self { config, clock }
+    }
+}
+
+/// One entry of `alarms.notifiers`
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NotifierConfig {
+    Log,
+    Webhook(WebhookConfig),
+}
+
+/// Which notifiers an alarm kind goes to, and how often
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct AlarmRoute {
+    /// Names from `alarms.notifiers`
+    pub notify: Vec<String>,
+    /// Hold back alarms of this kind raised within this long of the last one sent
+    pub cooldown_secs: u64,
+}
+
+impl Default for AlarmRoute {
+    fn default() -> Self {
+        Self {
+            notify: vec![],
+            cooldown_secs: 300,
+        }
+    }
+}
+
+/// The `alarms` section of the config file. Kinds without a route are logged only.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct AlarmConfig {
+    pub notifiers: BTreeMap<String, NotifierConfig>,
+    pub events: BTreeMap<AlarmKind, AlarmRoute>,
+    /// Raise `book_crossed` once the book stays crossed for longer than this
+    pub crossed_after_secs: u64,
+}
+
+impl Default for AlarmConfig {
+    fn default() -> Self {
+        Self {
+            notifiers: BTreeMap::new(),
+            events: BTreeMap::new(),
+            crossed_after_secs: 5,
+        }
+    }
+}
+
+impl AlarmConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, notifier) in &self.notifiers {
+            if let NotifierConfig::Webhook(webhook) = notifier
+                && !(webhook.url.starts_with("http://") || webhook.url.starts_with("https://"))
+            {
+                return Err(format!(
+                    "invalid config: alarms.notifiers.{}.url must be an http(s) URL",
+                    name
+                ));
+            }
+        }
+        for (kind, route) in &self.events {
+            if let Some(unknown) = route
+                .notify
+                .iter()
+                .find(|name| !self.notifiers.contains_key(*name))
+            {
+                return Err(format!(
+                    "invalid config: alarms.events.{} notifies unknown notifier '{}'",
+                    kind.as_str(),
+                    unknown
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A raised alarm on its way to the dispatcher
+#[derive(Debug)]
+pub struct RaisedAlarm {
+    pub event: AlarmEvent,
+    /// Signalled once the alarm has been dispatched
+    pub done: Option<oneshot::Sender<()>>,
+}
+
+/// Raises alarms from anywhere in the process. The default handle raises nothing.
+#[derive(Clone, Debug, Default)]
+pub struct Alarms {
+    tx: Option<mpsc::Sender<RaisedAlarm>>,
+}
+
+impl Alarms {
+    /// A handle and the receiving end for `AlarmDispatcher::run`
+    pub fn channel() -> (Self, mpsc::Receiver<RaisedAlarm>) {
+        let (tx, rx) = mpsc::channel(ALARM_QUEUE_CAPACITY);
+        (Self { tx: Some(tx) }, rx)
+    }
+
+    /// Queue `event` without waiting; dropped if the queue is full
+    pub fn raise(&self, event: AlarmEvent) {
+        if let Some(tx) = &self.tx
+            && tx.try_send(RaisedAlarm { event, done: None }).is_err()
+        {
+            tracing::warn!("Alarm queue full or closed, dropping an alarm");
+        }
+    }
+
+    /// Queue `event` and wait up to `timeout` for it to be dispatched, e.g. right before
+    /// the process exits
+    pub async fn raise_and_wait(&self, event: AlarmEvent, timeout: Duration) {
+        let Some(tx) = &self.tx else {
+            return;
+        };
+        let (done, dispatched) = oneshot::channel();
+        let raised = RaisedAlarm {
+            event,
+            done: Some(done),
+        };
+        let _ = tokio::time::timeout(timeout, async {
+            if tx.send(raised).await.is_ok() {
+                let _ = dispatched.await;
+            }
+        })
+        .await;
+    }
+}
+
+#[derive(Debug)]
+struct Route {
+    notifiers: Vec<Arc<dyn Notifier>>,
+    cooldown: Duration,
+}
+
+/// Sends each raised alarm to the notifiers of its kind, holding back repeats within the
+/// kind's cooldown so a flapping condition doesn't cause a storm
+#[derive(Debug)]
+pub struct AlarmDispatcher {
+    clock: SharedClock,
+    symbol: String,
+    routes: HashMap<AlarmKind, Route>,
+    last_sent: HashMap<AlarmKind, u64>, // unix millis
+    suppressed: HashMap<AlarmKind, u64>,
+}
+
+impl AlarmDispatcher {
+    /// Every kind is logged, with no cooldown, until routed elsewhere
+    pub fn new(clock: SharedClock, symbol: &str) -> Self {
+        Self {
+            clock,
+            symbol: symbol.to_string(),
+            routes: HashMap::new(),
+            last_sent: HashMap::new(),
+            suppressed: HashMap::new(),
+        }
+    }
+
+    /// Send alarms of `kind` to `notifiers`, at most once per `cooldown`
+    pub fn with_route(
+        mut self,
+        kind: AlarmKind,
+        notifiers: Vec<Arc<dyn Notifier>>,
+        cooldown: Duration,
+    ) -> Self {
+        self.routes.insert(
+            kind,
+            Route {
+                notifiers,
+                cooldown,
+            },
+        );
+        self
+    }
+
+    /// Routes from the config's `events`, to the built `notifiers` by name
+    pub fn with_config(
+        mut self,
+        config: &AlarmConfig,
+        notifiers: &BTreeMap<String, Arc<dyn Notifier>>,
+    ) -> Self {
+        for (kind, route) in &config.events {
+            let targets = route
+                .notify
+                .iter()
+                .filter_map(|name| notifiers.get(name).cloned())
+                .collect();
+            self = self.with_route(*kind, targets, Duration::from_secs(route.cooldown_secs));
+        }
+        self
+    }
+
+    /// Notify `event`'s route. Returns the alarm sent, or `None` if the cooldown held it back.
+    pub async fn dispatch(&mut self, event: AlarmEvent) -> Option<Alarm> {
+        let kind = event.kind();
+        let now = self.clock.now_millis();
+        let cooldown = self
+            .routes
+            .get(&kind)
+            .map_or(0, |route| route.cooldown.as_millis() as u64);
+        if let Some(last) = self.last_sent.get(&kind)
+            && now.saturating_sub(*last) < cooldown
+        {
+            *self.suppressed.entry(kind).or_default() += 1;
+            tracing::debug!("{} alarm held back by its cooldown", kind.as_str());
+            return None;
+        }
+        self.last_sent.insert(kind, now);
+        let alarm = Alarm {
+            symbol: self.symbol.clone(),
+            raised_at: now,
+            message: event.message(),
+            suppressed: self.suppressed.remove(&kind).unwrap_or(0),
+            event,
+        };
+        let log: Arc<dyn Notifier> = Arc::new(LogNotifier);
+        let notifiers = match self.routes.get(&kind) {
+            Some(route) => route.notifiers.as_slice(),
+            None => std::slice::from_ref(&log),
+        };
+        let results = join_all(notifiers.iter().map(|notifier| notifier.notify(&alarm))).await;
+        for (notifier, result) in notifiers.iter().zip(results) {
+            if let Err(e) = result {
+                tracing::error!(
+                    "{} alarm not delivered to {:?}: {}",
+                    kind.as_str(),
+                    notifier,
+                    e
+                );
+            }
+        }
+        Some(alarm)
+    }
+
+    /// Dispatch alarms until every `Alarms` handle is dropped
+    pub async fn run(mut self, mut alarms: mpsc::Receiver<RaisedAlarm>) {
+        while let Some(raised) = alarms.recv().await {
+            self.dispatch(raised.event).await;
+            if let Some(done) = raised.done {
+                let _ = done.send(());
+            }
+        }
+    }
+}
+
+/// Watches the book for the conditions raised from its state: every exchange stale, and
+/// a book staying crossed. Each is raised once when it starts and re-armed when it clears.
+#[derive(Debug)]
+pub struct BookAlarmWatch {
+    clock: SharedClock,
+    exchanges: Vec<String>,
+    /// When the book's settings don't set `stale_after_ms`
+    stale_after: Option<Duration>,
+    crossed_after: Duration,
+    seen_fresh: bool,
+    all_stale: bool,
+    crossed_since: Option<u64>, // unix millis
+    crossed_raised: bool,
+}
+
+impl BookAlarmWatch {
+    pub fn new(
+        clock: SharedClock,
+        exchanges: Vec<String>,
+        stale_after: Option<Duration>,
+        crossed_after: Duration,
+    ) -> Self {
+        Self {
+            clock,
+            exchanges,
+            stale_after,
+            crossed_after,
+            seen_fresh: false,
+            all_stale: false,
+            crossed_since: None,
+            crossed_raised: false,
+        }
+    }
+
+    /// The alarms `book` starts raising now
+    pub fn check(&mut self, book: &AggregatedOrderBook) -> Vec<AlarmEvent> {
+        let now = self.clock.now_millis();
+        let mut raised = vec![];
+        let stale_after = book
+            .config
+            .settings
+            .stale_after_ms
+            .map(Duration::from_millis)
+            .or(self.stale_after);
+        if let Some(stale_after) = stale_after {
+            let max_age = stale_after.as_millis() as u64;
+            let fresh = self.exchanges.iter().any(|exchange| {
+                book.last_update_at
+                    .get(exchange)
+                    .is_some_and(|at| now.saturating_sub(*at) <= max_age)
+            });
+            if fresh {
+                self.seen_fresh = true;
+                self.all_stale = false;
+            } else if self.seen_fresh && !self.all_stale {
+                // Not before the first data: startup has its own deadline
+                self.all_stale = true;
+                raised.push(AlarmEvent::AllExchangesStale {
+                    exchanges: self.exchanges.clone(),
+                    stale_after_ms: max_age,
+                });
+            }
+        }
+
+        let top = |levels: Option<&HashMap<String, OrderLevel>>| {
+            levels.and_then(|levels| levels.values().next().map(|level| level.price))
+        };
+        let best_bid = top(book.bids.values().next_back());
+        let best_ask = top(book.asks.values().next());
+        match (best_bid, best_ask) {
+            (Some(best_bid), Some(best_ask)) if best_bid > best_ask => {
+                let since = *self.crossed_since.get_or_insert(now);
+                let crossed_for_ms = now.saturating_sub(since);
+                if !self.crossed_raised && crossed_for_ms > self.crossed_after.as_millis() as u64 {
+                    self.crossed_raised = true;
+                    raised.push(AlarmEvent::BookCrossed {
+                        best_bid,
+                        best_ask,
+                        crossed_for_ms,
+                    });
+                }
+            }
+            _ => {
+                self.crossed_since = None;
+                self.crossed_raised = false;
+            }
+        }
+        raised
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::clock::MockClock;
+    use crate::modules::quarantine::{Admission, Quarantine, QuarantineConfig};
+    use crate::modules::types::{Exchange, OrderBook, OrderBookUpdate};
+    use std::sync::Mutex;
+
+    #[derive(Debug, Default)]
+    struct RecordingNotifier {
+        alarms: Mutex<Vec<Alarm>>,
+    }
+
+    impl Notifier for RecordingNotifier {
+        fn notify<'a>(&'a self, alarm: &'a Alarm) -> NotifyFuture<'a> {
+            self.alarms.lock().unwrap().push(alarm.clone());
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    impl RecordingNotifier {
+        fn payloads(&self) -> Vec<serde_json::Value> {
+            let alarms = self.alarms.lock().unwrap();
+            alarms
+                .iter()
+                .map(|alarm| serde_json::to_value(alarm).unwrap())
+                .collect()
+        }
+    }
+
+    fn level(exchange: Exchange, price: f64) -> OrderLevel {
+        OrderLevel {
+            exchange,
+            price,
+            amount: 1.0,
+        }
+    }
+
+    fn book(clock: &Arc<MockClock>) -> AggregatedOrderBook {
+        let mut book = AggregatedOrderBook::with_clock(clock.clone());
+        book.config.symbol = "ethbtc".to_string();
+        book.config.settings.stale_after_ms = Some(5_000);
+        book.merge_snapshots(vec![OrderBook {
+            last_update_id: 1,
+            bids: vec![level(Exchange::Binance, 100.0)],
+            asks: vec![level(Exchange::Binance, 101.0)],
+        }]);
+        book
+    }
+
+    fn dispatcher(clock: &Arc<MockClock>, recorder: &Arc<RecordingNotifier>) -> AlarmDispatcher {
+        let mut dispatcher = AlarmDispatcher::new(clock.clone(), "ethbtc");
+        for kind in [
+            AlarmKind::AllExchangesStale,
+            AlarmKind::BookCrossed,
+            AlarmKind::GrpcUnavailable,
+            AlarmKind::Quarantined,
+        ] {
+            dispatcher = dispatcher.with_route(
+                kind,
+                vec![recorder.clone() as Arc<dyn Notifier>],
+                Duration::from_secs(60),
+            );
+        }
+        dispatcher
+    }
+
+    #[tokio::test]
+    async fn stale_exchanges_and_a_lasting_cross_raise_once_each() {
+        let clock = Arc::new(MockClock::new(1_000));
+        let recorder = Arc::new(RecordingNotifier::default());
+        let mut dispatcher = dispatcher(&clock, &recorder);
+        let mut book = book(&clock);
+        let mut watch = BookAlarmWatch::new(
+            clock.clone(),
+            vec!["binance".to_string(), "bitstamp".to_string()],
+            None,
+            Duration::from_secs(2),
+        );
+        assert!(watch.check(&book).is_empty());
+
+        // Bitstamp never sent anything, and Binance goes quiet past the book's 5s
+        clock.advance(Duration::from_millis(5_001));
+        let raised = watch.check(&book);
+        assert_eq!(raised.len(), 1);
+        assert!(watch.check(&book).is_empty(), "raised once per episode");
+        for event in raised {
+            dispatcher.dispatch(event).await.unwrap();
+        }
+
+        // Fresh data re-arms it; a cross only counts once it lasts over 2s
+        book.merge_snapshots(vec![OrderBook {
+            last_update_id: 1,
+            bids: vec![level(Exchange::Bitstamp, 102.0)],
+            asks: vec![level(Exchange::Bitstamp, 103.0)],
+        }]);
+        assert!(watch.check(&book).is_empty());
+        clock.advance(Duration::from_secs(2));
+        assert!(watch.check(&book).is_empty());
+        clock.advance(Duration::from_millis(1));
+        let raised = watch.check(&book);
+        assert!(watch.check(&book).is_empty());
+        for event in raised {
+            dispatcher.dispatch(event).await.unwrap();
+        }
+
+        let payloads = recorder.payloads();
+        assert_eq!(
+            payloads[0],
+            serde_json::json!({
+                "kind": "all_exchanges_stale",
+                "symbol": "ethbtc",
+                "raised_at": 6_001,
+                "message": "no data from any exchange (binance, bitstamp) for over 5000ms",
+                "suppressed": 0,
+                "exchanges": ["binance", "bitstamp"],
+                "stale_after_ms": 5_000,
+            })
+        );
+        assert_eq!(payloads[1]["kind"], "book_crossed");
+        assert_eq!(payloads[1]["best_bid"], 102.0);
+        assert_eq!(payloads[1]["best_ask"], 101.0);
+        assert_eq!(payloads[1]["crossed_for_ms"], 2_001);
+    }
+
+    #[tokio::test]
+    async fn repeats_within_the_cooldown_are_suppressed_and_counted() {
+        let clock = Arc::new(MockClock::new(1_000));
+        let recorder = Arc::new(RecordingNotifier::default());
+        let mut dispatcher = dispatcher(&clock, &recorder);
+        let unavailable = || AlarmEvent::GrpcUnavailable {
+            reason: "listener 127.0.0.1:5002 stopped".to_string(),
+        };
+
+        assert!(dispatcher.dispatch(unavailable()).await.is_some());
+        clock.advance(Duration::from_secs(30));
+        assert!(dispatcher.dispatch(unavailable()).await.is_none());
+        assert!(dispatcher.dispatch(unavailable()).await.is_none());
+        // Other kinds have cooldowns of their own
+        let quarantined = AlarmEvent::Quarantined {
+            exchange: "bitstamp".to_string(),
+            until: 61_000,
+        };
+        assert!(dispatcher.dispatch(quarantined).await.is_some());
+        clock.advance(Duration::from_secs(30));
+        let sent = dispatcher.dispatch(unavailable()).await.unwrap();
+        assert_eq!(sent.suppressed, 2);
+
+        let kinds: Vec<_> = recorder
+            .payloads()
+            .iter()
+            .map(|p| (p["kind"].clone(), p["suppressed"].clone()))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ("grpc_unavailable".into(), 0.into()),
+                ("quarantined".into(), 0.into()),
+                ("grpc_unavailable".into(), 2.into()),
+            ]
+        );
+        assert_eq!(
+            recorder.payloads()[0]["message"],
+            "gRPC clients can't connect: listener 127.0.0.1:5002 stopped"
+        );
+    }
+
+    #[tokio::test]
+    async fn a_quarantine_is_raised_through_the_channel() {
+        let clock = Arc::new(MockClock::new(1_000));
+        let recorder = Arc::new(RecordingNotifier::default());
+        let (alarms, rx) = Alarms::channel();
+        let dispatched = tokio::spawn(dispatcher(&clock, &recorder).run(rx));
+        let mut book = book(&clock);
+        book.merge_snapshots(vec![OrderBook {
+            last_update_id: 1_000,
+            bids: vec![level(Exchange::Bitstamp, 99.0)],
+            asks: vec![],
+        }]);
+        let mut quarantine = Quarantine::new(
+            clock.clone(),
+            QuarantineConfig {
+                max_stale: 2,
+                window: Duration::from_secs(10),
+                cool_down: Duration::from_secs(30),
+            },
+        )
+        .with_alarms(alarms.clone());
+        let stale = || OrderBookUpdate {
+            exchange: Exchange::Bitstamp,
+            update_id: 10,
+            bids: vec![level(Exchange::Bitstamp, 99.0)],
+            ..Default::default()
+        };
+        let admissions: Vec<_> = (0..3)
+            .map(|_| quarantine.apply(&mut book, stale()).unwrap())
+            .collect();
+        assert_eq!(admissions[2], Admission::Quarantined);
+
+        alarms
+            .raise_and_wait(
+                AlarmEvent::GrpcUnavailable {
+                    reason: "stopped".to_string(),
+                },
+                Duration::from_secs(5),
+            )
+            .await;
+        let payloads = recorder.payloads();
+        assert_eq!(payloads.len(), 2, "the quarantine was queued first");
+        assert_eq!(payloads[0]["kind"], "quarantined");
+        assert_eq!(payloads[0]["exchange"], "bitstamp");
+        assert_eq!(payloads[0]["until"], 31_000);
+
+        drop((alarms, quarantine));
+        dispatched.await.unwrap();
+    }
+
+    #[test]
+    fn config_routes_kinds_to_named_notifiers() {
+        let config: AlarmConfig = serde_json::from_str(
+            r#"{
+                "notifiers": {
+                    "ops": { "type": "webhook", "url": "https://hooks.example/alarms", "max_retries": 5 },
+                    "log": { "type": "log" }
+                },
+                "events": { "book_crossed": { "notify": ["ops", "log"], "cooldown_secs": 60 } },
+                "crossed_after_secs": 3
+            }"#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+        let NotifierConfig::Webhook(webhook) = &config.notifiers["ops"] else {
+            panic!("ops is a webhook");
+        };
+        assert_eq!((webhook.max_retries, webhook.timeout_ms), (5, 5_000));
+        assert_eq!(config.events[&AlarmKind::BookCrossed].cooldown_secs, 60);
+
+        let mut unknown = config.clone();
+        unknown.events.insert(
+            AlarmKind::Quarantined,
+            AlarmRoute {
+                notify: vec!["pager".to_string()],
+                ..Default::default()
+            },
+        );
+        let err = unknown.validate().unwrap_err();
+        assert!(err.contains("alarms.events.quarantined"), "{}", err);
+        assert!(serde_json::from_str::<AlarmConfig>(r#"{ "events": { "fire": {} } }"#).is_err());
+    }
+}
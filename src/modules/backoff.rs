@@ -0,0 +1,126 @@
+use crate::modules::clock::SharedClock;
+use std::time::Duration;
+
+/// Exponential reconnect backoff. A connection that stayed up for at least `stable_after`
+/// resets the delay back to `initial`, so a single blip doesn't inherit a long delay.
+#[derive(Debug)]
+pub struct Backoff {
+    clock: SharedClock,
+    initial: Duration,
+    max: Duration,
+    stable_after: Duration,
+    current: Duration,
+    connected_at_millis: Option<u64>,
+}
+
+impl Backoff {
+    pub fn new(
+        clock: SharedClock,
+        initial: Duration,
+        max: Duration,
+        stable_after: Duration,
+    ) -> Self {
+        Self {
+            clock,
+            initial,
+            max,
+            stable_after,
+            current: initial,
+            connected_at_millis: None,
+        }
+    }
+
+    /// Record that a connection was established
+    pub fn on_connected(&mut self) {
+        self.connected_at_millis = Some(self.clock.now_millis());
+    }
+
+    /// Start over from the initial delay
+    pub fn reset(&mut self) {
+        self.current = self.initial;
+        self.connected_at_millis = None;
+    }
+
+    /// Delay to wait before the next reconnect attempt
+    pub fn next_delay(&mut self) -> Duration {
+        if let Some(connected_at) = self.connected_at_millis.take() {
+            let uptime = self.clock.now_millis().saturating_sub(connected_at);
+            if uptime >= self.stable_after.as_millis() as u64 {
+                self.current = self.initial;
+            }
+        }
+        let delay = self.current;
+        self.current = (self.current * 2).min(self.max);
+        delay
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::clock::TestTime;
+
+    fn backoff(time: &TestTime) -> Backoff {
+        Backoff::new(
+            time.shared(),
+            Duration::from_secs(2),
+            Duration::from_secs(30),
+            Duration::from_secs(60),
+        )
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn delays_double_up_to_the_cap() {
+        let time = TestTime::new(0);
+        let mut b = backoff(&time);
+        let delays: Vec<u64> = (0..6).map(|_| b.next_delay().as_secs()).collect();
+        assert_eq!(delays, vec![2, 4, 8, 16, 30, 30]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn short_lived_connection_keeps_growing_the_delay() {
+        let time = TestTime::new(0);
+        let mut b = backoff(&time);
+        assert_eq!(b.next_delay(), Duration::from_secs(2));
+
+        b.on_connected();
+        time.advance(Duration::from_secs(5)).await;
+        assert_eq!(b.next_delay(), Duration::from_secs(4));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stable_connection_resets_the_delay() {
+        let time = TestTime::new(0);
+        let mut b = backoff(&time);
+        b.next_delay();
+        b.next_delay();
+        b.next_delay();
+
+        b.on_connected();
+        time.advance(Duration::from_secs(61)).await;
+        assert_eq!(b.next_delay(), Duration::from_secs(2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_reconnect_loop_waits_out_each_delay() {
+        let time = TestTime::new(0);
+        let mut b = backoff(&time);
+        let (attempts_tx, mut attempts) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                attempts_tx.send(()).unwrap();
+                tokio::time::sleep(b.next_delay()).await;
+            }
+        });
+
+        // Attempts at 0s, 2s, 6s and 14s
+        let mut seen = 0;
+        for (step, expected) in [(0, 1), (1, 1), (1, 2), (3, 2), (1, 3), (8, 4)] {
+            time.advance(Duration::from_secs(step)).await;
+            while attempts.try_recv().is_ok() {
+                seen += 1;
+            }
+            assert_eq!(seen, expected, "after +{}s", step);
+        }
+    }
+}
@@ -1,15 +1,97 @@
 use crate::modules::types::Exchange;
-use futures_util::StreamExt;
-use futures_util::stream::{SplitSink, SplitStream};
-use tokio::net::TcpStream;
 
 use crate::modules::types::{OrderBook, OrderLevel};
+use serde::Deserialize;
 use serde_json::Value;
-use tokio_tungstenite::tungstenite::Message;
-use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async};
 
-// Get the snapshot of the orderbook from Binance.
-// The data returned looks like this:
+/// Which Binance deployment to talk to. Binance.US has its own hosts and symbol list,
+/// so its books are tagged as a separate exchange and never mixed with the global venue.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BinanceVariant {
+    #[default]
+    Global,
+    Us,
+}
+
+impl BinanceVariant {
+    pub fn exchange(&self) -> Exchange {
+        match self {
+            BinanceVariant::Global => Exchange::Binance,
+            BinanceVariant::Us => Exchange::BinanceUs,
+        }
+    }
+
+    /// Production hosts of this deployment
+    pub fn endpoint(&self) -> BinanceEndpoint {
+        let (rest, ws) = match self {
+            BinanceVariant::Global => ("https://api.binance.com", "wss://stream.binance.com:9443"),
+            BinanceVariant::Us => ("https://api.binance.us", "wss://stream.binance.us:9443"),
+        };
+        BinanceEndpoint {
+            variant: *self,
+            rest: rest.to_string(),
+            ws: ws.to_string(),
+        }
+    }
+}
+
+/// Levels per side of the REST snapshot. The diff stream covers every depth, but only the
+/// snapshot's levels are seeded, so the book is only known this deep.
+pub const BINANCE_SNAPSHOT_DEPTH: usize = 1000;
+
+/// REST and websocket base URLs for a Binance deployment; overridable for tests and proxies
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BinanceEndpoint {
+    pub variant: BinanceVariant,
+    pub rest: String,
+    pub ws: String,
+}
+
+impl BinanceEndpoint {
+    pub fn exchange(&self) -> Exchange {
+        self.variant.exchange()
+    }
+
+    pub fn depth_url(&self, symbol: &str) -> String {
+        format!(
+            "{}/api/v3/depth?symbol={}&limit={}",
+            self.rest,
+            symbol.to_uppercase(),
+            BINANCE_SNAPSHOT_DEPTH
+        )
+    }
+
+    pub fn stream_url(&self, symbol: &str) -> String {
+        format!("{}/ws/{}@depth@100ms", self.ws, symbol.to_lowercase())
+    }
+
+    pub fn exchange_info_url(&self, symbol: &str) -> String {
+        format!(
+            "{}/api/v3/exchangeInfo?symbol={}",
+            self.rest,
+            symbol.to_uppercase()
+        )
+    }
+}
+
+impl std::str::FromStr for BinanceVariant {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "global" => Ok(BinanceVariant::Global),
+            "us" => Ok(BinanceVariant::Us),
+            other => Err(format!(
+                "unknown binance variant '{}' (expected global or us)",
+                other
+            )),
+        }
+    }
+}
+
+// The Binance REST depth snapshot
+// looks looks like this:
 // {
 //     "lastUpdateId": 1234567890,
 //     "bids": [
@@ -21,49 +103,117 @@ use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async};
 //         ["100.00000001", "10.00000001"],
 //     ]
 // }
-pub async fn get_binance_snapshot(symbol: &str) -> OrderBook {
-    let url = format!(
-        "https://api.binance.com/api/v3/depth?symbol={}&limit=1000",
-        symbol.to_uppercase()
-    );
-    let response = reqwest::get(url).await.unwrap();
+// Parse the REST snapshot body returned by Binance, attributing levels to `exchange`.
+pub fn parse_binance_snapshot(body: &str, exchange: Exchange) -> Option<OrderBook> {
+    let data: Value = serde_json::from_str(body).ok()?;
+    let last_update_id = data["lastUpdateId"].as_u64()?;
     let mut bids = vec![];
     let mut asks = vec![];
-    let body = response.text().await.unwrap();
-    let data: Value = serde_json::from_str(&body).unwrap();
-    let last_update_id = data["lastUpdateId"].as_u64().unwrap();
-    let bids_json_array = data["bids"].as_array().unwrap();
+    let bids_json_array = data["bids"].as_array()?;
     for bid in bids_json_array {
         bids.push(OrderLevel {
-            exchange: Exchange::Binance.as_str(),
-            price: bid[0].as_str().unwrap().parse::<f64>().unwrap(),
-            amount: bid[1].as_str().unwrap().parse::<f64>().unwrap(),
+            exchange,
+            price: bid[0].as_str()?.parse::<f64>().ok()?,
+            amount: bid[1].as_str()?.parse::<f64>().ok()?,
         });
     }
-    let asks_json_array = data["asks"].as_array().unwrap();
+    let asks_json_array = data["asks"].as_array()?;
     for ask in asks_json_array {
         asks.push(OrderLevel {
-            exchange: Exchange::Binance.as_str(),
-            price: ask[0].as_str().unwrap().parse::<f64>().unwrap(),
-            amount: ask[1].as_str().unwrap().parse::<f64>().unwrap(),
+            exchange,
+            price: ask[0].as_str()?.parse::<f64>().ok()?,
+            amount: ask[1].as_str()?.parse::<f64>().ok()?,
         });
     }
-    OrderBook {
+    Some(OrderBook {
         last_update_id,
         bids,
         asks,
-    }
+    })
+}
+
+/// The PRICE_FILTER tick size of `symbol` in an exchangeInfo body, if it lists one
+pub fn parse_binance_tick_size(body: &str, symbol: &str) -> Option<f64> {
+    let data: Value = serde_json::from_str(body).ok()?;
+    let info = data["symbols"]
+        .as_array()?
+        .iter()
+        .find(|info| info["symbol"].as_str() == Some(symbol.to_uppercase().as_str()))?;
+    let filter = info["filters"]
+        .as_array()?
+        .iter()
+        .find(|filter| filter["filterType"] == "PRICE_FILTER")?;
+    let tick = filter["tickSize"].as_str()?.parse::<f64>().ok()?;
+    (tick.is_finite() && tick > 0.0).then_some(tick)
 }
 
-// Get the stream of the orderbook from Binance.
-pub async fn get_binance_stream(
-    symbol: &str,
-) -> (
-    SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>,
-    SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>,
-) {
-    let url = format!("wss://stream.binance.com:9443/ws/{}@depth@100ms", symbol);
-    let (ws_stream, _) = connect_async(url).await.unwrap();
-    let (write, read) = ws_stream.split();
-    (write, read)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::types::OrderBookUpdate;
+
+    #[test]
+    fn urls_follow_the_variant() {
+        let global = BinanceVariant::Global.endpoint();
+        assert_eq!(
+            global.depth_url("ethbtc"),
+            "https://api.binance.com/api/v3/depth?symbol=ETHBTC&limit=1000"
+        );
+        assert_eq!(
+            global.stream_url("ETHBTC"),
+            "wss://stream.binance.com:9443/ws/ethbtc@depth@100ms"
+        );
+        assert_eq!(
+            global.exchange_info_url("ethbtc"),
+            "https://api.binance.com/api/v3/exchangeInfo?symbol=ETHBTC"
+        );
+
+        let us = BinanceVariant::Us.endpoint();
+        assert_eq!(
+            us.depth_url("btcusd"),
+            "https://api.binance.us/api/v3/depth?symbol=BTCUSD&limit=1000"
+        );
+        assert_eq!(
+            us.stream_url("btcusd"),
+            "wss://stream.binance.us:9443/ws/btcusd@depth@100ms"
+        );
+        assert_eq!(
+            us.exchange_info_url("btcusd"),
+            "https://api.binance.us/api/v3/exchangeInfo?symbol=BTCUSD"
+        );
+    }
+
+    #[test]
+    fn levels_are_attributed_to_the_variant() {
+        let body = r#"{"lastUpdateId":7,"bids":[["1.0","2.0"]],"asks":[["1.1","3.0"]]}"#;
+        let diff = r#"{"u":8,"b":[["1.0","1.0"]],"a":[]}"#;
+        for variant in [BinanceVariant::Global, BinanceVariant::Us] {
+            let exchange = variant.exchange();
+            let snapshot = parse_binance_snapshot(body, variant.exchange()).unwrap();
+            assert!(
+                snapshot
+                    .bids
+                    .iter()
+                    .chain(snapshot.asks.iter())
+                    .all(|l| l.exchange == exchange)
+            );
+
+            let update = OrderBookUpdate::from_binance_variant_json(diff, variant).unwrap();
+            assert_eq!(update.exchange, exchange);
+            assert!(update.bids.iter().all(|l| l.exchange == exchange));
+        }
+        assert_eq!(BinanceVariant::Us.exchange().as_str(), "binance_us");
+        assert_eq!("US".parse(), Ok(BinanceVariant::Us));
+    }
+
+    #[test]
+    fn tick_size_comes_from_the_price_filter() {
+        let body = r#"{"symbols":[{"symbol":"ETHBTC","filters":[
+            {"filterType":"LOT_SIZE","stepSize":"0.00010000"},
+            {"filterType":"PRICE_FILTER","minPrice":"0.00000100","tickSize":"0.00000100"}]}]}"#;
+        assert_eq!(parse_binance_tick_size(body, "ethbtc"), Some(0.000001));
+        assert_eq!(parse_binance_tick_size(body, "btcusdt"), None);
+        let no_filter = r#"{"symbols":[{"symbol":"ETHBTC","filters":[]}]}"#;
+        assert_eq!(parse_binance_tick_size(no_filter, "ethbtc"), None);
+    }
 }
@@ -0,0 +1,273 @@
+use crate::modules::types::{Exchange, OrderBook, OrderLevel};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Levels per side of the subscribed book and of the REST book asked for
+pub const BITFINEX_DEPTH: usize = 100;
+
+/// The `conf` flag that appends a millisecond timestamp to every channel frame. Book frames
+/// carry no id otherwise, so the timestamp is the update id.
+pub const TIMESTAMP_FLAG: u64 = 32768;
+
+/// `info` codes asking clients to reconnect: the websocket server is restarting, or
+/// maintenance has ended and channels must be subscribed again
+const RECONNECT_CODES: [u64; 2] = [20051, 20061];
+
+/// REST and websocket base URLs for Bitfinex; overridable for tests and proxies
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BitfinexEndpoint {
+    pub rest: String,
+    pub ws: String,
+}
+
+impl Default for BitfinexEndpoint {
+    fn default() -> Self {
+        Self {
+            rest: "https://api-pub.bitfinex.com".to_string(),
+            ws: "wss://api-pub.bitfinex.com/ws/2".to_string(),
+        }
+    }
+}
+
+impl BitfinexEndpoint {
+    pub fn book_url(&self, symbol: &str) -> String {
+        format!(
+            "{}/v2/book/{}/P0?len={}",
+            self.rest,
+            bitfinex_symbol(symbol),
+            BITFINEX_DEPTH
+        )
+    }
+}
+
+/// Bitfinex's trading pair symbol: `t` and the upper-cased pair, e.g. `tETHBTC` for ethbtc.
+/// A symbol already in that form is kept.
+pub fn bitfinex_symbol(symbol: &str) -> String {
+    match symbol.strip_prefix('t') {
+        Some(pair) if !pair.is_empty() && pair == pair.to_uppercase() => symbol.to_string(),
+        _ => format!("t{}", symbol.to_uppercase()),
+    }
+}
+
+/// The `conf` message turning on frame timestamps; sent before subscribing
+pub fn conf_message() -> String {
+    serde_json::json!({ "event": "conf", "flags": TIMESTAMP_FLAG }).to_string()
+}
+
+/// The `subscribe` message for the raw-price book of `symbol`, every change as it happens
+pub fn subscribe_message(symbol: &str) -> String {
+    serde_json::json!({
+        "event": "subscribe",
+        "channel": "book",
+        "symbol": bitfinex_symbol(symbol),
+        "prec": "P0",
+        "freq": "F0",
+        "len": BITFINEX_DEPTH.to_string()
+    })
+    .to_string()
+}
+
+/// Whether a book frame is the snapshot sent on subscribing, a list of levels, rather than
+/// an update of a single level
+pub fn is_book_snapshot(text: &str) -> bool {
+    text.starts_with('[')
+        && text.split_once(',').is_some_and(|(_, levels)| {
+            let levels = levels.trim_start();
+            levels.starts_with("[[") || levels.starts_with("[]")
+        })
+}
+
+/// Whether a message is an `info` event asking clients to reconnect
+pub fn is_reconnect_request(text: &str) -> bool {
+    text.contains("\"info\"")
+        && serde_json::from_str::<Value>(text).is_ok_and(|message| {
+            message["event"] == "info"
+                && message["code"]
+                    .as_u64()
+                    .is_some_and(|code| RECONNECT_CODES.contains(&code))
+        })
+}
+
+/// Split `[price, count, amount]` levels into bids and asks. A positive amount is a bid, a
+/// negative one an ask; a count of 0 removes the level, from the side the amount's sign
+/// (1 or -1) names, and becomes an amount of 0.
+pub fn parse_levels<'a>(
+    levels: impl IntoIterator<Item = &'a Value>,
+) -> Option<(Vec<OrderLevel>, Vec<OrderLevel>)> {
+    let (mut bids, mut asks) = (vec![], vec![]);
+    for level in levels {
+        let price = level.get(0)?.as_f64()?;
+        let count = level.get(1)?.as_u64()?;
+        let amount = level.get(2)?.as_f64()?;
+        if amount == 0.0 {
+            return None;
+        }
+        let side = if amount > 0.0 { &mut bids } else { &mut asks };
+        side.push(OrderLevel {
+            exchange: Exchange::Bitfinex,
+            price,
+            amount: if count == 0 { 0.0 } else { amount.abs() },
+        });
+    }
+    Some((bids, asks))
+}
+
+/// Parse the REST book body, a list of levels. It has no id and Bitfinex has no sequence the
+/// stream's frame timestamps follow, so the id is 0 and Bitfinex is exempt from the snapshot
+/// age check (`snapshot_ids_follow_stream`): the channel's snapshot, sent on subscribing,
+/// replaces it.
+pub fn parse_bitfinex_snapshot(body: &str) -> Option<OrderBook> {
+    let v: Value = serde_json::from_str(body).ok()?;
+    let (bids, asks) = parse_levels(v.as_array()?)?;
+    Some(OrderBook {
+        last_update_id: 0,
+        bids,
+        asks,
+    })
+}
+
+/// The book channels Bitfinex assigned on one connection, from its `subscribed` acks, and
+/// their symbols. Channel frames only carry the id, so this is how they are told apart.
+#[derive(Debug, Default)]
+pub struct BitfinexChannels {
+    symbols: HashMap<u64, String>,
+}
+
+impl BitfinexChannels {
+    /// Record a `subscribed` ack for a book channel, or forget an `unsubscribed` one.
+    /// Returns whether `text` is for this connector: an event, or a frame on a book channel
+    /// of `symbol`.
+    pub fn admit(&mut self, text: &str, symbol: &str) -> bool {
+        let Ok(message) = serde_json::from_str::<Value>(text) else {
+            return true;
+        };
+        if let Some(frame) = message.as_array() {
+            return frame
+                .first()
+                .and_then(Value::as_u64)
+                .and_then(|id| self.symbols.get(&id))
+                .is_some_and(|subscribed| *subscribed == bitfinex_symbol(symbol));
+        }
+        let id = message["chanId"].as_u64();
+        match (message["event"].as_str(), id) {
+            (Some("subscribed"), Some(id)) if message["channel"] == "book" => {
+                let symbol = message["symbol"].as_str().unwrap_or_default();
+                self.symbols.insert(id, symbol.to_string());
+            }
+            (Some("unsubscribed"), Some(id)) => {
+                self.symbols.remove(&id);
+            }
+            _ => {}
+        }
+        true
+    }
+
+    pub fn symbol(&self, channel_id: u64) -> Option<&str> {
+        self.symbols.get(&channel_id).map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symbols_map_to_bitfinex_pairs() {
+        assert_eq!(bitfinex_symbol("ethbtc"), "tETHBTC");
+        assert_eq!(bitfinex_symbol("tETHBTC"), "tETHBTC");
+        assert_eq!(bitfinex_symbol("trxusd"), "tTRXUSD");
+        assert_eq!(
+            BitfinexEndpoint::default().book_url("ethbtc"),
+            "https://api-pub.bitfinex.com/v2/book/tETHBTC/P0?len=100"
+        );
+        let subscribe: Value = serde_json::from_str(&subscribe_message("ethbtc")).unwrap();
+        assert_eq!(subscribe["symbol"], "tETHBTC");
+        assert_eq!(subscribe["len"], "100");
+        let conf: Value = serde_json::from_str(&conf_message()).unwrap();
+        assert_eq!(conf["flags"], TIMESTAMP_FLAG);
+
+        let book = parse_bitfinex_snapshot(
+            "[[0.0512,2,1.5],[0.05119,1,0.25],[0.05125,1,-0.75],[0.05126,3,-2]]",
+        )
+        .unwrap();
+        assert_eq!(book.last_update_id, 0);
+        let levels = |levels: &[OrderLevel]| -> Vec<(f64, f64)> {
+            levels.iter().map(|l| (l.price, l.amount)).collect()
+        };
+        assert_eq!(levels(&book.bids), [(0.0512, 1.5), (0.05119, 0.25)]);
+        assert_eq!(levels(&book.asks), [(0.05125, 0.75), (0.05126, 2.0)]);
+        assert!(book.asks.iter().all(|l| l.exchange == Exchange::Bitfinex));
+        assert!(parse_bitfinex_snapshot(r#"["error",10020,"symbol: invalid"]"#).is_none());
+    }
+
+    #[test]
+    fn rest_snapshots_merge_after_the_stream_got_ahead() {
+        use crate::modules::types::{AggregatedOrderBook, OrderBookUpdate};
+
+        let body = "[[0.0512,2,1.5],[0.05125,1,-0.75]]";
+        let mut agg = AggregatedOrderBook::new();
+        agg.merge_snapshots(vec![parse_bitfinex_snapshot(body).unwrap()]);
+        let frame: Value = serde_json::from_str("[[0.05121,1,3]]").unwrap();
+        let (bids, asks) = parse_levels(frame.as_array().unwrap()).unwrap();
+        agg.apply_update(OrderBookUpdate {
+            exchange: Exchange::Bitfinex,
+            update_id: 1_700_000_000_000,
+            first_update_id: None,
+            received_at: None,
+            event_time: None,
+            bids,
+            asks,
+        })
+        .unwrap();
+
+        // A reconnect's REST book, after a frame stamped with its timestamp
+        let report = agg.merge_snapshots(vec![parse_bitfinex_snapshot(body).unwrap()]);
+        let stats = &report.per_exchange[&Exchange::Bitfinex];
+        assert_eq!(stats.skipped_reason, None);
+        assert_eq!(stats.removed, 1);
+        assert_eq!(agg.last_update_id["bitfinex"], 0);
+    }
+
+    #[test]
+    fn a_count_of_zero_removes_the_level_from_the_side_its_amount_names() {
+        let removals: Value = serde_json::from_str("[[0.0512,0,1],[0.05125,0,-1]]").unwrap();
+        let (bids, asks) = parse_levels(removals.as_array().unwrap()).unwrap();
+        assert_eq!((bids[0].price, bids[0].amount), (0.0512, 0.0));
+        assert_eq!((asks[0].price, asks[0].amount), (0.05125, 0.0));
+        let sideless: Value = serde_json::from_str("[[0.0512,1,0]]").unwrap();
+        assert!(parse_levels(sideless.as_array().unwrap()).is_none());
+
+        assert!(is_book_snapshot("[17470,[[0.0512,2,1.5]],1700000000000]"));
+        assert!(is_book_snapshot("[17470,[],1700000000000]"));
+        assert!(!is_book_snapshot("[17470,[0.0512,2,1.5],1700000000000]"));
+        assert!(!is_book_snapshot(r#"[17470,"hb",1700000000000]"#));
+        assert!(is_reconnect_request(
+            r#"{"event":"info","code":20051,"msg":"Stopping. Please try to reconnect"}"#
+        ));
+        assert!(!is_reconnect_request(
+            r#"{"event":"info","version":2,"serverId":"1","platform":{"status":1}}"#
+        ));
+    }
+
+    #[test]
+    fn frames_are_only_admitted_on_channels_acked_for_the_symbol() {
+        let mut channels = BitfinexChannels::default();
+        let update = "[17470,[0.0512,1,0.5],1700000000100]";
+        assert!(!channels.admit(update, "ethbtc"));
+        assert!(channels.admit(
+            r#"{"event":"subscribed","channel":"book","chanId":17470,"symbol":"tETHBTC","prec":"P0","freq":"F0","len":"100","pair":"ETHBTC"}"#,
+            "ethbtc"
+        ));
+        assert_eq!(channels.symbol(17470), Some("tETHBTC"));
+        assert!(channels.admit(update, "ethbtc"));
+        assert!(!channels.admit(update, "btcusd"));
+        assert!(!channels.admit("[99,[0.0512,1,0.5],1700000000100]", "ethbtc"));
+
+        assert!(channels.admit(
+            r#"{"event":"unsubscribed","status":"OK","chanId":17470}"#,
+            "ethbtc"
+        ));
+        assert_eq!(channels.symbol(17470), None);
+        assert!(!channels.admit(update, "ethbtc"));
+    }
+}
@@ -1,71 +1,384 @@
 use crate::modules::types::Exchange;
-use futures_util::SinkExt;
-use futures_util::StreamExt;
-use futures_util::stream::{SplitSink, SplitStream};
+use serde::de::{self, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor};
 use serde_json::Value;
-use tokio::net::TcpStream;
-use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async, tungstenite::Message};
+use std::fmt;
+use std::io::Read;
 
 use crate::modules::types::{OrderBook, OrderLevel};
 
-pub async fn get_bitstamp_snapshot(symbol: &str) -> OrderBook {
-    let url = format!(
-        "https://www.bitstamp.net/api/v2/order_book/{}/",
-        symbol.to_lowercase()
-    );
-    let response = reqwest::get(url).await.unwrap();
-    let body = response.text().await.unwrap();
-    let data: Value = serde_json::from_str(&body).unwrap();
-    let last_update_id = data["microtimestamp"]
-        .as_str()
-        .unwrap()
-        .parse::<u64>()
-        .unwrap();
-    let bids = data["bids"].as_array().unwrap();
-    let asks = data["asks"].as_array().unwrap();
-    let bids = bids
-        .iter()
-        .map(|bid| OrderLevel {
-            exchange: Exchange::Bitstamp.as_str(),
-            price: bid[0].as_str().unwrap().parse::<f64>().unwrap(),
-            amount: bid[1].as_str().unwrap().parse::<f64>().unwrap(),
+/// Levels per side the diff channel keeps current. The REST book is deeper, but changes
+/// beyond this aren't sent.
+pub const BITSTAMP_DIFF_DEPTH: usize = 100;
+
+/// REST and websocket base URLs for Bitstamp; overridable for tests and proxies
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BitstampEndpoint {
+    pub rest: String,
+    pub ws: String,
+}
+
+impl Default for BitstampEndpoint {
+    fn default() -> Self {
+        Self {
+            rest: "https://www.bitstamp.net".to_string(),
+            ws: "wss://ws.bitstamp.net".to_string(),
+        }
+    }
+}
+
+impl BitstampEndpoint {
+    pub fn order_book_url(&self, symbol: &str) -> String {
+        format!("{}/api/v2/order_book/{}/", self.rest, symbol.to_lowercase())
+    }
+}
+
+/// Websocket channels of one symbol
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BitstampChannel {
+    /// `diff_order_book_<symbol>`: incremental updates, applied to the book
+    Diff,
+    /// `order_book_<symbol>`: the top 100 levels, pushed periodically; only cross-checked
+    FullBook,
+}
+
+impl BitstampChannel {
+    pub fn name(&self, symbol: &str) -> String {
+        match self {
+            BitstampChannel::Diff => format!("diff_order_book_{}", symbol.to_lowercase()),
+            BitstampChannel::FullBook => format!("order_book_{}", symbol.to_lowercase()),
+        }
+    }
+
+    /// The `bts:subscribe` message for this channel of `symbol`
+    pub fn subscribe_message(&self, symbol: &str) -> String {
+        serde_json::json!({
+            "event": "bts:subscribe",
+            "data": {
+                "channel": self.name(symbol)
+            }
         })
-        .collect();
-    let asks = asks
-        .iter()
-        .map(|ask| OrderLevel {
-            exchange: Exchange::Bitstamp.as_str(),
-            price: ask[0].as_str().unwrap().parse::<f64>().unwrap(),
-            amount: ask[1].as_str().unwrap().parse::<f64>().unwrap(),
+        .to_string()
+    }
+
+    /// Channel a websocket message came on; `None` for other channels or no channel
+    pub fn of(text: &str) -> Option<Self> {
+        let message: Value = serde_json::from_str(text).ok()?;
+        let channel = message["channel"].as_str()?;
+        if channel.starts_with("diff_order_book_") {
+            Some(BitstampChannel::Diff)
+        } else if channel.starts_with("order_book_") {
+            Some(BitstampChannel::FullBook)
+        } else {
+            None
+        }
+    }
+}
+
+// Parse the REST order_book body returned by Bitstamp.
+pub fn parse_bitstamp_snapshot(body: &str) -> Option<OrderBook> {
+    let data: Value = serde_json::from_str(body).ok()?;
+    parse_book(&data)
+}
+
+/// Parse the REST order_book body as it is read from `reader`, building levels straight from
+/// the bytes rather than through a `Value`. Only the best `depth` levels of each side are
+/// kept; the rest are skipped without being allocated. The reader is left after the closing
+/// brace, so whatever follows it is never read.
+pub fn read_bitstamp_snapshot<R: Read>(
+    reader: R,
+    depth: Option<usize>,
+) -> Result<OrderBook, serde_json::Error> {
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    BookSeed {
+        depth: depth.unwrap_or(usize::MAX),
+    }
+    .deserialize(&mut deserializer)
+}
+
+/// Levels are allocated up front for at most this many per side; a deeper `depth` grows
+/// the vectors as levels arrive
+const PREALLOCATED_LEVELS: usize = 1024;
+
+struct BookSeed {
+    depth: usize,
+}
+
+impl<'de> DeserializeSeed<'de> for BookSeed {
+    type Value = OrderBook;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<OrderBook, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for BookSeed {
+    type Value = OrderBook;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a Bitstamp order book")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<OrderBook, A::Error> {
+        let (mut last_update_id, mut bids, mut asks) = (None, None, None);
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "microtimestamp" => {
+                    let id = map.next_value::<String>()?;
+                    last_update_id = Some(id.parse::<u64>().map_err(de::Error::custom)?);
+                }
+                "bids" => bids = Some(map.next_value_seed(SideSeed { depth: self.depth })?),
+                "asks" => asks = Some(map.next_value_seed(SideSeed { depth: self.depth })?),
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(OrderBook {
+            last_update_id: last_update_id
+                .ok_or_else(|| de::Error::missing_field("microtimestamp"))?,
+            bids: bids.ok_or_else(|| de::Error::missing_field("bids"))?,
+            asks: asks.ok_or_else(|| de::Error::missing_field("asks"))?,
         })
-        .collect();
-    OrderBook {
+    }
+}
+
+/// One side, best level first, kept to `depth` levels
+struct SideSeed {
+    depth: usize,
+}
+
+impl<'de> DeserializeSeed<'de> for SideSeed {
+    type Value = Vec<OrderLevel>;
+
+    fn deserialize<D: de::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Vec<OrderLevel>, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for SideSeed {
+    type Value = Vec<OrderLevel>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an array of levels")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<OrderLevel>, A::Error> {
+        let mut levels = Vec::with_capacity(self.depth.min(PREALLOCATED_LEVELS));
+        while levels.len() < self.depth {
+            match seq.next_element_seed(LevelSeed)? {
+                Some(level) => levels.push(level),
+                None => return Ok(levels),
+            }
+        }
+        while seq.next_element::<IgnoredAny>()?.is_some() {}
+        Ok(levels)
+    }
+}
+
+/// A `[price, amount]` level, both decimal strings
+struct LevelSeed;
+
+impl<'de> DeserializeSeed<'de> for LevelSeed {
+    type Value = OrderLevel;
+
+    fn deserialize<D: de::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<OrderLevel, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for LevelSeed {
+    type Value = OrderLevel;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a [price, amount] level")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<OrderLevel, A::Error> {
+        let price = seq
+            .next_element_seed(Decimal)?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let amount = seq
+            .next_element_seed(Decimal)?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        while seq.next_element::<IgnoredAny>()?.is_some() {}
+        Ok(OrderLevel {
+            exchange: Exchange::Bitstamp,
+            price,
+            amount,
+        })
+    }
+}
+
+/// A decimal string parsed in place, without an owned copy of it
+struct Decimal;
+
+impl<'de> DeserializeSeed<'de> for Decimal {
+    type Value = f64;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<f64, D::Error> {
+        deserializer.deserialize_str(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Decimal {
+    type Value = f64;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a decimal string")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<f64, E> {
+        value.parse::<f64>().map_err(E::custom)
+    }
+}
+
+/// Parse a message of the `order_book_<symbol>` channel; `Ok(None)` for control events
+pub fn parse_bitstamp_full_book(text: &str) -> Result<Option<OrderBook>, String> {
+    let message: Value = serde_json::from_str(text).map_err(|e| format!("invalid JSON: {}", e))?;
+    match message["event"].as_str() {
+        None => Err("missing event".to_string()),
+        Some("data") => parse_book(&message["data"])
+            .map(Some)
+            .ok_or_else(|| "malformed order_book data: missing bids/asks arrays".to_string()),
+        Some(_) => Ok(None),
+    }
+}
+
+// Both the REST snapshot and the full-book channel carry microtimestamp, bids and asks
+fn parse_book(data: &Value) -> Option<OrderBook> {
+    let last_update_id = data["microtimestamp"].as_str()?.parse::<u64>().ok()?;
+    let parse_side = |side: &Value| -> Option<Vec<OrderLevel>> {
+        side.as_array()?
+            .iter()
+            .map(|level| {
+                Some(OrderLevel {
+                    exchange: Exchange::Bitstamp,
+                    price: level[0].as_str()?.parse::<f64>().ok()?,
+                    amount: level[1].as_str()?.parse::<f64>().ok()?,
+                })
+            })
+            .collect()
+    };
+    let bids = parse_side(&data["bids"])?;
+    let asks = parse_side(&data["asks"])?;
+    Some(OrderBook {
         last_update_id,
         bids,
         asks,
-    }
+    })
 }
 
-pub async fn get_bitstamp_stream(
-    symbol: &str,
-) -> (
-    SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>,
-    SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>,
-) {
-    let ws_url_bitstamp = "wss://ws.bitstamp.net".to_string();
-    let (mut ws_stream_bitstamp, _) = connect_async(&ws_url_bitstamp).await.unwrap();
-    let subscribe_msg = serde_json::json!({
-        "event": "bts:subscribe",
-        "data": {
-            "channel": format!("diff_order_book_{}", symbol)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FULL_BOOK: &str = include_str!("../../tests/fixtures/bitstamp_order_book.json");
+
+    #[test]
+    fn full_book_messages_are_routed_and_parsed() {
+        assert_eq!(
+            BitstampChannel::of(FULL_BOOK),
+            Some(BitstampChannel::FullBook)
+        );
+        assert_eq!(
+            BitstampChannel::of(r#"{"event":"data","channel":"diff_order_book_ethbtc","data":{}}"#),
+            Some(BitstampChannel::Diff)
+        );
+        assert_eq!(
+            BitstampChannel::of(
+                r#"{"event":"bts:subscription_succeeded","channel":"live_trades_ethbtc"}"#
+            ),
+            None
+        );
+        assert_eq!(
+            BitstampChannel::FullBook.name("ETHBTC"),
+            "order_book_ethbtc"
+        );
+
+        let book = parse_bitstamp_full_book(FULL_BOOK).unwrap().unwrap();
+        assert_eq!(book.last_update_id, 1_700_000_000_123_456);
+        assert_eq!(book.bids.len(), 12);
+        assert_eq!(book.asks.len(), 12);
+        assert_eq!((book.bids[0].price, book.bids[0].amount), (0.05123, 1.5));
+        assert_eq!((book.asks[0].price, book.asks[0].amount), (0.05125, 0.75));
+        assert!(book.bids.iter().all(|l| l.exchange == Exchange::Bitstamp));
+        assert!(
+            parse_bitstamp_full_book(
+                r#"{"event":"bts:subscription_succeeded","channel":"order_book_ethbtc","data":{}}"#
+            )
+            .unwrap()
+            .is_none()
+        );
+        assert!(
+            parse_bitstamp_full_book(
+                r#"{"event":"data","channel":"order_book_ethbtc","data":{"microtimestamp":"1"}}"#
+            )
+            .is_err()
+        );
+    }
+
+    /// A REST body with `levels` per side, best first
+    fn rest_body(levels: usize) -> String {
+        let side = |best: f64, step: f64| -> String {
+            (0..levels)
+                .map(|i| {
+                    format!(
+                        r#"["{:.8}","{:.8}"]"#,
+                        best + step * i as f64,
+                        1.0 + i as f64
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        format!(
+            r#"{{"timestamp":"1700000000","microtimestamp":"1700000000123456","bids":[{}],"asks":[{}]}}"#,
+            side(0.05123, -0.00001),
+            side(0.05125, 0.00001)
+        )
+    }
+
+    #[test]
+    fn streamed_snapshots_stop_at_the_depth_and_match_the_buffered_parse() {
+        let body = rest_body(500);
+        let buffered = parse_bitstamp_snapshot(&body).unwrap();
+        let whole = read_bitstamp_snapshot(body.as_bytes(), None).unwrap();
+        assert_eq!(whole.last_update_id, buffered.last_update_id);
+        assert_eq!(
+            (whole.bids, whole.asks),
+            (buffered.bids.clone(), buffered.asks.clone())
+        );
+
+        let book = read_bitstamp_snapshot(body.as_bytes(), Some(BITSTAMP_DIFF_DEPTH)).unwrap();
+        assert_eq!(book.last_update_id, buffered.last_update_id);
+        assert_eq!(book.bids, buffered.bids[..BITSTAMP_DIFF_DEPTH]);
+        assert_eq!(book.asks, buffered.asks[..BITSTAMP_DIFF_DEPTH]);
+        // A shallower book than the depth is kept whole
+        let shallow = read_bitstamp_snapshot(rest_body(3).as_bytes(), Some(100)).unwrap();
+        assert_eq!((shallow.bids.len(), shallow.asks.len()), (3, 3));
+
+        // Nothing past the book's closing brace is read
+        let trailing = format!("{} trailing garbage", rest_body(2));
+        assert!(read_bitstamp_snapshot(trailing.as_bytes(), Some(1)).is_ok());
+
+        for invalid in [
+            r#"{"microtimestamp":"1700000000123456","bids":[]}"#,
+            r#"{"microtimestamp":"soon","bids":[],"asks":[]}"#,
+            r#"{"microtimestamp":"1","bids":[["0.05","x"]],"asks":[]}"#,
+            r#"{"microtimestamp":"1","bids":[["0.05"]],"asks":[]}"#,
+            r#"{"microtimestamp":"1","bids":[],"asks":[["0.05","1.0"]"#,
+        ] {
+            assert!(
+                read_bitstamp_snapshot(invalid.as_bytes(), Some(1)).is_err(),
+                "{}",
+                invalid
+            );
         }
-    });
-    let res = ws_stream_bitstamp
-        .send(Message::Text(subscribe_msg.to_string().into()))
-        .await;
-    if res.is_err() {
-        eprintln!("error sending subscribe message: {}", res.err().unwrap());
-    }
-    let (write_stream, read_stream) = ws_stream_bitstamp.split();
-    (write_stream, read_stream)
+    }
 }
@@ -0,0 +1,50 @@
+use std::process::Command;
+
+// Also compiled into `build.rs`, which embeds these results as `BUILD_*` compile-time env vars
+
+/// Embedded when a value can't be determined at build time, e.g. building outside a git checkout
+pub const UNKNOWN: &str = "unknown";
+
+/// Short commit hash from `git` (the program to run), or [`UNKNOWN`]
+pub fn git_hash(git: &str) -> String {
+    run(git, &["rev-parse", "--short=12", "HEAD"]).unwrap_or_else(|| UNKNOWN.to_string())
+}
+
+/// `rustc --version` of the compiler at `rustc`, or [`UNKNOWN`]
+pub fn rustc_version(rustc: &str) -> String {
+    run(rustc, &["--version"]).unwrap_or_else(|| UNKNOWN.to_string())
+}
+
+/// Enabled cargo features, sorted and comma-separated
+pub fn feature_list(features: impl IntoIterator<Item = String>) -> String {
+    let mut features: Vec<String> = features
+        .into_iter()
+        .map(|feature| feature.replace('_', "-"))
+        .collect();
+    features.sort();
+    features.join(",")
+}
+
+/// Trimmed stdout of a successful run, if not empty
+fn run(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    let stdout = stdout.trim();
+    (output.status.success() && !stdout.is_empty()).then(|| stdout.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_tools_fall_back_to_unknown() {
+        assert_eq!(git_hash("/nonexistent/git"), UNKNOWN);
+        assert_eq!(rustc_version("/nonexistent/rustc"), UNKNOWN);
+        assert_eq!(
+            feature_list(["GRPC".to_lowercase(), "core".into(), "some_feature".into()]),
+            "core,grpc,some-feature"
+        );
+        assert_eq!(feature_list([]), "");
+    }
+}
@@ -0,0 +1,73 @@
+use crate::modules::build_env::UNKNOWN;
+
+/// What this binary was built from, embedded by `build.rs`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    /// Short commit hash, or `unknown` when built outside a git checkout
+    pub git_hash: &'static str,
+    pub built_at: u64, // unix secs
+    pub features: Vec<&'static str>,
+    pub rustc_version: &'static str,
+}
+
+impl BuildInfo {
+    pub fn current() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            git_hash: env!("BUILD_GIT_HASH"),
+            built_at: env!("BUILD_TIMESTAMP").parse().unwrap_or(0),
+            features: env!("BUILD_FEATURES")
+                .split(',')
+                .filter(|f| !f.is_empty())
+                .collect(),
+            rustc_version: env!("BUILD_RUSTC_VERSION"),
+        }
+    }
+
+    /// `version+hash`, or just the version without a known hash
+    pub fn build_id(&self) -> String {
+        if self.git_hash == UNKNOWN {
+            self.version.to_string()
+        } else {
+            format!("{}+{}", self.version, self.git_hash)
+        }
+    }
+}
+
+/// The build plus what the running process serves
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServerInfo {
+    pub build: BuildInfo,
+    pub started_at: u64, // unix millis
+    pub uptime_ms: u64,
+    /// Configured exchanges, i.e. every one not left out of the config
+    pub exchanges: Vec<String>,
+    pub symbols: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_build_is_identified_by_version_and_hash() {
+        let build = BuildInfo::current();
+        assert_eq!(build.version, env!("CARGO_PKG_VERSION"));
+        assert!(!build.git_hash.is_empty());
+        assert!(!build.rustc_version.is_empty());
+        assert!(build.built_at > 0);
+        assert_eq!(build.features.contains(&"core"), cfg!(feature = "core"));
+
+        let unknown = BuildInfo {
+            git_hash: UNKNOWN,
+            ..build.clone()
+        };
+        assert_eq!(unknown.build_id(), build.version);
+        let known = BuildInfo {
+            git_hash: "0123456789ab",
+            ..build
+        };
+        assert_eq!(known.build_id(), format!("{}+0123456789ab", known.version));
+    }
+}
@@ -0,0 +1,180 @@
+use crate::modules::types::{Exchange, OrderBook, OrderLevel};
+use serde_json::Value;
+
+/// Levels per side of the `orderbook.50` topic and of the REST book asked for
+pub const BYBIT_DEPTH: usize = 50;
+
+/// REST and websocket base URLs for Bybit spot; overridable for tests and proxies
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BybitEndpoint {
+    pub rest: String,
+    pub ws: String,
+}
+
+impl Default for BybitEndpoint {
+    fn default() -> Self {
+        Self {
+            rest: "https://api.bybit.com".to_string(),
+            ws: "wss://stream.bybit.com/v5/public/spot".to_string(),
+        }
+    }
+}
+
+impl BybitEndpoint {
+    pub fn orderbook_url(&self, symbol: &str) -> String {
+        format!(
+            "{}/v5/market/orderbook?category=spot&symbol={}&limit={}",
+            self.rest,
+            bybit_symbol(symbol),
+            BYBIT_DEPTH
+        )
+    }
+}
+
+/// Bybit's spot symbol: upper-cased with no separator, e.g. `ETHBTC`
+pub fn bybit_symbol(symbol: &str) -> String {
+    symbol.to_uppercase()
+}
+
+/// The depth topic of `symbol`, e.g. `orderbook.50.ETHBTC`
+pub fn topic(symbol: &str) -> String {
+    format!("orderbook.{}.{}", BYBIT_DEPTH, bybit_symbol(symbol))
+}
+
+/// The `subscribe` (or `unsubscribe`) message for the depth topic of `symbol`
+pub fn subscription_message(op: &str, symbol: &str) -> String {
+    serde_json::json!({ "op": op, "args": [topic(symbol)] }).to_string()
+}
+
+pub fn subscribe_message(symbol: &str) -> String {
+    subscription_message("subscribe", symbol)
+}
+
+/// Whether a depth message is the `snapshot` sent on subscribing rather than a `delta`
+pub fn is_book_snapshot(text: &str) -> bool {
+    text.contains("\"type\":\"snapshot\"")
+}
+
+/// Levels of one side, `[price, size]` each
+pub fn parse_levels(side: &Value) -> Option<Vec<OrderLevel>> {
+    side.as_array()?
+        .iter()
+        .map(|level| {
+            Some(OrderLevel {
+                exchange: Exchange::Bybit,
+                price: level[0].as_str()?.parse::<f64>().ok()?,
+                amount: level[1].as_str()?.parse::<f64>().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Parse the REST orderbook body. Its id is the `u` the 50-level topic sequences its
+/// messages by; the topic's snapshot, sent on subscribing, still replaces it and starts the
+/// connector's sequence.
+pub fn parse_bybit_snapshot(body: &str) -> Option<OrderBook> {
+    let v: Value = serde_json::from_str(body).ok()?;
+    if v["retCode"].as_i64() != Some(0) {
+        return None;
+    }
+    Some(OrderBook {
+        last_update_id: v["result"]["u"].as_u64()?,
+        bids: parse_levels(&v["result"]["b"])?,
+        asks: parse_levels(&v["result"]["a"])?,
+    })
+}
+
+/// Where a delta stands in the subscription's sequence
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SequenceCheck {
+    InSequence,
+    /// `u` skipped ahead: the book has diverged and Bybit needs a resubscribe
+    Gap {
+        expected: u64,
+        got: u64,
+    },
+    /// Dropped while waiting for the snapshot of a (re)subscription
+    AwaitingSnapshot,
+}
+
+/// Follows the `u` of one subscription: each delta must be the one after the last message
+#[derive(Debug, Default)]
+pub struct BybitSequence {
+    last: Option<u64>,
+}
+
+impl BybitSequence {
+    /// A snapshot starts the sequence afresh
+    pub fn on_snapshot(&mut self, id: u64) {
+        self.last = Some(id);
+    }
+
+    pub fn check(&mut self, id: u64) -> SequenceCheck {
+        let Some(last) = self.last else {
+            return SequenceCheck::AwaitingSnapshot;
+        };
+        if id > last + 1 {
+            // Nothing is applied until the resubscription's snapshot
+            self.last = None;
+            return SequenceCheck::Gap {
+                expected: last + 1,
+                got: id,
+            };
+        }
+        self.last = Some(last.max(id));
+        SequenceCheck::InSequence
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symbols_map_to_bybit_topics() {
+        assert_eq!(topic("ethbtc"), "orderbook.50.ETHBTC");
+        assert_eq!(
+            BybitEndpoint::default().orderbook_url("btcusdt"),
+            "https://api.bybit.com/v5/market/orderbook?category=spot&symbol=BTCUSDT&limit=50"
+        );
+        let subscribe: Value = serde_json::from_str(&subscribe_message("ethbtc")).unwrap();
+        assert_eq!(subscribe["op"], "subscribe");
+        assert_eq!(subscribe["args"][0], "orderbook.50.ETHBTC");
+
+        let body = r#"{"retCode":0,"retMsg":"OK","result":{"s":"ETHBTC",
+            "b":[["0.05120","2.0"],["0.05119","0.25"]],"a":[["0.05125","1.5"]],
+            "ts":1700000000000,"u":912345,"seq":7961638724},"time":1700000000001}"#;
+        let book = parse_bybit_snapshot(body).unwrap();
+        assert_eq!(book.last_update_id, 912345);
+        assert!(crate::modules::aggregated_orderbook::snapshot_ids_follow_stream(Exchange::Bybit));
+        assert_eq!((book.bids[1].price, book.asks[0].amount), (0.05119, 1.5));
+        assert!(book.bids.iter().all(|l| l.exchange == Exchange::Bybit));
+        assert!(
+            parse_bybit_snapshot(
+                r#"{"retCode":10001,"retMsg":"Not supported symbols","result":{}}"#
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn a_gap_waits_for_the_next_snapshot() {
+        let mut sequence = BybitSequence::default();
+        assert_eq!(sequence.check(5), SequenceCheck::AwaitingSnapshot);
+        sequence.on_snapshot(10);
+        assert_eq!(sequence.check(11), SequenceCheck::InSequence);
+        // Redelivered: left to the book's stale check
+        assert_eq!(sequence.check(11), SequenceCheck::InSequence);
+        assert_eq!(sequence.check(12), SequenceCheck::InSequence);
+        assert_eq!(
+            sequence.check(15),
+            SequenceCheck::Gap {
+                expected: 13,
+                got: 15
+            }
+        );
+        assert_eq!(sequence.check(16), SequenceCheck::AwaitingSnapshot);
+        sequence.on_snapshot(20);
+        assert_eq!(sequence.check(21), SequenceCheck::InSequence);
+    }
+}
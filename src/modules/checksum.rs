@@ -0,0 +1,124 @@
+use crate::modules::types::OrderLevel;
+
+/// Entries per side covered by the checksum
+pub const CHECKSUM_DEPTH: usize = 10;
+
+/// The text a book checksum is computed over, from each side's entries as `(exchange,
+/// price, amount)` in ladder order (best first). This is the canonicalization clients
+/// must reproduce:
+///
+/// - the first [`CHECKSUM_DEPTH`] entries of each side, fewer if the side is shorter
+/// - each entry as `exchange:price:amount`, with price and amount to 8 decimals (`%.8f`)
+/// - entries joined by `,`, bids then asks joined by `|`
+///
+/// e.g. `binance:0.06510000:1.00000000|bitstamp:0.06520000:2.00000000`. Prices and amounts
+/// are taken as sent, so a ladder rounded for display or with cumulative amounts is
+/// checksummed as such.
+pub fn canonical<'a>(
+    bids: impl IntoIterator<Item = (&'a str, f64, f64)>,
+    asks: impl IntoIterator<Item = (&'a str, f64, f64)>,
+) -> String {
+    let side = |levels: Vec<(&str, f64, f64)>| {
+        levels
+            .into_iter()
+            .take(CHECKSUM_DEPTH)
+            .map(|(exchange, price, amount)| format!("{}:{:.8}:{:.8}", exchange, price, amount))
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    format!(
+        "{}|{}",
+        side(bids.into_iter().collect()),
+        side(asks.into_iter().collect())
+    )
+}
+
+/// CRC-32 (IEEE, as zlib's `crc32`) of [`canonical`]
+pub fn checksum<'a>(
+    bids: impl IntoIterator<Item = (&'a str, f64, f64)>,
+    asks: impl IntoIterator<Item = (&'a str, f64, f64)>,
+) -> u32 {
+    crc32(canonical(bids, asks).as_bytes())
+}
+
+/// [`checksum`] of a ladder of book levels
+pub fn book_checksum(bids: &[OrderLevel], asks: &[OrderLevel]) -> u32 {
+    let entry = |level: &OrderLevel| (level.exchange.as_str(), level.price, level.amount);
+    checksum(bids.iter().map(entry), asks.iter().map(entry))
+}
+
+/// CRC-32 (IEEE 802.3), as zlib computes it
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::types::Exchange;
+
+    fn level(exchange: Exchange, price: f64, amount: f64) -> OrderLevel {
+        OrderLevel {
+            exchange,
+            price,
+            amount,
+        }
+    }
+
+    // Pinned values: a change here breaks every client that recomputes the checksum
+    #[test]
+    fn the_canonicalization_is_pinned() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        let bids = [level(Exchange::Binance, 0.0651, 1.0)];
+        let asks = [level(Exchange::Bitstamp, 0.0652, 2.0)];
+        let entries = |levels: &[OrderLevel]| {
+            levels
+                .iter()
+                .map(|l| (l.exchange.as_str(), l.price, l.amount))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            canonical(entries(&bids), entries(&asks)),
+            "binance:0.06510000:1.00000000|bitstamp:0.06520000:2.00000000"
+        );
+        assert_eq!(book_checksum(&bids, &asks), 0x9929_B762);
+        assert_eq!(book_checksum(&[], &[]), 0x8BB1_D29A);
+    }
+
+    #[test]
+    fn only_the_top_ten_entries_of_each_side_count() {
+        let book = |depth: usize| {
+            let bids: Vec<_> = (0..depth)
+                .map(|i| level(Exchange::Binance, 0.05 - i as f64 * 0.001, i as f64 + 1.5))
+                .collect();
+            let asks: Vec<_> = (0..depth)
+                .map(|i| level(Exchange::Bitstamp, 0.051 + i as f64 * 0.001, 0.25))
+                .collect();
+            (bids, asks)
+        };
+        let (bids, asks) = book(12);
+        assert_eq!(book_checksum(&bids, &asks), 0x70CE_0BF5);
+        let (bids, asks) = book(10);
+        assert_eq!(book_checksum(&bids, &asks), 0x70CE_0BF5);
+
+        // Any change in the top ten does count
+        let mut moved = bids.clone();
+        moved[9].amount += 0.00000001;
+        assert_ne!(book_checksum(&moved, &asks), 0x70CE_0BF5);
+        let mut other = bids;
+        other[0].exchange = Exchange::BinanceUs;
+        assert_ne!(book_checksum(&other, &asks), 0x70CE_0BF5);
+    }
+}
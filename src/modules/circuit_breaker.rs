@@ -0,0 +1,255 @@
+use crate::modules::clock::SharedClock;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Connect attempts are allowed
+    Closed,
+    /// Too many recent failures: no attempts until the cool-down has passed
+    Open,
+    /// Cool-down elapsed: the next attempt is a probe that closes or re-opens the circuit
+    HalfOpen,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CircuitBreakerConfig {
+    /// Consecutive failures within `window` that open the circuit
+    pub failure_threshold: usize,
+    pub window: Duration,
+    pub cool_down: Duration,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            window: Duration::from_secs(60),
+            cool_down: Duration::from_secs(300),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BreakerStats {
+    pub state: CircuitState,
+    pub consecutive_failures: u64,
+    pub total_failures: u64,
+    pub times_opened: u64,
+    /// Unix millis at which an open circuit becomes eligible for a probe
+    pub open_until: Option<u64>,
+}
+
+/// Per-exchange circuit breaker for connect/sync attempts, so an exchange outage
+/// doesn't turn the reconnect loop into a snapshot request storm.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    name: String,
+    clock: SharedClock,
+    config: CircuitBreakerConfig,
+    state: CircuitState,
+    // Timestamps (unix millis) of the current run of consecutive failures
+    failures: VecDeque<u64>,
+    consecutive_failures: u64,
+    total_failures: u64,
+    times_opened: u64,
+    opened_at: Option<u64>,
+}
+
+impl CircuitBreaker {
+    pub fn new(name: &str, clock: SharedClock, config: CircuitBreakerConfig) -> Self {
+        Self {
+            name: name.to_string(),
+            clock,
+            config,
+            state: CircuitState::Closed,
+            failures: VecDeque::new(),
+            consecutive_failures: 0,
+            total_failures: 0,
+            times_opened: 0,
+            opened_at: None,
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        self.state
+    }
+
+    pub fn stats(&self) -> BreakerStats {
+        BreakerStats {
+            state: self.state,
+            consecutive_failures: self.consecutive_failures,
+            total_failures: self.total_failures,
+            times_opened: self.times_opened,
+            open_until: self.open_until(),
+        }
+    }
+
+    /// Unix millis at which an open circuit may be probed
+    pub fn open_until(&self) -> Option<u64> {
+        match self.state {
+            CircuitState::Open => self
+                .opened_at
+                .map(|at| at + self.config.cool_down.as_millis() as u64),
+            _ => None,
+        }
+    }
+
+    /// Whether an open circuit has finished its cool-down
+    pub fn probe_due(&self) -> bool {
+        self.open_until()
+            .is_some_and(|until| self.clock.now_millis() >= until)
+    }
+
+    /// Whether a connect attempt may be made now. Moves an open circuit whose
+    /// cool-down has elapsed to half-open.
+    pub fn allow_attempt(&mut self) -> bool {
+        match self.state {
+            CircuitState::Closed | CircuitState::HalfOpen => true,
+            CircuitState::Open => {
+                if self.probe_due() {
+                    self.transition(CircuitState::HalfOpen);
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    pub fn record_success(&mut self) {
+        self.failures.clear();
+        self.consecutive_failures = 0;
+        if self.state != CircuitState::Closed {
+            self.transition(CircuitState::Closed);
+        }
+    }
+
+    /// Record a failed attempt and return the resulting state
+    pub fn record_failure(&mut self) -> CircuitState {
+        let now = self.clock.now_millis();
+        let window = self.config.window.as_millis() as u64;
+        self.consecutive_failures += 1;
+        self.total_failures += 1;
+        self.failures.push_back(now);
+        while self
+            .failures
+            .front()
+            .is_some_and(|at| now.saturating_sub(*at) > window)
+        {
+            self.failures.pop_front();
+        }
+
+        let should_open = match self.state {
+            CircuitState::HalfOpen => true,
+            CircuitState::Closed => self.failures.len() >= self.config.failure_threshold,
+            CircuitState::Open => false,
+        };
+        if should_open {
+            self.opened_at = Some(now);
+            self.times_opened += 1;
+            self.failures.clear();
+            self.transition(CircuitState::Open);
+        }
+        self.state
+    }
+
+    fn transition(&mut self, to: CircuitState) {
+        tracing::warn!(
+            "{} circuit {:?} -> {:?} ({} consecutive failures, opened {} times)",
+            self.name,
+            self.state,
+            to,
+            self.consecutive_failures,
+            self.times_opened
+        );
+        self.state = to;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::clock::MockClock;
+    use std::sync::Arc;
+
+    fn breaker(clock: &Arc<MockClock>) -> CircuitBreaker {
+        CircuitBreaker::new(
+            "binance",
+            clock.clone(),
+            CircuitBreakerConfig {
+                failure_threshold: 3,
+                window: Duration::from_secs(60),
+                cool_down: Duration::from_secs(300),
+            },
+        )
+    }
+
+    #[test]
+    fn opens_after_threshold_failures_within_window() {
+        let clock = Arc::new(MockClock::new(0));
+        let mut b = breaker(&clock);
+        assert_eq!(b.record_failure(), CircuitState::Closed);
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(b.record_failure(), CircuitState::Closed);
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(b.record_failure(), CircuitState::Open);
+        assert!(!b.allow_attempt());
+
+        let stats = b.stats();
+        assert_eq!(stats.times_opened, 1);
+        assert_eq!(stats.consecutive_failures, 3);
+        assert_eq!(stats.open_until, Some(320_000));
+    }
+
+    #[test]
+    fn failures_spread_beyond_the_window_keep_it_closed() {
+        let clock = Arc::new(MockClock::new(0));
+        let mut b = breaker(&clock);
+        for _ in 0..5 {
+            assert_eq!(b.record_failure(), CircuitState::Closed);
+            clock.advance(Duration::from_secs(31));
+        }
+        assert_eq!(b.stats().consecutive_failures, 5);
+    }
+
+    #[test]
+    fn success_resets_the_failure_run() {
+        let clock = Arc::new(MockClock::new(0));
+        let mut b = breaker(&clock);
+        b.record_failure();
+        b.record_failure();
+        b.record_success();
+        assert_eq!(b.record_failure(), CircuitState::Closed);
+        assert_eq!(b.stats().total_failures, 3);
+    }
+
+    #[test]
+    fn half_open_probe_closes_or_reopens() {
+        let clock = Arc::new(MockClock::new(0));
+        let mut b = breaker(&clock);
+        for _ in 0..3 {
+            b.record_failure();
+        }
+
+        clock.advance(Duration::from_secs(299));
+        assert!(!b.probe_due());
+        assert!(!b.allow_attempt());
+        clock.advance(Duration::from_secs(1));
+        assert!(b.probe_due());
+        assert!(b.allow_attempt());
+        assert_eq!(b.state(), CircuitState::HalfOpen);
+
+        // A failed probe re-opens immediately for another full cool-down
+        assert_eq!(b.record_failure(), CircuitState::Open);
+        assert_eq!(b.stats().times_opened, 2);
+        assert!(!b.allow_attempt());
+
+        clock.advance(Duration::from_secs(300));
+        assert!(b.allow_attempt());
+        b.record_success();
+        assert_eq!(b.state(), CircuitState::Closed);
+        assert_eq!(b.stats().consecutive_failures, 0);
+        assert_eq!(b.stats().open_until, None);
+    }
+}
@@ -0,0 +1,128 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Wall-clock time source shared by the aggregator, freshness tracking and Summary construction.
+/// Everything is expressed as unix time so it can be correlated with Bitstamp's microtimestamps.
+pub trait Clock: Send + Sync + Debug {
+    /// Microseconds since the unix epoch
+    fn now_micros(&self) -> u64;
+
+    /// Milliseconds since the unix epoch
+    fn now_millis(&self) -> u64 {
+        self.now_micros() / 1_000
+    }
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_micros(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_micros() as u64)
+            .unwrap_or(0)
+    }
+}
+
+pub fn system_clock() -> SharedClock {
+    Arc::new(SystemClock)
+}
+
+/// Manually driven clock for tests: time only moves when `advance`/`set_millis` is called
+#[derive(Debug, Default)]
+pub struct MockClock {
+    micros: AtomicU64,
+}
+
+impl MockClock {
+    pub fn new(start_millis: u64) -> Self {
+        Self {
+            micros: AtomicU64::new(start_millis * 1_000),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.micros
+            .fetch_add(by.as_micros() as u64, Ordering::SeqCst);
+    }
+
+    pub fn set_millis(&self, millis: u64) {
+        self.micros.store(millis * 1_000, Ordering::SeqCst);
+    }
+}
+
+impl Clock for MockClock {
+    fn now_micros(&self) -> u64 {
+        self.micros.load(Ordering::SeqCst)
+    }
+}
+
+/// Paused tokio time and a `MockClock` stepped together, for `#[tokio::test(start_paused = true)]`
+/// tests of code that both sleeps and reads the clock
+#[cfg(test)]
+#[derive(Clone, Debug)]
+pub struct TestTime {
+    pub clock: Arc<MockClock>,
+}
+
+#[cfg(test)]
+impl TestTime {
+    pub fn new(start_millis: u64) -> Self {
+        Self {
+            clock: Arc::new(MockClock::new(start_millis)),
+        }
+    }
+
+    pub fn shared(&self) -> SharedClock {
+        self.clock.clone()
+    }
+
+    /// Move both clocks forward, letting the tasks woken by timers that come due run. The
+    /// mock clock jumps straight to the end; tokio time passes each timer in order.
+    pub async fn advance(&self, by: Duration) {
+        self.clock.advance(by);
+        tokio::time::sleep(by).await;
+        tokio::task::yield_now().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mock_clock_only_moves_when_advanced() {
+        let clock = MockClock::new(1_000);
+        assert_eq!(clock.now_millis(), 1_000);
+        assert_eq!(clock.now_micros(), 1_000_000);
+
+        clock.advance(Duration::from_millis(250));
+        assert_eq!(clock.now_millis(), 1_250);
+
+        clock.set_millis(5);
+        assert_eq!(clock.now_millis(), 5);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_time_steps_the_mock_clock_and_tokio_timers_together() {
+        let time = TestTime::new(1_000);
+        let started = tokio::time::Instant::now();
+        let sleeper = tokio::spawn(tokio::time::sleep(Duration::from_secs(3600)));
+
+        time.advance(Duration::from_secs(3600)).await;
+        assert_eq!(time.shared().now_millis(), 3_601_000);
+        assert_eq!(started.elapsed(), Duration::from_secs(3600));
+        sleeper.await.unwrap();
+    }
+
+    #[test]
+    fn system_clock_is_unix_time() {
+        // 2020-01-01T00:00:00Z in millis
+        assert!(SystemClock.now_millis() > 1_577_836_800_000);
+    }
+}
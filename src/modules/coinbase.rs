@@ -0,0 +1,155 @@
+use crate::config::dashed_instrument;
+use crate::modules::types::{Exchange, OrderBook, OrderLevel};
+use serde_json::Value;
+
+/// The level2 channel, batched every 50ms: the unbatched one needs an authenticated
+/// connection, and both send the same `snapshot` and `l2update` messages
+pub const COINBASE_CHANNEL: &str = "level2_batch";
+
+/// REST and websocket base URLs for Coinbase Exchange; overridable for tests and proxies
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CoinbaseEndpoint {
+    pub rest: String,
+    pub ws: String,
+}
+
+impl Default for CoinbaseEndpoint {
+    fn default() -> Self {
+        Self {
+            rest: "https://api.exchange.coinbase.com".to_string(),
+            ws: "wss://ws-feed.exchange.coinbase.com".to_string(),
+        }
+    }
+}
+
+impl CoinbaseEndpoint {
+    pub fn book_url(&self, symbol: &str) -> String {
+        format!(
+            "{}/products/{}/book?level=2",
+            self.rest,
+            coinbase_product(symbol)
+        )
+    }
+}
+
+/// Coinbase's product id for `symbol`: base and quote upper-cased and split by `-`, e.g.
+/// `ETH-BTC` for ethbtc. A symbol with no known quote is only upper-cased.
+pub fn coinbase_product(symbol: &str) -> String {
+    dashed_instrument(symbol)
+}
+
+/// The `subscribe` message for the level2 channel of `symbol`
+pub fn subscribe_message(symbol: &str) -> String {
+    serde_json::json!({
+        "type": "subscribe",
+        "product_ids": [coinbase_product(symbol)],
+        "channels": [COINBASE_CHANNEL]
+    })
+    .to_string()
+}
+
+/// Whether a level2 message is the `snapshot` sent on subscribing rather than an `l2update`
+pub fn is_book_snapshot(text: &str) -> bool {
+    text.contains("\"type\":\"snapshot\"")
+}
+
+/// Microseconds since the epoch of an RFC 3339 UTC time as Coinbase sends them, e.g.
+/// `2019-08-14T20:42:27.265123Z`
+pub fn timestamp_micros(time: &str) -> Option<u64> {
+    let time = time.strip_suffix('Z')?;
+    let (date, clock) = time.split_once('T')?;
+    let mut date = date.splitn(3, '-').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    let (clock, fraction) = clock.split_once('.').unwrap_or((clock, ""));
+    let mut clock = clock.splitn(3, ':').map(|part| part.parse::<u64>().ok());
+    let (hour, minute, second) = (clock.next()??, clock.next()??, clock.next()??);
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || !fraction.bytes().all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+    // Days from the civil date (Howard Hinnant's algorithm)
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = u64::try_from(era * 146_097 + day_of_era - 719_468).ok()?;
+    let micros = format!("{:0<6}", fraction.get(..6).unwrap_or(fraction));
+    let seconds = days * 86_400 + hour * 3_600 + minute * 60 + second;
+    Some(seconds * 1_000_000 + micros.parse::<u64>().ok()?)
+}
+
+/// Levels of one side of a book, `[price, size, ...]` each
+pub fn parse_levels(side: &Value) -> Option<Vec<OrderLevel>> {
+    side.as_array()?
+        .iter()
+        .map(|level| {
+            Some(OrderLevel {
+                exchange: Exchange::Coinbase,
+                price: level[0].as_str()?.parse::<f64>().ok()?,
+                amount: level[1].as_str()?.parse::<f64>().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// A book with both sides, as the REST product book and the channel's `snapshot` send it.
+/// Coinbase's level2 messages have no sequence number, so the id is the book's `time` in
+/// microseconds, as for `l2update`s; 0 if it has none.
+pub fn parse_book(v: &Value) -> Option<OrderBook> {
+    Some(OrderBook {
+        last_update_id: v["time"].as_str().and_then(timestamp_micros).unwrap_or(0),
+        bids: parse_levels(&v["bids"])?,
+        asks: parse_levels(&v["asks"])?,
+    })
+}
+
+/// Parse the REST product book body
+pub fn parse_coinbase_snapshot(body: &str) -> Option<OrderBook> {
+    parse_book(&serde_json::from_str(body).ok()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symbols_map_to_coinbase_products() {
+        assert_eq!(coinbase_product("ethbtc"), "ETH-BTC");
+        assert_eq!(coinbase_product("BTCUSD"), "BTC-USD");
+        assert_eq!(coinbase_product("sol-usdc"), "SOL-USDC");
+        assert_eq!(coinbase_product("xyz"), "XYZ");
+        assert_eq!(
+            CoinbaseEndpoint::default().book_url("ethbtc"),
+            "https://api.exchange.coinbase.com/products/ETH-BTC/book?level=2"
+        );
+        let subscribe: Value = serde_json::from_str(&subscribe_message("ethbtc")).unwrap();
+        assert_eq!(subscribe["product_ids"][0], "ETH-BTC");
+        assert_eq!(subscribe["channels"][0], COINBASE_CHANNEL);
+    }
+
+    #[test]
+    fn times_are_read_to_the_microsecond() {
+        assert_eq!(timestamp_micros("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(
+            timestamp_micros("2023-11-14T22:13:20.5Z"),
+            Some(1_700_000_000_500_000)
+        );
+        assert_eq!(
+            timestamp_micros("2024-02-29T00:00:01.123456789Z"),
+            Some(1_709_164_801_123_456)
+        );
+        assert_eq!(timestamp_micros("2023-11-14 22:13:20Z"), None);
+        assert_eq!(timestamp_micros("2023-13-01T00:00:00Z"), None);
+
+        let body = r#"{"bids":[["0.05120","2.0",3]],"asks":[["0.05125","1.5",1]],
+            "sequence":9182,"auction_mode":false,"auction":null,"time":"2023-11-14T22:13:20.000100Z"}"#;
+        let book = parse_coinbase_snapshot(body).unwrap();
+        assert_eq!(book.last_update_id, 1_700_000_000_000_100);
+        assert_eq!((book.bids[0].price, book.asks[0].amount), (0.0512, 1.5));
+        assert_eq!(book.bids[0].exchange, Exchange::Coinbase);
+        assert!(parse_coinbase_snapshot(r#"{"message":"NotFound"}"#).is_none());
+    }
+}
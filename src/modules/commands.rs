@@ -0,0 +1,269 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+/// Commands queued per connector before senders are refused; commands are rare
+const COMMAND_CAPACITY: usize = 16;
+
+/// Control-plane requests for one (exchange, symbol) connector
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConnectorCommand {
+    /// Rebuild the book from fresh snapshots
+    Resync,
+    /// Take the exchange out of the book: its levels are removed and its updates dropped
+    Pause,
+    /// Apply updates again; resyncs, since diffs were dropped while paused
+    Resume,
+    /// Drop the connection and reconnect without waiting for the reconnect delay
+    ReconnectNow,
+    /// Disconnect the exchange for good and remove its levels
+    Shutdown,
+    /// Playback speed multiplier; only replayed feeds have one
+    SetStreamSpeed(f64),
+}
+
+impl ConnectorCommand {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConnectorCommand::Resync => "resync",
+            ConnectorCommand::Pause => "pause",
+            ConnectorCommand::Resume => "resume",
+            ConnectorCommand::ReconnectNow => "reconnect_now",
+            ConnectorCommand::Shutdown => "shutdown",
+            ConnectorCommand::SetStreamSpeed(_) => "set_stream_speed",
+        }
+    }
+}
+
+/// What the connector's select loop does after a command
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommandAction {
+    Continue,
+    /// Fetch fresh snapshots
+    Resync,
+    /// Reconnect straight away, skipping the reconnect delay
+    Reconnect,
+    /// Stop connecting the exchange
+    Stop,
+}
+
+/// Per-feed state changed by commands
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FeedControl {
+    pub paused: bool,
+    pub stopped: bool,
+}
+
+impl FeedControl {
+    /// Whether the exchange should be connected and its updates applied
+    pub fn active(&self) -> bool {
+        !self.paused && !self.stopped
+    }
+
+    pub fn apply(&mut self, command: ConnectorCommand) -> CommandAction {
+        match command {
+            ConnectorCommand::Resync => CommandAction::Resync,
+            ConnectorCommand::Pause => {
+                self.paused = true;
+                CommandAction::Continue
+            }
+            ConnectorCommand::Resume if self.paused => {
+                self.paused = false;
+                CommandAction::Resync
+            }
+            ConnectorCommand::Resume => CommandAction::Continue,
+            ConnectorCommand::ReconnectNow => CommandAction::Reconnect,
+            ConnectorCommand::Shutdown => {
+                self.stopped = true;
+                CommandAction::Stop
+            }
+            ConnectorCommand::SetStreamSpeed(speed) => {
+                tracing::warn!(speed, "Stream speed ignored: not a replayed feed");
+                CommandAction::Continue
+            }
+        }
+    }
+}
+
+/// Command senders of the running connector tasks, keyed by (exchange, symbol)
+#[derive(Debug, Default)]
+pub struct CommandRegistry {
+    senders: Mutex<BTreeMap<(String, String), mpsc::Sender<ConnectorCommand>>>,
+}
+
+impl CommandRegistry {
+    /// Register a connector task for the feed, replacing any earlier one. The task reads its
+    /// commands from the returned receiver; dropping it unregisters the task.
+    pub fn register(&self, exchange: &str, symbol: &str) -> mpsc::Receiver<ConnectorCommand> {
+        let (tx, rx) = mpsc::channel(COMMAND_CAPACITY);
+        self.senders
+            .lock()
+            .unwrap()
+            .insert((exchange.to_string(), symbol.to_string()), tx);
+        rx
+    }
+
+    /// Queue a command for the feed's task
+    pub fn send(
+        &self,
+        exchange: &str,
+        symbol: &str,
+        command: ConnectorCommand,
+    ) -> Result<(), String> {
+        let key = (exchange.to_string(), symbol.to_string());
+        let mut senders = self.senders.lock().unwrap();
+        let sender = senders
+            .get(&key)
+            .ok_or_else(|| format!("no connector for {} {}", exchange, symbol))?;
+        match sender.try_send(command) {
+            Ok(()) => {
+                tracing::info!(exchange, symbol, command = command.as_str(), "Command sent");
+                Ok(())
+            }
+            Err(TrySendError::Full(_)) => Err(format!(
+                "connector for {} {} has {} commands pending",
+                exchange, symbol, COMMAND_CAPACITY
+            )),
+            Err(TrySendError::Closed(_)) => {
+                senders.remove(&key);
+                Err(format!("connector for {} {} has exited", exchange, symbol))
+            }
+        }
+    }
+
+    /// Feeds whose task is still running, sorted
+    pub fn feeds(&self) -> Vec<(String, String)> {
+        let mut senders = self.senders.lock().unwrap();
+        senders.retain(|_, sender| !sender.is_closed());
+        senders.keys().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::task::JoinHandle;
+
+    /// A connector that records its commands and exits on `Shutdown`
+    fn recording_connector(
+        registry: &CommandRegistry,
+        exchange: &str,
+    ) -> JoinHandle<Vec<ConnectorCommand>> {
+        let mut commands = registry.register(exchange, "ethbtc");
+        tokio::spawn(async move {
+            let mut received = vec![];
+            let mut control = FeedControl::default();
+            while let Some(command) = commands.recv().await {
+                received.push(command);
+                if control.apply(command) == CommandAction::Stop {
+                    break;
+                }
+            }
+            received
+        })
+    }
+
+    #[tokio::test]
+    async fn commands_reach_only_their_feed() {
+        let registry = CommandRegistry::default();
+        let binance = recording_connector(&registry, "binance");
+        let bitstamp = recording_connector(&registry, "bitstamp");
+
+        registry
+            .send("binance", "ethbtc", ConnectorCommand::Pause)
+            .unwrap();
+        registry
+            .send("bitstamp", "ethbtc", ConnectorCommand::SetStreamSpeed(2.0))
+            .unwrap();
+        registry
+            .send("binance", "ethbtc", ConnectorCommand::Resume)
+            .unwrap();
+        let err = registry
+            .send("binance", "btcusdt", ConnectorCommand::Resync)
+            .unwrap_err();
+        assert!(err.contains("no connector for binance btcusdt"), "{}", err);
+        for exchange in ["binance", "bitstamp"] {
+            registry
+                .send(exchange, "ethbtc", ConnectorCommand::Shutdown)
+                .unwrap();
+        }
+
+        assert_eq!(
+            binance.await.unwrap(),
+            vec![
+                ConnectorCommand::Pause,
+                ConnectorCommand::Resume,
+                ConnectorCommand::Shutdown
+            ]
+        );
+        assert_eq!(
+            bitstamp.await.unwrap(),
+            vec![
+                ConnectorCommand::SetStreamSpeed(2.0),
+                ConnectorCommand::Shutdown
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn sending_to_an_exited_task_fails_and_unregisters_it() {
+        let registry = CommandRegistry::default();
+        let binance = recording_connector(&registry, "binance");
+        let _bitstamp = recording_connector(&registry, "bitstamp");
+        registry
+            .send("binance", "ethbtc", ConnectorCommand::Shutdown)
+            .unwrap();
+        binance.await.unwrap();
+
+        assert_eq!(
+            registry.feeds(),
+            vec![("bitstamp".to_string(), "ethbtc".to_string())]
+        );
+        let err = registry
+            .send("binance", "ethbtc", ConnectorCommand::ReconnectNow)
+            .unwrap_err();
+        assert!(err.contains("no connector"), "{}", err);
+
+        // A task that exits without being told to is caught on the next send
+        let mut silent = registry.register("binance_us", "ethbtc");
+        silent.close();
+        let err = registry
+            .send("binance_us", "ethbtc", ConnectorCommand::Resync)
+            .unwrap_err();
+        assert!(err.contains("has exited"), "{}", err);
+        assert!(
+            registry
+                .send("binance_us", "ethbtc", ConnectorCommand::Resync)
+                .unwrap_err()
+                .contains("no connector")
+        );
+    }
+
+    #[test]
+    fn resuming_a_paused_feed_resyncs() {
+        let mut control = FeedControl::default();
+        assert_eq!(
+            control.apply(ConnectorCommand::Resume),
+            CommandAction::Continue
+        );
+        assert_eq!(
+            control.apply(ConnectorCommand::Pause),
+            CommandAction::Continue
+        );
+        assert!(control.paused);
+        assert_eq!(
+            control.apply(ConnectorCommand::Resume),
+            CommandAction::Resync
+        );
+        assert!(!control.paused);
+        assert_eq!(
+            control.apply(ConnectorCommand::ReconnectNow),
+            CommandAction::Reconnect
+        );
+        assert_eq!(
+            control.apply(ConnectorCommand::Shutdown),
+            CommandAction::Stop
+        );
+        assert!(control.stopped);
+    }
+}
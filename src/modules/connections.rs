@@ -0,0 +1,94 @@
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Live (exchange, symbol) connections. A connector must hold a claim for as long as its
+/// socket is open, so a second connection to the same feed can't double-apply diffs.
+#[derive(Debug, Default)]
+pub struct ConnectionRegistry {
+    active: Mutex<BTreeSet<(String, String)>>,
+    duplicate_claims: AtomicU64,
+}
+
+/// Releases its (exchange, symbol) slot when dropped
+#[derive(Debug)]
+pub struct ConnectionClaim {
+    registry: Arc<ConnectionRegistry>,
+    key: (String, String),
+}
+
+impl Drop for ConnectionClaim {
+    fn drop(&mut self) {
+        self.registry.active.lock().unwrap().remove(&self.key);
+    }
+}
+
+impl ConnectionRegistry {
+    /// Claim the feed, or `None` (logged and counted) if another connector already holds it
+    pub fn claim(self: &Arc<Self>, exchange: &str, symbol: &str) -> Option<ConnectionClaim> {
+        let key = (exchange.to_string(), symbol.to_string());
+        if !self.active.lock().unwrap().insert(key.clone()) {
+            self.duplicate_claims.fetch_add(1, Ordering::Relaxed);
+            tracing::error!(
+                "{} {} already has a live connection, aborting the duplicate",
+                exchange,
+                symbol
+            );
+            return None;
+        }
+        Some(ConnectionClaim {
+            registry: Arc::clone(self),
+            key,
+        })
+    }
+
+    /// Claimed (exchange, symbol) pairs, sorted
+    pub fn active(&self) -> Vec<(String, String)> {
+        self.active.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn duplicate_claims(&self) -> u64 {
+        self.duplicate_claims.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Barrier;
+    use std::thread;
+
+    #[test]
+    fn racing_spawns_let_only_one_connection_through() {
+        let registry = Arc::new(ConnectionRegistry::default());
+        let barrier = Arc::new(Barrier::new(8));
+        let connectors: Vec<_> = (0..8)
+            .map(|_| {
+                let registry = Arc::clone(&registry);
+                let barrier = Arc::clone(&barrier);
+                thread::spawn(move || {
+                    barrier.wait();
+                    registry.claim("binance", "ethbtc")
+                })
+            })
+            .collect();
+        let claims: Vec<ConnectionClaim> = connectors
+            .into_iter()
+            .filter_map(|c| c.join().unwrap())
+            .collect();
+
+        assert_eq!(claims.len(), 1);
+        assert_eq!(registry.duplicate_claims(), 7);
+        assert_eq!(
+            registry.active(),
+            vec![("binance".to_string(), "ethbtc".to_string())]
+        );
+
+        // Other feeds are unaffected, and teardown frees the slot
+        let other = registry.claim("bitstamp", "ethbtc");
+        assert!(other.is_some());
+        drop(claims);
+        assert!(registry.claim("binance", "ethbtc").is_some());
+        assert_eq!(registry.duplicate_claims(), 7);
+    }
+}
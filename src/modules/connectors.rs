@@ -0,0 +1,1056 @@
+use crate::modules::alarms::{
+    Alarm, LogNotifier, Notifier, NotifierConfig, NotifyFuture, WebhookNotifier,
+};
+use crate::modules::backoff::Backoff;
+use crate::modules::binance::{BinanceEndpoint, parse_binance_snapshot, parse_binance_tick_size};
+use crate::modules::bitfinex::{self, BitfinexEndpoint, parse_bitfinex_snapshot};
+use crate::modules::bitstamp::{BitstampChannel, BitstampEndpoint, read_bitstamp_snapshot};
+use crate::modules::bybit::{self, BybitEndpoint, parse_bybit_snapshot};
+use crate::modules::clock::SharedClock;
+use crate::modules::coinbase::{self, CoinbaseEndpoint, parse_coinbase_snapshot};
+use crate::modules::gateio::{self, GateIoEndpoint, parse_gateio_snapshot};
+use crate::modules::kraken::{self, KrakenEndpoint, parse_kraken_snapshot};
+use crate::modules::kucoin::{self, KucoinEndpoint, parse_kucoin_snapshot};
+use crate::modules::limits::PayloadLimits;
+use crate::modules::okx::{self, OkxEndpoint, parse_okx_snapshot};
+use crate::modules::quote::{ReferenceMid, parse_book_ticker_mid};
+use crate::modules::stuck_snapshot::StuckSnapshotGuard;
+use crate::modules::types::OrderBook;
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::{
+    MaybeTlsStream, WebSocketStream, connect_async, connect_async_with_config,
+};
+
+/// Start of the error returned for a REST body over the cap
+const BODY_TOO_LARGE: &str = "response body exceeds";
+
+pub type WsSink = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>;
+pub type WsStream = SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>;
+
+pub fn websocket_config(limits: &PayloadLimits) -> WebSocketConfig {
+    WebSocketConfig::default()
+        .max_message_size(Some(limits.max_frame_bytes))
+        .max_frame_size(Some(limits.max_frame_bytes))
+}
+
+/// Read a REST response body, giving up as soon as it exceeds `max_bytes`
+pub async fn read_body(
+    mut response: reqwest::Response,
+    max_bytes: usize,
+) -> Result<String, String> {
+    let too_large = || format!("{} the {} byte limit", BODY_TOO_LARGE, max_bytes);
+    if response
+        .content_length()
+        .is_some_and(|length| length > max_bytes as u64)
+    {
+        return Err(too_large());
+    }
+    let mut body = vec![];
+    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        if body.len() + chunk.len() > max_bytes {
+            return Err(too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    String::from_utf8(body).map_err(|e| format!("response body is not UTF-8: {}", e))
+}
+
+/// Parse a snapshot body on the blocking pool. A deep book (Binance's 1000 levels) takes
+/// milliseconds to parse, which would stall the stream reads sharing this runtime thread.
+/// `None` if the body doesn't parse or the parser panicked.
+pub async fn parse_off_thread<P>(body: String, parse: P) -> Option<OrderBook>
+where
+    P: FnOnce(&str) -> Option<OrderBook> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || parse(&body))
+        .await
+        .ok()
+        .flatten()
+}
+
+/// Chunks read ahead of the parser when a body is streamed into it
+const STREAMED_CHUNKS: usize = 8;
+
+/// The blocking side of a streamed REST body: reads the chunks as they arrive, and fails
+/// with the reading side's error. Only usable off the async runtime.
+pub struct BodyReader {
+    chunks: tokio::sync::mpsc::Receiver<Result<Vec<u8>, String>>,
+    chunk: Vec<u8>,
+    read: usize,
+}
+
+impl std::io::Read for BodyReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.read == self.chunk.len() {
+            match self.chunks.blocking_recv() {
+                Some(Ok(chunk)) => (self.chunk, self.read) = (chunk, 0),
+                Some(Err(e)) => return Err(std::io::Error::other(e)),
+                None => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.chunk.len() - self.read);
+        buf[..n].copy_from_slice(&self.chunk[self.read..self.read + n]);
+        self.read += n;
+        Ok(n)
+    }
+}
+
+/// Parse a REST response body on the blocking pool as it arrives, so it is never held whole.
+/// The reader fails once the body exceeds `max_bytes`, like `read_body`. A parser that is
+/// done before the end of the body stops the download. `None` if the parser panicked.
+pub async fn parse_streamed<T, P>(
+    mut response: reqwest::Response,
+    max_bytes: usize,
+    parse: P,
+) -> Option<T>
+where
+    T: Send + 'static,
+    P: FnOnce(BodyReader) -> T + Send + 'static,
+{
+    let (tx, chunks) = tokio::sync::mpsc::channel(STREAMED_CHUNKS);
+    let reader = BodyReader {
+        chunks,
+        chunk: vec![],
+        read: 0,
+    };
+    let parsed = tokio::task::spawn_blocking(move || parse(reader));
+    let too_large = || format!("{} the {} byte limit", BODY_TOO_LARGE, max_bytes);
+    if response
+        .content_length()
+        .is_some_and(|length| length > max_bytes as u64)
+    {
+        let _ = tx.send(Err(too_large())).await;
+    } else {
+        let mut length = 0;
+        loop {
+            let chunk = match response.chunk().await {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => break,
+                Err(e) => {
+                    let _ = tx.send(Err(e.to_string())).await;
+                    break;
+                }
+            };
+            length += chunk.len();
+            if length > max_bytes {
+                let _ = tx.send(Err(too_large())).await;
+                break;
+            }
+            if tx.send(Ok(Vec::from(chunk))).await.is_err() {
+                // The parser has what it needs
+                break;
+            }
+        }
+    }
+    drop(tx);
+    parsed.await.ok()
+}
+
+/// Whether a snapshot error came from `read_body`'s size cap
+pub fn is_body_too_large(error: &str) -> bool {
+    error.contains(BODY_TOO_LARGE)
+}
+
+/// Check whether the symbol is listed on this Binance deployment.
+/// `Err` means the check itself failed (e.g. the host was unreachable).
+pub async fn is_binance_symbol_listed(
+    symbol: &str,
+    endpoint: &BinanceEndpoint,
+) -> Result<bool, String> {
+    let response = reqwest::get(endpoint.exchange_info_url(symbol))
+        .await
+        .map_err(|e| {
+            format!(
+                "failed to query {} exchangeInfo: {}",
+                endpoint.exchange().as_str(),
+                e
+            )
+        })?;
+    // exchangeInfo answers unknown symbols with HTTP 400 "Invalid symbol"
+    Ok(response.status().is_success())
+}
+
+/// exchangeInfo for one symbol is a few KB
+const EXCHANGE_INFO_MAX_BYTES: usize = 1 << 20;
+
+/// The price tick Binance lists for `symbol`
+pub async fn get_binance_tick_size(
+    symbol: &str,
+    endpoint: &BinanceEndpoint,
+) -> Result<f64, String> {
+    let exchange = endpoint.exchange().as_str();
+    let response = reqwest::get(endpoint.exchange_info_url(symbol))
+        .await
+        .map_err(|e| format!("failed to query {} exchangeInfo: {}", exchange, e))?;
+    let body = read_body(response, EXCHANGE_INFO_MAX_BYTES)
+        .await
+        .map_err(|e| format!("{} exchangeInfo body failed: {}", exchange, e))?;
+    parse_binance_tick_size(&body, symbol).ok_or_else(|| {
+        format!(
+            "{} exchangeInfo lists no tick size for {}",
+            exchange, symbol
+        )
+    })
+}
+
+pub async fn get_binance_snapshot(
+    symbol: &str,
+    endpoint: &BinanceEndpoint,
+    limits: &PayloadLimits,
+) -> Result<OrderBook, String> {
+    let url = endpoint.depth_url(symbol);
+    let response = reqwest::get(url)
+        .await
+        .map_err(|e| format!("Binance snapshot request failed: {}", e))?;
+    let body = read_body(response, limits.max_snapshot_bytes)
+        .await
+        .map_err(|e| format!("Binance snapshot body failed: {}", e))?;
+    let exchange = endpoint.exchange();
+    parse_off_thread(body, move |body| parse_binance_snapshot(body, exchange))
+        .await
+        .ok_or_else(|| "invalid Binance snapshot".to_string())
+}
+
+pub async fn get_binance_stream(
+    symbol: &str,
+    endpoint: &BinanceEndpoint,
+    limits: &PayloadLimits,
+) -> Result<(WsSink, WsStream), String> {
+    let url = endpoint.stream_url(symbol);
+    let (ws_stream, _) = connect_async_with_config(url, Some(websocket_config(limits)), false)
+        .await
+        .map_err(|e| format!("Binance websocket connect failed: {}", e))?;
+    let (write, read) = ws_stream.split();
+    Ok((write, read))
+}
+
+/// Fetch Bitstamp's REST book, keeping the best `depth` levels of each side, or all of them
+/// for `None`. Bitstamp's REST cache has been seen serving one response for minutes: a book
+/// repeating the id `stuck` last accepted, while the stream has moved well past it, is
+/// fetched again with the cache busted, and given up on after the guard's retries.
+pub async fn get_bitstamp_snapshot(
+    symbol: &str,
+    endpoint: &BitstampEndpoint,
+    limits: &PayloadLimits,
+    depth: Option<usize>,
+    client: &reqwest::Client,
+    stuck: &mut StuckSnapshotGuard,
+) -> Result<OrderBook, String> {
+    let url = endpoint.order_book_url(symbol);
+    let mut retries = 0;
+    loop {
+        let request = if retries == 0 {
+            client.get(&url)
+        } else {
+            let cache_buster = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos();
+            client
+                .get(format!("{}?nocache={}", url, cache_buster))
+                .header(reqwest::header::CACHE_CONTROL, "no-cache")
+        };
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("Bitstamp snapshot request failed: {}", e))?;
+        // The body of a deep pair runs to megabytes, most of it past `depth`
+        let book = parse_streamed(response, limits.max_snapshot_bytes, move |body| {
+            read_bitstamp_snapshot(body, depth)
+        })
+        .await
+        .ok_or_else(|| "invalid Bitstamp snapshot".to_string())?
+        .map_err(|e| {
+            if e.is_io() {
+                format!("Bitstamp snapshot body failed: {}", e)
+            } else {
+                format!("invalid Bitstamp snapshot: {}", e)
+            }
+        })?;
+        if !stuck.is_stuck(book.last_update_id) {
+            stuck.accept(book.last_update_id);
+            return Ok(book);
+        }
+        if retries == stuck.config.max_retries {
+            return Err(stuck.stuck_error("Bitstamp", book.last_update_id));
+        }
+        retries += 1;
+        tracing::warn!(
+            exchange = "bitstamp",
+            "REST cache looks stuck: snapshot {} again while the stream is at {}, retrying with the cache busted ({}/{})",
+            book.last_update_id,
+            stuck.last_stream_id().unwrap_or_default(),
+            retries,
+            stuck.config.max_retries
+        );
+    }
+}
+
+pub async fn get_bitstamp_stream(
+    symbol: &str,
+    endpoint: &BitstampEndpoint,
+    limits: &PayloadLimits,
+    full_book: bool,
+) -> Result<(WsSink, WsStream), String> {
+    let ws_url_bitstamp = endpoint.ws.clone();
+    let (mut ws_stream_bitstamp, _) =
+        connect_async_with_config(&ws_url_bitstamp, Some(websocket_config(limits)), false)
+            .await
+            .map_err(|e| format!("Bitstamp websocket connect failed: {}", e))?;
+    // The full book is only a cross-check; it shares the socket and is told apart by channel
+    let channels = [
+        Some(BitstampChannel::Diff),
+        full_book.then_some(BitstampChannel::FullBook),
+    ];
+    for channel in channels.into_iter().flatten() {
+        let res = ws_stream_bitstamp
+            .send(Message::Text(channel.subscribe_message(symbol).into()))
+            .await;
+        if res.is_err() {
+            eprintln!("error sending subscribe message: {}", res.err().unwrap());
+        }
+    }
+    let (write_stream, read_stream) = ws_stream_bitstamp.split();
+    Ok((write_stream, read_stream))
+}
+
+pub async fn get_kraken_snapshot(
+    symbol: &str,
+    endpoint: &KrakenEndpoint,
+    limits: &PayloadLimits,
+) -> Result<OrderBook, String> {
+    let url = endpoint.depth_url(symbol);
+    let response = reqwest::get(url)
+        .await
+        .map_err(|e| format!("Kraken snapshot request failed: {}", e))?;
+    let body = read_body(response, limits.max_snapshot_bytes)
+        .await
+        .map_err(|e| format!("Kraken snapshot body failed: {}", e))?;
+    parse_off_thread(body, parse_kraken_snapshot)
+        .await
+        .ok_or_else(|| "invalid Kraken snapshot".to_string())
+}
+
+pub async fn get_kraken_stream(
+    symbol: &str,
+    endpoint: &KrakenEndpoint,
+    limits: &PayloadLimits,
+) -> Result<(WsSink, WsStream), String> {
+    let (mut ws_stream, _) =
+        connect_async_with_config(&endpoint.ws, Some(websocket_config(limits)), false)
+            .await
+            .map_err(|e| format!("Kraken websocket connect failed: {}", e))?;
+    ws_stream
+        .send(Message::Text(kraken::subscribe_message(symbol).into()))
+        .await
+        .map_err(|e| format!("Kraken subscribe failed: {}", e))?;
+    let (write, read) = ws_stream.split();
+    Ok((write, read))
+}
+
+pub async fn get_coinbase_snapshot(
+    symbol: &str,
+    endpoint: &CoinbaseEndpoint,
+    limits: &PayloadLimits,
+) -> Result<OrderBook, String> {
+    let url = endpoint.book_url(symbol);
+    // Coinbase refuses REST requests without a User-Agent
+    let response = reqwest::Client::new()
+        .get(url)
+        .header("User-Agent", env!("CARGO_PKG_NAME"))
+        .send()
+        .await
+        .map_err(|e| format!("Coinbase snapshot request failed: {}", e))?;
+    let body = read_body(response, limits.max_snapshot_bytes)
+        .await
+        .map_err(|e| format!("Coinbase snapshot body failed: {}", e))?;
+    parse_off_thread(body, parse_coinbase_snapshot)
+        .await
+        .ok_or_else(|| "invalid Coinbase snapshot".to_string())
+}
+
+pub async fn get_coinbase_stream(
+    symbol: &str,
+    endpoint: &CoinbaseEndpoint,
+    limits: &PayloadLimits,
+) -> Result<(WsSink, WsStream), String> {
+    let (mut ws_stream, _) =
+        connect_async_with_config(&endpoint.ws, Some(websocket_config(limits)), false)
+            .await
+            .map_err(|e| format!("Coinbase websocket connect failed: {}", e))?;
+    ws_stream
+        .send(Message::Text(coinbase::subscribe_message(symbol).into()))
+        .await
+        .map_err(|e| format!("Coinbase subscribe failed: {}", e))?;
+    let (write, read) = ws_stream.split();
+    Ok((write, read))
+}
+
+pub async fn get_okx_snapshot(
+    symbol: &str,
+    endpoint: &OkxEndpoint,
+    limits: &PayloadLimits,
+) -> Result<OrderBook, String> {
+    let url = endpoint.books_url(symbol);
+    let response = reqwest::get(url)
+        .await
+        .map_err(|e| format!("OKX snapshot request failed: {}", e))?;
+    let body = read_body(response, limits.max_snapshot_bytes)
+        .await
+        .map_err(|e| format!("OKX snapshot body failed: {}", e))?;
+    parse_off_thread(body, parse_okx_snapshot)
+        .await
+        .ok_or_else(|| "invalid OKX snapshot".to_string())
+}
+
+pub async fn get_okx_stream(
+    symbol: &str,
+    endpoint: &OkxEndpoint,
+    limits: &PayloadLimits,
+) -> Result<(WsSink, WsStream), String> {
+    let (mut ws_stream, _) =
+        connect_async_with_config(&endpoint.ws, Some(websocket_config(limits)), false)
+            .await
+            .map_err(|e| format!("OKX websocket connect failed: {}", e))?;
+    ws_stream
+        .send(Message::Text(okx::subscribe_message(symbol).into()))
+        .await
+        .map_err(|e| format!("OKX subscribe failed: {}", e))?;
+    let (write, read) = ws_stream.split();
+    Ok((write, read))
+}
+
+pub async fn get_bybit_snapshot(
+    symbol: &str,
+    endpoint: &BybitEndpoint,
+    limits: &PayloadLimits,
+) -> Result<OrderBook, String> {
+    let url = endpoint.orderbook_url(symbol);
+    let response = reqwest::get(url)
+        .await
+        .map_err(|e| format!("Bybit snapshot request failed: {}", e))?;
+    let body = read_body(response, limits.max_snapshot_bytes)
+        .await
+        .map_err(|e| format!("Bybit snapshot body failed: {}", e))?;
+    parse_off_thread(body, parse_bybit_snapshot)
+        .await
+        .ok_or_else(|| "invalid Bybit snapshot".to_string())
+}
+
+pub async fn get_bybit_stream(
+    symbol: &str,
+    endpoint: &BybitEndpoint,
+    limits: &PayloadLimits,
+) -> Result<(WsSink, WsStream), String> {
+    let (mut ws_stream, _) =
+        connect_async_with_config(&endpoint.ws, Some(websocket_config(limits)), false)
+            .await
+            .map_err(|e| format!("Bybit websocket connect failed: {}", e))?;
+    ws_stream
+        .send(Message::Text(bybit::subscribe_message(symbol).into()))
+        .await
+        .map_err(|e| format!("Bybit subscribe failed: {}", e))?;
+    let (write, read) = ws_stream.split();
+    Ok((write, read))
+}
+
+/// Unsubscribe and subscribe again to Bybit's depth topic of `symbol`, for the fresh
+/// snapshot that follows a new subscription
+pub async fn resubscribe_bybit(sink: &mut WsSink, symbol: &str) -> Result<(), String> {
+    for message in [
+        bybit::subscription_message("unsubscribe", symbol),
+        bybit::subscribe_message(symbol),
+    ] {
+        sink.send(Message::Text(message.into()))
+            .await
+            .map_err(|e| format!("Bybit resubscribe failed: {}", e))?;
+    }
+    Ok(())
+}
+
+pub async fn get_kucoin_snapshot(
+    symbol: &str,
+    endpoint: &KucoinEndpoint,
+    limits: &PayloadLimits,
+) -> Result<OrderBook, String> {
+    let url = endpoint.level2_url(symbol);
+    let response = reqwest::get(url)
+        .await
+        .map_err(|e| format!("KuCoin snapshot request failed: {}", e))?;
+    let body = read_body(response, limits.max_snapshot_bytes)
+        .await
+        .map_err(|e| format!("KuCoin snapshot body failed: {}", e))?;
+    parse_off_thread(body, parse_kucoin_snapshot)
+        .await
+        .ok_or_else(|| "invalid KuCoin snapshot".to_string())
+}
+
+/// The token response is well under a KB
+const BULLET_MAX_BYTES: usize = 1 << 16;
+
+/// KuCoin hands out the websocket to connect to with a token: ask `bullet-public` for both
+/// (unless the endpoint names a websocket), connect and subscribe. Also returns how often
+/// the connection must be pinged.
+pub async fn get_kucoin_stream(
+    symbol: &str,
+    endpoint: &KucoinEndpoint,
+    limits: &PayloadLimits,
+) -> Result<(WsSink, WsStream, Duration), String> {
+    let (url, ping_interval) = match &endpoint.ws {
+        Some(ws) => (ws.clone(), kucoin::DEFAULT_PING_INTERVAL),
+        None => {
+            let response = reqwest::Client::new()
+                .post(endpoint.bullet_url())
+                .send()
+                .await
+                .map_err(|e| format!("KuCoin token request failed: {}", e))?;
+            let body = read_body(response, BULLET_MAX_BYTES)
+                .await
+                .map_err(|e| format!("KuCoin token body failed: {}", e))?;
+            let connect_id = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis()
+                .to_string();
+            let token = kucoin::parse_bullet(&body, &connect_id)
+                .map_err(|e| format!("KuCoin token refused: {}", e))?;
+            (token.url, token.ping_interval)
+        }
+    };
+    let (mut ws_stream, _) = connect_async_with_config(&url, Some(websocket_config(limits)), false)
+        .await
+        .map_err(|e| format!("KuCoin websocket connect failed: {}", e))?;
+    ws_stream
+        .send(Message::Text(kucoin::subscribe_message(symbol).into()))
+        .await
+        .map_err(|e| format!("KuCoin subscribe failed: {}", e))?;
+    let (write, read) = ws_stream.split();
+    Ok((write, read, ping_interval))
+}
+
+/// Ping KuCoin every `interval` until the connection fails; it drops connections that go
+/// a ping timeout without one
+pub async fn keep_kucoin_alive(mut sink: WsSink, interval: Duration) {
+    let mut id = 0;
+    loop {
+        tokio::time::sleep(interval).await;
+        id += 1;
+        let ping = Message::Text(kucoin::ping_message(id).into());
+        if let Err(e) = sink.send(ping).await {
+            tracing::debug!("KuCoin ping failed: {}", e);
+            break;
+        }
+    }
+}
+
+pub async fn get_gateio_snapshot(
+    symbol: &str,
+    endpoint: &GateIoEndpoint,
+    limits: &PayloadLimits,
+) -> Result<OrderBook, String> {
+    let url = endpoint.order_book_url(symbol);
+    let response = reqwest::get(url)
+        .await
+        .map_err(|e| format!("Gate.io snapshot request failed: {}", e))?;
+    let body = read_body(response, limits.max_snapshot_bytes)
+        .await
+        .map_err(|e| format!("Gate.io snapshot body failed: {}", e))?;
+    parse_off_thread(body, parse_gateio_snapshot)
+        .await
+        .ok_or_else(|| "invalid Gate.io snapshot".to_string())
+}
+
+pub async fn get_gateio_stream(
+    symbol: &str,
+    endpoint: &GateIoEndpoint,
+    limits: &PayloadLimits,
+) -> Result<(WsSink, WsStream), String> {
+    let (mut ws_stream, _) =
+        connect_async_with_config(&endpoint.ws, Some(websocket_config(limits)), false)
+            .await
+            .map_err(|e| format!("Gate.io websocket connect failed: {}", e))?;
+    ws_stream
+        .send(Message::Text(gateio::subscribe_message(symbol).into()))
+        .await
+        .map_err(|e| format!("Gate.io subscribe failed: {}", e))?;
+    let (write, read) = ws_stream.split();
+    Ok((write, read))
+}
+
+pub async fn get_bitfinex_snapshot(
+    symbol: &str,
+    endpoint: &BitfinexEndpoint,
+    limits: &PayloadLimits,
+) -> Result<OrderBook, String> {
+    let url = endpoint.book_url(symbol);
+    let response = reqwest::get(url)
+        .await
+        .map_err(|e| format!("Bitfinex snapshot request failed: {}", e))?;
+    let body = read_body(response, limits.max_snapshot_bytes)
+        .await
+        .map_err(|e| format!("Bitfinex snapshot body failed: {}", e))?;
+    parse_off_thread(body, parse_bitfinex_snapshot)
+        .await
+        .ok_or_else(|| "invalid Bitfinex snapshot".to_string())
+}
+
+/// Connect and subscribe to the book channel, turning frame timestamps on first
+pub async fn get_bitfinex_stream(
+    symbol: &str,
+    endpoint: &BitfinexEndpoint,
+    limits: &PayloadLimits,
+) -> Result<(WsSink, WsStream), String> {
+    let (mut ws_stream, _) =
+        connect_async_with_config(&endpoint.ws, Some(websocket_config(limits)), false)
+            .await
+            .map_err(|e| format!("Bitfinex websocket connect failed: {}", e))?;
+    for message in [
+        bitfinex::conf_message(),
+        bitfinex::subscribe_message(symbol),
+    ] {
+        ws_stream
+            .send(Message::Text(message.into()))
+            .await
+            .map_err(|e| format!("Bitfinex subscribe failed: {}", e))?;
+    }
+    let (write, read) = ws_stream.split();
+    Ok((write, read))
+}
+
+impl ReferenceMid {
+    /// Keep the mid updated from the symbol's Binance best bid/ask stream, reconnecting
+    /// with backoff. Runs until the task is dropped.
+    pub async fn follow_binance(&self, symbol: &str, endpoint: &BinanceEndpoint) {
+        let mut backoff = Backoff::new(
+            self.clock().clone(),
+            Duration::from_secs(1),
+            Duration::from_secs(60),
+            Duration::from_secs(30),
+        );
+        let url = format!("{}/ws/{}@bookTicker", endpoint.ws, symbol.to_lowercase());
+        loop {
+            match connect_async(url.as_str()).await {
+                Ok((stream, _)) => {
+                    tracing::info!("Following {} as the notional reference", symbol);
+                    backoff.on_connected();
+                    let (_sink, mut stream) = stream.split();
+                    while let Some(Ok(message)) = stream.next().await {
+                        if let Message::Text(text) = message
+                            && let Some(mid) = parse_book_ticker_mid(&text)
+                        {
+                            self.set_mid(mid);
+                        }
+                    }
+                    tracing::warn!("{} reference stream ended, reconnecting", symbol);
+                }
+                Err(e) => tracing::warn!("{} reference connect failed: {}", symbol, e),
+            }
+            tokio::time::sleep(backoff.next_delay()).await;
+        }
+    }
+}
+
+impl WebhookNotifier {
+    async fn post(&self, alarm: &Alarm) -> Result<(), String> {
+        let response = reqwest::Client::new()
+            .post(&self.config.url)
+            .timeout(Duration::from_millis(self.config.timeout_ms))
+            .json(alarm)
+            .send()
+            .await
+            .map_err(|e| format!("webhook request failed: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("webhook answered {}", response.status()));
+        }
+        Ok(())
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    fn notify<'a>(&'a self, alarm: &'a Alarm) -> NotifyFuture<'a> {
+        Box::pin(async move {
+            let initial = Duration::from_millis(self.config.initial_backoff_ms);
+            let mut backoff = Backoff::new(self.clock.clone(), initial, initial * 16, initial);
+            let mut attempt = 0;
+            loop {
+                match self.post(alarm).await {
+                    Ok(()) => return Ok(()),
+                    Err(e) if attempt >= self.config.max_retries => {
+                        return Err(format!("{} (after {} attempts)", e, attempt + 1));
+                    }
+                    Err(e) => tracing::warn!("Alarm webhook failed, retrying: {}", e),
+                }
+                attempt += 1;
+                tokio::time::sleep(backoff.next_delay()).await;
+            }
+        })
+    }
+}
+
+/// The notifier an `alarms.notifiers` entry describes
+pub fn build_notifier(config: &NotifierConfig, clock: SharedClock) -> Arc<dyn Notifier> {
+    match config {
+        NotifierConfig::Log => Arc::new(LogNotifier),
+        NotifierConfig::Webhook(webhook) => Arc::new(WebhookNotifier::new(clock, webhook.clone())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::alarms::{AlarmEvent, WebhookConfig};
+    use crate::modules::clock::system_clock;
+    use crate::modules::quarantine::{Quarantine, QuarantineConfig};
+    use crate::modules::stuck_snapshot::{StuckSnapshotConfig, is_stuck_snapshot};
+    use crate::modules::types::{AggregatedOrderBook, Exchange, OrderBookUpdate};
+    use std::time::Instant;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::Error;
+
+    fn binance_diff(levels: usize) -> String {
+        let levels: Vec<String> = (0..levels)
+            .map(|i| format!(r#"["{}.0","1.0"]"#, 1 + i))
+            .collect();
+        format!(
+            r#"{{"e":"depthUpdate","U":1,"u":2,"b":[{}],"a":[]}}"#,
+            levels.join(",")
+        )
+    }
+
+    #[tokio::test]
+    async fn queued_updates_are_applied_while_a_deep_snapshot_parses() {
+        let side = |start: usize| -> String {
+            (0..5_000)
+                .map(|i| format!(r#"["{}.5","1.0"]"#, start + i))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let body = format!(
+            r#"{{"lastUpdateId":100,"bids":[{}],"asks":[{}]}}"#,
+            side(1),
+            side(10_001)
+        );
+        let (updates_tx, mut updates_rx) = tokio::sync::mpsc::unbounded_channel();
+        updates_tx.send(binance_diff(3)).unwrap();
+        let (started_tx, started_rx) = tokio::sync::oneshot::channel();
+
+        // The test runtime has one thread: parsed on it, the snapshot would hold up the
+        // update until it was done
+        let started = Instant::now();
+        let parse = tokio::spawn(parse_off_thread(body, move |body| {
+            let _ = started_tx.send(());
+            std::thread::sleep(Duration::from_millis(300));
+            parse_binance_snapshot(body, Exchange::Binance)
+        }));
+        started_rx.await.unwrap();
+        let mut book = AggregatedOrderBook::new();
+        let update = OrderBookUpdate::from_binance_json(&updates_rx.recv().await.unwrap());
+        assert!(book.apply_update(update.unwrap()).is_ok());
+        assert!(!parse.is_finished());
+        assert!(started.elapsed() < Duration::from_millis(300));
+
+        let snapshot = parse.await.unwrap().unwrap();
+        assert_eq!((snapshot.bids.len(), snapshot.asks.len()), (5_000, 5_000));
+        assert!(
+            parse_off_thread("{}".to_string(), |_| panic!("parser bug"))
+                .await
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn bodies_over_the_cap_are_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let _ = socket.read(&mut [0u8; 1024]).await;
+                // No Content-Length: the cap has to hold while streaming
+                let _ = socket
+                    .write_all(b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n")
+                    .await;
+                let _ = socket.write_all(&[b'x'; 64 << 10]).await;
+            }
+        });
+
+        let response = reqwest::get(&url).await.unwrap();
+        let err = read_body(response, 16 << 10).await.unwrap_err();
+        assert!(is_body_too_large(&err), "{}", err);
+        let response = reqwest::get(&url).await.unwrap();
+        assert_eq!(read_body(response, 64 << 10).await.unwrap().len(), 64 << 10);
+    }
+
+    #[tokio::test]
+    async fn streamed_bodies_are_capped_and_kept_to_the_depth() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let side = |best: usize| -> String {
+            (0..20_000)
+                .map(|i| format!(r#"["{}.5","1.0"]"#, best - i))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let body = format!(
+            r#"{{"timestamp":"1700000000","microtimestamp":"1700000000000000","bids":[{}],"asks":[{}]}}"#,
+            side(40_000),
+            side(80_000)
+        );
+        let served = body.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let _ = socket.read(&mut [0u8; 1024]).await;
+                // No Content-Length: the cap has to hold while streaming
+                let _ = socket
+                    .write_all(b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n")
+                    .await;
+                let _ = socket.write_all(served.as_bytes()).await;
+            }
+        });
+
+        let response = reqwest::get(&url).await.unwrap();
+        let book = parse_streamed(response, body.len(), |body| {
+            read_bitstamp_snapshot(body, Some(100))
+        })
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!((book.bids.len(), book.asks.len()), (100, 100));
+        assert_eq!(
+            (book.bids[99].price, book.asks[0].price),
+            (39_901.5, 80_000.5)
+        );
+
+        let response = reqwest::get(&url).await.unwrap();
+        let err = parse_streamed(response, body.len() / 2, |body| {
+            read_bitstamp_snapshot(body, Some(100))
+        })
+        .await
+        .unwrap()
+        .unwrap_err();
+        assert!(err.is_io(), "{}", err);
+        assert!(is_body_too_large(&err.to_string()), "{}", err);
+    }
+
+    /// Serve Bitstamp order books over HTTP/1.1, the fresh one only to requests that bust
+    /// the cache if `honour_no_cache`, and pass on each request's head
+    async fn serve_bitstamp(
+        honour_no_cache: bool,
+    ) -> (
+        BitstampEndpoint,
+        tokio::sync::mpsc::UnboundedReceiver<String>,
+    ) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = BitstampEndpoint {
+            rest: format!("http://{}", listener.local_addr().unwrap()),
+            ..BitstampEndpoint::default()
+        };
+        let (requests_tx, requests) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = vec![0u8; 4096];
+                let n = socket.read(&mut request).await.unwrap();
+                let request = String::from_utf8_lossy(&request[..n]).to_lowercase();
+                let microtimestamp = if honour_no_cache && request.contains("no-cache") {
+                    "1700000060000000"
+                } else {
+                    "1700000000000000"
+                };
+                let body = format!(
+                    r#"{{"timestamp":"1700000000","microtimestamp":"{}","bids":[["0.05120","1.0"]],"asks":[["0.05125","1.5"]]}}"#,
+                    microtimestamp
+                );
+                let _ = requests_tx.send(request);
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        (endpoint, requests)
+    }
+
+    #[tokio::test]
+    async fn a_stuck_bitstamp_cache_is_busted_then_quarantined() {
+        let (endpoint, mut requests) = serve_bitstamp(false).await;
+        let limits = PayloadLimits::default();
+        let mut stuck = StuckSnapshotGuard::new(StuckSnapshotConfig {
+            min_lead: 10_000_000,
+            max_retries: 2,
+        });
+        let client = reqwest::Client::new();
+        let book = get_bitstamp_snapshot("ethbtc", &endpoint, &limits, None, &client, &mut stuck)
+            .await
+            .unwrap();
+        assert_eq!(stuck.last_snapshot_id(), Some(book.last_update_id));
+        let first = requests.recv().await.unwrap();
+        assert!(
+            first.starts_with("get /api/v2/order_book/ethbtc/ http/1.1"),
+            "{}",
+            first
+        );
+        assert!(!first.contains("cache-control"), "{}", first);
+
+        // Repeating the id a second on is a quiet book, not a stuck cache
+        stuck.observe_stream(book.last_update_id + 1_000_000);
+        get_bitstamp_snapshot("ethbtc", &endpoint, &limits, None, &client, &mut stuck)
+            .await
+            .unwrap();
+        requests.recv().await.unwrap();
+
+        // The stream has moved a minute on, and every retry gets the same cached body
+        stuck.observe_stream(book.last_update_id + 60_000_000);
+        let err = get_bitstamp_snapshot("ethbtc", &endpoint, &limits, None, &client, &mut stuck)
+            .await
+            .unwrap_err();
+        assert!(is_stuck_snapshot(&err), "{}", err);
+        let plain = requests.recv().await.unwrap();
+        assert!(!plain.contains("no-cache"), "{}", plain);
+        let mut busters = vec![];
+        for _ in 0..2 {
+            let retry = requests.recv().await.unwrap();
+            assert!(
+                retry.contains("\r\ncache-control: no-cache\r\n"),
+                "{}",
+                retry
+            );
+            let path = retry.split(' ').nth(1).unwrap().to_string();
+            assert!(
+                path.starts_with("/api/v2/order_book/ethbtc/?nocache="),
+                "{}",
+                path
+            );
+            busters.push(path);
+        }
+        assert_ne!(busters[0], busters[1], "each retry busts the cache afresh");
+        assert!(requests.try_recv().is_err(), "no retries past the limit");
+
+        let mut agg = AggregatedOrderBook::new();
+        agg.merge_snapshots(vec![book]);
+        let mut quarantine = Quarantine::new(system_clock(), QuarantineConfig::default());
+        assert!(quarantine.on_sync_failure(&mut agg, "bitstamp", &err));
+        assert!(quarantine.cooling_down("bitstamp"));
+        assert!(!agg.last_update_id.contains_key("bitstamp"));
+        // The quarantine forgot the book's ids, but the guard still knows where the stream was
+        let err = get_bitstamp_snapshot("ethbtc", &endpoint, &limits, None, &client, &mut stuck)
+            .await
+            .unwrap_err();
+        assert!(is_stuck_snapshot(&err), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn a_busted_cache_that_serves_a_fresh_book_ends_the_retries() {
+        let (endpoint, mut requests) = serve_bitstamp(true).await;
+        let mut stuck = StuckSnapshotGuard::default();
+        stuck.accept(1_700_000_000_000_000);
+        stuck.observe_stream(1_700_000_045_000_000);
+        let book = get_bitstamp_snapshot(
+            "ethbtc",
+            &endpoint,
+            &PayloadLimits::default(),
+            None,
+            &reqwest::Client::new(),
+            &mut stuck,
+        )
+        .await
+        .unwrap();
+        assert_eq!(book.last_update_id, 1_700_000_060_000_000);
+        assert_eq!(stuck.last_snapshot_id(), Some(book.last_update_id));
+        assert!(!requests.recv().await.unwrap().contains("no-cache"));
+        assert!(requests.recv().await.unwrap().contains("no-cache"));
+        assert!(requests.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn websocket_messages_over_the_cap_fail_the_read() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(socket).await.unwrap();
+            let _ = ws.send(Message::Text(binance_diff(10).into())).await;
+            let _ = ws.send(Message::Text(binance_diff(100_000).into())).await;
+        });
+
+        let limits = PayloadLimits {
+            max_frame_bytes: 64 << 10,
+            ..Default::default()
+        };
+        let (mut ws, _) = tokio_tungstenite::connect_async_with_config(
+            url,
+            Some(websocket_config(&limits)),
+            false,
+        )
+        .await
+        .unwrap();
+        assert!(matches!(ws.next().await, Some(Ok(Message::Text(_)))));
+        assert!(matches!(ws.next().await, Some(Err(Error::Capacity(_)))));
+    }
+
+    #[tokio::test]
+    async fn webhooks_post_the_alarm_and_retry_failures() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/alarms", listener.local_addr().unwrap());
+        let (bodies_tx, mut bodies) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            // Fails the first attempt only
+            for status in [
+                "500 Internal Server Error",
+                "200 OK",
+                "500 Internal Server Error",
+            ] {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = vec![0u8; 4096];
+                let n = socket.read(&mut request).await.unwrap();
+                let request = String::from_utf8_lossy(&request[..n]).to_string();
+                let _ = bodies_tx.send(request.split("\r\n\r\n").nth(1).unwrap().to_string());
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+
+        let config = WebhookConfig {
+            url,
+            max_retries: 1,
+            initial_backoff_ms: 10,
+            ..Default::default()
+        };
+        let notifier = build_notifier(&NotifierConfig::Webhook(config), system_clock());
+        let alarm = Alarm {
+            symbol: "ethbtc".to_string(),
+            raised_at: 1_000,
+            message: "bitstamp quarantined until 31000".to_string(),
+            suppressed: 0,
+            event: AlarmEvent::Quarantined {
+                exchange: "bitstamp".to_string(),
+                until: 31_000,
+            },
+        };
+        notifier.notify(&alarm).await.unwrap();
+        for _ in 0..2 {
+            let body: serde_json::Value =
+                serde_json::from_str(&bodies.recv().await.unwrap()).unwrap();
+            assert_eq!(body["kind"], "quarantined");
+            assert_eq!(body["exchange"], "bitstamp");
+            assert_eq!(body["symbol"], "ethbtc");
+        }
+
+        // Out of retries: the one failure left is reported
+        let err = notifier.notify(&alarm).await.unwrap_err();
+        assert!(err.contains("after 2 attempts"), "{}", err);
+    }
+}
@@ -0,0 +1,289 @@
+use crate::modules::aggregated_orderbook::Top10Snapshot;
+use crate::modules::types::Side;
+use serde::Deserialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Relative tolerance of the spread check, against the larger best price
+pub const SPREAD_EPSILON: f64 = 1e-9;
+
+/// What happens to a Summary that fails the consistency check before it is sent
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SummaryConsistency {
+    /// Send it with the spread recomputed from the ladder; a Summary with a problem the
+    /// spread doesn't explain is still skipped
+    #[default]
+    FixSpread,
+    /// Don't send it
+    Skip,
+}
+
+/// Something wrong with a Summary as a client would read it
+#[derive(Clone, Debug, PartialEq)]
+pub enum Violation {
+    /// `spread` isn't best ask - best bid of the ladder, as its convention reports it
+    SpreadMismatch {
+        spread: Option<f64>,
+        expected: Option<f64>,
+    },
+    /// Bids must not go up, asks not down, from `index - 1` to `index`
+    Unsorted { side: Side, index: usize },
+    /// Negative or NaN
+    BadAmount {
+        side: Side,
+        index: usize,
+        amount: f64,
+    },
+}
+
+/// Spread the ladder implies: `None` while a side of the book is empty. A ladder cut to
+/// nothing implies nothing, so the spread isn't checked then.
+fn expected_spread(snap: &Top10Snapshot) -> Result<Option<f64>, ()> {
+    match (snap.bids.first(), snap.asks.first()) {
+        (Some(bid), Some(ask)) => Ok(Some(ask.price - bid.price)),
+        _ if snap.total_bid_levels == 0 || snap.total_ask_levels == 0 => Ok(None),
+        _ => Err(()),
+    }
+}
+
+/// Every violation in `snap`
+pub fn check(snap: &Top10Snapshot) -> Vec<Violation> {
+    let mut violations = vec![];
+    if let Ok(expected) = expected_spread(snap) {
+        let expected = expected.map(|spread| snap.spread_convention.report(spread));
+        let consistent = match (snap.spread, expected) {
+            (Some(spread), Some(expected)) => {
+                let scale = snap.bids[0]
+                    .price
+                    .abs()
+                    .max(snap.asks[0].price.abs())
+                    .max(1.0);
+                (spread - expected).abs() <= SPREAD_EPSILON * scale
+            }
+            (spread, expected) => spread.is_none() && expected.is_none(),
+        };
+        if !consistent {
+            violations.push(Violation::SpreadMismatch {
+                spread: snap.spread,
+                expected,
+            });
+        }
+    }
+    for (side, levels) in [(Side::Bid, &snap.bids), (Side::Ask, &snap.asks)] {
+        for (index, level) in levels.iter().enumerate() {
+            if level.amount.is_nan() || level.amount < 0.0 {
+                violations.push(Violation::BadAmount {
+                    side,
+                    index,
+                    amount: level.amount,
+                });
+            }
+        }
+        for index in 1..levels.len() {
+            let (previous, price) = (levels[index - 1].price, levels[index].price);
+            let sorted = match side {
+                Side::Bid => price <= previous,
+                Side::Ask => price >= previous,
+            };
+            if !sorted {
+                violations.push(Violation::Unsorted { side, index });
+            }
+        }
+    }
+    violations
+}
+
+/// Counts of Summaries that failed the check, shared by every outbound stream
+#[derive(Debug, Default)]
+pub struct ConsistencyCounters {
+    pub violations: AtomicU64,
+    pub fixed: AtomicU64,
+    pub skipped: AtomicU64,
+}
+
+impl SummaryConsistency {
+    /// `snap` as it may be sent, or `None` to skip it. Every failure is counted, and
+    /// logged in full if `log` allows it.
+    pub fn vet(
+        &self,
+        mut snap: Top10Snapshot,
+        counters: &ConsistencyCounters,
+        log: impl FnOnce() -> bool,
+    ) -> Option<Top10Snapshot> {
+        let violations = check(&snap);
+        if violations.is_empty() {
+            return Some(snap);
+        }
+        counters.violations.fetch_add(1, Ordering::Relaxed);
+        let fixable = violations
+            .iter()
+            .all(|v| matches!(v, Violation::SpreadMismatch { .. }));
+        let fix = *self == SummaryConsistency::FixSpread && fixable;
+        if log() {
+            tracing::error!(
+                symbol = snap.symbol,
+                version = snap.version,
+                spread = ?snap.spread,
+                bids = ?snap.bids,
+                asks = ?snap.asks,
+                "Inconsistent Summary ({}): {:?}",
+                if fix { "spread fixed" } else { "skipped" },
+                violations
+            );
+        }
+        if !fix {
+            counters.skipped.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        counters.fixed.fetch_add(1, Ordering::Relaxed);
+        let expected = expected_spread(&snap).ok().flatten();
+        snap.set_spread(expected);
+        Some(snap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::types::{Exchange, OrderLevel};
+
+    fn level(price: f64, amount: f64) -> OrderLevel {
+        OrderLevel {
+            exchange: Exchange::Binance,
+            price,
+            amount,
+        }
+    }
+
+    fn snap(spread: Option<f64>, bids: Vec<OrderLevel>, asks: Vec<OrderLevel>) -> Top10Snapshot {
+        Top10Snapshot {
+            spread,
+            total_bid_levels: bids.len(),
+            total_ask_levels: asks.len(),
+            bids,
+            asks,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn checks_the_spread_order_and_amounts() {
+        let good = snap(
+            Some(0.00002),
+            vec![
+                level(0.05123, 1.0),
+                level(0.05122, 2.0),
+                level(0.05122, 0.5),
+            ],
+            vec![level(0.05125, 1.0), level(0.05126, 3.0)],
+        );
+        // Index arithmetic can be an ulp off the stored prices; that is within tolerance
+        assert!(check(&good).is_empty());
+        assert!(check(&snap(None, vec![level(1.0, 1.0)], vec![])).is_empty());
+        let mut cut = good.clone();
+        cut.bids.clear();
+        assert!(
+            check(&cut).is_empty(),
+            "a ladder cut to nothing implies no spread"
+        );
+
+        let off = snap(
+            Some(0.0001),
+            vec![level(100.0, 1.0)],
+            vec![level(100.5, 1.0)],
+        );
+        assert_eq!(
+            check(&off),
+            vec![Violation::SpreadMismatch {
+                spread: Some(0.0001),
+                expected: Some(0.5),
+            }]
+        );
+        let missing = snap(None, vec![level(100.0, 1.0)], vec![level(100.5, 1.0)]);
+        assert_eq!(check(&missing).len(), 1);
+
+        let bad = snap(
+            Some(0.5),
+            vec![level(100.0, 1.0), level(100.2, -1.0)],
+            vec![level(100.5, 1.0), level(100.4, 1.0)],
+        );
+        assert_eq!(
+            check(&bad),
+            vec![
+                Violation::BadAmount {
+                    side: Side::Bid,
+                    index: 1,
+                    amount: -1.0
+                },
+                Violation::Unsorted {
+                    side: Side::Bid,
+                    index: 1
+                },
+                Violation::Unsorted {
+                    side: Side::Ask,
+                    index: 1
+                },
+            ]
+        );
+        let nan = snap(
+            Some(0.5),
+            vec![level(100.0, f64::NAN)],
+            vec![level(100.5, 1.0)],
+        );
+        assert!(matches!(
+            check(&nan)[..],
+            [Violation::BadAmount {
+                side: Side::Bid,
+                index: 0,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn policies_fix_the_spread_or_skip() {
+        let counters = ConsistencyCounters::default();
+        let off = snap(
+            Some(0.0001),
+            vec![level(100.0, 1.0)],
+            vec![level(100.5, 1.0)],
+        );
+
+        let fixed = SummaryConsistency::FixSpread
+            .vet(off.clone(), &counters, || true)
+            .unwrap();
+        assert_eq!(fixed.spread, Some(0.5));
+        assert!(
+            SummaryConsistency::Skip
+                .vet(off, &counters, || true)
+                .is_none()
+        );
+        // Only the spread can be fixed up
+        let unsorted = snap(
+            Some(0.5),
+            vec![level(100.0, 1.0), level(100.2, 1.0)],
+            vec![level(100.5, 1.0)],
+        );
+        assert!(
+            SummaryConsistency::FixSpread
+                .vet(unsorted, &counters, || false)
+                .is_none()
+        );
+        let good = snap(Some(0.5), vec![level(100.0, 1.0)], vec![level(100.5, 1.0)]);
+        assert!(
+            SummaryConsistency::Skip
+                .vet(good, &counters, || true)
+                .is_some()
+        );
+
+        let counts = |c: &AtomicU64| c.load(Ordering::Relaxed);
+        assert_eq!(
+            (
+                counts(&counters.violations),
+                counts(&counters.fixed),
+                counts(&counters.skipped)
+            ),
+            (3, 1, 2)
+        );
+    }
+}
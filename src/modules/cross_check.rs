@@ -0,0 +1,206 @@
+use crate::modules::types::{OrderBook, OrderLevel, Side};
+
+/// How an independent full book is compared against the book we maintain from diffs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CrossCheckConfig {
+    /// Top levels compared on each side
+    pub levels: usize,
+    /// Largest relative amount difference that still matches, e.g. 0.0001 for 1bp
+    pub tolerance: f64,
+    /// Consecutive divergent checks before a resync
+    pub resync_after: usize,
+}
+
+impl Default for CrossCheckConfig {
+    fn default() -> Self {
+        Self {
+            levels: 10,
+            tolerance: 0.0001,
+            resync_after: 3,
+        }
+    }
+}
+
+/// First level where the two books disagree; `None` where a book has no level there
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    pub side: Side,
+    pub index: usize,
+    /// (price, amount) in the full book
+    pub reference: Option<(f64, f64)>,
+    /// (price, amount) in our book
+    pub ours: Option<(f64, f64)>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CrossCheckCounts {
+    pub checks: u64,
+    pub divergences: u64,
+    pub resyncs: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CrossCheck {
+    Match,
+    Diverged(Divergence),
+    /// Diverged `resync_after` times in a row
+    Resync(Divergence),
+}
+
+/// Compare the top `levels` of each side: prices must be equal and amounts within the
+/// relative tolerance
+pub fn compare(
+    reference: &OrderBook,
+    ours: &OrderBook,
+    config: &CrossCheckConfig,
+) -> Option<Divergence> {
+    let level = |levels: &[OrderLevel], index: usize| {
+        levels.get(index).map(|level| (level.price, level.amount))
+    };
+    for (side, reference, ours) in [
+        (Side::Bid, &reference.bids, &ours.bids),
+        (Side::Ask, &reference.asks, &ours.asks),
+    ] {
+        for index in 0..config.levels {
+            let (expected, actual) = (level(reference, index), level(ours, index));
+            let matches = match (expected, actual) {
+                (None, None) => break,
+                (Some((price, amount)), Some((our_price, our_amount))) => {
+                    price == our_price
+                        && (amount - our_amount).abs() <= config.tolerance * amount.max(our_amount)
+                }
+                _ => false,
+            };
+            if !matches {
+                return Some(Divergence {
+                    side,
+                    index,
+                    reference: expected,
+                    ours: actual,
+                });
+            }
+        }
+    }
+    None
+}
+
+/// Tracks consecutive divergences of one exchange
+#[derive(Debug)]
+pub struct CrossChecker {
+    config: CrossCheckConfig,
+    consecutive: usize,
+}
+
+impl CrossChecker {
+    pub fn new(config: CrossCheckConfig) -> Self {
+        Self {
+            config,
+            consecutive: 0,
+        }
+    }
+
+    pub fn config(&self) -> &CrossCheckConfig {
+        &self.config
+    }
+
+    pub fn check(&mut self, reference: &OrderBook, ours: &OrderBook) -> CrossCheck {
+        match compare(reference, ours, &self.config) {
+            None => {
+                self.consecutive = 0;
+                CrossCheck::Match
+            }
+            Some(divergence) => {
+                self.consecutive += 1;
+                if self.consecutive >= self.config.resync_after {
+                    self.consecutive = 0;
+                    CrossCheck::Resync(divergence)
+                } else {
+                    CrossCheck::Diverged(divergence)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::bitstamp::parse_bitstamp_full_book;
+    use crate::modules::types::{AggregatedOrderBook, Exchange};
+
+    const FULL_BOOK: &str = include_str!("../../tests/fixtures/bitstamp_order_book.json");
+
+    /// Our view of the fixture: a book built from the same levels, as if from diffs
+    fn maintained() -> (OrderBook, AggregatedOrderBook) {
+        let reference = parse_bitstamp_full_book(FULL_BOOK).unwrap().unwrap();
+        let mut agg = AggregatedOrderBook::new();
+        agg.merge_snapshots(vec![reference.clone()]);
+        (reference, agg)
+    }
+
+    #[test]
+    fn a_book_built_from_the_same_levels_matches() {
+        let (reference, agg) = maintained();
+        let ours = agg.exchange_book(Exchange::Bitstamp.as_str(), 10);
+        assert_eq!(ours.bids.len(), 10);
+        assert_eq!(
+            compare(&reference, &ours, &CrossCheckConfig::default()),
+            None
+        );
+    }
+
+    #[test]
+    fn amounts_within_the_tolerance_match_and_others_diverge() {
+        let (reference, agg) = maintained();
+        let config = CrossCheckConfig::default();
+        let mut ours = agg.exchange_book("bitstamp", 10);
+        ours.asks[2].amount *= 1.00005;
+        assert_eq!(compare(&reference, &ours, &config), None);
+
+        ours.asks[2].amount *= 1.01;
+        let divergence = compare(&reference, &ours, &config).unwrap();
+        assert_eq!((divergence.side, divergence.index), (Side::Ask, 2));
+        assert_eq!(divergence.reference, Some((reference.asks[2].price, 1.75)));
+
+        // A level we are missing, or one we still hold, diverges at its index
+        let mut ours = agg.exchange_book("bitstamp", 10);
+        ours.bids.remove(0);
+        let divergence = compare(&reference, &ours, &config).unwrap();
+        assert_eq!((divergence.side, divergence.index), (Side::Bid, 0));
+        let mut short = reference.clone();
+        short.bids.truncate(3);
+        let divergence = compare(&short, &agg.exchange_book("bitstamp", 10), &config).unwrap();
+        assert_eq!(divergence.index, 3);
+        assert_eq!(divergence.reference, None);
+    }
+
+    #[test]
+    fn only_a_persistent_divergence_resyncs() {
+        let (reference, agg) = maintained();
+        let good = agg.exchange_book("bitstamp", 10);
+        let mut bad = good.clone();
+        bad.bids[0].amount = 100.0;
+        let mut checker = CrossChecker::new(CrossCheckConfig {
+            resync_after: 2,
+            ..Default::default()
+        });
+
+        assert!(matches!(
+            checker.check(&reference, &bad),
+            CrossCheck::Diverged(_)
+        ));
+        assert_eq!(checker.check(&reference, &good), CrossCheck::Match);
+        assert!(matches!(
+            checker.check(&reference, &bad),
+            CrossCheck::Diverged(_)
+        ));
+        assert!(matches!(
+            checker.check(&reference, &bad),
+            CrossCheck::Resync(_)
+        ));
+        assert!(
+            matches!(checker.check(&reference, &bad), CrossCheck::Diverged(_)),
+            "the count restarts after a resync"
+        );
+    }
+}
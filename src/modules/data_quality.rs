@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+
+/// How much of the configured exchange set is behind a published book
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DataQuality {
+    /// Every configured exchange is contributing fresh data
+    #[default]
+    Full,
+    /// Some are stale or disconnected; the book is thinner than configured
+    Partial,
+    /// None is contributing fresh data
+    Stale,
+}
+
+/// Quality of a book aggregating `configured`, given which exchanges are fresh, and the
+/// configured exchanges that aren't, in configured order
+pub fn assess(configured: &[String], fresh: impl Fn(&str) -> bool) -> (DataQuality, Vec<String>) {
+    let lagging: Vec<String> = configured
+        .iter()
+        .filter(|exchange| !fresh(exchange))
+        .cloned()
+        .collect();
+    let quality = if lagging.is_empty() {
+        DataQuality::Full
+    } else if lagging.len() == configured.len() {
+        DataQuality::Stale
+    } else {
+        DataQuality::Partial
+    };
+    (quality, lagging)
+}
+
+/// The quality last published, to log when it changes
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct QualityTracker {
+    pub quality: DataQuality,
+    pub lagging: Vec<String>,
+}
+
+impl QualityTracker {
+    /// Record the quality being published; a description of the change if its level changed
+    pub fn observe(&mut self, quality: DataQuality, lagging: &[String]) -> Option<String> {
+        let previous = std::mem::replace(&mut self.quality, quality);
+        self.lagging = lagging.to_vec();
+        if previous == quality {
+            return None;
+        }
+        Some(match quality {
+            DataQuality::Full => format!(
+                "Data quality {:?} -> Full: every configured exchange is contributing",
+                previous
+            ),
+            _ => format!(
+                "Data quality {:?} -> {:?}: not contributing fresh data: {}",
+                previous,
+                quality,
+                lagging.join(", ")
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quality_follows_the_share_of_fresh_exchanges() {
+        let configured = vec!["bitstamp".to_string(), "binance".to_string()];
+        let assessed = |fresh: &[&str]| assess(&configured, |e| fresh.contains(&e));
+        assert_eq!(
+            assessed(&["binance", "bitstamp"]),
+            (DataQuality::Full, vec![])
+        );
+        assert_eq!(
+            assessed(&["binance"]),
+            (DataQuality::Partial, vec!["bitstamp".to_string()])
+        );
+        assert_eq!(assessed(&[]), (DataQuality::Stale, configured.clone()));
+        assert_eq!(assess(&[], |_| false), (DataQuality::Full, vec![]));
+    }
+
+    #[test]
+    fn only_changes_of_level_are_reported() {
+        let mut tracker = QualityTracker::default();
+        assert_eq!(tracker.observe(DataQuality::Full, &[]), None);
+        let bitstamp = vec!["bitstamp".to_string()];
+        assert_eq!(
+            tracker.observe(DataQuality::Partial, &bitstamp).as_deref(),
+            Some("Data quality Full -> Partial: not contributing fresh data: bitstamp")
+        );
+        // A different exchange lagging is the same level
+        let binance = vec!["binance".to_string()];
+        assert_eq!(tracker.observe(DataQuality::Partial, &binance), None);
+        assert_eq!(tracker.lagging, binance);
+        let both = vec!["bitstamp".to_string(), "binance".to_string()];
+        assert!(
+            tracker
+                .observe(DataQuality::Stale, &both)
+                .unwrap()
+                .starts_with("Data quality Partial -> Stale")
+        );
+        assert_eq!(
+            tracker.observe(DataQuality::Full, &[]).as_deref(),
+            Some("Data quality Stale -> Full: every configured exchange is contributing")
+        );
+    }
+}
@@ -0,0 +1,243 @@
+use crate::modules::aggregated_orderbook::Top10Snapshot;
+use crate::modules::clock::SharedClock;
+use crate::modules::stream_metrics::StreamState;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug)]
+pub struct DedupConfig {
+    /// Force a Summary after this long without one so clients can tell the stream is alive
+    pub heartbeat: Duration,
+    /// How often to re-check the book while nothing is being emitted
+    pub poll_interval: Duration,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Emission {
+    /// Content changed since the last emitted Summary
+    Changed,
+    /// Unchanged, but the quiet period is over
+    Heartbeat,
+    Skip,
+}
+
+/// Publisher counters, shared by every outbound stream
+#[derive(Debug, Default)]
+pub struct DedupCounters {
+    pub emitted: AtomicU64,
+    pub skipped: AtomicU64,
+    pub heartbeats: AtomicU64,
+}
+
+/// Hash of what a consumer sees in a Summary. `generated_at`, `version` and the raw
+/// update ids churn on every book change, including ones outside the published ladder,
+/// so they are left out.
+pub fn content_hash(snap: &Top10Snapshot) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    snap.symbol.hash(&mut hasher);
+    snap.spread.map(f64::to_bits).hash(&mut hasher);
+    for level in snap.bids.iter().chain(snap.asks.iter()) {
+        level.exchange.hash(&mut hasher);
+        level.price.to_bits().hash(&mut hasher);
+        level.amount.to_bits().hash(&mut hasher);
+    }
+    snap.bids.len().hash(&mut hasher);
+    (snap.state as u8).hash(&mut hasher);
+    snap.index_price.map(f64::to_bits).hash(&mut hasher);
+    snap.smart_best_bid.map(f64::to_bits).hash(&mut hasher);
+    snap.smart_best_ask.map(f64::to_bits).hash(&mut hasher);
+    snap.exchanges.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Per-stream dedup state: skips Summaries identical to the last emitted one
+#[derive(Debug)]
+pub struct SummaryDedup {
+    clock: SharedClock,
+    config: DedupConfig,
+    last_hash: Option<u64>,
+    last_emit_at: u64,
+}
+
+impl SummaryDedup {
+    /// What each deduplicating stream keeps for its client: the last hash, no levels
+    pub fn retained_state() -> StreamState {
+        StreamState {
+            levels: 0,
+            bytes: std::mem::size_of::<SummaryDedup>() as u64,
+        }
+    }
+
+    pub fn new(clock: SharedClock, config: DedupConfig) -> Self {
+        Self {
+            clock,
+            config,
+            last_hash: None,
+            last_emit_at: 0,
+        }
+    }
+
+    pub fn poll_interval(&self) -> Duration {
+        self.config.poll_interval
+    }
+
+    /// Decide whether `snap` should be emitted, recording it as emitted if so
+    pub fn check(&mut self, snap: &Top10Snapshot, counters: &DedupCounters) -> Emission {
+        let hash = content_hash(snap);
+        let now = self.clock.now_millis();
+        let emission = if self.last_hash != Some(hash) {
+            Emission::Changed
+        } else if now.saturating_sub(self.last_emit_at) >= self.config.heartbeat.as_millis() as u64
+        {
+            Emission::Heartbeat
+        } else {
+            Emission::Skip
+        };
+
+        match emission {
+            Emission::Skip => {
+                counters.skipped.fetch_add(1, Ordering::Relaxed);
+            }
+            Emission::Changed | Emission::Heartbeat => {
+                if emission == Emission::Heartbeat {
+                    counters.heartbeats.fetch_add(1, Ordering::Relaxed);
+                }
+                counters.emitted.fetch_add(1, Ordering::Relaxed);
+                self.last_hash = Some(hash);
+                self.last_emit_at = now;
+            }
+        }
+        emission
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::clock::MockClock;
+    use crate::modules::types::{
+        AggregatedOrderBook, Exchange, OrderBook, OrderBookUpdate, OrderLevel,
+    };
+    use std::sync::Arc;
+
+    fn level(price: f64, amount: f64) -> OrderLevel {
+        OrderLevel {
+            exchange: Exchange::Binance,
+            price,
+            amount,
+        }
+    }
+
+    fn setup() -> (Arc<MockClock>, AggregatedOrderBook, SummaryDedup) {
+        let clock = Arc::new(MockClock::new(1_000));
+        let mut book = AggregatedOrderBook::with_clock(clock.clone());
+        book.merge_snapshots(vec![OrderBook {
+            last_update_id: 1,
+            bids: vec![level(100.0, 1.0)],
+            asks: vec![level(101.0, 1.0)],
+        }]);
+        let dedup = SummaryDedup::new(
+            clock.clone(),
+            DedupConfig {
+                heartbeat: Duration::from_secs(5),
+                poll_interval: Duration::from_millis(50),
+            },
+        );
+        (clock, book, dedup)
+    }
+
+    #[test]
+    fn idle_book_only_emits_heartbeats() {
+        let (clock, book, mut dedup) = setup();
+        let counters = DedupCounters::default();
+        assert_eq!(
+            dedup.check(&book.get_top10_snapshot(), &counters),
+            Emission::Changed
+        );
+
+        let mut emissions = vec![];
+        // 12s of polling every 100ms
+        for _ in 0..120 {
+            clock.advance(Duration::from_millis(100));
+            let emission = dedup.check(&book.get_top10_snapshot(), &counters);
+            if emission != Emission::Skip {
+                emissions.push(emission);
+            }
+        }
+        assert_eq!(emissions, vec![Emission::Heartbeat, Emission::Heartbeat]);
+        assert_eq!(counters.heartbeats.load(Ordering::Relaxed), 2);
+        assert_eq!(counters.emitted.load(Ordering::Relaxed), 3);
+        assert_eq!(counters.skipped.load(Ordering::Relaxed), 118);
+    }
+
+    #[test]
+    fn a_single_level_change_is_emitted_exactly_once() {
+        let (clock, mut book, mut dedup) = setup();
+        let counters = DedupCounters::default();
+        dedup.check(&book.get_top10_snapshot(), &counters);
+
+        book.handle_update(OrderBookUpdate {
+            exchange: Exchange::Binance,
+            update_id: 2,
+            first_update_id: None,
+            received_at: None,
+            event_time: None,
+            bids: vec![level(100.0, 2.0)],
+            asks: vec![],
+        })
+        .unwrap();
+
+        let emissions: Vec<Emission> = (0..10)
+            .map(|_| {
+                clock.advance(Duration::from_millis(100));
+                dedup.check(&book.get_top10_snapshot(), &counters)
+            })
+            .filter(|e| *e != Emission::Skip)
+            .collect();
+        assert_eq!(emissions, vec![Emission::Changed]);
+    }
+
+    #[test]
+    fn moves_below_the_display_precision_are_skipped_once_rounded() {
+        let (clock, mut book, mut dedup) = setup();
+        let counters = DedupCounters::default();
+        let rounded =
+            |book: &AggregatedOrderBook| book.get_top10_snapshot().into_display_rounded(2);
+        dedup.check(&rounded(&book), &counters);
+
+        let mut move_bid = |update_id, from: f64, to: f64| {
+            book.handle_update(OrderBookUpdate {
+                exchange: Exchange::Binance,
+                update_id,
+                first_update_id: None,
+                received_at: None,
+                event_time: None,
+                bids: vec![level(from, 0.0), level(to, 1.0)],
+                asks: vec![],
+            })
+            .unwrap();
+            clock.advance(Duration::from_millis(100));
+            let unrounded = content_hash(&book.get_top10_snapshot());
+            (dedup.check(&rounded(&book), &counters), unrounded)
+        };
+        let (jitter, before) = move_bid(2, 100.0, 100.004);
+        assert_eq!(jitter, Emission::Skip, "100.004 still shows as 100.00");
+        let (jitter, after) = move_bid(3, 100.004, 100.009);
+        assert_eq!(jitter, Emission::Skip);
+        assert_ne!(before, after, "unrounded, both moves are changes");
+        assert_eq!(move_bid(4, 100.009, 100.011).0, Emission::Changed);
+    }
+
+    #[test]
+    fn timestamp_and_version_churn_is_not_a_change() {
+        let (clock, book, _) = setup();
+        let a = book.get_top10_snapshot();
+        clock.advance(Duration::from_secs(1));
+        let mut b = book.get_top10_snapshot();
+        b.version += 1;
+        assert_ne!(a.generated_at, b.generated_at);
+        assert_eq!(content_hash(&a), content_hash(&b));
+    }
+}
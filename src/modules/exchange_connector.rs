@@ -0,0 +1,790 @@
+use crate::modules::aggregated_orderbook::authoritative_depth;
+use crate::modules::binance::{BinanceEndpoint, parse_binance_snapshot};
+use crate::modules::bitfinex::{self, BitfinexChannels, BitfinexEndpoint, parse_bitfinex_snapshot};
+use crate::modules::bitstamp::{BitstampChannel, BitstampEndpoint, parse_bitstamp_snapshot};
+use crate::modules::bybit::{
+    self, BybitEndpoint, BybitSequence, SequenceCheck, parse_bybit_snapshot,
+};
+use crate::modules::coinbase::{self, CoinbaseEndpoint, parse_coinbase_snapshot};
+use crate::modules::connectors::{self, WsSink, WsStream};
+use crate::modules::gateio::{self, GateIoEndpoint, parse_gateio_snapshot};
+use crate::modules::handshake::Confirmation;
+use crate::modules::kraken::{self, KrakenEndpoint, parse_kraken_snapshot};
+use crate::modules::kucoin::{
+    self, DeltaCheck, KucoinEndpoint, KucoinSequence, parse_kucoin_snapshot,
+};
+use crate::modules::limits::PayloadLimits;
+use crate::modules::okx::{self, OkxBookMirror, OkxEndpoint, parse_okx_snapshot};
+use crate::modules::router::{RoutedMessage, route_message};
+use crate::modules::stuck_snapshot::{StuckSnapshotConfig, StuckSnapshotGuard};
+use crate::modules::types::{Exchange, OrderBook, OrderBookUpdate};
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::Message;
+
+pub type ConnectorFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, String>> + Send + 'a>>;
+
+/// A connected and subscribed websocket
+pub struct Connection {
+    pub sink: WsSink,
+    pub stream: WsStream,
+    /// How often the exchange must be pinged to keep the connection, if it must
+    pub ping_interval: Option<Duration>,
+}
+
+/// What to do with a text frame before it is routed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Screen {
+    Pass,
+    /// Route it, but the frame showed a problem worth a warning
+    Warn(String),
+    /// Not for the book followed on this connection
+    Drop,
+}
+
+/// What to do with an update, judged against the ones before it on the connection
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Apply,
+    /// Dropped without a word, e.g. already in the book
+    Skip,
+    /// Changes were missed: subscribe again on the same connection for a fresh snapshot
+    Resubscribe(String),
+    /// Changes were missed: only a new REST snapshot can recover them
+    Resync(String),
+}
+
+/// What the connector loop needs from an exchange: where its diff stream is, how to
+/// subscribe to it, how to read its REST snapshot and how to route its messages. Each
+/// implementation is held to the same contract by `tests/connector_conformance.rs`, with a
+/// fixture bundle of its own under `tests/fixtures/conformance`.
+///
+/// An exchange whose messages must be checked against each other keeps that state per
+/// connection: `start_session` resets it, then `screen`, `check_update` and
+/// `on_streamed_snapshot` see the connection's messages in order.
+pub trait ExchangeConnector: Send + Sync + Debug {
+    /// Exchange every level and update is attributed to
+    fn exchange(&self) -> Exchange;
+
+    /// Websocket URL of `symbol`'s diff stream
+    fn stream_url(&self, symbol: &str) -> String;
+
+    /// Text frames to send once connected, and again after every reconnect
+    fn subscribe_messages(&self, symbol: &str) -> Vec<String>;
+
+    fn parse_snapshot(&self, body: &str) -> Option<OrderBook>;
+
+    /// Levels per side its feed keeps current; the book drops deeper ones
+    fn authoritative_depth(&self) -> Option<usize> {
+        authoritative_depth(self.exchange())
+    }
+
+    fn route(&self, msg: Message) -> RoutedMessage {
+        route_message(self.exchange(), msg)
+    }
+
+    /// What confirms the subscription of `symbol` on a new connection
+    fn confirmation(&self, symbol: &str) -> Confirmation;
+
+    /// Connect to `symbol`'s diff stream and subscribe to it
+    fn connect<'a>(
+        &'a self,
+        symbol: &'a str,
+        limits: &'a PayloadLimits,
+    ) -> ConnectorFuture<'a, Connection>;
+
+    /// Fetch `symbol`'s REST snapshot
+    fn fetch_snapshot<'a>(
+        &'a mut self,
+        symbol: &'a str,
+        limits: &'a PayloadLimits,
+    ) -> ConnectorFuture<'a, OrderBook>;
+
+    /// Ping a connection that has a `ping_interval` until it fails
+    fn keep_alive(&self, _sink: WsSink, _interval: Duration) -> ConnectorFuture<'static, ()> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Subscribe to `symbol` again on a live connection
+    fn resubscribe<'a>(
+        &'a self,
+        _sink: &'a mut WsSink,
+        _symbol: &'a str,
+    ) -> ConnectorFuture<'a, ()> {
+        let exchange = self.exchange();
+        Box::pin(async move { Err(format!("{} can't resubscribe", exchange)) })
+    }
+
+    /// A new connection synced, with its book starting at `snapshot`
+    fn start_session(&mut self, _snapshot: &OrderBook) {}
+
+    fn screen(&mut self, _text: &str, _symbol: &str) -> Screen {
+        Screen::Pass
+    }
+
+    fn check_update(&mut self, _update: &OrderBookUpdate) -> Verdict {
+        Verdict::Apply
+    }
+
+    /// A snapshot sent on the connection, about to replace the exchange's levels. Returns
+    /// whether it restarts the sequence, so that it applies even if its id went backwards.
+    fn on_streamed_snapshot(&mut self, _book: &OrderBook) -> bool {
+        false
+    }
+}
+
+impl ExchangeConnector for BinanceEndpoint {
+    fn exchange(&self) -> Exchange {
+        self.variant.exchange()
+    }
+
+    fn stream_url(&self, symbol: &str) -> String {
+        BinanceEndpoint::stream_url(self, symbol)
+    }
+
+    /// The stream URL names the symbol, so there is nothing to send
+    fn subscribe_messages(&self, _symbol: &str) -> Vec<String> {
+        vec![]
+    }
+
+    fn parse_snapshot(&self, body: &str) -> Option<OrderBook> {
+        parse_binance_snapshot(body, self.variant.exchange())
+    }
+
+    fn confirmation(&self, _symbol: &str) -> Confirmation {
+        Confirmation::FirstFrame
+    }
+
+    fn connect<'a>(
+        &'a self,
+        symbol: &'a str,
+        limits: &'a PayloadLimits,
+    ) -> ConnectorFuture<'a, Connection> {
+        Box::pin(async move {
+            let (sink, stream) = connectors::get_binance_stream(symbol, self, limits).await?;
+            Ok(Connection {
+                sink,
+                stream,
+                ping_interval: None,
+            })
+        })
+    }
+
+    fn fetch_snapshot<'a>(
+        &'a mut self,
+        symbol: &'a str,
+        limits: &'a PayloadLimits,
+    ) -> ConnectorFuture<'a, OrderBook> {
+        Box::pin(connectors::get_binance_snapshot(symbol, self, limits))
+    }
+}
+
+/// Bitstamp's connector. Its REST cache has been seen serving one book for minutes, so the
+/// fetches remember the last snapshot they accepted and where the stream has got to.
+#[derive(Debug)]
+pub struct BitstampConnector {
+    pub endpoint: BitstampEndpoint,
+    /// Also subscribe to the full order_book channel, for cross-checks
+    pub full_book: bool,
+    /// Levels per side read from the REST book before the rest is skipped; `None` (the
+    /// default) reads them all
+    pub depth: Option<usize>,
+    pub stuck: StuckSnapshotGuard,
+    /// Shared by every snapshot fetch, so they reuse its connections
+    client: reqwest::Client,
+}
+
+impl BitstampConnector {
+    pub fn new(endpoint: BitstampEndpoint, stuck: StuckSnapshotConfig) -> Self {
+        Self {
+            endpoint,
+            full_book: false,
+            depth: None,
+            stuck: StuckSnapshotGuard::new(stuck),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn with_full_book(mut self) -> Self {
+        self.full_book = true;
+        self
+    }
+
+    pub fn with_depth(mut self, depth: usize) -> Self {
+        self.depth = Some(depth);
+        self
+    }
+}
+
+impl ExchangeConnector for BitstampConnector {
+    fn exchange(&self) -> Exchange {
+        Exchange::Bitstamp
+    }
+
+    fn stream_url(&self, _symbol: &str) -> String {
+        self.endpoint.ws.clone()
+    }
+
+    /// Only the diff channel; the full-book cross-check subscribes on top of it
+    fn subscribe_messages(&self, symbol: &str) -> Vec<String> {
+        vec![BitstampChannel::Diff.subscribe_message(symbol)]
+    }
+
+    fn parse_snapshot(&self, body: &str) -> Option<OrderBook> {
+        parse_bitstamp_snapshot(body)
+    }
+
+    fn confirmation(&self, symbol: &str) -> Confirmation {
+        Confirmation::for_exchange(Exchange::Bitstamp, BitstampChannel::Diff.name(symbol))
+    }
+
+    fn connect<'a>(
+        &'a self,
+        symbol: &'a str,
+        limits: &'a PayloadLimits,
+    ) -> ConnectorFuture<'a, Connection> {
+        Box::pin(async move {
+            let (sink, stream) =
+                connectors::get_bitstamp_stream(symbol, &self.endpoint, limits, self.full_book)
+                    .await?;
+            Ok(Connection {
+                sink,
+                stream,
+                ping_interval: None,
+            })
+        })
+    }
+
+    fn fetch_snapshot<'a>(
+        &'a mut self,
+        symbol: &'a str,
+        limits: &'a PayloadLimits,
+    ) -> ConnectorFuture<'a, OrderBook> {
+        Box::pin(connectors::get_bitstamp_snapshot(
+            symbol,
+            &self.endpoint,
+            limits,
+            self.depth,
+            &self.client,
+            &mut self.stuck,
+        ))
+    }
+
+    fn check_update(&mut self, update: &OrderBookUpdate) -> Verdict {
+        self.stuck.observe_stream(update.update_id);
+        Verdict::Apply
+    }
+}
+
+impl ExchangeConnector for KrakenEndpoint {
+    fn exchange(&self) -> Exchange {
+        Exchange::Kraken
+    }
+
+    fn stream_url(&self, _symbol: &str) -> String {
+        self.ws.clone()
+    }
+
+    fn subscribe_messages(&self, symbol: &str) -> Vec<String> {
+        vec![kraken::subscribe_message(symbol)]
+    }
+
+    fn parse_snapshot(&self, body: &str) -> Option<OrderBook> {
+        parse_kraken_snapshot(body)
+    }
+
+    fn confirmation(&self, symbol: &str) -> Confirmation {
+        Confirmation::for_exchange(Exchange::Kraken, kraken::kraken_pair(symbol))
+    }
+
+    fn connect<'a>(
+        &'a self,
+        symbol: &'a str,
+        limits: &'a PayloadLimits,
+    ) -> ConnectorFuture<'a, Connection> {
+        Box::pin(async move {
+            let (sink, stream) = connectors::get_kraken_stream(symbol, self, limits).await?;
+            Ok(Connection {
+                sink,
+                stream,
+                ping_interval: None,
+            })
+        })
+    }
+
+    fn fetch_snapshot<'a>(
+        &'a mut self,
+        symbol: &'a str,
+        limits: &'a PayloadLimits,
+    ) -> ConnectorFuture<'a, OrderBook> {
+        Box::pin(connectors::get_kraken_snapshot(symbol, self, limits))
+    }
+}
+
+impl ExchangeConnector for CoinbaseEndpoint {
+    fn exchange(&self) -> Exchange {
+        Exchange::Coinbase
+    }
+
+    fn stream_url(&self, _symbol: &str) -> String {
+        self.ws.clone()
+    }
+
+    fn subscribe_messages(&self, symbol: &str) -> Vec<String> {
+        vec![coinbase::subscribe_message(symbol)]
+    }
+
+    fn parse_snapshot(&self, body: &str) -> Option<OrderBook> {
+        parse_coinbase_snapshot(body)
+    }
+
+    fn confirmation(&self, symbol: &str) -> Confirmation {
+        Confirmation::for_exchange(Exchange::Coinbase, coinbase::coinbase_product(symbol))
+    }
+
+    fn connect<'a>(
+        &'a self,
+        symbol: &'a str,
+        limits: &'a PayloadLimits,
+    ) -> ConnectorFuture<'a, Connection> {
+        Box::pin(async move {
+            let (sink, stream) = connectors::get_coinbase_stream(symbol, self, limits).await?;
+            Ok(Connection {
+                sink,
+                stream,
+                ping_interval: None,
+            })
+        })
+    }
+
+    fn fetch_snapshot<'a>(
+        &'a mut self,
+        symbol: &'a str,
+        limits: &'a PayloadLimits,
+    ) -> ConnectorFuture<'a, OrderBook> {
+        Box::pin(connectors::get_coinbase_snapshot(symbol, self, limits))
+    }
+}
+
+/// OKX's connector. Its checksums cover prices and sizes as sent, so they are checked
+/// against a copy of its book kept for the connection.
+#[derive(Debug, Default)]
+pub struct OkxConnector {
+    pub endpoint: OkxEndpoint,
+    mirror: OkxBookMirror,
+}
+
+impl OkxConnector {
+    pub fn new(endpoint: OkxEndpoint) -> Self {
+        Self {
+            endpoint,
+            mirror: OkxBookMirror::default(),
+        }
+    }
+}
+
+impl ExchangeConnector for OkxConnector {
+    fn exchange(&self) -> Exchange {
+        Exchange::Okx
+    }
+
+    fn stream_url(&self, _symbol: &str) -> String {
+        self.endpoint.ws.clone()
+    }
+
+    fn subscribe_messages(&self, symbol: &str) -> Vec<String> {
+        vec![okx::subscribe_message(symbol)]
+    }
+
+    fn parse_snapshot(&self, body: &str) -> Option<OrderBook> {
+        parse_okx_snapshot(body)
+    }
+
+    fn confirmation(&self, symbol: &str) -> Confirmation {
+        Confirmation::for_exchange(Exchange::Okx, okx::okx_inst_id(symbol))
+    }
+
+    fn connect<'a>(
+        &'a self,
+        symbol: &'a str,
+        limits: &'a PayloadLimits,
+    ) -> ConnectorFuture<'a, Connection> {
+        Box::pin(async move {
+            let (sink, stream) = connectors::get_okx_stream(symbol, &self.endpoint, limits).await?;
+            Ok(Connection {
+                sink,
+                stream,
+                ping_interval: None,
+            })
+        })
+    }
+
+    fn fetch_snapshot<'a>(
+        &'a mut self,
+        symbol: &'a str,
+        limits: &'a PayloadLimits,
+    ) -> ConnectorFuture<'a, OrderBook> {
+        Box::pin(connectors::get_okx_snapshot(symbol, &self.endpoint, limits))
+    }
+
+    fn start_session(&mut self, _snapshot: &OrderBook) {
+        self.mirror = OkxBookMirror::default();
+    }
+
+    fn screen(&mut self, text: &str, _symbol: &str) -> Screen {
+        match self.mirror.observe(text) {
+            Ok(()) => Screen::Pass,
+            Err(e) => Screen::Warn(format!("OKX book {}", e)),
+        }
+    }
+}
+
+/// Bybit's connector. Deltas must follow on from one another; a gap means resubscribing.
+#[derive(Debug, Default)]
+pub struct BybitConnector {
+    pub endpoint: BybitEndpoint,
+    sequence: BybitSequence,
+}
+
+impl BybitConnector {
+    pub fn new(endpoint: BybitEndpoint) -> Self {
+        Self {
+            endpoint,
+            sequence: BybitSequence::default(),
+        }
+    }
+}
+
+impl ExchangeConnector for BybitConnector {
+    fn exchange(&self) -> Exchange {
+        Exchange::Bybit
+    }
+
+    fn stream_url(&self, _symbol: &str) -> String {
+        self.endpoint.ws.clone()
+    }
+
+    fn subscribe_messages(&self, symbol: &str) -> Vec<String> {
+        vec![bybit::subscribe_message(symbol)]
+    }
+
+    fn parse_snapshot(&self, body: &str) -> Option<OrderBook> {
+        parse_bybit_snapshot(body)
+    }
+
+    fn confirmation(&self, symbol: &str) -> Confirmation {
+        Confirmation::for_exchange(Exchange::Bybit, bybit::topic(symbol))
+    }
+
+    fn connect<'a>(
+        &'a self,
+        symbol: &'a str,
+        limits: &'a PayloadLimits,
+    ) -> ConnectorFuture<'a, Connection> {
+        Box::pin(async move {
+            let (sink, stream) =
+                connectors::get_bybit_stream(symbol, &self.endpoint, limits).await?;
+            Ok(Connection {
+                sink,
+                stream,
+                ping_interval: None,
+            })
+        })
+    }
+
+    fn fetch_snapshot<'a>(
+        &'a mut self,
+        symbol: &'a str,
+        limits: &'a PayloadLimits,
+    ) -> ConnectorFuture<'a, OrderBook> {
+        Box::pin(connectors::get_bybit_snapshot(
+            symbol,
+            &self.endpoint,
+            limits,
+        ))
+    }
+
+    fn resubscribe<'a>(&'a self, sink: &'a mut WsSink, symbol: &'a str) -> ConnectorFuture<'a, ()> {
+        Box::pin(connectors::resubscribe_bybit(sink, symbol))
+    }
+
+    /// The sequence starts at the snapshot sent after subscribing, which also covers a `u`
+    /// that went backwards since the REST book
+    fn start_session(&mut self, _snapshot: &OrderBook) {
+        self.sequence = BybitSequence::default();
+    }
+
+    fn check_update(&mut self, update: &OrderBookUpdate) -> Verdict {
+        match self.sequence.check(update.update_id) {
+            SequenceCheck::InSequence => Verdict::Apply,
+            SequenceCheck::AwaitingSnapshot => Verdict::Skip,
+            SequenceCheck::Gap { expected, got } => Verdict::Resubscribe(format!(
+                "Sequence gap (expected u {}, got {})",
+                expected, got
+            )),
+        }
+    }
+
+    /// A subscription's snapshot is the book even when its `u` went backwards, as after a
+    /// restart of Bybit's service
+    fn on_streamed_snapshot(&mut self, book: &OrderBook) -> bool {
+        self.sequence.on_snapshot(book.last_update_id);
+        true
+    }
+}
+
+/// KuCoin's connector. Deltas are checked against the snapshot's sequence, then each
+/// other's.
+#[derive(Debug, Default)]
+pub struct KucoinConnector {
+    pub endpoint: KucoinEndpoint,
+    sequence: Option<KucoinSequence>,
+}
+
+impl KucoinConnector {
+    pub fn new(endpoint: KucoinEndpoint) -> Self {
+        Self {
+            endpoint,
+            sequence: None,
+        }
+    }
+}
+
+impl ExchangeConnector for KucoinConnector {
+    fn exchange(&self) -> Exchange {
+        Exchange::Kucoin
+    }
+
+    /// The websocket the endpoint names. Without one, the URL comes from the token request
+    /// made when connecting, and this is empty.
+    fn stream_url(&self, _symbol: &str) -> String {
+        self.endpoint.ws.clone().unwrap_or_default()
+    }
+
+    fn subscribe_messages(&self, symbol: &str) -> Vec<String> {
+        vec![kucoin::subscribe_message(symbol)]
+    }
+
+    fn parse_snapshot(&self, body: &str) -> Option<OrderBook> {
+        parse_kucoin_snapshot(body)
+    }
+
+    fn confirmation(&self, symbol: &str) -> Confirmation {
+        Confirmation::for_exchange(Exchange::Kucoin, kucoin::topic(symbol))
+    }
+
+    /// The token request says how often to ping
+    fn connect<'a>(
+        &'a self,
+        symbol: &'a str,
+        limits: &'a PayloadLimits,
+    ) -> ConnectorFuture<'a, Connection> {
+        Box::pin(async move {
+            let (sink, stream, interval) =
+                connectors::get_kucoin_stream(symbol, &self.endpoint, limits).await?;
+            Ok(Connection {
+                sink,
+                stream,
+                ping_interval: Some(interval),
+            })
+        })
+    }
+
+    fn fetch_snapshot<'a>(
+        &'a mut self,
+        symbol: &'a str,
+        limits: &'a PayloadLimits,
+    ) -> ConnectorFuture<'a, OrderBook> {
+        Box::pin(connectors::get_kucoin_snapshot(
+            symbol,
+            &self.endpoint,
+            limits,
+        ))
+    }
+
+    fn keep_alive(&self, sink: WsSink, interval: Duration) -> ConnectorFuture<'static, ()> {
+        Box::pin(async move {
+            connectors::keep_kucoin_alive(sink, interval).await;
+            Ok(())
+        })
+    }
+
+    fn start_session(&mut self, snapshot: &OrderBook) {
+        self.sequence = Some(KucoinSequence::starting_at(snapshot.last_update_id));
+    }
+
+    fn check_update(&mut self, update: &OrderBookUpdate) -> Verdict {
+        let Some(sequence) = self.sequence.as_mut() else {
+            return Verdict::Apply;
+        };
+        let start = update.first_update_id.unwrap_or(update.update_id);
+        match sequence.check(start, update.update_id) {
+            DeltaCheck::Apply => Verdict::Apply,
+            DeltaCheck::Stale => Verdict::Skip,
+            DeltaCheck::Gap { expected, got } => {
+                Verdict::Resync(format!("Sequence gap (expected {}, got {})", expected, got))
+            }
+        }
+    }
+}
+
+impl ExchangeConnector for GateIoEndpoint {
+    fn exchange(&self) -> Exchange {
+        Exchange::GateIo
+    }
+
+    fn stream_url(&self, _symbol: &str) -> String {
+        self.ws.clone()
+    }
+
+    fn subscribe_messages(&self, symbol: &str) -> Vec<String> {
+        vec![gateio::subscribe_message(symbol)]
+    }
+
+    fn parse_snapshot(&self, body: &str) -> Option<OrderBook> {
+        parse_gateio_snapshot(body)
+    }
+
+    fn confirmation(&self, symbol: &str) -> Confirmation {
+        Confirmation::for_exchange(Exchange::GateIo, gateio::gateio_pair(symbol))
+    }
+
+    fn connect<'a>(
+        &'a self,
+        symbol: &'a str,
+        limits: &'a PayloadLimits,
+    ) -> ConnectorFuture<'a, Connection> {
+        Box::pin(async move {
+            let (sink, stream) = connectors::get_gateio_stream(symbol, self, limits).await?;
+            Ok(Connection {
+                sink,
+                stream,
+                ping_interval: None,
+            })
+        })
+    }
+
+    fn fetch_snapshot<'a>(
+        &'a mut self,
+        symbol: &'a str,
+        limits: &'a PayloadLimits,
+    ) -> ConnectorFuture<'a, OrderBook> {
+        Box::pin(connectors::get_gateio_snapshot(symbol, self, limits))
+    }
+}
+
+/// Bitfinex's connector. Frames only carry the channel id the ack on their connection
+/// assigned, so the acks are followed per connection.
+#[derive(Debug, Default)]
+pub struct BitfinexConnector {
+    pub endpoint: BitfinexEndpoint,
+    channels: BitfinexChannels,
+}
+
+impl BitfinexConnector {
+    pub fn new(endpoint: BitfinexEndpoint) -> Self {
+        Self {
+            endpoint,
+            channels: BitfinexChannels::default(),
+        }
+    }
+}
+
+impl ExchangeConnector for BitfinexConnector {
+    fn exchange(&self) -> Exchange {
+        Exchange::Bitfinex
+    }
+
+    fn stream_url(&self, _symbol: &str) -> String {
+        self.endpoint.ws.clone()
+    }
+
+    /// Timestamps are turned on before subscribing: they are the frames' only ids
+    fn subscribe_messages(&self, symbol: &str) -> Vec<String> {
+        vec![
+            bitfinex::conf_message(),
+            bitfinex::subscribe_message(symbol),
+        ]
+    }
+
+    fn parse_snapshot(&self, body: &str) -> Option<OrderBook> {
+        parse_bitfinex_snapshot(body)
+    }
+
+    fn confirmation(&self, symbol: &str) -> Confirmation {
+        Confirmation::for_exchange(Exchange::Bitfinex, bitfinex::bitfinex_symbol(symbol))
+    }
+
+    fn connect<'a>(
+        &'a self,
+        symbol: &'a str,
+        limits: &'a PayloadLimits,
+    ) -> ConnectorFuture<'a, Connection> {
+        Box::pin(async move {
+            let (sink, stream) =
+                connectors::get_bitfinex_stream(symbol, &self.endpoint, limits).await?;
+            Ok(Connection {
+                sink,
+                stream,
+                ping_interval: None,
+            })
+        })
+    }
+
+    fn fetch_snapshot<'a>(
+        &'a mut self,
+        symbol: &'a str,
+        limits: &'a PayloadLimits,
+    ) -> ConnectorFuture<'a, OrderBook> {
+        Box::pin(connectors::get_bitfinex_snapshot(
+            symbol,
+            &self.endpoint,
+            limits,
+        ))
+    }
+
+    fn start_session(&mut self, _snapshot: &OrderBook) {
+        self.channels = BitfinexChannels::default();
+    }
+
+    fn screen(&mut self, text: &str, symbol: &str) -> Screen {
+        if self.channels.admit(text, symbol) {
+            Screen::Pass
+        } else {
+            Screen::Drop
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::binance::BinanceVariant;
+
+    #[test]
+    fn connectors_subscribe_by_url_or_by_message() {
+        let us = BinanceVariant::Us.endpoint();
+        assert_eq!(us.exchange(), Exchange::BinanceUs);
+        assert_eq!(
+            ExchangeConnector::stream_url(&us, "BTCUSD"),
+            "wss://stream.binance.us:9443/ws/btcusd@depth@100ms"
+        );
+        assert!(us.subscribe_messages("btcusd").is_empty());
+        assert_eq!(us.authoritative_depth(), Some(1000));
+
+        let bitstamp =
+            BitstampConnector::new(BitstampEndpoint::default(), StuckSnapshotConfig::default());
+        assert_eq!(bitstamp.stream_url("ethbtc"), "wss://ws.bitstamp.net");
+        assert_eq!(
+            bitstamp.subscribe_messages("ETHBTC"),
+            [r#"{"data":{"channel":"diff_order_book_ethbtc"},"event":"bts:subscribe"}"#]
+        );
+        assert_eq!(bitstamp.authoritative_depth(), Some(100));
+        // The REST book is read whole unless a depth is set
+        assert_eq!(bitstamp.depth, None);
+        assert_eq!(bitstamp.with_depth(100).depth, Some(100));
+        assert_eq!(CoinbaseEndpoint::default().authoritative_depth(), None);
+    }
+}
@@ -0,0 +1,203 @@
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// How much of the end of a JSONL file is read to find its last record
+pub const TAIL_BYTES: u64 = 64 * 1024;
+
+/// Where an earlier session's JSONL output stops: its last complete snapshot record
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionEnd {
+    pub epoch: u64,
+    pub version: u64,
+    /// Unix millis
+    pub generated_at: u64,
+}
+
+/// First record of a session's JSONL output when an earlier session's output exists, so
+/// offline consumers account for the gap instead of joining the two series
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "record", rename = "gap_marker", rename_all = "camelCase")]
+pub struct GapMarker {
+    pub old_epoch: u64,
+    pub old_version: u64,
+    pub new_epoch: u64,
+    /// From the last persisted snapshot to the start of the new epoch
+    pub gap_ms: u64,
+}
+
+impl GapMarker {
+    pub fn after(previous: SessionEnd, new_epoch: u64) -> Self {
+        Self {
+            old_epoch: previous.epoch,
+            old_version: previous.version,
+            new_epoch,
+            gap_ms: new_epoch.saturating_sub(previous.generated_at),
+        }
+    }
+
+    /// The marker as one JSONL line, newline included
+    pub fn to_jsonl(&self) -> String {
+        // A struct of integers always serializes
+        serde_json::to_string(self).unwrap() + "\n"
+    }
+}
+
+/// The most recently modified `.jsonl` file in `dir`, if any
+pub fn most_recent_jsonl(dir: &Path) -> Result<Option<PathBuf>, String> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| format!("failed to list {}: {}", dir.display(), e))?;
+    let mut newest = None;
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("failed to list {}: {}", dir.display(), e))?;
+        let path = entry.path();
+        if path.extension().is_none_or(|ext| ext != "jsonl") {
+            continue;
+        }
+        let modified = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .map_err(|e| format!("failed to stat {}: {}", path.display(), e))?;
+        // Names break ties between files written within the clock's resolution
+        if newest
+            .as_ref()
+            .is_none_or(|(at, newest_path)| (modified, &path) > (*at, newest_path))
+        {
+            newest = Some((modified, path));
+        }
+    }
+    Ok(newest.map(|(_, path)| path))
+}
+
+/// The last snapshot record in the tail of a JSONL file. Lines that don't parse as one,
+/// e.g. a record cut short by a crash or an earlier gap marker, are skipped.
+pub fn last_session_end(path: &Path) -> Result<Option<SessionEnd>, String> {
+    let read_error = |e: std::io::Error| format!("failed to read {}: {}", path.display(), e);
+    let mut file = std::fs::File::open(path).map_err(read_error)?;
+    let len = file.metadata().map_err(read_error)?.len();
+    let start = len.saturating_sub(TAIL_BYTES);
+    file.seek(SeekFrom::Start(start)).map_err(read_error)?;
+    let mut tail = vec![];
+    file.read_to_end(&mut tail).map_err(read_error)?;
+    let tail = String::from_utf8_lossy(&tail);
+
+    let mut lines = tail.lines();
+    if start > 0 {
+        // Most likely the end of a line that started before the tail
+        lines.next();
+    }
+    Ok(lines
+        .rev()
+        .filter(|line| !line.trim().is_empty())
+        .find_map(|line| serde_json::from_str(line).ok()))
+}
+
+/// The marker to write first in a session starting `new_epoch`, from the newest JSONL
+/// file in `dir`, or `None` if there is no earlier output to follow on from
+pub fn startup_gap_marker(dir: &Path, new_epoch: u64) -> Result<Option<GapMarker>, String> {
+    let Some(path) = most_recent_jsonl(dir)? else {
+        return Ok(None);
+    };
+    Ok(last_session_end(&path)?.map(|end| GapMarker::after(end, new_epoch)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::aggregated_orderbook::Top10Snapshot;
+    use std::io::Write;
+
+    fn dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gap-marker-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn record(epoch: u64, version: u64, generated_at: u64) -> String {
+        let snap = Top10Snapshot {
+            epoch,
+            version,
+            generated_at,
+            symbol: "ethbtc".to_string(),
+            ..Default::default()
+        };
+        serde_json::to_string(&snap).unwrap() + "\n"
+    }
+
+    #[test]
+    fn the_marker_follows_on_from_the_last_complete_record() {
+        let dir = dir("corrupt");
+        let path = dir.join("session.jsonl");
+        let mut file = std::fs::File::create(&path).unwrap();
+        for version in 1..=3 {
+            file.write_all(record(1_000, version, 5_000 + version).as_bytes())
+                .unwrap();
+        }
+        // A crash mid-write leaves half a record behind
+        let cut = record(1_000, 4, 5_004);
+        file.write_all(&cut.as_bytes()[..cut.len() / 2]).unwrap();
+
+        let marker = startup_gap_marker(&dir, 65_003).unwrap().unwrap();
+        assert_eq!(
+            marker,
+            GapMarker {
+                old_epoch: 1_000,
+                old_version: 3,
+                new_epoch: 65_003,
+                gap_ms: 60_000,
+            }
+        );
+        assert_eq!(
+            marker.to_jsonl(),
+            "{\"record\":\"gap_marker\",\"oldEpoch\":1000,\"oldVersion\":3,\"newEpoch\":65003,\"gapMs\":60000}\n"
+        );
+
+        // The marker written by that session is skipped on the next startup
+        file.write_all(b"\n").unwrap();
+        file.write_all(marker.to_jsonl().as_bytes()).unwrap();
+        assert_eq!(last_session_end(&path).unwrap().unwrap().version, 3);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn only_the_tail_of_the_newest_file_is_read() {
+        let dir = dir("tail");
+        std::fs::write(dir.join("a.jsonl"), record(1, 1, 10)).unwrap();
+        std::fs::write(dir.join("notes.txt"), record(9, 9, 90)).unwrap();
+        let newest = dir.join("b.jsonl");
+        let mut long = String::new();
+        while (long.len() as u64) < TAIL_BYTES * 2 {
+            long += &record(2, long.len() as u64, 20);
+        }
+        long += &record(2, 999_999, 30);
+        std::fs::write(&newest, &long).unwrap();
+
+        assert_eq!(most_recent_jsonl(&dir).unwrap(), Some(newest.clone()));
+        assert_eq!(
+            last_session_end(&newest).unwrap(),
+            Some(SessionEnd {
+                epoch: 2,
+                version: 999_999,
+                generated_at: 30,
+            })
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn no_marker_without_earlier_records() {
+        let dir = dir("empty");
+        assert_eq!(startup_gap_marker(&dir, 1_000).unwrap(), None);
+        std::fs::write(dir.join("session.jsonl"), "\n{\"trunc").unwrap();
+        assert_eq!(startup_gap_marker(&dir, 1_000).unwrap(), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(
+            startup_gap_marker(&dir, 1_000)
+                .unwrap_err()
+                .contains("failed to list")
+        );
+    }
+}
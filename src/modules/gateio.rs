@@ -0,0 +1,123 @@
+use crate::config::dashed_instrument;
+use crate::modules::types::{Exchange, OrderBook, OrderLevel};
+use serde_json::Value;
+
+/// The incremental book channel: changed levels every `GATEIO_INTERVAL`, each message
+/// carrying the `U..u` range of ids it covers
+pub const GATEIO_CHANNEL: &str = "spot.order_book_update";
+
+pub const GATEIO_INTERVAL: &str = "100ms";
+
+/// Levels per side of the REST book asked for. The channel covers every depth, but only the
+/// snapshot's levels are seeded, so the book is only known this deep.
+pub const GATEIO_SNAPSHOT_DEPTH: usize = 100;
+
+/// REST and websocket base URLs for Gate.io spot; overridable for tests and proxies
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GateIoEndpoint {
+    pub rest: String,
+    pub ws: String,
+}
+
+impl Default for GateIoEndpoint {
+    fn default() -> Self {
+        Self {
+            rest: "https://api.gateio.ws".to_string(),
+            ws: "wss://api.gateio.ws/ws/v4/".to_string(),
+        }
+    }
+}
+
+impl GateIoEndpoint {
+    /// The REST book with its `id`, which the channel's ids continue
+    pub fn order_book_url(&self, symbol: &str) -> String {
+        format!(
+            "{}/api/v4/spot/order_book?currency_pair={}&limit={}&with_id=true",
+            self.rest,
+            gateio_pair(symbol),
+            GATEIO_SNAPSHOT_DEPTH
+        )
+    }
+}
+
+/// Gate.io's currency pair for `symbol`: upper-cased with base and quote split by `_`, e.g.
+/// `ETH_BTC` for ethbtc. A pair already split is only upper-cased.
+pub fn gateio_pair(symbol: &str) -> String {
+    let symbol = symbol.to_uppercase();
+    if symbol.contains('_') {
+        return symbol;
+    }
+    dashed_instrument(&symbol).replace('-', "_")
+}
+
+/// The `subscribe` message for the book channel of `symbol`
+pub fn subscribe_message(symbol: &str) -> String {
+    let time = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    serde_json::json!({
+        "time": time,
+        "channel": GATEIO_CHANNEL,
+        "event": "subscribe",
+        "payload": [gateio_pair(symbol), GATEIO_INTERVAL]
+    })
+    .to_string()
+}
+
+/// Levels of one side, `[price, amount]` each
+pub fn parse_levels(side: &Value) -> Option<Vec<OrderLevel>> {
+    side.as_array()?
+        .iter()
+        .map(|level| {
+            Some(OrderLevel {
+                exchange: Exchange::GateIo,
+                price: level[0].as_str()?.parse::<f64>().ok()?,
+                amount: level[1].as_str()?.parse::<f64>().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Parse the REST order book body, with its `id` as the id
+pub fn parse_gateio_snapshot(body: &str) -> Option<OrderBook> {
+    let v: Value = serde_json::from_str(body).ok()?;
+    Some(OrderBook {
+        last_update_id: v["id"].as_u64()?,
+        bids: parse_levels(&v["bids"])?,
+        asks: parse_levels(&v["asks"])?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symbols_map_to_gateio_pairs() {
+        assert_eq!(gateio_pair("ethbtc"), "ETH_BTC");
+        assert_eq!(gateio_pair("BTCUSDT"), "BTC_USDT");
+        assert_eq!(gateio_pair("sol_usdc"), "SOL_USDC");
+        assert_eq!(
+            GateIoEndpoint::default().order_book_url("ethbtc"),
+            "https://api.gateio.ws/api/v4/spot/order_book?currency_pair=ETH_BTC&limit=100&with_id=true"
+        );
+        let subscribe: Value = serde_json::from_str(&subscribe_message("ethbtc")).unwrap();
+        assert_eq!(subscribe["channel"], GATEIO_CHANNEL);
+        assert_eq!(subscribe["payload"][0], "ETH_BTC");
+        assert!(subscribe["time"].is_u64());
+
+        let body = r#"{"id":8613227385,"current":1700000000123,"update":1700000000120,
+            "asks":[["0.05125","1.5"]],"bids":[["0.05120","2.0"],["0.05119","0.25"]]}"#;
+        let book = parse_gateio_snapshot(body).unwrap();
+        assert_eq!(book.last_update_id, 8_613_227_385);
+        assert_eq!((book.bids[1].price, book.asks[0].amount), (0.05119, 1.5));
+        assert!(book.bids.iter().all(|l| l.exchange == Exchange::GateIo));
+        // Without `with_id` there is nothing for the channel's ids to continue
+        assert!(parse_gateio_snapshot(r#"{"current":1,"update":1,"asks":[],"bids":[]}"#).is_none());
+        assert!(
+            parse_gateio_snapshot(r#"{"label":"INVALID_CURRENCY","message":"Invalid currency"}"#)
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,716 @@
+use crate::modules::gateio;
+use crate::modules::types::Exchange;
+use futures_util::StreamExt;
+use futures_util::stream::{self, Chain, Iter, Stream};
+use serde_json::Value;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::{Error, Message};
+
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A websocket stream whose subscription was confirmed, with the messages read while
+/// waiting for it put back in front
+pub type Confirmed<S> = Chain<Iter<std::vec::IntoIter<Result<Message, Error>>>, S>;
+
+/// What confirms a connector's subscription
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Confirmation {
+    /// Bitstamp's `bts:subscription_succeeded` on the channel; a `bts:error` refuses it
+    BitstampAck { channel: String },
+    /// Kraken's `subscriptionStatus` for the pair: `subscribed`, or `error` to refuse it
+    KrakenAck { pair: String },
+    /// Coinbase's `subscriptions` listing the product on a channel; an `error` refuses it
+    CoinbaseAck { product_id: String },
+    /// OKX's `subscribe` event for the instrument's channel; an `error` event refuses it
+    OkxAck { inst_id: String },
+    /// Bybit's successful reply to the `subscribe` request; a failed one refuses it
+    BybitAck { topic: String },
+    /// KuCoin's `ack` of the request for the topic; an `error` for it refuses it
+    KucoinAck { topic: String },
+    /// Gate.io's successful `subscribe` reply on the book channel; one with an `error`
+    /// refuses it
+    GateIoAck { pair: String },
+    /// Bitfinex's `subscribed` event for the symbol's book channel; an `error` event refuses
+    /// it. The ack is kept in the stream: it maps the channel id the book's frames carry.
+    BitfinexAck { symbol: String },
+    /// Binance streams are subscribed by URL and never ack: the first data frame confirms
+    FirstFrame,
+}
+
+impl Confirmation {
+    /// `channel` is the Bitstamp channel, Kraken pair, Coinbase product, OKX instrument,
+    /// Bybit or KuCoin topic, Gate.io pair or Bitfinex symbol subscribed to
+    pub fn for_exchange(exchange: Exchange, channel: String) -> Self {
+        match exchange {
+            Exchange::Bitstamp => Confirmation::BitstampAck { channel },
+            Exchange::Kraken => Confirmation::KrakenAck { pair: channel },
+            Exchange::Coinbase => Confirmation::CoinbaseAck {
+                product_id: channel,
+            },
+            Exchange::Okx => Confirmation::OkxAck { inst_id: channel },
+            Exchange::Bybit => Confirmation::BybitAck { topic: channel },
+            Exchange::Kucoin => Confirmation::KucoinAck { topic: channel },
+            Exchange::GateIo => Confirmation::GateIoAck { pair: channel },
+            Exchange::Bitfinex => Confirmation::BitfinexAck { symbol: channel },
+            Exchange::Binance | Exchange::BinanceUs => Confirmation::FirstFrame,
+        }
+    }
+
+    /// `Ok(true)` if `text` confirms the subscription, `Err` if it refuses it
+    pub fn check(&self, text: &str) -> Result<bool, String> {
+        let channel = match self {
+            Confirmation::FirstFrame => return Ok(true),
+            Confirmation::KrakenAck { pair } => return Self::check_kraken(pair, text),
+            Confirmation::CoinbaseAck { product_id } => {
+                return Self::check_coinbase(product_id, text);
+            }
+            Confirmation::OkxAck { inst_id } => return Self::check_okx(inst_id, text),
+            Confirmation::BybitAck { .. } => return Self::check_bybit(text),
+            Confirmation::KucoinAck { topic } => return Self::check_kucoin(topic, text),
+            Confirmation::GateIoAck { .. } => return Self::check_gateio(text),
+            Confirmation::BitfinexAck { symbol } => return Self::check_bitfinex(symbol, text),
+            Confirmation::BitstampAck { channel } => channel,
+        };
+        let Ok(message) = serde_json::from_str::<Value>(text) else {
+            return Ok(false);
+        };
+        match message["event"].as_str() {
+            Some("bts:subscription_succeeded") => Ok(message["channel"] == channel.as_str()),
+            Some("bts:error") => Err(format!(
+                "subscription refused: {}",
+                message["data"]["message"]
+                    .as_str()
+                    .unwrap_or("no reason given")
+            )),
+            _ => Ok(false),
+        }
+    }
+
+    fn check_kraken(pair: &str, text: &str) -> Result<bool, String> {
+        let Ok(message) = serde_json::from_str::<Value>(text) else {
+            return Ok(false);
+        };
+        if message["event"] != "subscriptionStatus" || message["pair"] != pair {
+            return Ok(false);
+        }
+        match message["status"].as_str() {
+            Some("subscribed") => Ok(true),
+            Some("error") => Err(format!(
+                "subscription refused: {}",
+                message["errorMessage"]
+                    .as_str()
+                    .unwrap_or("no reason given")
+            )),
+            _ => Ok(false),
+        }
+    }
+
+    fn check_coinbase(product_id: &str, text: &str) -> Result<bool, String> {
+        let Ok(message) = serde_json::from_str::<Value>(text) else {
+            return Ok(false);
+        };
+        match message["type"].as_str() {
+            Some("subscriptions") => Ok(message["channels"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .flat_map(|channel| channel["product_ids"].as_array().into_iter().flatten())
+                .any(|id| id == product_id)),
+            Some("error") => Err(format!(
+                "subscription refused: {}",
+                message["reason"]
+                    .as_str()
+                    .or(message["message"].as_str())
+                    .unwrap_or("no reason given")
+            )),
+            _ => Ok(false),
+        }
+    }
+
+    fn check_okx(inst_id: &str, text: &str) -> Result<bool, String> {
+        let Ok(message) = serde_json::from_str::<Value>(text) else {
+            return Ok(false);
+        };
+        match message["event"].as_str() {
+            Some("subscribe") => Ok(message["arg"]["instId"] == inst_id),
+            Some("error") => Err(format!(
+                "subscription refused: {}",
+                message["msg"].as_str().unwrap_or("no reason given")
+            )),
+            _ => Ok(false),
+        }
+    }
+
+    /// Bybit's reply doesn't name the topic; a connection subscribes to only one
+    fn check_bybit(text: &str) -> Result<bool, String> {
+        let Ok(message) = serde_json::from_str::<Value>(text) else {
+            return Ok(false);
+        };
+        if message["op"] != "subscribe" {
+            return Ok(false);
+        }
+        match message["success"].as_bool() {
+            Some(true) => Ok(true),
+            Some(false) => Err(format!(
+                "subscription refused: {}",
+                message["ret_msg"].as_str().unwrap_or("no reason given")
+            )),
+            None => Ok(false),
+        }
+    }
+
+    /// KuCoin echoes the request id, which is the topic
+    fn check_kucoin(topic: &str, text: &str) -> Result<bool, String> {
+        let Ok(message) = serde_json::from_str::<Value>(text) else {
+            return Ok(false);
+        };
+        if message["id"] != topic {
+            return Ok(false);
+        }
+        match message["type"].as_str() {
+            Some("ack") => Ok(true),
+            Some("error") => Err(format!(
+                "subscription refused: {}",
+                message["data"].as_str().unwrap_or("no reason given")
+            )),
+            _ => Ok(false),
+        }
+    }
+
+    /// Gate.io's reply names the channel but not the pair, which is only subscribed once
+    fn check_gateio(text: &str) -> Result<bool, String> {
+        let Ok(message) = serde_json::from_str::<Value>(text) else {
+            return Ok(false);
+        };
+        if message["event"] != "subscribe" || message["channel"] != gateio::GATEIO_CHANNEL {
+            return Ok(false);
+        }
+        if let Some(error) = message.get("error").filter(|error| !error.is_null()) {
+            return Err(format!(
+                "subscription refused: {}",
+                error["message"].as_str().unwrap_or("no reason given")
+            ));
+        }
+        Ok(message["result"]["status"] == "success")
+    }
+
+    fn check_bitfinex(symbol: &str, text: &str) -> Result<bool, String> {
+        let Ok(message) = serde_json::from_str::<Value>(text) else {
+            return Ok(false);
+        };
+        match message["event"].as_str() {
+            Some("subscribed") => Ok(message["channel"] == "book" && message["symbol"] == symbol),
+            Some("error") => Err(format!(
+                "subscription refused: {}",
+                message["msg"].as_str().unwrap_or("no reason given")
+            )),
+            _ => Ok(false),
+        }
+    }
+
+    /// Whether `text` is data on the subscribed channel
+    pub fn is_data(&self, text: &str) -> bool {
+        let channel = match self {
+            Confirmation::FirstFrame => return true,
+            // Channel frames are arrays, events objects
+            Confirmation::KrakenAck { .. } => return text.trim_start().starts_with('['),
+            Confirmation::CoinbaseAck { product_id } => {
+                return serde_json::from_str::<Value>(text).is_ok_and(|message| {
+                    matches!(message["type"].as_str(), Some("snapshot" | "l2update"))
+                        && message["product_id"] == product_id.as_str()
+                });
+            }
+            Confirmation::OkxAck { inst_id } => {
+                return serde_json::from_str::<Value>(text).is_ok_and(|message| {
+                    message.get("action").is_some() && message["arg"]["instId"] == inst_id.as_str()
+                });
+            }
+            Confirmation::BybitAck { topic } => {
+                return serde_json::from_str::<Value>(text)
+                    .is_ok_and(|message| message["topic"] == topic.as_str());
+            }
+            Confirmation::KucoinAck { topic } => {
+                return serde_json::from_str::<Value>(text).is_ok_and(|message| {
+                    message["type"] == "message" && message["topic"] == topic.as_str()
+                });
+            }
+            Confirmation::GateIoAck { pair } => {
+                return serde_json::from_str::<Value>(text).is_ok_and(|message| {
+                    message["event"] == "update" && message["result"]["s"] == pair.as_str()
+                });
+            }
+            // Levels are arrays, heartbeats strings
+            Confirmation::BitfinexAck { .. } => {
+                return serde_json::from_str::<Value>(text)
+                    .is_ok_and(|message| message[1].is_array());
+            }
+            Confirmation::BitstampAck { channel } => channel,
+        };
+        serde_json::from_str::<Value>(text).is_ok_and(|message| {
+            message["event"] == "data" && message["channel"] == channel.as_str()
+        })
+    }
+}
+
+/// Read until the subscription is confirmed, failing after `timeout` or if the socket
+/// errors or closes first. Data read on the way, the confirming Binance frame and Bitfinex ack
+/// included, is returned in front of the stream so nothing is lost.
+///
+/// With `first_data`, the channel must also send data within that window of the
+/// confirmation: a subscription raced against a reconnect can be acked and then stay
+/// silent, which only a data deadline catches.
+pub async fn confirm<S>(
+    mut ws: S,
+    confirmation: &Confirmation,
+    timeout: Duration,
+    first_data: Option<Duration>,
+) -> Result<Confirmed<S>, String>
+where
+    S: Stream<Item = Result<Message, Error>> + Unpin,
+{
+    let mut pending = vec![];
+    let wait = async {
+        while let Some(message) = ws.next().await {
+            let message = message.map_err(|e| format!("read failed while subscribing: {}", e))?;
+            let confirmed = match &message {
+                Message::Text(text) => confirmation.check(text)?,
+                Message::Close(_) => break,
+                _ => false,
+            };
+            let is_ack = confirmed
+                && !matches!(
+                    confirmation,
+                    Confirmation::FirstFrame | Confirmation::BitfinexAck { .. }
+                );
+            if !is_ack {
+                pending.push(Ok(message));
+            }
+            if confirmed {
+                return Ok(());
+            }
+        }
+        Err("connection closed before the subscription was confirmed".to_string())
+    };
+    match tokio::time::timeout(timeout, wait).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return Err(e),
+        Err(_) => {
+            return Err(format!(
+                "subscription not confirmed within {}ms",
+                timeout.as_millis()
+            ));
+        }
+    }
+
+    let has_data = |pending: &[Result<Message, Error>]| {
+        pending
+            .iter()
+            .any(|m| matches!(m, Ok(Message::Text(text)) if confirmation.is_data(text)))
+    };
+    if let Some(window) = first_data
+        && !has_data(&pending)
+    {
+        let wait = async {
+            while let Some(message) = ws.next().await {
+                let message =
+                    message.map_err(|e| format!("read failed waiting for data: {}", e))?;
+                if let Message::Close(_) = message {
+                    break;
+                }
+                pending.push(Ok(message));
+                if has_data(&pending[pending.len() - 1..]) {
+                    return Ok(());
+                }
+            }
+            Err("connection closed before any data was received".to_string())
+        };
+        match tokio::time::timeout(window, wait).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => return Err(e),
+            Err(_) => {
+                return Err(format!(
+                    "no data within {}ms of the subscription",
+                    window.as_millis()
+                ));
+            }
+        }
+    }
+    Ok(stream::iter(pending).chain(ws))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::SinkExt;
+    use tokio::net::TcpListener;
+
+    const CHANNEL: &str = "diff_order_book_ethbtc";
+
+    /// A mock exchange that answers the first client message with `replies`, then idles
+    async fn mock_exchange(replies: Vec<String>) -> String {
+        mock_exchange_paced(replies.into_iter().map(|r| (Duration::ZERO, r)).collect()).await
+    }
+
+    /// Like `mock_exchange`, waiting the given delay before each reply
+    async fn mock_exchange_paced(replies: Vec<(Duration, String)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(socket).await.unwrap();
+            let _subscribe = ws.next().await;
+            for (delay, reply) in replies {
+                tokio::time::sleep(delay).await;
+                ws.send(Message::Text(reply.into())).await.unwrap();
+            }
+            std::future::pending::<()>().await;
+        });
+        url
+    }
+
+    async fn subscribe(url: &str, confirmation: &Confirmation) -> Result<Vec<String>, String> {
+        subscribe_expecting_data(url, confirmation, None).await
+    }
+
+    async fn subscribe_expecting_data(
+        url: &str,
+        confirmation: &Confirmation,
+        first_data: Option<Duration>,
+    ) -> Result<Vec<String>, String> {
+        let (mut ws, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        ws.send(Message::Text("subscribe".into())).await.unwrap();
+        let (_, read) = ws.split();
+        let confirmed = confirm(read, confirmation, Duration::from_millis(200), first_data).await?;
+        // Whatever the handshake read comes first, then the live stream
+        let texts = confirmed
+            .take_until(tokio::time::sleep(Duration::from_millis(50)))
+            .filter_map(|m| async move { m.ok()?.into_text().ok().map(|t| t.to_string()) })
+            .collect()
+            .await;
+        Ok(texts)
+    }
+
+    fn bitstamp() -> Confirmation {
+        Confirmation::for_exchange(Exchange::Bitstamp, CHANNEL.to_string())
+    }
+
+    #[tokio::test]
+    async fn an_ack_for_the_channel_confirms_and_earlier_data_is_kept() {
+        let url = mock_exchange(vec![
+            r#"{"event":"bts:subscription_succeeded","channel":"order_book_ethbtc","data":{}}"#
+                .to_string(),
+            r#"{"event":"bts:subscription_succeeded","channel":"diff_order_book_ethbtc","data":{}}"#
+                .to_string(),
+            r#"{"event":"data","channel":"diff_order_book_ethbtc"}"#.to_string(),
+        ])
+        .await;
+        let texts = subscribe(&url, &bitstamp()).await.unwrap();
+        assert_eq!(
+            texts.len(),
+            2,
+            "the diff channel's ack is consumed: {:?}",
+            texts
+        );
+        assert!(texts[0].contains("order_book_ethbtc"));
+        assert!(texts[1].contains(r#""event":"data""#));
+    }
+
+    #[tokio::test]
+    async fn a_missing_ack_times_out() {
+        let url = mock_exchange(vec![
+            r#"{"event":"bts:subscription_succeeded","channel":"live_trades_ethbtc"}"#.to_string(),
+        ])
+        .await;
+        let err = subscribe(&url, &bitstamp()).await.unwrap_err();
+        assert!(err.contains("not confirmed within 200ms"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn an_error_ack_refuses_the_subscription() {
+        let url = mock_exchange(vec![
+            r#"{"event":"bts:error","channel":"","data":{"code":null,"message":"Bad subscription string."}}"#
+                .to_string(),
+        ])
+        .await;
+        let err = subscribe(&url, &bitstamp()).await.unwrap_err();
+        assert_eq!(err, "subscription refused: Bad subscription string.");
+    }
+
+    #[tokio::test]
+    async fn the_first_binance_frame_confirms_and_is_kept() {
+        let diff = r#"{"e":"depthUpdate","U":1,"u":2,"b":[],"a":[]}"#.to_string();
+        let url = mock_exchange(vec![diff.clone()]).await;
+        let binance = Confirmation::for_exchange(Exchange::Binance, String::new());
+        assert_eq!(subscribe(&url, &binance).await.unwrap(), vec![diff]);
+
+        let silent = mock_exchange(vec![]).await;
+        assert!(subscribe(&silent, &binance).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn kraken_confirms_on_the_pairs_subscription_status() {
+        let kraken = Confirmation::for_exchange(Exchange::Kraken, "ETH/XBT".to_string());
+        let book = r#"[336,{"as":[],"bs":[]},"book-100","ETH/XBT"]"#.to_string();
+        let url = mock_exchange(vec![
+            r#"{"event":"systemStatus","status":"online"}"#.to_string(),
+            r#"{"event":"subscriptionStatus","status":"subscribed","pair":"XBT/USD"}"#.to_string(),
+            r#"{"event":"subscriptionStatus","status":"subscribed","pair":"ETH/XBT"}"#.to_string(),
+            book.clone(),
+        ])
+        .await;
+        let window = Some(Duration::from_millis(100));
+        let texts = subscribe_expecting_data(&url, &kraken, window)
+            .await
+            .unwrap();
+        assert_eq!(texts.len(), 3, "{:?}", texts);
+        assert_eq!(texts[2], book);
+
+        let url = mock_exchange(vec![
+            r#"{"event":"subscriptionStatus","status":"error","pair":"ETH/XBT","errorMessage":"Currency pair not supported ETH/XBT"}"#
+                .to_string(),
+        ])
+        .await;
+        let err = subscribe(&url, &kraken).await.unwrap_err();
+        assert_eq!(
+            err,
+            "subscription refused: Currency pair not supported ETH/XBT"
+        );
+    }
+
+    #[tokio::test]
+    async fn coinbase_confirms_on_subscriptions_listing_the_product() {
+        let coinbase = Confirmation::for_exchange(Exchange::Coinbase, "ETH-BTC".to_string());
+        let book = r#"{"type":"snapshot","product_id":"ETH-BTC","bids":[],"asks":[]}"#.to_string();
+        let url = mock_exchange(vec![
+            r#"{"type":"subscriptions","channels":[{"name":"level2_batch","product_ids":["BTC-USD"]}]}"#.to_string(),
+            r#"{"type":"subscriptions","channels":[{"name":"level2_batch","product_ids":["BTC-USD","ETH-BTC"]}]}"#.to_string(),
+            book.clone(),
+        ])
+        .await;
+        let window = Some(Duration::from_millis(100));
+        let texts = subscribe_expecting_data(&url, &coinbase, window)
+            .await
+            .unwrap();
+        assert_eq!(texts.len(), 2, "{:?}", texts);
+        assert_eq!(texts[1], book);
+
+        let url = mock_exchange(vec![
+            r#"{"type":"error","message":"Failed to subscribe","reason":"ETH-XYZ is not a valid product"}"#
+                .to_string(),
+        ])
+        .await;
+        let err = subscribe(&url, &coinbase).await.unwrap_err();
+        assert_eq!(err, "subscription refused: ETH-XYZ is not a valid product");
+    }
+
+    #[tokio::test]
+    async fn okx_confirms_on_the_subscribe_event_for_the_instrument() {
+        let okx = Confirmation::for_exchange(Exchange::Okx, "ETH-BTC".to_string());
+        let book =
+            r#"{"arg":{"channel":"books","instId":"ETH-BTC"},"action":"snapshot","data":[]}"#
+                .to_string();
+        let url = mock_exchange(vec![
+            r#"{"event":"subscribe","arg":{"channel":"books","instId":"BTC-USDT"},"connId":"1"}"#
+                .to_string(),
+            r#"{"event":"subscribe","arg":{"channel":"books","instId":"ETH-BTC"},"connId":"1"}"#
+                .to_string(),
+            book.clone(),
+        ])
+        .await;
+        let window = Some(Duration::from_millis(100));
+        let texts = subscribe_expecting_data(&url, &okx, window).await.unwrap();
+        assert_eq!(texts.len(), 2, "{:?}", texts);
+        assert_eq!(texts[1], book);
+
+        let url = mock_exchange(vec![
+            r#"{"event":"error","code":"60018","msg":"instId:ETH-XYZ doesn't exist","connId":"1"}"#
+                .to_string(),
+        ])
+        .await;
+        let err = subscribe(&url, &okx).await.unwrap_err();
+        assert_eq!(err, "subscription refused: instId:ETH-XYZ doesn't exist");
+    }
+
+    #[tokio::test]
+    async fn bybit_confirms_on_a_successful_subscribe_reply() {
+        let bybit = Confirmation::for_exchange(Exchange::Bybit, "orderbook.50.ETHBTC".to_string());
+        let book = r#"{"topic":"orderbook.50.ETHBTC","type":"snapshot","data":{}}"#.to_string();
+        let url = mock_exchange(vec![
+            r#"{"success":true,"ret_msg":"pong","conn_id":"1","op":"ping"}"#.to_string(),
+            r#"{"success":true,"ret_msg":"","conn_id":"1","op":"subscribe"}"#.to_string(),
+            book.clone(),
+        ])
+        .await;
+        let window = Some(Duration::from_millis(100));
+        let texts = subscribe_expecting_data(&url, &bybit, window)
+            .await
+            .unwrap();
+        assert_eq!(texts.len(), 2, "{:?}", texts);
+        assert_eq!(texts[1], book);
+
+        let url = mock_exchange(vec![
+            r#"{"success":false,"ret_msg":"error:handler not found,topic:orderbook.50.ETHXYZ","conn_id":"1","op":"subscribe"}"#
+                .to_string(),
+        ])
+        .await;
+        let err = subscribe(&url, &bybit).await.unwrap_err();
+        assert_eq!(
+            err,
+            "subscription refused: error:handler not found,topic:orderbook.50.ETHXYZ"
+        );
+    }
+
+    #[tokio::test]
+    async fn kucoin_confirms_on_the_ack_of_its_request() {
+        let topic = "/market/level2:ETH-BTC".to_string();
+        let kucoin = Confirmation::for_exchange(Exchange::Kucoin, topic);
+        let delta = r#"{"type":"message","topic":"/market/level2:ETH-BTC","subject":"trade.l2update","data":{}}"#
+            .to_string();
+        let url = mock_exchange(vec![
+            r#"{"id":"hQvf8jkno","type":"welcome"}"#.to_string(),
+            r#"{"id":"/market/level2:ETH-BTC","type":"ack"}"#.to_string(),
+            delta.clone(),
+        ])
+        .await;
+        let window = Some(Duration::from_millis(100));
+        let texts = subscribe_expecting_data(&url, &kucoin, window)
+            .await
+            .unwrap();
+        assert_eq!(texts.len(), 2, "{:?}", texts);
+        assert_eq!(texts[1], delta);
+
+        let url = mock_exchange(vec![
+            r#"{"id":"/market/level2:ETH-BTC","type":"error","code":404,"data":"topic /market/level2:ETH-BTC is not found"}"#
+                .to_string(),
+        ])
+        .await;
+        let err = subscribe(&url, &kucoin).await.unwrap_err();
+        assert_eq!(
+            err,
+            "subscription refused: topic /market/level2:ETH-BTC is not found"
+        );
+    }
+
+    #[tokio::test]
+    async fn gateio_confirms_on_a_successful_subscribe_reply() {
+        let gateio = Confirmation::for_exchange(Exchange::GateIo, "ETH_BTC".to_string());
+        let update = r#"{"time":1700000000,"time_ms":1700000000100,"channel":"spot.order_book_update","event":"update","result":{"t":1700000000095,"e":"depthUpdate","E":1700000000,"s":"ETH_BTC","U":101,"u":102,"b":[],"a":[]}}"#
+            .to_string();
+        let url = mock_exchange(vec![
+            r#"{"time":1700000000,"time_ms":1700000000001,"channel":"spot.pong","event":"","result":null}"#.to_string(),
+            r#"{"time":1700000000,"time_ms":1700000000002,"channel":"spot.order_book_update","event":"subscribe","result":{"status":"success"}}"#.to_string(),
+            update.clone(),
+        ])
+        .await;
+        let window = Some(Duration::from_millis(100));
+        let texts = subscribe_expecting_data(&url, &gateio, window)
+            .await
+            .unwrap();
+        assert_eq!(texts.len(), 2, "{:?}", texts);
+        assert_eq!(texts[1], update);
+
+        let url = mock_exchange(vec![
+            r#"{"time":1700000000,"time_ms":1700000000002,"channel":"spot.order_book_update","event":"subscribe","error":{"code":2,"message":"unknown currency pair XYZ_BTC"},"result":null}"#
+                .to_string(),
+        ])
+        .await;
+        let err = subscribe(&url, &gateio).await.unwrap_err();
+        assert_eq!(err, "subscription refused: unknown currency pair XYZ_BTC");
+    }
+
+    #[tokio::test]
+    async fn bitfinex_keeps_its_ack_to_map_the_channel_id() {
+        let bitfinex = Confirmation::for_exchange(Exchange::Bitfinex, "tETHBTC".to_string());
+        let ack = r#"{"event":"subscribed","channel":"book","chanId":17470,"symbol":"tETHBTC","prec":"P0","freq":"F0","len":"100","pair":"ETHBTC"}"#
+            .to_string();
+        let snapshot = "[17470,[[0.0512,2,1.5]],1700000000000]".to_string();
+        let url = mock_exchange(vec![
+            r#"{"event":"info","version":2,"serverId":"1","platform":{"status":1}}"#.to_string(),
+            r#"{"event":"subscribed","channel":"book","chanId":9,"symbol":"tBTCUSD"}"#.to_string(),
+            ack.clone(),
+            r#"[17470,"hb",1700000000000]"#.to_string(),
+            snapshot.clone(),
+        ])
+        .await;
+        let window = Some(Duration::from_millis(100));
+        let texts = subscribe_expecting_data(&url, &bitfinex, window)
+            .await
+            .unwrap();
+        assert_eq!(texts.len(), 5, "{:?}", texts);
+        assert_eq!((&texts[2], &texts[4]), (&ack, &snapshot));
+
+        let url = mock_exchange(vec![
+            r#"{"event":"error","msg":"symbol: invalid","code":10300}"#.to_string(),
+        ])
+        .await;
+        let err = subscribe(&url, &bitfinex).await.unwrap_err();
+        assert_eq!(err, "subscription refused: symbol: invalid");
+    }
+
+    const ACK: &str =
+        r#"{"event":"bts:subscription_succeeded","channel":"diff_order_book_ethbtc","data":{}}"#;
+    const DATA: &str = r#"{"event":"data","channel":"diff_order_book_ethbtc","data":{}}"#;
+
+    #[tokio::test]
+    async fn an_ack_without_data_fails_the_liveness_window() {
+        let url = mock_exchange(vec![
+            ACK.to_string(),
+            // Data on another channel doesn't count
+            r#"{"event":"data","channel":"order_book_ethbtc","data":{}}"#.to_string(),
+        ])
+        .await;
+        let window = Some(Duration::from_millis(100));
+        let err = subscribe_expecting_data(&url, &bitstamp(), window)
+            .await
+            .unwrap_err();
+        assert_eq!(err, "no data within 100ms of the subscription");
+    }
+
+    #[tokio::test]
+    async fn late_data_within_the_window_confirms_and_is_kept() {
+        let url = mock_exchange_paced(vec![
+            (Duration::ZERO, ACK.to_string()),
+            (Duration::from_millis(300), DATA.to_string()),
+        ])
+        .await;
+        // Later than the handshake timeout, but within the data window
+        let window = Some(Duration::from_secs(2));
+        let texts = subscribe_expecting_data(&url, &bitstamp(), window)
+            .await
+            .unwrap();
+        assert_eq!(texts, vec![DATA.to_string()]);
+
+        // Data that came before the ack already satisfies it
+        let url = mock_exchange(vec![DATA.to_string(), ACK.to_string()]).await;
+        let window = Some(Duration::from_millis(1));
+        assert!(
+            subscribe_expecting_data(&url, &bitstamp(), window)
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn repeatedly_silent_channels_open_the_circuit() {
+        use crate::modules::circuit_breaker::{CircuitBreaker, CircuitBreakerConfig, CircuitState};
+        let config = CircuitBreakerConfig {
+            failure_threshold: 3,
+            ..Default::default()
+        };
+        let clock = crate::modules::clock::system_clock();
+        let mut breaker = CircuitBreaker::new("bitstamp", clock, config);
+        for attempt in 1..=3 {
+            // Each reconnect gets a fresh socket that acks and goes quiet
+            let url = mock_exchange(vec![ACK.to_string()]).await;
+            let window = Some(Duration::from_millis(50));
+            assert!(
+                subscribe_expecting_data(&url, &bitstamp(), window)
+                    .await
+                    .is_err()
+            );
+            let state = breaker.record_failure();
+            assert_eq!(state == CircuitState::Open, attempt == 3);
+        }
+        assert!(!breaker.allow_attempt());
+    }
+}
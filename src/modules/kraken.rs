@@ -0,0 +1,171 @@
+use crate::config::quote_currency;
+use crate::modules::types::{Exchange, OrderBook, OrderLevel};
+use serde_json::Value;
+
+/// Levels per side asked of Kraken, by the REST snapshot and the book channel alike. The
+/// channel doesn't send removals for levels pushed out of this depth.
+pub const KRAKEN_DEPTH: usize = 100;
+
+/// REST and websocket base URLs for Kraken; overridable for tests and proxies
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KrakenEndpoint {
+    pub rest: String,
+    pub ws: String,
+}
+
+impl Default for KrakenEndpoint {
+    fn default() -> Self {
+        Self {
+            rest: "https://api.kraken.com".to_string(),
+            ws: "wss://ws.kraken.com".to_string(),
+        }
+    }
+}
+
+impl KrakenEndpoint {
+    pub fn depth_url(&self, symbol: &str) -> String {
+        format!(
+            "{}/0/public/Depth?pair={}&count={}",
+            self.rest,
+            kraken_pair(symbol).replace('/', ""),
+            KRAKEN_DEPTH
+        )
+    }
+}
+
+/// Kraken's websocket name for `symbol`: base and quote split by `/`, with bitcoin as
+/// `XBT`, e.g. `ETH/XBT` for ethbtc. A symbol with no known quote is only upper-cased.
+pub fn kraken_pair(symbol: &str) -> String {
+    let code = |currency: &str| match currency {
+        "btc" | "xbt" => "XBT".to_string(),
+        other => other.to_uppercase(),
+    };
+    let symbol = symbol.to_lowercase();
+    if let Some((base, quote)) = symbol.split_once('/') {
+        return format!("{}/{}", code(base), code(quote));
+    }
+    let xbt = (symbol.len() > 3 && symbol.ends_with("xbt")).then_some("xbt");
+    quote_currency(&symbol)
+        .or(xbt)
+        .map(|quote| {
+            let base = &symbol[..symbol.len() - quote.len()];
+            format!("{}/{}", code(base), code(quote))
+        })
+        .unwrap_or_else(|| symbol.to_uppercase())
+}
+
+/// The `subscribe` message for the book channel of `symbol`
+pub fn subscribe_message(symbol: &str) -> String {
+    serde_json::json!({
+        "event": "subscribe",
+        "pair": [kraken_pair(symbol)],
+        "subscription": {
+            "name": "book",
+            "depth": KRAKEN_DEPTH
+        }
+    })
+    .to_string()
+}
+
+/// Whether a book-channel frame is the snapshot sent on subscribing (`as`/`bs` keys)
+/// rather than an update (`a`/`b`)
+pub fn is_book_snapshot(text: &str) -> bool {
+    text.contains("\"as\"") || text.contains("\"bs\"")
+}
+
+/// A level timestamp (seconds with up to 6 decimals, as a string or a number) in
+/// microseconds
+pub fn timestamp_micros(timestamp: &Value) -> Option<u64> {
+    let text = match timestamp {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        _ => return None,
+    };
+    let (secs, fraction) = text.split_once('.').unwrap_or((&text, ""));
+    let micros = format!("{:0<6}", fraction.get(..6).unwrap_or(fraction));
+    Some(secs.parse::<u64>().ok()? * 1_000_000 + micros.parse::<u64>().ok()?)
+}
+
+/// Levels of one side, `[price, volume, timestamp, ...]` each, and the newest timestamp
+/// among them in microseconds (0 if empty)
+pub fn parse_levels(side: &Value) -> Option<(Vec<OrderLevel>, u64)> {
+    let mut newest = 0;
+    let levels = side
+        .as_array()?
+        .iter()
+        .map(|level| {
+            newest = newest.max(timestamp_micros(&level[2])?);
+            Some(OrderLevel {
+                exchange: Exchange::Kraken,
+                price: level[0].as_str()?.parse::<f64>().ok()?,
+                amount: level[1].as_str()?.parse::<f64>().ok()?,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some((levels, newest))
+}
+
+/// Parse the REST Depth body. Kraken's book has no sequence number, so the snapshot's id
+/// is its newest level timestamp in microseconds, as for the channel's updates.
+pub fn parse_kraken_snapshot(body: &str) -> Option<OrderBook> {
+    let v: Value = serde_json::from_str(body).ok()?;
+    if !v["error"]
+        .as_array()
+        .is_some_and(|errors| errors.is_empty())
+    {
+        return None;
+    }
+    // Keyed by Kraken's own pair name, e.g. XETHXXBT
+    let (_, book) = v["result"].as_object()?.iter().next()?;
+    let (bids, newest_bid) = parse_levels(&book["bids"])?;
+    let (asks, newest_ask) = parse_levels(&book["asks"])?;
+    Some(OrderBook {
+        last_update_id: newest_bid.max(newest_ask),
+        bids,
+        asks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symbols_map_to_kraken_pairs() {
+        assert_eq!(kraken_pair("ethbtc"), "ETH/XBT");
+        assert_eq!(kraken_pair("BTCUSDT"), "XBT/USDT");
+        assert_eq!(kraken_pair("solusd"), "SOL/USD");
+        assert_eq!(kraken_pair("eth/btc"), "ETH/XBT");
+        assert_eq!(kraken_pair("ETHXBT"), "ETH/XBT");
+        assert_eq!(kraken_pair("xyz"), "XYZ");
+        assert_eq!(
+            KrakenEndpoint::default().depth_url("ethbtc"),
+            "https://api.kraken.com/0/public/Depth?pair=ETHXBT&count=100"
+        );
+        let subscribe: Value = serde_json::from_str(&subscribe_message("ethbtc")).unwrap();
+        assert_eq!(subscribe["pair"][0], "ETH/XBT");
+        assert_eq!(subscribe["subscription"]["name"], "book");
+    }
+
+    #[test]
+    fn rest_snapshots_take_the_newest_level_timestamp_as_id() {
+        let body = r#"{"error":[],"result":{"XETHXXBT":{
+            "asks":[["0.05125","1.500",1700000001],["0.05126","3.000",1700000000]],
+            "bids":[["0.05120","2.000",1700000002],["0.05119","0.250",1699999999]]}}}"#;
+        let book = parse_kraken_snapshot(body).unwrap();
+        assert_eq!(book.last_update_id, 1_700_000_002_000_000);
+        assert_eq!((book.bids[0].price, book.bids[0].amount), (0.0512, 2.0));
+        assert_eq!(book.asks.len(), 2);
+        assert!(book.asks.iter().all(|l| l.exchange == Exchange::Kraken));
+
+        assert!(parse_kraken_snapshot(r#"{"error":["EQuery:Unknown asset pair"]}"#).is_none());
+        assert_eq!(
+            timestamp_micros(&Value::from("1534614248.123678")),
+            Some(1_534_614_248_123_678)
+        );
+        assert_eq!(
+            timestamp_micros(&Value::from("1534614248.5")),
+            Some(1_534_614_248_500_000)
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use crate::config::dashed_instrument;
+use crate::modules::types::{Exchange, OrderBook, OrderLevel};
+use serde_json::Value;
+use std::time::Duration;
+
+/// Levels per side of the public REST snapshot. The level2 channel covers every depth, but
+/// only the snapshot's levels are seeded, so the book is only known this deep.
+pub const KUCOIN_SNAPSHOT_DEPTH: usize = 100;
+
+/// How often to ping when the token response doesn't say
+pub const DEFAULT_PING_INTERVAL: Duration = Duration::from_secs(18);
+
+/// REST base URL for KuCoin, and the websocket to use instead of the one the token request
+/// hands out; overridable for tests and proxies
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KucoinEndpoint {
+    pub rest: String,
+    /// Connected to directly, without asking `bullet-public` for a token
+    pub ws: Option<String>,
+}
+
+impl Default for KucoinEndpoint {
+    fn default() -> Self {
+        Self {
+            rest: "https://api.kucoin.com".to_string(),
+            ws: None,
+        }
+    }
+}
+
+impl KucoinEndpoint {
+    /// Where to POST for a public websocket token
+    pub fn bullet_url(&self) -> String {
+        format!("{}/api/v1/bullet-public", self.rest)
+    }
+
+    pub fn level2_url(&self, symbol: &str) -> String {
+        format!(
+            "{}/api/v1/market/orderbook/level2_{}?symbol={}",
+            self.rest,
+            KUCOIN_SNAPSHOT_DEPTH,
+            kucoin_symbol(symbol)
+        )
+    }
+}
+
+/// KuCoin's symbol for `symbol`, e.g. `ETH-BTC` for ethbtc
+pub fn kucoin_symbol(symbol: &str) -> String {
+    dashed_instrument(symbol)
+}
+
+/// The level2 topic of `symbol`, e.g. `/market/level2:ETH-BTC`
+pub fn topic(symbol: &str) -> String {
+    format!("/market/level2:{}", kucoin_symbol(symbol))
+}
+
+/// The `subscribe` message for the level2 topic of `symbol`. The topic doubles as the
+/// request id, which KuCoin echoes in its `ack`.
+pub fn subscribe_message(symbol: &str) -> String {
+    let topic = topic(symbol);
+    serde_json::json!({
+        "id": topic,
+        "type": "subscribe",
+        "topic": topic,
+        "privateChannel": false,
+        "response": true
+    })
+    .to_string()
+}
+
+/// The `ping` KuCoin expects every ping interval, or it drops the connection
+pub fn ping_message(id: u64) -> String {
+    serde_json::json!({ "id": id.to_string(), "type": "ping" }).to_string()
+}
+
+/// What the token request hands out: the websocket URL to connect to, token included, and
+/// how often to ping it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BulletToken {
+    pub url: String,
+    pub ping_interval: Duration,
+}
+
+/// Parse the `bullet-public` body. `connect_id` names the connection in KuCoin's `welcome`.
+pub fn parse_bullet(body: &str, connect_id: &str) -> Result<BulletToken, String> {
+    let v: Value = serde_json::from_str(body).map_err(|e| format!("invalid JSON: {}", e))?;
+    if v["code"].as_str() != Some("200000") {
+        return Err(format!(
+            "error {}: {}",
+            v["code"].as_str().unwrap_or("without code"),
+            v["msg"].as_str().unwrap_or("no message")
+        ));
+    }
+    let token = v["data"]["token"].as_str().ok_or("missing token")?;
+    let server = &v["data"]["instanceServers"][0];
+    let endpoint = server["endpoint"]
+        .as_str()
+        .ok_or("missing instance server")?;
+    Ok(BulletToken {
+        url: format!("{}?token={}&connectId={}", endpoint, token, connect_id),
+        ping_interval: server["pingInterval"]
+            .as_u64()
+            .map_or(DEFAULT_PING_INTERVAL, Duration::from_millis),
+    })
+}
+
+/// Levels of one side, `[price, size]` each, or `[price, size, sequence]` for a delta's
+/// changes. Changes are put in sequence order, so a price changed twice ends as of the later.
+pub fn parse_levels(side: &Value) -> Option<Vec<OrderLevel>> {
+    let mut levels = side
+        .as_array()?
+        .iter()
+        .map(|level| {
+            let sequence = match level.get(2) {
+                Some(sequence) => sequence.as_str()?.parse::<u64>().ok()?,
+                None => 0,
+            };
+            let level = OrderLevel {
+                exchange: Exchange::Kucoin,
+                price: level[0].as_str()?.parse::<f64>().ok()?,
+                amount: level[1].as_str()?.parse::<f64>().ok()?,
+            };
+            Some((sequence, level))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    levels.sort_by_key(|(sequence, _)| *sequence);
+    Some(levels.into_iter().map(|(_, level)| level).collect())
+}
+
+/// Parse the REST level2 body, with its `sequence` as the id
+pub fn parse_kucoin_snapshot(body: &str) -> Option<OrderBook> {
+    let v: Value = serde_json::from_str(body).ok()?;
+    if v["code"].as_str() != Some("200000") {
+        return None;
+    }
+    let book = &v["data"];
+    Some(OrderBook {
+        last_update_id: book["sequence"].as_str()?.parse().ok()?,
+        bids: parse_levels(&book["bids"])?,
+        asks: parse_levels(&book["asks"])?,
+    })
+}
+
+/// What to do with a delta covering `sequenceStart..=sequenceEnd`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeltaCheck {
+    Apply,
+    /// Already in the book
+    Stale,
+    /// Changes were missed; only a new snapshot can recover them
+    Gap {
+        expected: u64,
+        got: u64,
+    },
+}
+
+/// Holds KuCoin's deltas to its rule: each must start at or before the sequence after the
+/// last one applied, the snapshot's to begin with, and end past it
+#[derive(Debug)]
+pub struct KucoinSequence {
+    last: u64,
+}
+
+impl KucoinSequence {
+    pub fn starting_at(snapshot_sequence: u64) -> Self {
+        Self {
+            last: snapshot_sequence,
+        }
+    }
+
+    pub fn check(&mut self, start: u64, end: u64) -> DeltaCheck {
+        if end <= self.last {
+            return DeltaCheck::Stale;
+        }
+        if start > self.last + 1 {
+            return DeltaCheck::Gap {
+                expected: self.last + 1,
+                got: start,
+            };
+        }
+        self.last = end;
+        DeltaCheck::Apply
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_and_snapshots_are_read_from_kucoins_envelopes() {
+        assert_eq!(topic("ethbtc"), "/market/level2:ETH-BTC");
+        assert_eq!(
+            KucoinEndpoint::default().level2_url("btcusdt"),
+            "https://api.kucoin.com/api/v1/market/orderbook/level2_100?symbol=BTC-USDT"
+        );
+        let subscribe: Value = serde_json::from_str(&subscribe_message("ethbtc")).unwrap();
+        assert_eq!(subscribe["topic"], "/market/level2:ETH-BTC");
+        assert_eq!(subscribe["id"], subscribe["topic"]);
+
+        let bullet = r#"{"code":"200000","data":{"token":"2neAiuYvAU61ZD","instanceServers":[
+            {"endpoint":"wss://ws-api-spot.kucoin.com/","encrypt":true,"protocol":"websocket",
+             "pingInterval":18000,"pingTimeout":10000}]}}"#;
+        assert_eq!(
+            parse_bullet(bullet, "c1"),
+            Ok(BulletToken {
+                url: "wss://ws-api-spot.kucoin.com/?token=2neAiuYvAU61ZD&connectId=c1".to_string(),
+                ping_interval: Duration::from_secs(18),
+            })
+        );
+        let refused = parse_bullet(r#"{"code":"429000","msg":"Too Many Requests"}"#, "c1");
+        assert_eq!(refused, Err("error 429000: Too Many Requests".to_string()));
+
+        let body = r#"{"code":"200000","data":{"time":1700000000000,"sequence":"3262786978",
+            "bids":[["0.05120","2.0"],["0.05119","0.25"]],"asks":[["0.05125","1.5"]]}}"#;
+        let book = parse_kucoin_snapshot(body).unwrap();
+        assert_eq!(book.last_update_id, 3_262_786_978);
+        assert_eq!((book.bids[1].price, book.asks[0].amount), (0.05119, 1.5));
+        assert!(book.bids.iter().all(|l| l.exchange == Exchange::Kucoin));
+
+        // A delta's changes in sequence order: the later change of a price wins
+        let changes: Value = serde_json::from_str(
+            r#"[["0.0513","0","12"],["0.0514","1","10"],["0.0513","2","11"]]"#,
+        )
+        .unwrap();
+        let amounts: Vec<_> = parse_levels(&changes)
+            .unwrap()
+            .iter()
+            .map(|l| (l.price, l.amount))
+            .collect();
+        assert_eq!(amounts, [(0.0514, 1.0), (0.0513, 2.0), (0.0513, 0.0)]);
+    }
+
+    #[test]
+    fn deltas_must_be_contiguous_with_the_snapshot() {
+        let mut sequence = KucoinSequence::starting_at(100);
+        assert_eq!(sequence.check(95, 100), DeltaCheck::Stale);
+        // Straddles the snapshot: its changes up to 100 are already in it, and reapplying
+        // them in order leaves the same levels
+        assert_eq!(sequence.check(98, 103), DeltaCheck::Apply);
+        assert_eq!(sequence.check(104, 104), DeltaCheck::Apply);
+        assert_eq!(sequence.check(104, 104), DeltaCheck::Stale);
+        assert_eq!(
+            sequence.check(107, 109),
+            DeltaCheck::Gap {
+                expected: 105,
+                got: 107
+            }
+        );
+    }
+}
@@ -0,0 +1,221 @@
+use crate::modules::types::AggregatedOrderBook;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LatencyBudget {
+    /// An update taking longer than this, lock wait included, is a slow apply
+    pub budget: Duration,
+    /// This many slow applies in a row mark the exchange degraded
+    pub degraded_after: usize,
+}
+
+impl Default for LatencyBudget {
+    fn default() -> Self {
+        Self {
+            budget: Duration::from_millis(5),
+            degraded_after: 10,
+        }
+    }
+}
+
+/// Where the time of one update went
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ApplyTiming {
+    /// Waiting for the book's write lock
+    pub lock_wait: Duration,
+    /// Applying the update under the lock
+    pub apply: Duration,
+}
+
+impl ApplyTiming {
+    pub fn total(&self) -> Duration {
+        self.lock_wait + self.apply
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BudgetEvent {
+    Degraded,
+    Recovered,
+}
+
+/// Outcome of checking one apply against the budget
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BudgetCheck {
+    pub slow: bool,
+    pub consecutive_slow: usize,
+    pub event: Option<BudgetEvent>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct ExchangeLatency {
+    slow_total: u64,
+    consecutive_slow: usize,
+    degraded: bool,
+}
+
+/// Per-exchange slow-apply counts, fed by the update loop
+#[derive(Debug, Default)]
+pub struct LatencyMonitor {
+    config: LatencyBudget,
+    exchanges: Mutex<BTreeMap<String, ExchangeLatency>>,
+}
+
+impl LatencyMonitor {
+    pub fn new(config: LatencyBudget) -> Self {
+        Self {
+            config,
+            exchanges: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn budget(&self) -> Duration {
+        self.config.budget
+    }
+
+    /// Check one apply. A degraded exchange recovers on its first apply within budget.
+    pub fn record(&self, exchange: &str, timing: ApplyTiming) -> BudgetCheck {
+        let slow = timing.total() > self.config.budget;
+        let mut exchanges = self.exchanges.lock().unwrap();
+        let latency = exchanges.entry(exchange.to_string()).or_default();
+        let mut event = None;
+        if slow {
+            latency.slow_total += 1;
+            latency.consecutive_slow += 1;
+            if !latency.degraded && latency.consecutive_slow >= self.config.degraded_after {
+                latency.degraded = true;
+                event = Some(BudgetEvent::Degraded);
+            }
+        } else {
+            latency.consecutive_slow = 0;
+            if latency.degraded {
+                latency.degraded = false;
+                event = Some(BudgetEvent::Recovered);
+            }
+        }
+        BudgetCheck {
+            slow,
+            consecutive_slow: latency.consecutive_slow,
+            event,
+        }
+    }
+
+    /// Applies over budget since startup
+    pub fn slow_total(&self, exchange: &str) -> u64 {
+        let exchanges = self.exchanges.lock().unwrap();
+        exchanges.get(exchange).map_or(0, |l| l.slow_total)
+    }
+
+    pub fn all_slow_totals(&self) -> BTreeMap<String, u64> {
+        let exchanges = self.exchanges.lock().unwrap();
+        exchanges
+            .iter()
+            .map(|(exchange, l)| (exchange.clone(), l.slow_total))
+            .collect()
+    }
+}
+
+/// Run `apply` under the book's write lock, timing the wait and the apply separately
+pub async fn timed_write<T>(
+    book: &RwLock<AggregatedOrderBook>,
+    apply: impl FnOnce(&mut AggregatedOrderBook) -> T,
+) -> (T, ApplyTiming) {
+    let start = Instant::now();
+    let mut book = book.write().await;
+    let locked = Instant::now();
+    let result = apply(&mut book);
+    let timing = ApplyTiming {
+        lock_wait: locked - start,
+        apply: locked.elapsed(),
+    };
+    (result, timing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::types::{Exchange, OrderBook, OrderBookUpdate, OrderLevel};
+
+    fn timing(apply_ms: u64) -> ApplyTiming {
+        ApplyTiming {
+            lock_wait: Duration::from_millis(1),
+            apply: Duration::from_millis(apply_ms),
+        }
+    }
+
+    #[test]
+    fn consecutive_slow_applies_degrade_until_one_is_within_budget() {
+        let monitor = LatencyMonitor::new(LatencyBudget {
+            budget: Duration::from_millis(5),
+            degraded_after: 3,
+        });
+        assert!(
+            !monitor.record("binance", timing(4)).slow,
+            "exactly on budget, lock wait included"
+        );
+        let check = monitor.record("binance", timing(5));
+        assert!(check.slow);
+        assert_eq!(check.event, None);
+        assert_eq!(monitor.record("binance", timing(50)).event, None);
+        let check = monitor.record("binance", timing(50));
+        assert_eq!(
+            (check.consecutive_slow, check.event),
+            (3, Some(BudgetEvent::Degraded))
+        );
+        assert_eq!(
+            monitor.record("binance", timing(50)).event,
+            None,
+            "already degraded"
+        );
+
+        assert_eq!(monitor.record("bitstamp", timing(1)).event, None);
+        assert_eq!(
+            monitor.record("binance", timing(1)).event,
+            Some(BudgetEvent::Recovered)
+        );
+        assert_eq!(monitor.slow_total("binance"), 4);
+        assert_eq!(
+            monitor.all_slow_totals(),
+            BTreeMap::from([("binance".to_string(), 4), ("bitstamp".to_string(), 0)])
+        );
+    }
+
+    #[tokio::test]
+    async fn a_huge_update_blows_the_budget() {
+        let level = |price: f64| OrderLevel {
+            exchange: Exchange::Binance,
+            price,
+            amount: 1.0,
+        };
+        let mut book = AggregatedOrderBook::new();
+        book.merge_snapshots(vec![OrderBook {
+            last_update_id: 1,
+            bids: vec![level(1.0)],
+            asks: vec![level(1_000_000.0)],
+        }]);
+        let book = RwLock::new(book);
+        let update = OrderBookUpdate {
+            exchange: Exchange::Binance,
+            update_id: 2,
+            first_update_id: None,
+            received_at: None,
+            event_time: None,
+            bids: (2..200_000).map(|p| level(p as f64)).collect(),
+            asks: vec![],
+        };
+
+        let (applied, timing) = timed_write(&book, |book| book.handle_update(update)).await;
+        applied.unwrap();
+        let monitor = LatencyMonitor::new(LatencyBudget {
+            budget: Duration::from_micros(1),
+            degraded_after: 1,
+        });
+        let check = monitor.record("binance", timing);
+        assert!(check.slow, "{:?}", timing);
+        assert_eq!(check.event, Some(BudgetEvent::Degraded));
+        assert_eq!(monitor.slow_total("binance"), 1);
+    }
+}
@@ -0,0 +1,105 @@
+use crate::modules::status::StatusRegistry;
+use crate::modules::types::OrderBookUpdate;
+
+/// Caps on how much a single exchange payload can make us parse and hold
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PayloadLimits {
+    /// Largest websocket message accepted; a larger one fails the connection
+    pub max_frame_bytes: usize,
+    /// Levels kept per side of one update; the rest are dropped
+    pub max_update_levels: usize,
+    /// Largest REST snapshot body read
+    pub max_snapshot_bytes: usize,
+}
+
+impl Default for PayloadLimits {
+    fn default() -> Self {
+        Self {
+            max_frame_bytes: 4 << 20,
+            max_update_levels: 5_000,
+            max_snapshot_bytes: 32 << 20,
+        }
+    }
+}
+
+/// Payloads refused or cut by the limits, per exchange
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PayloadViolations {
+    pub levels_dropped: u64,
+    pub oversized_frames: u64,
+    pub oversized_snapshots: u64,
+}
+
+impl PayloadLimits {
+    /// Keep the first `max_update_levels` levels of each side, counting and logging the
+    /// rest. Returns how many were dropped.
+    pub fn cap_update(&self, update: &mut OrderBookUpdate, status: &StatusRegistry) -> usize {
+        let mut dropped = 0;
+        for side in [&mut update.bids, &mut update.asks] {
+            dropped += side.len().saturating_sub(self.max_update_levels);
+            side.truncate(self.max_update_levels);
+        }
+        if dropped > 0 {
+            let exchange = update.exchange.as_str();
+            status.record_dropped_levels(exchange, dropped as u64);
+            if status
+                .log_limiter
+                .allow(&format!("{} update over the level cap", exchange))
+            {
+                tracing::warn!(
+                    exchange,
+                    dropped,
+                    max_levels = self.max_update_levels,
+                    "Update over the level cap, excess levels dropped"
+                );
+            }
+        }
+        dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::binance::BinanceVariant;
+
+    fn binance_diff(bids: usize, asks: usize) -> String {
+        let levels = |count: usize| {
+            (0..count)
+                .map(|i| format!(r#"["{}.0","1.0"]"#, 1 + i))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        format!(
+            r#"{{"e":"depthUpdate","U":1,"u":2,"b":[{}],"a":[{}]}}"#,
+            levels(bids),
+            levels(asks)
+        )
+    }
+
+    #[test]
+    fn oversized_updates_are_truncated_and_counted() {
+        let limits = PayloadLimits {
+            max_update_levels: 1_000,
+            ..Default::default()
+        };
+        let status = StatusRegistry::default();
+        let text = binance_diff(200_000, 10);
+        let mut update = OrderBookUpdate::classify_binance_json(&text, BinanceVariant::Global)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(limits.cap_update(&mut update, &status), 199_000);
+        assert_eq!(update.bids.len(), 1_000);
+        assert_eq!(
+            update.bids.last().unwrap().price,
+            1_000.0,
+            "the first levels are kept"
+        );
+        assert_eq!(update.asks.len(), 10);
+        assert_eq!(limits.cap_update(&mut update, &status), 0);
+        let counted = status.exchanges()[0].payload;
+        assert_eq!(counted.levels_dropped, 199_000);
+        assert_eq!(counted.oversized_frames, 0);
+    }
+}
@@ -0,0 +1,218 @@
+use crate::modules::clock::{SharedClock, system_clock};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+pub const DEFAULT_SUPPRESSION_WINDOW: Duration = Duration::from_secs(60);
+
+/// Repeats of one warning that were not logged, reported in one line once their window ends
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rollup {
+    pub key: String,
+    pub suppressed: u64,
+    pub window: Duration,
+}
+
+impl Rollup {
+    pub fn log(&self) {
+        tracing::warn!(
+            key = self.key.as_str(),
+            suppressed = self.suppressed,
+            "Suppressed {} occurrences of {} in the last {}s",
+            self.suppressed,
+            self.key,
+            self.window.as_secs()
+        );
+    }
+}
+
+#[derive(Debug)]
+struct Window {
+    started_at: u64, // unix millis
+    suppressed: u64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    windows: BTreeMap<String, Window>,
+    totals: BTreeMap<String, u64>,
+}
+
+/// Rate limiter for hot-path warnings, keyed by what is being warned about: the first
+/// occurrence of a key is logged, repeats within `window` of it are only counted, and the
+/// count is rolled up into one line when the window ends. A zero window logs everything.
+#[derive(Debug)]
+pub struct LogLimiter {
+    clock: SharedClock,
+    window: Duration,
+    state: Mutex<State>,
+}
+
+impl Default for LogLimiter {
+    fn default() -> Self {
+        Self::new(system_clock(), DEFAULT_SUPPRESSION_WINDOW)
+    }
+}
+
+impl LogLimiter {
+    pub fn new(clock: SharedClock, window: Duration) -> Self {
+        Self {
+            clock,
+            window,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Whether to log this occurrence of `key`, with the roll-up of its previous window if
+    /// that ended with repeats not yet reported
+    pub fn check(&self, key: &str) -> (bool, Option<Rollup>) {
+        let now = self.clock.now_millis();
+        let window_ms = self.window.as_millis() as u64;
+        let mut state = self.state.lock().unwrap();
+        match state.windows.get_mut(key) {
+            Some(open) if now.saturating_sub(open.started_at) < window_ms => {
+                open.suppressed += 1;
+                *state.totals.entry(key.to_string()).or_default() += 1;
+                (false, None)
+            }
+            Some(ended) => {
+                let rollup = self.rollup(key, ended.suppressed);
+                *ended = Window {
+                    started_at: now,
+                    suppressed: 0,
+                };
+                (true, rollup)
+            }
+            None => {
+                state.windows.insert(
+                    key.to_string(),
+                    Window {
+                        started_at: now,
+                        suppressed: 0,
+                    },
+                );
+                (true, None)
+            }
+        }
+    }
+
+    /// Like [`check`](Self::check), logging the roll-up if there is one
+    pub fn allow(&self, key: &str) -> bool {
+        let (allow, rollup) = self.check(key);
+        if let Some(rollup) = rollup {
+            rollup.log();
+        }
+        allow
+    }
+
+    /// Close every window that has ended, returning the roll-ups of those with repeats.
+    /// The next occurrence of their keys is logged as a first one.
+    pub fn take_rollups(&self) -> Vec<Rollup> {
+        let now = self.clock.now_millis();
+        let window_ms = self.window.as_millis() as u64;
+        let mut rollups = vec![];
+        self.state.lock().unwrap().windows.retain(|key, window| {
+            if now.saturating_sub(window.started_at) < window_ms {
+                return true;
+            }
+            rollups.extend(self.rollup(key, window.suppressed));
+            false
+        });
+        rollups
+    }
+
+    /// Log the roll-ups of every ended window; called periodically so repeats that stop
+    /// are still reported
+    pub fn flush(&self) {
+        for rollup in self.take_rollups() {
+            rollup.log();
+        }
+    }
+
+    /// Occurrences not logged since startup, by key
+    pub fn suppressed_totals(&self) -> BTreeMap<String, u64> {
+        self.state.lock().unwrap().totals.clone()
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    fn rollup(&self, key: &str, suppressed: u64) -> Option<Rollup> {
+        (suppressed > 0).then(|| Rollup {
+            key: key.to_string(),
+            suppressed,
+            window: self.window,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::clock::MockClock;
+    use std::sync::Arc;
+
+    const KEY: &str = "binance update id not greater than last";
+
+    fn limiter() -> (Arc<MockClock>, LogLimiter) {
+        let clock = Arc::new(MockClock::new(1_000));
+        let limiter = LogLimiter::new(clock.clone(), Duration::from_secs(60));
+        (clock, limiter)
+    }
+
+    #[test]
+    fn repeats_are_counted_and_rolled_up_when_the_window_ends() {
+        let (clock, limiter) = limiter();
+        assert_eq!(limiter.check(KEY), (true, None));
+        for _ in 0..4_812 {
+            clock.advance(Duration::from_millis(10));
+            assert_eq!(limiter.check(KEY), (false, None));
+        }
+        // Other keys have windows of their own
+        assert_eq!(limiter.check("bitstamp"), (true, None));
+
+        clock.set_millis(61_000);
+        let (allow, rollup) = limiter.check(KEY);
+        assert!(allow, "the first occurrence of a new window is logged");
+        assert_eq!(
+            rollup,
+            Some(Rollup {
+                key: KEY.to_string(),
+                suppressed: 4_812,
+                window: Duration::from_secs(60),
+            })
+        );
+        assert_eq!(limiter.check(KEY), (false, None));
+        assert_eq!(
+            limiter.suppressed_totals(),
+            BTreeMap::from([(KEY.to_string(), 4_813)])
+        );
+    }
+
+    #[test]
+    fn ended_windows_are_rolled_up_without_a_new_occurrence() {
+        let (clock, limiter) = limiter();
+        limiter.check(KEY);
+        limiter.check(KEY);
+        limiter.check("quiet");
+        clock.advance(Duration::from_secs(59));
+        assert_eq!(limiter.take_rollups(), vec![]);
+
+        clock.advance(Duration::from_secs(1));
+        let rollups = limiter.take_rollups();
+        assert_eq!(rollups.len(), 1, "nothing to report for a key seen once");
+        assert_eq!((rollups[0].key.as_str(), rollups[0].suppressed), (KEY, 1));
+        assert_eq!(limiter.check(KEY), (true, None), "already reported");
+    }
+
+    #[test]
+    fn a_zero_window_logs_everything() {
+        let clock = Arc::new(MockClock::new(1_000));
+        let limiter = LogLimiter::new(clock, Duration::ZERO);
+        for _ in 0..3 {
+            assert_eq!(limiter.check(KEY), (true, None));
+        }
+        assert!(limiter.suppressed_totals().is_empty());
+    }
+}
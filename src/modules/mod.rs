@@ -1,4 +1,63 @@
 pub mod aggregated_orderbook;
+pub mod alarms;
+pub mod backoff;
 pub mod binance;
+pub mod bitfinex;
 pub mod bitstamp;
+pub mod build_env;
+pub mod build_info;
+pub mod bybit;
+pub mod checksum;
+pub mod circuit_breaker;
+pub mod clock;
+pub mod coinbase;
+pub mod commands;
+pub mod connections;
+#[cfg(feature = "connectors")]
+pub mod connectors;
+pub mod consistency;
+pub mod cross_check;
+pub mod data_quality;
+pub mod dedup;
+#[cfg(feature = "connectors")]
+pub mod exchange_connector;
+pub mod gap_marker;
+pub mod gateio;
+#[cfg(feature = "connectors")]
+pub mod handshake;
+pub mod kraken;
+pub mod kucoin;
+pub mod latency;
+pub mod limits;
+pub mod log_limiter;
+pub mod multi_summary;
+pub mod okx;
+pub mod one_sided;
+pub mod parse_failures;
+pub mod quarantine;
+pub mod quote;
+pub mod rate;
+pub mod replay;
+#[cfg(feature = "connectors")]
+pub mod router;
+pub mod sequence_reset;
+pub mod shutdown;
+pub mod smart_best;
+pub mod snapshot_fetch;
+pub mod spread_convention;
+pub mod startup;
+pub mod stats;
+pub mod status;
+pub mod stream_metrics;
+pub mod stuck_snapshot;
+pub mod sync_state;
+pub mod throttle;
+pub mod tie_break;
+pub mod timeseries;
+pub mod traded_estimate;
 pub mod types;
+pub mod update_age;
+pub mod update_queue;
+pub mod uptime;
+pub mod warm_cache;
+pub mod warm_start;
@@ -0,0 +1,143 @@
+use crate::modules::aggregated_orderbook::{DepthUnit, Top10Snapshot};
+use crate::modules::clock::SharedClock;
+use async_stream::stream;
+use futures::Stream;
+use futures::future::select_all;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// The published snapshots of several books, read together so cross-symbol consumers (e.g.
+/// cross rates) don't combine ladders from different moments
+#[derive(Clone, Debug, Default)]
+pub struct MultiSummary {
+    /// Unix millis, common to every summary
+    pub generated_at: u64,
+    /// One per book, in the order asked for; each keeps its own version
+    pub summaries: Vec<Top10Snapshot>,
+    /// Symbol whose change triggered this one; `None` for the first of a stream
+    pub changed: Option<String>,
+}
+
+/// Read the latest snapshot of every book in one pass, without yielding in between, so
+/// no book publishes while the others are being read
+pub fn capture(
+    books: &mut [watch::Receiver<Arc<Top10Snapshot>>],
+    clock: &SharedClock,
+    depth: Option<usize>,
+    unit: DepthUnit,
+    changed: Option<String>,
+) -> MultiSummary {
+    let snaps: Vec<Arc<Top10Snapshot>> = books
+        .iter_mut()
+        .map(|book| book.borrow_and_update().clone())
+        .collect();
+    MultiSummary {
+        generated_at: clock.now_millis(),
+        summaries: snaps
+            .into_iter()
+            .map(|snap| {
+                let mut snap = Top10Snapshot::clone(&snap);
+                if let Some(depth) = depth {
+                    snap.truncate(depth, unit);
+                }
+                snap
+            })
+            .collect(),
+        changed,
+    }
+}
+
+/// A capture of every book now, then one each time any of them publishes, tagged with the
+/// symbol that did. Changes that land together are sent as one. Ends once a book is dropped.
+pub fn subscribe(
+    mut books: Vec<watch::Receiver<Arc<Top10Snapshot>>>,
+    clock: SharedClock,
+    depth: Option<usize>,
+    unit: DepthUnit,
+) -> impl Stream<Item = MultiSummary> + Send + 'static {
+    stream! {
+        yield capture(&mut books, &clock, depth, unit, None);
+        loop {
+            let changes = books.iter_mut().map(|book| Box::pin(book.changed()));
+            let (changed, index, _) = select_all(changes).await;
+            if changed.is_err() {
+                break;
+            }
+            let symbol = books[index].borrow().symbol.clone();
+            yield capture(&mut books, &clock, depth, unit, Some(symbol));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::clock::MockClock;
+    use crate::modules::types::{AggregatedOrderBook, Exchange, OrderBook, OrderLevel};
+    use futures::StreamExt;
+    use std::time::Duration;
+
+    fn book(clock: SharedClock, symbol: &str, mid: f64) -> AggregatedOrderBook {
+        let mut book = AggregatedOrderBook::with_clock(clock);
+        book.config.symbol = symbol.to_string();
+        let level = |price| OrderLevel {
+            exchange: Exchange::Binance,
+            price,
+            amount: 1.0,
+        };
+        book.merge_snapshots(vec![OrderBook {
+            last_update_id: 1,
+            bids: vec![level(mid - 1.0), level(mid - 2.0)],
+            asks: vec![level(mid + 1.0), level(mid + 2.0)],
+        }]);
+        book
+    }
+
+    #[tokio::test]
+    async fn books_are_captured_together_and_changes_are_tagged() {
+        let clock = Arc::new(MockClock::new(1_000));
+        let mut ethbtc = book(clock.clone(), "ethbtc", 100.0);
+        let mut btcusdt = book(clock.clone(), "btcusdt", 50_000.0);
+        let (ethbtc_version, btcusdt_version) = (ethbtc.version, btcusdt.version);
+        let mut summaries = Box::pin(subscribe(
+            vec![btcusdt.subscribe(), ethbtc.subscribe()],
+            clock.clone(),
+            Some(1),
+            DepthUnit::PriceLevels,
+        ));
+
+        let first = summaries.next().await.unwrap();
+        assert_eq!(first.generated_at, 1_000);
+        assert_eq!(first.changed, None);
+        let symbols: Vec<_> = first.summaries.iter().map(|s| s.symbol.as_str()).collect();
+        assert_eq!(symbols, ["btcusdt", "ethbtc"], "in the order asked for");
+        assert_eq!(first.summaries[0].version, btcusdt_version);
+        assert_eq!(first.summaries[1].version, ethbtc_version);
+        assert_eq!(first.summaries[0].bids.len(), 1);
+
+        clock.advance(Duration::from_millis(250));
+        ethbtc.merge_snapshots(vec![OrderBook {
+            last_update_id: 2,
+            bids: vec![OrderLevel {
+                exchange: Exchange::Bitstamp,
+                price: 99.5,
+                amount: 2.0,
+            }],
+            asks: vec![],
+        }]);
+        let second = summaries.next().await.unwrap();
+        assert_eq!(second.generated_at, 1_250);
+        assert_eq!(second.changed.as_deref(), Some("ethbtc"));
+        assert_eq!(second.summaries[0].version, btcusdt_version);
+        assert_eq!(second.summaries[1].version, ethbtc.version);
+
+        btcusdt.clear();
+        let third = summaries.next().await.unwrap();
+        assert_eq!(third.changed.as_deref(), Some("btcusdt"));
+        assert!(third.summaries[0].bids.is_empty());
+        assert_eq!(third.summaries[1].version, ethbtc.version);
+
+        drop(btcusdt);
+        assert!(summaries.next().await.is_none());
+    }
+}
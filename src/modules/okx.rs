@@ -0,0 +1,312 @@
+use crate::config::dashed_instrument;
+use crate::modules::checksum::crc32;
+use crate::modules::types::{Exchange, OrderBook, OrderLevel};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// The 400-level book channel: a `snapshot` on subscribing, then `update`s every 100ms
+pub const OKX_CHANNEL: &str = "books";
+
+/// Levels per side of the REST book, the channel's depth
+pub const OKX_DEPTH: usize = 400;
+
+/// Levels per side covered by the channel's checksum
+const CHECKSUM_LEVELS: usize = 25;
+
+/// REST and websocket base URLs for OKX; overridable for tests and proxies
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OkxEndpoint {
+    pub rest: String,
+    pub ws: String,
+}
+
+impl Default for OkxEndpoint {
+    fn default() -> Self {
+        Self {
+            rest: "https://www.okx.com".to_string(),
+            ws: "wss://ws.okx.com:8443/ws/v5/public".to_string(),
+        }
+    }
+}
+
+impl OkxEndpoint {
+    pub fn books_url(&self, symbol: &str) -> String {
+        format!(
+            "{}/api/v5/market/books?instId={}&sz={}",
+            self.rest,
+            okx_inst_id(symbol),
+            OKX_DEPTH
+        )
+    }
+}
+
+/// OKX's spot instrument id for `symbol`, e.g. `ETH-BTC` for ethbtc
+pub fn okx_inst_id(symbol: &str) -> String {
+    dashed_instrument(symbol)
+}
+
+/// The `subscribe` message for the book channel of `symbol`
+pub fn subscribe_message(symbol: &str) -> String {
+    serde_json::json!({
+        "op": "subscribe",
+        "args": [{ "channel": OKX_CHANNEL, "instId": okx_inst_id(symbol) }]
+    })
+    .to_string()
+}
+
+/// Whether a book-channel message is the `snapshot` sent on subscribing rather than an
+/// `update`
+pub fn is_book_snapshot(text: &str) -> bool {
+    text.contains("\"action\":\"snapshot\"")
+}
+
+/// Levels of one side, `[price, size, _, orders]` each
+pub fn parse_levels(side: &Value) -> Option<Vec<OrderLevel>> {
+    side.as_array()?
+        .iter()
+        .map(|level| {
+            Some(OrderLevel {
+                exchange: Exchange::Okx,
+                price: level[0].as_str()?.parse::<f64>().ok()?,
+                amount: level[1].as_str()?.parse::<f64>().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Parse the REST books body. It has no `seqId` and its `ts` isn't on the `seqId` scale, so
+/// the id is 0 and OKX is exempt from the snapshot age check (`snapshot_ids_follow_stream`):
+/// the channel's snapshot, sent on subscribing, replaces it and starts the sequence.
+pub fn parse_okx_snapshot(body: &str) -> Option<OrderBook> {
+    let v: Value = serde_json::from_str(body).ok()?;
+    if v["code"].as_str() != Some("0") {
+        return None;
+    }
+    let book = &v["data"][0];
+    Some(OrderBook {
+        last_update_id: 0,
+        bids: parse_levels(&book["bids"])?,
+        asks: parse_levels(&book["asks"])?,
+    })
+}
+
+/// The channel's checksum of a book, best levels first: CRC-32 of the top 25 bids and asks
+/// interleaved as `bidPx:bidSz:askPx:askSz:...`, with prices and sizes as OKX sent them
+pub fn okx_checksum<'a>(
+    bids: impl IntoIterator<Item = &'a (String, String)>,
+    asks: impl IntoIterator<Item = &'a (String, String)>,
+) -> i32 {
+    let mut bids = bids.into_iter().take(CHECKSUM_LEVELS);
+    let mut asks = asks.into_iter().take(CHECKSUM_LEVELS);
+    let mut fields: Vec<&str> = vec![];
+    loop {
+        let (bid, ask) = (bids.next(), asks.next());
+        if bid.is_none() && ask.is_none() {
+            break;
+        }
+        for (price, size) in bid.into_iter().chain(ask) {
+            fields.push(price);
+            fields.push(size);
+        }
+    }
+    crc32(fields.join(":").as_bytes()) as i32
+}
+
+/// One side as OKX sent it, keyed by price. Prices are positive, so their bit patterns
+/// order like the prices themselves.
+type RawSide = BTreeMap<u64, (String, String)>;
+
+/// A copy of the OKX book with prices and sizes as sent, to verify the channel's checksums.
+/// The aggregated book only keeps parsed and rounded levels, which can't reproduce them.
+#[derive(Debug, Default)]
+pub struct OkxBookMirror {
+    bids: RawSide,
+    asks: RawSide,
+    /// Whether a snapshot has been seen since the mirror was created
+    synced: bool,
+}
+
+impl OkxBookMirror {
+    /// Apply a book-channel message and check the book against its checksum. Other messages,
+    /// and updates before the first snapshot, are not checked.
+    pub fn observe(&mut self, text: &str) -> Result<(), String> {
+        let Ok(v) = serde_json::from_str::<Value>(text) else {
+            return Ok(());
+        };
+        match v["action"].as_str() {
+            Some("snapshot") => {
+                self.bids.clear();
+                self.asks.clear();
+                self.synced = true;
+            }
+            Some("update") if self.synced => {}
+            _ => return Ok(()),
+        }
+        let book = &v["data"][0];
+        Self::apply(&mut self.bids, &book["bids"])?;
+        Self::apply(&mut self.asks, &book["asks"])?;
+        let Some(expected) = book["checksum"].as_i64() else {
+            return Ok(());
+        };
+        let computed = okx_checksum(self.bids.values().rev(), self.asks.values());
+        if i64::from(computed) != expected {
+            return Err(format!(
+                "checksum mismatch at seqId {}: OKX sent {}, book gives {}",
+                book["seqId"], expected, computed
+            ));
+        }
+        Ok(())
+    }
+
+    fn apply(side: &mut RawSide, levels: &Value) -> Result<(), String> {
+        for level in levels.as_array().into_iter().flatten() {
+            let (Some(price), Some(size)) = (level[0].as_str(), level[1].as_str()) else {
+                return Err(format!("malformed level {}", level));
+            };
+            let Ok(key) = price.parse::<f64>().map(f64::to_bits) else {
+                return Err(format!("malformed price {}", price));
+            };
+            if size.parse::<f64>().is_ok_and(|size| size == 0.0) {
+                side.remove(&key);
+            } else {
+                side.insert(key, (price.to_string(), size.to_string()));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw(levels: &[(&str, &str)]) -> Vec<(String, String)> {
+        levels
+            .iter()
+            .map(|(price, size)| (price.to_string(), size.to_string()))
+            .collect()
+    }
+
+    fn message(action: &str, bids: &str, asks: &str, checksum: i32, seq: u64) -> String {
+        format!(
+            r#"{{"arg":{{"channel":"books","instId":"ETH-BTC"}},"action":"{}","data":[{{"asks":{},"bids":{},"ts":"1700000000000","checksum":{},"prevSeqId":{},"seqId":{}}}]}}"#,
+            action,
+            asks,
+            bids,
+            checksum,
+            seq - 1,
+            seq
+        )
+    }
+
+    #[test]
+    fn symbols_map_to_okx_instruments() {
+        assert_eq!(okx_inst_id("ethbtc"), "ETH-BTC");
+        assert_eq!(okx_inst_id("BTCUSDT"), "BTC-USDT");
+        assert_eq!(okx_inst_id("sol-usdc"), "SOL-USDC");
+        assert_eq!(
+            OkxEndpoint::default().books_url("ethbtc"),
+            "https://www.okx.com/api/v5/market/books?instId=ETH-BTC&sz=400"
+        );
+        let subscribe: Value = serde_json::from_str(&subscribe_message("ethbtc")).unwrap();
+        assert_eq!(subscribe["args"][0]["instId"], "ETH-BTC");
+        assert_eq!(subscribe["args"][0]["channel"], OKX_CHANNEL);
+
+        let body = r#"{"code":"0","msg":"","data":[{"asks":[["0.05125","1.5","0","2"]],
+            "bids":[["0.05120","2.0","0","1"],["0.05119","0.25","0","1"]],"ts":"1700000000000"}]}"#;
+        let book = parse_okx_snapshot(body).unwrap();
+        assert_eq!(book.last_update_id, 0);
+        assert_eq!((book.bids[1].price, book.asks[0].amount), (0.05119, 1.5));
+        assert!(book.bids.iter().all(|l| l.exchange == Exchange::Okx));
+        assert!(
+            parse_okx_snapshot(
+                r#"{"code":"51001","msg":"Instrument ID does not exist","data":[]}"#
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn rest_snapshots_merge_after_the_stream_got_ahead() {
+        use crate::modules::types::{AggregatedOrderBook, OrderBookUpdate};
+
+        let body = r#"{"code":"0","msg":"","data":[{"asks":[["0.05125","1.5","0","2"]],
+            "bids":[["0.05120","2.0","0","1"]],"ts":"1700000000000"}]}"#;
+        let mut agg = AggregatedOrderBook::new();
+        agg.merge_snapshots(vec![parse_okx_snapshot(body).unwrap()]);
+        let level = |price, amount| OrderLevel {
+            exchange: Exchange::Okx,
+            price,
+            amount,
+        };
+        agg.apply_update(OrderBookUpdate {
+            exchange: Exchange::Okx,
+            update_id: 500,
+            first_update_id: None,
+            received_at: None,
+            event_time: None,
+            bids: vec![level(0.05121, 3.0)],
+            asks: vec![],
+        })
+        .unwrap();
+
+        // A reconnect's REST book, after the stream's seqId got to 500
+        let report = agg.merge_snapshots(vec![parse_okx_snapshot(body).unwrap()]);
+        assert_eq!(report.per_exchange[&Exchange::Okx].skipped_reason, None);
+        assert_eq!(report.per_exchange[&Exchange::Okx].removed, 1);
+        assert_eq!(agg.last_update_id["okx"], 0);
+    }
+
+    #[test]
+    fn checksums_interleave_the_top_levels_as_sent() {
+        let bids = raw(&[("3366.1", "7"), ("3366", "6")]);
+        let asks = raw(&[("3366.8", "9"), ("3368", "8"), ("3372", "8")]);
+        assert_eq!(
+            okx_checksum(&bids, &asks),
+            crc32(b"3366.1:7:3366.8:9:3366:6:3368:8:3372:8") as i32
+        );
+        // Only the top 25 of each side count
+        let deep: Vec<_> = (0..30)
+            .map(|i| (format!("{}", 100 - i), "1".to_string()))
+            .collect();
+        assert_eq!(okx_checksum(&deep, &asks), okx_checksum(&deep[..25], &asks));
+    }
+
+    #[test]
+    fn the_mirror_follows_updates_and_reports_mismatches() {
+        let mut mirror = OkxBookMirror::default();
+        // Nothing to check against before the snapshot
+        let early = message("update", r#"[["0.9","1","0","1"]]"#, "[]", 0, 9);
+        assert_eq!(mirror.observe(&early), Ok(()));
+
+        let bids = r#"[["0.99","2","0","1"],["0.98","3","0","1"]]"#;
+        let asks = r#"[["1.01","1","0","1"]]"#;
+        let checksum = okx_checksum(
+            &raw(&[("0.99", "2"), ("0.98", "3")]),
+            &raw(&[("1.01", "1")]),
+        );
+        assert_eq!(
+            mirror.observe(&message("snapshot", bids, asks, checksum, 10)),
+            Ok(())
+        );
+
+        // The best bid goes, an ask is added behind the best
+        let checksum = okx_checksum(
+            &raw(&[("0.98", "3")]),
+            &raw(&[("1.01", "1"), ("1.02", "4")]),
+        );
+        let update = message(
+            "update",
+            r#"[["0.99","0","0","0"]]"#,
+            r#"[["1.02","4","0","1"]]"#,
+            checksum,
+            11,
+        );
+        assert_eq!(mirror.observe(&update), Ok(()));
+
+        let update = message("update", r#"[["0.97","1","0","1"]]"#, "[]", checksum, 12);
+        let err = mirror.observe(&update).unwrap_err();
+        assert!(err.starts_with("checksum mismatch at seqId 12"), "{}", err);
+        assert_eq!(mirror.observe(r#"{"event":"subscribe"}"#), Ok(()));
+    }
+}
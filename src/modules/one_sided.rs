@@ -0,0 +1,40 @@
+use crate::modules::aggregated_orderbook::Top10Snapshot;
+use serde::Deserialize;
+
+/// What a Summary stream sends while a side of the book is empty, e.g. when the asks of an
+/// illiquid pair empty out
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OneSidedSummaries {
+    /// Send them, with the empty side flagged and no spread
+    #[default]
+    Emit,
+    /// Send nothing until both sides have levels again
+    Hold,
+}
+
+impl OneSidedSummaries {
+    /// Whether `snap` is held back from streams
+    pub fn holds(&self, snap: &Top10Snapshot) -> bool {
+        *self == OneSidedSummaries::Hold
+            && (snap.total_bid_levels == 0 || snap.total_ask_levels == 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_hold_holds_and_only_one_sided_books() {
+        let snap = |bids, asks| Top10Snapshot {
+            total_bid_levels: bids,
+            total_ask_levels: asks,
+            ..Default::default()
+        };
+        for (bids, asks, one_sided) in [(3, 0, true), (0, 2, true), (0, 0, true), (3, 2, false)] {
+            assert!(!OneSidedSummaries::Emit.holds(&snap(bids, asks)));
+            assert_eq!(OneSidedSummaries::Hold.holds(&snap(bids, asks)), one_sided);
+        }
+    }
+}
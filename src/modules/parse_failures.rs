@@ -0,0 +1,159 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+
+pub const DEFAULT_SAMPLES_PER_EXCHANGE: usize = 20;
+/// Payloads are cut to this many bytes; the original length is kept alongside
+pub const MAX_PAYLOAD_BYTES: usize = 2048;
+
+/// A message that couldn't be classified or parsed, kept for debugging
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseFailure {
+    pub exchange: String,
+    pub at: u64, // unix millis
+    pub reason: String,
+    pub payload: String,
+    pub payload_len: usize,
+}
+
+impl ParseFailure {
+    pub fn truncated(&self) -> bool {
+        self.payload.len() < self.payload_len
+    }
+}
+
+/// Last few parse failures per exchange, oldest dropped first
+#[derive(Debug)]
+pub struct ParseFailureLog {
+    per_exchange: usize,
+    samples: Mutex<BTreeMap<String, VecDeque<ParseFailure>>>,
+}
+
+impl Default for ParseFailureLog {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_SAMPLES_PER_EXCHANGE)
+    }
+}
+
+impl ParseFailureLog {
+    pub fn with_capacity(per_exchange: usize) -> Self {
+        Self {
+            per_exchange,
+            samples: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn record(&self, exchange: &str, at: u64, reason: &str, payload: &str) {
+        let mut end = payload.len().min(MAX_PAYLOAD_BYTES);
+        while !payload.is_char_boundary(end) {
+            end -= 1;
+        }
+        let failure = ParseFailure {
+            exchange: exchange.to_string(),
+            at,
+            reason: reason.to_string(),
+            payload: payload[..end].to_string(),
+            payload_len: payload.len(),
+        };
+        tracing::warn!(
+            "{} parse failure: {} ({} bytes): {}",
+            exchange,
+            failure.reason,
+            failure.payload_len,
+            failure.payload
+        );
+
+        if self.per_exchange == 0 {
+            return;
+        }
+        let mut samples = self.samples.lock().unwrap();
+        let buffer = samples.entry(exchange.to_string()).or_default();
+        if buffer.len() == self.per_exchange {
+            buffer.pop_front();
+        }
+        buffer.push_back(failure);
+    }
+
+    /// Samples for one exchange, or every exchange when `None`, oldest first
+    pub fn samples(&self, exchange: Option<&str>) -> Vec<ParseFailure> {
+        let samples = self.samples.lock().unwrap();
+        match exchange {
+            Some(exchange) => samples
+                .get(exchange)
+                .map(|b| b.iter().cloned().collect())
+                .unwrap_or_default(),
+            None => samples.values().flatten().cloned().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::types::{Exchange, OrderBookUpdate};
+
+    // Mirrors the websocket loop: control messages are ignored, failures are sampled
+    fn feed(log: &ParseFailureLog, exchange: Exchange, at: u64, text: &str) {
+        let parsed = match exchange {
+            Exchange::Bitstamp => OrderBookUpdate::classify_bitstamp_json(text),
+            _ => OrderBookUpdate::classify_binance_json(text, Default::default()),
+        };
+        if let Err(reason) = parsed {
+            log.record(exchange.as_str(), at, &reason, text);
+        }
+    }
+
+    #[test]
+    fn malformed_messages_are_sampled_with_reasons() {
+        let log = ParseFailureLog::default();
+        feed(
+            &log,
+            Exchange::Bitstamp,
+            1,
+            r#"{"event":"bts:subscription_succeeded","data":{}}"#,
+        );
+        feed(
+            &log,
+            Exchange::Bitstamp,
+            2,
+            r#"{"event":"data","data":{"bids":"oops"}}"#,
+        );
+        feed(&log, Exchange::Binance, 3, r#"{"result":null,"id":1}"#);
+        feed(&log, Exchange::Binance, 4, "not json");
+        feed(&log, Exchange::Binance, 5, r#"{"e":"depthUpdate","u":7}"#);
+
+        let bitstamp = log.samples(Some("bitstamp"));
+        assert_eq!(bitstamp.len(), 1);
+        assert_eq!(bitstamp[0].at, 2);
+        assert!(
+            bitstamp[0].reason.contains("data"),
+            "{}",
+            bitstamp[0].reason
+        );
+
+        let binance = log.samples(Some("binance"));
+        assert_eq!(binance.iter().map(|f| f.at).collect::<Vec<_>>(), vec![4, 5]);
+        assert!(binance[0].reason.starts_with("invalid JSON"));
+        assert_eq!(binance[1].payload, r#"{"e":"depthUpdate","u":7}"#);
+        assert_eq!(log.samples(None).len(), 3);
+    }
+
+    #[test]
+    fn buffer_is_bounded_and_payloads_truncated() {
+        let log = ParseFailureLog::with_capacity(3);
+        for at in 0..10 {
+            log.record("binance", at, "bad", "x");
+        }
+        let samples = log.samples(Some("binance"));
+        assert_eq!(
+            samples.iter().map(|f| f.at).collect::<Vec<_>>(),
+            vec![7, 8, 9]
+        );
+
+        let huge = "é".repeat(MAX_PAYLOAD_BYTES);
+        log.record("bitstamp", 0, "bad", &huge);
+        let sample = &log.samples(Some("bitstamp"))[0];
+        assert!(sample.truncated());
+        assert!(sample.payload.len() <= MAX_PAYLOAD_BYTES);
+        assert_eq!(sample.payload_len, huge.len());
+    }
+}
@@ -0,0 +1,393 @@
+use crate::modules::aggregated_orderbook::UpdateOutcome;
+use crate::modules::alarms::{AlarmEvent, Alarms};
+use crate::modules::clock::SharedClock;
+use crate::modules::stuck_snapshot::is_stuck_snapshot;
+use crate::modules::types::{AggregatedOrderBook, OrderBookUpdate};
+use std::collections::HashMap;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug)]
+pub struct QuarantineConfig {
+    /// Quarantine after more than this many consecutive stale updates
+    pub max_stale: usize,
+    /// A run of stale updates longer ago than this starts over
+    pub window: Duration,
+    /// How long a quarantined exchange is ignored before it is resynced
+    pub cool_down: Duration,
+}
+
+impl Default for QuarantineConfig {
+    fn default() -> Self {
+        Self {
+            max_stale: 50,
+            window: Duration::from_secs(10),
+            cool_down: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Admission {
+    Applied,
+    Stale,
+    /// The exchange is quarantined; the update was dropped unseen
+    Ignored,
+    /// This update tripped the quarantine and the exchange's levels were cleared
+    Quarantined,
+    /// The exchange restarted its sequence and this update is its new baseline
+    SequenceReset,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct StaleRun {
+    count: usize,
+    started_at: u64,
+}
+
+/// Catches venues whose update ids keep going backwards. Each stale update is harmless on its
+/// own, but a long run of them leaves the venue's levels frozen in the book.
+#[derive(Debug)]
+pub struct Quarantine {
+    clock: SharedClock,
+    config: QuarantineConfig,
+    runs: HashMap<String, StaleRun>,
+    resync_at: HashMap<String, u64>, // quarantined exchange -> unix millis
+    times_quarantined: HashMap<String, u64>,
+    alarms: Alarms,
+}
+
+impl Quarantine {
+    pub fn new(clock: SharedClock, config: QuarantineConfig) -> Self {
+        Self {
+            clock,
+            config,
+            runs: HashMap::new(),
+            resync_at: HashMap::new(),
+            times_quarantined: HashMap::new(),
+            alarms: Alarms::default(),
+        }
+    }
+
+    /// Raise an alarm each time an exchange is quarantined
+    pub fn with_alarms(mut self, alarms: Alarms) -> Self {
+        self.alarms = alarms;
+        self
+    }
+
+    /// Apply `update` unless its exchange is quarantined, tracking stale runs
+    pub fn apply(
+        &mut self,
+        book: &mut AggregatedOrderBook,
+        update: OrderBookUpdate,
+    ) -> Result<Admission, String> {
+        let exchange = update.exchange.to_string();
+        if self.resync_at.contains_key(&exchange) {
+            return Ok(Admission::Ignored);
+        }
+        match book.apply_update(update)? {
+            UpdateOutcome::Applied => {
+                self.runs.remove(&exchange);
+                Ok(Admission::Applied)
+            }
+            // Ends the stale run that led up to it
+            UpdateOutcome::SequenceReset => {
+                self.runs.remove(&exchange);
+                Ok(Admission::SequenceReset)
+            }
+            UpdateOutcome::Stale => {
+                let now = self.clock.now_millis();
+                let window = self.config.window.as_millis() as u64;
+                let run = self.runs.entry(exchange.clone()).or_insert(StaleRun {
+                    count: 0,
+                    started_at: now,
+                });
+                if now.saturating_sub(run.started_at) > window {
+                    *run = StaleRun {
+                        count: 0,
+                        started_at: now,
+                    };
+                }
+                run.count += 1;
+                if run.count <= self.config.max_stale {
+                    return Ok(Admission::Stale);
+                }
+
+                tracing::warn!(
+                    "{} sent {} stale updates in a row, quarantining it for {}s",
+                    exchange,
+                    run.count,
+                    self.config.cool_down.as_secs()
+                );
+                self.start(book, &exchange);
+                Ok(Admission::Quarantined)
+            }
+        }
+    }
+
+    /// Quarantine `exchange` if its sync failed on a REST snapshot stuck in a cache: a
+    /// resync would only bring the same stale book back. `false` for any other failure.
+    pub fn on_sync_failure(
+        &mut self,
+        book: &mut AggregatedOrderBook,
+        exchange: &str,
+        error: &str,
+    ) -> bool {
+        if !is_stuck_snapshot(error) {
+            return false;
+        }
+        tracing::warn!(
+            "{} keeps serving a stale snapshot, quarantining it for {}s",
+            exchange,
+            self.config.cool_down.as_secs()
+        );
+        self.start(book, exchange);
+        true
+    }
+
+    fn start(&mut self, book: &mut AggregatedOrderBook, exchange: &str) {
+        self.runs.remove(exchange);
+        let until = self.clock.now_millis() + self.config.cool_down.as_millis() as u64;
+        self.resync_at.insert(exchange.to_string(), until);
+        self.alarms.raise(AlarmEvent::Quarantined {
+            exchange: exchange.to_string(),
+            until,
+        });
+        *self
+            .times_quarantined
+            .entry(exchange.to_string())
+            .or_default() += 1;
+        book.remove_exchange(exchange);
+    }
+
+    pub fn is_quarantined(&self, exchange: &str) -> bool {
+        self.resync_at.contains_key(exchange)
+    }
+
+    pub fn quarantined_until(&self, exchange: &str) -> Option<u64> {
+        self.resync_at.get(exchange).copied()
+    }
+
+    pub fn times_quarantined(&self, exchange: &str) -> u64 {
+        self.times_quarantined.get(exchange).copied().unwrap_or(0)
+    }
+
+    /// Whether `exchange` is quarantined and still serving its cool-down, so not worth
+    /// connecting to yet
+    pub fn cooling_down(&self, exchange: &str) -> bool {
+        let now = self.clock.now_millis();
+        self.resync_at.get(exchange).is_some_and(|at| now < *at)
+    }
+
+    /// Whether any quarantined exchange has served its cool-down and should be resynced
+    pub fn resync_due(&self) -> bool {
+        let now = self.clock.now_millis();
+        self.resync_at.values().any(|at| now >= *at)
+    }
+
+    /// Lift the quarantine once a fresh snapshot for `exchange` has been merged
+    pub fn release(&mut self, exchange: &str) {
+        if self.resync_at.remove(exchange).is_some() {
+            tracing::info!("{} resynced, quarantine lifted", exchange);
+        }
+        self.runs.remove(exchange);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::clock::MockClock;
+    use crate::modules::stuck_snapshot::StuckSnapshotGuard;
+    use crate::modules::types::{Exchange, OrderBook, OrderLevel};
+    use std::sync::Arc;
+
+    fn level(exchange: Exchange, price: f64, amount: f64) -> OrderLevel {
+        OrderLevel {
+            exchange,
+            price,
+            amount,
+        }
+    }
+
+    fn snapshot(exchange: Exchange, last_update_id: u64) -> OrderBook {
+        OrderBook {
+            last_update_id,
+            bids: vec![level(exchange, 100.0, 1.0)],
+            asks: vec![level(exchange, 101.0, 1.0)],
+        }
+    }
+
+    fn update(update_id: u64, price: f64) -> OrderBookUpdate {
+        OrderBookUpdate {
+            exchange: Exchange::Bitstamp,
+            update_id,
+            first_update_id: None,
+            received_at: None,
+            event_time: None,
+            bids: vec![level(Exchange::Bitstamp, price, 2.0)],
+            asks: vec![],
+        }
+    }
+
+    fn setup() -> (Arc<MockClock>, AggregatedOrderBook, Quarantine) {
+        let clock = Arc::new(MockClock::new(1_000));
+        let mut book = AggregatedOrderBook::with_clock(clock.clone());
+        book.merge_snapshots(vec![
+            snapshot(Exchange::Binance, 10),
+            snapshot(Exchange::Bitstamp, 1_000),
+        ]);
+        let quarantine = Quarantine::new(
+            clock.clone(),
+            QuarantineConfig {
+                max_stale: 5,
+                window: Duration::from_secs(10),
+                cool_down: Duration::from_secs(30),
+            },
+        );
+        (clock, book, quarantine)
+    }
+
+    #[test]
+    fn a_long_stale_run_quarantines_and_a_snapshot_recovers() {
+        let (clock, mut book, mut quarantine) = setup();
+
+        // Ids keep jumping backwards below the snapshot's 1000
+        let admissions: Vec<Admission> = (0..8)
+            .map(|i| {
+                clock.advance(Duration::from_millis(100));
+                quarantine.apply(&mut book, update(900 - i, 99.0)).unwrap()
+            })
+            .collect();
+        assert_eq!(admissions[..5], [Admission::Stale; 5]);
+        assert_eq!(admissions[5], Admission::Quarantined);
+        assert_eq!(admissions[6..], [Admission::Ignored; 2]);
+
+        assert!(quarantine.is_quarantined("bitstamp"));
+        assert_eq!(quarantine.times_quarantined("bitstamp"), 1);
+        assert!(
+            book.bids
+                .values()
+                .chain(book.asks.values())
+                .all(|bucket| !bucket.contains_key("bitstamp"))
+        );
+        assert!(!book.bids.is_empty(), "other venues keep their levels");
+
+        // Even a good id is dropped until the cool-down passes and a snapshot is merged
+        assert_eq!(
+            quarantine.apply(&mut book, update(2_000, 99.0)).unwrap(),
+            Admission::Ignored
+        );
+        assert!(!quarantine.resync_due());
+        clock.advance(Duration::from_secs(30));
+        assert!(quarantine.resync_due());
+
+        book.merge_snapshots(vec![snapshot(Exchange::Bitstamp, 3_000)]);
+        quarantine.release("bitstamp");
+        assert!(!quarantine.is_quarantined("bitstamp"));
+        assert_eq!(
+            quarantine.apply(&mut book, update(3_001, 99.5)).unwrap(),
+            Admission::Applied
+        );
+        assert!(book.bids.values().any(|b| b.contains_key("bitstamp")));
+    }
+
+    #[test]
+    fn a_stuck_snapshot_quarantines_its_exchange_for_the_cool_down() {
+        let (clock, mut book, mut quarantine) = setup();
+        let timed_out = "Bitstamp snapshot request failed: timed out";
+        assert!(!quarantine.on_sync_failure(&mut book, "bitstamp", timed_out));
+        assert!(!quarantine.is_quarantined("bitstamp"));
+
+        let stuck = StuckSnapshotGuard::default().stuck_error("bitstamp", 1_000);
+        assert!(quarantine.on_sync_failure(&mut book, "bitstamp", &stuck));
+        assert!(quarantine.is_quarantined("bitstamp"));
+        assert!(quarantine.cooling_down("bitstamp"));
+        assert_eq!(quarantine.times_quarantined("bitstamp"), 1);
+        assert!(book.bids.values().all(|b| !b.contains_key("bitstamp")));
+        assert_eq!(
+            quarantine.apply(&mut book, update(2_000, 99.0)).unwrap(),
+            Admission::Ignored
+        );
+
+        clock.advance(Duration::from_secs(30));
+        assert!(!quarantine.cooling_down("bitstamp"));
+        assert!(quarantine.resync_due());
+    }
+
+    #[test]
+    fn applied_updates_and_the_window_reset_the_run() {
+        let (clock, mut book, mut quarantine) = setup();
+        for _ in 0..3 {
+            for _ in 0..5 {
+                quarantine.apply(&mut book, update(1, 99.0)).unwrap();
+            }
+            clock.advance(Duration::from_secs(11));
+        }
+        assert!(!quarantine.is_quarantined("bitstamp"));
+
+        for id in 0..20 {
+            let admission = if id % 2 == 0 {
+                quarantine.apply(&mut book, update(1, 99.0)).unwrap()
+            } else {
+                quarantine
+                    .apply(&mut book, update(1_000 + id, 99.0))
+                    .unwrap()
+            };
+            assert_ne!(admission, Admission::Quarantined);
+        }
+    }
+
+    #[test]
+    fn a_sequence_reset_ends_the_stale_run_but_not_a_quarantine() {
+        let (_, mut book, mut quarantine) = setup();
+        let stamped = |update_id, price, event_time| OrderBookUpdate {
+            event_time: Some(event_time),
+            ..update(update_id, price)
+        };
+        assert_eq!(
+            quarantine.apply(&mut book, stamped(1_000_000, 99.0, 10_000)),
+            Ok(Admission::Applied)
+        );
+        // Small glitches backwards are stale, however new their data
+        for i in 0..4 {
+            assert_eq!(
+                quarantine.apply(&mut book, stamped(999_990 - i, 99.0, 11_000)),
+                Ok(Admission::Stale)
+            );
+        }
+        // The stream restarted its ids: the new sequence replaces the old one
+        assert_eq!(
+            quarantine.apply(&mut book, stamped(7, 98.0, 12_000)),
+            Ok(Admission::SequenceReset)
+        );
+        let bitstamp_bids: Vec<f64> = book
+            .bids
+            .values()
+            .filter_map(|bucket| bucket.get("bitstamp"))
+            .map(|level| level.price)
+            .collect();
+        assert_eq!(bitstamp_bids, [98.0], "levels of the old sequence are gone");
+        assert_eq!(book.last_update_id["bitstamp"], 7);
+        assert_eq!(
+            quarantine.apply(&mut book, stamped(8, 98.5, 12_100)),
+            Ok(Admission::Applied)
+        );
+
+        // The run before the reset doesn't count towards a quarantine
+        for _ in 0..5 {
+            assert_eq!(
+                quarantine.apply(&mut book, stamped(8, 99.0, 12_200)),
+                Ok(Admission::Stale)
+            );
+        }
+        assert_eq!(
+            quarantine.apply(&mut book, stamped(8, 99.0, 12_200)),
+            Ok(Admission::Quarantined)
+        );
+        // A quarantined exchange waits for its snapshot, even on what looks like a reset
+        assert_eq!(
+            quarantine.apply(&mut book, stamped(1, 99.0, 20_000)),
+            Ok(Admission::Ignored)
+        );
+    }
+}
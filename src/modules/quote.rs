@@ -0,0 +1,111 @@
+use crate::modules::clock::SharedClock;
+use serde_json::Value;
+use std::fmt::Debug;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Converts amounts in the book's quote currency into another currency, e.g. BTC into USD
+/// for an ETH/BTC book
+pub trait QuoteConverter: Send + Sync + Debug {
+    /// Target currency label, e.g. "usd"
+    fn currency(&self) -> &str;
+
+    /// Units of the target currency per unit of the quote currency, or `None` if the
+    /// reference price is missing or stale
+    fn rate(&self) -> Option<f64>;
+}
+
+/// Which currency notional figures are in
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum NotionalUnit {
+    /// The symbol's own quote currency
+    #[default]
+    Quote,
+    Converted {
+        currency: String,
+        rate: f64,
+    },
+    /// Conversion into `currency` was asked for but no usable rate was available, so the
+    /// figures are left in the quote currency
+    Unavailable {
+        currency: String,
+    },
+}
+
+/// Mid price of a reference symbol (e.g. btcusdt for an ethbtc book) used as the rate
+#[derive(Debug)]
+pub struct ReferenceMid {
+    currency: String,
+    clock: SharedClock,
+    max_age: Duration,
+    latest: Mutex<Option<(f64, u64)>>, // (mid, unix millis)
+}
+
+impl ReferenceMid {
+    pub fn new(currency: &str, clock: SharedClock, max_age: Duration) -> Self {
+        Self {
+            currency: currency.to_lowercase(),
+            clock,
+            max_age,
+            latest: Mutex::new(None),
+        }
+    }
+
+    pub fn set_mid(&self, mid: f64) {
+        if mid.is_finite() && mid > 0.0 {
+            *self.latest.lock().unwrap() = Some((mid, self.clock.now_millis()));
+        }
+    }
+
+    pub fn clock(&self) -> &SharedClock {
+        &self.clock
+    }
+}
+
+impl QuoteConverter for ReferenceMid {
+    fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    fn rate(&self) -> Option<f64> {
+        let (mid, at) = (*self.latest.lock().unwrap())?;
+        let age = self.clock.now_millis().saturating_sub(at);
+        (age <= self.max_age.as_millis() as u64).then_some(mid)
+    }
+}
+
+/// Mid of a Binance `bookTicker` message (`b`/`a` are the best bid and ask prices)
+pub fn parse_book_ticker_mid(text: &str) -> Option<f64> {
+    let v: Value = serde_json::from_str(text).ok()?;
+    let price = |key: &str| v.get(key)?.as_str()?.parse::<f64>().ok();
+    Some((price("b")? + price("a")?) / 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::clock::MockClock;
+    use std::sync::Arc;
+
+    #[test]
+    fn reference_mid_goes_stale() {
+        let clock = Arc::new(MockClock::new(1_000));
+        let reference = ReferenceMid::new("USD", clock.clone(), Duration::from_secs(5));
+        assert_eq!(reference.currency(), "usd");
+        assert_eq!(reference.rate(), None);
+
+        reference.set_mid(
+            parse_book_ticker_mid(
+                r#"{"u":1,"s":"BTCUSDT","b":"60000.0","B":"1","a":"60002.0","A":"2"}"#,
+            )
+            .unwrap(),
+        );
+        assert_eq!(reference.rate(), Some(60_001.0));
+        clock.advance(Duration::from_secs(6));
+        assert_eq!(reference.rate(), None);
+
+        reference.set_mid(f64::NAN);
+        assert_eq!(reference.rate(), None);
+        assert_eq!(parse_book_ticker_mid(r#"{"result":null,"id":1}"#), None);
+    }
+}
@@ -0,0 +1,257 @@
+use crate::modules::clock::{SharedClock, system_clock};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Completed seconds kept for the peak rate
+const PEAK_WINDOW_SECS: usize = 60;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateConfig {
+    /// Time constant of the smoothed (EWMA) rate
+    pub horizon: Duration,
+    /// A second with more than this many times the smoothed rate is a burst
+    pub burst_multiple: f64,
+}
+
+impl Default for RateConfig {
+    fn default() -> Self {
+        Self {
+            horizon: Duration::from_secs(30),
+            burst_multiple: 3.0,
+        }
+    }
+}
+
+/// Messages per second, as of the last completed second
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RateStats {
+    pub last_second: f64,
+    pub smoothed: f64,
+    pub peak_last_minute: f64,
+    pub bursting: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BurstEvent {
+    Started(RateStats),
+    Ended(RateStats),
+}
+
+#[derive(Clone, Debug)]
+struct ExchangeRate {
+    second: u64, // unix seconds of the second being counted
+    count: u64,
+    recent: VecDeque<u64>, // completed seconds, newest last
+    smoothed: Option<f64>,
+    bursting: bool,
+}
+
+impl ExchangeRate {
+    fn new(second: u64) -> Self {
+        Self {
+            second,
+            count: 0,
+            recent: VecDeque::with_capacity(PEAK_WINDOW_SECS),
+            smoothed: None,
+            bursting: false,
+        }
+    }
+
+    /// Close every second before `now`, returning the burst transition if there was one
+    fn advance(&mut self, now: u64, config: &RateConfig) -> Option<BurstEvent> {
+        if now <= self.second {
+            return None;
+        }
+        let was_bursting = self.bursting;
+        let alpha = 1.0 - (-1.0 / config.horizon.as_secs_f64().max(1.0)).exp();
+        self.close(self.count, alpha, config);
+        // Seconds without any message; decay the average in one step
+        let idle = now - self.second - 1;
+        if idle > 0 {
+            self.smoothed = self
+                .smoothed
+                .map(|smoothed| smoothed * (1.0 - alpha).powf(idle as f64));
+            for _ in 0..idle.min(PEAK_WINDOW_SECS as u64) {
+                self.push(0);
+            }
+            self.bursting = false;
+        }
+        self.second = now;
+        self.count = 0;
+        match (was_bursting, self.bursting) {
+            (false, true) => Some(BurstEvent::Started(self.stats())),
+            (true, false) => Some(BurstEvent::Ended(self.stats())),
+            _ => None,
+        }
+    }
+
+    fn close(&mut self, count: u64, alpha: f64, config: &RateConfig) {
+        let rate = count as f64;
+        self.bursting = match self.smoothed {
+            Some(smoothed) if smoothed > 0.0 => rate > config.burst_multiple * smoothed,
+            _ => false,
+        };
+        self.smoothed = Some(match self.smoothed {
+            Some(smoothed) => smoothed + alpha * (rate - smoothed),
+            None => rate,
+        });
+        self.push(count);
+    }
+
+    fn push(&mut self, count: u64) {
+        if self.recent.len() == PEAK_WINDOW_SECS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(count);
+    }
+
+    fn stats(&self) -> RateStats {
+        RateStats {
+            last_second: self.recent.back().copied().unwrap_or(0) as f64,
+            smoothed: self.smoothed.unwrap_or(0.0),
+            peak_last_minute: self.recent.iter().copied().max().unwrap_or(0) as f64,
+            bursting: self.bursting,
+        }
+    }
+}
+
+/// Per-exchange message rates, fed by the connectors
+#[derive(Debug)]
+pub struct RateTracker {
+    clock: SharedClock,
+    config: RateConfig,
+    exchanges: Mutex<BTreeMap<String, ExchangeRate>>,
+}
+
+impl Default for RateTracker {
+    fn default() -> Self {
+        Self::new(system_clock(), RateConfig::default())
+    }
+}
+
+impl RateTracker {
+    pub fn new(clock: SharedClock, config: RateConfig) -> Self {
+        Self {
+            clock,
+            config,
+            exchanges: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Count one message. Returns a burst transition detected when a second closed.
+    pub fn record(&self, exchange: &str) -> Option<BurstEvent> {
+        let now = self.clock.now_millis() / 1_000;
+        let mut exchanges = self.exchanges.lock().unwrap();
+        let rate = exchanges
+            .entry(exchange.to_string())
+            .or_insert_with(|| ExchangeRate::new(now));
+        let event = rate.advance(now, &self.config);
+        rate.count += 1;
+        event
+    }
+
+    /// Rates as of now, counting seconds without messages as zero
+    pub fn stats(&self, exchange: &str) -> Option<RateStats> {
+        let exchanges = self.exchanges.lock().unwrap();
+        exchanges.get(exchange).map(|rate| self.current(rate))
+    }
+
+    pub fn all(&self) -> BTreeMap<String, RateStats> {
+        let exchanges = self.exchanges.lock().unwrap();
+        exchanges
+            .iter()
+            .map(|(exchange, rate)| (exchange.clone(), self.current(rate)))
+            .collect()
+    }
+
+    fn current(&self, rate: &ExchangeRate) -> RateStats {
+        let mut rate = rate.clone();
+        rate.advance(self.clock.now_millis() / 1_000, &self.config);
+        rate.stats()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::clock::MockClock;
+    use std::sync::Arc;
+
+    fn tracker(clock: &Arc<MockClock>) -> RateTracker {
+        RateTracker::new(
+            clock.clone(),
+            RateConfig {
+                horizon: Duration::from_secs(10),
+                burst_multiple: 3.0,
+            },
+        )
+    }
+
+    /// `per_second` messages evenly spread over each of `seconds` seconds
+    fn feed(
+        tracker: &RateTracker,
+        clock: &MockClock,
+        per_second: u64,
+        seconds: u64,
+    ) -> Vec<BurstEvent> {
+        let step = Duration::from_millis(1_000 / per_second);
+        let mut events = vec![];
+        for _ in 0..seconds * per_second {
+            events.extend(tracker.record("binance"));
+            clock.advance(step);
+        }
+        events
+    }
+
+    #[test]
+    fn steady_arrivals_converge_and_bursts_start_and_end() {
+        let clock = Arc::new(MockClock::new(1_000_000));
+        let tracker = tracker(&clock);
+        assert_eq!(tracker.stats("binance"), None);
+
+        assert!(feed(&tracker, &clock, 10, 30).is_empty());
+        let steady = tracker.stats("binance").unwrap();
+        assert_eq!(steady.last_second, 10.0);
+        assert!((steady.smoothed - 10.0).abs() < 1e-9);
+        assert_eq!(steady.peak_last_minute, 10.0);
+        assert!(!steady.bursting);
+
+        // The burst is detected once its second closes, i.e. on the next message
+        assert!(feed(&tracker, &clock, 50, 1).is_empty());
+        let events = feed(&tracker, &clock, 10, 1);
+        let Some(BurstEvent::Started(stats)) = events.first().copied() else {
+            panic!("expected a burst start, got {:?}", events);
+        };
+        assert_eq!(stats.last_second, 50.0);
+        assert_eq!(stats.peak_last_minute, 50.0);
+        assert!(stats.bursting);
+
+        let events = feed(&tracker, &clock, 10, 2);
+        assert!(matches!(events.as_slice(), [BurstEvent::Ended(_)]));
+        assert!(!tracker.stats("binance").unwrap().bursting);
+        assert_eq!(tracker.stats("binance").unwrap().peak_last_minute, 50.0);
+    }
+
+    #[test]
+    fn idle_seconds_decay_the_rate_and_age_out_the_peak() {
+        let clock = Arc::new(MockClock::new(1_000_000));
+        let tracker = tracker(&clock);
+        feed(&tracker, &clock, 20, 5);
+
+        clock.advance(Duration::from_secs(10));
+        let idle = tracker.stats("binance").unwrap();
+        assert_eq!(idle.last_second, 0.0);
+        assert_eq!(idle.peak_last_minute, 20.0);
+        // One time constant without messages leaves about 1/e of the rate
+        assert!(idle.smoothed < 20.0 * 0.4 && idle.smoothed > 20.0 * 0.3);
+
+        clock.advance(Duration::from_secs(120));
+        let all = tracker.all();
+        assert_eq!(all["binance"].peak_last_minute, 0.0);
+        assert!(all["binance"].smoothed < 1e-3);
+
+        // Stats are a read-only view: recording resumes from where it left off
+        assert_eq!(tracker.record("binance"), None);
+    }
+}
@@ -0,0 +1,221 @@
+use crate::modules::binance::{BinanceVariant, parse_binance_snapshot};
+use crate::modules::bitfinex::parse_bitfinex_snapshot;
+use crate::modules::bitstamp::parse_bitstamp_snapshot;
+use crate::modules::bybit::parse_bybit_snapshot;
+use crate::modules::coinbase::parse_coinbase_snapshot;
+use crate::modules::gateio::parse_gateio_snapshot;
+use crate::modules::kraken::parse_kraken_snapshot;
+use crate::modules::kucoin::parse_kucoin_snapshot;
+use crate::modules::okx::parse_okx_snapshot;
+use crate::modules::types::{
+    AggregatedOrderBook, Exchange, OrderBook, OrderBookUpdate, OrderLevel,
+};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+/// One recorded event of a session, in arrival order
+#[derive(Clone, Debug)]
+pub enum ReplayEvent {
+    /// REST snapshot body as served by the exchange
+    Snapshot { exchange: Exchange, body: String },
+    /// Websocket text frame as received
+    Message { exchange: Exchange, text: String },
+    /// The exchange connection dropped (the live loop reconnects and refetches snapshots)
+    Disconnect { exchange: Exchange },
+}
+
+/// A recorded session plus, optionally, the venue's true book at the end of it
+#[derive(Clone, Debug, Default)]
+pub struct Recording {
+    pub events: Vec<ReplayEvent>,
+    pub expected: Vec<OrderBook>,
+}
+
+pub(crate) fn parse_exchange(v: &Value) -> Result<Exchange, String> {
+    v.get("exchange")
+        .and_then(|e| e.as_str())
+        .ok_or_else(|| "missing exchange".to_string())?
+        .parse()
+}
+
+fn parse_levels(exchange: Exchange, v: Option<&Value>) -> Result<Vec<OrderLevel>, String> {
+    v.and_then(|v| v.as_array())
+        .ok_or("missing levels")?
+        .iter()
+        .map(|level| {
+            let price = level.get(0).and_then(|x| x.as_f64());
+            let amount = level.get(1).and_then(|x| x.as_f64());
+            match (price, amount) {
+                (Some(price), Some(amount)) => Ok(OrderLevel {
+                    exchange,
+                    price,
+                    amount,
+                }),
+                _ => Err(format!("invalid level {}", level)),
+            }
+        })
+        .collect()
+}
+
+/// Parse a JSONL recording. Each line is an object with an `event` of
+/// `snapshot` (with `body`), `message` (with `payload`), `disconnect` or
+/// `expected` (with numeric `bids`/`asks` for the venue's true final book).
+pub fn parse_recording(text: &str) -> Result<Recording, String> {
+    let mut recording = Recording::default();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let err = |e: String| format!("line {}: {}", n + 1, e);
+        let v: Value = serde_json::from_str(line).map_err(|e| err(e.to_string()))?;
+        let exchange = parse_exchange(&v).map_err(err)?;
+        match v.get("event").and_then(|e| e.as_str()) {
+            Some("snapshot") => recording.events.push(ReplayEvent::Snapshot {
+                exchange,
+                body: v
+                    .get("body")
+                    .ok_or_else(|| err("missing body".into()))?
+                    .to_string(),
+            }),
+            Some("message") => recording.events.push(ReplayEvent::Message {
+                exchange,
+                text: v
+                    .get("payload")
+                    .ok_or_else(|| err("missing payload".into()))?
+                    .to_string(),
+            }),
+            Some("disconnect") => recording.events.push(ReplayEvent::Disconnect { exchange }),
+            Some("expected") => recording.expected.push(OrderBook {
+                last_update_id: 0,
+                bids: parse_levels(exchange, v.get("bids")).map_err(err)?,
+                asks: parse_levels(exchange, v.get("asks")).map_err(err)?,
+            }),
+            other => return Err(err(format!("unknown event {:?}", other))),
+        }
+    }
+    Ok(recording)
+}
+
+/// Feed recorded events through the same parse/merge/update path as the live loop
+pub fn replay(book: &mut AggregatedOrderBook, events: &[ReplayEvent]) {
+    // Like the live loop, which connects the stream before fetching the snapshot and only
+    // reads it after the merge: diffs that arrive first wait for the snapshot
+    let mut pending: HashMap<Exchange, Vec<&str>> = HashMap::new();
+    let mut synced: HashSet<Exchange> = HashSet::new();
+    for event in events {
+        match event {
+            ReplayEvent::Snapshot { exchange, body } => {
+                let snapshot = match exchange {
+                    Exchange::Binance | Exchange::BinanceUs => {
+                        parse_binance_snapshot(body, *exchange)
+                    }
+                    Exchange::Bitstamp => parse_bitstamp_snapshot(body),
+                    Exchange::Kraken => parse_kraken_snapshot(body),
+                    Exchange::Coinbase => parse_coinbase_snapshot(body),
+                    Exchange::Okx => parse_okx_snapshot(body),
+                    Exchange::Bybit => parse_bybit_snapshot(body),
+                    Exchange::Kucoin => parse_kucoin_snapshot(body),
+                    Exchange::GateIo => parse_gateio_snapshot(body),
+                    Exchange::Bitfinex => parse_bitfinex_snapshot(body),
+                };
+                match snapshot {
+                    Some(snapshot) => {
+                        book.merge_snapshots(vec![snapshot]);
+                        synced.insert(*exchange);
+                        for text in pending.remove(exchange).unwrap_or_default() {
+                            apply_message(book, *exchange, text);
+                        }
+                    }
+                    None => tracing::error!("Unparseable {} snapshot in replay", exchange.as_str()),
+                }
+            }
+            ReplayEvent::Message { exchange, text } => {
+                if synced.contains(exchange) {
+                    apply_message(book, *exchange, text);
+                } else {
+                    pending.entry(*exchange).or_default().push(text);
+                }
+            }
+            ReplayEvent::Disconnect { exchange } => {
+                tracing::info!("{} disconnected in replay", exchange.as_str());
+                synced.remove(exchange);
+                pending.remove(exchange);
+            }
+        }
+    }
+}
+
+/// The update in a websocket text frame of `exchange`; `None` for acks and anything else
+pub fn parse_update(exchange: Exchange, text: &str) -> Option<OrderBookUpdate> {
+    match exchange {
+        Exchange::Binance => OrderBookUpdate::from_binance_json(text),
+        Exchange::BinanceUs => OrderBookUpdate::from_binance_variant_json(text, BinanceVariant::Us),
+        Exchange::Bitstamp => OrderBookUpdate::from_bitstamp_json(text),
+        Exchange::Kraken => OrderBookUpdate::from_kraken_json(text),
+        Exchange::Coinbase => OrderBookUpdate::from_coinbase_json(text),
+        Exchange::Okx => OrderBookUpdate::from_okx_json(text),
+        Exchange::Bybit => OrderBookUpdate::from_bybit_json(text),
+        Exchange::Kucoin => OrderBookUpdate::from_kucoin_json(text),
+        Exchange::GateIo => OrderBookUpdate::from_gateio_json(text),
+        Exchange::Bitfinex => OrderBookUpdate::from_bitfinex_json(text),
+    }
+}
+
+fn apply_message(book: &mut AggregatedOrderBook, exchange: Exchange, text: &str) {
+    if let Some(update) = parse_update(exchange, text)
+        && let Err(e) = book.handle_update(update)
+    {
+        tracing::error!("{} update failed in replay: {}", exchange.as_str(), e);
+    }
+}
+
+/// One exchange's side of the aggregated book as (price, amount), best first
+pub fn exchange_levels(
+    book: &AggregatedOrderBook,
+    exchange: Exchange,
+    bids: bool,
+) -> Vec<(f64, f64)> {
+    let map = if bids { &book.bids } else { &book.asks };
+    let levels = map
+        .values()
+        .filter_map(|bucket| bucket.get(exchange.as_str()))
+        .map(|level| (level.price, level.amount));
+    if bids {
+        levels.rev().collect()
+    } else {
+        levels.collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_event_kind() {
+        let text = r#"
+{"event":"snapshot","exchange":"binance","body":{"lastUpdateId":5,"bids":[["1.0","2.0"]],"asks":[["1.1","3.0"]]}}
+{"event":"message","exchange":"binance","payload":{"u":6,"b":[["1.0","0"]],"a":[]}}
+{"event":"disconnect","exchange":"binance"}
+{"event":"expected","exchange":"binance","bids":[],"asks":[[1.1,3.0]]}
+"#;
+        let recording = parse_recording(text).unwrap();
+        assert_eq!(recording.events.len(), 3);
+        assert_eq!(recording.expected.len(), 1);
+
+        let mut book = AggregatedOrderBook::new();
+        replay(&mut book, &recording.events);
+        assert!(exchange_levels(&book, Exchange::Binance, true).is_empty());
+        assert_eq!(
+            exchange_levels(&book, Exchange::Binance, false),
+            vec![(1.1, 3.0)]
+        );
+    }
+
+    #[test]
+    fn reports_the_offending_line() {
+        let err = parse_recording("{\"event\":\"nope\",\"exchange\":\"binance\"}").unwrap_err();
+        assert!(err.starts_with("line 1"), "{}", err);
+    }
+}
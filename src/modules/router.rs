@@ -0,0 +1,614 @@
+use crate::modules::binance::BinanceVariant;
+use crate::modules::bitfinex;
+use crate::modules::bitstamp::BitstampChannel;
+use crate::modules::bybit;
+use crate::modules::coinbase;
+use crate::modules::kraken;
+use crate::modules::okx;
+use crate::modules::types::{Exchange, OrderBook, OrderBookUpdate};
+use tokio_tungstenite::tungstenite::Message;
+
+/// Websocket control frames, handled the same way for every exchange
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ControlKind {
+    /// Answered by tungstenite itself
+    Ping,
+    Pong,
+    /// The exchange closed the connection
+    Close,
+    /// The exchange asked for a reconnect ahead of maintenance (Bitstamp
+    /// `bts:request_reconnect`, Bitfinex's restart and maintenance `info` codes);
+    /// subscriptions are made again on the new connection
+    ReconnectRequest,
+}
+
+impl ControlKind {
+    /// Whether the connector must reconnect
+    pub fn ends_connection(&self) -> bool {
+        matches!(self, ControlKind::Close | ControlKind::ReconnectRequest)
+    }
+}
+
+/// What a websocket message from an exchange asks of the connector loop
+#[derive(Debug)]
+pub enum RoutedMessage {
+    /// A book diff, to apply
+    Update(OrderBookUpdate),
+    /// A Bitstamp `order_book_<symbol>` message: the top 100 levels, only cross-checked
+    FullBook(String),
+    /// A book sent on the stream (the first message after subscribing on Kraken, Coinbase, OKX,
+    /// Bybit and Bitfinex), to merge like a REST snapshot: it replaces the exchange's levels
+    Snapshot(OrderBook),
+    Control(ControlKind),
+    /// Nothing to do, e.g. a subscription ack or a binary frame
+    Ignored,
+    /// Text that isn't a valid update, with the reason; sampled into the parse-failure log
+    ParseFailure {
+        reason: String,
+        text: String,
+    },
+}
+
+/// Route a message read from `exchange`'s socket
+pub fn route_message(exchange: Exchange, msg: Message) -> RoutedMessage {
+    let text = match msg {
+        Message::Text(text) => text.to_string(),
+        Message::Ping(_) => return RoutedMessage::Control(ControlKind::Ping),
+        Message::Pong(_) => return RoutedMessage::Control(ControlKind::Pong),
+        Message::Close(_) => return RoutedMessage::Control(ControlKind::Close),
+        Message::Binary(_) | Message::Frame(_) => return RoutedMessage::Ignored,
+    };
+    let parsed = match exchange {
+        Exchange::Bitstamp if text.contains("\"bts:request_reconnect\"") => {
+            return RoutedMessage::Control(ControlKind::ReconnectRequest);
+        }
+        // The substring check keeps diffs from being parsed twice
+        Exchange::Bitstamp
+            if text.contains("\"order_book_")
+                && BitstampChannel::of(&text) == Some(BitstampChannel::FullBook) =>
+        {
+            return RoutedMessage::FullBook(text);
+        }
+        Exchange::Bitstamp => OrderBookUpdate::classify_bitstamp_json(&text),
+        Exchange::Binance => OrderBookUpdate::classify_binance_json(&text, BinanceVariant::Global),
+        Exchange::BinanceUs => OrderBookUpdate::classify_binance_json(&text, BinanceVariant::Us),
+        Exchange::Kraken if kraken::is_book_snapshot(&text) => {
+            match OrderBookUpdate::classify_kraken_json(&text) {
+                Ok(Some(book)) => {
+                    return RoutedMessage::Snapshot(OrderBook {
+                        last_update_id: book.update_id,
+                        bids: book.bids,
+                        asks: book.asks,
+                    });
+                }
+                parsed => parsed,
+            }
+        }
+        Exchange::Kraken => OrderBookUpdate::classify_kraken_json(&text),
+        Exchange::Coinbase if coinbase::is_book_snapshot(&text) => {
+            match OrderBookUpdate::classify_coinbase_json(&text) {
+                Ok(Some(book)) => {
+                    return RoutedMessage::Snapshot(OrderBook {
+                        last_update_id: book.update_id,
+                        bids: book.bids,
+                        asks: book.asks,
+                    });
+                }
+                parsed => parsed,
+            }
+        }
+        Exchange::Coinbase => OrderBookUpdate::classify_coinbase_json(&text),
+        Exchange::Okx if okx::is_book_snapshot(&text) => {
+            match OrderBookUpdate::classify_okx_json(&text) {
+                Ok(Some(book)) => {
+                    return RoutedMessage::Snapshot(OrderBook {
+                        last_update_id: book.update_id,
+                        bids: book.bids,
+                        asks: book.asks,
+                    });
+                }
+                parsed => parsed,
+            }
+        }
+        Exchange::Okx => OrderBookUpdate::classify_okx_json(&text),
+        Exchange::Bybit if bybit::is_book_snapshot(&text) => {
+            match OrderBookUpdate::classify_bybit_json(&text) {
+                Ok(Some(book)) => {
+                    return RoutedMessage::Snapshot(OrderBook {
+                        last_update_id: book.update_id,
+                        bids: book.bids,
+                        asks: book.asks,
+                    });
+                }
+                parsed => parsed,
+            }
+        }
+        Exchange::Bybit => OrderBookUpdate::classify_bybit_json(&text),
+        Exchange::Kucoin => OrderBookUpdate::classify_kucoin_json(&text),
+        Exchange::GateIo => OrderBookUpdate::classify_gateio_json(&text),
+        Exchange::Bitfinex if bitfinex::is_reconnect_request(&text) => {
+            return RoutedMessage::Control(ControlKind::ReconnectRequest);
+        }
+        Exchange::Bitfinex if bitfinex::is_book_snapshot(&text) => {
+            match OrderBookUpdate::classify_bitfinex_json(&text) {
+                Ok(Some(book)) => {
+                    return RoutedMessage::Snapshot(OrderBook {
+                        last_update_id: book.update_id,
+                        bids: book.bids,
+                        asks: book.asks,
+                    });
+                }
+                parsed => parsed,
+            }
+        }
+        Exchange::Bitfinex => OrderBookUpdate::classify_bitfinex_json(&text),
+    };
+    match parsed {
+        Ok(Some(update)) => RoutedMessage::Update(update),
+        Ok(None) => RoutedMessage::Ignored,
+        Err(reason) => RoutedMessage::ParseFailure { reason, text },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_tungstenite::tungstenite::Bytes;
+
+    const EXCHANGES: [Exchange; 10] = [
+        Exchange::Binance,
+        Exchange::BinanceUs,
+        Exchange::Bitstamp,
+        Exchange::Kraken,
+        Exchange::Coinbase,
+        Exchange::Okx,
+        Exchange::Bybit,
+        Exchange::Kucoin,
+        Exchange::GateIo,
+        Exchange::Bitfinex,
+    ];
+
+    fn text(text: &str) -> Message {
+        Message::Text(text.into())
+    }
+
+    #[test]
+    fn control_and_binary_frames_route_the_same_for_every_exchange() {
+        for exchange in EXCHANGES {
+            assert!(matches!(
+                route_message(exchange, Message::Ping(Bytes::from_static(b"hi"))),
+                RoutedMessage::Control(ControlKind::Ping)
+            ));
+            assert!(matches!(
+                route_message(exchange, Message::Pong(Bytes::new())),
+                RoutedMessage::Control(ControlKind::Pong)
+            ));
+            assert!(matches!(
+                route_message(exchange, Message::Close(None)),
+                RoutedMessage::Control(ControlKind::Close)
+            ));
+            assert!(matches!(
+                route_message(exchange, Message::Binary(Bytes::from_static(b"\x00"))),
+                RoutedMessage::Ignored
+            ));
+        }
+        assert!(ControlKind::Close.ends_connection());
+        assert!(ControlKind::ReconnectRequest.ends_connection());
+        assert!(!ControlKind::Ping.ends_connection());
+        assert!(!ControlKind::Pong.ends_connection());
+    }
+
+    #[test]
+    fn binance_text_is_an_update_an_ack_or_a_parse_failure() {
+        let diff = r#"{"e":"depthUpdate","E":5,"U":1,"u":2,"b":[["0.05","1.0"]],"a":[]}"#;
+        for exchange in [Exchange::Binance, Exchange::BinanceUs] {
+            let RoutedMessage::Update(update) = route_message(exchange, text(diff)) else {
+                panic!("not an update");
+            };
+            assert_eq!(update.exchange, exchange);
+            assert_eq!(update.bids[0].exchange, exchange);
+            assert_eq!((update.update_id, update.event_time), (2, Some(5)));
+
+            assert!(matches!(
+                route_message(exchange, text(r#"{"result":null,"id":1}"#)),
+                RoutedMessage::Ignored
+            ));
+            let RoutedMessage::ParseFailure { reason, text } =
+                route_message(exchange, text(r#"{"e":"depthUpdate","u":7}"#))
+            else {
+                panic!("not a parse failure");
+            };
+            assert!(reason.contains("missing b/a"), "{}", reason);
+            assert_eq!(text, r#"{"e":"depthUpdate","u":7}"#);
+        }
+    }
+
+    #[test]
+    fn bitstamp_text_is_routed_by_channel_and_event() {
+        let diff = r#"{"event":"data","channel":"diff_order_book_ethbtc","data":{"microtimestamp":"9","bids":[["0.05","1.0"]],"asks":[]}}"#;
+        let RoutedMessage::Update(update) = route_message(Exchange::Bitstamp, text(diff)) else {
+            panic!("not an update");
+        };
+        assert_eq!((update.exchange, update.update_id), (Exchange::Bitstamp, 9));
+
+        let full = r#"{"event":"data","channel":"order_book_ethbtc","data":{"microtimestamp":"9","bids":[],"asks":[]}}"#;
+        assert!(matches!(
+            route_message(Exchange::Bitstamp, text(full)),
+            RoutedMessage::FullBook(book) if book == full
+        ));
+        assert!(matches!(
+            route_message(
+                Exchange::Bitstamp,
+                text(
+                    r#"{"event":"bts:subscription_succeeded","channel":"diff_order_book_ethbtc","data":{}}"#
+                )
+            ),
+            RoutedMessage::Ignored
+        ));
+        assert!(matches!(
+            route_message(
+                Exchange::Bitstamp,
+                text(r#"{"event":"bts:request_reconnect","channel":"","data":""}"#)
+            ),
+            RoutedMessage::Control(ControlKind::ReconnectRequest)
+        ));
+        assert!(matches!(
+            route_message(Exchange::Bitstamp, text("not json")),
+            RoutedMessage::ParseFailure { .. }
+        ));
+    }
+
+    #[test]
+    fn kraken_books_are_snapshots_and_the_rest_updates() {
+        let snapshot = r#"[336,{"as":[["0.05125","1.5","1700000001.000100"]],"bs":[["0.05120","2.0","1700000000.500000"]]},"book-100","ETH/XBT"]"#;
+        let RoutedMessage::Snapshot(book) = route_message(Exchange::Kraken, text(snapshot)) else {
+            panic!("not a snapshot");
+        };
+        assert_eq!(book.last_update_id, 1_700_000_001_000_100);
+        assert_eq!((book.bids[0].price, book.asks[0].amount), (0.0512, 1.5));
+
+        // A delta can split its sides over two objects and carries a checksum
+        let delta = r#"[336,{"a":[["0.05126","0.00000000","1700000002.000000"]]},{"b":[["0.05121","0.5","1700000003.000000","r"]],"c":"974942666"},"book-100","ETH/XBT"]"#;
+        let RoutedMessage::Update(update) = route_message(Exchange::Kraken, text(delta)) else {
+            panic!("not an update");
+        };
+        assert_eq!(
+            (update.exchange, update.update_id, update.event_time),
+            (
+                Exchange::Kraken,
+                1_700_000_003_000_000,
+                Some(1_700_000_003_000)
+            )
+        );
+        assert_eq!(
+            (update.asks[0].amount, update.bids[0].price),
+            (0.0, 0.05121)
+        );
+        assert_eq!(update.first_update_id, None);
+
+        for event in [
+            r#"{"event":"heartbeat"}"#,
+            r#"{"event":"subscriptionStatus","status":"subscribed","pair":"ETH/XBT"}"#,
+        ] {
+            assert!(matches!(
+                route_message(Exchange::Kraken, text(event)),
+                RoutedMessage::Ignored
+            ));
+        }
+        assert!(matches!(
+            route_message(Exchange::Kraken, text(r#"[336,"book-100","ETH/XBT"]"#)),
+            RoutedMessage::ParseFailure { .. }
+        ));
+    }
+
+    #[test]
+    fn coinbase_changes_are_split_by_side() {
+        let snapshot = r#"{"type":"snapshot","product_id":"ETH-BTC","bids":[["0.05120","2.0"]],"asks":[["0.05125","1.5"]]}"#;
+        let RoutedMessage::Snapshot(book) = route_message(Exchange::Coinbase, text(snapshot))
+        else {
+            panic!("not a snapshot");
+        };
+        // The channel's snapshot has no time; the connector loop stamps it on arrival
+        assert_eq!(book.last_update_id, 0);
+        assert_eq!((book.bids[0].price, book.asks[0].amount), (0.0512, 1.5));
+
+        let l2update = r#"{"type":"l2update","product_id":"ETH-BTC","changes":[["buy","0.05121","0.5"],["sell","0.05125","0.00000000"],["buy","0.05119","1.25"]],"time":"2023-11-14T22:13:20.000100Z"}"#;
+        let RoutedMessage::Update(update) = route_message(Exchange::Coinbase, text(l2update))
+        else {
+            panic!("not an update");
+        };
+        assert_eq!(
+            (update.exchange, update.update_id, update.event_time),
+            (
+                Exchange::Coinbase,
+                1_700_000_000_000_100,
+                Some(1_700_000_000_000)
+            )
+        );
+        assert_eq!(
+            update.bids.iter().map(|l| l.price).collect::<Vec<_>>(),
+            vec![0.05121, 0.05119]
+        );
+        assert_eq!((update.asks.len(), update.asks[0].amount), (1, 0.0));
+        assert_eq!(update.first_update_id, None);
+
+        for event in [
+            r#"{"type":"subscriptions","channels":[{"name":"level2_batch","product_ids":["ETH-BTC"]}]}"#,
+            r#"{"type":"heartbeat","sequence":90,"product_id":"ETH-BTC"}"#,
+        ] {
+            assert!(matches!(
+                route_message(Exchange::Coinbase, text(event)),
+                RoutedMessage::Ignored
+            ));
+        }
+        for failure in [
+            r#"{"type":"error","message":"Failed to subscribe","reason":"ETH-XYZ is not a valid product"}"#,
+            r#"{"type":"l2update","product_id":"ETH-BTC","changes":[["hold","0.05","1"]],"time":"2023-11-14T22:13:20Z"}"#,
+            r#"{"type":"l2update","product_id":"ETH-BTC","changes":[]}"#,
+        ] {
+            assert!(matches!(
+                route_message(Exchange::Coinbase, text(failure)),
+                RoutedMessage::ParseFailure { .. }
+            ));
+        }
+    }
+
+    #[test]
+    fn okx_updates_are_sequenced_by_seq_id() {
+        let snapshot = r#"{"arg":{"channel":"books","instId":"ETH-BTC"},"action":"snapshot","data":[{"asks":[["0.05125","1.5","0","2"]],"bids":[["0.05120","2.0","0","1"]],"ts":"1700000000000","checksum":-1200119424,"prevSeqId":-1,"seqId":123456}]}"#;
+        let RoutedMessage::Snapshot(book) = route_message(Exchange::Okx, text(snapshot)) else {
+            panic!("not a snapshot");
+        };
+        assert_eq!(book.last_update_id, 123_456);
+        assert_eq!((book.bids[0].price, book.asks[0].amount), (0.0512, 1.5));
+
+        let update = r#"{"arg":{"channel":"books","instId":"ETH-BTC"},"action":"update","data":[{"asks":[["0.05125","0","0","0"]],"bids":[["0.05121","0.5","0","1"]],"ts":"1700000000100","checksum":12,"prevSeqId":123456,"seqId":123460}]}"#;
+        let RoutedMessage::Update(update) = route_message(Exchange::Okx, text(update)) else {
+            panic!("not an update");
+        };
+        assert_eq!(
+            (
+                update.exchange,
+                update.first_update_id,
+                update.update_id,
+                update.event_time
+            ),
+            (
+                Exchange::Okx,
+                Some(123_457),
+                123_460,
+                Some(1_700_000_000_100)
+            )
+        );
+        assert_eq!(
+            (update.bids[0].price, update.asks[0].amount),
+            (0.05121, 0.0)
+        );
+
+        for event in [
+            r#"{"event":"subscribe","arg":{"channel":"books","instId":"ETH-BTC"},"connId":"a4d3ae55"}"#,
+            // Sent when nothing changed, to show the connection is alive
+            r#"{"arg":{"channel":"books","instId":"ETH-BTC"},"action":"update","data":[{"asks":[],"bids":[],"ts":"1700000000200","checksum":12,"prevSeqId":123460,"seqId":123460}]}"#,
+        ] {
+            assert!(matches!(
+                route_message(Exchange::Okx, text(event)),
+                RoutedMessage::Ignored
+            ));
+        }
+        for failure in [
+            r#"{"event":"error","code":"60018","msg":"Wrong URL or channel:books,instId:ETH-XYZ doesn't exist.","connId":"a4d3ae55"}"#,
+            r#"{"arg":{"channel":"books","instId":"ETH-BTC"},"action":"update","data":[{"asks":[],"bids":[["0.05","1","0","1"]],"ts":"1700000000000"}]}"#,
+            r#"{"arg":{"channel":"books","instId":"ETH-BTC"},"data":[]}"#,
+        ] {
+            assert!(matches!(
+                route_message(Exchange::Okx, text(failure)),
+                RoutedMessage::ParseFailure { .. }
+            ));
+        }
+    }
+
+    #[test]
+    fn bybit_deltas_are_sequenced_by_u() {
+        let snapshot = r#"{"topic":"orderbook.50.ETHBTC","type":"snapshot","ts":1700000000000,"data":{"s":"ETHBTC","b":[["0.05120","2.0"]],"a":[["0.05125","1.5"]],"u":200,"seq":7961638724},"cts":1699999999998}"#;
+        let RoutedMessage::Snapshot(book) = route_message(Exchange::Bybit, text(snapshot)) else {
+            panic!("not a snapshot");
+        };
+        assert_eq!(book.last_update_id, 200);
+        assert_eq!((book.bids[0].price, book.asks[0].amount), (0.0512, 1.5));
+
+        let delta = r#"{"topic":"orderbook.50.ETHBTC","type":"delta","ts":1700000000020,"data":{"s":"ETHBTC","b":[["0.05121","0.5"]],"a":[["0.05125","0"]],"u":201,"seq":7961638730},"cts":1700000000018}"#;
+        let RoutedMessage::Update(update) = route_message(Exchange::Bybit, text(delta)) else {
+            panic!("not an update");
+        };
+        assert_eq!(
+            (
+                update.exchange,
+                update.first_update_id,
+                update.update_id,
+                update.event_time
+            ),
+            (Exchange::Bybit, Some(201), 201, Some(1_700_000_000_020))
+        );
+        assert_eq!(
+            (update.bids[0].price, update.asks[0].amount),
+            (0.05121, 0.0)
+        );
+
+        for reply in [
+            r#"{"success":true,"ret_msg":"","conn_id":"cejreassvfrsfvb9v1a0-2m","op":"subscribe"}"#,
+            r#"{"success":true,"ret_msg":"pong","conn_id":"cejreassvfrsfvb9v1a0-2m","op":"ping"}"#,
+        ] {
+            assert!(matches!(
+                route_message(Exchange::Bybit, text(reply)),
+                RoutedMessage::Ignored
+            ));
+        }
+        for failure in [
+            r#"{"success":false,"ret_msg":"error:handler not found,topic:orderbook.50.ETHXYZ","conn_id":"1","op":"subscribe"}"#,
+            r#"{"topic":"orderbook.50.ETHBTC","type":"delta","ts":1700000000020,"data":{"s":"ETHBTC","b":[],"a":[]}}"#,
+            r#"{"topic":"orderbook.50.ETHBTC","data":{"u":1}}"#,
+        ] {
+            assert!(matches!(
+                route_message(Exchange::Bybit, text(failure)),
+                RoutedMessage::ParseFailure { .. }
+            ));
+        }
+    }
+
+    #[test]
+    fn kucoin_deltas_carry_their_sequence_range() {
+        let delta = r#"{"type":"message","topic":"/market/level2:ETH-BTC","subject":"trade.l2update","data":{"changes":{"asks":[["0.05125","0","3262786981"]],"bids":[["0.05121","0.5","3262786980"]]},"sequenceEnd":3262786981,"sequenceStart":3262786980,"symbol":"ETH-BTC","time":1700000000020}}"#;
+        let RoutedMessage::Update(update) = route_message(Exchange::Kucoin, text(delta)) else {
+            panic!("not an update");
+        };
+        assert_eq!(
+            (
+                update.exchange,
+                update.first_update_id,
+                update.update_id,
+                update.event_time
+            ),
+            (
+                Exchange::Kucoin,
+                Some(3_262_786_980),
+                3_262_786_981,
+                Some(1_700_000_000_020)
+            )
+        );
+        assert_eq!(
+            (update.bids[0].price, update.asks[0].amount),
+            (0.05121, 0.0)
+        );
+
+        for notice in [
+            r#"{"id":"hQvf8jkno","type":"welcome"}"#,
+            r#"{"id":"/market/level2:ETH-BTC","type":"ack"}"#,
+            r#"{"id":"1","type":"pong"}"#,
+        ] {
+            assert!(matches!(
+                route_message(Exchange::Kucoin, text(notice)),
+                RoutedMessage::Ignored
+            ));
+        }
+        for failure in [
+            r#"{"id":"/market/level2:XYZ-BTC","type":"error","code":404,"data":"topic /market/level2:XYZ-BTC is not found"}"#,
+            r#"{"type":"message","topic":"/market/level2:ETH-BTC","subject":"trade.l2update","data":{"changes":{"asks":[],"bids":[]},"sequenceEnd":3}}"#,
+            r#"{"type":"message","topic":"/market/ticker:ETH-BTC","subject":"trade.ticker","data":{}}"#,
+        ] {
+            assert!(matches!(
+                route_message(Exchange::Kucoin, text(failure)),
+                RoutedMessage::ParseFailure { .. }
+            ));
+        }
+    }
+
+    #[test]
+    fn gateio_updates_carry_their_id_range() {
+        let update = r#"{"time":1700000000,"time_ms":1700000000020,"channel":"spot.order_book_update","event":"update","result":{"t":1700000000020,"e":"depthUpdate","E":1700000000,"s":"ETH_BTC","U":8613227386,"u":8613227388,"b":[["0.05121","0.5"]],"a":[["0.05125","0"]]}}"#;
+        let RoutedMessage::Update(update) = route_message(Exchange::GateIo, text(update)) else {
+            panic!("not an update");
+        };
+        assert_eq!(
+            (
+                update.exchange,
+                update.first_update_id,
+                update.update_id,
+                update.event_time
+            ),
+            (
+                Exchange::GateIo,
+                Some(8_613_227_386),
+                8_613_227_388,
+                Some(1_700_000_000_020)
+            )
+        );
+        assert_eq!(
+            (update.bids[0].price, update.asks[0].amount),
+            (0.05121, 0.0)
+        );
+
+        for notice in [
+            r#"{"time":1700000000,"channel":"spot.order_book_update","event":"subscribe","error":null,"result":{"status":"success"}}"#,
+            r#"{"time":1700000000,"channel":"spot.pong","event":"","result":null}"#,
+        ] {
+            assert!(matches!(
+                route_message(Exchange::GateIo, text(notice)),
+                RoutedMessage::Ignored
+            ));
+        }
+        for failure in [
+            r#"{"time":1700000000,"channel":"spot.order_book_update","event":"subscribe","error":{"code":2,"message":"unknown currency pair XYZ_BTC"},"result":null}"#,
+            r#"{"time":1700000000,"channel":"spot.order_book_update","event":"update","result":{"t":1,"s":"ETH_BTC","u":3,"b":[],"a":[]}}"#,
+            r#"{"time":1700000000,"channel":"spot.trades","event":"update","result":{}}"#,
+        ] {
+            assert!(matches!(
+                route_message(Exchange::GateIo, text(failure)),
+                RoutedMessage::ParseFailure { .. }
+            ));
+        }
+    }
+
+    #[test]
+    fn bitfinex_frames_are_positional_with_the_timestamp_as_id() {
+        let update = "[17470,[0.05125,0,-1],1700000000020]";
+        let RoutedMessage::Update(update) = route_message(Exchange::Bitfinex, text(update)) else {
+            panic!("not an update");
+        };
+        assert_eq!(
+            (
+                update.exchange,
+                update.first_update_id,
+                update.update_id,
+                update.event_time
+            ),
+            (
+                Exchange::Bitfinex,
+                None,
+                1_700_000_000_020,
+                Some(1_700_000_000_020)
+            )
+        );
+        assert!(update.bids.is_empty());
+        assert_eq!(
+            (update.asks[0].price, update.asks[0].amount),
+            (0.05125, 0.0)
+        );
+
+        let snapshot = "[17470,[[0.0512,2,1.5],[0.05125,1,-0.75]],1700000000000]";
+        let RoutedMessage::Snapshot(book) = route_message(Exchange::Bitfinex, text(snapshot))
+        else {
+            panic!("not a snapshot");
+        };
+        assert_eq!(book.last_update_id, 1_700_000_000_000);
+        assert_eq!((book.bids[0].amount, book.asks[0].amount), (1.5, 0.75));
+
+        for notice in [
+            r#"{"event":"info","version":2,"serverId":"1","platform":{"status":1}}"#,
+            r#"{"event":"conf","status":"OK","flags":32768}"#,
+            r#"{"event":"subscribed","channel":"book","chanId":17470,"symbol":"tETHBTC","prec":"P0","freq":"F0","len":"100","pair":"ETHBTC"}"#,
+            r#"[17470,"hb",1700000000050]"#,
+        ] {
+            assert!(matches!(
+                route_message(Exchange::Bitfinex, text(notice)),
+                RoutedMessage::Ignored
+            ));
+        }
+        assert!(matches!(
+            route_message(
+                Exchange::Bitfinex,
+                text(r#"{"event":"info","code":20051,"msg":"Stopping. Please try to reconnect"}"#)
+            ),
+            RoutedMessage::Control(ControlKind::ReconnectRequest)
+        ));
+        for failure in [
+            r#"{"event":"error","msg":"symbol: invalid","code":10300}"#,
+            "[17470,[0.05125,1,-1]]",
+            "[17470,[0.05125,1,0],1700000000020]",
+        ] {
+            assert!(matches!(
+                route_message(Exchange::Bitfinex, text(failure)),
+                RoutedMessage::ParseFailure { .. }
+            ));
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use serde::Deserialize;
+
+/// When an update id below the last applied one is taken as the exchange restarting its
+/// sequence, e.g. after maintenance, rather than as a stale update. Set per exchange under
+/// `sequence_reset.<exchange>` in the config file.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct SequenceResetConfig {
+    pub enabled: bool,
+    /// The id must be more than this below the last applied one
+    pub min_drop: u64,
+    /// ...and below the last applied one divided by this
+    pub min_factor: f64,
+}
+
+impl Default for SequenceResetConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_drop: 1_000,
+            min_factor: 2.0,
+        }
+    }
+}
+
+impl SequenceResetConfig {
+    /// Whether an update with `update_id`, sent by the exchange at `event_time` (unix
+    /// millis), restarts the sequence. The id must have dropped far enough below `last_id`,
+    /// and the update must be newer than `freshest`, the newest event time applied so far.
+    /// Without both event times a backwards id is never a reset.
+    pub fn is_reset(
+        &self,
+        last_id: u64,
+        update_id: u64,
+        event_time: Option<u64>,
+        freshest: Option<u64>,
+    ) -> bool {
+        let dropped = update_id < last_id
+            && last_id - update_id > self.min_drop
+            && (update_id as f64) < last_id as f64 / self.min_factor;
+        let newer = matches!((event_time, freshest), (Some(at), Some(freshest)) if at > freshest);
+        self.enabled && dropped && newer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_a_large_drop_with_newer_data_is_a_reset() {
+        let config = SequenceResetConfig::default();
+        // Stream ids restarting from zero, with a newer event time
+        assert!(config.is_reset(5_000_000, 12, Some(2_000), Some(1_000)));
+
+        // A small backward glitch stays stale
+        assert!(!config.is_reset(5_000_000, 4_999_990, Some(2_000), Some(1_000)));
+        // Far down in absolute terms but not by the factor
+        assert!(!config.is_reset(5_000_000, 3_000_000, Some(2_000), Some(1_000)));
+        // Not newer than what was applied: a replay of old data, not a reset
+        assert!(!config.is_reset(5_000_000, 12, Some(1_000), Some(1_000)));
+        assert!(!config.is_reset(5_000_000, 12, None, Some(1_000)));
+        assert!(!config.is_reset(5_000_000, 12, Some(2_000), None));
+
+        let disabled = SequenceResetConfig {
+            enabled: false,
+            ..config
+        };
+        assert!(!disabled.is_reset(5_000_000, 12, Some(2_000), Some(1_000)));
+        let strict = SequenceResetConfig {
+            min_drop: 10_000_000,
+            ..config
+        };
+        assert!(!strict.is_reset(5_000_000, 12, Some(2_000), Some(1_000)));
+    }
+}
@@ -0,0 +1,237 @@
+use crate::modules::aggregated_orderbook::MergeStats;
+use crate::modules::status::StatusRegistry;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Why the process is exiting; each kind has its own documented exit code
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExitReason {
+    /// Stdin closed in stdio mode, or SIGINT/SIGTERM
+    Clean(String),
+    /// Invalid config file, arguments or symbol
+    Config(String),
+    /// The connectors stopped, panicked or never produced a book
+    Connector(String),
+    /// A gRPC listener could not be bound
+    GrpcBind(String),
+    /// No exchange synced within the startup deadline
+    StartupDeadline(String),
+    /// Anything else, e.g. a server that stopped after it started
+    Other(String),
+}
+
+impl ExitReason {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            ExitReason::Clean(_) => 0,
+            ExitReason::Other(_) => 1,
+            ExitReason::Config(_) => 2,
+            ExitReason::Connector(_) => 3,
+            ExitReason::GrpcBind(_) => 4,
+            ExitReason::StartupDeadline(_) => 5,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            ExitReason::Clean(cause) => format!("clean shutdown ({})", cause),
+            ExitReason::Config(e) => format!("config error: {}", e),
+            ExitReason::Connector(e) => format!("fatal connector error: {}", e),
+            ExitReason::GrpcBind(e) => format!("gRPC bind failure: {}", e),
+            ExitReason::StartupDeadline(e) => format!("startup deadline missed: {}", e),
+            ExitReason::Other(e) => format!("error: {}", e),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ExchangeCounts {
+    /// Text messages received, including ones that failed to parse
+    pub messages: u64,
+    pub applied: u64,
+    /// Stale, quarantined or rejected updates
+    pub ignored: u64,
+    pub reconnects: u64,
+    /// Snapshots merged into the book, and the entries they inserted, replaced or removed
+    pub snapshots: u64,
+    pub snapshot_levels: u64,
+    /// Snapshots skipped, e.g. as older than data already applied
+    pub snapshots_skipped: u64,
+}
+
+/// Lifetime counters for the shutdown report
+#[derive(Debug, Default)]
+pub struct RunCounters {
+    exchanges: Mutex<BTreeMap<String, ExchangeCounts>>,
+    /// Summary streams opened over gRPC or stdio
+    pub streams_served: AtomicU64,
+}
+
+impl RunCounters {
+    pub fn record_message(&self, exchange: &str) {
+        self.update(exchange, |counts| counts.messages += 1);
+    }
+
+    pub fn record_update(&self, exchange: &str, applied: bool) {
+        self.update(exchange, |counts| {
+            if applied {
+                counts.applied += 1;
+            } else {
+                counts.ignored += 1;
+            }
+        });
+    }
+
+    pub fn record_reconnect(&self, exchange: &str) {
+        self.update(exchange, |counts| counts.reconnects += 1);
+    }
+
+    pub fn record_merge(&self, exchange: &str, stats: &MergeStats) {
+        self.update(exchange, |counts| {
+            if stats.skipped_reason.is_some() {
+                counts.snapshots_skipped += 1;
+            } else {
+                counts.snapshots += 1;
+                counts.snapshot_levels += stats.inserted + stats.replaced + stats.removed;
+            }
+        });
+    }
+
+    pub fn exchanges(&self) -> BTreeMap<String, ExchangeCounts> {
+        self.exchanges.lock().unwrap().clone()
+    }
+
+    fn update(&self, exchange: &str, f: impl FnOnce(&mut ExchangeCounts)) {
+        let mut exchanges = self.exchanges.lock().unwrap();
+        f(exchanges.entry(exchange.to_string()).or_default());
+    }
+}
+
+/// Summary logged as the last lines before exit
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShutdownReport {
+    pub runtime: Duration,
+    pub exchanges: BTreeMap<String, ExchangeCounts>,
+    pub streams_served: u64,
+    pub reason: ExitReason,
+}
+
+impl ShutdownReport {
+    pub fn collect(status: &StatusRegistry, runtime: Duration, reason: ExitReason) -> Self {
+        Self {
+            runtime,
+            exchanges: status.counters.exchanges(),
+            streams_served: status.counters.streams_served.load(Ordering::Relaxed),
+            reason,
+        }
+    }
+
+    pub fn exit_code(&self) -> u8 {
+        self.reason.exit_code()
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "Shutdown after {}: {} gRPC/stdio stream(s) served",
+            format_runtime(self.runtime),
+            self.streams_served
+        )];
+        for (exchange, counts) in &self.exchanges {
+            lines.push(format!(
+                "  {}: {} messages, {} applied, {} ignored, {} reconnects, {} snapshots ({} skipped)",
+                exchange,
+                counts.messages,
+                counts.applied,
+                counts.ignored,
+                counts.reconnects,
+                counts.snapshots,
+                counts.snapshots_skipped
+            ));
+        }
+        lines.push(format!(
+            "Exit code {}: {}",
+            self.exit_code(),
+            self.reason.describe()
+        ));
+        lines
+    }
+}
+
+/// `1h02m03s`, `2m05s` or `7.250s`
+pub fn format_runtime(runtime: Duration) -> String {
+    let secs = runtime.as_secs();
+    match (secs / 3600, secs % 3600 / 60, secs % 60) {
+        (0, 0, _) => format!("{:.3}s", runtime.as_secs_f64()),
+        (0, m, s) => format!("{}m{:02}s", m, s),
+        (h, m, s) => format!("{}h{:02}m{:02}s", h, m, s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::aggregated_orderbook::SkipReason;
+
+    #[test]
+    fn each_exit_reason_maps_to_its_documented_code() {
+        let e = || "boom".to_string();
+        assert_eq!(ExitReason::Clean(e()).exit_code(), 0);
+        assert_eq!(ExitReason::Other(e()).exit_code(), 1);
+        assert_eq!(ExitReason::Config(e()).exit_code(), 2);
+        assert_eq!(ExitReason::Connector(e()).exit_code(), 3);
+        assert_eq!(ExitReason::GrpcBind(e()).exit_code(), 4);
+        assert_eq!(ExitReason::StartupDeadline(e()).exit_code(), 5);
+    }
+
+    #[test]
+    fn report_lists_runtime_exchange_counts_and_reason() {
+        let status = StatusRegistry::default();
+        for _ in 0..3 {
+            status.counters.record_message("binance");
+        }
+        status.counters.record_update("binance", true);
+        status.counters.record_update("binance", true);
+        status.counters.record_update("binance", false);
+        status.counters.record_reconnect("bitstamp");
+        let merged = MergeStats {
+            inserted: 4,
+            replaced: 1,
+            ..Default::default()
+        };
+        status.counters.record_merge("bitstamp", &merged);
+        let skipped = MergeStats {
+            skipped_reason: Some(SkipReason::Stale {
+                applied: 10,
+                snapshot: 9,
+            }),
+            ..Default::default()
+        };
+        status.counters.record_merge("bitstamp", &skipped);
+        status
+            .counters
+            .streams_served
+            .fetch_add(2, Ordering::Relaxed);
+
+        let report = ShutdownReport::collect(
+            &status,
+            Duration::from_secs(3723),
+            ExitReason::Connector("connector task panicked".to_string()),
+        );
+        assert_eq!(report.exit_code(), 3);
+        assert_eq!(
+            report.lines(),
+            vec![
+                "Shutdown after 1h02m03s: 2 gRPC/stdio stream(s) served",
+                "  binance: 3 messages, 2 applied, 1 ignored, 0 reconnects, 0 snapshots (0 skipped)",
+                "  bitstamp: 0 messages, 0 applied, 0 ignored, 1 reconnects, 1 snapshots (1 skipped)",
+                "Exit code 3: fatal connector error: connector task panicked",
+            ]
+        );
+        assert_eq!(report.exchanges["bitstamp"].snapshot_levels, 5);
+
+        assert_eq!(format_runtime(Duration::from_millis(7_250)), "7.250s");
+        assert_eq!(format_runtime(Duration::from_secs(125)), "2m05s");
+    }
+}
@@ -0,0 +1,38 @@
+/// The "smart best" of one side: the best price whose cumulative size, summed from the top
+/// across exchanges, reaches `min_qty`, so a dust order at a marginally better price
+/// doesn't define the published best. `levels` are `(price, total amount at that price)`,
+/// best first. `None` if the whole side adds up to less than `min_qty`.
+pub fn smart_best(levels: impl IntoIterator<Item = (f64, f64)>, min_qty: f64) -> Option<f64> {
+    let mut cumulative = 0.0;
+    levels.into_iter().find_map(|(price, amount)| {
+        cumulative += amount;
+        (cumulative >= min_qty).then_some(price)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_raw_best_qualifies_when_it_is_large_enough() {
+        let bids = [(100.0, 5.0), (99.0, 1.0)];
+        assert_eq!(smart_best(bids, 5.0), Some(100.0));
+        assert_eq!(smart_best(bids, 0.1), Some(100.0));
+    }
+
+    #[test]
+    fn dust_levels_are_skipped_until_the_cumulative_size_is_reached() {
+        let asks = [(101.0, 0.01), (101.5, 0.02), (102.0, 0.5), (103.0, 2.0)];
+        // 0.01 + 0.02 + 0.5 reaches 0.5 at the third price
+        assert_eq!(smart_best(asks, 0.5), Some(102.0));
+        assert_eq!(smart_best(asks, 0.53), Some(102.0));
+        assert_eq!(smart_best(asks, 0.54), Some(103.0));
+    }
+
+    #[test]
+    fn nothing_qualifies_when_the_side_is_too_thin() {
+        assert_eq!(smart_best([(100.0, 0.1), (99.0, 0.2)], 1.0), None);
+        assert_eq!(smart_best([], 1.0), None);
+    }
+}
@@ -0,0 +1,499 @@
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{oneshot, watch};
+
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 4;
+
+/// Fetches run in this order when an exchange's slots are full
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FetchPriority {
+    /// The stream is already connected and buffering diffs until the snapshot lands
+    Connected,
+    Disconnected,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncProgress {
+    Queued,
+    Fetching,
+    Synced,
+    Failed,
+}
+
+#[derive(Debug, Default)]
+struct ExchangeQueue {
+    in_flight: usize,
+    waiting: BTreeMap<(FetchPriority, u64), oneshot::Sender<FetchPermit>>,
+}
+
+/// Result of a fetch, shared with the requests that joined it
+type SharedResult = Arc<dyn Any + Send + Sync>;
+
+/// A fetch other requests for the same (exchange, symbol) can still join
+#[derive(Debug)]
+struct Joinable {
+    id: u64,
+    started: bool,
+    result: watch::Receiver<Option<SharedResult>>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    queues: HashMap<String, ExchangeQueue>,
+    next_seq: u64,
+    progress: BTreeMap<(String, String), SyncProgress>,
+    joinable: HashMap<(String, String), Joinable>,
+    coalesced: HashMap<String, u64>,
+}
+
+/// Bounds concurrent REST snapshot fetches per exchange, so a startup or mass resync over
+/// many symbols doesn't trip rate limits or hold dozens of full-depth payloads at once.
+/// Waiting fetches start by priority, then in arrival order. A fetch requested while
+/// another for the same (exchange, symbol) has not started its REST call yet shares that
+/// call's result, so a flap that triggers several resyncs costs one call per feed.
+#[derive(Debug)]
+pub struct SnapshotCoordinator {
+    max_in_flight: usize,
+    coalesce_window: Duration,
+    inner: Mutex<Inner>,
+}
+
+impl Default for SnapshotCoordinator {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_IN_FLIGHT)
+    }
+}
+
+/// One in-flight slot for an exchange; dropping it hands the slot to the next waiter
+#[derive(Debug)]
+struct FetchPermit {
+    coordinator: Option<Arc<SnapshotCoordinator>>,
+    exchange: String,
+}
+
+impl Drop for FetchPermit {
+    fn drop(&mut self) {
+        if let Some(coordinator) = self.coordinator.take() {
+            coordinator.release(&self.exchange);
+        }
+    }
+}
+
+/// Stops later requests from joining a fetch once it ends, however it ends
+struct JoinableGuard<'a> {
+    coordinator: &'a SnapshotCoordinator,
+    key: (String, String),
+    id: u64,
+}
+
+impl Drop for JoinableGuard<'_> {
+    fn drop(&mut self) {
+        let mut inner = self.coordinator.inner.lock().unwrap();
+        if inner
+            .joinable
+            .get(&self.key)
+            .is_some_and(|j| j.id == self.id)
+        {
+            inner.joinable.remove(&self.key);
+        }
+    }
+}
+
+impl SnapshotCoordinator {
+    pub fn new(max_in_flight: usize) -> Self {
+        Self {
+            max_in_flight: max_in_flight.max(1),
+            coalesce_window: Duration::ZERO,
+            inner: Mutex::default(),
+        }
+    }
+
+    /// Hold each fetch back this long before it queues, so requests for the same feed made
+    /// meanwhile join it (0 by default: only requests made while it queues join)
+    pub fn with_coalesce_window(mut self, window: Duration) -> Self {
+        self.coalesce_window = window;
+        self
+    }
+
+    /// Run `fetch` once a slot for `exchange` is free, recording the symbol's progress, or
+    /// share the result of a fetch for the same symbol that has not started yet
+    pub async fn fetch<T>(
+        self: &Arc<Self>,
+        exchange: &str,
+        symbol: &str,
+        priority: FetchPriority,
+        fetch: impl Future<Output = Result<T, String>>,
+    ) -> Result<T, String>
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        let key = (exchange.to_string(), symbol.to_string());
+        if let Some(result) = self.join(&key).await {
+            return result;
+        }
+        let (tx, rx) = watch::channel(None);
+        let id = {
+            let mut inner = self.inner.lock().unwrap();
+            let id = inner.next_seq;
+            inner.next_seq += 1;
+            let joinable = Joinable {
+                id,
+                started: false,
+                result: rx,
+            };
+            inner.joinable.insert(key.clone(), joinable);
+            id
+        };
+        let _joinable = JoinableGuard {
+            coordinator: self,
+            key: key.clone(),
+            id,
+        };
+        if !self.coalesce_window.is_zero() {
+            tokio::time::sleep(self.coalesce_window).await;
+        }
+
+        let _permit = self.acquire(exchange, symbol, priority).await;
+        if let Some(joinable) = self.inner.lock().unwrap().joinable.get_mut(&key) {
+            joinable.started = true;
+        }
+        self.set_progress(exchange, symbol, SyncProgress::Fetching);
+        let result = fetch.await;
+        let progress = match result {
+            Ok(_) => SyncProgress::Synced,
+            Err(_) => SyncProgress::Failed,
+        };
+        self.set_progress(exchange, symbol, progress);
+        tx.send_replace(Some(Arc::new(result.clone())));
+        result
+    }
+
+    /// Requests for `exchange` that shared another request's fetch since startup
+    pub fn coalesced(&self, exchange: &str) -> u64 {
+        let inner = self.inner.lock().unwrap();
+        inner.coalesced.get(exchange).copied().unwrap_or(0)
+    }
+
+    /// Wait for the result of a joinable fetch for `key`; `None` if there is none, or it
+    /// was cancelled before finishing
+    async fn join<T: Clone + 'static>(&self, key: &(String, String)) -> Option<Result<T, String>> {
+        let mut result = {
+            let inner = self.inner.lock().unwrap();
+            let joinable = inner.joinable.get(key).filter(|j| !j.started)?;
+            joinable.result.clone()
+        };
+        let shared = result.wait_for(Option::is_some).await.ok()?.clone()?;
+        let result = shared.downcast_ref::<Result<T, String>>()?.clone();
+        let mut inner = self.inner.lock().unwrap();
+        *inner.coalesced.entry(key.0.clone()).or_default() += 1;
+        tracing::debug!(
+            exchange = key.0,
+            symbol = key.1,
+            "Snapshot fetch joined one already requested"
+        );
+        Some(result)
+    }
+
+    /// Latest progress per (exchange, symbol), sorted
+    pub fn progress(&self) -> Vec<((String, String), SyncProgress)> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .progress
+            .iter()
+            .map(|(key, progress)| (key.clone(), *progress))
+            .collect()
+    }
+
+    pub fn in_flight(&self, exchange: &str) -> usize {
+        let inner = self.inner.lock().unwrap();
+        inner.queues.get(exchange).map_or(0, |q| q.in_flight)
+    }
+
+    async fn acquire(
+        self: &Arc<Self>,
+        exchange: &str,
+        symbol: &str,
+        priority: FetchPriority,
+    ) -> FetchPermit {
+        let waiting = {
+            let mut inner = self.inner.lock().unwrap();
+            let seq = inner.next_seq;
+            inner.next_seq += 1;
+            inner.progress.insert(
+                (exchange.to_string(), symbol.to_string()),
+                SyncProgress::Queued,
+            );
+            let queue = inner.queues.entry(exchange.to_string()).or_default();
+            if queue.in_flight < self.max_in_flight && queue.waiting.is_empty() {
+                queue.in_flight += 1;
+                None
+            } else {
+                let (tx, rx) = oneshot::channel();
+                queue.waiting.insert((priority, seq), tx);
+                Some(rx)
+            }
+        };
+        match waiting {
+            // The sender is only dropped after handing over a permit
+            Some(rx) => rx.await.expect("snapshot fetch queue dropped a waiter"),
+            None => FetchPermit {
+                coordinator: Some(Arc::clone(self)),
+                exchange: exchange.to_string(),
+            },
+        }
+    }
+
+    fn release(self: &Arc<Self>, exchange: &str) {
+        loop {
+            let next = {
+                let mut inner = self.inner.lock().unwrap();
+                let queue = inner.queues.get_mut(exchange).unwrap();
+                match queue.waiting.pop_first() {
+                    Some((_, next)) => next,
+                    None => {
+                        queue.in_flight -= 1;
+                        return;
+                    }
+                }
+            };
+            // Sent outside the lock: a permit dropped by a cancelled waiter releases again
+            let permit = FetchPermit {
+                coordinator: Some(Arc::clone(self)),
+                exchange: exchange.to_string(),
+            };
+            match next.send(permit) {
+                Ok(()) => return,
+                Err(mut unclaimed) => unclaimed.coordinator = None,
+            }
+        }
+    }
+
+    fn set_progress(&self, exchange: &str, symbol: &str, progress: SyncProgress) {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .progress
+            .insert((exchange.to_string(), symbol.to_string()), progress);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// Stands in for a REST snapshot call, recording how many run at once
+    #[derive(Default)]
+    struct MockFetcher {
+        current: AtomicUsize,
+        peak: AtomicUsize,
+        order: Mutex<Vec<String>>,
+    }
+
+    impl MockFetcher {
+        async fn fetch(&self, symbol: &str) -> Result<u64, String> {
+            let now = self.current.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(now, Ordering::SeqCst);
+            self.order.lock().unwrap().push(symbol.to_string());
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            self.current.fetch_sub(1, Ordering::SeqCst);
+            Ok(1_000)
+        }
+    }
+
+    async fn wait_until_queued(coordinator: &SnapshotCoordinator, symbol: &str) {
+        while !coordinator
+            .progress()
+            .iter()
+            .any(|((_, s), p)| s == symbol && *p == SyncProgress::Queued)
+        {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn in_flight_fetches_per_exchange_stay_within_the_bound() {
+        let coordinator = Arc::new(SnapshotCoordinator::new(4));
+        let binance = Arc::new(MockFetcher::default());
+        let bitstamp = Arc::new(MockFetcher::default());
+
+        let fetches: Vec<_> = (0..24)
+            .map(|i| {
+                let coordinator = Arc::clone(&coordinator);
+                let (exchange, fetcher) = if i % 2 == 0 {
+                    ("binance", Arc::clone(&binance))
+                } else {
+                    ("bitstamp", Arc::clone(&bitstamp))
+                };
+                tokio::spawn(async move {
+                    let symbol = format!("sym{}", i);
+                    coordinator
+                        .fetch(
+                            exchange,
+                            &symbol,
+                            FetchPriority::Connected,
+                            fetcher.fetch(&symbol),
+                        )
+                        .await
+                })
+            })
+            .collect();
+        for fetch in fetches {
+            assert_eq!(fetch.await.unwrap(), Ok(1_000));
+        }
+
+        assert_eq!(binance.peak.load(Ordering::SeqCst), 4);
+        assert_eq!(bitstamp.peak.load(Ordering::SeqCst), 4);
+        assert_eq!(coordinator.in_flight("binance"), 0);
+        let progress = coordinator.progress();
+        assert_eq!(progress.len(), 24);
+        assert!(progress.iter().all(|(_, p)| *p == SyncProgress::Synced));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn connected_symbols_are_fetched_first() {
+        let coordinator = Arc::new(SnapshotCoordinator::new(1));
+        let fetcher = Arc::new(MockFetcher::default());
+        let (release, gate) = oneshot::channel::<()>();
+
+        let first = {
+            let coordinator = Arc::clone(&coordinator);
+            tokio::spawn(async move {
+                coordinator
+                    .fetch("binance", "first", FetchPriority::Connected, async {
+                        gate.await.unwrap();
+                        Ok(())
+                    })
+                    .await
+            })
+        };
+        while coordinator.in_flight("binance") == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        let mut queued = vec![];
+        for (symbol, priority) in [
+            ("cold1", FetchPriority::Disconnected),
+            ("hot1", FetchPriority::Connected),
+            ("cold2", FetchPriority::Disconnected),
+            ("hot2", FetchPriority::Connected),
+        ] {
+            let (waiter, fetcher) = (Arc::clone(&coordinator), Arc::clone(&fetcher));
+            queued.push(tokio::spawn(async move {
+                waiter
+                    .fetch("binance", symbol, priority, fetcher.fetch(symbol))
+                    .await
+            }));
+            wait_until_queued(&coordinator, symbol).await;
+        }
+
+        release.send(()).unwrap();
+        first.await.unwrap().unwrap();
+        for fetch in queued {
+            fetch.await.unwrap().unwrap();
+        }
+        assert_eq!(
+            *fetcher.order.lock().unwrap(),
+            vec!["hot1", "hot2", "cold1", "cold2"]
+        );
+        assert_eq!(fetcher.peak.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn requests_for_a_feed_made_before_its_fetch_starts_share_one_call() {
+        let coordinator =
+            Arc::new(SnapshotCoordinator::new(4).with_coalesce_window(Duration::from_millis(50)));
+        let fetcher = Arc::new(MockFetcher::default());
+        let request = |symbol: &'static str, delay_ms: u64| {
+            let (coordinator, fetcher) = (Arc::clone(&coordinator), Arc::clone(&fetcher));
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+                coordinator
+                    .fetch(
+                        "binance",
+                        symbol,
+                        FetchPriority::Connected,
+                        fetcher.fetch(symbol),
+                    )
+                    .await
+            })
+        };
+        // Within the window, a second symbol, and after the shared call has started
+        let requests = [
+            request("ethbtc", 0),
+            request("ethbtc", 20),
+            request("btcusdt", 20),
+            request("ethbtc", 52),
+        ];
+        for request in requests {
+            assert_eq!(request.await.unwrap(), Ok(1_000));
+        }
+        assert_eq!(
+            *fetcher.order.lock().unwrap(),
+            vec!["ethbtc", "btcusdt", "ethbtc"]
+        );
+        assert_eq!(coordinator.coalesced("binance"), 1);
+
+        // If the fetch it joined is cancelled before it starts, a request fetches itself
+        let leader = request("ethbtc", 0);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let joined = request("ethbtc", 0);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        leader.abort();
+        assert!(leader.await.unwrap_err().is_cancelled());
+        assert_eq!(joined.await.unwrap(), Ok(1_000));
+        assert_eq!(fetcher.order.lock().unwrap().len(), 4);
+        assert_eq!(coordinator.coalesced("binance"), 1);
+    }
+
+    #[tokio::test]
+    async fn a_cancelled_waiter_does_not_leak_its_slot() {
+        let coordinator = Arc::new(SnapshotCoordinator::new(1));
+        let (release, gate) = oneshot::channel::<()>();
+        let holder = {
+            let coordinator = Arc::clone(&coordinator);
+            tokio::spawn(async move {
+                coordinator
+                    .fetch("bitstamp", "held", FetchPriority::Connected, async {
+                        gate.await.unwrap();
+                        Ok(())
+                    })
+                    .await
+            })
+        };
+        while coordinator.in_flight("bitstamp") == 0 {
+            tokio::task::yield_now().await;
+        }
+        let cancelled = {
+            let coordinator = Arc::clone(&coordinator);
+            tokio::spawn(async move {
+                coordinator
+                    .fetch("bitstamp", "gone", FetchPriority::Connected, async {
+                        Ok(())
+                    })
+                    .await
+            })
+        };
+        wait_until_queued(&coordinator, "gone").await;
+        cancelled.abort();
+        let _ = cancelled.await;
+
+        release.send(()).unwrap();
+        holder.await.unwrap().unwrap();
+        assert_eq!(coordinator.in_flight("bitstamp"), 0);
+        let result = coordinator
+            .fetch("bitstamp", "next", FetchPriority::Connected, async {
+                Err::<(), _>("boom".to_string())
+            })
+            .await;
+        assert!(result.is_err());
+        assert!(
+            coordinator
+                .progress()
+                .contains(&(("bitstamp".into(), "next".into()), SyncProgress::Failed))
+        );
+    }
+}
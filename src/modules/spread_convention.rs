@@ -0,0 +1,46 @@
+use serde::Deserialize;
+
+/// How a crossed book's spread (best ask below best bid) is reported in Summaries and the
+/// spread history. The book itself always keeps the signed value.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpreadConvention {
+    /// Best ask - best bid, negative while crossed
+    #[default]
+    Signed,
+    /// Zero while crossed; Summaries flag the crossing with `crossed`
+    ClampZero,
+    /// How far apart the best prices are, whichever is above
+    Absolute,
+}
+
+impl SpreadConvention {
+    /// `signed` (best ask - best bid) as reported under this convention
+    pub fn report(&self, signed: f64) -> f64 {
+        match self {
+            SpreadConvention::Signed => signed,
+            SpreadConvention::ClampZero => signed.max(0.0),
+            SpreadConvention::Absolute => signed.abs(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_crossed_spreads_are_reported_differently() {
+        for convention in [
+            SpreadConvention::Signed,
+            SpreadConvention::ClampZero,
+            SpreadConvention::Absolute,
+        ] {
+            assert_eq!(convention.report(0.5), 0.5, "{:?}", convention);
+            assert_eq!(convention.report(0.0), 0.0, "{:?}", convention);
+        }
+        assert_eq!(SpreadConvention::Signed.report(-0.25), -0.25);
+        assert_eq!(SpreadConvention::ClampZero.report(-0.25), 0.0);
+        assert_eq!(SpreadConvention::Absolute.report(-0.25), 0.25);
+    }
+}
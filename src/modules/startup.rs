@@ -0,0 +1,352 @@
+use crate::modules::backoff::Backoff;
+use crate::modules::circuit_breaker::{CircuitBreaker, CircuitState};
+use crate::modules::clock::SharedClock;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Whether the process is serving every configured exchange
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Readiness {
+    /// Within the startup deadline, waiting for exchanges to sync
+    Starting,
+    /// Every configured exchange has synced
+    Ready,
+    /// Past the deadline with some exchanges synced; the rest, with their last error, are
+    /// still being retried
+    Degraded { failed: BTreeMap<String, String> },
+    /// Past the deadline with no exchange synced
+    Failed { failed: BTreeMap<String, String> },
+}
+
+#[derive(Clone, Debug, Default)]
+struct Progress {
+    synced: BTreeSet<String>,
+    /// Not synced yet, with the last error if an attempt failed
+    pending: BTreeMap<String, Option<String>>,
+    /// The startup deadline was reached or every exchange synced
+    decided: bool,
+}
+
+/// First sync of each configured exchange, reported by the connectors and awaited by the
+/// startup sequence against its deadline
+#[derive(Debug)]
+pub struct StartupTracker {
+    progress: watch::Sender<Progress>,
+}
+
+impl Default for StartupTracker {
+    fn default() -> Self {
+        Self {
+            progress: watch::Sender::new(Progress::default()),
+        }
+    }
+}
+
+impl StartupTracker {
+    /// The exchanges startup waits for
+    pub fn expect<'a>(&self, exchanges: impl IntoIterator<Item = &'a str>) {
+        self.progress.send_modify(|progress| {
+            progress.pending = exchanges
+                .into_iter()
+                .map(|exchange| (exchange.to_string(), None))
+                .collect();
+        });
+    }
+
+    /// The exchange's snapshot is merged into the book
+    pub fn synced(&self, exchange: &str) {
+        self.progress.send_if_modified(|progress| {
+            progress.pending.remove(exchange).is_some()
+                && progress.synced.insert(exchange.to_string())
+        });
+    }
+
+    /// A connect/sync attempt failed; kept for the startup summary until the exchange syncs
+    pub fn failed(&self, exchange: &str, error: &str) {
+        self.progress
+            .send_if_modified(|progress| match progress.pending.get_mut(exchange) {
+                Some(last) => {
+                    *last = Some(error.to_string());
+                    true
+                }
+                None => false,
+            });
+    }
+
+    /// Exchanges that have not synced since startup
+    pub fn stragglers(&self) -> Vec<String> {
+        self.progress.borrow().pending.keys().cloned().collect()
+    }
+
+    pub fn readiness(&self) -> Readiness {
+        let progress = self.progress.borrow();
+        let failed = || {
+            progress
+                .pending
+                .iter()
+                .map(|(exchange, error)| {
+                    let error = error.as_deref().unwrap_or("no attempt finished");
+                    (exchange.clone(), error.to_string())
+                })
+                .collect()
+        };
+        if progress.pending.is_empty() {
+            Readiness::Ready
+        } else if !progress.decided {
+            Readiness::Starting
+        } else if progress.synced.is_empty() {
+            Readiness::Failed { failed: failed() }
+        } else {
+            Readiness::Degraded { failed: failed() }
+        }
+    }
+
+    /// Wait until every expected exchange has synced, or for at most `deadline`, and decide
+    /// the startup outcome
+    pub async fn wait(&self, deadline: Duration) -> Readiness {
+        let mut progress = self.progress.subscribe();
+        let _ = tokio::time::timeout(
+            deadline,
+            progress.wait_for(|progress| progress.pending.is_empty()),
+        )
+        .await;
+        self.progress
+            .send_modify(|progress| progress.decided = true);
+        self.readiness()
+    }
+}
+
+/// When to reconnect for exchanges that didn't sync while the others stream, with a
+/// growing delay. An exchange with an open circuit is left to its breaker's probe.
+#[derive(Debug)]
+pub struct StragglerRetry {
+    clock: SharedClock,
+    backoff: Backoff,
+    retry_at: Option<u64>, // unix millis
+}
+
+impl StragglerRetry {
+    pub fn new(clock: SharedClock, initial: Duration, max: Duration) -> Self {
+        Self {
+            backoff: Backoff::new(clock.clone(), initial, max, max),
+            clock,
+            retry_at: None,
+        }
+    }
+
+    /// After a connect pass, given the breakers of the exchanges that didn't sync: the delay
+    /// until they are retried, or `None` if nothing is to be retried
+    pub fn after_pass<'a>(
+        &mut self,
+        stragglers: impl IntoIterator<Item = &'a CircuitBreaker>,
+    ) -> Option<Duration> {
+        let states: Vec<CircuitState> = stragglers.into_iter().map(|b| b.state()).collect();
+        if states.is_empty() {
+            self.backoff.reset();
+        }
+        if states.iter().all(|state| *state == CircuitState::Open) {
+            self.retry_at = None;
+            return None;
+        }
+        let delay = self.backoff.next_delay();
+        self.retry_at = Some(self.clock.now_millis() + delay.as_millis() as u64);
+        Some(delay)
+    }
+
+    pub fn due(&self) -> bool {
+        self.retry_at
+            .is_some_and(|at| self.clock.now_millis() >= at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::circuit_breaker::CircuitBreakerConfig;
+    use crate::modules::clock::{MockClock, TestTime};
+    use std::sync::Arc;
+
+    const DEADLINE: Duration = Duration::from_secs(30);
+
+    /// Outcome of each attempt of a mock connector: how long it takes and whether it syncs.
+    /// Attempts past the end of the script fail.
+    type Script = Vec<(u64, bool)>;
+
+    /// Connect passes as the connector loop makes them: every exchange allowed by its
+    /// breaker is attempted together, then the stragglers are retried after the backoff,
+    /// or after the cool-down when their circuits are open
+    async fn run_connectors(
+        time: TestTime,
+        tracker: Arc<StartupTracker>,
+        scripts: Vec<(&'static str, Script)>,
+    ) {
+        let config = CircuitBreakerConfig {
+            failure_threshold: 3,
+            window: Duration::from_secs(60),
+            cool_down: Duration::from_secs(20),
+        };
+        let mut feeds: Vec<_> = scripts
+            .into_iter()
+            .map(|(exchange, script)| {
+                let breaker = CircuitBreaker::new(exchange, time.shared(), config);
+                (exchange, script.into_iter(), breaker, false)
+            })
+            .collect();
+        let mut retry = StragglerRetry::new(
+            time.shared(),
+            Duration::from_secs(2),
+            Duration::from_secs(8),
+        );
+        loop {
+            let mut pass_took = 0;
+            for (exchange, script, breaker, synced) in feeds.iter_mut() {
+                if *synced || !breaker.allow_attempt() {
+                    continue;
+                }
+                let (took, ok) = script.next().unwrap_or((1_000, false));
+                pass_took = pass_took.max(took);
+                if ok {
+                    breaker.record_success();
+                    *synced = true;
+                } else {
+                    breaker.record_failure();
+                    tracker.failed(exchange, &format!("{} refused", exchange));
+                }
+            }
+            time.advance(Duration::from_millis(pass_took)).await;
+            for (exchange, _, _, synced) in &feeds {
+                if *synced {
+                    tracker.synced(exchange);
+                }
+            }
+            let stragglers = feeds.iter().filter(|f| !f.3).map(|f| &f.2);
+            let wait = match retry.after_pass(stragglers) {
+                Some(delay) => delay,
+                None if feeds.iter().all(|f| f.3) => return,
+                // Every straggler's circuit is open: wait for the first probe
+                None => {
+                    let until = feeds.iter().filter_map(|f| f.2.open_until()).min().unwrap();
+                    Duration::from_millis(until - time.shared().now_millis())
+                }
+            };
+            time.advance(wait).await;
+            assert!(retry.due() || feeds.iter().any(|f| f.2.probe_due()));
+        }
+    }
+
+    fn start(scripts: Vec<(&'static str, Script)>) -> Arc<StartupTracker> {
+        let tracker = Arc::new(StartupTracker::default());
+        tracker.expect(scripts.iter().map(|(exchange, _)| *exchange));
+        let time = TestTime {
+            clock: Arc::new(MockClock::new(0)),
+        };
+        tokio::spawn(run_connectors(time, Arc::clone(&tracker), scripts));
+        tracker
+    }
+
+    fn failed(exchanges: &[&str]) -> BTreeMap<String, String> {
+        exchanges
+            .iter()
+            .map(|e| (e.to_string(), format!("{} refused", e)))
+            .collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn every_exchange_syncing_in_time_is_ready_without_waiting_out_the_deadline() {
+        let started = tokio::time::Instant::now();
+        let tracker = start(vec![
+            ("binance", vec![(300, true)]),
+            // Fails twice, then syncs on the second straggler retry
+            ("bitstamp", vec![(500, false), (500, false), (500, true)]),
+        ]);
+        assert_eq!(tracker.readiness(), Readiness::Starting);
+        assert_eq!(tracker.wait(DEADLINE).await, Readiness::Ready);
+        // 0.5s pass, 2s backoff, 0.5s retry, 4s backoff, 0.5s retry
+        assert_eq!(started.elapsed(), Duration::from_millis(7_500));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_partial_start_is_degraded_and_recovers_when_the_straggler_syncs() {
+        let tracker = start(vec![
+            ("binance", vec![(300, true)]),
+            // Opens its circuit after three failures, then the probe after the cool-down
+            // syncs, but only after the deadline
+            (
+                "bitstamp",
+                vec![(500, false), (500, false), (500, false), (500, true)],
+            ),
+        ]);
+        assert_eq!(
+            tracker.wait(Duration::from_secs(10)).await,
+            Readiness::Degraded {
+                failed: failed(&["bitstamp"])
+            }
+        );
+        assert_eq!(tracker.stragglers(), ["bitstamp"]);
+
+        tokio::time::sleep(DEADLINE).await;
+        assert_eq!(tracker.readiness(), Readiness::Ready);
+        assert!(tracker.stragglers().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn nothing_synced_by_the_deadline_fails_with_every_error() {
+        let tracker = start(vec![("binance", vec![]), ("bitstamp", vec![])]);
+        let started = tokio::time::Instant::now();
+        assert_eq!(
+            tracker.wait(DEADLINE).await,
+            Readiness::Failed {
+                failed: failed(&["binance", "bitstamp"])
+            }
+        );
+        assert_eq!(started.elapsed(), DEADLINE);
+
+        // An exchange with no finished attempt is reported as such; others are ignored
+        let tracker = StartupTracker::default();
+        tracker.expect(["binance", "bitstamp"]);
+        tracker.failed("binance", "binance refused");
+        tracker.failed("kraken", "not expected");
+        assert_eq!(
+            tracker.wait(DEADLINE).await,
+            Readiness::Failed {
+                failed: BTreeMap::from([
+                    ("binance".to_string(), "binance refused".to_string()),
+                    ("bitstamp".to_string(), "no attempt finished".to_string()),
+                ])
+            }
+        );
+    }
+
+    #[test]
+    fn stragglers_with_open_circuits_wait_for_their_probe() {
+        let clock = Arc::new(MockClock::new(0));
+        let mut retry = StragglerRetry::new(
+            clock.clone(),
+            Duration::from_secs(2),
+            Duration::from_secs(8),
+        );
+        let mut breaker = CircuitBreaker::new(
+            "bitstamp",
+            clock.clone(),
+            CircuitBreakerConfig {
+                failure_threshold: 1,
+                ..Default::default()
+            },
+        );
+        assert_eq!(retry.after_pass([&breaker]), Some(Duration::from_secs(2)));
+        assert!(!retry.due());
+        clock.advance(Duration::from_secs(2));
+        assert!(retry.due());
+        assert_eq!(retry.after_pass([&breaker]), Some(Duration::from_secs(4)));
+
+        breaker.record_failure();
+        assert_eq!(retry.after_pass([&breaker]), None);
+        assert!(!retry.due());
+
+        // Nothing left to retry starts the backoff over
+        assert_eq!(retry.after_pass([]), None);
+        breaker.record_success();
+        assert_eq!(retry.after_pass([&breaker]), Some(Duration::from_secs(2)));
+    }
+}
@@ -0,0 +1,309 @@
+use crate::modules::rate::RateStats;
+use crate::modules::throttle::GaugeSample;
+use crate::modules::traded_estimate::TradedVolumeEstimator;
+use crate::modules::types::Side;
+use std::collections::{BTreeMap, VecDeque};
+
+pub const DEFAULT_HISTORY_CAPACITY: usize = 1024;
+
+/// Book-level metrics captured after each applied change
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StatsSample {
+    pub at: u64, // unix millis
+    pub version: u64,
+    pub spread: Option<f64>, // signed, negative while crossed; unset while a side is empty
+    pub index_price: Option<f64>,
+}
+
+/// Shape of one side of the book, over whole price levels (all exchanges at a price)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SideShape {
+    pub levels: usize,
+    pub mean_size: f64,
+    pub median_size: f64,
+    /// Price distance between the best and the 10th price level, if there are 10
+    pub top10_distance: Option<f64>,
+}
+
+/// Price levels per side a derived metric left out as further than the symbol's
+/// `max_level_distance_bps` from the mid
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ExcludedLevels {
+    pub bids: usize,
+    pub asks: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BookShape {
+    pub bids: SideShape,
+    pub asks: SideShape,
+    /// Each exchange's share of the total amount on both sides, summing to 1
+    pub exchange_share: BTreeMap<String, f64>,
+    /// Levels the side shapes and exchange shares leave out
+    pub excluded_levels: ExcludedLevels,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShapeSample {
+    pub at: u64, // unix millis
+    pub version: u64,
+    pub shape: BookShape,
+}
+
+/// The aggregated book against one venue alone. Deltas are signed so that positive means
+/// the aggregated book is better; bps are relative to the venue's price.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VenueImprovement {
+    pub best_price: Option<f64>,
+    pub best_delta: Option<f64>,
+    pub best_delta_bps: Option<f64>,
+    /// Average price of filling the clip on the venue alone; `None` if it can't fill it
+    pub vwap: Option<f64>,
+    pub vwap_delta: Option<f64>,
+    pub vwap_delta_bps: Option<f64>,
+}
+
+/// Best price and clip VWAP of one side, aggregated and per venue
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SideImprovement {
+    pub best_price: Option<f64>,
+    /// Credited with the best price; among several quoting it, the first by the tie-break
+    pub best_exchange: Option<String>,
+    pub vwap: Option<f64>,
+    pub venues: BTreeMap<String, VenueImprovement>,
+}
+
+/// How much better the aggregated book is than each venue for a clip of `clip_size`:
+/// selling into the bids and buying from the asks
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PriceImprovement {
+    pub clip_size: f64,
+    pub bids: SideImprovement,
+    pub asks: SideImprovement,
+    /// Depth weights other than 1.0 the aggregated VWAPs were computed with
+    pub depth_weights: BTreeMap<String, f64>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImprovementSample {
+    pub at: u64, // unix millis
+    pub version: u64,
+    pub improvement: PriceImprovement,
+}
+
+/// Latest entries of the history
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BookStats {
+    pub latest: Option<StatsSample>,
+    pub shape: Option<ShapeSample>,
+    /// Last published top-N notional imbalance, if the gauge is enabled
+    pub imbalance: Option<GaugeSample>,
+    /// Levels among the top N the imbalance currently leaves out, if the gauge is enabled
+    pub imbalance_excluded_levels: Option<ExcludedLevels>,
+    /// Incoming messages per second by exchange; filled in from the status registry
+    pub update_rates: BTreeMap<String, RateStats>,
+    /// Updates over the apply-latency budget by exchange; filled in from the status registry
+    pub slow_apply_total: BTreeMap<String, u64>,
+    pub price_improvement: Option<ImprovementSample>,
+    /// Estimated traded-through volume by exchange, if the estimator is enabled
+    pub traded_estimate: Option<TradedVolumeEstimator>,
+    /// Depth weights other than 1.0 the shape sizes and imbalance are computed with
+    pub depth_weights: BTreeMap<String, f64>,
+}
+
+/// Fixed-size ring buffer of recent samples; the oldest is dropped when full. Book-shape
+/// and price-improvement samples are taken less often and kept in rings of the same size.
+#[derive(Clone, Debug)]
+pub struct StatsHistory {
+    capacity: usize,
+    samples: VecDeque<StatsSample>,
+    shapes: VecDeque<ShapeSample>,
+    improvements: VecDeque<ImprovementSample>,
+}
+
+impl Default for StatsHistory {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_HISTORY_CAPACITY)
+    }
+}
+
+impl StatsHistory {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity,
+            samples: VecDeque::with_capacity(capacity),
+            shapes: VecDeque::new(),
+            improvements: VecDeque::new(),
+        }
+    }
+
+    pub fn record(&mut self, sample: StatsSample) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    /// Samples, oldest first
+    pub fn samples(&self) -> impl DoubleEndedIterator<Item = &StatsSample> {
+        self.samples.iter()
+    }
+
+    pub fn latest(&self) -> Option<&StatsSample> {
+        self.samples.back()
+    }
+
+    pub fn record_shape(&mut self, sample: ShapeSample) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.shapes.len() == self.capacity {
+            self.shapes.pop_front();
+        }
+        self.shapes.push_back(sample);
+    }
+
+    /// Shape samples, oldest first
+    pub fn shapes(&self) -> impl DoubleEndedIterator<Item = &ShapeSample> {
+        self.shapes.iter()
+    }
+
+    pub fn latest_shape(&self) -> Option<&ShapeSample> {
+        self.shapes.back()
+    }
+
+    pub fn record_improvement(&mut self, sample: ImprovementSample) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.improvements.len() == self.capacity {
+            self.improvements.pop_front();
+        }
+        self.improvements.push_back(sample);
+    }
+
+    /// Price-improvement samples, oldest first
+    pub fn improvements(&self) -> impl DoubleEndedIterator<Item = &ImprovementSample> {
+        self.improvements.iter()
+    }
+
+    pub fn latest_improvement(&self) -> Option<&ImprovementSample> {
+        self.improvements.back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+}
+
+impl SideShape {
+    /// `levels` as (price, total amount), best first
+    pub fn from_levels(levels: &[(f64, f64)]) -> Self {
+        if levels.is_empty() {
+            return Self::default();
+        }
+        let mut sizes: Vec<f64> = levels.iter().map(|(_, amount)| *amount).collect();
+        sizes.sort_by(f64::total_cmp);
+        let mid = sizes.len() / 2;
+        let median_size = if sizes.len().is_multiple_of(2) {
+            (sizes[mid - 1] + sizes[mid]) / 2.0
+        } else {
+            sizes[mid]
+        };
+        Self {
+            levels: levels.len(),
+            mean_size: sizes.iter().sum::<f64>() / sizes.len() as f64,
+            median_size,
+            top10_distance: levels.get(9).map(|(price, _)| (price - levels[0].0).abs()),
+        }
+    }
+}
+
+/// Average price of filling `clip` from `levels` as (price, amount), best first; `None` if
+/// they hold less than the clip
+pub fn clip_vwap(levels: impl IntoIterator<Item = (f64, f64)>, clip: f64) -> Option<f64> {
+    let (mut filled, mut notional) = (0.0, 0.0);
+    for (price, amount) in levels {
+        let take = amount.min(clip - filled);
+        filled += take;
+        notional += take * price;
+        if filled >= clip {
+            return Some(notional / clip);
+        }
+    }
+    None
+}
+
+impl SideImprovement {
+    /// Compare the aggregated side against each venue's own levels, all best first
+    pub fn compare(
+        side: Side,
+        clip: f64,
+        aggregated: &[(f64, f64)],
+        venues: impl IntoIterator<Item = (String, Vec<(f64, f64)>)>,
+    ) -> Self {
+        // Positive when the aggregated price is the better one for this side
+        let delta = |ours: Option<f64>, theirs: Option<f64>| {
+            let (ours, theirs) = (ours?, theirs?);
+            let delta = match side {
+                Side::Bid => ours - theirs,
+                Side::Ask => theirs - ours,
+            };
+            Some((delta, delta / theirs * 10_000.0))
+        };
+        let best_price = aggregated.first().map(|(price, _)| *price);
+        let vwap = clip_vwap(aggregated.iter().copied(), clip);
+        let venues = venues
+            .into_iter()
+            .map(|(exchange, levels)| {
+                let venue_best = levels.first().map(|(price, _)| *price);
+                let venue_vwap = clip_vwap(levels, clip);
+                let best = delta(best_price, venue_best);
+                let clip = delta(vwap, venue_vwap);
+                let venue = VenueImprovement {
+                    best_price: venue_best,
+                    best_delta: best.map(|(d, _)| d),
+                    best_delta_bps: best.map(|(_, bps)| bps),
+                    vwap: venue_vwap,
+                    vwap_delta: clip.map(|(d, _)| d),
+                    vwap_delta_bps: clip.map(|(_, bps)| bps),
+                };
+                (exchange, venue)
+            })
+            .collect();
+        Self {
+            best_price,
+            best_exchange: None,
+            vwap,
+            venues,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_only_the_most_recent_samples() {
+        let mut history = StatsHistory::with_capacity(3);
+        for version in 0..5 {
+            history.record(StatsSample {
+                at: version * 10,
+                version,
+                spread: Some(0.1),
+                index_price: None,
+            });
+        }
+        let versions: Vec<u64> = history.samples().map(|s| s.version).collect();
+        assert_eq!(versions, vec![2, 3, 4]);
+        assert_eq!(history.latest().unwrap().at, 40);
+    }
+}
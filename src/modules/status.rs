@@ -0,0 +1,242 @@
+use crate::modules::aggregated_orderbook::authoritative_depth;
+use crate::modules::circuit_breaker::{BreakerStats, CircuitState};
+use crate::modules::commands::CommandRegistry;
+use crate::modules::connections::ConnectionRegistry;
+use crate::modules::consistency::ConsistencyCounters;
+use crate::modules::cross_check::{CrossCheck, CrossCheckCounts};
+use crate::modules::dedup::DedupCounters;
+use crate::modules::latency::LatencyMonitor;
+use crate::modules::limits::PayloadViolations;
+use crate::modules::log_limiter::LogLimiter;
+use crate::modules::parse_failures::ParseFailureLog;
+use crate::modules::rate::{RateStats, RateTracker};
+use crate::modules::shutdown::RunCounters;
+use crate::modules::snapshot_fetch::SnapshotCoordinator;
+use crate::modules::startup::StartupTracker;
+use crate::modules::stream_metrics::StreamMetrics;
+use crate::modules::timeseries::TimeSeriesStore;
+use crate::modules::types::Exchange;
+use crate::modules::uptime::UptimeLog;
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connecting,
+    /// Connected, waiting for the exchange to confirm the subscription
+    Subscribing,
+    Connected,
+    Disconnected,
+    /// Ignored after repeated stale updates until a resync
+    Quarantined,
+    /// Left out of the configured exchanges; never connected
+    NotConfigured,
+    /// Connected, but updates keep taking longer than the latency budget to apply
+    Degraded,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExchangeStatus {
+    pub exchange: String,
+    pub connection: ConnectionState,
+    pub breaker: BreakerStats,
+    pub quarantined_until: Option<u64>, // unix millis
+    pub times_quarantined: u64,
+    pub rate: RateStats, // incoming messages per second
+    pub payload: PayloadViolations,
+    /// Updates that took longer than the latency budget to apply
+    pub slow_applies: u64,
+    pub cross_checks: CrossCheckCounts,
+    /// Updates dropped for waiting longer than the max update age before being applied
+    pub dropped_as_old: u64,
+    /// Updates dropped by the exchange's full update queue, over every connection
+    pub queue_overflows: u64,
+    /// Levels per side of the exchange's book that the aggregation keeps; `None` for all
+    pub authoritative_depth: Option<usize>,
+}
+
+impl ExchangeStatus {
+    fn new(exchange: &str) -> Self {
+        Self {
+            exchange: exchange.to_string(),
+            connection: ConnectionState::Connecting,
+            breaker: BreakerStats {
+                state: CircuitState::Closed,
+                consecutive_failures: 0,
+                total_failures: 0,
+                times_opened: 0,
+                open_until: None,
+            },
+            quarantined_until: None,
+            times_quarantined: 0,
+            rate: RateStats::default(),
+            payload: PayloadViolations::default(),
+            slow_applies: 0,
+            cross_checks: CrossCheckCounts::default(),
+            dropped_as_old: 0,
+            queue_overflows: 0,
+            authoritative_depth: exchange
+                .parse::<Exchange>()
+                .ok()
+                .and_then(authoritative_depth),
+        }
+    }
+}
+
+/// Connector and publisher health shared between the websocket loop, the outbound streams
+/// and the status/admin RPCs
+#[derive(Debug, Default)]
+pub struct StatusRegistry {
+    exchanges: RwLock<BTreeMap<String, ExchangeStatus>>,
+    pub connections: Arc<ConnectionRegistry>,
+    pub commands: Arc<CommandRegistry>,
+    pub snapshots: Arc<SnapshotCoordinator>,
+    pub startup: StartupTracker,
+    pub publisher: DedupCounters,
+    pub consistency: ConsistencyCounters,
+    pub streams: Arc<StreamMetrics>,
+    pub parse_failures: ParseFailureLog,
+    pub uptime: UptimeLog,
+    pub counters: RunCounters,
+    pub rates: RateTracker,
+    pub latency: LatencyMonitor,
+    pub log_limiter: Arc<LogLimiter>,
+    pub timeseries: Arc<TimeSeriesStore>,
+}
+
+pub type SharedStatus = Arc<StatusRegistry>;
+
+impl StatusRegistry {
+    /// Allow this many concurrent snapshot fetches per exchange, coalescing requests for the
+    /// same feed made within `coalesce_window`
+    pub fn with_snapshot_fetches(
+        mut self,
+        max_in_flight: usize,
+        coalesce_window: Duration,
+    ) -> Self {
+        self.snapshots =
+            Arc::new(SnapshotCoordinator::new(max_in_flight).with_coalesce_window(coalesce_window));
+        self
+    }
+
+    /// Cap the state retained by all stateful server streams together, in bytes
+    pub fn with_stream_state_limit(mut self, bytes: Option<u64>) -> Self {
+        self.streams = Arc::new(StreamMetrics::default().with_state_limit(bytes));
+        self
+    }
+
+    /// Rate limit hot-path warnings with `limiter`, shared with the books
+    pub fn with_log_limiter(mut self, limiter: Arc<LogLimiter>) -> Self {
+        self.log_limiter = limiter;
+        self
+    }
+
+    /// Record sampled metrics into `store`, shared with the books
+    pub fn with_timeseries(mut self, store: Arc<TimeSeriesStore>) -> Self {
+        self.timeseries = store;
+        self
+    }
+
+    pub fn with_rates(mut self, rates: RateTracker) -> Self {
+        self.rates = rates;
+        self
+    }
+
+    pub fn with_latency(mut self, latency: LatencyMonitor) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    pub fn set_connection(&self, exchange: &str, connection: ConnectionState) {
+        self.update(exchange, |status| status.connection = connection);
+        self.uptime.set_connected(
+            exchange,
+            matches!(
+                connection,
+                ConnectionState::Connected | ConnectionState::Degraded
+            ),
+        );
+    }
+
+    /// Move a connected exchange to `Degraded` and back; other states are left alone
+    pub fn set_degraded(&self, exchange: &str, degraded: bool) {
+        self.update(exchange, |status| {
+            status.connection = match (status.connection, degraded) {
+                (ConnectionState::Connected, true) => ConnectionState::Degraded,
+                (ConnectionState::Degraded, false) => ConnectionState::Connected,
+                (connection, _) => connection,
+            }
+        });
+    }
+
+    /// Whether the exchange has levels in the book, for the uptime report
+    pub fn set_contributing(&self, exchange: &str, contributing: bool) {
+        self.uptime.set_contributing(exchange, contributing);
+    }
+
+    pub fn set_breaker(&self, exchange: &str, breaker: BreakerStats) {
+        self.update(exchange, |status| status.breaker = breaker);
+    }
+
+    pub fn set_quarantine(&self, exchange: &str, until: Option<u64>, times: u64) {
+        self.update(exchange, |status| {
+            status.quarantined_until = until;
+            status.times_quarantined = times;
+        });
+    }
+
+    pub fn record_dropped_levels(&self, exchange: &str, dropped: u64) {
+        self.update(exchange, |status| status.payload.levels_dropped += dropped);
+    }
+
+    pub fn record_oversized_frame(&self, exchange: &str) {
+        self.update(exchange, |status| status.payload.oversized_frames += 1);
+    }
+
+    pub fn record_oversized_snapshot(&self, exchange: &str) {
+        self.update(exchange, |status| status.payload.oversized_snapshots += 1);
+    }
+
+    pub fn record_dropped_as_old(&self, exchange: &str) {
+        self.update(exchange, |status| status.dropped_as_old += 1);
+    }
+
+    /// Add the overflows of one connection's update queue, made afresh on each connection
+    pub fn record_queue_overflows(&self, exchange: &str, overflows: u64) {
+        self.update(exchange, |status| status.queue_overflows += overflows);
+    }
+
+    pub fn record_cross_check(&self, exchange: &str, outcome: &CrossCheck) {
+        self.update(exchange, |status| {
+            let counts = &mut status.cross_checks;
+            counts.checks += 1;
+            match outcome {
+                CrossCheck::Match => {}
+                CrossCheck::Diverged(_) => counts.divergences += 1,
+                CrossCheck::Resync(_) => {
+                    counts.divergences += 1;
+                    counts.resyncs += 1;
+                }
+            }
+        });
+    }
+
+    /// All known exchanges, sorted by name
+    pub fn exchanges(&self) -> Vec<ExchangeStatus> {
+        let mut exchanges: Vec<ExchangeStatus> =
+            self.exchanges.read().unwrap().values().cloned().collect();
+        for status in &mut exchanges {
+            status.rate = self.rates.stats(&status.exchange).unwrap_or_default();
+            status.slow_applies = self.latency.slow_total(&status.exchange);
+        }
+        exchanges
+    }
+
+    fn update(&self, exchange: &str, f: impl FnOnce(&mut ExchangeStatus)) {
+        let mut exchanges = self.exchanges.write().unwrap();
+        f(exchanges
+            .entry(exchange.to_string())
+            .or_insert_with(|| ExchangeStatus::new(exchange)));
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Why a server stream ended
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StreamEnd {
+    /// The client went away or cancelled; the stream was dropped mid-flight
+    ClientCancel,
+    /// The source ended, i.e. the server is shutting down
+    ServerShutdown,
+    Error(String),
+}
+
+impl StreamEnd {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StreamEnd::ClientCancel => "client_cancel",
+            StreamEnd::ServerShutdown => "server_shutdown",
+            StreamEnd::Error(_) => "error",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MethodCounters {
+    pub active: u64,
+    pub opened: u64,
+    pub messages_sent: u64,
+}
+
+/// Server memory one stream keeps for its client, e.g. dedup hashes or a last-sent snapshot
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StreamState {
+    /// Book levels retained
+    pub levels: u64,
+    /// Estimated bytes retained, levels included
+    pub bytes: u64,
+}
+
+/// An open stream that keeps state
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StatefulStream {
+    pub method: &'static str,
+    pub peer: String,
+    pub state: StreamState,
+}
+
+/// Open and finished server streams per RPC method
+#[derive(Debug, Default)]
+pub struct StreamMetrics {
+    methods: Mutex<BTreeMap<&'static str, MethodCounters>>,
+    /// Cap on the state retained by all open streams together, in bytes
+    state_limit: Option<u64>,
+    stateful: Mutex<BTreeMap<u64, StatefulStream>>,
+    next_id: AtomicU64,
+}
+
+impl StreamMetrics {
+    /// Refuse stateful streams that would take the total retained state over `bytes`.
+    /// Streams that keep no state are never refused.
+    pub fn with_state_limit(mut self, bytes: Option<u64>) -> Self {
+        self.state_limit = bytes;
+        self
+    }
+
+    /// Like `open`, for a stream that keeps `state` for its client. Refused if the state
+    /// limit would be exceeded.
+    pub fn open_stateful(
+        self: &Arc<Self>,
+        method: &'static str,
+        peer: Option<String>,
+        request: String,
+        state: StreamState,
+    ) -> Result<StreamGuard, String> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        {
+            let mut stateful = self.stateful.lock().unwrap();
+            let retained: u64 = stateful.values().map(|s| s.state.bytes).sum();
+            if let Some(limit) = self.state_limit
+                && retained + state.bytes > limit
+            {
+                tracing::warn!(
+                    method,
+                    peer,
+                    request,
+                    retained_bytes = retained,
+                    "Stream refused: per-stream state limit reached"
+                );
+                return Err(format!(
+                    "per-stream state limit reached: {} of {} bytes retained by {} streams",
+                    retained,
+                    limit,
+                    stateful.len()
+                ));
+            }
+            stateful.insert(
+                id,
+                StatefulStream {
+                    method,
+                    peer: peer.clone().unwrap_or_else(|| "unknown".to_string()),
+                    state,
+                },
+            );
+        }
+        let mut guard = self.open(method, peer, request);
+        guard.stateful = Some(id);
+        Ok(guard)
+    }
+
+    /// State retained by all open streams
+    pub fn retained_state(&self) -> StreamState {
+        let stateful = self.stateful.lock().unwrap();
+        StreamState {
+            levels: stateful.values().map(|s| s.state.levels).sum(),
+            bytes: stateful.values().map(|s| s.state.bytes).sum(),
+        }
+    }
+
+    /// Open streams that keep state, oldest first
+    pub fn stateful_streams(&self) -> Vec<StatefulStream> {
+        self.stateful.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Count and log a new stream. `request` describes what was asked for (symbol, depth,
+    /// options), never payloads. The stream is closed when the guard is dropped.
+    pub fn open(
+        self: &Arc<Self>,
+        method: &'static str,
+        peer: Option<String>,
+        request: String,
+    ) -> StreamGuard {
+        {
+            let mut methods = self.methods.lock().unwrap();
+            let counters = methods.entry(method).or_default();
+            counters.active += 1;
+            counters.opened += 1;
+        }
+        let peer = peer.unwrap_or_else(|| "unknown".to_string());
+        tracing::info!(method, peer, request, "Stream opened");
+        StreamGuard {
+            metrics: Arc::clone(self),
+            method,
+            peer,
+            request,
+            opened_at: Instant::now(),
+            sent: 0,
+            end: None,
+            stateful: None,
+        }
+    }
+
+    pub fn methods(&self) -> BTreeMap<&'static str, MethodCounters> {
+        self.methods.lock().unwrap().clone()
+    }
+
+    pub fn active_streams(&self) -> u64 {
+        self.methods
+            .lock()
+            .unwrap()
+            .values()
+            .map(|c| c.active)
+            .sum()
+    }
+
+    pub fn streams_total(&self) -> u64 {
+        self.methods
+            .lock()
+            .unwrap()
+            .values()
+            .map(|c| c.opened)
+            .sum()
+    }
+}
+
+/// One open stream; logs its close with the duration, message count and cause
+#[derive(Debug)]
+pub struct StreamGuard {
+    metrics: Arc<StreamMetrics>,
+    method: &'static str,
+    peer: String,
+    request: String,
+    opened_at: Instant,
+    sent: u64,
+    end: Option<StreamEnd>,
+    /// Key of the stream's state entry, if it keeps any
+    stateful: Option<u64>,
+}
+
+impl StreamGuard {
+    /// Update what a stateful stream retains, e.g. as its last-sent snapshot grows
+    pub fn set_state(&mut self, state: StreamState) {
+        if let Some(id) = self.stateful
+            && let Some(entry) = self.metrics.stateful.lock().unwrap().get_mut(&id)
+        {
+            entry.state = state;
+        }
+    }
+
+    pub fn record_sent(&mut self) {
+        self.sent += 1;
+        let mut methods = self.metrics.methods.lock().unwrap();
+        methods.entry(self.method).or_default().messages_sent += 1;
+    }
+
+    /// Set the cause logged on close; a guard dropped without one was cancelled by the client
+    pub fn end(&mut self, end: StreamEnd) {
+        self.end.get_or_insert(end);
+    }
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        {
+            let mut methods = self.metrics.methods.lock().unwrap();
+            let counters = methods.entry(self.method).or_default();
+            counters.active = counters.active.saturating_sub(1);
+        }
+        let state = self
+            .stateful
+            .and_then(|id| self.metrics.stateful.lock().unwrap().remove(&id))
+            .map(|s| s.state)
+            .unwrap_or_default();
+        let end = self.end.take().unwrap_or(StreamEnd::ClientCancel);
+        let error = match &end {
+            StreamEnd::Error(message) => message.as_str(),
+            _ => "",
+        };
+        tracing::info!(
+            method = self.method,
+            peer = self.peer,
+            request = self.request,
+            duration_ms = self.opened_at.elapsed().as_millis() as u64,
+            messages_sent = self.sent,
+            state_bytes = state.bytes,
+            cause = end.as_str(),
+            error,
+            "Stream closed"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streams_are_counted_per_method_until_their_guard_drops() {
+        let metrics = Arc::new(StreamMetrics::default());
+        let mut summary = metrics.open("BookSummary", None, "depth=10".to_string());
+        let imbalance = metrics.open(
+            "StreamImbalance",
+            Some("127.0.0.1:5000".to_string()),
+            String::new(),
+        );
+        summary.record_sent();
+        summary.record_sent();
+        assert_eq!((metrics.active_streams(), metrics.streams_total()), (2, 2));
+
+        summary.end(StreamEnd::ServerShutdown);
+        summary.end(StreamEnd::Error(
+            "ignored, the first cause wins".to_string(),
+        ));
+        assert_eq!(summary.end, Some(StreamEnd::ServerShutdown));
+        drop(summary);
+        drop(imbalance);
+        let methods = metrics.methods();
+        assert_eq!(
+            methods["BookSummary"],
+            MethodCounters {
+                active: 0,
+                opened: 1,
+                messages_sent: 2
+            }
+        );
+        assert_eq!(methods["StreamImbalance"].messages_sent, 0);
+        assert_eq!((metrics.active_streams(), metrics.streams_total()), (0, 2));
+    }
+
+    #[test]
+    fn stateful_streams_are_refused_past_the_state_limit() {
+        let metrics = Arc::new(StreamMetrics::default().with_state_limit(Some(100)));
+        let state = |levels, bytes| StreamState { levels, bytes };
+        let mut first = metrics
+            .open_stateful("BookSummary", None, String::new(), state(0, 40))
+            .unwrap();
+        let second = metrics
+            .open_stateful("BookSummary", None, String::new(), state(2, 60))
+            .unwrap();
+        assert_eq!(metrics.retained_state(), state(2, 100));
+        let err = metrics
+            .open_stateful("BookSummary", None, String::new(), state(0, 1))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            "per-stream state limit reached: 100 of 100 bytes retained by 2 streams"
+        );
+        // Stateless streams don't count
+        let _plain = metrics.open("BookSummary", None, String::new());
+
+        first.set_state(state(0, 10));
+        assert_eq!(metrics.stateful_streams()[0].state, state(0, 10));
+        drop(second);
+        assert_eq!(metrics.retained_state(), state(0, 10));
+        assert_eq!(metrics.stateful_streams().len(), 1);
+        assert!(
+            metrics
+                .open_stateful("BookSummary", None, String::new(), state(0, 90))
+                .is_ok()
+        );
+        assert_eq!(metrics.active_streams(), 2);
+    }
+}
@@ -0,0 +1,113 @@
+/// Start of the error a snapshot fetch returns when the REST endpoint is still serving the
+/// cached book after the cache-busting retries
+const STUCK_SNAPSHOT: &str = "snapshot endpoint stuck";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StuckSnapshotConfig {
+    /// How far past a snapshot's id the stream must be, in the venue's id units, before a
+    /// repeat of that id is taken for a cached response rather than a quiet book
+    pub min_lead: u64,
+    /// Cache-busting retries before the fetch gives up
+    pub max_retries: usize,
+}
+
+impl Default for StuckSnapshotConfig {
+    fn default() -> Self {
+        Self {
+            // Ten seconds of Bitstamp's microtimestamp ids
+            min_lead: 10_000_000,
+            max_retries: 3,
+        }
+    }
+}
+
+/// Notices a REST snapshot endpoint serving one cached book over and over: every resync
+/// would rebuild the same stale book while the stream moves on. Remembers the id of the
+/// last snapshot one exchange's fetches accepted, and the last id seen on its stream. The
+/// latter is kept here rather than read off the book, which forgets an exchange's ids when
+/// it is quarantined or its circuit opens.
+#[derive(Debug, Default)]
+pub struct StuckSnapshotGuard {
+    pub config: StuckSnapshotConfig,
+    last_snapshot_id: Option<u64>,
+    last_stream_id: Option<u64>,
+}
+
+impl StuckSnapshotGuard {
+    pub fn new(config: StuckSnapshotConfig) -> Self {
+        Self {
+            config,
+            last_snapshot_id: None,
+            last_stream_id: None,
+        }
+    }
+
+    /// Whether a snapshot with `snapshot_id` repeats the last one accepted while the stream
+    /// has moved well past it
+    pub fn is_stuck(&self, snapshot_id: u64) -> bool {
+        self.last_snapshot_id == Some(snapshot_id)
+            && self
+                .last_stream_id
+                .is_some_and(|id| id > snapshot_id.saturating_add(self.config.min_lead))
+    }
+
+    /// An update with `update_id` arrived on the stream
+    pub fn observe_stream(&mut self, update_id: u64) {
+        self.last_stream_id = Some(update_id);
+    }
+
+    pub fn last_stream_id(&self) -> Option<u64> {
+        self.last_stream_id
+    }
+
+    pub fn accept(&mut self, snapshot_id: u64) {
+        self.last_snapshot_id = Some(snapshot_id);
+    }
+
+    pub fn last_snapshot_id(&self) -> Option<u64> {
+        self.last_snapshot_id
+    }
+
+    /// The error for a fetch that gave up on `snapshot_id`
+    pub fn stuck_error(&self, exchange: &str, snapshot_id: u64) -> String {
+        format!(
+            "{} {}: id {} still repeats after {} cache-busting retries",
+            exchange, STUCK_SNAPSHOT, snapshot_id, self.config.max_retries
+        )
+    }
+}
+
+/// Whether a sync error came from a snapshot fetch that gave up on a stuck cache
+pub fn is_stuck_snapshot(error: &str) -> bool {
+    error.contains(STUCK_SNAPSHOT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_a_repeat_the_stream_has_left_behind_is_stuck() {
+        let mut guard = StuckSnapshotGuard::new(StuckSnapshotConfig {
+            min_lead: 1_000,
+            max_retries: 2,
+        });
+        // Nothing to repeat yet, nor a stream to have left it behind
+        assert!(!guard.is_stuck(5_000));
+        guard.accept(5_000);
+        assert!(!guard.is_stuck(5_000));
+        guard.observe_stream(100_000);
+        assert!(guard.is_stuck(5_000));
+        // A new id is never stuck, and a quiet book can repeat its snapshot
+        assert!(!guard.is_stuck(7_000));
+        guard.observe_stream(5_900);
+        assert!(!guard.is_stuck(5_000));
+        assert_eq!(guard.last_stream_id(), Some(5_900));
+
+        let error = guard.stuck_error("bitstamp", 5_000);
+        assert!(is_stuck_snapshot(&error), "{}", error);
+        assert!(!is_stuck_snapshot(
+            "Bitstamp snapshot request failed: timed out"
+        ));
+    }
+}
@@ -0,0 +1,146 @@
+use std::future::Future;
+use std::sync::Mutex;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SyncState {
+    /// No snapshot applied, or the last sync failed
+    #[default]
+    Idle,
+    /// A snapshot fetch and merge is in flight
+    Syncing,
+    Live,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    state: SyncState,
+    resync_requested: bool,
+}
+
+/// Per-exchange sync token: at most one snapshot-and-merge runs at a time, and resyncs
+/// requested while one is in flight are coalesced into a single follow-up sync.
+#[derive(Debug, Default)]
+pub struct SyncTracker {
+    exchange: String,
+    inner: Mutex<Inner>,
+}
+
+impl SyncTracker {
+    pub fn new(exchange: &str) -> Self {
+        Self {
+            exchange: exchange.to_string(),
+            inner: Mutex::default(),
+        }
+    }
+
+    pub fn state(&self) -> SyncState {
+        self.inner.lock().unwrap().state
+    }
+
+    /// Take the token. Returns false, and leaves a resync request behind, if a sync is
+    /// already in flight
+    pub fn begin_sync(&self) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.state == SyncState::Syncing {
+            if !inner.resync_requested {
+                tracing::info!(
+                    "{} sync already in flight, queueing one resync",
+                    self.exchange
+                );
+            }
+            inner.resync_requested = true;
+            return false;
+        }
+        inner.state = SyncState::Syncing;
+        true
+    }
+
+    /// Release the token. Returns true if a resync was requested meanwhile; the request is
+    /// consumed and the caller should sync again.
+    pub fn finish_sync(&self, synced: bool) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        debug_assert_eq!(inner.state, SyncState::Syncing);
+        inner.state = if synced {
+            SyncState::Live
+        } else {
+            SyncState::Idle
+        };
+        std::mem::take(&mut inner.resync_requested)
+    }
+
+    /// Run `sync` unless one is already in flight, then repeat it once more for any resync
+    /// requested while it ran. Returns whether this call ran a sync at all.
+    pub async fn run<F, Fut>(&self, mut sync: F) -> bool
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = bool>,
+    {
+        if !self.begin_sync() {
+            return false;
+        }
+        loop {
+            let synced = sync().await;
+            if !self.finish_sync(synced) || !self.begin_sync() {
+                return true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::sync::{Notify, RwLock};
+
+    #[tokio::test]
+    async fn resyncs_during_a_sync_are_coalesced_into_one_fetch() {
+        let tracker = Arc::new(SyncTracker::new("binance"));
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let release = Arc::new(Notify::new());
+        // Stands in for the merged book: each sync replaces its last_update_id
+        let book = Arc::new(RwLock::new(Vec::<u64>::new()));
+
+        let sync = {
+            let (fetches, release, book) = (fetches.clone(), release.clone(), book.clone());
+            move || {
+                let (fetches, release, book) = (fetches.clone(), release.clone(), book.clone());
+                async move {
+                    let id = fetches.fetch_add(1, Ordering::SeqCst) as u64 + 1;
+                    if id == 1 {
+                        release.notified().await;
+                    }
+                    *book.write().await = vec![id * 100];
+                    true
+                }
+            }
+        };
+
+        let initial = {
+            let (tracker, sync) = (tracker.clone(), sync.clone());
+            tokio::spawn(async move { tracker.run(sync).await })
+        };
+        while tracker.state() != SyncState::Syncing {
+            tokio::task::yield_now().await;
+        }
+
+        // Two triggers back to back while the initial sync is still fetching
+        assert!(!tracker.run(sync.clone()).await);
+        assert!(!tracker.run(sync.clone()).await);
+        release.notify_one();
+        assert!(initial.await.unwrap());
+
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        assert_eq!(tracker.state(), SyncState::Live);
+        assert_eq!(*book.read().await, vec![200]);
+    }
+
+    #[test]
+    fn failed_sync_goes_back_to_idle() {
+        let tracker = SyncTracker::new("bitstamp");
+        assert!(tracker.begin_sync());
+        assert!(!tracker.finish_sync(false));
+        assert_eq!(tracker.state(), SyncState::Idle);
+    }
+}
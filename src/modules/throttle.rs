@@ -0,0 +1,126 @@
+use crate::modules::clock::SharedClock;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Derived metrics are streamed at a low rate; this is their default cap
+pub const GAUGE_PUBLISH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// When a derived metric is worth republishing
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ThrottleConfig {
+    /// Minimum time between two published values
+    pub min_interval: Duration,
+    /// A value is only published once it differs from the last published one by more than this
+    pub min_delta: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GaugeSample {
+    pub at: u64, // unix millis
+    pub value: f64,
+}
+
+/// Gauge for derived metrics computed on every book change. Values are offered as often as
+/// they're computed; only the ones passing the throttle are published to subscribers.
+#[derive(Debug)]
+pub struct ThrottledGauge {
+    config: ThrottleConfig,
+    clock: SharedClock,
+    published: watch::Sender<Option<GaugeSample>>,
+}
+
+impl ThrottledGauge {
+    pub fn new(clock: SharedClock, config: ThrottleConfig) -> Self {
+        Self {
+            config,
+            clock,
+            published: watch::channel(None).0,
+        }
+    }
+
+    /// Publish `value` if it moved by more than `min_delta` and `min_interval` has passed
+    /// since the last publish. Returns whether it was published.
+    pub fn offer(&self, value: f64) -> bool {
+        if !value.is_finite() {
+            return false;
+        }
+        let now = self.clock.now_millis();
+        let due = self.latest().is_none_or(|last| {
+            (value - last.value).abs() > self.config.min_delta
+                && now.saturating_sub(last.at) >= self.config.min_interval.as_millis() as u64
+        });
+        if due {
+            self.published
+                .send_replace(Some(GaugeSample { at: now, value }));
+        }
+        due
+    }
+
+    /// Last published value
+    pub fn latest(&self) -> Option<GaugeSample> {
+        *self.published.borrow()
+    }
+
+    /// Receiver notified on every publish
+    pub fn subscribe(&self) -> watch::Receiver<Option<GaugeSample>> {
+        self.published.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::clock::TestTime;
+
+    fn gauge(time: &TestTime) -> ThrottledGauge {
+        ThrottledGauge::new(
+            time.shared(),
+            ThrottleConfig {
+                min_interval: Duration::from_secs(1),
+                min_delta: 0.05,
+            },
+        )
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn small_moves_are_suppressed() {
+        let time = TestTime::new(1_000);
+        let gauge = gauge(&time);
+        assert!(gauge.offer(0.10), "the first value is always published");
+        time.advance(Duration::from_secs(5)).await;
+        assert!(!gauge.offer(0.14));
+        assert!(!gauge.offer(0.05));
+        assert_eq!(gauge.latest().unwrap().value, 0.10);
+
+        assert!(gauge.offer(0.16));
+        assert_eq!(
+            gauge.latest(),
+            Some(GaugeSample {
+                at: 6_000,
+                value: 0.16
+            })
+        );
+        assert!(!gauge.offer(f64::NAN));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn publishes_at_most_once_per_interval() {
+        let time = TestTime::new(1_000);
+        let gauge = gauge(&time);
+        let mut published = gauge.subscribe();
+        assert!(gauge.offer(-0.5));
+        published.mark_unchanged();
+
+        // A large move on every change, one change every 100ms
+        let mut count = 0;
+        for i in 1..=30 {
+            time.advance(Duration::from_millis(100)).await;
+            if gauge.offer(-0.5 + i as f64 * 0.1) {
+                count += 1;
+            }
+        }
+        assert_eq!(count, 3, "one per second over 3s");
+        assert!(published.has_changed().unwrap());
+        assert_eq!(gauge.latest().unwrap().at, 4_000);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::modules::types::OrderLevel;
+use serde::Deserialize;
+use std::cmp::Ordering;
+
+/// Which exchange comes first when several quote the same price
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TieBreak {
+    /// By exchange name
+    #[default]
+    Alphabetical,
+    /// Larger amount first
+    LargerSize,
+    /// In the configured `venue_priority` order; unlisted exchanges after the listed ones
+    VenuePriority,
+}
+
+/// The one ordering of entries at the same price, used for ladder order, entry cuts and
+/// the exchange credited with the best price. Anything left tied goes by exchange name.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TieBreaker<'a> {
+    pub policy: TieBreak,
+    pub priority: &'a [String],
+}
+
+impl TieBreaker<'_> {
+    /// Preferred entry first
+    pub fn compare(&self, a: &OrderLevel, b: &OrderLevel) -> Ordering {
+        let preferred = match self.policy {
+            TieBreak::Alphabetical => Ordering::Equal,
+            TieBreak::LargerSize => b.amount.total_cmp(&a.amount),
+            TieBreak::VenuePriority => self
+                .rank(a.exchange.as_str())
+                .cmp(&self.rank(b.exchange.as_str())),
+        };
+        preferred.then_with(|| a.exchange.as_str().cmp(b.exchange.as_str()))
+    }
+
+    pub fn sort(&self, levels: &mut [OrderLevel]) {
+        levels.sort_by(|a, b| self.compare(a, b));
+    }
+
+    /// The entry to credit among `levels` at one price
+    pub fn best<'l>(
+        &self,
+        levels: impl IntoIterator<Item = &'l OrderLevel>,
+    ) -> Option<&'l OrderLevel> {
+        levels.into_iter().min_by(|a, b| self.compare(a, b))
+    }
+
+    fn rank(&self, exchange: &str) -> usize {
+        self.priority
+            .iter()
+            .position(|listed| listed == exchange)
+            .unwrap_or(self.priority.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::types::Exchange;
+
+    fn tie() -> Vec<OrderLevel> {
+        [
+            (Exchange::Bitstamp, 1.0),
+            (Exchange::BinanceUs, 3.0),
+            (Exchange::Binance, 2.0),
+        ]
+        .into_iter()
+        .map(|(exchange, amount)| OrderLevel {
+            exchange,
+            price: 0.05,
+            amount,
+        })
+        .collect()
+    }
+
+    fn order(tie_breaker: TieBreaker) -> Vec<&'static str> {
+        let mut levels = tie();
+        tie_breaker.sort(&mut levels);
+        let order: Vec<_> = levels.iter().map(|l| l.exchange.as_str()).collect();
+        let tied = tie();
+        assert_eq!(
+            tie_breaker.best(&tied).map(|l| l.exchange.as_str()),
+            order.first().copied(),
+            "the representative is the first in order"
+        );
+        order
+    }
+
+    #[test]
+    fn each_policy_orders_a_tie_deterministically() {
+        assert_eq!(
+            order(TieBreaker::default()),
+            ["binance", "binance_us", "bitstamp"]
+        );
+        assert_eq!(
+            order(TieBreaker {
+                policy: TieBreak::LargerSize,
+                priority: &[],
+            }),
+            ["binance_us", "binance", "bitstamp"]
+        );
+        let priority = ["bitstamp".to_string()];
+        assert_eq!(
+            order(TieBreaker {
+                policy: TieBreak::VenuePriority,
+                priority: &priority,
+            }),
+            ["bitstamp", "binance", "binance_us"],
+            "unlisted exchanges follow by name"
+        );
+
+        // Equal sizes fall back to the name
+        let mut levels = tie();
+        levels.iter_mut().for_each(|l| l.amount = 1.0);
+        TieBreaker {
+            policy: TieBreak::LargerSize,
+            priority: &[],
+        }
+        .sort(&mut levels);
+        assert_eq!(levels[0].exchange, Exchange::Binance);
+        assert_eq!(TieBreaker::default().best(&[]), None);
+    }
+}
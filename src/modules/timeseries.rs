@@ -0,0 +1,322 @@
+use crate::modules::clock::{SharedClock, system_clock};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+pub const DEFAULT_SERIES_CAPACITY: usize = 1024;
+
+/// How much of each series is kept: at most `max_samples` points, and with `max_age` only
+/// those that recent. A zero `max_samples` records nothing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Retention {
+    pub max_samples: usize,
+    pub max_age: Option<Duration>,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self {
+            max_samples: DEFAULT_SERIES_CAPACITY,
+            max_age: None,
+        }
+    }
+}
+
+/// A series is a metric `name` with a `label`: the exchange or symbol it is about, or empty
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SeriesKey {
+    pub name: String,
+    pub label: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Point {
+    pub at: u64, // unix millis
+    pub value: f64,
+}
+
+/// The points of a query falling in `[start, end)`, summarized
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bucket {
+    pub start: u64, // unix millis
+    pub end: u64,
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+    pub count: usize,
+}
+
+/// A series that can be queried, and the span of its retained points
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SeriesInfo {
+    pub key: SeriesKey,
+    pub points: usize,
+    pub first_at: u64,
+    pub last_at: u64,
+}
+
+/// Recent values of the metrics sampled around the process (spread, apply latency, update
+/// rates, book shape), kept under one retention policy and queried by name
+#[derive(Debug)]
+pub struct TimeSeriesStore {
+    clock: SharedClock,
+    retention: Retention,
+    series: Mutex<BTreeMap<SeriesKey, VecDeque<Point>>>,
+}
+
+impl Default for TimeSeriesStore {
+    fn default() -> Self {
+        Self::new(system_clock(), Retention::default())
+    }
+}
+
+impl TimeSeriesStore {
+    pub fn new(clock: SharedClock, retention: Retention) -> Self {
+        Self {
+            clock,
+            retention,
+            series: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn retention(&self) -> Retention {
+        self.retention
+    }
+
+    /// Append `value` to the series, as of now
+    pub fn record(&self, name: &str, label: &str, value: f64) {
+        self.record_at(name, label, self.clock.now_millis(), value);
+    }
+
+    /// Append `value` to the series, dropping the points it no longer retains. Non-finite
+    /// values are skipped.
+    pub fn record_at(&self, name: &str, label: &str, at: u64, value: f64) {
+        if self.retention.max_samples == 0 || !value.is_finite() {
+            return;
+        }
+        let key = SeriesKey {
+            name: name.to_string(),
+            label: label.to_string(),
+        };
+        let mut series = self.series.lock().unwrap();
+        let points = series.entry(key).or_default();
+        while points.len() >= self.retention.max_samples {
+            points.pop_front();
+        }
+        points.push_back(Point { at, value });
+        if let Some(max_age) = self.retention.max_age {
+            let oldest = at.saturating_sub(max_age.as_millis() as u64);
+            while points.front().is_some_and(|point| point.at < oldest) {
+                points.pop_front();
+            }
+        }
+    }
+
+    /// Every series with retained points, by name then label
+    pub fn list(&self) -> Vec<SeriesInfo> {
+        let series = self.series.lock().unwrap();
+        series
+            .iter()
+            .filter_map(|(key, points)| {
+                Some(SeriesInfo {
+                    key: key.clone(),
+                    points: points.len(),
+                    first_at: points.front()?.at,
+                    last_at: points.back()?.at,
+                })
+            })
+            .collect()
+    }
+
+    /// Points of the series within `[from, to]` (either end open if `None`), downsampled
+    /// into at most `max_points` equal-width time buckets; 0 keeps every point as its own
+    /// bucket. Empty buckets are left out. `None` for a series that was never recorded.
+    pub fn query(
+        &self,
+        key: &SeriesKey,
+        from: Option<u64>,
+        to: Option<u64>,
+        max_points: usize,
+    ) -> Option<Vec<Bucket>> {
+        let series = self.series.lock().unwrap();
+        let points: Vec<Point> = series
+            .get(key)?
+            .iter()
+            .filter(|point| from.is_none_or(|from| point.at >= from))
+            .filter(|point| to.is_none_or(|to| point.at <= to))
+            .copied()
+            .collect();
+        drop(series);
+        Some(downsample(&points, max_points))
+    }
+}
+
+/// Summarize `points`, oldest first, into at most `max_points` buckets
+pub fn downsample(points: &[Point], max_points: usize) -> Vec<Bucket> {
+    let (Some(first), Some(last)) = (points.first(), points.last()) else {
+        return vec![];
+    };
+    if max_points == 0 || points.len() <= max_points {
+        return points
+            .iter()
+            .map(|point| Bucket {
+                start: point.at,
+                end: point.at + 1,
+                min: point.value,
+                max: point.value,
+                avg: point.value,
+                count: 1,
+            })
+            .collect();
+    }
+    let span = last.at - first.at + 1;
+    let width = span.div_ceil(max_points as u64);
+    let mut buckets: Vec<Bucket> = vec![];
+    for point in points {
+        let start = first.at + (point.at - first.at) / width * width;
+        match buckets.last_mut() {
+            Some(bucket) if bucket.start == start => {
+                bucket.min = bucket.min.min(point.value);
+                bucket.max = bucket.max.max(point.value);
+                // Running total until the bucket is complete
+                bucket.avg += point.value;
+                bucket.count += 1;
+            }
+            _ => buckets.push(Bucket {
+                start,
+                end: start + width,
+                min: point.value,
+                max: point.value,
+                avg: point.value,
+                count: 1,
+            }),
+        }
+    }
+    for bucket in &mut buckets {
+        bucket.avg /= bucket.count as f64;
+    }
+    buckets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::clock::MockClock;
+    use std::sync::Arc;
+
+    fn key(name: &str, label: &str) -> SeriesKey {
+        SeriesKey {
+            name: name.to_string(),
+            label: label.to_string(),
+        }
+    }
+
+    #[test]
+    fn buckets_summarize_min_max_and_mean() {
+        // One point every 10ms from 1_000 to 1_090, valued 0 to 9
+        let points: Vec<Point> = (0..10)
+            .map(|i| Point {
+                at: 1_000 + i * 10,
+                value: i as f64,
+            })
+            .collect();
+        let buckets = downsample(&points, 3);
+        // 91ms split in 3 is 31ms per bucket: 4, 3 and 3 points
+        let summary: Vec<_> = buckets
+            .iter()
+            .map(|b| (b.start, b.end, b.min, b.max, b.avg, b.count))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (1_000, 1_031, 0.0, 3.0, 1.5, 4),
+                (1_031, 1_062, 4.0, 6.0, 5.0, 3),
+                (1_062, 1_093, 7.0, 9.0, 8.0, 3),
+            ]
+        );
+        assert_eq!(downsample(&points, 10).len(), 10, "nothing to merge");
+        assert_eq!(downsample(&points, 0).len(), 10);
+        assert_eq!(downsample(&points, 1)[0].avg, 4.5);
+        assert!(downsample(&[], 3).is_empty());
+    }
+
+    #[test]
+    fn queries_filter_by_range_and_skip_empty_buckets() {
+        let store = TimeSeriesStore::default();
+        for (at, value) in [(100, 1.0), (110, 3.0), (500, 10.0), (900, 2.0)] {
+            store.record_at("spread", "ethbtc", at, value);
+        }
+        store.record_at("spread", "btcusdt", 100, 7.0);
+
+        let spread = key("spread", "ethbtc");
+        let all = store.query(&spread, None, None, 0).unwrap();
+        assert_eq!(all.len(), 4);
+        let middle = store.query(&spread, Some(110), Some(500), 0).unwrap();
+        let values: Vec<_> = middle.iter().map(|b| b.avg).collect();
+        assert_eq!(values, vec![3.0, 10.0], "both ends are inclusive");
+
+        // 801ms in 3 buckets of 267ms, one point in each but the first
+        let buckets = store.query(&spread, None, None, 3).unwrap();
+        let counts: Vec<_> = buckets.iter().map(|b| (b.start, b.count)).collect();
+        assert_eq!(counts, vec![(100, 2), (367, 1), (634, 1)]);
+        assert_eq!(buckets[0].avg, 2.0);
+
+        assert_eq!(store.query(&key("spread", "ltcbtc"), None, None, 0), None);
+        let listed: Vec<_> = store
+            .list()
+            .into_iter()
+            .map(|info| (info.key.label, info.points, info.first_at, info.last_at))
+            .collect();
+        assert_eq!(
+            listed,
+            vec![
+                ("btcusdt".to_string(), 1, 100, 100),
+                ("ethbtc".to_string(), 4, 100, 900)
+            ]
+        );
+    }
+
+    #[test]
+    fn retention_caps_samples_and_age() {
+        let clock = Arc::new(MockClock::new(10_000));
+        let by_count = TimeSeriesStore::new(
+            clock.clone(),
+            Retention {
+                max_samples: 3,
+                max_age: None,
+            },
+        );
+        for value in 0..5 {
+            by_count.record("apply_latency_us", "binance", value as f64);
+        }
+        let kept = by_count
+            .query(&key("apply_latency_us", "binance"), None, None, 0)
+            .unwrap();
+        let values: Vec<_> = kept.iter().map(|b| b.avg).collect();
+        assert_eq!(values, vec![2.0, 3.0, 4.0]);
+
+        let by_age = TimeSeriesStore::new(
+            clock,
+            Retention {
+                max_samples: 100,
+                max_age: Some(Duration::from_secs(60)),
+            },
+        );
+        for at in [0, 30_000, 61_000, 90_000] {
+            by_age.record_at("update_rate", "bitstamp", at, 1.0);
+        }
+        by_age.record_at("update_rate", "bitstamp", 95_000, f64::NAN);
+        let info = &by_age.list()[0];
+        assert_eq!((info.points, info.first_at), (3, 30_000));
+
+        let off = TimeSeriesStore::new(
+            Arc::new(MockClock::new(0)),
+            Retention {
+                max_samples: 0,
+                max_age: None,
+            },
+        );
+        off.record("spread", "", 1.0);
+        assert!(off.list().is_empty());
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::{BTreeMap, HashSet};
+
+/// Volume one exchange is estimated to have traded, from levels at the top of its book that
+/// disappeared in a diff. Only an estimate: a cancel at the top looks the same as a fill.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TradedEstimate {
+    /// Removed from the top of the bids, i.e. sold into them
+    pub bid_volume: f64,
+    /// Removed from the top of the asks, i.e. bought from them
+    pub ask_volume: f64,
+    /// Diffs that removed top-of-book levels
+    pub events: u64,
+    pub last_at: Option<u64>, // unix millis
+}
+
+/// Cumulative estimates per exchange since `since`; kept across resyncs and resets
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TradedVolumeEstimator {
+    pub since: u64, // unix millis
+    pub exchanges: BTreeMap<String, TradedEstimate>,
+}
+
+impl TradedVolumeEstimator {
+    pub fn new(since: u64) -> Self {
+        Self {
+            since,
+            exchanges: BTreeMap::new(),
+        }
+    }
+
+    /// Count the amounts one diff removed from the top of `exchange`'s bids and asks
+    pub fn record(&mut self, exchange: &str, bid: f64, ask: f64, at: u64) {
+        if bid <= 0.0 && ask <= 0.0 {
+            return;
+        }
+        let estimate = self.exchanges.entry(exchange.to_string()).or_default();
+        estimate.bid_volume += bid;
+        estimate.ask_volume += ask;
+        estimate.events += 1;
+        estimate.last_at = Some(at);
+    }
+}
+
+/// Amount of the removed levels that were at the top of one exchange's side: its levels from
+/// the best down, for as long as each one is removed. A removal behind a level that stays
+/// was a cancel. `levels` are the exchange's (price index, amount) before the diff, best
+/// first; `removed` the price indices the diff removes.
+pub fn traded_through(
+    levels: impl IntoIterator<Item = (usize, f64)>,
+    removed: &HashSet<usize>,
+) -> f64 {
+    levels
+        .into_iter()
+        .take_while(|(idx, _)| removed.contains(idx))
+        .map(|(_, amount)| amount)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_a_run_of_removals_from_the_best_counts() {
+        let levels = [(103, 1.0), (102, 2.0), (101, 4.0)];
+        let removed = |indices: &[usize]| indices.iter().copied().collect::<HashSet<_>>();
+        assert_eq!(traded_through(levels, &removed(&[103])), 1.0);
+        assert_eq!(traded_through(levels, &removed(&[103, 102])), 3.0);
+        assert_eq!(
+            traded_through(levels, &removed(&[103, 101])),
+            1.0,
+            "101 was behind a level that stayed"
+        );
+        assert_eq!(traded_through(levels, &removed(&[102])), 0.0);
+        assert_eq!(
+            traded_through(levels, &removed(&[104])),
+            0.0,
+            "not in the book"
+        );
+        assert_eq!(traded_through([], &removed(&[103])), 0.0);
+
+        let mut estimator = TradedVolumeEstimator::new(1_000);
+        estimator.record("binance", 0.0, 0.0, 1_500);
+        assert!(estimator.exchanges.is_empty(), "nothing traded, no event");
+        estimator.record("binance", 3.0, 0.0, 2_000);
+        estimator.record("binance", 0.0, 0.5, 3_000);
+        assert_eq!(
+            estimator.exchanges["binance"],
+            TradedEstimate {
+                bid_volume: 3.0,
+                ask_volume: 0.5,
+                events: 2,
+                last_at: Some(3_000),
+            }
+        );
+    }
+}
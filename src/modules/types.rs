@@ -1,55 +1,176 @@
+use crate::config::SymbolConfig;
+use crate::modules::aggregated_orderbook::{
+    BoundaryPolicy, EmissionReason, ImbalanceGauge, LevelCounts, LevelOrigin, ResyncHold,
+    Tombstone, TombstoneConfig, Top10Snapshot,
+};
+use crate::modules::binance::BinanceVariant;
+use crate::modules::bitfinex;
+use crate::modules::bybit;
+use crate::modules::clock::SharedClock;
+use crate::modules::coinbase;
+use crate::modules::data_quality::QualityTracker;
+use crate::modules::gateio;
+use crate::modules::kraken;
+use crate::modules::kucoin;
+use crate::modules::log_limiter::LogLimiter;
+use crate::modules::okx;
+use crate::modules::sequence_reset::SequenceResetConfig;
+use crate::modules::spread_convention::SpreadConvention;
+use crate::modules::stats::StatsHistory;
+use crate::modules::timeseries::TimeSeriesStore;
+use crate::modules::traded_estimate::TradedVolumeEstimator;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Exchange {
+    #[default]
     Binance,
+    BinanceUs,
     Bitstamp,
+    Kraken,
+    Coinbase,
+    Okx,
+    Bybit,
+    Kucoin,
+    GateIo,
+    Bitfinex,
 }
 
 impl Exchange {
     pub fn as_str(&self) -> &'static str {
         match self {
             Exchange::Binance => "binance",
+            Exchange::BinanceUs => "binance_us",
             Exchange::Bitstamp => "bitstamp",
+            Exchange::Kraken => "kraken",
+            Exchange::Coinbase => "coinbase",
+            Exchange::Okx => "okx",
+            Exchange::Bybit => "bybit",
+            Exchange::Kucoin => "kucoin",
+            Exchange::GateIo => "gateio",
+            Exchange::Bitfinex => "bitfinex",
+        }
+    }
+}
+
+impl std::fmt::Display for Exchange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Exchange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "binance" => Ok(Exchange::Binance),
+            "binance_us" => Ok(Exchange::BinanceUs),
+            "bitstamp" => Ok(Exchange::Bitstamp),
+            "kraken" => Ok(Exchange::Kraken),
+            "coinbase" => Ok(Exchange::Coinbase),
+            "okx" => Ok(Exchange::Okx),
+            "bybit" => Ok(Exchange::Bybit),
+            "kucoin" => Ok(Exchange::Kucoin),
+            "gateio" => Ok(Exchange::GateIo),
+            "bitfinex" => Ok(Exchange::Bitfinex),
+            other => Err(format!("unknown exchange '{}'", other)),
         }
     }
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Side {
+    Bid,
+    Ask,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct OrderBook {
     pub last_update_id: u64,
     pub bids: Vec<OrderLevel>,
     pub asks: Vec<OrderLevel>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct OrderLevel {
-    pub exchange: &'static str,
+    pub exchange: Exchange,
     pub price: f64,
     pub amount: f64,
 }
 
-#[derive(Default, Debug)]
+#[derive(Debug)]
 pub struct AggregatedOrderBook {
-    pub spread: f64,
+    pub spread: Option<f64>, // best ask - best bid; unset unless both sides have levels
     pub bids: BTreeMap<usize, HashMap<String, OrderLevel>>, // price index -> { exchange -> level }
     pub asks: BTreeMap<usize, HashMap<String, OrderLevel>>, // price index -> { exchange -> level }
+    pub level_counts: LevelCounts, // levels per exchange on each side, kept with every insert/removal
     pub last_update_id: HashMap<String, u64>,
+    pub last_update_at: HashMap<String, u64>, // exchange -> unix millis of the last applied data
+    pub version: u64,                         // bumped on every applied change to the book
+    pub generation: u64, // bumped when the book is cleared; version keeps counting
+    pub epoch: u64, // unix millis the book was created or last cleared at; changes with every restart or reset
+    pub clock: SharedClock,
+    pub smoothing: Option<TombstoneConfig>,
+    pub tombstones: HashMap<(Side, usize, String), Tombstone>, // (side, price index, exchange) -> removed level
+    pub level_updated_at: HashMap<(Side, usize, String), u64>, // (side, price index, exchange) -> unix millis of the entry's last change
+    pub level_origins: HashMap<(Side, usize, String), LevelOrigin>, // (side, price index, exchange) -> update that last wrote the entry
+    pub raw_prices: HashMap<(Side, usize, String), f64>, // (side, price index, exchange) -> the exchange's own price, where snapping to the tick changed it
+    pub boundary_policy: Option<BoundaryPolicy>, // overrides each exchange's documented policy
+    pub awaiting_boundary: HashSet<String>, // exchanges whose first diff reaching the snapshot id hasn't arrived
+    pub awaiting_snapshot: HashSet<String>, // exchanges cleared by a reset; their diffs wait for a snapshot
+    pub config: SymbolConfig,
+    pub history: StatsHistory, // recent spread/index samples, one per applied change
+    pub shape_interval: Option<Duration>, // how often to sample book shape into the history
+    pub improvement_sampling: Option<(f64, Duration)>, // (clip size, interval) of price-improvement samples
+    pub imbalance: Option<ImbalanceGauge>, // top-N notional imbalance, offered on every applied change
+    pub traded_estimate: Option<TradedVolumeEstimator>, // volume estimated from top-of-book removals in diffs
+    pub resync_hold: Option<ResyncHold>, // publication held during a multi-exchange resync
+    pub sequence_reset: HashMap<String, SequenceResetConfig>, // exchange -> reset thresholds; unlisted use the defaults
+    pub last_event_at: HashMap<String, u64>, // exchange -> newest event time applied, unix millis
+    pub configured_exchanges: Vec<String>, // exchanges the symbol aggregates; empty to judge data quality by those that synced
+    pub quality: QualityTracker,           // data quality last published
+    pub spread_convention: SpreadConvention, // how snapshots and the spread series report a crossed spread; `spread` stays signed
+    pub(crate) published: watch::Sender<Arc<Top10Snapshot>>, // latest whole-update snapshot
+    pub last_reason: EmissionReason,         // why the latest snapshot was published
+    pub log_limiter: Arc<LogLimiter>, // rate limits the warnings repeated on every bad update
+    pub timeseries: Arc<TimeSeriesStore>, // spread, index price and shape series for GetTimeSeries
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct OrderBookUpdate {
-    pub exchange: &'static str,
+    pub exchange: Exchange,
     pub update_id: u64,
+    /// First update id covered by the diff (Binance `U`); Bitstamp diffs carry only one id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_update_id: Option<u64>,
+    /// Unix millis the message was read off the socket; `None` for updates not from a feed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub received_at: Option<u64>,
+    /// Unix millis the exchange stamped the update with (Binance `E`, Bitstamp `timestamp`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event_time: Option<u64>,
     pub bids: Vec<OrderLevel>,
     pub asks: Vec<OrderLevel>,
 }
 
 impl OrderBookUpdate {
     pub fn from_binance_json(text: &str) -> Option<Self> {
+        Self::from_binance_variant_json(text, BinanceVariant::Global)
+    }
+
+    pub fn from_binance_variant_json(text: &str, variant: BinanceVariant) -> Option<Self> {
         let v: Value = serde_json::from_str(text).ok()?;
-        Self::parse_binance_diff(&v)
+        Self::parse_binance_diff(&v, variant.exchange())
     }
 
     pub fn from_bitstamp_json(text: &str) -> Option<Self> {
@@ -57,18 +178,348 @@ impl OrderBookUpdate {
         Self::parse_bitstamp(&v)
     }
 
+    /// Like `from_binance_variant_json`, but tells control messages (`Ok(None)`, e.g.
+    /// subscription responses) apart from messages that failed to parse (`Err(reason)`)
+    pub fn classify_binance_json(
+        text: &str,
+        variant: BinanceVariant,
+    ) -> Result<Option<Self>, String> {
+        let v: Value = serde_json::from_str(text).map_err(|e| format!("invalid JSON: {}", e))?;
+        if v.get("result").is_some() && v.get("id").is_some() {
+            return Ok(None);
+        }
+        Self::parse_binance_diff(&v, variant.exchange())
+            .map(Some)
+            .ok_or_else(|| "not a depth update: missing b/a level arrays".to_string())
+    }
+
+    /// Like `from_bitstamp_json`, but tells non-data events (`Ok(None)`, e.g.
+    /// subscription_succeeded) apart from messages that failed to parse (`Err(reason)`)
+    pub fn classify_bitstamp_json(text: &str) -> Result<Option<Self>, String> {
+        let v: Value = serde_json::from_str(text).map_err(|e| format!("invalid JSON: {}", e))?;
+        match v.get("event").and_then(|e| e.as_str()) {
+            None => Err("missing event".to_string()),
+            Some("data") => Self::parse_bitstamp(&v)
+                .map(Some)
+                .ok_or_else(|| "malformed data event: missing bids/asks arrays".to_string()),
+            Some(_) => Ok(None),
+        }
+    }
+
+    pub fn from_kraken_json(text: &str) -> Option<Self> {
+        Self::classify_kraken_json(text).ok().flatten()
+    }
+
+    /// Parse a frame of Kraken's book channel: the snapshot sent on subscribing (`as`/`bs`)
+    /// or an update (`a`/`b`, possibly in two objects, with a checksum `c` that isn't
+    /// checked). Events such as heartbeats and subscription status are `Ok(None)`.
+    pub fn classify_kraken_json(text: &str) -> Result<Option<Self>, String> {
+        let v: Value = serde_json::from_str(text).map_err(|e| format!("invalid JSON: {}", e))?;
+        if v.get("event").is_some() {
+            return Ok(None);
+        }
+        let frame = v.as_array().ok_or("not an event or a channel frame")?;
+        let books: Vec<&Value> = frame.iter().filter(|part| part.is_object()).collect();
+        if books.is_empty() {
+            return Err("channel frame without book data".to_string());
+        }
+        let mut update = Self {
+            exchange: Exchange::Kraken,
+            ..Default::default()
+        };
+        for book in books {
+            for (key, side) in book.as_object().into_iter().flatten() {
+                let levels = match key.as_str() {
+                    "as" | "a" => &mut update.asks,
+                    "bs" | "b" => &mut update.bids,
+                    _ => continue,
+                };
+                let (parsed, newest) = kraken::parse_levels(side)
+                    .ok_or_else(|| format!("malformed {} levels", key))?;
+                levels.extend(parsed);
+                update.update_id = update.update_id.max(newest);
+            }
+        }
+        if update.bids.is_empty() && update.asks.is_empty() {
+            return Err("book frame without levels".to_string());
+        }
+        update.event_time = Some(update.update_id / 1_000);
+        Ok(Some(update))
+    }
+
+    pub fn from_coinbase_json(text: &str) -> Option<Self> {
+        Self::classify_coinbase_json(text).ok().flatten()
+    }
+
+    /// Parse a message of Coinbase's level2 channel: the `snapshot` sent on subscribing or an
+    /// `l2update` (`changes` of `[side, price, size]`, side `buy` or `sell`), with the
+    /// message `time` in microseconds as the id. `error` messages fail; other types, such as
+    /// `subscriptions` and heartbeats, are `Ok(None)`.
+    pub fn classify_coinbase_json(text: &str) -> Result<Option<Self>, String> {
+        let v: Value = serde_json::from_str(text).map_err(|e| format!("invalid JSON: {}", e))?;
+        let mut update = Self {
+            exchange: Exchange::Coinbase,
+            ..Default::default()
+        };
+        match v.get("type").and_then(|t| t.as_str()) {
+            None => return Err("missing type".to_string()),
+            Some("error") => {
+                return Err(format!(
+                    "error: {} ({})",
+                    v["message"].as_str().unwrap_or("no message"),
+                    v["reason"].as_str().unwrap_or("no reason")
+                ));
+            }
+            Some("snapshot") => {
+                let book = coinbase::parse_book(&v)
+                    .ok_or("malformed snapshot: missing bids/asks arrays")?;
+                update.update_id = book.last_update_id;
+                update.bids = book.bids;
+                update.asks = book.asks;
+            }
+            Some("l2update") => {
+                update.update_id = v["time"]
+                    .as_str()
+                    .and_then(coinbase::timestamp_micros)
+                    .ok_or("l2update without a valid time")?;
+                let changes = v["changes"].as_array().ok_or("l2update without changes")?;
+                for change in changes {
+                    let level = |i: usize| change[i].as_str()?.parse::<f64>().ok();
+                    let (Some(price), Some(amount)) = (level(1), level(2)) else {
+                        return Err(format!("malformed change {}", change));
+                    };
+                    let levels = match change[0].as_str() {
+                        Some("buy") => &mut update.bids,
+                        Some("sell") => &mut update.asks,
+                        _ => return Err(format!("change with unknown side {}", change[0])),
+                    };
+                    levels.push(OrderLevel {
+                        exchange: Exchange::Coinbase,
+                        price,
+                        amount,
+                    });
+                }
+            }
+            Some(_) => return Ok(None),
+        }
+        update.event_time = Some(update.update_id / 1_000);
+        Ok(Some(update))
+    }
+
+    pub fn from_okx_json(text: &str) -> Option<Self> {
+        Self::classify_okx_json(text).ok().flatten()
+    }
+
+    /// Parse a message of OKX's books channel: the `snapshot` sent on subscribing or an
+    /// `update`, with `seqId` as the id and `prevSeqId + 1` as the first id of an update.
+    /// An `error` event fails; other events, such as the `subscribe` ack, and updates that
+    /// only keep the connection alive (no levels, `seqId == prevSeqId`) are `Ok(None)`.
+    pub fn classify_okx_json(text: &str) -> Result<Option<Self>, String> {
+        let v: Value = serde_json::from_str(text).map_err(|e| format!("invalid JSON: {}", e))?;
+        match v.get("event").and_then(|e| e.as_str()) {
+            Some("error") => {
+                return Err(format!(
+                    "error {}: {}",
+                    v["code"].as_str().unwrap_or("without code"),
+                    v["msg"].as_str().unwrap_or("no message")
+                ));
+            }
+            Some(_) => return Ok(None),
+            None => {}
+        }
+        let action = v["action"].as_str().ok_or("missing action")?;
+        let book = &v["data"][0];
+        let update_id = book["seqId"].as_u64().ok_or("missing seqId")?;
+        let bids = okx::parse_levels(&book["bids"]).ok_or("malformed bids")?;
+        let asks = okx::parse_levels(&book["asks"]).ok_or("malformed asks")?;
+        let prev = book["prevSeqId"].as_i64();
+        let first_update_id = match action {
+            "snapshot" => None,
+            "update" if bids.is_empty() && asks.is_empty() && prev == Some(update_id as i64) => {
+                return Ok(None);
+            }
+            "update" => prev.and_then(|prev| u64::try_from(prev + 1).ok()),
+            other => return Err(format!("unknown action '{}'", other)),
+        };
+        Ok(Some(Self {
+            exchange: Exchange::Okx,
+            first_update_id,
+            update_id,
+            event_time: book["ts"].as_str().and_then(|ts| ts.parse().ok()),
+            bids,
+            asks,
+            ..Default::default()
+        }))
+    }
+
+    pub fn from_bybit_json(text: &str) -> Option<Self> {
+        Self::classify_bybit_json(text).ok().flatten()
+    }
+
+    /// Parse a message of Bybit's depth topic: the `snapshot` sent on subscribing or a
+    /// `delta`, with `u` as the id (a delta covers only its own). Replies to requests
+    /// (`op`) are `Ok(None)` unless they report a failure.
+    pub fn classify_bybit_json(text: &str) -> Result<Option<Self>, String> {
+        let v: Value = serde_json::from_str(text).map_err(|e| format!("invalid JSON: {}", e))?;
+        if let Some(op) = v.get("op").and_then(|op| op.as_str()) {
+            if v["success"] == false {
+                return Err(format!(
+                    "{} failed: {}",
+                    op,
+                    v["ret_msg"].as_str().unwrap_or("no message")
+                ));
+            }
+            return Ok(None);
+        }
+        let kind = v["type"].as_str().ok_or("missing type")?;
+        let book = &v["data"];
+        let update_id = book["u"].as_u64().ok_or("missing u")?;
+        let first_update_id = match kind {
+            "snapshot" => None,
+            "delta" => Some(update_id),
+            other => return Err(format!("unknown type '{}'", other)),
+        };
+        Ok(Some(Self {
+            exchange: Exchange::Bybit,
+            first_update_id,
+            update_id,
+            event_time: v["ts"].as_u64(),
+            bids: bybit::parse_levels(&book["b"]).ok_or("malformed bids")?,
+            asks: bybit::parse_levels(&book["a"]).ok_or("malformed asks")?,
+            ..Default::default()
+        }))
+    }
+
+    pub fn from_kucoin_json(text: &str) -> Option<Self> {
+        Self::classify_kucoin_json(text).ok().flatten()
+    }
+
+    /// Parse a message of KuCoin's level2 topic: an `l2update` delta covering
+    /// `sequenceStart..=sequenceEnd`, with the end as the id. An `error` fails; `welcome`,
+    /// `ack`, `pong` and other notices are `Ok(None)`.
+    pub fn classify_kucoin_json(text: &str) -> Result<Option<Self>, String> {
+        let v: Value = serde_json::from_str(text).map_err(|e| format!("invalid JSON: {}", e))?;
+        match v["type"].as_str().ok_or("missing type")? {
+            "message" => {}
+            "error" => {
+                return Err(format!(
+                    "error {}: {}",
+                    v["code"],
+                    v["data"].as_str().unwrap_or("no message")
+                ));
+            }
+            _ => return Ok(None),
+        }
+        if v["subject"] != "trade.l2update" {
+            return Err(format!("unknown subject {}", v["subject"]));
+        }
+        let delta = &v["data"];
+        Ok(Some(Self {
+            exchange: Exchange::Kucoin,
+            first_update_id: Some(
+                delta["sequenceStart"]
+                    .as_u64()
+                    .ok_or("missing sequenceStart")?,
+            ),
+            update_id: delta["sequenceEnd"].as_u64().ok_or("missing sequenceEnd")?,
+            event_time: delta["time"].as_u64(),
+            bids: kucoin::parse_levels(&delta["changes"]["bids"]).ok_or("malformed bids")?,
+            asks: kucoin::parse_levels(&delta["changes"]["asks"]).ok_or("malformed asks")?,
+            ..Default::default()
+        }))
+    }
+
+    pub fn from_gateio_json(text: &str) -> Option<Self> {
+        Self::classify_gateio_json(text).ok().flatten()
+    }
+
+    /// Parse a message of Gate.io's book channel: an `update` covering `U..u` like a
+    /// Binance diff, with `u` as the id. A reply carrying an `error` fails; other replies and
+    /// `spot.pong` are `Ok(None)`.
+    pub fn classify_gateio_json(text: &str) -> Result<Option<Self>, String> {
+        let v: Value = serde_json::from_str(text).map_err(|e| format!("invalid JSON: {}", e))?;
+        if let Some(error) = v.get("error").filter(|error| !error.is_null()) {
+            return Err(format!(
+                "error {}: {}",
+                error["code"],
+                error["message"].as_str().unwrap_or("no message")
+            ));
+        }
+        if v["event"] != "update" {
+            return Ok(None);
+        }
+        if v["channel"] != gateio::GATEIO_CHANNEL {
+            return Err(format!("unknown channel {}", v["channel"]));
+        }
+        let diff = &v["result"];
+        Ok(Some(Self {
+            exchange: Exchange::GateIo,
+            first_update_id: Some(diff["U"].as_u64().ok_or("missing U")?),
+            update_id: diff["u"].as_u64().ok_or("missing u")?,
+            event_time: diff["t"].as_u64(),
+            bids: gateio::parse_levels(&diff["b"]).ok_or("malformed bids")?,
+            asks: gateio::parse_levels(&diff["a"]).ok_or("malformed asks")?,
+            ..Default::default()
+        }))
+    }
+
+    pub fn from_bitfinex_json(text: &str) -> Option<Self> {
+        Self::classify_bitfinex_json(text).ok().flatten()
+    }
+
+    /// Parse a frame of Bitfinex's book channel, `[CHANNEL_ID, LEVELS, MTS]`: the snapshot
+    /// of levels sent on subscribing, or one changed `[PRICE, COUNT, AMOUNT]` level. The
+    /// `MTS` timestamp, appended since the `conf` message, is the id. An `error` event fails;
+    /// other events and heartbeats are `Ok(None)`.
+    pub fn classify_bitfinex_json(text: &str) -> Result<Option<Self>, String> {
+        let v: Value = serde_json::from_str(text).map_err(|e| format!("invalid JSON: {}", e))?;
+        let Some(frame) = v.as_array() else {
+            if v["event"] == "error" {
+                return Err(format!(
+                    "error {}: {}",
+                    v["code"],
+                    v["msg"].as_str().unwrap_or("no message")
+                ));
+            }
+            return Ok(None);
+        };
+        let levels = frame.get(1).ok_or("missing levels")?;
+        if levels.is_string() {
+            return Ok(None);
+        }
+        let update_id = frame
+            .get(2)
+            .and_then(Value::as_u64)
+            .ok_or("frame without a timestamp")?;
+        let levels = match levels.as_array().ok_or("malformed levels")? {
+            list if list.iter().all(Value::is_array) => list.iter().collect::<Vec<_>>(),
+            _ => vec![levels],
+        };
+        let (bids, asks) = bitfinex::parse_levels(levels).ok_or("malformed levels")?;
+        Ok(Some(Self {
+            exchange: Exchange::Bitfinex,
+            update_id,
+            event_time: Some(update_id),
+            bids,
+            asks,
+            ..Default::default()
+        }))
+    }
+
     // Parse the diff of the orderbook from Binance.
-    fn parse_binance_diff(v: &Value) -> Option<Self> {
+    fn parse_binance_diff(v: &Value, exchange: Exchange) -> Option<Self> {
         let bids = v.get("b")?.as_array()?;
         let asks = v.get("a")?.as_array()?;
         let update_id = v.get("u").and_then(|x| x.as_u64()).unwrap_or(0);
+        let first_update_id = v.get("U").and_then(|x| x.as_u64());
+        let event_time = v.get("E").and_then(|x| x.as_u64());
         let bids = bids
             .iter()
             .filter_map(|arr| {
                 let price = arr.get(0).and_then(|x| x.as_str())?.parse::<f64>().ok()?;
                 let amount = arr.get(1).and_then(|x| x.as_str())?.parse::<f64>().ok()?;
                 Some(OrderLevel {
-                    exchange: Exchange::Binance.as_str(),
+                    exchange,
                     price,
                     amount,
                 })
@@ -80,15 +531,18 @@ impl OrderBookUpdate {
                 let price = arr.get(0).and_then(|x| x.as_str())?.parse::<f64>().ok()?;
                 let amount = arr.get(1).and_then(|x| x.as_str())?.parse::<f64>().ok()?;
                 Some(OrderLevel {
-                    exchange: Exchange::Binance.as_str(),
+                    exchange,
                     price,
                     amount,
                 })
             })
             .collect();
         Some(Self {
-            exchange: Exchange::Binance.as_str(),
+            exchange,
             update_id,
+            first_update_id,
+            received_at: None,
+            event_time,
             bids,
             asks,
         })
@@ -104,6 +558,11 @@ impl OrderBookUpdate {
             .and_then(|x| x.as_str())
             .and_then(|s| s.parse::<u64>().ok())
             .unwrap_or(0);
+        let event_time = data
+            .get("timestamp")
+            .and_then(|x| x.as_str())
+            .and_then(|s| s.parse::<u64>().ok())
+            .map(|secs| secs * 1_000);
         let bids = data
             .get("bids")?
             .as_array()?
@@ -112,7 +571,7 @@ impl OrderBookUpdate {
                 let price = arr.get(0).and_then(|x| x.as_str())?.parse::<f64>().ok()?;
                 let amount = arr.get(1).and_then(|x| x.as_str())?.parse::<f64>().ok()?;
                 Some(OrderLevel {
-                    exchange: Exchange::Bitstamp.as_str(),
+                    exchange: Exchange::Bitstamp,
                     price,
                     amount,
                 })
@@ -126,15 +585,18 @@ impl OrderBookUpdate {
                 let price = arr.get(0).and_then(|x| x.as_str())?.parse::<f64>().ok()?;
                 let amount = arr.get(1).and_then(|x| x.as_str())?.parse::<f64>().ok()?;
                 Some(OrderLevel {
-                    exchange: Exchange::Bitstamp.as_str(),
+                    exchange: Exchange::Bitstamp,
                     price,
                     amount,
                 })
             })
             .collect();
         Some(Self {
-            exchange: Exchange::Bitstamp.as_str(),
+            exchange: Exchange::Bitstamp,
             update_id,
+            first_update_id: None,
+            received_at: None,
+            event_time,
             bids,
             asks,
         })
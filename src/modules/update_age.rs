@@ -0,0 +1,107 @@
+use crate::modules::clock::SharedClock;
+use crate::modules::types::OrderBookUpdate;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+pub const DEFAULT_MAX_UPDATE_AGE: Duration = Duration::from_secs(2);
+
+/// Drops updates that waited too long between the socket read and their application, so a
+/// backlog after a stall can't publish old prices as fresh
+#[derive(Debug)]
+pub struct UpdateAgeGuard {
+    clock: SharedClock,
+    max_age: Duration,
+    dropped: BTreeMap<String, u64>,
+    needs_resync: BTreeSet<String>,
+}
+
+impl UpdateAgeGuard {
+    pub fn new(clock: SharedClock, max_age: Duration) -> Self {
+        Self {
+            clock,
+            max_age,
+            dropped: BTreeMap::new(),
+            needs_resync: BTreeSet::new(),
+        }
+    }
+
+    pub fn max_age(&self) -> Duration {
+        self.max_age
+    }
+
+    /// Age of the update in millis if it is over the max age, counting it and marking its
+    /// exchange for resync: the dropped update leaves a gap in the sequence. Updates without
+    /// a receive time are never too old.
+    pub fn too_old(&mut self, update: &OrderBookUpdate) -> Option<u64> {
+        let age = self.clock.now_millis().saturating_sub(update.received_at?);
+        if age <= self.max_age.as_millis() as u64 {
+            return None;
+        }
+        let exchange = update.exchange.as_str();
+        *self.dropped.entry(exchange.to_string()).or_default() += 1;
+        self.needs_resync.insert(exchange.to_string());
+        Some(age)
+    }
+
+    /// Updates dropped as too old since startup
+    pub fn dropped(&self, exchange: &str) -> u64 {
+        self.dropped.get(exchange).copied().unwrap_or(0)
+    }
+
+    /// Exchanges that dropped an update since the last call
+    pub fn take_resync(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.needs_resync).into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::clock::MockClock;
+    use crate::modules::types::Exchange;
+    use crate::modules::update_queue::{self, OverflowPolicy};
+    use std::sync::Arc;
+
+    fn update(exchange: Exchange, update_id: u64) -> OrderBookUpdate {
+        OrderBookUpdate {
+            exchange,
+            update_id,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn updates_queued_past_the_max_age_are_dropped_and_resync() {
+        let clock = Arc::new(MockClock::new(1_700_000_000_000));
+        let (tx, mut rx) = update_queue::bounded(8, OverflowPolicy::Block);
+        let diffs = [1, 2].map(|id| update(Exchange::Binance, id));
+        update_queue::forward(futures_util::stream::iter(diffs), tx, clock.clone()).await;
+
+        // The aggregator stalls with both updates queued
+        clock.advance(Duration::from_millis(2_500));
+        let mut guard = UpdateAgeGuard::new(clock.clone(), DEFAULT_MAX_UPDATE_AGE);
+        while let Some((received_at, mut update)) = rx.recv().await {
+            update.received_at = Some(received_at);
+            assert_eq!(guard.too_old(&update), Some(2_500));
+        }
+        assert_eq!(guard.dropped("binance"), 2);
+        assert_eq!(guard.take_resync(), vec!["binance".to_string()]);
+        assert!(guard.take_resync().is_empty());
+    }
+
+    #[test]
+    fn updates_within_the_max_age_are_applied() {
+        let clock = Arc::new(MockClock::new(10_000));
+        let mut guard = UpdateAgeGuard::new(clock.clone(), DEFAULT_MAX_UPDATE_AGE);
+        let mut bitstamp = update(Exchange::Bitstamp, 1);
+        assert_eq!(guard.too_old(&bitstamp), None, "no receive time");
+        bitstamp.received_at = Some(8_000);
+        assert_eq!(guard.too_old(&bitstamp), None, "exactly the max age");
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(guard.too_old(&bitstamp), Some(2_001));
+        assert_eq!(
+            (guard.dropped("bitstamp"), guard.dropped("binance")),
+            (1, 0)
+        );
+    }
+}
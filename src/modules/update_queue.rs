@@ -0,0 +1,303 @@
+use crate::modules::clock::SharedClock;
+use futures_util::{Stream, StreamExt};
+use std::collections::VecDeque;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+/// What to do when the reader produces faster than the aggregator consumes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait for space, pushing backpressure onto the socket read
+    Block,
+    /// Discard the oldest queued item to make room (conflate)
+    DropOldest,
+    /// Discard the incoming item
+    DropNewest,
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('_', "-").as_str() {
+            "block" => Ok(OverflowPolicy::Block),
+            "drop-oldest" => Ok(OverflowPolicy::DropOldest),
+            "drop-newest" => Ok(OverflowPolicy::DropNewest),
+            other => Err(format!(
+                "unknown overflow policy '{}' (expected block, drop-oldest or drop-newest)",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QueueStats {
+    pub capacity: usize,
+    pub len: usize,
+    pub overflow_count: u64,
+    pub needs_resync: bool,
+}
+
+struct Shared<T> {
+    items: Mutex<VecDeque<T>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    not_empty: Notify,
+    not_full: Notify,
+    overflow_count: AtomicU64,
+    needs_resync: AtomicBool,
+    sender_closed: AtomicBool,
+    receiver_closed: AtomicBool,
+}
+
+impl<T> Shared<T> {
+    fn mark_overflow(&self) {
+        self.overflow_count.fetch_add(1, Ordering::Relaxed);
+        // A dropped item leaves a gap in the exchange's sequence, so the book must be rebuilt
+        self.needs_resync.store(true, Ordering::Release);
+    }
+
+    fn stats(&self) -> QueueStats {
+        QueueStats {
+            capacity: self.capacity,
+            len: self.items.lock().unwrap().len(),
+            overflow_count: self.overflow_count.load(Ordering::Relaxed),
+            needs_resync: self.needs_resync.load(Ordering::Acquire),
+        }
+    }
+}
+
+/// Producer side of a bounded per-exchange update queue
+pub struct UpdateSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Consumer side of a bounded per-exchange update queue
+pub struct UpdateReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Read-only view of a queue, usable after the receiver has been turned into a stream
+pub struct QueueHandle<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Clone for QueueHandle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+/// Create a bounded queue with the given capacity and overflow policy
+pub fn bounded<T>(capacity: usize, policy: OverflowPolicy) -> (UpdateSender<T>, UpdateReceiver<T>) {
+    assert!(capacity > 0, "update queue capacity must be greater than 0");
+    let shared = Arc::new(Shared {
+        items: Mutex::new(VecDeque::with_capacity(capacity)),
+        capacity,
+        policy,
+        not_empty: Notify::new(),
+        not_full: Notify::new(),
+        overflow_count: AtomicU64::new(0),
+        needs_resync: AtomicBool::new(false),
+        sender_closed: AtomicBool::new(false),
+        receiver_closed: AtomicBool::new(false),
+    });
+    (
+        UpdateSender {
+            shared: Arc::clone(&shared),
+        },
+        UpdateReceiver { shared },
+    )
+}
+
+impl<T> UpdateSender<T> {
+    /// Push an item according to the overflow policy. Returns the item back if the receiver is gone.
+    pub async fn send(&self, item: T) -> Result<(), T> {
+        let mut item = Some(item);
+        loop {
+            {
+                let mut items = self.shared.items.lock().unwrap();
+                if self.shared.receiver_closed.load(Ordering::Acquire) {
+                    return Err(item.take().unwrap());
+                }
+                if items.len() < self.shared.capacity {
+                    items.push_back(item.take().unwrap());
+                    self.shared.not_empty.notify_one();
+                    return Ok(());
+                }
+                match self.shared.policy {
+                    OverflowPolicy::Block => {}
+                    OverflowPolicy::DropOldest => {
+                        items.pop_front();
+                        items.push_back(item.take().unwrap());
+                        self.shared.mark_overflow();
+                        self.shared.not_empty.notify_one();
+                        return Ok(());
+                    }
+                    OverflowPolicy::DropNewest => {
+                        self.shared.mark_overflow();
+                        return Ok(());
+                    }
+                }
+            }
+            self.shared.not_full.notified().await;
+        }
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        self.shared.stats()
+    }
+}
+
+impl<T> Drop for UpdateSender<T> {
+    fn drop(&mut self) {
+        self.shared.sender_closed.store(true, Ordering::Release);
+        self.shared.not_empty.notify_one();
+    }
+}
+
+impl<T> UpdateReceiver<T> {
+    /// Pop the next item, waiting if the queue is empty. Returns None once the sender is gone and the queue is drained.
+    pub async fn recv(&mut self) -> Option<T> {
+        loop {
+            {
+                let mut items = self.shared.items.lock().unwrap();
+                if let Some(item) = items.pop_front() {
+                    self.shared.not_full.notify_one();
+                    return Some(item);
+                }
+                if self.shared.sender_closed.load(Ordering::Acquire) {
+                    return None;
+                }
+            }
+            self.shared.not_empty.notified().await;
+        }
+    }
+
+    pub fn handle(&self) -> QueueHandle<T> {
+        QueueHandle {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+
+    pub fn into_stream(self) -> impl Stream<Item = T> + Unpin {
+        Box::pin(futures_util::stream::unfold(self, |mut rx| async move {
+            let item = rx.recv().await?;
+            Some((item, rx))
+        }))
+    }
+}
+
+impl<T> Drop for UpdateReceiver<T> {
+    fn drop(&mut self) {
+        self.shared.receiver_closed.store(true, Ordering::Release);
+        self.shared.not_full.notify_one();
+    }
+}
+
+impl<T> QueueHandle<T> {
+    pub fn stats(&self) -> QueueStats {
+        self.shared.stats()
+    }
+
+    /// Returns true (once) if an item was dropped since the last call, meaning the exchange needs a resync
+    pub fn take_resync(&self) -> bool {
+        self.shared.needs_resync.swap(false, Ordering::AcqRel)
+    }
+}
+
+/// Forward every item of a stream into the queue, with the unix millis it was read at, until
+/// the stream ends or the receiver is dropped
+pub async fn forward<S, T>(mut stream: S, tx: UpdateSender<(u64, T)>, clock: SharedClock)
+where
+    S: Stream<Item = T> + Unpin,
+{
+    while let Some(item) = stream.next().await {
+        if tx.send((clock.now_millis(), item)).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::FutureExt;
+
+    #[tokio::test]
+    async fn block_policy_waits_for_space_without_dropping() {
+        let (tx, mut rx) = bounded(2, OverflowPolicy::Block);
+        tx.send(1).await.unwrap();
+        tx.send(2).await.unwrap();
+
+        // Consumer is stalled, so the third send must not complete
+        let mut pending = Box::pin(tx.send(3));
+        assert!(pending.as_mut().now_or_never().is_none());
+
+        assert_eq!(rx.recv().await, Some(1));
+        pending.await.unwrap();
+        assert_eq!(rx.recv().await, Some(2));
+        assert_eq!(rx.recv().await, Some(3));
+
+        let stats = rx.handle().stats();
+        assert_eq!(stats.overflow_count, 0);
+        assert!(!stats.needs_resync);
+    }
+
+    #[tokio::test]
+    async fn drop_oldest_policy_conflates_and_flags_resync() {
+        let (tx, mut rx) = bounded(2, OverflowPolicy::DropOldest);
+        for i in 1..=5 {
+            tx.send(i).await.unwrap();
+        }
+
+        let handle = rx.handle();
+        assert_eq!(handle.stats().overflow_count, 3);
+        assert_eq!(handle.stats().len, 2);
+        assert_eq!(rx.recv().await, Some(4));
+        assert_eq!(rx.recv().await, Some(5));
+
+        assert!(handle.take_resync());
+        assert!(!handle.take_resync());
+    }
+
+    #[tokio::test]
+    async fn drop_newest_policy_discards_incoming_and_flags_resync() {
+        let (tx, mut rx) = bounded(2, OverflowPolicy::DropNewest);
+        for i in 1..=5 {
+            tx.send(i).await.unwrap();
+        }
+
+        let handle = rx.handle();
+        assert_eq!(handle.stats().overflow_count, 3);
+        assert_eq!(rx.recv().await, Some(1));
+        assert_eq!(rx.recv().await, Some(2));
+        assert!(handle.take_resync());
+    }
+
+    #[tokio::test]
+    async fn closed_ends_are_reported_to_the_other_side() {
+        let (tx, rx) = bounded(1, OverflowPolicy::Block);
+        drop(rx);
+        assert_eq!(tx.send(7).await, Err(7));
+
+        let (tx, mut rx) = bounded(4, OverflowPolicy::Block);
+        tx.send(1).await.unwrap();
+        drop(tx);
+        assert_eq!(rx.recv().await, Some(1));
+        assert_eq!(rx.recv().await, None);
+    }
+
+    #[test]
+    fn overflow_policy_parses_from_cli_values() {
+        assert_eq!("block".parse(), Ok(OverflowPolicy::Block));
+        assert_eq!("drop-oldest".parse(), Ok(OverflowPolicy::DropOldest));
+        assert_eq!("drop_newest".parse(), Ok(OverflowPolicy::DropNewest));
+        assert!("conflate".parse::<OverflowPolicy>().is_err());
+    }
+}
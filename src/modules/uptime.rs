@@ -0,0 +1,255 @@
+use crate::modules::clock::{SharedClock, system_clock};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+pub const DEFAULT_UPTIME_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Activity {
+    pub connected: bool,
+    /// Connected and with levels in the book
+    pub contributing: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ExchangeUptime {
+    /// Fractions of the report range, 0 to 1
+    pub connected: f64,
+    pub contributing: f64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UptimeReport {
+    /// The range actually covered: never before process start or the retained window
+    pub from: u64, // unix millis
+    pub to: u64, // unix millis
+    pub exchanges: BTreeMap<String, ExchangeUptime>,
+    /// Spread averaged over the part of the range with spread samples
+    pub average_spread: Option<f64>,
+}
+
+#[derive(Debug, Default)]
+struct ExchangeTimeline {
+    connected: bool,
+    has_levels: bool,
+    transitions: VecDeque<(u64, Activity)>, // (unix millis, activity from then on)
+}
+
+/// Per-exchange connected/contributing history since process start, pruned to a window.
+/// Time with no recorded activity counts as neither connected nor contributing.
+#[derive(Debug)]
+pub struct UptimeLog {
+    clock: SharedClock,
+    window: Duration,
+    started_at: u64,
+    timelines: Mutex<BTreeMap<String, ExchangeTimeline>>,
+}
+
+impl Default for UptimeLog {
+    fn default() -> Self {
+        Self::new(system_clock(), DEFAULT_UPTIME_WINDOW)
+    }
+}
+
+impl UptimeLog {
+    pub fn new(clock: SharedClock, window: Duration) -> Self {
+        Self {
+            started_at: clock.now_millis(),
+            clock,
+            window,
+            timelines: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Unix millis the log, i.e. the process, started at
+    pub fn started_at(&self) -> u64 {
+        self.started_at
+    }
+
+    pub fn set_connected(&self, exchange: &str, connected: bool) {
+        self.update(exchange, |timeline| timeline.connected = connected);
+    }
+
+    /// Whether the exchange currently has levels in the book
+    pub fn set_contributing(&self, exchange: &str, has_levels: bool) {
+        self.update(exchange, |timeline| timeline.has_levels = has_levels);
+    }
+
+    /// Report over `[from, to]`, clipped to what the log still covers. `spreads` are
+    /// (unix millis, spread) samples, oldest first.
+    pub fn report(&self, from: u64, to: u64, spreads: &[(u64, f64)]) -> UptimeReport {
+        let retained_from = self
+            .clock
+            .now_millis()
+            .saturating_sub(self.window.as_millis() as u64);
+        let from = from.max(self.started_at).max(retained_from);
+        let to = to.max(from);
+        let timelines = self.timelines.lock().unwrap();
+        let exchanges = timelines
+            .iter()
+            .map(|(exchange, timeline)| {
+                let uptime = ExchangeUptime {
+                    connected: active_fraction(&timeline.transitions, from, to, |a| a.connected),
+                    contributing: active_fraction(&timeline.transitions, from, to, |a| {
+                        a.contributing
+                    }),
+                };
+                (exchange.clone(), uptime)
+            })
+            .collect();
+        UptimeReport {
+            from,
+            to,
+            exchanges,
+            average_spread: time_weighted_average(spreads, from, to),
+        }
+    }
+
+    fn update(&self, exchange: &str, f: impl FnOnce(&mut ExchangeTimeline)) {
+        let now = self.clock.now_millis();
+        let mut timelines = self.timelines.lock().unwrap();
+        let timeline = timelines.entry(exchange.to_string()).or_default();
+        f(timeline);
+        let activity = Activity {
+            connected: timeline.connected,
+            contributing: timeline.connected && timeline.has_levels,
+        };
+        if timeline.transitions.back().map(|(_, a)| *a) == Some(activity) {
+            return;
+        }
+        timeline.transitions.push_back((now, activity));
+        // Keep the last transition before the window: it gives the state at its start
+        let cutoff = now.saturating_sub(self.window.as_millis() as u64);
+        while timeline
+            .transitions
+            .get(1)
+            .is_some_and(|(at, _)| *at <= cutoff)
+        {
+            timeline.transitions.pop_front();
+        }
+    }
+}
+
+/// Fraction of `[from, to]` during which `active` held, per the step function `transitions`
+pub fn active_fraction(
+    transitions: &VecDeque<(u64, Activity)>,
+    from: u64,
+    to: u64,
+    active: impl Fn(&Activity) -> bool,
+) -> f64 {
+    if to <= from {
+        return 0.0;
+    }
+    let mut active_ms = 0;
+    for (i, (start, activity)) in transitions.iter().enumerate() {
+        let end = transitions.get(i + 1).map_or(to, |(at, _)| *at);
+        let (start, end) = ((*start).max(from), end.min(to));
+        if end > start && active(activity) {
+            active_ms += end - start;
+        }
+    }
+    active_ms as f64 / (to - from) as f64
+}
+
+/// Trapezoidal average of linearly interpolated `samples` over their overlap with
+/// `[from, to]`; `None` if they don't cover any of it
+pub fn time_weighted_average(samples: &[(u64, f64)], from: u64, to: u64) -> Option<f64> {
+    let at = |t: u64, (t0, v0): (u64, f64), (t1, v1): (u64, f64)| {
+        v0 + (v1 - v0) * (t - t0) as f64 / (t1 - t0) as f64
+    };
+    let (mut area, mut covered) = (0.0, 0);
+    for pair in samples.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        let (start, end) = (a.0.max(from), b.0.min(to));
+        if end <= start {
+            continue;
+        }
+        area += (at(start, a, b) + at(end, a, b)) / 2.0 * (end - start) as f64;
+        covered += end - start;
+    }
+    (covered > 0).then(|| area / covered as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::clock::{Clock, MockClock};
+    use std::sync::Arc;
+
+    #[test]
+    fn spread_is_integrated_with_the_trapezoid_rule() {
+        // 1.0 -> 3.0 over the first 10s, then flat at 3.0 for 10s
+        let samples = [(0, 1.0), (10_000, 3.0), (20_000, 3.0)];
+        assert_eq!(time_weighted_average(&samples, 0, 20_000), Some(2.5));
+        // Clipped to the middle of the ramp: 2.0 -> 3.0 then 3.0
+        assert_eq!(time_weighted_average(&samples, 5_000, 15_000), Some(2.75));
+        // Past the last sample nothing is known, so only the covered part counts
+        assert_eq!(time_weighted_average(&samples, 10_000, 60_000), Some(3.0));
+        assert_eq!(time_weighted_average(&samples, 30_000, 40_000), None);
+        assert_eq!(time_weighted_average(&samples[..1], 0, 20_000), None);
+    }
+
+    #[test]
+    fn uptime_follows_status_transitions_and_counts_gaps_as_down() {
+        let clock = Arc::new(MockClock::new(100_000));
+        let log = UptimeLog::new(clock.clone(), DEFAULT_UPTIME_WINDOW);
+        let step = |secs| clock.advance(Duration::from_secs(secs));
+
+        // binance: down 10s, connected 10s without levels, contributing 20s, disconnected 20s
+        step(10);
+        log.set_connected("binance", true);
+        step(10);
+        log.set_contributing("binance", true);
+        step(20);
+        log.set_connected("binance", false);
+        // bitstamp only shows up halfway through
+        log.set_connected("bitstamp", true);
+        log.set_contributing("bitstamp", true);
+        step(20);
+
+        let report = log.report(0, clock.now_millis(), &[]);
+        assert_eq!(report.from, 100_000, "the range starts at process start");
+        assert_eq!(report.to, 160_000);
+        assert_eq!(
+            report.exchanges["binance"],
+            ExchangeUptime {
+                connected: 0.5,
+                contributing: 1.0 / 3.0,
+            }
+        );
+        assert_eq!(
+            report.exchanges["bitstamp"],
+            ExchangeUptime {
+                connected: 1.0 / 3.0,
+                contributing: 1.0 / 3.0,
+            }
+        );
+        assert_eq!(report.average_spread, None);
+
+        // Levels left over from before a disconnect don't count as contributing
+        log.set_contributing("binance", true);
+        assert_eq!(
+            log.report(150_000, 160_000, &[]).exchanges["binance"].contributing,
+            0.0
+        );
+    }
+
+    #[test]
+    fn old_transitions_are_pruned_but_keep_the_state_at_the_window_start() {
+        let clock = Arc::new(MockClock::new(0));
+        let log = UptimeLog::new(clock.clone(), Duration::from_secs(60));
+        log.set_connected("binance", true);
+        for _ in 0..10 {
+            clock.advance(Duration::from_secs(30));
+            log.set_contributing("binance", true);
+            log.set_contributing("binance", false);
+        }
+        let transitions = log.timelines.lock().unwrap()["binance"].transitions.len();
+        assert!(transitions <= 5, "{} transitions kept", transitions);
+
+        let report = log.report(0, clock.now_millis(), &[]);
+        assert_eq!(report.from, 240_000);
+        assert_eq!(report.exchanges["binance"].connected, 1.0);
+    }
+}
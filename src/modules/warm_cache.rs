@@ -0,0 +1,240 @@
+use crate::modules::types::{AggregatedOrderBook, Exchange, OrderBook, OrderLevel};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+pub const WARM_CACHE_SCHEMA_VERSION: u32 = 1;
+
+/// Bitstamp, Kraken and Coinbase ids are microsecond timestamps; allow this much clock skew
+/// before one counts as being in the future
+const TIMESTAMP_ID_SKEW_MICROS: u64 = 60_000_000;
+
+/// One exchange's levels and sequencing state as of the save
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedBook {
+    pub last_update_id: Option<u64>,
+    pub bids: Vec<OrderLevel>,
+    pub asks: Vec<OrderLevel>,
+}
+
+/// The book persisted across a restart. Levels are only worth restoring together with the
+/// update id they reflect, so diffs already applied before the restart are skipped.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WarmCache {
+    pub schema_version: u32,
+    pub symbol: String,
+    pub saved_at: u64, // unix millis
+    pub book_version: u64,
+    pub exchanges: BTreeMap<String, CachedBook>,
+}
+
+/// What survived validation
+#[derive(Debug, Default)]
+pub struct Restored {
+    pub books: Vec<OrderBook>,
+    /// One reason per discarded exchange (or the whole cache)
+    pub discarded: Vec<String>,
+}
+
+impl WarmCache {
+    pub fn capture(book: &AggregatedOrderBook) -> Self {
+        let mut exchanges: BTreeMap<String, CachedBook> = BTreeMap::new();
+        let sides = [(&book.bids, true), (&book.asks, false)];
+        for (side, is_bid) in sides {
+            for bucket in side.values() {
+                for (exchange, level) in bucket {
+                    let cached = exchanges
+                        .entry(exchange.clone())
+                        .or_insert_with(|| CachedBook {
+                            last_update_id: book.last_update_id.get(exchange).copied(),
+                            bids: vec![],
+                            asks: vec![],
+                        });
+                    let levels = if is_bid {
+                        &mut cached.bids
+                    } else {
+                        &mut cached.asks
+                    };
+                    levels.push(level.clone());
+                }
+            }
+        }
+        Self {
+            schema_version: WARM_CACHE_SCHEMA_VERSION,
+            symbol: book.config.symbol.clone(),
+            saved_at: book.clock.now_millis(),
+            book_version: book.version,
+            exchanges,
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let json = serde_json::to_string(self)
+            .map_err(|e| format!("failed to serialize warm cache: {}", e))?;
+        // Write then rename so a crash mid-save never leaves a truncated cache behind
+        let tmp = format!("{}.tmp", path);
+        std::fs::write(&tmp, json)
+            .and_then(|_| std::fs::rename(&tmp, path))
+            .map_err(|e| format!("failed to write warm cache {}: {}", path, e))
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read warm cache {}: {}", path, e))?;
+        serde_json::from_str(&text).map_err(|e| format!("invalid warm cache {}: {}", path, e))
+    }
+
+    /// Books that are safe to merge for `symbol` at `now_micros`. An exchange is dropped if
+    /// its update id is missing or implausible; the whole cache if it doesn't match.
+    pub fn restore(self, symbol: &str, now_micros: u64) -> Restored {
+        let mut restored = Restored::default();
+        let whole_cache = if self.schema_version != WARM_CACHE_SCHEMA_VERSION {
+            Some(format!("schema version {}", self.schema_version))
+        } else if self.symbol != symbol.to_lowercase() {
+            Some(format!("saved for {}", self.symbol))
+        } else if self.saved_at > now_micros / 1_000 {
+            Some(format!("saved in the future at {}", self.saved_at))
+        } else if self.book_version == 0 && !self.exchanges.is_empty() {
+            Some("levels saved from a book that never applied a change".to_string())
+        } else {
+            None
+        };
+        if let Some(reason) = whole_cache {
+            restored
+                .discarded
+                .push(format!("warm cache discarded: {}", reason));
+            return restored;
+        }
+
+        for (name, cached) in self.exchanges {
+            let discard = match (Exchange::from_str(&name), cached.last_update_id) {
+                (Err(e), _) => Some(e),
+                (Ok(_), None) => Some("no update id stored".to_string()),
+                (Ok(_), Some(0)) => Some("update id 0".to_string()),
+                (Ok(Exchange::Bitstamp | Exchange::Kraken | Exchange::Coinbase), Some(id))
+                    if id > now_micros.saturating_add(TIMESTAMP_ID_SKEW_MICROS) =>
+                {
+                    Some(format!("timestamp id {} is in the future", id))
+                }
+                (Ok(_), Some(last_update_id)) => {
+                    restored.books.push(OrderBook {
+                        last_update_id,
+                        bids: cached.bids,
+                        asks: cached.asks,
+                    });
+                    None
+                }
+            };
+            if let Some(reason) = discard {
+                restored.discarded.push(format!("{}: {}", name, reason));
+            }
+        }
+        restored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AppConfig;
+    use crate::modules::clock::MockClock;
+    use std::sync::Arc;
+
+    const NOW_MILLIS: u64 = 1_700_000_000_000;
+
+    fn level(exchange: Exchange, price: f64, amount: f64) -> OrderLevel {
+        OrderLevel {
+            exchange,
+            price,
+            amount,
+        }
+    }
+
+    fn cache() -> WarmCache {
+        let mut book = AggregatedOrderBook::with_clock(Arc::new(MockClock::new(NOW_MILLIS)))
+            .with_config(AppConfig::default().resolve("ethbtc"));
+        book.merge_snapshots(vec![
+            OrderBook {
+                last_update_id: 42,
+                bids: vec![level(Exchange::Binance, 100.0, 1.0)],
+                asks: vec![level(Exchange::Binance, 101.0, 2.0)],
+            },
+            OrderBook {
+                last_update_id: NOW_MILLIS * 1_000 - 5,
+                bids: vec![level(Exchange::Bitstamp, 99.5, 3.0)],
+                asks: vec![],
+            },
+        ]);
+        WarmCache::capture(&book)
+    }
+
+    fn restore(cache: WarmCache) -> Restored {
+        cache.restore("ethbtc", NOW_MILLIS * 1_000)
+    }
+
+    #[test]
+    fn ids_round_trip_through_a_saved_cache() {
+        let path = std::env::temp_dir().join(format!("warm_cache_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let saved = cache();
+        saved.save(path).unwrap();
+        let loaded = WarmCache::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(loaded, saved);
+        assert_eq!(loaded.exchanges["binance"].last_update_id, Some(42));
+
+        let restored = restore(loaded);
+        assert!(restored.discarded.is_empty(), "{:?}", restored.discarded);
+        let ids: Vec<u64> = restored.books.iter().map(|b| b.last_update_id).collect();
+        assert_eq!(ids, vec![42, NOW_MILLIS * 1_000 - 5]);
+        assert_eq!(restored.books[0].asks[0].price, 101.0);
+    }
+
+    #[test]
+    fn exchanges_with_missing_or_implausible_ids_are_discarded() {
+        let mut missing = cache();
+        missing.exchanges.get_mut("binance").unwrap().last_update_id = None;
+        let restored = restore(missing);
+        assert_eq!(restored.discarded, vec!["binance: no update id stored"]);
+        assert_eq!(restored.books.len(), 1, "bitstamp is still restored");
+
+        let mut zero = cache();
+        zero.exchanges.get_mut("binance").unwrap().last_update_id = Some(0);
+        assert_eq!(restore(zero).discarded, vec!["binance: update id 0"]);
+
+        let mut future = cache();
+        let id = (NOW_MILLIS + 3_600_000) * 1_000;
+        future.exchanges.get_mut("bitstamp").unwrap().last_update_id = Some(id);
+        let restored = restore(future);
+        assert_eq!(
+            restored.discarded,
+            vec![format!("bitstamp: timestamp id {} is in the future", id)]
+        );
+        assert_eq!(restored.books[0].last_update_id, 42);
+
+        let mut unknown = cache();
+        let bitstamp = unknown.exchanges.remove("bitstamp").unwrap();
+        unknown.exchanges.insert("huobi".to_string(), bitstamp);
+        assert_eq!(restore(unknown).discarded.len(), 1);
+    }
+
+    #[test]
+    fn a_cache_for_another_symbol_schema_or_time_is_discarded_whole() {
+        let mut other = cache();
+        other.symbol = "btcusdt".to_string();
+        let mut schema = cache();
+        schema.schema_version = WARM_CACHE_SCHEMA_VERSION + 1;
+        let mut future = cache();
+        future.saved_at = NOW_MILLIS + 1;
+        let mut never_applied = cache();
+        never_applied.book_version = 0;
+        for cache in [other, schema, future, never_applied] {
+            let restored = restore(cache);
+            assert!(restored.books.is_empty());
+            assert_eq!(restored.discarded.len(), 1);
+            assert!(restored.discarded[0].starts_with("warm cache discarded"));
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use crate::modules::aggregated_orderbook::UpdateOutcome;
+use crate::modules::replay::{parse_exchange, parse_update};
+use crate::modules::types::{AggregatedOrderBook, Exchange, OrderBook, OrderBookUpdate};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::time::Duration;
+
+/// How far back the recorded tail of the last session is replayed on a warm start
+pub const DEFAULT_REPLAY_WINDOW: Duration = Duration::from_secs(60);
+
+/// One event of the recorded tail of the last session, in arrival order
+#[derive(Clone, Debug, PartialEq)]
+pub enum TailEvent {
+    /// Websocket text frame as received
+    Message {
+        at: u64, // unix millis
+        exchange: Exchange,
+        text: String,
+    },
+    /// The recorded session disconnected or resynced the exchange: diffs after it don't
+    /// follow on from those before
+    Break { at: u64, exchange: Exchange },
+}
+
+/// Parse the tail of a JSONL recording (see `replay::parse_recording`) whose lines also
+/// carry `at`, the unix millis they were recorded at. Snapshots and disconnects become
+/// breaks; `expected` lines are skipped.
+pub fn parse_tail(text: &str) -> Result<Vec<TailEvent>, String> {
+    let mut events = vec![];
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let err = |e: String| format!("line {}: {}", n + 1, e);
+        let v: Value = serde_json::from_str(line).map_err(|e| err(e.to_string()))?;
+        let event = v.get("event").and_then(|e| e.as_str());
+        if event == Some("expected") {
+            continue;
+        }
+        let exchange = parse_exchange(&v).map_err(err)?;
+        let at = v
+            .get("at")
+            .and_then(|at| at.as_u64())
+            .ok_or_else(|| err("missing at".into()))?;
+        match event {
+            Some("message") => events.push(TailEvent::Message {
+                at,
+                exchange,
+                text: v
+                    .get("payload")
+                    .ok_or_else(|| err("missing payload".into()))?
+                    .to_string(),
+            }),
+            Some("snapshot") | Some("disconnect") => events.push(TailEvent::Break { at, exchange }),
+            other => return Err(err(format!("unknown event {:?}", other))),
+        }
+    }
+    Ok(events)
+}
+
+/// How the tail went for one exchange of the warm cache
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExchangeReplay {
+    /// Update id of the cached book
+    pub cached_id: u64,
+    /// Update id the book reached after the replay
+    pub replayed_id: u64,
+    /// Diffs the cache already reflected
+    pub reflected: usize,
+    pub replayed: usize,
+    /// Diffs left out once the replay stopped
+    pub skipped: usize,
+    /// Why the replay stopped short, if it did
+    pub stopped: Option<String>,
+}
+
+/// A warm start: the cached books merged and the recorded tail replayed on top
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WarmStart {
+    pub exchanges: BTreeMap<String, ExchangeReplay>,
+    /// Diffs in the tail for exchanges that weren't cached; they sync from a snapshot
+    pub uncached: usize,
+}
+
+/// The live streams taking over from a warm start
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Handover {
+    pub applied: usize,
+    /// Buffered diffs the replay already covered
+    pub overlapping: usize,
+    /// Exchanges whose live stream doesn't follow on from the replayed book; their levels
+    /// are removed and they need a snapshot
+    pub resync: Vec<String>,
+}
+
+/// Whether `update` follows on from `last_id`: a Binance diff must cover `last_id + 1`;
+/// Bitstamp diffs only carry one id, so they follow on unless the stream broke since
+fn follows_on(update: &OrderBookUpdate, last_id: u64, broken: bool) -> bool {
+    match update.first_update_id {
+        Some(first) => first <= last_id + 1,
+        None => !broken,
+    }
+}
+
+impl WarmStart {
+    /// Merge `cached` (as restored from the warm cache) into `book`, then replay the tail
+    /// of the last session on top. Diffs the cache reflects are skipped; the rest are applied
+    /// while they were recorded within `window` of `now` (unix millis) and follow on from
+    /// the book. The first that doesn't ends that exchange's replay.
+    pub fn run(
+        book: &mut AggregatedOrderBook,
+        cached: Vec<OrderBook>,
+        tail: &[TailEvent],
+        now: u64,
+        window: Duration,
+    ) -> Self {
+        book.merge_snapshots(cached);
+        let mut warm = Self::default();
+        for (exchange, &id) in &book.last_update_id {
+            warm.exchanges.insert(
+                exchange.clone(),
+                ExchangeReplay {
+                    cached_id: id,
+                    replayed_id: id,
+                    ..Default::default()
+                },
+            );
+        }
+        let oldest = now.saturating_sub(window.as_millis() as u64);
+        let mut broken: HashSet<Exchange> = HashSet::new();
+        for event in tail {
+            let (at, exchange, text) = match event {
+                TailEvent::Break { exchange, .. } => {
+                    broken.insert(*exchange);
+                    continue;
+                }
+                TailEvent::Message { at, exchange, text } => (*at, *exchange, text),
+            };
+            let Some(update) = parse_update(exchange, text) else {
+                continue;
+            };
+            let Some(replay) = warm.exchanges.get_mut(exchange.as_str()) else {
+                warm.uncached += 1;
+                continue;
+            };
+            if replay.stopped.is_some() {
+                replay.skipped += 1;
+                continue;
+            }
+            if update.update_id <= replay.replayed_id {
+                replay.reflected += 1;
+                continue;
+            }
+            let stop = if at < oldest {
+                Some(format!(
+                    "update {} recorded at {} is older than the {}s replay window",
+                    update.update_id,
+                    at,
+                    window.as_secs()
+                ))
+            } else if !follows_on(&update, replay.replayed_id, broken.contains(&exchange)) {
+                Some(format!(
+                    "update {} doesn't follow on from {}",
+                    update.update_id, replay.replayed_id
+                ))
+            } else {
+                let update_id = update.update_id;
+                match book.apply_update(update) {
+                    Ok(UpdateOutcome::Applied) => {
+                        replay.replayed += 1;
+                        replay.replayed_id = update_id;
+                        broken.remove(&exchange);
+                        None
+                    }
+                    Ok(outcome) => Some(format!("update {} was {:?}", update_id, outcome)),
+                    Err(e) => Some(e),
+                }
+            };
+            if let Some(reason) = stop {
+                tracing::warn!("Warm start replay of {} stopped: {}", exchange, reason);
+                replay.skipped += 1;
+                replay.stopped = Some(reason);
+            }
+        }
+        for (exchange, replay) in &warm.exchanges {
+            tracing::info!(
+                exchange = exchange.as_str(),
+                cached_id = replay.cached_id,
+                replayed_id = replay.replayed_id,
+                reflected = replay.reflected,
+                replayed = replay.replayed,
+                skipped = replay.skipped,
+                "Warm start replayed {} recorded diffs",
+                replay.replayed
+            );
+        }
+        warm
+    }
+
+    /// Hand over to the live streams: apply the diffs buffered since they connected,
+    /// oldest first. Diffs the replay covered are dropped. An exchange whose first new diff
+    /// doesn't follow on (Bitstamp: whose buffer doesn't overlap the replay, as its ids
+    /// can't show a gap) has its levels removed and is listed for resync; later diffs of it
+    /// are left to the snapshot. Exchanges that weren't warm started are listed too.
+    pub fn hand_over(
+        &self,
+        book: &mut AggregatedOrderBook,
+        buffered: Vec<OrderBookUpdate>,
+    ) -> Handover {
+        let mut handover = Handover::default();
+        let mut overlapped: HashSet<Exchange> = HashSet::new();
+        for update in buffered {
+            let exchange = update.exchange;
+            if handover.resync.iter().any(|e| e == exchange.as_str()) {
+                continue;
+            }
+            let last_id = match book.last_update_id.get(exchange.as_str()) {
+                Some(&id) if self.exchanges.contains_key(exchange.as_str()) => id,
+                _ => {
+                    handover.resync.push(exchange.to_string());
+                    continue;
+                }
+            };
+            if update.update_id <= last_id {
+                handover.overlapping += 1;
+                overlapped.insert(exchange);
+                continue;
+            }
+            let update_id = update.update_id;
+            let applied = follows_on(&update, last_id, !overlapped.contains(&exchange))
+                && matches!(book.apply_update(update), Ok(UpdateOutcome::Applied));
+            if !applied {
+                tracing::warn!(
+                    "Live {} update {} doesn't follow on from the warm start at {}; resyncing",
+                    exchange,
+                    update_id,
+                    last_id
+                );
+                book.remove_exchange(exchange.as_str());
+                handover.resync.push(exchange.to_string());
+                continue;
+            }
+            handover.applied += 1;
+            overlapped.insert(exchange);
+        }
+        handover
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tail_lines_need_a_time_and_breaks_cover_resyncs() {
+        let tail = parse_tail(concat!(
+            r#"{"event":"message","exchange":"binance","at":1000,"payload":{"u":1}}"#,
+            "\n",
+            r#"{"event":"disconnect","exchange":"bitstamp","at":1001}"#,
+            "\n\n",
+            r#"{"event":"snapshot","exchange":"binance","at":1002,"body":{}}"#,
+            "\n",
+            r#"{"event":"expected","exchange":"binance","bids":[],"asks":[]}"#,
+        ))
+        .unwrap();
+        assert_eq!(
+            tail,
+            vec![
+                TailEvent::Message {
+                    at: 1000,
+                    exchange: Exchange::Binance,
+                    text: r#"{"u":1}"#.to_string()
+                },
+                TailEvent::Break {
+                    at: 1001,
+                    exchange: Exchange::Bitstamp
+                },
+                TailEvent::Break {
+                    at: 1002,
+                    exchange: Exchange::Binance
+                },
+            ]
+        );
+        let err = parse_tail(r#"{"event":"message","exchange":"binance","payload":{}}"#);
+        assert_eq!(err.unwrap_err(), "line 1: missing at");
+    }
+}
@@ -0,0 +1,163 @@
+use crate::handlers::Handlers;
+use crate::modules::aggregated_orderbook::DepthUnit;
+use futures::StreamExt;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+// JSON-RPC 2.0 error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// Outbound lines waiting for stdout; a slow reader pauses subscriptions instead of
+/// growing memory
+const OUTPUT_BUFFER: usize = 256;
+
+#[derive(Deserialize)]
+struct StdioRequest {
+    method: String,
+    #[serde(default)]
+    params: Params,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Params {
+    depth: Option<usize>,
+    #[serde(default)]
+    depth_unit: DepthUnit,
+}
+
+/// One stdin/stdout conversation: each request line gets one response line, and every
+/// subscription then writes `summary` notifications until the session ends
+pub struct StdioSession {
+    handlers: Handlers,
+    out: mpsc::Sender<String>,
+    next_subscription: u64,
+    subscriptions: Vec<JoinHandle<()>>,
+}
+
+impl Drop for StdioSession {
+    fn drop(&mut self) {
+        for subscription in &self.subscriptions {
+            subscription.abort();
+        }
+    }
+}
+
+impl StdioSession {
+    pub fn new(handlers: Handlers, out: mpsc::Sender<String>) -> Self {
+        Self {
+            handlers,
+            out,
+            next_subscription: 1,
+            subscriptions: vec![],
+        }
+    }
+
+    pub async fn handle_line(&mut self, line: &str) {
+        let value: Value = match serde_json::from_str(line) {
+            Ok(value) => value,
+            Err(e) => {
+                let message = format!("invalid JSON: {}", e);
+                return self.send(error(Value::Null, PARSE_ERROR, &message)).await;
+            }
+        };
+        let id = value.get("id").cloned().unwrap_or_default();
+        let request: StdioRequest = match serde_json::from_value(value) {
+            Ok(request) => request,
+            Err(e) => {
+                let message = format!("invalid request: {}", e);
+                return self.send(error(id, INVALID_REQUEST, &message)).await;
+            }
+        };
+        let Params { depth, depth_unit } = request.params;
+        let response = match request.method.as_str() {
+            "get_summary" => result(id, json!(self.handlers.summary(depth, depth_unit).await)),
+            "get_spread" => result(id, json!({ "spread": self.handlers.spread().await })),
+            "subscribe" => {
+                let subscription = self.next_subscription;
+                self.next_subscription += 1;
+                // Acknowledge before the first notification can be written
+                self.send(result(id, json!({ "subscription": subscription })))
+                    .await;
+                let summaries = self
+                    .handlers
+                    .subscribe(depth, depth_unit, None, false)
+                    .await;
+                let out = self.out.clone();
+                self.subscriptions.push(tokio::spawn(async move {
+                    let mut summaries = Box::pin(summaries);
+                    while let Some(snap) = summaries.next().await {
+                        let notification = json!({
+                            "jsonrpc": "2.0",
+                            "method": "summary",
+                            "params": {
+                                "subscription": subscription,
+                                "is_initial_snapshot": snap.is_initial_snapshot,
+                                "summary": snap,
+                            },
+                        });
+                        if out.send(notification.to_string()).await.is_err() {
+                            break;
+                        }
+                    }
+                }));
+                return;
+            }
+            other => error(id, METHOD_NOT_FOUND, &format!("unknown method {}", other)),
+        };
+        self.send(response).await;
+    }
+
+    async fn send(&self, message: Value) {
+        // The writer only goes away once the session is being torn down
+        let _ = self.out.send(message.to_string()).await;
+    }
+}
+
+fn result(id: Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+fn error(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+/// Serve line-delimited JSON requests from `input` until it closes
+pub async fn serve<R, W>(handlers: Handlers, input: R, mut output: W) -> Result<(), String>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let (tx, mut rx) = mpsc::channel::<String>(OUTPUT_BUFFER);
+    let writer = tokio::spawn(async move {
+        while let Some(line) = rx.recv().await {
+            output.write_all(line.as_bytes()).await?;
+            output.write_all(b"\n").await?;
+            output.flush().await?;
+        }
+        Ok::<(), std::io::Error>(())
+    });
+
+    let mut session = StdioSession::new(handlers, tx);
+    let mut lines = input.lines();
+    while let Some(line) = lines
+        .next_line()
+        .await
+        .map_err(|e| format!("failed to read stdin: {}", e))?
+    {
+        if !line.trim().is_empty() {
+            session.handle_line(&line).await;
+        }
+    }
+
+    drop(session);
+    writer
+        .await
+        .map_err(|e| format!("stdout writer failed: {}", e))?
+        .map_err(|e| format!("failed to write stdout: {}", e))
+}
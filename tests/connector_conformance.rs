@@ -0,0 +1,484 @@
+//! The contract every exchange connector must meet, run against a mock transport with the
+//! exchange's fixture bundle from `tests/fixtures/conformance`. A new connector adds its
+//! bundle and an arm to `connector`, then a test below.
+
+use std::fmt::Write;
+
+use futures_util::{SinkExt, StreamExt};
+use keyrock_mm_rust_task::modules::aggregated_orderbook::UpdateOutcome;
+use keyrock_mm_rust_task::modules::binance::{BinanceEndpoint, BinanceVariant};
+use keyrock_mm_rust_task::modules::bitfinex::BitfinexEndpoint;
+use keyrock_mm_rust_task::modules::bitstamp::BitstampEndpoint;
+use keyrock_mm_rust_task::modules::bybit::BybitEndpoint;
+use keyrock_mm_rust_task::modules::coinbase::CoinbaseEndpoint;
+use keyrock_mm_rust_task::modules::exchange_connector::{
+    BitfinexConnector, BitstampConnector, BybitConnector, ExchangeConnector, KucoinConnector,
+    OkxConnector,
+};
+use keyrock_mm_rust_task::modules::gateio::GateIoEndpoint;
+use keyrock_mm_rust_task::modules::kraken::KrakenEndpoint;
+use keyrock_mm_rust_task::modules::kucoin::KucoinEndpoint;
+use keyrock_mm_rust_task::modules::okx::OkxEndpoint;
+use keyrock_mm_rust_task::modules::router::{ControlKind, RoutedMessage};
+use keyrock_mm_rust_task::modules::stuck_snapshot::StuckSnapshotConfig;
+use keyrock_mm_rust_task::modules::types::{AggregatedOrderBook, Exchange, OrderLevel};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::Message;
+
+/// Recorded frames of one exchange and what the book must look like after them
+#[derive(Debug, Deserialize)]
+struct Bundle {
+    symbol: String,
+    snapshot: Value,
+    /// In arrival order; the first is older than the snapshot
+    diffs: Vec<Value>,
+    /// Acks and other frames that carry no update
+    ignored: Vec<Value>,
+    /// The exchange's request to reconnect, if it has one
+    reconnect_request: Option<Value>,
+    expected: Expected,
+}
+
+#[derive(Debug, Deserialize)]
+struct Expected {
+    snapshot_id: u64,
+    applied: Vec<u64>,
+    stale: Vec<u64>,
+    last_update_id: u64,
+    bids: Vec<(f64, f64)>,
+    asks: Vec<(f64, f64)>,
+}
+
+/// The connector of `exchange` pointed at the mock transport, and the name of its bundle.
+/// No wildcard: a new exchange doesn't compile until it has a suite.
+fn connector(exchange: Exchange, ws: String) -> (&'static str, Box<dyn ExchangeConnector>) {
+    match exchange {
+        Exchange::Binance | Exchange::BinanceUs => {
+            let variant = match exchange {
+                Exchange::BinanceUs => BinanceVariant::Us,
+                _ => BinanceVariant::Global,
+            };
+            let endpoint = BinanceEndpoint {
+                ws,
+                ..variant.endpoint()
+            };
+            ("binance", Box::new(endpoint))
+        }
+        Exchange::Bitstamp => {
+            let endpoint = BitstampEndpoint {
+                ws,
+                ..BitstampEndpoint::default()
+            };
+            let connector = BitstampConnector::new(endpoint, StuckSnapshotConfig::default());
+            ("bitstamp", Box::new(connector))
+        }
+        Exchange::Kraken => {
+            let endpoint = KrakenEndpoint {
+                ws,
+                ..KrakenEndpoint::default()
+            };
+            ("kraken", Box::new(endpoint))
+        }
+        Exchange::Coinbase => {
+            let endpoint = CoinbaseEndpoint {
+                ws,
+                ..CoinbaseEndpoint::default()
+            };
+            ("coinbase", Box::new(endpoint))
+        }
+        Exchange::Okx => {
+            let endpoint = OkxEndpoint {
+                ws,
+                ..OkxEndpoint::default()
+            };
+            ("okx", Box::new(OkxConnector::new(endpoint)))
+        }
+        Exchange::Bybit => {
+            let endpoint = BybitEndpoint {
+                ws,
+                ..BybitEndpoint::default()
+            };
+            ("bybit", Box::new(BybitConnector::new(endpoint)))
+        }
+        Exchange::Kucoin => {
+            let endpoint = KucoinEndpoint {
+                ws: Some(ws),
+                ..KucoinEndpoint::default()
+            };
+            ("kucoin", Box::new(KucoinConnector::new(endpoint)))
+        }
+        Exchange::GateIo => {
+            let endpoint = GateIoEndpoint {
+                ws,
+                ..GateIoEndpoint::default()
+            };
+            ("gateio", Box::new(endpoint))
+        }
+        Exchange::Bitfinex => {
+            let endpoint = BitfinexEndpoint {
+                ws,
+                ..BitfinexEndpoint::default()
+            };
+            ("bitfinex", Box::new(BitfinexConnector::new(endpoint)))
+        }
+    }
+}
+
+fn load_bundle(name: &str) -> Bundle {
+    let path = format!(
+        "{}/tests/fixtures/conformance/{}.json",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    );
+    let text = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+    serde_json::from_str(&text).unwrap_or_else(|e| panic!("{}: {}", path, e))
+}
+
+enum Frame {
+    Text(String),
+    Close,
+}
+
+/// A websocket server playing one script per connection, in order. Resolves to the text
+/// frames each connection sent, once the client has gone from every one.
+async fn mock_transport(scripts: Vec<Vec<Frame>>) -> (String, JoinHandle<Vec<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let server = tokio::spawn(async move {
+        let mut received = vec![];
+        for script in scripts {
+            let (socket, _) = listener.accept().await.unwrap();
+            let ws = tokio_tungstenite::accept_async(socket).await.unwrap();
+            let (mut sink, mut stream) = ws.split();
+            let reader = tokio::spawn(async move {
+                let mut texts = vec![];
+                while let Some(Ok(msg)) = stream.next().await {
+                    if let Message::Text(text) = msg {
+                        texts.push(text.to_string());
+                    }
+                }
+                texts
+            });
+            for frame in script {
+                let msg = match frame {
+                    Frame::Text(text) => Message::Text(text.into()),
+                    Frame::Close => Message::Close(None),
+                };
+                if sink.send(msg).await.is_err() {
+                    break;
+                }
+            }
+            received.push(reader.await.unwrap());
+        }
+        received
+    });
+    (url, server)
+}
+
+/// What the connector made of the mock transport
+#[derive(Default)]
+struct Session {
+    outcomes: Vec<(u64, UpdateOutcome)>,
+    /// Why each connection ended
+    ended: Vec<ControlKind>,
+}
+
+/// Connect once per script, sending the subscriptions and applying updates to `book`
+/// until the connection ends, like the live loop
+async fn run_session(
+    connector: &dyn ExchangeConnector,
+    symbol: &str,
+    connections: usize,
+    book: &mut AggregatedOrderBook,
+) -> Result<Session, String> {
+    let mut session = Session::default();
+    for _ in 0..connections {
+        let (mut ws, _) = tokio_tungstenite::connect_async(connector.stream_url(symbol))
+            .await
+            .map_err(|e| format!("connect failed: {}", e))?;
+        for text in connector.subscribe_messages(symbol) {
+            ws.send(Message::Text(text.into()))
+                .await
+                .map_err(|e| format!("subscribe failed: {}", e))?;
+        }
+        while let Some(msg) = ws.next().await {
+            let msg = msg.map_err(|e| format!("read failed: {}", e))?;
+            match connector.route(msg) {
+                RoutedMessage::Update(update) => {
+                    let id = update.update_id;
+                    session.outcomes.push((id, book.apply_update(update)?));
+                }
+                // Replaces the exchange's levels, like a REST snapshot
+                RoutedMessage::Snapshot(snapshot) => {
+                    book.merge_snapshots(vec![snapshot]);
+                }
+                RoutedMessage::Control(kind) if kind.ends_connection() => {
+                    session.ended.push(kind);
+                    break;
+                }
+                RoutedMessage::ParseFailure { reason, text } => {
+                    return Err(format!("{}: {}", reason, text));
+                }
+                RoutedMessage::Control(_) | RoutedMessage::FullBook(_) | RoutedMessage::Ignored => {
+                }
+            }
+        }
+    }
+    Ok(session)
+}
+
+enum Outcome {
+    Passed,
+    Skipped(&'static str),
+    Failed(String),
+}
+
+/// Outcome of each step of the scenario for one exchange
+struct Report {
+    exchange: Exchange,
+    steps: Vec<(&'static str, Outcome)>,
+}
+
+impl Report {
+    fn check(&mut self, step: &'static str, result: Result<(), String>) {
+        let outcome = match result {
+            Ok(()) => Outcome::Passed,
+            Err(reason) => Outcome::Failed(reason),
+        };
+        self.steps.push((step, outcome));
+    }
+
+    fn skip(&mut self, step: &'static str, why: &'static str) {
+        self.steps.push((step, Outcome::Skipped(why)));
+    }
+
+    fn assert_passed(self) {
+        let mut table = format!("{} conformance:\n", self.exchange);
+        let mut failed = 0;
+        for (step, outcome) in &self.steps {
+            let line = match outcome {
+                Outcome::Passed => "ok".to_string(),
+                Outcome::Skipped(why) => format!("skipped ({})", why),
+                Outcome::Failed(reason) => {
+                    failed += 1;
+                    format!("FAILED: {}", reason)
+                }
+            };
+            writeln!(table, "  {:<32} {}", step, line).unwrap();
+        }
+        println!("{}", table);
+        assert_eq!(failed, 0, "{}", table);
+    }
+}
+
+fn expect<T: PartialEq + std::fmt::Debug>(got: T, expected: T) -> Result<(), String> {
+    if got == expected {
+        Ok(())
+    } else {
+        Err(format!("got {:?}, expected {:?}", got, expected))
+    }
+}
+
+/// Run the standard scenario against `exchange`'s connector and bundle
+async fn conformance(exchange: Exchange) -> Report {
+    let mut report = Report {
+        exchange,
+        steps: vec![],
+    };
+    // Frames are parsed without a transport first
+    let (bundle_name, parser) = connector(exchange, String::new());
+    assert_eq!(
+        parser.exchange(),
+        exchange,
+        "connector of the wrong exchange"
+    );
+    let bundle = load_bundle(bundle_name);
+    let frame = |value: &Value| value.to_string();
+
+    // Parsing, frame by frame
+    let snapshot = parser.parse_snapshot(&frame(&bundle.snapshot));
+    report.check(
+        "snapshot parse",
+        match &snapshot {
+            None => Err("not parsed".to_string()),
+            Some(snapshot) => {
+                let tagged = snapshot
+                    .bids
+                    .iter()
+                    .chain(&snapshot.asks)
+                    .all(|level| level.exchange == exchange);
+                expect(
+                    (snapshot.last_update_id, tagged),
+                    (bundle.expected.snapshot_id, true),
+                )
+            }
+        },
+    );
+    let parsed: Result<Vec<u64>, String> = bundle
+        .diffs
+        .iter()
+        .map(
+            |diff| match parser.route(Message::Text(frame(diff).into())) {
+                RoutedMessage::Update(update) if update.exchange == exchange => {
+                    Ok(update.update_id)
+                }
+                RoutedMessage::Update(update) => Err(format!("attributed to {}", update.exchange)),
+                other => Err(format!("routed as {:?}", other)),
+            },
+        )
+        .collect();
+    let mut ids = bundle.expected.stale.clone();
+    ids.extend(&bundle.expected.applied);
+    report.check("diff parse", parsed.and_then(|parsed| expect(parsed, ids)));
+    let ignored = bundle.ignored.iter().try_for_each(|value| {
+        match parser.route(Message::Text(frame(value).into())) {
+            RoutedMessage::Ignored => Ok(()),
+            other => Err(format!("{} routed as {:?}", value, other)),
+        }
+    });
+    report.check("acks ignored", ignored);
+
+    // The live scenario: the first connection ends on the exchange's reconnect request (or
+    // a close), the second on a close
+    let half = bundle.diffs.len() / 2;
+    let connection = |diffs: &[Value], end: Frame| {
+        let mut script: Vec<Frame> = bundle
+            .ignored
+            .iter()
+            .map(|v| Frame::Text(frame(v)))
+            .collect();
+        script.extend(diffs.iter().map(|v| Frame::Text(frame(v))));
+        script.push(end);
+        script
+    };
+    let first_end = match &bundle.reconnect_request {
+        Some(request) => Frame::Text(frame(request)),
+        None => Frame::Close,
+    };
+    let scripts = vec![
+        connection(&bundle.diffs[..half], first_end),
+        connection(&bundle.diffs[half..], Frame::Close),
+    ];
+    let (url, transport) = mock_transport(scripts).await;
+    let (_, connector) = connector(exchange, url);
+    let mut book = AggregatedOrderBook::new();
+    book.config.symbol = bundle.symbol.clone();
+    if let Some(snapshot) = snapshot {
+        book.merge_snapshots(vec![snapshot]);
+    }
+    let session = match run_session(connector.as_ref(), &bundle.symbol, 2, &mut book).await {
+        Ok(session) => session,
+        Err(reason) => {
+            report.check("live session", Err(reason));
+            return report;
+        }
+    };
+    let received = transport.await.unwrap();
+
+    let subscriptions = connector.subscribe_messages(&bundle.symbol);
+    report.check(
+        "subscribe on every connection",
+        expect(received, vec![subscriptions.clone(), subscriptions]),
+    );
+    match bundle.reconnect_request {
+        Some(_) => report.check(
+            "reconnect on request",
+            expect(session.ended.first(), Some(&ControlKind::ReconnectRequest)),
+        ),
+        None => report.skip("reconnect on request", "the exchange has none"),
+    }
+    report.check(
+        "reconnect on close",
+        expect(session.ended.last(), Some(&ControlKind::Close)),
+    );
+    let by_outcome = |wanted: UpdateOutcome| -> Vec<u64> {
+        session
+            .outcomes
+            .iter()
+            .filter(|(_, outcome)| *outcome == wanted)
+            .map(|(id, _)| *id)
+            .collect()
+    };
+    report.check(
+        "sequencing",
+        expect(
+            (
+                by_outcome(UpdateOutcome::Applied),
+                by_outcome(UpdateOutcome::Stale),
+            ),
+            (bundle.expected.applied, bundle.expected.stale),
+        ),
+    );
+    let book = book.exchange_book(exchange.as_str(), usize::MAX);
+    let levels = |levels: &[OrderLevel]| -> Vec<(f64, f64)> {
+        levels
+            .iter()
+            .map(|level| (level.price, level.amount))
+            .collect()
+    };
+    report.check(
+        "removals and final book",
+        expect(
+            (book.last_update_id, levels(&book.bids), levels(&book.asks)),
+            (
+                bundle.expected.last_update_id,
+                bundle.expected.bids,
+                bundle.expected.asks,
+            ),
+        ),
+    );
+    report
+}
+
+#[tokio::test]
+async fn binance() {
+    conformance(Exchange::Binance).await.assert_passed();
+}
+
+#[tokio::test]
+async fn binance_us() {
+    conformance(Exchange::BinanceUs).await.assert_passed();
+}
+
+#[tokio::test]
+async fn bitstamp() {
+    conformance(Exchange::Bitstamp).await.assert_passed();
+}
+
+#[tokio::test]
+async fn kraken() {
+    conformance(Exchange::Kraken).await.assert_passed();
+}
+
+#[tokio::test]
+async fn coinbase() {
+    conformance(Exchange::Coinbase).await.assert_passed();
+}
+
+#[tokio::test]
+async fn okx() {
+    conformance(Exchange::Okx).await.assert_passed();
+}
+
+#[tokio::test]
+async fn bybit() {
+    conformance(Exchange::Bybit).await.assert_passed();
+}
+
+#[tokio::test]
+async fn kucoin() {
+    conformance(Exchange::Kucoin).await.assert_passed();
+}
+
+#[tokio::test]
+async fn gateio() {
+    conformance(Exchange::GateIo).await.assert_passed();
+}
+
+#[tokio::test]
+async fn bitfinex() {
+    conformance(Exchange::Bitfinex).await.assert_passed();
+}
@@ -0,0 +1,1253 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use keyrock_mm_rust_task::client::smoke;
+use keyrock_mm_rust_task::config::{AppConfig, GrpcListener, ListenAddr};
+use keyrock_mm_rust_task::grpc_error::{ErrorDetails, describe};
+use keyrock_mm_rust_task::grpc_listeners;
+use keyrock_mm_rust_task::grpc_service::orderbook::admin_client::AdminClient;
+use keyrock_mm_rust_task::grpc_service::orderbook::discovery_client::DiscoveryClient;
+use keyrock_mm_rust_task::grpc_service::orderbook::market_data_client::MarketDataClient;
+use keyrock_mm_rust_task::grpc_service::orderbook::{
+    ConnectorCommand, ConnectorCommandRequest, DepthCurveRequest, EmissionKind, EmissionReason,
+    Empty, MultiSummaryRequest, ParseFailuresRequest, PriceImprovementRequest, Readiness,
+    ResetSymbolRequest, Summary, SummaryRequest, TimeSeriesRequest,
+};
+use keyrock_mm_rust_task::grpc_service::{
+    OrderbookAggregatorService, create_grpc_server, create_grpc_server_with_auth,
+};
+use keyrock_mm_rust_task::modules::clock::MockClock;
+use keyrock_mm_rust_task::modules::commands;
+use keyrock_mm_rust_task::modules::dedup::{DedupConfig, SummaryDedup};
+use keyrock_mm_rust_task::modules::one_sided::OneSidedSummaries;
+use keyrock_mm_rust_task::modules::quote::QuoteConverter;
+use keyrock_mm_rust_task::modules::status::{ConnectionState, StatusRegistry};
+use keyrock_mm_rust_task::modules::throttle::{GAUGE_PUBLISH_INTERVAL, ThrottleConfig};
+use keyrock_mm_rust_task::modules::types::{
+    AggregatedOrderBook, Exchange, OrderBook, OrderBookUpdate, OrderLevel,
+};
+use tokio::net::TcpListener;
+use tokio::sync::RwLock;
+use tonic::Code;
+use tonic::transport::Channel;
+
+fn level(exchange: Exchange, price: f64, amount: f64) -> OrderLevel {
+    OrderLevel {
+        exchange,
+        price,
+        amount,
+    }
+}
+
+fn service() -> OrderbookAggregatorService {
+    let mut book = AggregatedOrderBook::with_clock(Arc::new(MockClock::new(1_000)))
+        .with_config(AppConfig::default().resolve("ethbtc"));
+    book.merge_snapshots(vec![
+        OrderBook {
+            last_update_id: 10,
+            bids: vec![level(Exchange::Binance, 100.0, 1.0)],
+            asks: vec![level(Exchange::Binance, 101.0, 2.0)],
+        },
+        OrderBook {
+            last_update_id: 20,
+            bids: vec![level(Exchange::Bitstamp, 99.5, 3.0)],
+            asks: vec![level(Exchange::Bitstamp, 101.5, 4.0)],
+        },
+    ]);
+    OrderbookAggregatorService::new(Arc::new(RwLock::new(book)))
+}
+
+async fn start(with_admin: bool) -> Channel {
+    serve(service(), with_admin).await
+}
+
+/// Serve the public router on an ephemeral port and connect to it
+async fn serve(service: OrderbookAggregatorService, with_admin: bool) -> Channel {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr: SocketAddr = listener.local_addr().unwrap();
+    let incoming = async_stream::stream! {
+        loop {
+            yield listener.accept().await.map(|(stream, _)| stream);
+        }
+    };
+    let router = create_grpc_server(service, with_admin);
+    tokio::spawn(router.serve_with_incoming(incoming));
+    Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn disabled_admin_is_absent_while_market_data_still_serves() {
+    let channel = start(false).await;
+
+    let mut market_data = MarketDataClient::new(channel.clone());
+    let mut summaries = market_data
+        .book_summary(SummaryRequest::default())
+        .await
+        .unwrap()
+        .into_inner();
+    let summary = summaries.message().await.unwrap().unwrap();
+    assert_eq!(summary.symbol, "ethbtc");
+    assert_eq!(summary.bids[0].price, 100.0);
+
+    let symbols = DiscoveryClient::new(channel.clone())
+        .list_symbols(Empty {})
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(symbols.symbols[0].symbol, "ethbtc");
+
+    let mut admin = AdminClient::new(channel);
+    let err = admin
+        .get_parse_failures(ParseFailuresRequest::default())
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::Unimplemented);
+}
+
+#[tokio::test]
+async fn enabled_admin_shares_the_public_listener() {
+    let channel = start(true).await;
+    let failures = AdminClient::new(channel)
+        .get_parse_failures(ParseFailuresRequest::default())
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(failures.failures.is_empty());
+}
+
+#[tokio::test]
+async fn connector_commands_are_routed_to_the_registered_feed() {
+    let service = service();
+    let mut binance = service.status.commands.register("binance", "ethbtc");
+    let channel = serve(service, true).await;
+    let mut admin = AdminClient::new(channel);
+
+    admin
+        .send_connector_command(ConnectorCommandRequest {
+            exchange: "Binance".to_string(),
+            command: ConnectorCommand::Pause as i32,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        binance.recv().await,
+        Some(commands::ConnectorCommand::Pause)
+    );
+
+    let err = admin
+        .send_connector_command(ConnectorCommandRequest {
+            exchange: "bitstamp".to_string(),
+            symbol: "ethbtc".to_string(),
+            command: ConnectorCommand::Resync as i32,
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::FailedPrecondition);
+    let err = admin
+        .send_connector_command(ConnectorCommandRequest {
+            exchange: "binance".to_string(),
+            command: ConnectorCommand::SetStreamSpeed as i32,
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::InvalidArgument);
+
+    drop(binance);
+    let err = admin
+        .send_connector_command(ConnectorCommandRequest {
+            exchange: "binance".to_string(),
+            command: ConnectorCommand::ReconnectNow as i32,
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    assert!(err.message().contains("has exited"), "{}", err.message());
+}
+
+#[tokio::test]
+async fn resetting_a_symbol_clears_the_book_resyncs_its_feeds_and_marks_streams() {
+    let service = service();
+    let mut binance = service.status.commands.register("binance", "ethbtc");
+    let mut bitstamp = service.status.commands.register("bitstamp", "ethbtc");
+    let mut other_symbol = service.status.commands.register("binance", "btcusdt");
+    let channel = serve(service, true).await;
+    let mut summaries = MarketDataClient::new(channel.clone())
+        .book_summary(SummaryRequest::default())
+        .await
+        .unwrap()
+        .into_inner();
+    let before = summaries.message().await.unwrap().unwrap();
+    assert_eq!((before.generation, before.bids.len()), (0, 2));
+    assert_eq!(before.epoch, 1_000, "the book's start time");
+
+    let mut admin = AdminClient::new(channel);
+    let report = admin
+        .reset_symbol(ResetSymbolRequest::default())
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(report.symbol, "ethbtc");
+    assert_eq!(report.generation, 1);
+    // The mock clock hasn't moved, the epoch still has
+    assert_eq!(report.epoch, 1_001);
+    assert_eq!(report.resynced, vec!["binance", "bitstamp"]);
+    for feed in [&mut binance, &mut bitstamp] {
+        assert_eq!(feed.recv().await, Some(commands::ConnectorCommand::Resync));
+    }
+    assert!(
+        other_symbol.try_recv().is_err(),
+        "other symbols are left alone"
+    );
+
+    let marker = summaries.message().await.unwrap().unwrap();
+    assert!(
+        marker.is_initial_snapshot,
+        "clients must discard their book"
+    );
+    assert_eq!((marker.generation, marker.epoch), (1, report.epoch));
+    assert!(
+        marker.version > before.version,
+        "the version keeps counting"
+    );
+    assert!(marker.bids.is_empty() && marker.asks.is_empty());
+    assert!(marker.last_update_ids.is_empty());
+
+    let err = admin
+        .reset_symbol(ResetSymbolRequest {
+            symbol: "btcusdt".to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::NotFound);
+}
+
+#[tokio::test]
+async fn streams_are_counted_from_open_to_close() {
+    let service = service();
+    let streams = Arc::clone(&service.status.streams);
+    let channel = serve(service, false).await;
+
+    let mut summaries = MarketDataClient::new(channel.clone())
+        .book_summary(SummaryRequest::default())
+        .await
+        .unwrap()
+        .into_inner();
+    summaries.message().await.unwrap().unwrap();
+    let report = DiscoveryClient::new(channel)
+        .get_status(Empty {})
+        .await
+        .unwrap()
+        .into_inner()
+        .streams
+        .unwrap();
+    assert_eq!((report.active_streams, report.streams_total), (1, 1));
+    assert_eq!(report.messages_sent_total["BookSummary"], 1);
+
+    // Dropping the stream cancels it on the server
+    drop(summaries);
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while streams.active_streams() > 0 {
+            tokio::task::yield_now().await;
+        }
+    })
+    .await
+    .expect("the server never saw the stream close");
+    assert_eq!(streams.streams_total(), 1);
+    assert_eq!(streams.methods()["BookSummary"].messages_sent, 1);
+}
+
+#[tokio::test]
+async fn stateful_streams_are_capped_and_their_state_reported() {
+    let per_stream = SummaryDedup::retained_state().bytes;
+    let dedup = DedupConfig {
+        heartbeat: Duration::from_secs(3600),
+        poll_interval: Duration::from_millis(5),
+    };
+    let status = StatusRegistry::default().with_stream_state_limit(Some(per_stream * 8));
+    let service = service().with_status(Arc::new(status)).with_dedup(dedup);
+    let streams = Arc::clone(&service.status.streams);
+    let channel = serve(service, false).await;
+    let open = || async {
+        MarketDataClient::new(channel.clone())
+            .book_summary(SummaryRequest::default())
+            .await
+    };
+
+    let mut open_streams = vec![];
+    for _ in 0..8 {
+        let mut stream = open().await.unwrap().into_inner();
+        stream.message().await.unwrap().unwrap();
+        open_streams.push(stream);
+    }
+    let err = open().await.unwrap_err();
+    assert_eq!(err.code(), Code::ResourceExhausted);
+    assert!(err.message().contains("state limit reached"), "{}", err);
+
+    let report = DiscoveryClient::new(channel.clone())
+        .get_status(Empty {})
+        .await
+        .unwrap()
+        .into_inner()
+        .streams
+        .unwrap();
+    assert_eq!(report.retained_state_bytes, per_stream * 8);
+    assert_eq!(report.retained_state_levels, 0);
+    assert_eq!(report.stateful_streams.len(), 8);
+    assert!(
+        report
+            .stateful_streams
+            .iter()
+            .all(|s| s.method == "BookSummary" && s.retained_bytes == per_stream)
+    );
+
+    // Closing streams frees their state for new ones
+    open_streams.truncate(5);
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while streams.retained_state().bytes > per_stream * 5 {
+            tokio::task::yield_now().await;
+        }
+    })
+    .await
+    .expect("the server never saw the streams close");
+    assert_eq!(streams.stateful_streams().len(), 5);
+    let mut reopened = open().await.unwrap().into_inner();
+    reopened.message().await.unwrap().unwrap();
+    assert_eq!(streams.retained_state().bytes, per_stream * 6);
+}
+
+#[tokio::test]
+async fn plain_streams_ignore_the_state_limit() {
+    let status = StatusRegistry::default().with_stream_state_limit(Some(0));
+    let service = service().with_status(Arc::new(status));
+    let streams = Arc::clone(&service.status.streams);
+    let channel = serve(service, false).await;
+    let mut open_streams = vec![];
+    for _ in 0..3 {
+        let mut stream = MarketDataClient::new(channel.clone())
+            .book_summary(SummaryRequest::default())
+            .await
+            .unwrap()
+            .into_inner();
+        stream.message().await.unwrap().unwrap();
+        open_streams.push(stream);
+    }
+    assert_eq!(streams.active_streams(), 3);
+    assert_eq!(streams.retained_state().bytes, 0);
+}
+
+#[tokio::test]
+async fn smoke_checks_pass_against_the_test_server() {
+    for with_admin in [true, false] {
+        let results = smoke::run(start(with_admin).await).await;
+        assert!(
+            results.iter().all(|r| r.passed()),
+            "{}",
+            smoke::render(&results)
+        );
+        assert_eq!(results.len(), 8);
+    }
+}
+
+/// Stands in for a live reference price; `None` is a missing or stale one
+#[derive(Debug)]
+struct FixedRate(Option<f64>);
+
+impl QuoteConverter for FixedRate {
+    fn currency(&self) -> &str {
+        "usd"
+    }
+
+    fn rate(&self) -> Option<f64> {
+        self.0
+    }
+}
+
+#[tokio::test]
+async fn depth_curve_notionals_are_converted_on_request() {
+    let request = |convert_notional| DepthCurveRequest {
+        symbol: String::new(),
+        points: 4,
+        range_bps: 200.0,
+        convert_notional,
+        ..Default::default()
+    };
+    let channel = serve(
+        service().with_converter(Arc::new(FixedRate(Some(2.0)))),
+        true,
+    )
+    .await;
+    let mut market_data = MarketDataClient::new(channel);
+
+    let plain = market_data
+        .get_depth_curve(request(false))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(plain.notional_currency, None);
+    assert!(!plain.conversion_unavailable);
+    // Best bids are 100.0 x 1.0 on Binance and 99.5 x 3.0 on Bitstamp
+    assert_eq!(plain.bids.last().unwrap().cumulative_notional, 398.5);
+
+    let converted = market_data
+        .get_depth_curve(request(true))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(converted.notional_currency.as_deref(), Some("usd"));
+    assert_eq!(converted.conversion_rate, Some(2.0));
+    assert!(!converted.conversion_unavailable);
+    for (plain, converted) in plain.bids.iter().zip(&converted.bids) {
+        assert_eq!(converted.cumulative_amount, plain.cumulative_amount);
+        assert_eq!(
+            converted.cumulative_notional,
+            plain.cumulative_notional * 2.0
+        );
+    }
+
+    // Without a usable rate the notionals stay in the quote currency, flagged
+    let channel = serve(service().with_converter(Arc::new(FixedRate(None))), true).await;
+    let stale = MarketDataClient::new(channel)
+        .get_depth_curve(request(true))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(stale.conversion_unavailable);
+    assert_eq!(stale.notional_currency, None);
+    assert_eq!(stale.bids, plain.bids);
+}
+
+#[tokio::test]
+async fn price_improvement_compares_the_book_with_each_venue() {
+    let mut market_data = MarketDataClient::new(start(false).await);
+    let request = |symbol: &str, clip_size| PriceImprovementRequest {
+        symbol: symbol.to_string(),
+        clip_size,
+    };
+    let improvement = market_data
+        .get_price_improvement(request("", 2.0))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!((improvement.at, improvement.clip_size), (1_000, 2.0));
+
+    // Bids: Binance 100.0 x 1.0 and Bitstamp 99.5 x 3.0
+    let bids = improvement.bids.unwrap();
+    assert_eq!((bids.best_price, bids.vwap), (Some(100.0), Some(99.75)));
+    let binance = &bids.venues["binance"];
+    assert_eq!(binance.best_delta, Some(0.0));
+    assert_eq!(
+        (binance.vwap, binance.vwap_delta),
+        (None, None),
+        "Binance alone can't fill the clip"
+    );
+    assert_eq!(bids.venues["bitstamp"].vwap_delta, Some(0.25));
+
+    // Asks: Binance 101.0 x 2.0 and Bitstamp 101.5 x 4.0
+    let asks = improvement.asks.unwrap();
+    let bitstamp = &asks.venues["bitstamp"];
+    assert_eq!(
+        (bitstamp.best_delta, bitstamp.vwap_delta),
+        (Some(0.5), Some(0.5))
+    );
+    assert_eq!(asks.venues["binance"].vwap_delta_bps, Some(0.0));
+
+    let status = market_data
+        .get_price_improvement(request("", 0.0))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    let status = market_data
+        .get_price_improvement(request("btcusd", 1.0))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+#[tokio::test]
+async fn refusals_carry_their_reason_field_and_sync_states_as_metadata() {
+    let details = |status: &tonic::Status| {
+        let details = ErrorDetails::from_status(status);
+        (details.reason.unwrap(), details.field)
+    };
+    let service = service();
+    let _binance = service.status.commands.register("binance", "ethbtc");
+    let channel = serve(service, true).await;
+    let mut market_data = MarketDataClient::new(channel.clone());
+    let depth_curve = |symbol: &str, points| DepthCurveRequest {
+        symbol: symbol.to_string(),
+        points,
+        range_bps: 100.0,
+        ..Default::default()
+    };
+
+    let status = market_data
+        .get_depth_curve(depth_curve("btcusd", 10))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    assert_eq!(
+        details(&status),
+        ("UNKNOWN_SYMBOL".to_string(), Some("symbol".to_string()))
+    );
+    let status = market_data
+        .get_depth_curve(depth_curve("", 0))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert_eq!(
+        details(&status),
+        ("DEPTH_OUT_OF_RANGE".to_string(), Some("points".to_string()))
+    );
+    let status = market_data
+        .get_price_improvement(PriceImprovementRequest {
+            symbol: String::new(),
+            clip_size: -1.0,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(
+        details(&status),
+        (
+            "INVALID_ARGUMENT".to_string(),
+            Some("clip_size".to_string())
+        )
+    );
+    let status = market_data.stream_imbalance(Empty {}).await.unwrap_err();
+    assert_eq!(details(&status), ("NOT_ENABLED".to_string(), None));
+
+    let status = AdminClient::new(channel)
+        .send_connector_command(ConnectorCommandRequest {
+            exchange: "bitstamp".to_string(),
+            command: ConnectorCommand::Resync as i32,
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    assert_eq!(
+        details(&status),
+        (
+            "EXCHANGE_DISABLED".to_string(),
+            Some("exchange".to_string())
+        )
+    );
+
+    // Before any exchange contributes, the book can't answer
+    let unsynced = OrderbookAggregatorService::new(Arc::new(RwLock::new(
+        AggregatedOrderBook::new().with_config(AppConfig::default().resolve("ethbtc")),
+    )));
+    unsynced
+        .status
+        .set_connection("binance", ConnectionState::Subscribing);
+    unsynced
+        .status
+        .set_connection("bitstamp", ConnectionState::NotConfigured);
+    let status = MarketDataClient::new(serve(unsynced, false).await)
+        .get_depth_curve(depth_curve("", 10))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unavailable);
+    assert_eq!(details(&status), ("NOT_SYNCED".to_string(), None));
+    assert_eq!(
+        ErrorDetails::from_status(&status).sync_states,
+        vec![
+            ("binance".to_string(), "SUBSCRIBING".to_string()),
+            ("bitstamp".to_string(), "NOT_CONFIGURED".to_string()),
+        ]
+    );
+    assert_eq!(
+        describe(&status),
+        "Unavailable: no exchange is synced yet [NOT_SYNCED binance=SUBSCRIBING bitstamp=NOT_CONFIGURED]"
+    );
+}
+
+async fn first_summary(channel: Channel, request: SummaryRequest) -> Summary {
+    let mut summaries = MarketDataClient::new(channel)
+        .book_summary(request)
+        .await
+        .unwrap()
+        .into_inner();
+    summaries.message().await.unwrap().unwrap()
+}
+
+#[tokio::test]
+async fn summary_checksums_match_the_ladder_as_sent() {
+    let channel = start(false).await;
+    for request in [
+        SummaryRequest::default(),
+        SummaryRequest {
+            cumulative: true,
+            display_decimals: Some(0),
+            ..Default::default()
+        },
+    ] {
+        let summary = first_summary(channel.clone(), request).await;
+        assert_ne!(summary.checksum, 0);
+        assert_eq!(smoke::check_checksum(&summary), Ok(summary.checksum));
+    }
+}
+
+#[tokio::test]
+async fn inverted_summaries_and_curves_flip_quote_and_base() {
+    let channel = start(false).await;
+    let plain = first_summary(channel.clone(), SummaryRequest::default()).await;
+    let request = SummaryRequest {
+        invert: true,
+        ..Default::default()
+    };
+    let inverted = first_summary(channel.clone(), request).await;
+    assert!(inverted.inverted && !plain.inverted);
+    // The best bid, 100.0 x 1.0 on Binance, is the best ask of the flipped book
+    let best = &inverted.asks[0];
+    assert_eq!(
+        (best.exchange.as_str(), best.price),
+        ("binance", 1.0 / 100.0)
+    );
+    assert_eq!(best.amount, 100.0);
+    assert_eq!(inverted.bids.len(), plain.asks.len());
+    assert_eq!(smoke::check_checksum(&inverted), Ok(inverted.checksum));
+
+    let mut market_data = MarketDataClient::new(channel);
+    let request = |convert_notional| DepthCurveRequest {
+        points: 4,
+        range_bps: 200.0,
+        convert_notional,
+        invert: true,
+        ..Default::default()
+    };
+    let curve = market_data
+        .get_depth_curve(request(false))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(curve.bids.iter().all(|p| p.price < 1.0));
+    assert_eq!(curve.asks.last().unwrap().cumulative_amount, 398.5);
+    let status = market_data
+        .get_depth_curve(request(true))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn level_details_are_only_sent_when_requested() {
+    let channel = start(false).await;
+
+    let plain = first_summary(channel.clone(), SummaryRequest::default()).await;
+    assert!(plain.bids.iter().all(|l| l.detail.is_none()));
+
+    let request = SummaryRequest {
+        include_level_details: true,
+        ..Default::default()
+    };
+    let detailed = first_summary(channel, request).await;
+    let detail = detailed.bids[0].detail.unwrap();
+    assert_eq!(detail.contributor_count, 1);
+    assert_eq!(
+        detail.newest_contribution_age_ms,
+        Some(0),
+        "the clock never moved"
+    );
+    assert!(detailed.asks.iter().all(|l| l.detail.is_some()));
+}
+
+#[tokio::test]
+async fn imbalance_is_streamed_and_reported_in_book_stats_when_enabled() {
+    let mut disabled = MarketDataClient::new(start(false).await);
+    let status = disabled.stream_imbalance(Empty {}).await.unwrap_err();
+    assert_eq!(status.code(), Code::Unimplemented);
+    let stats = disabled
+        .get_book_stats(Empty {})
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(stats.imbalance, None);
+
+    let throttle = ThrottleConfig {
+        min_interval: GAUGE_PUBLISH_INTERVAL,
+        min_delta: 0.05,
+    };
+    let mut book = AggregatedOrderBook::with_clock(Arc::new(MockClock::new(1_000)))
+        .with_config(AppConfig::default().resolve("ethbtc"))
+        .with_imbalance_gauge(10, throttle);
+    book.merge_snapshots(vec![OrderBook {
+        last_update_id: 10,
+        bids: vec![level(Exchange::Binance, 100.0, 3.0)],
+        asks: vec![level(Exchange::Binance, 100.0, 1.0)],
+    }]);
+    let service = OrderbookAggregatorService::new(Arc::new(RwLock::new(book)));
+    let mut client = MarketDataClient::new(serve(service, false).await);
+
+    let mut samples = client
+        .stream_imbalance(Empty {})
+        .await
+        .unwrap()
+        .into_inner();
+    let first = samples.message().await.unwrap().unwrap();
+    assert_eq!((first.at, first.value), (1_000, 0.5));
+    let stats = client.get_book_stats(Empty {}).await.unwrap().into_inner();
+    assert_eq!(stats.imbalance, Some(first));
+}
+
+#[tokio::test]
+async fn the_traded_volume_estimate_is_served_when_enabled() {
+    let mut disabled = MarketDataClient::new(start(false).await);
+    let status = disabled
+        .get_traded_volume_estimate(Empty {})
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unimplemented);
+
+    let mut book = AggregatedOrderBook::with_clock(Arc::new(MockClock::new(1_000)))
+        .with_config(AppConfig::default().resolve("ethbtc"))
+        .with_traded_volume_estimate();
+    book.merge_snapshots(vec![OrderBook {
+        last_update_id: 10,
+        bids: vec![
+            level(Exchange::Binance, 100.0, 1.5),
+            level(Exchange::Binance, 99.0, 2.0),
+        ],
+        asks: vec![level(Exchange::Binance, 101.0, 2.0)],
+    }]);
+    for (update_id, bids) in [
+        (11, vec![]),
+        (12, vec![level(Exchange::Binance, 100.0, 0.0)]),
+    ] {
+        book.handle_update(OrderBookUpdate {
+            exchange: Exchange::Binance,
+            update_id,
+            first_update_id: None,
+            received_at: None,
+            event_time: None,
+            bids,
+            asks: vec![],
+        })
+        .unwrap();
+    }
+    let service = OrderbookAggregatorService::new(Arc::new(RwLock::new(book)));
+    let mut client = MarketDataClient::new(serve(service, false).await);
+
+    let estimate = client
+        .get_traded_volume_estimate(Empty {})
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(estimate.since, 1_000);
+    let binance = &estimate.exchanges["binance"];
+    assert_eq!((binance.bid_volume, binance.ask_volume), (1.5, 0.0));
+    assert_eq!((binance.events, binance.last_at), (1, Some(1_000)));
+    let stats = client.get_book_stats(Empty {}).await.unwrap().into_inner();
+    assert_eq!(stats.traded_estimate, Some(estimate));
+}
+
+#[tokio::test]
+async fn status_reports_degraded_readiness_with_the_stragglers() {
+    let service = service();
+    let startup = &service.status.startup;
+    startup.expect(["binance", "bitstamp"]);
+    startup.synced("binance");
+    startup.failed("bitstamp", "connection refused");
+    startup.wait(Duration::ZERO).await;
+    let status = Arc::clone(&service.status);
+    let mut client = DiscoveryClient::new(serve(service, false).await);
+
+    let report = client.get_status(Empty {}).await.unwrap().into_inner();
+    assert_eq!(report.readiness(), Readiness::Degraded);
+    assert_eq!(
+        report.stragglers,
+        HashMap::from([("bitstamp".to_string(), "connection refused".to_string())])
+    );
+
+    status.startup.synced("bitstamp");
+    let report = client.get_status(Empty {}).await.unwrap().into_inner();
+    assert_eq!(report.readiness(), Readiness::Ready);
+    assert!(report.stragglers.is_empty());
+}
+
+#[tokio::test]
+async fn status_adds_up_queue_overflows_over_connections() {
+    let service = service();
+    // Two connections' queues, each starting from zero
+    service.status.record_queue_overflows("binance", 3);
+    service.status.record_queue_overflows("binance", 2);
+    let mut client = DiscoveryClient::new(serve(service, false).await);
+
+    let report = client.get_status(Empty {}).await.unwrap().into_inner();
+    let binance = report
+        .exchanges
+        .iter()
+        .find(|status| status.exchange == "binance")
+        .unwrap();
+    assert_eq!(binance.queue_overflows, 5);
+}
+
+#[tokio::test]
+async fn server_info_identifies_the_build_and_summaries_carry_it_when_stamped() {
+    let configured = service();
+    configured
+        .status
+        .set_connection("binance", ConnectionState::Connected);
+    configured
+        .status
+        .set_connection("bitstamp", ConnectionState::NotConfigured);
+    let channel = serve(configured, false).await;
+    let info = DiscoveryClient::new(channel.clone())
+        .get_server_info(Empty {})
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+    assert!(!info.git_hash.is_empty());
+    assert!(!info.rustc_version.is_empty());
+    assert!(info.built_at > 0);
+    assert!(info.features.contains(&"grpc".to_string()));
+    assert!(info.build_id.starts_with(&info.version));
+    assert_eq!(info.exchanges, vec!["binance"], "left out of the config");
+    assert_eq!(info.symbols, vec!["ethbtc"]);
+
+    let first = |channel| async move {
+        let mut stream = MarketDataClient::new(channel)
+            .book_summary(SummaryRequest::default())
+            .await
+            .unwrap()
+            .into_inner();
+        stream.message().await.unwrap().unwrap()
+    };
+    assert_eq!(first(channel).await.build_id, None);
+    let stamped = serve(service().with_build_id_stamp(), false).await;
+    assert_eq!(first(stamped).await.build_id, Some(info.build_id));
+}
+
+fn tcp_listener(addr: &str, auth_token: Option<&str>) -> GrpcListener {
+    GrpcListener {
+        addr: ListenAddr::Tcp(addr.parse().unwrap()),
+        tls: None,
+        auth_token: auth_token.map(str::to_string),
+    }
+}
+
+#[tokio::test]
+async fn every_listener_serves_the_same_book() {
+    let listeners = [
+        tcp_listener("127.0.0.1:0", None),
+        tcp_listener("[::1]:0", Some("s3cret")),
+    ];
+    let service = service();
+    let mut channels = vec![];
+    for listener in grpc_listeners::bind_all(&listeners).await.unwrap() {
+        let addr = listener.local_addr().unwrap();
+        let router = create_grpc_server_with_auth(
+            service.clone(),
+            false,
+            listener.config.auth_token.clone(),
+        );
+        tokio::spawn(listener.serve(router));
+        let channel = Channel::from_shared(format!("http://{}", addr))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        channels.push(channel);
+    }
+
+    let v4 = first_summary(channels[0].clone(), SummaryRequest::default()).await;
+    assert_eq!(v4.bids[0].price, 100.0);
+
+    // The IPv6 listener wants its token
+    let mut market_data = MarketDataClient::new(channels[1].clone());
+    let status = market_data
+        .book_summary(SummaryRequest::default())
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+    let mut request = tonic::Request::new(SummaryRequest::default());
+    request
+        .metadata_mut()
+        .insert("authorization", "Bearer s3cret".parse().unwrap());
+    let v6 = market_data
+        .book_summary(request)
+        .await
+        .unwrap()
+        .into_inner()
+        .message()
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!((v6.version, v6.checksum), (v4.version, v4.checksum));
+}
+
+#[tokio::test]
+async fn a_listener_that_cannot_bind_fails_them_all_and_is_named() {
+    let taken = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let taken = taken.local_addr().unwrap().to_string();
+    let err = grpc_listeners::bind_all(&[
+        tcp_listener("127.0.0.1:0", None),
+        tcp_listener(&taken, None),
+    ])
+    .await
+    .unwrap_err();
+    assert!(
+        err.contains(&format!("failed to bind gRPC listener {}", taken)),
+        "{}",
+        err
+    );
+}
+
+#[tokio::test]
+async fn each_summary_says_why_it_was_sent() {
+    let clock = Arc::new(MockClock::new(1_000));
+    let mut book = AggregatedOrderBook::with_clock(clock.clone())
+        .with_config(AppConfig::default().resolve("ethbtc"));
+    book.merge_snapshots(vec![OrderBook {
+        last_update_id: 10,
+        bids: vec![level(Exchange::Binance, 100.0, 1.0)],
+        asks: vec![level(Exchange::Binance, 101.0, 2.0)],
+    }]);
+    let book = Arc::new(RwLock::new(book));
+    let plain = serve(OrderbookAggregatorService::new(Arc::clone(&book)), false).await;
+    let deduplicated = serve(
+        OrderbookAggregatorService::new(Arc::clone(&book)).with_dedup(DedupConfig {
+            heartbeat: Duration::from_secs(5),
+            poll_interval: Duration::from_millis(5),
+        }),
+        false,
+    )
+    .await;
+    let connect = async |channel| {
+        MarketDataClient::new(channel)
+            .book_summary(SummaryRequest::default())
+            .await
+            .unwrap()
+            .into_inner()
+    };
+    let kind = |summary: &Summary| summary.reason.as_ref().unwrap().kind();
+
+    let mut summaries = connect(plain).await;
+    let mut heartbeats = connect(deduplicated).await;
+    for stream in [&mut summaries, &mut heartbeats] {
+        let initial = stream.message().await.unwrap().unwrap();
+        assert_eq!(kind(&initial), EmissionKind::InitialSnapshot);
+    }
+
+    book.write()
+        .await
+        .handle_update(OrderBookUpdate {
+            exchange: Exchange::Binance,
+            update_id: 11,
+            bids: vec![level(Exchange::Binance, 100.1, 1.0)],
+            ..Default::default()
+        })
+        .unwrap();
+    for stream in [&mut summaries, &mut heartbeats] {
+        let changed = stream.message().await.unwrap().unwrap();
+        assert_eq!(
+            changed.reason,
+            Some(EmissionReason {
+                kind: EmissionKind::BookChange as i32,
+                exchange: Some("binance".to_string()),
+                update_id: Some(11),
+            })
+        );
+    }
+
+    // Nothing changes; the deduplicating stream speaks up once the quiet period is over
+    clock.advance(Duration::from_secs(5));
+    let heartbeat = heartbeats.message().await.unwrap().unwrap();
+    assert_eq!(kind(&heartbeat), EmissionKind::Heartbeat);
+
+    let settings = book.read().await.config.settings.clone();
+    book.write().await.apply_settings(settings).unwrap();
+    assert_eq!(
+        kind(&summaries.message().await.unwrap().unwrap()),
+        EmissionKind::ConfigChange
+    );
+    book.write().await.merge_snapshots(vec![OrderBook {
+        last_update_id: 20,
+        bids: vec![level(Exchange::Bitstamp, 99.5, 3.0)],
+        asks: vec![],
+    }]);
+    assert_eq!(
+        kind(&summaries.message().await.unwrap().unwrap()),
+        EmissionKind::Resync
+    );
+    // A reset starts a new generation, which every stream resends in full
+    book.write().await.clear();
+    assert_eq!(
+        kind(&summaries.message().await.unwrap().unwrap()),
+        EmissionKind::InitialSnapshot
+    );
+}
+
+#[tokio::test]
+async fn every_stream_starts_with_a_marked_snapshot_of_the_current_version() {
+    let mut book = AggregatedOrderBook::with_clock(Arc::new(MockClock::new(1_000)))
+        .with_config(AppConfig::default().resolve("ethbtc"));
+    book.merge_snapshots(vec![OrderBook {
+        last_update_id: 10,
+        bids: vec![level(Exchange::Binance, 100.0, 1.0)],
+        asks: vec![level(Exchange::Binance, 101.0, 2.0)],
+    }]);
+    let book = Arc::new(RwLock::new(book));
+    // Dedup state is per stream; a reconnect still gets the unchanged book in full
+    let service = OrderbookAggregatorService::new(Arc::clone(&book)).with_dedup(DedupConfig {
+        heartbeat: Duration::from_secs(3600),
+        poll_interval: Duration::from_millis(5),
+    });
+    let channel = serve(service, false).await;
+    let mut update_id = 10;
+    let mut update = async |price: f64| {
+        update_id += 1;
+        book.write()
+            .await
+            .handle_update(OrderBookUpdate {
+                exchange: Exchange::Binance,
+                update_id,
+                first_update_id: None,
+                received_at: None,
+                event_time: None,
+                bids: vec![level(Exchange::Binance, price, 1.0)],
+                asks: vec![],
+            })
+            .unwrap();
+    };
+    let connect = || async {
+        MarketDataClient::new(channel.clone())
+            .book_summary(SummaryRequest::default())
+            .await
+            .unwrap()
+            .into_inner()
+    };
+
+    let mut first_stream = connect().await;
+    let initial = first_stream.message().await.unwrap().unwrap();
+    assert!(initial.is_initial_snapshot);
+    update(100.1).await;
+    let next = first_stream.message().await.unwrap().unwrap();
+    assert!(!next.is_initial_snapshot);
+    assert_eq!(next.version, initial.version + 1);
+    drop(first_stream);
+
+    // The book moves on while the client is away
+    update(100.2).await;
+    let mut second_stream = connect().await;
+    let resync = second_stream.message().await.unwrap().unwrap();
+    assert!(resync.is_initial_snapshot);
+    assert_eq!(resync.version, next.version + 1);
+    assert_eq!(resync.bids.len(), 3, "the full ladder, not a delta");
+
+    let mut third_stream = connect().await;
+    let unchanged = third_stream.message().await.unwrap().unwrap();
+    assert!(unchanged.is_initial_snapshot);
+    assert_eq!(unchanged.version, resync.version);
+
+    update(100.3).await;
+    for stream in [&mut second_stream, &mut third_stream] {
+        let next = stream.message().await.unwrap().unwrap();
+        assert!(!next.is_initial_snapshot);
+        assert_eq!(next.version, resync.version + 1);
+        assert_eq!(
+            next.epoch, initial.epoch,
+            "updates and reconnects keep the epoch"
+        );
+    }
+}
+
+#[tokio::test]
+async fn one_sided_books_flag_the_empty_side_and_follow_the_emission_policy() {
+    let bids_only = || {
+        let mut book = AggregatedOrderBook::with_clock(Arc::new(MockClock::new(1_000)))
+            .with_config(AppConfig::default().resolve("ethbtc"));
+        book.merge_snapshots(vec![OrderBook {
+            last_update_id: 10,
+            bids: vec![level(Exchange::Binance, 100.0, 1.0)],
+            asks: vec![],
+        }]);
+        Arc::new(RwLock::new(book))
+    };
+
+    let emitting = serve(OrderbookAggregatorService::new(bids_only()), false).await;
+    let summary = first_summary(emitting, SummaryRequest::default()).await;
+    assert_eq!((summary.bids_present, summary.asks_present), (true, false));
+    assert_eq!(summary.spread, None, "no spread without asks");
+    assert!(summary.asks.is_empty());
+
+    let book = bids_only();
+    let holding =
+        OrderbookAggregatorService::new(Arc::clone(&book)).with_one_sided(OneSidedSummaries::Hold);
+    let mut summaries = MarketDataClient::new(serve(holding, false).await)
+        .book_summary(SummaryRequest::default())
+        .await
+        .unwrap()
+        .into_inner();
+    let held = tokio::time::timeout(Duration::from_millis(100), summaries.message()).await;
+    assert!(held.is_err(), "nothing is sent while the asks are empty");
+
+    book.write()
+        .await
+        .handle_update(OrderBookUpdate {
+            exchange: Exchange::Binance,
+            update_id: 11,
+            first_update_id: None,
+            received_at: None,
+            event_time: None,
+            bids: vec![],
+            asks: vec![level(Exchange::Binance, 101.0, 2.0)],
+        })
+        .unwrap();
+    let summary = summaries.message().await.unwrap().unwrap();
+    assert!(
+        summary.is_initial_snapshot,
+        "the first one sent is the full ladder"
+    );
+    assert_eq!((summary.bids_present, summary.asks_present), (true, true));
+    assert_eq!(summary.spread, Some(1.0));
+}
+
+#[tokio::test]
+async fn multi_summaries_read_the_served_symbols_together() {
+    let service = service();
+    let book = Arc::clone(&service.aggregated_orderbook);
+    let mut market_data = MarketDataClient::new(serve(service, false).await);
+    let request = |symbols: &[&str], depth| MultiSummaryRequest {
+        symbols: symbols.iter().map(|s| s.to_string()).collect(),
+        depth,
+    };
+
+    let multi = market_data
+        .get_multi_summary(request(&["ETHBTC"], Some(1)))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(multi.generated_at, 1_000);
+    assert_eq!(multi.changed_symbol, None);
+    assert_eq!(multi.summaries.len(), 1);
+    let summary = &multi.summaries[0];
+    assert_eq!((summary.symbol.as_str(), summary.bids.len()), ("ethbtc", 1));
+    assert_eq!(summary.version, book.read().await.version);
+
+    for (bad, field) in [
+        (request(&["ethbtc", "btcusdt"], None), "symbol"),
+        (request(&[], Some(11)), "depth"),
+    ] {
+        let status = market_data.get_multi_summary(bad).await.unwrap_err();
+        assert_eq!(
+            ErrorDetails::from_status(&status).field.as_deref(),
+            Some(field)
+        );
+    }
+
+    let mut stream = market_data
+        .stream_multi_summary(request(&[], None))
+        .await
+        .unwrap()
+        .into_inner();
+    let first = stream.message().await.unwrap().unwrap();
+    assert_eq!(first.summaries[0].bids.len(), 2);
+    assert_eq!(first.changed_symbol, None);
+    book.write()
+        .await
+        .handle_update(OrderBookUpdate {
+            exchange: Exchange::Binance,
+            update_id: 11,
+            bids: vec![level(Exchange::Binance, 100.2, 1.0)],
+            ..Default::default()
+        })
+        .unwrap();
+    let changed = stream.message().await.unwrap().unwrap();
+    assert_eq!(changed.changed_symbol.as_deref(), Some("ethbtc"));
+    assert_eq!(changed.summaries[0].bids[0].price, 100.2);
+}
+
+#[tokio::test]
+async fn time_series_are_listed_and_queried_by_name() {
+    let clock = Arc::new(MockClock::new(1_000));
+    let status = Arc::new(StatusRegistry::default());
+    let mut book = AggregatedOrderBook::with_clock(clock.clone())
+        .with_config(AppConfig::default().resolve("ethbtc"))
+        .with_timeseries(Arc::clone(&status.timeseries));
+    book.merge_snapshots(vec![OrderBook {
+        last_update_id: 10,
+        bids: vec![level(Exchange::Binance, 100.0, 1.0)],
+        asks: vec![level(Exchange::Binance, 101.0, 2.0)],
+    }]);
+    for (update_id, ask) in [(11, 100.8), (12, 100.6), (13, 100.4)] {
+        clock.advance(Duration::from_millis(100));
+        book.handle_update(OrderBookUpdate {
+            exchange: Exchange::Binance,
+            update_id,
+            asks: vec![level(Exchange::Binance, ask, 1.0)],
+            ..Default::default()
+        })
+        .unwrap();
+    }
+    status
+        .timeseries
+        .record_at("update_rate", "binance", 1_000, 12.0);
+    let service = OrderbookAggregatorService::new(Arc::new(RwLock::new(book))).with_status(status);
+    let mut discovery = DiscoveryClient::new(serve(service, false).await);
+
+    let listed = discovery
+        .get_time_series(TimeSeriesRequest::default())
+        .await
+        .unwrap()
+        .into_inner();
+    let names: Vec<_> = listed
+        .series
+        .iter()
+        .map(|s| (s.name.as_str(), s.label.as_str()))
+        .collect();
+    assert!(names.contains(&("spread", "ethbtc")), "{:?}", names);
+    assert!(names.contains(&("update_rate", "binance")), "{:?}", names);
+    assert!(listed.points.is_empty());
+
+    let request = |max_points, from| TimeSeriesRequest {
+        name: "spread".to_string(),
+        label: "ETHBTC".to_string(),
+        from,
+        max_points,
+        ..Default::default()
+    };
+    let all = discovery
+        .get_time_series(request(0, None))
+        .await
+        .unwrap()
+        .into_inner()
+        .points;
+    let spreads: Vec<_> = all.iter().map(|p| (p.start, p.avg)).collect();
+    assert_eq!(spreads.len(), 4, "{:?}", spreads);
+    assert_eq!(spreads[0], (1_000, 1.0));
+    assert_eq!(spreads[3].0, 1_300);
+
+    let downsampled = discovery
+        .get_time_series(request(1, Some(1_100)))
+        .await
+        .unwrap()
+        .into_inner()
+        .points;
+    assert_eq!(downsampled.len(), 1);
+    assert_eq!(downsampled[0].count, 3);
+    assert!((downsampled[0].min - 0.4).abs() < 1e-9 && (downsampled[0].max - 0.8).abs() < 1e-9);
+
+    let status = discovery
+        .get_time_series(TimeSeriesRequest {
+            name: "spread".to_string(),
+            label: "btcusdt".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert_eq!(
+        ErrorDetails::from_status(&status).field.as_deref(),
+        Some("name")
+    );
+}
@@ -1,3 +1,7 @@
+use keyrock_mm_rust_task::config::AppConfig;
+use keyrock_mm_rust_task::modules::aggregated_orderbook::{
+    DepthUnit, UpdateOutcome, repeats_update_ids,
+};
 use keyrock_mm_rust_task::modules::types::{
     AggregatedOrderBook, Exchange, OrderBook, OrderBookUpdate, OrderLevel,
 };
@@ -6,23 +10,20 @@ fn make_snapshot(exchange: Exchange) -> OrderBook {
     // 20 bids from 100.00 down by 0.01, 20 asks from 100.50 up by 0.01.
     let bids: Vec<OrderLevel> = (0..20)
         .map(|i| OrderLevel {
-            exchange: exchange.as_str(),
+            exchange,
             price: 100.00 - (i as f64) * 0.01,
             amount: 1.0 + i as f64 * 0.1,
         })
         .collect();
     let asks: Vec<OrderLevel> = (0..20)
         .map(|i| OrderLevel {
-            exchange: exchange.as_str(),
+            exchange,
             price: 100.50 + (i as f64) * 0.01,
             amount: 2.0 + i as f64 * 0.05,
         })
         .collect();
     OrderBook {
-        last_update_id: match exchange {
-            Exchange::Binance => 111,
-            Exchange::Bitstamp => 222,
-        },
+        last_update_id: 111,
         bids,
         asks,
     }
@@ -37,6 +38,7 @@ fn build_book() -> AggregatedOrderBook {
 }
 
 #[test]
+#[allow(clippy::manual_next_back)]
 fn merge_snapshots_keeps_all_levels_and_combines_exchanges() {
     let agg = build_book();
 
@@ -58,11 +60,12 @@ fn merge_snapshots_keeps_all_levels_and_combines_exchanges() {
     let best_ask_price = ask_bucket.values().next().unwrap().price;
     let expected_spread = best_ask_price - best_bid_price;
     println!("expected_spread: {}", expected_spread);
-    println!("agg.spread: {}", agg.spread);
-    assert!((agg.spread - expected_spread).abs() < 1e-9);
+    println!("agg.spread: {:?}", agg.spread);
+    assert!((agg.spread.unwrap() - expected_spread).abs() < 1e-9);
 }
 
 #[test]
+#[allow(clippy::approx_constant, clippy::manual_next_back)]
 fn update_inserts_new_levels_and_keeps_all() {
     let mut agg = build_book();
 
@@ -81,16 +84,19 @@ fn update_inserts_new_levels_and_keeps_all() {
     // 1) Insert a new top bid above current best → should become new best, size increases
     let new_top_bid_price = prev_best_bid_price + 0.05;
     let bid_update = OrderBookUpdate {
-        exchange: Exchange::Binance.as_str(),
+        exchange: Exchange::Binance,
         update_id: 1000,
+        first_update_id: None,
+        received_at: None,
+        event_time: None,
         bids: vec![OrderLevel {
-            exchange: Exchange::Binance.as_str(),
+            exchange: Exchange::Binance,
             price: new_top_bid_price,
             amount: 3.14,
         }],
         asks: vec![],
     };
-    agg.handle_update(bid_update);
+    agg.handle_update(bid_update).unwrap();
 
     assert_eq!(agg.bids.len(), prev_bid_count + 1);
     // New best bid price present
@@ -102,16 +108,19 @@ fn update_inserts_new_levels_and_keeps_all() {
     // 2) Insert a new top ask below current best → should become new best ask, size increases
     let new_top_ask_price = prev_best_ask_price - 0.05;
     let ask_update = OrderBookUpdate {
-        exchange: Exchange::Bitstamp.as_str(),
+        exchange: Exchange::Bitstamp,
         update_id: 2000,
+        first_update_id: None,
+        received_at: None,
+        event_time: None,
         bids: vec![],
         asks: vec![OrderLevel {
-            exchange: Exchange::Bitstamp.as_str(),
+            exchange: Exchange::Bitstamp,
             price: new_top_ask_price,
             amount: 1.11,
         }],
     };
-    agg.handle_update(ask_update);
+    agg.handle_update(ask_update).unwrap();
 
     assert_eq!(agg.asks.len(), prev_ask_count + 1);
     let best_ask_idx_after = *agg.asks.keys().next().unwrap();
@@ -121,6 +130,7 @@ fn update_inserts_new_levels_and_keeps_all() {
 }
 
 #[test]
+#[allow(clippy::manual_next_back)]
 fn update_existing_amount_changes() {
     let mut agg = build_book();
     // Pick the best bid level
@@ -131,16 +141,19 @@ fn update_existing_amount_changes() {
     // Change amount for Binance on this price
     let new_amount = 9.99;
     let upd = OrderBookUpdate {
-        exchange: Exchange::Binance.as_str(),
+        exchange: Exchange::Binance,
         update_id: 3000,
+        first_update_id: None,
+        received_at: None,
+        event_time: None,
         bids: vec![OrderLevel {
-            exchange: Exchange::Binance.as_str(),
+            exchange: Exchange::Binance,
             price: old_price,
             amount: new_amount,
         }],
         asks: vec![],
     };
-    agg.handle_update(upd);
+    agg.handle_update(upd).unwrap();
 
     let bucket = agg.bids.get(&best_bid_idx).unwrap();
     let updated = bucket.get("binance").unwrap();
@@ -148,6 +161,7 @@ fn update_existing_amount_changes() {
 }
 
 #[test]
+#[allow(clippy::manual_next_back)]
 fn update_same_price_adds_second_exchange_and_creates_if_missing() {
     // Start from a single-exchange snapshot so we can add the other exchange at the same price
     let mut agg = AggregatedOrderBook::new();
@@ -167,16 +181,19 @@ fn update_same_price_adds_second_exchange_and_creates_if_missing() {
         .unwrap()
         .price;
     let upd_same_price = OrderBookUpdate {
-        exchange: Exchange::Bitstamp.as_str(),
+        exchange: Exchange::Bitstamp,
         update_id: 4000,
+        first_update_id: None,
+        received_at: None,
+        event_time: None,
         bids: vec![OrderLevel {
-            exchange: Exchange::Bitstamp.as_str(),
+            exchange: Exchange::Bitstamp,
             price: best_bid_price,
             amount: 7.77,
         }],
         asks: vec![],
     };
-    agg.handle_update(upd_same_price);
+    agg.handle_update(upd_same_price).unwrap();
     let bucket = agg.bids.get(&best_bid_idx).unwrap();
     assert!(bucket.contains_key("binance"));
     assert!(bucket.contains_key("bitstamp"));
@@ -193,27 +210,33 @@ fn update_same_price_adds_second_exchange_and_creates_if_missing() {
         .price
         - 0.02;
     let upd_ask_binance = OrderBookUpdate {
-        exchange: Exchange::Binance.as_str(),
+        exchange: Exchange::Binance,
         update_id: 5000,
+        first_update_id: None,
+        received_at: None,
+        event_time: None,
         bids: vec![],
         asks: vec![OrderLevel {
-            exchange: Exchange::Binance.as_str(),
+            exchange: Exchange::Binance,
             price: new_ask_price,
             amount: 1.23,
         }],
     };
     let upd_ask_bitstamp = OrderBookUpdate {
-        exchange: Exchange::Bitstamp.as_str(),
+        exchange: Exchange::Bitstamp,
         update_id: 5001,
+        first_update_id: None,
+        received_at: None,
+        event_time: None,
         bids: vec![],
         asks: vec![OrderLevel {
-            exchange: Exchange::Bitstamp.as_str(),
+            exchange: Exchange::Bitstamp,
             price: new_ask_price,
             amount: 4.56,
         }],
     };
-    agg.handle_update(upd_ask_binance);
-    agg.handle_update(upd_ask_bitstamp);
+    agg.handle_update(upd_ask_binance).unwrap();
+    agg.handle_update(upd_ask_bitstamp).unwrap();
 
     // Verify the lowest ask price is the new one and has both exchanges
     let best_ask_idx_after = *agg.asks.keys().next().unwrap();
@@ -223,3 +246,134 @@ fn update_same_price_adds_second_exchange_and_creates_if_missing() {
     assert!(best_ask_bucket.contains_key("binance"));
     assert!(best_ask_bucket.contains_key("bitstamp"));
 }
+
+/// Binance and Bitstamp both at the best bid and ask, Bitstamp larger on the bid and
+/// Binance on the ask
+fn tied_book(settings: &str) -> AggregatedOrderBook {
+    let config = AppConfig::from_json_str(&format!(r#"{{ "defaults": {} }}"#, settings)).unwrap();
+    let mut agg = AggregatedOrderBook::new().with_config(config.resolve("ethbtc"));
+    let level = |exchange, price, amount| OrderLevel {
+        exchange,
+        price,
+        amount,
+    };
+    agg.merge_snapshots(vec![
+        OrderBook {
+            last_update_id: 1,
+            bids: vec![level(Exchange::Binance, 100.0, 1.0)],
+            asks: vec![level(Exchange::Binance, 101.0, 5.0)],
+        },
+        OrderBook {
+            last_update_id: 1,
+            bids: vec![level(Exchange::Bitstamp, 100.0, 3.0)],
+            asks: vec![level(Exchange::Bitstamp, 101.0, 2.0)],
+        },
+    ]);
+    agg
+}
+
+/// (bid order, ask order, one-entry best bid, best bid and ask exchange credited)
+fn tie_outcome(settings: &str) -> (Vec<Exchange>, Vec<Exchange>, Exchange, [String; 2]) {
+    let agg = tied_book(settings);
+    let exchanges = |levels: &[OrderLevel]| levels.iter().map(|l| l.exchange).collect();
+    let snapshot = agg.get_top10_snapshot();
+    let cut = agg.get_snapshot(1, DepthUnit::Entries);
+    assert_eq!(cut.bids.len(), 1);
+    let improvement = agg.price_improvement(1.0);
+    (
+        exchanges(&snapshot.bids),
+        exchanges(&snapshot.asks),
+        cut.bids[0].exchange,
+        [
+            improvement.bids.best_exchange.unwrap(),
+            improvement.asks.best_exchange.unwrap(),
+        ],
+    )
+}
+
+#[test]
+fn a_tie_at_the_best_price_follows_the_configured_policy() {
+    use Exchange::{Binance, Bitstamp};
+    let credited = |bid: &str, ask: &str| [bid.to_string(), ask.to_string()];
+
+    assert_eq!(
+        tie_outcome("{}"),
+        (
+            vec![Binance, Bitstamp],
+            vec![Binance, Bitstamp],
+            Binance,
+            credited("binance", "binance")
+        ),
+        "alphabetical by default"
+    );
+    assert_eq!(
+        tie_outcome(r#"{ "tie_break": "larger_size" }"#),
+        (
+            vec![Bitstamp, Binance],
+            vec![Binance, Bitstamp],
+            Bitstamp,
+            credited("bitstamp", "binance")
+        )
+    );
+    assert_eq!(
+        tie_outcome(r#"{ "tie_break": "venue_priority", "venue_priority": ["bitstamp"] }"#),
+        (
+            vec![Bitstamp, Binance],
+            vec![Bitstamp, Binance],
+            Bitstamp,
+            credited("bitstamp", "bitstamp")
+        )
+    );
+}
+
+#[test]
+fn a_repeated_update_id_is_stale_unless_the_exchange_shares_ids() {
+    for exchange in [
+        Exchange::Binance,
+        Exchange::BinanceUs,
+        Exchange::Bitstamp,
+        Exchange::Kraken,
+        Exchange::Coinbase,
+        Exchange::Okx,
+        Exchange::Bybit,
+        Exchange::Kucoin,
+        Exchange::GateIo,
+        Exchange::Bitfinex,
+    ] {
+        let mut agg = AggregatedOrderBook::new();
+        agg.merge_snapshots(vec![make_snapshot(exchange)]);
+        let update = |amount| OrderBookUpdate {
+            exchange,
+            update_id: 112,
+            first_update_id: None,
+            received_at: None,
+            event_time: None,
+            bids: vec![OrderLevel {
+                exchange,
+                price: 100.00,
+                amount,
+            }],
+            asks: vec![],
+        };
+        let applied = Ok(UpdateOutcome::Applied);
+        assert_eq!(agg.apply_update(update(5.0)), applied, "{}", exchange);
+        let repeat = agg.apply_update(update(6.0));
+        assert_eq!(
+            repeat == applied,
+            repeats_update_ids(exchange),
+            "{}: {:?}",
+            exchange,
+            repeat
+        );
+        let older = OrderBookUpdate {
+            update_id: 110,
+            ..update(7.0)
+        };
+        assert_eq!(
+            agg.apply_update(older),
+            Ok(UpdateOutcome::Stale),
+            "{}",
+            exchange
+        );
+    }
+}
@@ -0,0 +1,28 @@
+use keyrock_mm_rust_task::modules::replay::{exchange_levels, parse_recording, replay};
+use keyrock_mm_rust_task::modules::types::{AggregatedOrderBook, Exchange};
+
+// Sanitized reproduction of the Bitstamp drift incident: a burst of diffs sharing one
+// microtimestamp, a disconnect, a stale (cached) REST snapshot and finally a fresh one.
+const BITSTAMP_DESYNC: &str = include_str!("fixtures/bitstamp_desync.jsonl");
+
+fn levels(book: &[keyrock_mm_rust_task::modules::types::OrderLevel]) -> Vec<(f64, f64)> {
+    book.iter().map(|l| (l.price, l.amount)).collect()
+}
+
+#[test]
+fn bitstamp_desync_incident_replays_to_the_venue_book() {
+    let recording = parse_recording(BITSTAMP_DESYNC).expect("fixture parses");
+    let expected = &recording.expected[0];
+
+    let mut book = AggregatedOrderBook::new();
+    replay(&mut book, &recording.events);
+
+    assert_eq!(
+        exchange_levels(&book, Exchange::Bitstamp, true),
+        levels(&expected.bids)
+    );
+    assert_eq!(
+        exchange_levels(&book, Exchange::Bitstamp, false),
+        levels(&expected.asks)
+    );
+}
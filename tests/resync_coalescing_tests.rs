@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use keyrock_mm_rust_task::modules::clock::MockClock;
+use keyrock_mm_rust_task::modules::snapshot_fetch::{FetchPriority, SnapshotCoordinator};
+use keyrock_mm_rust_task::modules::types::{AggregatedOrderBook, Exchange, OrderBook, OrderLevel};
+
+const HOLD_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Stands in for both exchanges' REST snapshot endpoints, counting calls per exchange
+#[derive(Default)]
+struct MockRest {
+    calls: Mutex<HashMap<Exchange, usize>>,
+}
+
+impl MockRest {
+    async fn snapshot(&self, exchange: Exchange, latency_ms: u64) -> Result<OrderBook, String> {
+        let id = {
+            let mut calls = self.calls.lock().unwrap();
+            let calls = calls.entry(exchange).or_default();
+            *calls += 1;
+            *calls as u64
+        };
+        tokio::time::sleep(Duration::from_millis(latency_ms)).await;
+        Ok(snapshot(exchange, 1_000 + id))
+    }
+
+    fn calls(&self, exchange: Exchange) -> usize {
+        self.calls
+            .lock()
+            .unwrap()
+            .get(&exchange)
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+fn snapshot(exchange: Exchange, last_update_id: u64) -> OrderBook {
+    let level = |price: f64| OrderLevel {
+        exchange,
+        price,
+        amount: 1.0,
+    };
+    OrderBook {
+        last_update_id,
+        bids: vec![level(0.05), level(0.0499)],
+        asks: vec![level(0.0501), level(0.0502)],
+    }
+}
+
+fn book(clock: &Arc<MockClock>) -> AggregatedOrderBook {
+    let mut book = AggregatedOrderBook::with_clock(clock.clone());
+    book.merge_snapshots(vec![
+        snapshot(Exchange::Bitstamp, 1),
+        snapshot(Exchange::Binance, 1),
+    ]);
+    book
+}
+
+/// Every commit publishes its version, so this counts the versions published since `before`
+fn published_since(book: &AggregatedOrderBook, before: u64) -> u64 {
+    book.published_snapshot().version - before
+}
+
+/// One resync request for a feed, as made by the reconnect path or by the exchange's own
+/// recovery
+async fn resync(
+    coordinator: &Arc<SnapshotCoordinator>,
+    rest: &MockRest,
+    exchange: Exchange,
+    latency_ms: u64,
+) -> Result<OrderBook, String> {
+    coordinator
+        .fetch(
+            exchange.as_str(),
+            "ethbtc",
+            FetchPriority::Connected,
+            rest.snapshot(exchange, latency_ms),
+        )
+        .await
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_double_flap_costs_one_call_per_feed_and_publishes_once() {
+    let clock = Arc::new(MockClock::new(1_700_000_000_000));
+    let coordinator =
+        Arc::new(SnapshotCoordinator::new(4).with_coalesce_window(Duration::from_millis(100)));
+    let rest = MockRest::default();
+    let mut book = book(&clock);
+    let before = book.version;
+
+    // Both streams break at once: the reconnect path resyncs both feeds and each exchange's
+    // own recovery asks again for its feed
+    book.hold_for_resync(["bitstamp", "binance"], HOLD_TIMEOUT);
+    let (reconnect_bitstamp, reconnect_binance, recover_bitstamp, recover_binance) = tokio::join!(
+        resync(&coordinator, &rest, Exchange::Bitstamp, 20),
+        resync(&coordinator, &rest, Exchange::Binance, 80),
+        resync(&coordinator, &rest, Exchange::Bitstamp, 20),
+        resync(&coordinator, &rest, Exchange::Binance, 80),
+    );
+    assert_eq!(
+        (
+            rest.calls(Exchange::Bitstamp),
+            rest.calls(Exchange::Binance)
+        ),
+        (1, 1)
+    );
+    assert_eq!(coordinator.coalesced("bitstamp"), 1);
+    assert_eq!(coordinator.coalesced("binance"), 1);
+    let id = |result: &Result<OrderBook, String>| result.as_ref().unwrap().last_update_id;
+    assert_eq!(id(&recover_bitstamp), id(&reconnect_bitstamp));
+    assert_eq!(
+        id(&recover_binance),
+        id(&reconnect_binance),
+        "both requests got the same snapshot"
+    );
+
+    // Bitstamp lands first and a Binance level is dropped meanwhile: nothing is published
+    book.merge_snapshots(vec![reconnect_bitstamp.unwrap()]);
+    book.remove_exchange("binance");
+    assert_eq!(published_since(&book, before), 0);
+    assert!(
+        book.resync_hold
+            .as_ref()
+            .unwrap()
+            .pending
+            .contains("binance"),
+        "waiting for Binance"
+    );
+
+    book.merge_snapshots(vec![reconnect_binance.unwrap()]);
+    assert_eq!(
+        published_since(&book, before),
+        1,
+        "one consolidated version"
+    );
+    assert!(book.resync_hold.is_none());
+    let snap = book.published_snapshot();
+    assert_eq!(snap.last_update_ids.get("binance"), Some(&1_001));
+    assert_eq!(snap.last_update_ids.get("bitstamp"), Some(&1_001));
+
+    // A later resync, after the shared calls have started, fetches again
+    resync(&coordinator, &rest, Exchange::Binance, 0)
+        .await
+        .unwrap();
+    assert_eq!(rest.calls(Exchange::Binance), 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_slow_venue_holds_publication_only_until_the_timeout() {
+    let clock = Arc::new(MockClock::new(1_700_000_000_000));
+    let mut book = book(&clock);
+    let before = book.version;
+
+    book.hold_for_resync(["bitstamp", "binance"], HOLD_TIMEOUT);
+    book.merge_snapshots(vec![snapshot(Exchange::Bitstamp, 2)]);
+    book.release_expired_hold();
+    assert_eq!(
+        published_since(&book, before),
+        0,
+        "still within the timeout"
+    );
+
+    clock.advance(HOLD_TIMEOUT);
+    book.release_expired_hold();
+    assert_eq!(published_since(&book, before), 1);
+    assert_eq!(
+        book.published_snapshot().last_update_ids.get("bitstamp"),
+        Some(&2)
+    );
+
+    // The straggler is published on its own when it lands
+    book.merge_snapshots(vec![snapshot(Exchange::Binance, 2)]);
+    assert_eq!(published_since(&book, before), 2);
+
+    // A venue that gives up ends the hold as well, and a hold with no changes publishes nothing
+    book.hold_for_resync(["bitstamp", "binance"], HOLD_TIMEOUT);
+    book.end_resync("binance");
+    book.end_resync("bitstamp");
+    assert!(book.resync_hold.is_none());
+    assert_eq!(published_since(&book, before), 2);
+}
@@ -0,0 +1,238 @@
+use keyrock_mm_rust_task::modules::replay::{ReplayEvent, exchange_levels, replay};
+use keyrock_mm_rust_task::modules::types::{AggregatedOrderBook, Exchange};
+use std::collections::BTreeMap;
+
+const FIRST_ID: u64 = 91;
+const LAST_ID: u64 = 110;
+/// (U, u) of each websocket diff; contiguous, so together they cover every event
+const DIFFS: [(u64, u64); 5] = [(91, 95), (96, 99), (100, 102), (103, 105), (106, 110)];
+
+/// Stand-in for Binance: each update id changes one level, diffs bundle runs of ids and
+/// the REST snapshot is the book as of any id. Prices are in ticks of 1e-5.
+struct MockBinance {
+    initial: Vec<(bool, u64, u64)>,     // (is_bid, ticks, amount)
+    events: Vec<(u64, bool, u64, u64)>, // (update id, is_bid, ticks, amount)
+}
+
+type Side = BTreeMap<u64, u64>;
+
+impl MockBinance {
+    fn new() -> Self {
+        let initial = vec![
+            (true, 5_000, 4),
+            (true, 4_998, 6),
+            (true, 4_990, 9),
+            (false, 5_002, 3),
+            (false, 5_004, 5),
+            (false, 5_010, 8),
+        ];
+        // Repeatedly touch a few prices near the top, removing some (amount 0)
+        let events = (FIRST_ID..=LAST_ID)
+            .map(|id| {
+                let is_bid = id % 3 != 0;
+                let offset = id % 4;
+                let ticks = if is_bid {
+                    5_000 - offset
+                } else {
+                    5_002 + offset
+                };
+                (id, is_bid, ticks, id * 7 % 5)
+            })
+            .collect();
+        Self { initial, events }
+    }
+
+    fn book_at(&self, id: u64) -> (Side, Side) {
+        let (mut bids, mut asks) = (Side::new(), Side::new());
+        let changes = self.initial.iter().copied().chain(
+            self.events
+                .iter()
+                .filter(|(at, ..)| *at <= id)
+                .map(|&(_, is_bid, ticks, amount)| (is_bid, ticks, amount)),
+        );
+        for (is_bid, ticks, amount) in changes {
+            let side = if is_bid { &mut bids } else { &mut asks };
+            match amount {
+                0 => side.remove(&ticks),
+                _ => side.insert(ticks, amount),
+            };
+        }
+        (bids, asks)
+    }
+
+    fn snapshot(&self, id: u64) -> ReplayEvent {
+        let (bids, asks) = self.book_at(id);
+        let body = format!(
+            r#"{{"lastUpdateId":{},"bids":[{}],"asks":[{}]}}"#,
+            id,
+            levels_json(bids.iter().rev()),
+            levels_json(asks.iter())
+        );
+        ReplayEvent::Snapshot {
+            exchange: Exchange::Binance,
+            body,
+        }
+    }
+
+    /// The latest amount of every price touched in `[first, last]`, as Binance sends it
+    fn diff(&self, (first, last): (u64, u64)) -> ReplayEvent {
+        let (mut bids, mut asks) = (Side::new(), Side::new());
+        for &(_, is_bid, ticks, amount) in self
+            .events
+            .iter()
+            .filter(|(id, ..)| (first..=last).contains(id))
+        {
+            let side = if is_bid { &mut bids } else { &mut asks };
+            side.insert(ticks, amount);
+        }
+        let text = format!(
+            r#"{{"e":"depthUpdate","E":1,"s":"ETHBTC","U":{},"u":{},"b":[{}],"a":[{}]}}"#,
+            first,
+            last,
+            levels_json(bids.iter()),
+            levels_json(asks.iter())
+        );
+        ReplayEvent::Message {
+            exchange: Exchange::Binance,
+            text,
+        }
+    }
+}
+
+fn price(ticks: u64) -> f64 {
+    ticks as f64 / 100_000.0
+}
+
+fn levels_json<'a>(levels: impl Iterator<Item = (&'a u64, &'a u64)>) -> String {
+    levels
+        .map(|(ticks, amount)| format!(r#"["{:.8}","{:.8}"]"#, price(*ticks), *amount as f64))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn best_first(side: &Side, bids: bool) -> Vec<(f64, f64)> {
+    let levels = side
+        .iter()
+        .map(|(ticks, amount)| (price(*ticks), *amount as f64));
+    if bids {
+        levels.rev().collect()
+    } else {
+        levels.collect()
+    }
+}
+
+enum Step {
+    Snapshot(u64),
+    Diff(usize),
+}
+
+#[test]
+fn every_snapshot_and_diff_ordering_converges_to_the_venue_book() {
+    use Step::{Diff, Snapshot};
+    let scenarios = [
+        (
+            "snapshot before any diff",
+            vec![Snapshot(100), Diff(0), Diff(1), Diff(2), Diff(3), Diff(4)],
+        ),
+        (
+            "slow snapshot, every diff arrives first",
+            vec![Diff(0), Diff(1), Diff(2), Diff(3), Diff(4), Snapshot(100)],
+        ),
+        (
+            "snapshot lands after the straddling diff",
+            vec![Diff(0), Diff(1), Diff(2), Snapshot(100), Diff(3), Diff(4)],
+        ),
+        (
+            "stream joined late, straddling diff first",
+            vec![Snapshot(100), Diff(2), Diff(3), Diff(4)],
+        ),
+        (
+            "snapshot id equal to a diff's final id",
+            vec![Diff(0), Diff(1), Snapshot(99), Diff(2), Diff(3), Diff(4)],
+        ),
+        (
+            "snapshot newer than buffered diffs",
+            vec![Diff(0), Diff(1), Diff(2), Diff(3), Snapshot(105), Diff(4)],
+        ),
+        (
+            "diffs redelivered after a hiccup",
+            vec![
+                Diff(1),
+                Snapshot(100),
+                Diff(2),
+                Diff(3),
+                Diff(2),
+                Diff(4),
+                Diff(3),
+            ],
+        ),
+    ];
+
+    let mock = MockBinance::new();
+    let (expected_bids, expected_asks) = mock.book_at(LAST_ID);
+    for (name, steps) in scenarios {
+        let events: Vec<ReplayEvent> = steps
+            .iter()
+            .map(|step| match step {
+                Snapshot(id) => mock.snapshot(*id),
+                Diff(i) => mock.diff(DIFFS[*i]),
+            })
+            .collect();
+        let mut book = AggregatedOrderBook::new();
+        replay(&mut book, &events);
+
+        assert_eq!(
+            exchange_levels(&book, Exchange::Binance, true),
+            best_first(&expected_bids, true),
+            "bids: {}",
+            name
+        );
+        assert_eq!(
+            exchange_levels(&book, Exchange::Binance, false),
+            best_first(&expected_asks, false),
+            "asks: {}",
+            name
+        );
+        assert_eq!(book.last_update_id["binance"], LAST_ID, "{}", name);
+    }
+}
+
+#[test]
+fn a_restarted_stream_becomes_the_new_baseline_and_a_glitch_stays_stale() {
+    let diff = |(first, last): (u64, u64), event_time: u64, bid: &str| ReplayEvent::Message {
+        exchange: Exchange::Binance,
+        text: format!(
+            r#"{{"e":"depthUpdate","E":{},"s":"ETHBTC","U":{},"u":{},"b":[["{}","1.0"]],"a":[]}}"#,
+            event_time, first, last, bid
+        ),
+    };
+    let mut events = vec![
+        ReplayEvent::Snapshot {
+            exchange: Exchange::Binance,
+            body:
+                r#"{"lastUpdateId":5000000,"bids":[["0.05000","4.0"]],"asks":[["0.05002","3.0"]]}"#
+                    .to_string(),
+        },
+        diff((5_000_001, 5_000_001), 1_000, "0.04999"),
+        // A few ids backwards, though stamped later
+        diff((4_999_990, 4_999_995), 2_000, "0.04990"),
+    ];
+    let mut book = AggregatedOrderBook::new();
+    replay(&mut book, &events);
+    assert_eq!(
+        exchange_levels(&book, Exchange::Binance, true),
+        [(0.05, 4.0), (0.04999, 1.0)]
+    );
+    assert_eq!(book.last_update_id["binance"], 5_000_001);
+
+    // Ids restart after maintenance: the old sequence's levels go
+    events.push(diff((1, 3), 3_000, "0.04000"));
+    let mut book = AggregatedOrderBook::new();
+    replay(&mut book, &events);
+    assert_eq!(
+        exchange_levels(&book, Exchange::Binance, true),
+        [(0.04, 1.0)]
+    );
+    assert!(exchange_levels(&book, Exchange::Binance, false).is_empty());
+    assert_eq!(book.last_update_id["binance"], 3);
+}
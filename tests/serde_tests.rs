@@ -0,0 +1,100 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use keyrock_mm_rust_task::modules::aggregated_orderbook::{
+    BookState, SNAPSHOT_SCHEMA_VERSION, Top10Snapshot,
+};
+use keyrock_mm_rust_task::modules::data_quality::DataQuality;
+use keyrock_mm_rust_task::modules::types::{Exchange, OrderBook, OrderBookUpdate, OrderLevel};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+// Golden files pin the wire shape seen by downstream consumers. After an intentional
+// change, regenerate them with `UPDATE_GOLDEN=1 cargo test --test serde_tests`.
+fn assert_golden<T: Serialize + DeserializeOwned>(name: &str, value: &T) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/golden")
+        .join(format!("{}.json", name));
+    let json = serde_json::to_string_pretty(value).unwrap() + "\n";
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, &json).unwrap();
+    }
+    let golden = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(json, golden, "{} no longer matches its golden file", name);
+
+    let parsed: T = serde_json::from_str(&golden).unwrap();
+    assert_eq!(
+        serde_json::to_string_pretty(&parsed).unwrap() + "\n",
+        golden
+    );
+}
+
+fn level(exchange: Exchange, price: f64, amount: f64) -> OrderLevel {
+    OrderLevel {
+        exchange,
+        price,
+        amount,
+    }
+}
+
+#[test]
+fn order_book_shape() {
+    assert_golden(
+        "order_book",
+        &OrderBook {
+            last_update_id: 42,
+            bids: vec![level(Exchange::Bitstamp, 0.0651, 1.5)],
+            asks: vec![level(Exchange::Bitstamp, 0.0652, 2.25)],
+        },
+    );
+}
+
+#[test]
+fn order_book_update_shape() {
+    assert_golden(
+        "order_book_update",
+        &OrderBookUpdate {
+            exchange: Exchange::BinanceUs,
+            update_id: 43,
+            first_update_id: None,
+            received_at: None,
+            event_time: None,
+            bids: vec![level(Exchange::BinanceUs, 0.0651, 0.0)],
+            asks: vec![],
+        },
+    );
+}
+
+#[test]
+fn top10_snapshot_shape() {
+    let mut snapshot = Top10Snapshot {
+        schema_version: SNAPSHOT_SCHEMA_VERSION,
+        symbol: "ethbtc".to_string(),
+        version: 7,
+        epoch: 1_699_990_000_000,
+        spread: Some(0.0001),
+        bids: vec![level(Exchange::Binance, 0.0651, 1.0)],
+        asks: vec![level(Exchange::Bitstamp, 0.0652, 2.0)],
+        generated_at: 1_700_000_000_000,
+        last_update_ids: BTreeMap::from([
+            ("binance".to_string(), 100),
+            ("bitstamp".to_string(), 200),
+        ]),
+        state: BookState::Normal,
+        data_quality: DataQuality::Partial,
+        lagging_exchanges: vec!["binance".to_string()],
+        exchanges: vec!["binance".to_string(), "bitstamp".to_string()],
+        index_price: Some(0.06515),
+        ..Default::default()
+    };
+    snapshot.stamp_checksum();
+    assert_golden("top10_snapshot", &snapshot);
+}
+
+#[test]
+fn unknown_exchanges_are_rejected() {
+    let err =
+        serde_json::from_str::<OrderLevel>(r#"{"exchange":"huobi","price":1.0,"amount":1.0}"#)
+            .unwrap_err();
+    assert!(err.to_string().contains("huobi"), "{}", err);
+}
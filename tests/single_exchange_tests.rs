@@ -0,0 +1,147 @@
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::SinkExt;
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::process::Command;
+use tokio::sync::Notify;
+use tokio_tungstenite::tungstenite::Message;
+
+const SNAPSHOT_ID: u64 = 100;
+
+/// Serve `exchangeInfo` and a fixed depth snapshot over plain HTTP/1.1
+async fn mock_binance_rest(listener: TcpListener) {
+    loop {
+        let Ok((mut socket, _)) = listener.accept().await else {
+            return;
+        };
+        tokio::spawn(async move {
+            let mut request = vec![];
+            let mut buf = [0u8; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                match socket.read(&mut buf).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => request.extend_from_slice(&buf[..n]),
+                }
+            }
+            let body = if String::from_utf8_lossy(&request).starts_with("GET /api/v3/depth") {
+                format!(
+                    r#"{{"lastUpdateId":{},"bids":[["0.05000000","1.00000000"]],"asks":[["0.05001000","2.00000000"]]}}"#,
+                    SNAPSHOT_ID
+                )
+            } else {
+                "{}".to_string()
+            };
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            let _ = socket.write_all(response.as_bytes()).await;
+        });
+    }
+}
+
+/// Send one diff per notification, each changing the best bid's amount
+async fn mock_binance_ws(listener: TcpListener, next_diff: Arc<Notify>) {
+    loop {
+        let Ok((socket, _)) = listener.accept().await else {
+            return;
+        };
+        let next_diff = Arc::clone(&next_diff);
+        tokio::spawn(async move {
+            let Ok(mut ws) = tokio_tungstenite::accept_async(socket).await else {
+                return;
+            };
+            for id in SNAPSHOT_ID + 1.. {
+                next_diff.notified().await;
+                let diff = format!(
+                    r#"{{"e":"depthUpdate","E":1,"s":"ETHBTC","U":{},"u":{},"b":[["0.05000000","{}.00000000"]],"a":[]}}"#,
+                    id,
+                    id,
+                    id % 7 + 1
+                );
+                if ws.send(Message::Text(diff.into())).await.is_err() {
+                    return;
+                }
+            }
+        });
+    }
+}
+
+#[tokio::test]
+async fn binance_only_pipeline_streams_summaries() {
+    let rest = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let ws = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let config = format!(
+        r#"{{"exchanges":["binance"],"endpoints":{{"binance_rest":"http://{}","binance_ws":"ws://{}"}}}}"#,
+        rest.local_addr().unwrap(),
+        ws.local_addr().unwrap()
+    );
+    tokio::spawn(mock_binance_rest(rest));
+    let next_diff = Arc::new(Notify::new());
+    tokio::spawn(mock_binance_ws(ws, Arc::clone(&next_diff)));
+    // Binance subscriptions are confirmed by their first frame, so release one up front
+    next_diff.notify_one();
+    let path = std::env::temp_dir().join(format!("single-exchange-{}.json", std::process::id()));
+    std::fs::write(&path, config).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_keyrock_mm_rust_task"))
+        .arg("--config")
+        .arg(&path)
+        .args(["--stdio", "--startup-timeout-secs", "10"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    stdin
+        .write_all(b"{\"id\":1,\"method\":\"subscribe\",\"params\":{\"depth\":5}}\n")
+        .await
+        .unwrap();
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+
+    // Two distinct book versions mean diffs are flowing, not just the snapshot
+    let mut versions = vec![];
+    let read = async {
+        while versions.len() < 2 {
+            let line = lines.next_line().await.unwrap().expect("stdout closed");
+            let message: Value = serde_json::from_str(&line).unwrap();
+            let Some(summary) = message.pointer("/params/summary") else {
+                continue;
+            };
+            assert_eq!(summary["exchanges"], serde_json::json!(["binance"]));
+            assert!(summary["spread"].as_f64().unwrap() > 0.0);
+            let version = summary["version"].as_u64().unwrap();
+            if !versions.contains(&version) {
+                versions.push(version);
+            }
+            // Each Summary releases the next diff
+            next_diff.notify_one();
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(20), read)
+        .await
+        .expect("no Summaries from the Binance-only pipeline");
+
+    // Closing stdin is a clean shutdown
+    drop(stdin);
+    let output = tokio::time::timeout(Duration::from_secs(10), child.wait_with_output())
+        .await
+        .unwrap()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert!(stderr.contains("bitstamp is not configured"), "{}", stderr);
+    assert!(
+        !stderr.contains("bitstamp connect/sync failed"),
+        "{}",
+        stderr
+    );
+}
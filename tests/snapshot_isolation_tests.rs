@@ -0,0 +1,114 @@
+use std::sync::Arc;
+
+use keyrock_mm_rust_task::modules::aggregated_orderbook::Top10Snapshot;
+use keyrock_mm_rust_task::modules::types::{
+    AggregatedOrderBook, Exchange, OrderBook, OrderBookUpdate, OrderLevel,
+};
+use tokio::sync::RwLock;
+
+const UPDATES: u64 = 2_000;
+const READERS: usize = 8;
+
+fn level(price: f64, amount: f64) -> OrderLevel {
+    OrderLevel {
+        exchange: Exchange::Binance,
+        price,
+        amount,
+    }
+}
+
+// Step `k` moves both sides up by one: bid 100+k / ask 101+k, so every whole
+// update leaves a spread of exactly 1 with both best levels at the same step.
+fn step(k: u64) -> OrderBookUpdate {
+    let prev = (k - 1) as f64;
+    let next = k as f64;
+    OrderBookUpdate {
+        exchange: Exchange::Binance,
+        update_id: k + 1,
+        first_update_id: None,
+        received_at: None,
+        event_time: None,
+        bids: vec![level(100.0 + prev, 0.0), level(100.0 + next, 1.0)],
+        asks: vec![level(101.0 + prev, 0.0), level(101.0 + next, 1.0)],
+    }
+}
+
+fn assert_consistent(snap: &Top10Snapshot) {
+    let (bid, ask) = (&snap.bids[0], &snap.asks[0]);
+    assert_eq!(snap.bids.len(), 1, "version {}", snap.version);
+    assert_eq!(snap.asks.len(), 1, "version {}", snap.version);
+    assert_eq!(ask.price - bid.price, 1.0, "version {}", snap.version);
+    assert!(
+        (snap.spread.unwrap() - 1.0).abs() < 1e-9,
+        "version {}",
+        snap.version
+    );
+    // Snapshot version 1 is the merged snapshot at step 0, each update adds one
+    assert_eq!(bid.price, 100.0 + (snap.version - 1) as f64);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn readers_only_observe_whole_updates() {
+    let mut book = AggregatedOrderBook::new();
+    book.merge_snapshots(vec![OrderBook {
+        last_update_id: 1,
+        bids: vec![level(100.0, 1.0)],
+        asks: vec![level(101.0, 1.0)],
+    }]);
+    let final_version = 1 + UPDATES;
+    let book = Arc::new(RwLock::new(book));
+
+    let mut readers = vec![];
+    for _ in 0..READERS {
+        let mut published = book.read().await.subscribe();
+        readers.push(tokio::spawn(async move {
+            let mut last_version = 0;
+            let mut observed = 0;
+            loop {
+                let snap = published.borrow_and_update().clone();
+                assert!(snap.version >= last_version, "version went backwards");
+                assert_consistent(&snap);
+                last_version = snap.version;
+                observed += 1;
+                if last_version == final_version {
+                    return observed;
+                }
+                published.changed().await.unwrap();
+            }
+        }));
+    }
+    // Readers polling through the lock instead of the channel
+    for _ in 0..READERS {
+        let book = Arc::clone(&book);
+        readers.push(tokio::spawn(async move {
+            let mut observed = 0;
+            loop {
+                let snap = book.read().await.published_snapshot();
+                assert_consistent(&snap);
+                observed += 1;
+                if snap.version == final_version {
+                    return observed;
+                }
+                tokio::task::yield_now().await;
+            }
+        }));
+    }
+
+    let writer = {
+        let book = Arc::clone(&book);
+        tokio::spawn(async move {
+            for k in 1..=UPDATES {
+                book.write().await.handle_update(step(k)).unwrap();
+                if k % 16 == 0 {
+                    tokio::task::yield_now().await;
+                }
+            }
+        })
+    };
+
+    writer.await.unwrap();
+    for reader in readers {
+        assert!(reader.await.unwrap() > 0);
+    }
+    assert_eq!(book.read().await.version, final_version);
+}
@@ -0,0 +1,62 @@
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::time::{Duration, Instant};
+
+fn write_config(name: &str, contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("{}-{}.json", name, std::process::id()));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+fn run_with_config(path: &PathBuf, extra: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_keyrock_mm_rust_task"))
+        .arg("--config")
+        .arg(path)
+        .args(extra)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn invalid_config_exits_before_serving() {
+    let path = write_config(
+        "startup-invalid",
+        r#"{"binance_variant":"global","typo":1}"#,
+    );
+    let output = run_with_config(&path, &[]);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("error: invalid config"), "{}", stderr);
+}
+
+#[test]
+fn unreachable_exchanges_fail_startup_after_the_timeout() {
+    // Port 1 refuses connections, so no connector can ever become ready
+    let path = write_config(
+        "startup-unreachable",
+        r#"{"endpoints":{
+            "binance_rest":"http://127.0.0.1:1","binance_ws":"ws://127.0.0.1:1",
+            "bitstamp_rest":"http://127.0.0.1:1","bitstamp_ws":"ws://127.0.0.1:1"}}"#,
+    );
+    let started = Instant::now();
+    let output = run_with_config(&path, &["--startup-timeout-secs", "1"]);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(output.status.code(), Some(5), "startup deadline missed");
+    assert!(started.elapsed() < Duration::from_secs(20));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("no exchange data within 1s of startup"),
+        "{}",
+        stderr
+    );
+    // Each venue is listed with its last error and circuit state
+    assert!(stderr.contains("bitstamp: Bitstamp"), "{}", stderr);
+    assert!(
+        stderr.contains("(circuit Closed, 1 failures)"),
+        "{}",
+        stderr
+    );
+}
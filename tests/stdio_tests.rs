@@ -0,0 +1,131 @@
+use std::sync::Arc;
+
+use keyrock_mm_rust_task::config::AppConfig;
+use keyrock_mm_rust_task::handlers::Handlers;
+use keyrock_mm_rust_task::modules::clock::MockClock;
+use keyrock_mm_rust_task::modules::status::SharedStatus;
+use keyrock_mm_rust_task::modules::types::{
+    AggregatedOrderBook, Exchange, OrderBook, OrderBookUpdate, OrderLevel,
+};
+use keyrock_mm_rust_task::stdio_service::{StdioSession, serve};
+use serde_json::Value;
+use tokio::sync::{RwLock, mpsc};
+
+const FIXTURE: &str = include_str!("fixtures/stdio_requests.jsonl");
+
+fn level(exchange: Exchange, price: f64, amount: f64) -> OrderLevel {
+    OrderLevel {
+        exchange,
+        price,
+        amount,
+    }
+}
+
+fn handlers() -> Handlers {
+    let mut book = AggregatedOrderBook::with_clock(Arc::new(MockClock::new(1_000)))
+        .with_config(AppConfig::default().resolve("ethbtc"));
+    book.merge_snapshots(vec![
+        OrderBook {
+            last_update_id: 10,
+            bids: vec![level(Exchange::Binance, 100.0, 1.0)],
+            asks: vec![level(Exchange::Binance, 101.0, 2.0)],
+        },
+        OrderBook {
+            last_update_id: 20,
+            bids: vec![level(Exchange::Bitstamp, 99.5, 3.0)],
+            asks: vec![level(Exchange::Bitstamp, 101.5, 4.0)],
+        },
+    ]);
+    Handlers::new(Arc::new(RwLock::new(book)), SharedStatus::default())
+}
+
+fn parse(line: &str) -> Value {
+    serde_json::from_str(line).unwrap()
+}
+
+#[tokio::test]
+async fn requests_match_fixture_responses() {
+    let (tx, mut rx) = mpsc::channel(16);
+    let mut session = StdioSession::new(handlers(), tx);
+    for case in FIXTURE.lines().filter(|l| !l.trim().is_empty()) {
+        let case = parse(case);
+        let request = case["request"].as_str().unwrap();
+        session.handle_line(request).await;
+        let response = parse(&rx.recv().await.unwrap());
+        assert_eq!(response, case["response"], "request: {}", request);
+    }
+}
+
+#[tokio::test]
+async fn subscriptions_stream_each_published_snapshot() {
+    let handlers = handlers();
+    let (tx, mut rx) = mpsc::channel(16);
+    let mut session = StdioSession::new(handlers.clone(), tx);
+    session
+        .handle_line(r#"{"id":"s","method":"subscribe","params":{"depth":1}}"#)
+        .await;
+
+    let ack = parse(&rx.recv().await.unwrap());
+    assert_eq!(ack["id"], "s");
+    assert_eq!(ack["result"]["subscription"], 1);
+
+    let first = parse(&rx.recv().await.unwrap());
+    assert_eq!(first["method"], "summary");
+    assert_eq!(first["params"]["subscription"], 1);
+    assert_eq!(first["params"]["is_initial_snapshot"], true);
+    assert_eq!(first["params"]["summary"]["version"], 1);
+    assert_eq!(
+        first["params"]["summary"]["bids"].as_array().unwrap().len(),
+        1
+    );
+
+    handlers
+        .book
+        .write()
+        .await
+        .handle_update(OrderBookUpdate {
+            exchange: Exchange::Binance,
+            update_id: 11,
+            first_update_id: None,
+            received_at: None,
+            event_time: None,
+            bids: vec![level(Exchange::Binance, 100.5, 1.0)],
+            asks: vec![],
+        })
+        .unwrap();
+    let second = parse(&rx.recv().await.unwrap());
+    assert_eq!(second["params"]["is_initial_snapshot"], false);
+    assert_eq!(second["params"]["summary"]["version"], 2);
+    assert_eq!(
+        second["params"]["summary"]["epoch"],
+        first["params"]["summary"]["epoch"]
+    );
+    assert_eq!(second["params"]["summary"]["bids"][0]["price"], 100.5);
+
+    // A client resubscribing mid-flow starts over from a marked snapshot of the current book
+    session
+        .handle_line(r#"{"id":"again","method":"subscribe","params":{"depth":1}}"#)
+        .await;
+    assert_eq!(
+        parse(&rx.recv().await.unwrap())["result"]["subscription"],
+        2
+    );
+    let resubscribed = parse(&rx.recv().await.unwrap());
+    assert_eq!(resubscribed["params"]["subscription"], 2);
+    assert_eq!(resubscribed["params"]["is_initial_snapshot"], true);
+    assert_eq!(resubscribed["params"]["summary"]["version"], 2);
+}
+
+#[tokio::test]
+async fn serve_answers_each_line_until_input_closes() {
+    let (output, mut reader) = tokio::io::duplex(4096);
+    let input: &[u8] = b"{\"id\":1,\"method\":\"get_spread\"}\n\n{\"id\":2,\"method\":\"nope\"}\n";
+    serve(handlers(), input, output).await.unwrap();
+
+    let mut written = String::new();
+    tokio::io::AsyncReadExt::read_to_string(&mut reader, &mut written)
+        .await
+        .unwrap();
+    let ids: Vec<Value> = written.lines().map(|l| parse(l)["id"].clone()).collect();
+    assert_eq!(ids, vec![Value::from(1), Value::from(2)]);
+}
@@ -0,0 +1,150 @@
+use keyrock_mm_rust_task::modules::replay::{ReplayEvent, replay};
+use keyrock_mm_rust_task::modules::traded_estimate::TradedEstimate;
+use keyrock_mm_rust_task::modules::types::{AggregatedOrderBook, Exchange};
+
+fn binance_snapshot(id: u64, bids: &str, asks: &str) -> ReplayEvent {
+    ReplayEvent::Snapshot {
+        exchange: Exchange::Binance,
+        body: format!(
+            r#"{{"lastUpdateId":{},"bids":[{}],"asks":[{}]}}"#,
+            id, bids, asks
+        ),
+    }
+}
+
+fn binance_diff(id: u64, bids: &str, asks: &str) -> ReplayEvent {
+    ReplayEvent::Message {
+        exchange: Exchange::Binance,
+        text: format!(
+            r#"{{"e":"depthUpdate","E":1,"s":"ETHBTC","U":{},"u":{},"b":[{}],"a":[{}]}}"#,
+            id, id, bids, asks
+        ),
+    }
+}
+
+fn bitstamp_diff(micros: u64, bids: &str, asks: &str) -> ReplayEvent {
+    ReplayEvent::Message {
+        exchange: Exchange::Bitstamp,
+        text: format!(
+            r#"{{"event":"data","channel":"diff_order_book_ethbtc","data":{{"timestamp":"1700000000","microtimestamp":"{}","bids":[{}],"asks":[{}]}}}}"#,
+            micros, bids, asks
+        ),
+    }
+}
+
+fn estimate(book: &AggregatedOrderBook, exchange: &str) -> TradedEstimate {
+    let estimator = book.traded_estimate.as_ref().unwrap();
+    estimator
+        .exchanges
+        .get(exchange)
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn volumes(book: &AggregatedOrderBook, exchange: &str) -> (f64, f64, u64) {
+    let estimate = estimate(book, exchange);
+    (estimate.bid_volume, estimate.ask_volume, estimate.events)
+}
+
+const BIDS: &str = r#"["0.05000","1"],["0.04999","2"],["0.04998","4"]"#;
+const ASKS: &str = r#"["0.05001","3"],["0.05002","5"]"#;
+
+#[test]
+fn removals_from_the_top_of_an_exchange_count_as_traded() {
+    let mut book = AggregatedOrderBook::new().with_traded_volume_estimate();
+    replay(
+        &mut book,
+        &[
+            binance_snapshot(100, BIDS, ASKS),
+            // The first diff after a snapshot reconciles against it
+            binance_diff(101, r#"["0.05000","0"]"#, ""),
+            // The new best bid is hit
+            binance_diff(102, r#"["0.04999","0"]"#, ""),
+            // Behind a best ask that stays: a cancel
+            binance_diff(103, "", r#"["0.05002","0"]"#),
+            // An amount change at the top is not a deletion
+            binance_diff(104, r#"["0.04998","1"]"#, ""),
+            // The best ask goes while a new one is quoted inside it in the same diff
+            binance_diff(105, "", r#"["0.05001","0"],["0.05000","2"]"#),
+            // Replayed out of order: stale, never applied
+            binance_diff(102, r#"["0.04998","0"]"#, ""),
+        ],
+    );
+    assert_eq!(volumes(&book, "binance"), (2.0, 3.0, 2));
+    assert_eq!(book.asks.len(), 1, "every diff was applied");
+    assert!(estimate(&book, "binance").last_at.is_some());
+}
+
+#[test]
+fn a_sweep_counts_every_level_it_takes_but_not_levels_behind_a_survivor() {
+    let mut book = AggregatedOrderBook::new().with_traded_volume_estimate();
+    replay(
+        &mut book,
+        &[
+            binance_snapshot(100, BIDS, ASKS),
+            binance_diff(101, "", ""),
+            // The top two bids are swept in one diff
+            binance_diff(102, r#"["0.05000","0"],["0.04999","0"]"#, ""),
+        ],
+    );
+    assert_eq!(volumes(&book, "binance"), (3.0, 0.0, 1));
+
+    let mut book = AggregatedOrderBook::new().with_traded_volume_estimate();
+    replay(
+        &mut book,
+        &[
+            binance_snapshot(100, BIDS, ASKS),
+            binance_diff(101, "", ""),
+            // The best and the third go, the second stays
+            binance_diff(102, r#"["0.05000","0"],["0.04998","0"]"#, ""),
+        ],
+    );
+    assert_eq!(volumes(&book, "binance"), (1.0, 0.0, 1));
+}
+
+#[test]
+fn snapshot_and_disconnect_removals_never_count() {
+    let mut book = AggregatedOrderBook::new().with_traded_volume_estimate();
+    replay(
+        &mut book,
+        &[
+            binance_snapshot(100, BIDS, ASKS),
+            binance_diff(101, "", ""),
+            // A resync's snapshot without the top levels replaces the book
+            binance_snapshot(200, r#"["0.04998","4"]"#, r#"["0.05002","5"]"#),
+            binance_diff(201, r#"["0.04998","0"]"#, ""),
+            ReplayEvent::Disconnect {
+                exchange: Exchange::Binance,
+            },
+        ],
+    );
+    book.remove_exchange("binance");
+    assert_eq!(volumes(&book, "binance"), (0.0, 0.0, 0));
+}
+
+#[test]
+fn exchanges_are_estimated_against_their_own_top_of_book() {
+    let mut book = AggregatedOrderBook::new().with_traded_volume_estimate();
+    replay(
+        &mut book,
+        &[
+            binance_snapshot(100, BIDS, ASKS),
+            ReplayEvent::Snapshot {
+                exchange: Exchange::Bitstamp,
+                body: r#"{"timestamp":"1700000000","microtimestamp":"1700000000000000","bids":[["0.04990","10"]],"asks":[["0.05010","1"],["0.05020","2"]]}"#
+                    .to_string(),
+            },
+            bitstamp_diff(1_700_000_000_100_000, "", ""),
+            binance_diff(101, "", ""),
+            // Bitstamp's best ask is far behind Binance's but still its own top
+            bitstamp_diff(1_700_000_000_200_000, "", r#"["0.05010","0"]"#),
+            // A deep Binance bid above Bitstamp's best is still behind Binance's own best
+            binance_diff(102, r#"["0.04998","0"]"#, ""),
+        ],
+    );
+    assert_eq!(volumes(&book, "bitstamp"), (0.0, 1.0, 1));
+    assert_eq!(volumes(&book, "binance"), (0.0, 0.0, 0));
+
+    let disabled = AggregatedOrderBook::new();
+    assert!(disabled.traded_estimate.is_none());
+}
@@ -0,0 +1,264 @@
+use keyrock_mm_rust_task::modules::clock::MockClock;
+use keyrock_mm_rust_task::modules::replay::{exchange_levels, parse_update};
+use keyrock_mm_rust_task::modules::types::{AggregatedOrderBook, Exchange, OrderBook, OrderLevel};
+use keyrock_mm_rust_task::modules::warm_cache::WarmCache;
+use keyrock_mm_rust_task::modules::warm_start::{DEFAULT_REPLAY_WINDOW, TailEvent, WarmStart};
+use std::sync::Arc;
+use std::time::Duration;
+
+// One diff a second from here; Bitstamp ids are the diffs' microtimestamps
+const START_MILLIS: u64 = 1_700_000_000_000;
+const SNAPSHOT: u64 = 100;
+
+/// The `n`th diff of `exchange` after the snapshot: (id, recorded at, text)
+fn diff(exchange: Exchange, n: u64) -> (u64, u64, String) {
+    let at = START_MILLIS + n * 1_000;
+    let bid = format!("{:.2}", 99.0 - (n % 5) as f64 * 0.01);
+    let ask = format!("{:.2}", 101.0 + (n % 3) as f64 * 0.01);
+    // Every fourth diff pulls its bid
+    let bid_amount = if n.is_multiple_of(4) { 0.0 } else { n as f64 };
+    match exchange {
+        Exchange::Bitstamp => {
+            let id = at * 1_000;
+            let text = format!(
+                r#"{{"event":"data","channel":"diff_order_book_ethbtc","data":{{"timestamp":"{}","microtimestamp":"{}","bids":[["{}","{}"]],"asks":[["{}","{}"]]}}}}"#,
+                at / 1_000,
+                id,
+                bid,
+                bid_amount,
+                ask,
+                n
+            );
+            (id, at, text)
+        }
+        _ => {
+            let id = SNAPSHOT + n;
+            let text = format!(
+                r#"{{"e":"depthUpdate","E":{},"s":"ETHBTC","U":{},"u":{},"b":[["{}","{}"]],"a":[["{}","{}"]]}}"#,
+                at, id, id, bid, bid_amount, ask, n
+            );
+            (id, at, text)
+        }
+    }
+}
+
+fn snapshot(exchange: Exchange) -> OrderBook {
+    let level = |price, amount| OrderLevel {
+        exchange,
+        price,
+        amount,
+    };
+    OrderBook {
+        last_update_id: match exchange {
+            Exchange::Bitstamp => START_MILLIS * 1_000,
+            _ => SNAPSHOT,
+        },
+        bids: vec![level(99.0, 5.0), level(98.9, 7.0)],
+        asks: vec![level(101.0, 4.0), level(101.1, 6.0)],
+    }
+}
+
+fn apply(book: &mut AggregatedOrderBook, exchange: Exchange, diffs: impl Iterator<Item = u64>) {
+    for n in diffs {
+        let update = parse_update(exchange, &diff(exchange, n).2).unwrap();
+        book.apply_update(update).unwrap();
+    }
+}
+
+/// A book that never restarted: the snapshot and every diff up to `last`
+fn uninterrupted(exchanges: &[Exchange], last: u64) -> AggregatedOrderBook {
+    let mut book = AggregatedOrderBook::new();
+    book.merge_snapshots(exchanges.iter().map(|&e| snapshot(e)).collect());
+    for &exchange in exchanges {
+        apply(&mut book, exchange, 1..=last);
+    }
+    book
+}
+
+fn tail(exchange: Exchange, diffs: impl Iterator<Item = u64>) -> Vec<TailEvent> {
+    diffs
+        .map(|n| {
+            let (_, at, text) = diff(exchange, n);
+            TailEvent::Message { at, exchange, text }
+        })
+        .collect()
+}
+
+/// The process restarted with a cache saved after diff `cached`, the recording's `tail`,
+/// and the live streams having buffered diffs `live` by the time it hands over
+struct Restart {
+    exchanges: Vec<Exchange>,
+    cached: u64,
+    tail: Vec<TailEvent>,
+    live: std::ops::RangeInclusive<u64>,
+    now: u64,
+    window: Duration,
+}
+
+fn restart(r: &Restart) -> (AggregatedOrderBook, WarmStart, Vec<String>) {
+    let mut before = uninterrupted(&r.exchanges, r.cached);
+    // Saved just before the restart
+    before.clock = Arc::new(MockClock::new(r.now));
+    let cache = WarmCache::capture(&before);
+    let restored = cache.restore(&before.config.symbol, r.now * 1_000);
+    assert!(restored.discarded.is_empty(), "{:?}", restored.discarded);
+
+    let mut book = AggregatedOrderBook::new();
+    let warm = WarmStart::run(&mut book, restored.books, &r.tail, r.now, r.window);
+    let mut buffered = vec![];
+    for n in r.live.clone() {
+        for &exchange in &r.exchanges {
+            buffered.push(parse_update(exchange, &diff(exchange, n).2).unwrap());
+        }
+    }
+    let handover = warm.hand_over(&mut book, buffered);
+    (book, warm, handover.resync)
+}
+
+fn assert_same_book(
+    book: &AggregatedOrderBook,
+    reference: &AggregatedOrderBook,
+    exchange: Exchange,
+) {
+    for bids in [true, false] {
+        assert_eq!(
+            exchange_levels(book, exchange, bids),
+            exchange_levels(reference, exchange, bids),
+            "{} {}",
+            exchange,
+            if bids { "bids" } else { "asks" }
+        );
+    }
+    assert_eq!(
+        book.last_update_id.get(exchange.as_str()),
+        reference.last_update_id.get(exchange.as_str())
+    );
+}
+
+#[test]
+fn cache_tail_and_live_match_a_book_that_never_restarted() {
+    let exchanges = vec![Exchange::Binance, Exchange::Bitstamp];
+    // Cache saved at 20, recording runs 10..=40, live buffered 36..=50: the recording's
+    // head is reflected by the cache and its end overlaps the live buffer
+    let mut recorded = vec![];
+    for n in 10..=40 {
+        for &exchange in &exchanges {
+            recorded.extend(tail(exchange, n..=n));
+        }
+    }
+    let r = Restart {
+        exchanges: exchanges.clone(),
+        cached: 20,
+        tail: recorded,
+        live: 36..=50,
+        now: START_MILLIS + 45_000,
+        window: DEFAULT_REPLAY_WINDOW,
+    };
+    let (book, warm, resync) = restart(&r);
+
+    assert!(resync.is_empty(), "{:?}", resync);
+    let reference = uninterrupted(&exchanges, 50);
+    for &exchange in &exchanges {
+        assert_same_book(&book, &reference, exchange);
+        let replay = &warm.exchanges[exchange.as_str()];
+        assert_eq!(
+            (replay.reflected, replay.replayed, replay.skipped),
+            (11, 20, 0)
+        );
+        assert_eq!(replay.stopped, None);
+    }
+    assert_eq!(warm.exchanges["binance"].replayed_id, SNAPSHOT + 40);
+}
+
+#[test]
+fn diffs_older_than_the_window_end_the_replay_and_the_exchange_resyncs() {
+    let r = Restart {
+        exchanges: vec![Exchange::Binance],
+        cached: 20,
+        tail: tail(Exchange::Binance, 10..=40),
+        live: 36..=50,
+        now: START_MILLIS + 45_000,
+        // Diffs 21..=24 were recorded more than 20s before now
+        window: Duration::from_secs(20),
+    };
+    let (book, warm, resync) = restart(&r);
+
+    let replay = &warm.exchanges["binance"];
+    assert_eq!(
+        (replay.reflected, replay.replayed, replay.skipped),
+        (11, 0, 20)
+    );
+    assert!(
+        replay
+            .stopped
+            .as_ref()
+            .unwrap()
+            .contains("older than the 20s")
+    );
+    // Nothing can bridge 20 to the live stream, so the cached levels go
+    assert_eq!(resync, vec!["binance".to_string()]);
+    assert!(exchange_levels(&book, Exchange::Binance, true).is_empty());
+    assert!(!book.last_update_id.contains_key("binance"));
+
+    // The same cache with a window that covers the gap is fine
+    let (book, _, resync) = restart(&Restart {
+        window: Duration::from_secs(25),
+        ..r
+    });
+    assert!(resync.is_empty());
+    assert_same_book(
+        &book,
+        &uninterrupted(&[Exchange::Binance], 50),
+        Exchange::Binance,
+    );
+}
+
+#[test]
+fn live_streams_that_dont_meet_the_replay_resync() {
+    // The recording ends at 30 but the live streams only buffered from 36
+    for exchange in [Exchange::Binance, Exchange::Bitstamp] {
+        let (book, warm, resync) = restart(&Restart {
+            exchanges: vec![exchange],
+            cached: 20,
+            tail: tail(exchange, 15..=30),
+            live: 36..=50,
+            now: START_MILLIS + 45_000,
+            window: DEFAULT_REPLAY_WINDOW,
+        });
+        assert_eq!(warm.exchanges[exchange.as_str()].replayed, 10);
+        assert_eq!(resync, vec![exchange.to_string()], "{}", exchange);
+        assert!(exchange_levels(&book, exchange, false).is_empty());
+    }
+}
+
+#[test]
+fn a_break_in_the_recording_stops_a_bitstamp_replay() {
+    // The recorded session reconnected Bitstamp after 25; ids alone can't show what it
+    // missed, so the replay stops there and the live stream has to overlap it
+    let mut recorded = tail(Exchange::Bitstamp, 15..=25);
+    recorded.push(TailEvent::Break {
+        at: START_MILLIS + 25_500,
+        exchange: Exchange::Bitstamp,
+    });
+    recorded.extend(tail(Exchange::Bitstamp, 28..=40));
+    let r = Restart {
+        exchanges: vec![Exchange::Bitstamp],
+        cached: 20,
+        tail: recorded,
+        live: 24..=50,
+        now: START_MILLIS + 45_000,
+        window: DEFAULT_REPLAY_WINDOW,
+    };
+    let (book, warm, resync) = restart(&r);
+
+    let replay = &warm.exchanges["bitstamp"];
+    assert_eq!((replay.replayed, replay.skipped), (5, 13));
+    assert!(replay.stopped.is_some());
+    // The live buffer overlaps the replay, so it carries on without a resync
+    assert!(resync.is_empty());
+    assert_same_book(
+        &book,
+        &uninterrupted(&[Exchange::Bitstamp], 50),
+        Exchange::Bitstamp,
+    );
+}